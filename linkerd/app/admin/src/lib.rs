@@ -1,8 +1,10 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
+mod push;
 mod server;
 mod stack;
 
+pub use self::push::PushConfig;
 pub use self::server::{Admin, Latch, Readiness};
 pub use self::stack::{Config, Task};
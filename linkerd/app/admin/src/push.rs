@@ -0,0 +1,341 @@
+//! Periodically pushes the proxy's metrics to a Prometheus Pushgateway.
+//!
+//! Pushing is intended for workloads that can't be scraped (e.g. short-lived
+//! jobs). It doesn't replace the admin server's `/metrics` endpoint, which
+//! continues to serve the same metrics.
+
+use http::{header, StatusCode};
+use hyper::{client::HttpConnector, Body, Client};
+use linkerd_app_core::{
+    drain,
+    metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics},
+    Addr, Error,
+};
+use std::{
+    fmt::{self, Write},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::time;
+use tracing::{debug, warn};
+
+metrics::metrics! {
+    metrics_push_total: Counter {
+        "The total number of attempts to push metrics to the Pushgateway."
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PushConfig {
+    /// The Pushgateway's address.
+    pub addr: Addr,
+
+    /// The `job` grouping label.
+    pub job: String,
+
+    /// The `instance` grouping label. When unset, the proxy's metrics are
+    /// grouped by job alone.
+    pub instance: Option<String>,
+
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Counts push attempts by their outcome. Nothing is reported when pushing is
+/// disabled.
+#[derive(Clone, Debug)]
+pub(crate) struct PushMetrics(Option<Arc<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    pushed: Counter,
+    dropped: Counter,
+}
+
+struct Outcome(&'static str);
+
+#[derive(Debug, Error)]
+#[error("Pushgateway responded with {0}")]
+struct UnexpectedStatus(StatusCode);
+
+#[derive(Debug, Error)]
+#[error("push timed out after {0:?}")]
+struct PushTimeout(Duration);
+
+// === impl PushConfig ===
+
+impl PushConfig {
+    /// Returns the URI of the metrics group to which the proxy pushes its
+    /// metrics.
+    fn uri(&self) -> Result<http::Uri, Error> {
+        let mut uri = format!("http://{}/metrics", self.addr);
+        push_label(&mut uri, "job", &self.job)?;
+        if let Some(instance) = self.instance.as_ref() {
+            push_label(&mut uri, "instance", instance)?;
+        }
+        Ok(uri.parse()?)
+    }
+
+    /// Builds a task that pushes `report` every `interval` until the proxy
+    /// shuts down, when the metrics are pushed a final time.
+    ///
+    /// The metrics group is replaced by each push, so a push that fails is
+    /// dropped rather than retried: the next push includes all of its
+    /// metrics.
+    pub(crate) fn build<R>(
+        self,
+        report: R,
+        metrics: PushMetrics,
+        drain: drain::Watch,
+    ) -> Result<impl std::future::Future<Output = ()> + Send + 'static, Error>
+    where
+        R: FmtMetrics + Send + Sync + 'static,
+    {
+        let uri = self.uri()?;
+        let client = Client::new();
+        let PushConfig {
+            interval, timeout, ..
+        } = self;
+        Ok(async move {
+            let mut pushes = time::interval(interval);
+            let shutdown = drain.signaled();
+            tokio::pin!(shutdown);
+            loop {
+                tokio::select! {
+                    _ = pushes.tick() => {
+                        push(&client, &uri, &report, timeout, &metrics).await;
+                    }
+                    release = &mut shutdown => {
+                        debug!("Pushing metrics before shutdown");
+                        push(&client, &uri, &report, timeout, &metrics).await;
+                        drop(release);
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Appends a grouping label to a metrics group's path.
+///
+/// Values that can't be used as a path segment as-is are base64-encoded, as
+/// the Pushgateway supports.
+fn push_label(uri: &mut String, name: &str, value: &str) -> fmt::Result {
+    let is_plain = !matches!(value, "" | "." | "..")
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'));
+    if is_plain {
+        return write!(uri, "/{}/{}", name, value);
+    }
+    // The Pushgateway represents empty values as a lone padding character.
+    let value = match base64::encode_config(value, base64::URL_SAFE) {
+        encoded if encoded.is_empty() => "=".to_string(),
+        encoded => encoded,
+    };
+    write!(uri, "/{}@base64/{}", name, value)
+}
+
+async fn push<R: FmtMetrics>(
+    client: &Client<HttpConnector>,
+    uri: &http::Uri,
+    report: &R,
+    timeout: Duration,
+    metrics: &PushMetrics,
+) {
+    match time::timeout(timeout, send(client, uri, report)).await {
+        Ok(Ok(())) => {
+            debug!(%uri, "Pushed metrics");
+            metrics.incr(|m| &m.pushed);
+        }
+        Ok(Err(error)) => {
+            warn!(%uri, %error, "Failed to push metrics");
+            metrics.incr(|m| &m.dropped);
+        }
+        Err(_) => {
+            warn!(%uri, error = %PushTimeout(timeout), "Failed to push metrics");
+            metrics.incr(|m| &m.dropped);
+        }
+    }
+}
+
+async fn send<R: FmtMetrics>(
+    client: &Client<HttpConnector>,
+    uri: &http::Uri,
+    report: &R,
+) -> Result<(), Error> {
+    let req = http::Request::put(uri.clone())
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(report.as_display().to_string()))?;
+    let rsp = client.request(req).await?;
+    if !rsp.status().is_success() {
+        return Err(UnexpectedStatus(rsp.status()).into());
+    }
+    Ok(())
+}
+
+// === impl PushMetrics ===
+
+impl PushMetrics {
+    pub(crate) fn new(enabled: bool) -> Self {
+        if !enabled {
+            return Self(None);
+        }
+        Self(Some(Default::default()))
+    }
+
+    fn incr(&self, counter: impl FnOnce(&Inner) -> &Counter) {
+        if let Some(inner) = self.0.as_ref() {
+            counter(inner).incr();
+        }
+    }
+}
+
+impl FmtMetrics for PushMetrics {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = match self.0.as_ref() {
+            Some(inner) => inner,
+            None => return Ok(()),
+        };
+        metrics_push_total.fmt_help(f)?;
+        inner
+            .pushed
+            .fmt_metric_labeled(f, metrics_push_total.name, Outcome("pushed"))?;
+        inner
+            .dropped
+            .fmt_metric_labeled(f, metrics_push_total.name, Outcome("dropped"))?;
+        Ok(())
+    }
+}
+
+impl FmtLabels for Outcome {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "outcome=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Request, Response, Server,
+    };
+    use std::{convert::Infallible, net::SocketAddr};
+    use tokio::sync::mpsc;
+
+    /// A Pushgateway that records the path and body of each push, responding
+    /// with `status`.
+    fn pushgateway(status: StatusCode) -> (SocketAddr, mpsc::UnboundedReceiver<(String, String)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let make = make_service_fn(move |_| {
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let tx = tx.clone();
+                    async move {
+                        assert_eq!(req.method(), http::Method::PUT);
+                        let path = req.uri().path().to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let _ = tx.send((path, String::from_utf8(body.to_vec()).unwrap()));
+                        let rsp = Response::builder().status(status).body(Body::empty());
+                        Ok::<_, Infallible>(rsp.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, rx)
+    }
+
+    fn config(addr: SocketAddr, instance: Option<&str>, interval: Duration) -> PushConfig {
+        PushConfig {
+            addr: addr.into(),
+            job: "batch".to_string(),
+            instance: instance.map(Into::into),
+            interval,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn pushes_metrics() {
+        let (addr, mut pushes) = pushgateway(StatusCode::OK);
+        let metrics = PushMetrics::new(true);
+        let (drain_tx, drain) = drain::channel();
+        let task = config(addr, Some("pod-0"), Duration::from_secs(60))
+            .build(metrics.clone(), metrics.clone(), drain)
+            .expect("config must be valid");
+        let task = tokio::spawn(task);
+
+        let (path, body) = pushes.recv().await.expect("metrics must be pushed");
+        assert_eq!(path, "/metrics/job/batch/instance/pod-0");
+        assert!(body.contains("metrics_push_total{outcome=\"pushed\"} 0"));
+
+        // Metrics are pushed a final time when the proxy shuts down.
+        drain_tx.drain().await;
+        task.await.unwrap();
+        let (_, body) = pushes.recv().await.expect("metrics must be pushed");
+        assert!(body.contains("metrics_push_total{outcome=\"pushed\"} 1"));
+        assert_eq!(u64::from(&metrics.0.as_ref().unwrap().pushed), 2);
+        assert_eq!(u64::from(&metrics.0.as_ref().unwrap().dropped), 0);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn drops_failed_pushes() {
+        let (addr, mut pushes) = pushgateway(StatusCode::SERVICE_UNAVAILABLE);
+        let metrics = PushMetrics::new(true);
+        let (_drain_tx, drain) = drain::channel();
+        let task = config(addr, None, Duration::from_millis(10))
+            .build((), metrics.clone(), drain)
+            .expect("config must be valid");
+        let task = tokio::spawn(task);
+
+        let (path, _) = pushes.recv().await.expect("metrics must be pushed");
+        assert_eq!(path, "/metrics/job/batch");
+        pushes
+            .recv()
+            .await
+            .expect("failed pushes must not stop pushing");
+        task.abort();
+
+        assert!(u64::from(&metrics.0.as_ref().unwrap().dropped) >= 1);
+        assert_eq!(u64::from(&metrics.0.as_ref().unwrap().pushed), 0);
+    }
+
+    #[test]
+    fn encodes_grouping_labels() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 9091));
+        let mut config = config(addr, Some("pod-0"), Duration::from_secs(60));
+        assert_eq!(
+            config.uri().unwrap(),
+            "http://127.0.0.1:9091/metrics/job/batch/instance/pod-0"
+        );
+
+        config.job = "batch/daily".to_string();
+        config.instance = Some("pod 0".to_string());
+        assert_eq!(
+            config.uri().unwrap(),
+            "http://127.0.0.1:9091/metrics/job@base64/YmF0Y2gvZGFpbHk=/instance@base64/cG9kIDA="
+        );
+
+        config.instance = Some(String::new());
+        assert_eq!(
+            config.uri().unwrap(),
+            "http://127.0.0.1:9091/metrics/job@base64/YmF0Y2gvZGFpbHk=/instance@base64/="
+        );
+    }
+
+    #[test]
+    fn only_reports_metrics_when_enabled() {
+        assert!(PushMetrics::new(true)
+            .as_display()
+            .to_string()
+            .contains("metrics_push_total"));
+        assert_eq!(PushMetrics::new(false).as_display().to_string(), "");
+    }
+}
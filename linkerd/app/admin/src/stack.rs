@@ -1,3 +1,4 @@
+use crate::push::{PushConfig, PushMetrics};
 use linkerd_app_core::{
     classify,
     config::ServerConfig,
@@ -20,12 +21,19 @@ use tracing::debug;
 pub struct Config {
     pub server: ServerConfig,
     pub metrics_retain_idle: Duration,
+
+    /// Determines how response status codes are labeled in HTTP metrics.
+    pub metrics_status_labels: metrics::StatusLabels,
+
+    /// When set, metrics are also pushed to a Pushgateway.
+    pub metrics_push: Option<PushConfig>,
 }
 
 pub struct Task {
     pub listen_addr: Local<ServerAddr>,
     pub latch: crate::Latch,
     pub serve: Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>,
+    pub push: Option<Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>>,
 }
 
 #[derive(Debug, Error)]
@@ -64,6 +72,16 @@ impl Config {
     {
         let (listen_addr, listen) = bind.bind(&self.server)?;
 
+        let push_metrics = PushMetrics::new(self.metrics_push.is_some());
+        let report = push_metrics.clone().and_then(report);
+        let push = match self.metrics_push {
+            Some(config) => {
+                let push = config.build(report.clone(), push_metrics, drain.clone())?;
+                Some(Box::pin(push) as Pin<Box<_>>)
+            }
+            None => None,
+        };
+
         let (ready, latch) = crate::server::Readiness::new();
         let admin = crate::server::Admin::new(report, ready, shutdown, trace);
         let admin = svc::stack(admin)
@@ -71,7 +89,12 @@ impl Config {
             .push_on_response(
                 svc::layers()
                     .push(metrics.http_errors.clone())
-                    .push(errors::layer())
+                    .push(errors::layer(
+                        false,
+                        false,
+                        errors::proxy_error_header(),
+                        false,
+                    ))
                     .push(http::BoxResponse::layer()),
             )
             .push_map_target(Target::from)
@@ -132,6 +155,7 @@ impl Config {
                     tls,
                     client_addr: addrs.param(),
                     target_addr,
+                    class: None,
                 }
             })
             .push(svc::BoxNewService::layer())
@@ -145,6 +169,7 @@ impl Config {
             listen_addr,
             latch,
             serve,
+            push,
         })
     }
 }
@@ -108,7 +108,12 @@ impl IpMatch {
 
     #[inline]
     pub fn matches(&self, addr: IpAddr) -> bool {
-        self.0.iter().any(|net| match (net, addr) {
+        self.matching(addr).is_some()
+    }
+
+    /// Returns the first network that contains `addr`, if any.
+    pub fn matching(&self, addr: IpAddr) -> Option<&IpNet> {
+        self.0.iter().find(|net| match (net, addr) {
             (IpNet::V4(net), IpAddr::V4(ip)) => net.contains(&ip),
             (IpNet::V6(net), IpAddr::V6(ip)) => net.contains(&ip),
             _ => false,
@@ -0,0 +1,172 @@
+use parking_lot::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BreakerThresholds {
+    /// The number of consecutive failures that open the breaker. Zero disables
+    /// the breaker.
+    pub max_failures: usize,
+
+    /// How long the breaker remains open before a request may probe its
+    /// target.
+    pub open_timeout: Duration,
+}
+
+/// A circuit breaker that opens after consecutive failures.
+///
+/// While a breaker is open, no requests are permitted. After its
+/// `open_timeout`, a single request is permitted to probe the target: the
+/// breaker closes if it succeeds and remains open for another `open_timeout`
+/// if it fails.
+///
+/// Each time the breaker opens, a new period begins. Outcomes of requests that
+/// were permitted in an earlier period are ignored, so that a slow request
+/// dispatched before the breaker opened cannot close it.
+#[derive(Debug)]
+pub struct Breaker {
+    thresholds: BreakerThresholds,
+    state: Mutex<State>,
+}
+
+/// Records the period in which a request was permitted.
+#[derive(Copy, Clone, Debug)]
+pub struct Permit {
+    period: u64,
+}
+
+/// A change in a breaker's state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    Opened,
+    Closed,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: usize,
+    open_until: Option<Instant>,
+    period: u64,
+}
+
+// === impl Breaker ===
+
+impl Breaker {
+    pub fn new(thresholds: BreakerThresholds) -> Self {
+        Self {
+            thresholds,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Returns a permit if a request may be dispatched to the target.
+    ///
+    /// Once an open breaker's timeout elapses, a single request is permitted
+    /// and the breaker remains open until that request completes.
+    pub fn permit(&self) -> Option<Permit> {
+        let mut state = self.state.lock();
+        match state.open_until {
+            None => {}
+            Some(until) if Instant::now() < until => return None,
+            Some(_) => {
+                debug!("Probing circuit breaker target");
+                state.open_until = Some(Instant::now() + self.thresholds.open_timeout);
+            }
+        }
+        Some(Permit {
+            period: state.period,
+        })
+    }
+
+    /// Records the outcome of a permitted request, returning the breaker's
+    /// transition, if any.
+    pub fn record(&self, permit: Permit, failed: bool) -> Option<Transition> {
+        let mut state = self.state.lock();
+        if permit.period != state.period {
+            debug!(failed, "Ignoring outcome from an earlier breaker period");
+            return None;
+        }
+
+        if !failed {
+            state.failures = 0;
+            if state.open_until.take().is_some() {
+                debug!("Closing circuit breaker");
+                return Some(Transition::Closed);
+            }
+            return None;
+        }
+
+        state.failures += 1;
+        if state.failures < self.thresholds.max_failures {
+            return None;
+        }
+        debug!(
+            failures = state.failures,
+            timeout = ?self.thresholds.open_timeout,
+            "Opening circuit breaker"
+        );
+        state.period += 1;
+        let was_open = state
+            .open_until
+            .replace(Instant::now() + self.thresholds.open_timeout)
+            .is_some();
+        if was_open {
+            None
+        } else {
+            Some(Transition::Opened)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(max_failures: usize) -> Breaker {
+        Breaker::new(BreakerThresholds {
+            max_failures,
+            open_timeout: Duration::from_secs(10),
+        })
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn opens_after_consecutive_failures() {
+        let breaker = breaker(2);
+        let permit = breaker.permit().unwrap();
+        assert_eq!(breaker.record(permit, true), None);
+        assert_eq!(breaker.record(permit, false), None);
+        assert_eq!(breaker.record(permit, true), None);
+        assert_eq!(breaker.record(permit, true), Some(Transition::Opened));
+        assert!(breaker.permit().is_none());
+
+        // Once the timeout elapses, a single request probes the target.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let probe = breaker.permit().expect("a probe must be permitted");
+        assert!(breaker.permit().is_none());
+        assert_eq!(breaker.record(probe, true), None);
+        assert!(breaker.permit().is_none());
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let probe = breaker.permit().expect("a probe must be permitted");
+        assert_eq!(breaker.record(probe, false), Some(Transition::Closed));
+        assert!(breaker.permit().is_some());
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn ignores_outcomes_from_earlier_periods() {
+        let breaker = breaker(1);
+        let slow = breaker.permit().unwrap();
+        let failed = breaker.permit().unwrap();
+        assert_eq!(breaker.record(failed, true), Some(Transition::Opened));
+
+        // A request dispatched before the breaker opened does not close it...
+        assert_eq!(breaker.record(slow, false), None);
+        assert!(breaker.permit().is_none());
+
+        // ...but a probe does.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let probe = breaker.permit().unwrap();
+        assert_eq!(breaker.record(probe, false), Some(Transition::Closed));
+    }
+}
@@ -1,8 +1,10 @@
 pub use crate::exp_backoff::ExponentialBackoff;
 use crate::{
-    proxy::http::{h1, h2},
+    connection_log::ConnectionLog,
+    memory_pressure::MemoryPressure,
+    proxy::http::{h1, h2, ClientDisconnect, CloseDelimited, Via},
     svc::Param,
-    transport::{Keepalive, ListenAddr},
+    transport::{DscpMarking, Keepalive, ListenAddr},
 };
 use std::{
     collections::HashSet,
@@ -15,6 +17,9 @@ pub struct ServerConfig {
     pub addr: ListenAddr,
     pub keepalive: Keepalive,
     pub h2_settings: h2::Settings,
+
+    /// Determines how accepted connections are marked for QoS.
+    pub dscp: DscpMarking,
 }
 
 #[derive(Clone, Debug)]
@@ -24,6 +29,18 @@ pub struct ConnectConfig {
     pub keepalive: Keepalive,
     pub h1_settings: h1::PoolSettings,
     pub h2_settings: h2::Settings,
+
+    /// Determines how HTTP/1 responses whose bodies are delimited by the
+    /// connection closing are forwarded.
+    pub close_delimited: CloseDelimited,
+
+    /// Limits how many bytes of each streaming response body are read from the
+    /// upstream before they are written to the client. When zero, response
+    /// bodies are read only as quickly as the client consumes them.
+    pub response_read_ahead_bytes: usize,
+
+    /// Determines how established connections are marked for QoS.
+    pub dscp: DscpMarking,
 }
 
 #[derive(Clone, Debug)]
@@ -35,6 +52,30 @@ pub struct ProxyConfig {
     pub dispatch_timeout: Duration,
     pub max_in_flight_requests: usize,
     pub detect_protocol_timeout: Duration,
+
+    /// Determines whether requests are canceled when their clients disconnect.
+    pub client_disconnect: ClientDisconnect,
+
+    /// Determines whether connections are logged as they are accepted and
+    /// closed.
+    pub connection_log: ConnectionLog,
+
+    /// Determines whether error responses include the ID of the request's
+    /// trace.
+    pub echo_trace_id: bool,
+
+    /// Determines whether forwarded TCP connections are copied with
+    /// `splice(2)` when both of their ends are plain TCP sockets. This is only
+    /// supported on Linux.
+    pub tcp_splice: bool,
+
+    /// Determines whether listeners stop accepting connections while the
+    /// proxy's memory usage is high.
+    pub memory_pressure: MemoryPressure,
+
+    /// If set, the proxy appends an entry with this pseudonym to the `Via`
+    /// headers of the HTTP requests and responses it forwards.
+    pub via: Option<Via>,
 }
 
 /// A `HashSet` specialized for ports.
@@ -64,6 +105,12 @@ impl Param<Keepalive> for ServerConfig {
     }
 }
 
+impl Param<DscpMarking> for ServerConfig {
+    fn param(&self) -> DscpMarking {
+        self.dscp.clone()
+    }
+}
+
 // === impl PortHasher ===
 
 impl Hasher for PortHasher {
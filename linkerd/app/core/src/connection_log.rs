@@ -0,0 +1,611 @@
+//! Audit logs for each connection as it is accepted and closed.
+//!
+//! Connection logs are emitted at the `info` level with the client's address,
+//! the connection's target port, and, when the connection closes, the client's
+//! identity, the detected protocol, the connection's duration, the number of
+//! bytes read and written, and the reason the connection closed.
+//!
+//! Because each connection produces two log lines, logs are rate-limited.
+//! Connections that are accepted once the limit has been reached are not
+//! logged at all, and the number of such connections is reported on the next
+//! accept log.
+//!
+//! Alternatively, connections may be logged as JSON records, which are written
+//! to stdout rather than to the proxy's diagnostic log. Each logged connection
+//! produces exactly one record, when it closes, that includes all of the
+//! connection's metadata as well as its TLS version and cipher suite. Records
+//! are written by a dedicated thread, so that a slow stdout never blocks the
+//! proxy; records that cannot be buffered for it are dropped and reported as
+//! suppressed by the next record.
+
+use crate::{
+    io,
+    svc::{self, Param},
+    tls,
+    transport::{ClientAddr, OrigDstAddr, Remote},
+    Conditional, Error,
+};
+use futures::{future, prelude::*};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::{
+    io::Write,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Determines whether accepted connections are logged.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionLog(Option<Arc<Log>>);
+
+/// Determines how connections are logged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Connections are logged by the proxy's diagnostic log as they are
+    /// accepted and closed.
+    Plain,
+
+    /// Each connection is logged as a JSON record when it closes.
+    Json,
+}
+
+/// The IO type of logged connections, which counts the bytes read from and
+/// written to the client.
+pub type Io<I> = io::SensorIo<I, Sensor>;
+
+/// Counts the bytes transferred on a logged connection.
+#[derive(Clone, Debug)]
+pub struct Sensor(Option<Arc<Connection>>);
+
+/// An accept target that may be logged when the connection closes.
+#[derive(Clone, Debug)]
+pub struct Accepted<A> {
+    addrs: A,
+    conn: Option<Arc<Connection>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewConnectionLog<N> {
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogConnection<S> {
+    inner: S,
+    conn: Option<Arc<Connection>>,
+}
+
+/// Records the TLS session of each terminated connection that is logged.
+#[derive(Clone, Debug)]
+pub struct RecordTlsSession<S> {
+    inner: S,
+}
+
+#[derive(Debug)]
+struct Log {
+    format: Format,
+    limit: RateLimit,
+    records: Option<mpsc::SyncSender<String>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    log: Arc<Log>,
+    client_addr: SocketAddr,
+    target_addr: SocketAddr,
+    accepted_at: Instant,
+    suppressed: u64,
+    identity: Mutex<Option<String>>,
+    protocol: Mutex<Option<String>>,
+    tls: Mutex<Option<TlsSession>>,
+    read_bytes: AtomicU64,
+    write_bytes: AtomicU64,
+}
+
+#[derive(Debug)]
+struct TlsSession {
+    version: String,
+    cipher_suite: String,
+}
+
+/// Limits the number of connections logged each second.
+#[derive(Debug)]
+struct RateLimit {
+    max_per_second: u64,
+    start: Instant,
+    window: AtomicU64,
+    logged: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+/// Logs a connection's close when dropped, so that connections are logged even
+/// if they do not complete.
+struct Closing {
+    conn: Arc<Connection>,
+    reason: Option<String>,
+}
+
+/// The number of JSON records that may be buffered for the writer thread.
+const RECORD_BUFFER_CAPACITY: usize = 1_000;
+
+tokio::task_local! {
+    static CONNECTION: Arc<Connection>;
+}
+
+/// Records the client identity of the connection being served by the current
+/// task, if it is logged.
+pub fn record_client_identity(tls: &tls::ConditionalServerTls) {
+    let identity = match tls {
+        Conditional::Some(tls::ServerTls::Established {
+            client_id: Some(id),
+            ..
+        }) => id.to_string(),
+        Conditional::Some(tls::ServerTls::Established {
+            client_id: None, ..
+        }) => "unauthenticated".to_string(),
+        Conditional::Some(tls::ServerTls::Passthru { .. }) => "passthru".to_string(),
+        Conditional::None(_) => "none".to_string(),
+    };
+    let _ = CONNECTION.try_with(|conn| *conn.identity.lock() = Some(identity));
+}
+
+/// Records the protocol of the connection being served by the current task,
+/// if it is logged.
+pub fn record_protocol(protocol: impl std::fmt::Display) {
+    let _ = CONNECTION.try_with(|conn| *conn.protocol.lock() = Some(protocol.to_string()));
+}
+
+// === impl ConnectionLog ===
+
+impl ConnectionLog {
+    /// Logs at most `max_per_second` connections each second.
+    pub fn new(max_per_second: u64, format: Format) -> Self {
+        if format == Format::Plain {
+            return Self::with_records(max_per_second, format, None);
+        }
+
+        let (tx, rx) = mpsc::sync_channel(RECORD_BUFFER_CAPACITY);
+        let spawned = std::thread::Builder::new()
+            .name("connection-log".into())
+            .spawn(move || write_stdout(rx));
+        if let Err(error) = spawned {
+            warn!(%error, "Failed to spawn the connection log writer");
+            return Self::disabled();
+        }
+        Self::with_records(max_per_second, format, Some(tx))
+    }
+
+    fn with_records(
+        max_per_second: u64,
+        format: Format,
+        records: Option<mpsc::SyncSender<String>>,
+    ) -> Self {
+        Self(Some(Arc::new(Log {
+            format,
+            records,
+            limit: RateLimit {
+                max_per_second,
+                start: Instant::now(),
+                window: AtomicU64::new(0),
+                logged: AtomicU64::new(0),
+                suppressed: AtomicU64::new(0),
+            },
+        })))
+    }
+
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    /// Logs an accepted connection, if it is within the rate limit.
+    pub fn accept<A, I>(&self, addrs: A, io: I) -> (Accepted<A>, Io<I>)
+    where
+        A: Param<Remote<ClientAddr>> + Param<OrigDstAddr>,
+    {
+        let conn = self.0.as_ref().and_then(|log| {
+            let suppressed = log.limit.acquire()?;
+            let Remote(ClientAddr(client_addr)) = addrs.param();
+            let OrigDstAddr(target_addr) = addrs.param();
+            if log.format == Format::Plain {
+                info!(
+                    client.addr = %client_addr,
+                    target.port = target_addr.port(),
+                    suppressed,
+                    "Connection accepted"
+                );
+            }
+            Some(Arc::new(Connection {
+                log: log.clone(),
+                client_addr,
+                target_addr,
+                accepted_at: Instant::now(),
+                suppressed,
+                identity: Mutex::new(None),
+                protocol: Mutex::new(None),
+                tls: Mutex::new(None),
+                read_bytes: AtomicU64::new(0),
+                write_bytes: AtomicU64::new(0),
+            }))
+        });
+        let io = io::SensorIo::new(io, Sensor(conn.clone()));
+        (Accepted { addrs, conn }, io)
+    }
+}
+
+// === impl RateLimit ===
+
+impl RateLimit {
+    /// Returns the number of connections that have not been logged since the
+    /// last connection was logged, or `None` if this connection should not be
+    /// logged.
+    fn acquire(&self) -> Option<u64> {
+        let now = self.start.elapsed().as_secs();
+        let window = self.window.load(Ordering::Acquire);
+        if now != window
+            && self
+                .window
+                .compare_exchange(window, now, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            self.logged.store(0, Ordering::Release);
+        }
+
+        if self.logged.fetch_add(1, Ordering::AcqRel) < self.max_per_second {
+            return Some(self.suppressed.swap(0, Ordering::AcqRel));
+        }
+        self.suppressed.fetch_add(1, Ordering::AcqRel);
+        None
+    }
+}
+
+// === impl Sensor ===
+
+impl io::Sensor for Sensor {
+    fn record_read(&mut self, sz: usize) {
+        if let Some(conn) = self.0.as_ref() {
+            conn.read_bytes.fetch_add(sz as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_write(&mut self, sz: usize) {
+        if let Some(conn) = self.0.as_ref() {
+            conn.write_bytes.fetch_add(sz as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_close(&mut self, _: Option<linkerd_errno::Errno>) {}
+
+    fn record_error<T>(&mut self, op: io::Poll<T>) -> io::Poll<T> {
+        op
+    }
+}
+
+// === impl Accepted ===
+
+impl<A: Param<Remote<ClientAddr>>> Param<Remote<ClientAddr>> for Accepted<A> {
+    fn param(&self) -> Remote<ClientAddr> {
+        self.addrs.param()
+    }
+}
+
+// === impl NewConnectionLog ===
+
+impl<N> NewConnectionLog<N> {
+    pub fn new(inner: N) -> Self {
+        Self { inner }
+    }
+}
+
+impl<A, N: svc::NewService<A>> svc::NewService<Accepted<A>> for NewConnectionLog<N> {
+    type Service = LogConnection<N::Service>;
+
+    fn new_service(&mut self, Accepted { addrs, conn }: Accepted<A>) -> Self::Service {
+        LogConnection {
+            inner: self.inner.new_service(addrs),
+            conn,
+        }
+    }
+}
+
+// === impl LogConnection ===
+
+impl<I, S> svc::Service<I> for LogConnection<S>
+where
+    S: svc::Service<I, Response = ()>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let serve = self.inner.call(io).err_into::<Error>();
+        let conn = match self.conn.clone() {
+            Some(conn) => conn,
+            None => return future::Either::Left(serve),
+        };
+
+        future::Either::Right(Box::pin(CONNECTION.scope(conn.clone(), async move {
+            let mut closing = Closing { conn, reason: None };
+            let res = serve.await;
+            closing.reason = Some(match res.as_ref() {
+                Ok(()) => "closed".to_string(),
+                Err(error) => error.to_string(),
+            });
+            res
+        })))
+    }
+}
+
+// === impl RecordTlsSession ===
+
+impl<S> RecordTlsSession<S> {
+    pub fn layer() -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<I, S> svc::Service<tls::server::Io<I>> for RecordTlsSession<S>
+where
+    S: svc::Service<tls::server::Io<I>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, io: tls::server::Io<I>) -> Self::Future {
+        if let io::EitherIo::Left(ref stream) = io {
+            use tls::Session;
+
+            let (_, session) = stream.get_ref();
+            let _ = CONNECTION.try_with(|conn| {
+                *conn.tls.lock() = Some(TlsSession {
+                    version: session
+                        .get_protocol_version()
+                        .map(|v| format!("{:?}", v))
+                        .unwrap_or_default(),
+                    cipher_suite: session
+                        .get_negotiated_ciphersuite()
+                        .map(|c| format!("{:?}", c.suite))
+                        .unwrap_or_default(),
+                });
+            });
+        }
+
+        self.inner.call(io)
+    }
+}
+
+// === impl Closing ===
+
+impl Drop for Closing {
+    fn drop(&mut self) {
+        let conn = &*self.conn;
+        let identity = conn.identity.lock().take();
+        let protocol = conn.protocol.lock().take();
+        let tls = conn.tls.lock().take();
+        let duration_ms = conn.accepted_at.elapsed().as_millis() as u64;
+        let read_bytes = conn.read_bytes.load(Ordering::Relaxed);
+        let write_bytes = conn.write_bytes.load(Ordering::Relaxed);
+        let reason = self.reason.as_deref().unwrap_or("canceled");
+
+        match conn.log.format {
+            Format::Plain => info!(
+                client.addr = %conn.client_addr,
+                client.id = identity.as_deref().unwrap_or("-"),
+                target.port = conn.target_addr.port(),
+                protocol = protocol.as_deref().unwrap_or("-"),
+                tls.version = tls.as_ref().map(|t| &*t.version).unwrap_or("-"),
+                tls.cipher_suite = tls.as_ref().map(|t| &*t.cipher_suite).unwrap_or("-"),
+                duration_ms,
+                read_bytes,
+                write_bytes,
+                reason,
+                "Connection closed"
+            ),
+            Format::Json => {
+                let record = json!({
+                    "client_addr": conn.client_addr.to_string(),
+                    "client_id": identity,
+                    "server_addr": conn.target_addr.to_string(),
+                    "port": conn.target_addr.port(),
+                    "protocol": protocol,
+                    "tls_version": tls.as_ref().map(|t| &t.version),
+                    "tls_cipher_suite": tls.as_ref().map(|t| &t.cipher_suite),
+                    "duration_ms": duration_ms,
+                    "read_bytes": read_bytes,
+                    "write_bytes": write_bytes,
+                    "reason": reason,
+                    "suppressed": conn.suppressed,
+                });
+                if let Some(records) = conn.log.records.as_ref() {
+                    // Records are never written on the runtime's threads; if
+                    // the writer falls behind, the record is dropped rather
+                    // than blocking the proxy.
+                    if records.try_send(record.to_string()).is_err() {
+                        conn.log.limit.suppressed.fetch_add(1, Ordering::AcqRel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes JSON record lines to stdout, independently of the diagnostic log,
+/// until the connection log is dropped.
+fn write_stdout(records: mpsc::Receiver<String>) {
+    let stdout = std::io::stdout();
+    for record in records {
+        let mut stdout = stdout.lock();
+        let _ = writeln!(stdout, "{}", record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use svc::{NewService, ServiceExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Clone)]
+    struct Addrs;
+
+    impl Param<Remote<ClientAddr>> for Addrs {
+        fn param(&self) -> Remote<ClientAddr> {
+            Remote(ClientAddr(([10, 0, 0, 1], 12345).into()))
+        }
+    }
+
+    impl Param<OrigDstAddr> for Addrs {
+        fn param(&self) -> OrigDstAddr {
+            OrigDstAddr(([10, 0, 0, 2], 8080).into())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        fn lines(&self) -> Vec<String> {
+            let logs = self.0.lock().unwrap();
+            String::from_utf8_lossy(&*logs)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    /// Serves a single logged connection on which the server reads a ping and
+    /// writes a pong.
+    async fn serve(log: &ConnectionLog) {
+        let (client, server) = tokio::io::duplex(64);
+        let (accepted, io) = log.accept(Addrs, server);
+        let mut new_conn = NewConnectionLog::new(|_: Addrs| {
+            svc::mk(|mut io: Io<io::DuplexStream>| async move {
+                record_protocol("h2");
+                let mut buf = [0u8; 4];
+                io.read_exact(&mut buf).await?;
+                io.write_all(b"pong!").await?;
+                Ok::<_, Error>(())
+            })
+        });
+
+        let mut client = client;
+        client.write_all(b"ping").await.unwrap();
+        new_conn.new_service(accepted).oneshot(io).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn logs_accept_and_close() {
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .without_time()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let log = ConnectionLog::new(1, Format::Plain);
+        serve(&log).await;
+        // The second connection exceeds the limit, so it is not logged.
+        serve(&log).await;
+
+        let lines = logs.lines();
+        assert_eq!(lines.len(), 2, "{:#?}", lines);
+        let accept = &lines[0];
+        assert!(accept.contains("Connection accepted"), "{}", accept);
+        assert!(accept.contains("client.addr=10.0.0.1:12345"), "{}", accept);
+        assert!(accept.contains("target.port=8080"), "{}", accept);
+
+        let close = &lines[1];
+        assert!(close.contains("Connection closed"), "{}", close);
+        assert!(close.contains("client.addr=10.0.0.1:12345"), "{}", close);
+        assert!(close.contains("protocol=\"h2\""), "{}", close);
+        assert!(close.contains("read_bytes=4"), "{}", close);
+        assert!(close.contains("write_bytes=5"), "{}", close);
+        assert!(close.contains("reason=\"closed\""), "{}", close);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn logs_json_record_on_close() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let log = ConnectionLog::with_records(10, Format::Json, Some(tx));
+        serve(&log).await;
+
+        let records = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(records.len(), 1, "{:#?}", records);
+        let record = serde_json::from_str::<serde_json::Value>(&records[0]).unwrap();
+        assert_eq!(
+            record,
+            json!({
+                "client_addr": "10.0.0.1:12345",
+                "client_id": null,
+                "server_addr": "10.0.0.2:8080",
+                "port": 8080,
+                "protocol": "h2",
+                "tls_version": null,
+                "tls_cipher_suite": null,
+                "duration_ms": 0,
+                "read_bytes": 4,
+                "write_bytes": 5,
+                "reason": "closed",
+                "suppressed": 0,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn drops_records_when_the_writer_falls_behind() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let log = ConnectionLog::with_records(10, Format::Json, Some(tx));
+        serve(&log).await;
+        // The writer has not consumed the first record, so the second is
+        // dropped instead of blocking the connection.
+        serve(&log).await;
+
+        let records = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(records.len(), 1, "{:#?}", records);
+
+        serve(&log).await;
+        let record = rx.try_recv().expect("record must be buffered");
+        let record = serde_json::from_str::<serde_json::Value>(&record).unwrap();
+        assert_eq!(
+            record["suppressed"], 1,
+            "dropped records must be reported as suppressed"
+        );
+    }
+}
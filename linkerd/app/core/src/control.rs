@@ -80,7 +80,8 @@ impl Config {
             }
         };
 
-        svc::stack(ConnectTcp::new(self.connect.keepalive))
+        let connect = ConnectTcp::new(self.connect.keepalive, self.connect.dscp.clone());
+        svc::stack(connect)
             .push(tls::Client::layer(identity))
             .push_timeout(self.connect.timeout)
             .push(self::client::layer())
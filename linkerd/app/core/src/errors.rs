@@ -1,23 +1,36 @@
-use http::{header::HeaderValue, StatusCode};
+use bytes::Bytes;
+use http::{
+    header::{HeaderName, HeaderValue},
+    StatusCode,
+};
 use linkerd_errno::Errno;
 use linkerd_error::Error;
 use linkerd_error_metrics::{self as error_metrics, RecordErrorLayer, Registry};
 use linkerd_error_respond as respond;
 pub use linkerd_error_respond::RespondLayer;
 use linkerd_metrics::{metrics, Counter, FmtLabels, FmtMetrics};
-use linkerd_proxy_http::{ClientHandle, HasH2Reason};
+use linkerd_proxy_http::{ClientHandle, HasH2Reason, RequestBodyTimedOut};
 use linkerd_timeout::{FailFastError, ResponseTimeout};
 use linkerd_tls as tls;
+use linkerd_trace_context as trace_context;
+use parking_lot::Mutex;
 use pin_project::pin_project;
+use std::convert::TryFrom;
 use std::fmt;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use thiserror::Error;
+use tokio::time::Instant;
 use tonic::{self as grpc, Code};
 use tracing::{debug, warn};
 
+/// Describes the proxy error that caused an error response, by default.
 pub const L5D_PROXY_ERROR: &str = "l5d-proxy-error";
 
+/// Carries the ID of the request's trace on error responses, when enabled.
+pub const L5D_PROXY_TRACE_ID: &str = "l5d-proxy-trace-id";
+
 metrics! {
     inbound_http_errors_total: Counter {
         "The total number of inbound HTTP requests that could not be processed due to a proxy error."
@@ -28,8 +41,37 @@ metrics! {
     }
 }
 
-pub fn layer() -> respond::RespondLayer<NewRespond> {
-    respond::RespondLayer::new(NewRespond(()))
+/// Synthesizes responses for proxy errors.
+///
+/// When `echo_trace_id` is set, error responses to requests that carry a trace
+/// context include the trace's ID in an `l5d-proxy-trace-id` header.
+///
+/// When `json_bodies` is set, gateway errors (502, 503, and 504 responses) for
+/// requests that accept `application/json` describe the error in a JSON body.
+///
+/// Error responses describe the error in the `error_header`, if one is set.
+/// This is usually `l5d-proxy-error`.
+///
+/// gRPC error responses carry their status in a Trailers-Only response, unless
+/// `grpc_trailers` is set, in which case the status is sent in the trailers of
+/// an empty response body.
+pub fn layer(
+    echo_trace_id: bool,
+    json_bodies: bool,
+    error_header: Option<HeaderName>,
+    grpc_trailers: bool,
+) -> respond::RespondLayer<NewRespond> {
+    respond::RespondLayer::new(NewRespond {
+        echo_trace_id,
+        json_bodies,
+        error_header,
+        grpc_trailers,
+    })
+}
+
+/// Returns the default header in which proxy errors are described.
+pub fn proxy_error_header() -> Option<HeaderName> {
+    Some(HeaderName::from_static(L5D_PROXY_ERROR))
 }
 
 #[derive(Clone)]
@@ -57,24 +99,68 @@ pub struct HttpError {
 pub enum Reason {
     DispatchTimeout,
     ResponseTimeout,
+    RequestBodyTimeout,
     IdentityRequired,
     Io(Option<Errno>),
     FailFast,
     GatewayLoop,
     NotFound,
+    BadRequest,
+    RateLimited,
+    CircuitOpen,
+    LoadShed,
+    ConnectFailed,
+    UpstreamReset,
     Unexpected,
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct NewRespond(());
+#[derive(Clone, Debug)]
+pub struct NewRespond {
+    echo_trace_id: bool,
+    json_bodies: bool,
+    error_header: Option<HeaderName>,
+    grpc_trailers: bool,
+}
 
 #[derive(Clone, Debug)]
 pub struct Respond {
     version: http::Version,
     is_grpc: bool,
+    grpc_trailers: bool,
     client: Option<ClientHandle>,
+    trace_id: Option<HeaderValue>,
+    rate_limit: Option<ErrorRateLimit>,
+    is_json: bool,
+    redact: bool,
+    error_header: Option<HeaderName>,
 }
 
+/// Limits the rate at which errors are described by error responses.
+///
+/// When set as a request extension, errors in excess of the limit are not
+/// logged and are answered with a generic 503 Service Unavailable, so that an
+/// unavailable application doesn't flood the proxy with work. Successful
+/// responses are never limited.
+#[derive(Clone, Debug)]
+pub struct ErrorRateLimit(Arc<Mutex<Tokens>>);
+
+/// When set as a request extension, error responses describe all errors with
+/// a generic message, so that they do not reveal the proxy's view of the
+/// cluster to the client. Response statuses are not affected.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RedactErrorMessages(());
+
+#[derive(Debug)]
+struct Tokens {
+    per_second: f64,
+    available: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Error)]
+#[error("too many proxy errors")]
+pub struct ErrorResponsesThrottled(());
+
 #[pin_project(project = ResponseBodyProj)]
 pub enum ResponseBody<B> {
     NonGrpc(#[pin] B),
@@ -87,6 +173,15 @@ pub enum ResponseBody<B> {
 
 const GRPC_CONTENT_TYPE: &str = "application/grpc";
 
+const GRPC_STATUS: &str = "grpc-status";
+
+const GRPC_MESSAGE: &str = "grpc-message";
+
+/// Replaces error messages for clients that must not see the proxy's errors.
+const REDACTED_MESSAGE: &str = "upstream error";
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
 impl<B: hyper::body::HttpBody> hyper::body::HttpBody for ResponseBody<B>
 where
     B::Error: Into<Error>,
@@ -101,8 +196,11 @@ where
         match self.project() {
             ResponseBodyProj::NonGrpc(inner) => inner.poll_data(cx),
             ResponseBodyProj::Grpc { inner, trailers } => {
-                // should not be calling poll_data if we have set trailers derived from an error
-                assert!(trailers.is_none());
+                // Once trailers have been derived from an error, the body has
+                // no more data.
+                if trailers.is_some() {
+                    return Poll::Ready(None);
+                }
                 match inner.poll_data(cx) {
                     Poll::Ready(Some(Err(error))) => {
                         let error = error.into();
@@ -141,6 +239,11 @@ where
     fn size_hint(&self) -> http_body::SizeHint {
         match self {
             Self::NonGrpc(inner) => inner.size_hint(),
+            // A length is not reported for a body that ends with error
+            // trailers, so that the server does not set a content-length.
+            Self::Grpc {
+                trailers: Some(_), ..
+            } => http_body::SizeHint::default(),
             Self::Grpc { inner, .. } => inner.size_hint(),
         }
     }
@@ -152,7 +255,7 @@ impl<B: Default + hyper::body::HttpBody> Default for ResponseBody<B> {
     }
 }
 
-impl<ReqB, RspB: Default + hyper::body::HttpBody>
+impl<ReqB, RspB: Default + From<Bytes> + hyper::body::HttpBody>
     respond::NewRespond<http::Request<ReqB>, http::Response<RspB>> for NewRespond
 {
     type Response = http::Response<ResponseBody<RspB>>;
@@ -162,6 +265,17 @@ impl<ReqB, RspB: Default + hyper::body::HttpBody>
         let client = req.extensions().get::<ClientHandle>().cloned();
         debug_assert!(client.is_some(), "Missing client handle");
 
+        let trace_id = if self.echo_trace_id {
+            trace_context::trace_id(req).and_then(|id| HeaderValue::try_from(id.to_string()).ok())
+        } else {
+            None
+        };
+        let rate_limit = req.extensions().get::<ErrorRateLimit>().cloned();
+        let is_json = self.json_bodies && accepts_json(req.headers());
+        let redact = req.extensions().get::<RedactErrorMessages>().is_some();
+        let error_header = self.error_header.clone();
+        let grpc_trailers = self.grpc_trailers;
+
         match req.version() {
             http::Version::HTTP_2 => {
                 let is_grpc = req
@@ -171,20 +285,34 @@ impl<ReqB, RspB: Default + hyper::body::HttpBody>
                     .unwrap_or(false);
                 Respond {
                     is_grpc,
+                    grpc_trailers,
                     client,
+                    trace_id,
+                    rate_limit,
+                    is_json,
+                    redact,
+                    error_header,
                     version: http::Version::HTTP_2,
                 }
             }
             version => Respond {
                 version,
                 client,
+                trace_id,
+                rate_limit,
+                is_json,
+                redact,
+                error_header,
                 is_grpc: false,
+                grpc_trailers,
             },
         }
     }
 }
 
-impl<RspB: Default + hyper::body::HttpBody> respond::Respond<http::Response<RspB>> for Respond {
+impl<RspB: Default + From<Bytes> + hyper::body::HttpBody> respond::Respond<http::Response<RspB>>
+    for Respond
+{
     type Response = http::Response<ResponseBody<RspB>>;
 
     fn respond(&self, res: Result<http::Response<RspB>, Error>) -> Result<Self::Response, Error> {
@@ -205,7 +333,16 @@ impl<RspB: Default + hyper::body::HttpBody> respond::Respond<http::Response<RspB
                         debug!("Missing client address");
                         ([0, 0, 0, 0], 0).into()
                     });
-                warn!(client.addr = %addr, "Failed to proxy request: {}", error);
+                let throttled = self
+                    .rate_limit
+                    .as_ref()
+                    .map(|limit| !limit.try_acquire())
+                    .unwrap_or(false);
+                if throttled {
+                    debug!(client.addr = %addr, "Throttling error response: {}", error);
+                } else {
+                    warn!(client.addr = %addr, "Failed to proxy request: {}", error);
+                }
 
                 if self.version == http::Version::HTTP_2 {
                     if let Some(reset) = error.h2_reason() {
@@ -214,33 +351,84 @@ impl<RspB: Default + hyper::body::HttpBody> respond::Respond<http::Response<RspB
                     }
                 }
 
+                let error = if throttled {
+                    ErrorResponsesThrottled(()).into()
+                } else {
+                    error
+                };
+
                 // Gracefully teardown the server-side connection.
                 if let Some(ClientHandle { ref close, .. }) = self.client.as_ref() {
                     debug!("Closing server-side connection");
                     close.close();
                 }
 
-                // Set the l5d error header on all responses.
+                // Set the error header on all responses, if it's enabled.
+                let message = if self.redact {
+                    Some(HeaderValue::from_static(REDACTED_MESSAGE))
+                } else {
+                    proxy_error_message(&*error)
+                };
                 let mut builder = http::Response::builder();
-                builder = set_l5d_proxy_error_header(builder, &*error);
+                if let (Some(name), Some(message)) = (self.error_header.as_ref(), message.clone()) {
+                    builder = builder.header(name, message);
+                }
+                if let Some(trace_id) = self.trace_id.clone() {
+                    builder = builder.header(L5D_PROXY_TRACE_ID, trace_id);
+                }
 
                 if self.is_grpc {
                     let mut rsp = builder
                         .version(http::Version::HTTP_2)
-                        .header(http::header::CONTENT_LENGTH, "0")
                         .header(http::header::CONTENT_TYPE, GRPC_CONTENT_TYPE)
                         .body(ResponseBody::default())
                         .expect("app::errors response is valid");
-                    let code = set_grpc_status(&*error, rsp.headers_mut());
+
+                    if self.grpc_trailers {
+                        // The status is sent in the response's trailers so
+                        // that clients do not mistake the response for a
+                        // truncated success. The body carries the trailers,
+                        // so no content-length is set.
+                        let mut trailers = http::HeaderMap::new();
+                        let code = set_grpc_status(&*error, &mut trailers);
+                        if self.redact {
+                            trailers
+                                .insert(GRPC_MESSAGE, HeaderValue::from_static(REDACTED_MESSAGE));
+                        }
+                        *rsp.body_mut() = ResponseBody::Grpc {
+                            inner: RspB::default(),
+                            trailers: Some(trailers),
+                        };
+                        debug!(?code, "Handling error with gRPC status in trailers");
+                        return Ok(rsp);
+                    }
+
+                    let headers = rsp.headers_mut();
+                    headers.insert(http::header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+                    let code = set_grpc_status(&*error, headers);
+                    if self.redact {
+                        headers.insert(GRPC_MESSAGE, HeaderValue::from_static(REDACTED_MESSAGE));
+                    }
                     debug!(?code, "Handling error with gRPC status");
                     return Ok(rsp);
                 }
 
-                let rsp = set_http_status(builder, &*error)
+                let mut rsp = set_http_status(builder, &*error)
                     .version(self.version)
                     .header(http::header::CONTENT_LENGTH, "0")
                     .body(ResponseBody::default())
                     .expect("error response must be valid");
+                if self.is_json {
+                    if let Some(json) = json_body(&rsp, message.as_ref(), &*error) {
+                        let headers = rsp.headers_mut();
+                        headers.insert(http::header::CONTENT_LENGTH, json.len().into());
+                        headers.insert(
+                            http::header::CONTENT_TYPE,
+                            HeaderValue::from_static(JSON_CONTENT_TYPE),
+                        );
+                        *rsp.body_mut() = ResponseBody::NonGrpc(RspB::from(json));
+                    }
+                }
                 let status = rsp.status();
                 debug!(%status, version = ?self.version, "Handling error with HTTP response");
                 Ok(rsp)
@@ -249,47 +437,98 @@ impl<RspB: Default + hyper::body::HttpBody> respond::Respond<http::Response<RspB
     }
 }
 
-fn set_l5d_proxy_error_header(
-    mut builder: http::response::Builder,
+/// Returns true if the request's `Accept` header permits JSON responses.
+///
+/// JSON is matched by `application/json`, `application/*`, and `*/*` media
+/// ranges. As in content negotiation, the most specific of these ranges
+/// determines whether JSON is acceptable, so JSON may be excluded by a range
+/// with a quality of zero even when a wildcard range permits it.
+fn accepts_json(headers: &http::HeaderMap) -> bool {
+    let mut matched = None::<(u8, bool)>;
+    let ranges = headers
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+    for range in ranges {
+        let mut parts = range.split(';').map(str::trim);
+        let (ty, subty) = match parts.next().and_then(|media| media.split_once('/')) {
+            Some((ty, subty)) => (ty.trim(), subty.trim()),
+            None => continue,
+        };
+        let specificity = if ty == "*" && subty == "*" {
+            0
+        } else if !ty.eq_ignore_ascii_case("application") {
+            continue;
+        } else if subty == "*" {
+            1
+        } else if subty.eq_ignore_ascii_case("json") {
+            2
+        } else {
+            continue;
+        };
+
+        // Media ranges with a quality of zero are not acceptable.
+        let acceptable = !parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map(|q| q <= 0.0)
+                .unwrap_or(false)
+        });
+        if matched.map_or(true, |(s, _)| specificity > s) {
+            matched = Some((specificity, acceptable));
+        }
+    }
+    matched.map_or(false, |(_, acceptable)| acceptable)
+}
+
+/// Describes gateway errors as a JSON object with the error's message and
+/// its reason. Other errors have no body.
+fn json_body<B>(
+    rsp: &http::Response<B>,
+    message: Option<&HeaderValue>,
     error: &(dyn std::error::Error + 'static),
-) -> http::response::Builder {
+) -> Option<Bytes> {
+    let status = rsp.status();
+    if status != StatusCode::BAD_GATEWAY
+        && status != StatusCode::SERVICE_UNAVAILABLE
+        && status != StatusCode::GATEWAY_TIMEOUT
+    {
+        return None;
+    }
+    let message = message?.to_str().ok()?;
+    let body = serde_json::json!({
+        "error": message,
+        "code": LabelError::reason(error).as_str(),
+    });
+    Some(body.to_string().into())
+}
+
+fn proxy_error_message(error: &(dyn std::error::Error + 'static)) -> Option<HeaderValue> {
     if let Some(HttpError { message, .. }) = error.downcast_ref::<HttpError>() {
-        builder.header(L5D_PROXY_ERROR, HeaderValue::from_static(message))
+        Some(HeaderValue::from_static(message))
     } else if error.is::<ResponseTimeout>() {
-        builder.header(
-            L5D_PROXY_ERROR,
-            HeaderValue::from_static("request timed out"),
-        )
+        Some(HeaderValue::from_static("request timed out"))
     } else if error.is::<ConnectTimeout>() {
-        builder.header(
-            L5D_PROXY_ERROR,
-            HeaderValue::from_static("failed to connect"),
-        )
+        Some(HeaderValue::from_static("failed to connect"))
     } else if let Some(e) = error.downcast_ref::<FailFastError>() {
-        builder.header(
-            L5D_PROXY_ERROR,
+        Some(
             HeaderValue::from_str(&e.to_string()).unwrap_or_else(|error| {
                 warn!(%error, "Failed to encode fail-fast error message");
                 HeaderValue::from_static("service in fail-fast")
             }),
         )
     } else if error.is::<tower::timeout::error::Elapsed>() {
-        builder.header(
-            L5D_PROXY_ERROR,
-            HeaderValue::from_static("proxy dispatch timed out"),
-        )
-    } else if error.is::<IdentityRequired>() {
-        if let Ok(msg) = HeaderValue::from_str(&error.to_string()) {
-            builder = builder.header(L5D_PROXY_ERROR, msg)
-        }
-        builder
+        Some(HeaderValue::from_static("proxy dispatch timed out"))
+    } else if error.is::<IdentityRequired>() || error.is::<RequestBodyTooLarge>() {
+        HeaderValue::from_str(&error.to_string()).ok()
+    } else if error.is::<ErrorResponsesThrottled>() {
+        Some(HeaderValue::from_static("too many proxy errors"))
     } else if let Some(source) = error.source() {
-        set_l5d_proxy_error_header(builder, source)
+        proxy_error_message(source)
     } else {
-        builder.header(
-            L5D_PROXY_ERROR,
-            HeaderValue::from_static("proxy received invalid response"),
-        )
+        Some(HeaderValue::from_static("proxy received invalid response"))
     }
 }
 
@@ -301,6 +540,8 @@ fn set_http_status(
         builder.status(*http)
     } else if error.is::<ResponseTimeout>() {
         builder.status(StatusCode::GATEWAY_TIMEOUT)
+    } else if error.is::<RequestBodyTimedOut>() {
+        builder.status(StatusCode::REQUEST_TIMEOUT)
     } else if error.is::<ConnectTimeout>() {
         builder.status(StatusCode::GATEWAY_TIMEOUT)
     } else if error.is::<FailFastError>() {
@@ -309,6 +550,10 @@ fn set_http_status(
         builder.status(StatusCode::SERVICE_UNAVAILABLE)
     } else if error.is::<IdentityRequired>() {
         builder.status(StatusCode::FORBIDDEN)
+    } else if error.is::<RequestBodyTooLarge>() {
+        builder.status(StatusCode::PAYLOAD_TOO_LARGE)
+    } else if error.is::<ErrorResponsesThrottled>() {
+        builder.status(StatusCode::SERVICE_UNAVAILABLE)
     } else if let Some(source) = error.source() {
         set_http_status(builder, source)
     } else {
@@ -320,9 +565,6 @@ fn set_grpc_status(
     error: &(dyn std::error::Error + 'static),
     headers: &mut http::HeaderMap,
 ) -> grpc::Code {
-    const GRPC_STATUS: &str = "grpc-status";
-    const GRPC_MESSAGE: &str = "grpc-message";
-
     if let Some(HttpError { grpc, message, .. }) = error.downcast_ref::<HttpError>() {
         headers.insert(GRPC_STATUS, code_header(*grpc));
         headers.insert(GRPC_MESSAGE, HeaderValue::from_static(message));
@@ -332,6 +574,14 @@ fn set_grpc_status(
         headers.insert(GRPC_STATUS, code_header(code));
         headers.insert(GRPC_MESSAGE, HeaderValue::from_static("request timed out"));
         code
+    } else if error.is::<RequestBodyTimedOut>() {
+        let code = Code::DeadlineExceeded;
+        headers.insert(GRPC_STATUS, code_header(code));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("request body timed out"),
+        );
+        code
     } else if let Some(e) = error.downcast_ref::<FailFastError>() {
         let code = Code::Unavailable;
         headers.insert(GRPC_STATUS, code_header(code));
@@ -358,6 +608,21 @@ fn set_grpc_status(
             headers.insert(GRPC_MESSAGE, msg);
         }
         code
+    } else if error.is::<RequestBodyTooLarge>() {
+        let code = Code::ResourceExhausted;
+        headers.insert(GRPC_STATUS, code_header(code));
+        if let Ok(msg) = HeaderValue::from_str(&error.to_string()) {
+            headers.insert(GRPC_MESSAGE, msg);
+        }
+        code
+    } else if error.is::<ErrorResponsesThrottled>() {
+        let code = Code::Unavailable;
+        headers.insert(GRPC_STATUS, code_header(code));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("too many proxy errors"),
+        );
+        code
     } else if error.is::<std::io::Error>() {
         let code = Code::Unavailable;
         headers.insert(GRPC_STATUS, code_header(code));
@@ -423,18 +688,30 @@ impl fmt::Display for IdentityRequired {
 
 impl std::error::Error for IdentityRequired {}
 
+/// Indicates that a request's body exceeded the number of bytes permitted by
+/// the proxy.
+#[derive(Copy, Clone, Debug, Error)]
+#[error("request body exceeds the limit of {limit} bytes")]
+pub struct RequestBodyTooLarge {
+    pub limit: u64,
+}
+
 impl LabelError {
     fn reason(err: &(dyn std::error::Error + 'static)) -> Reason {
         if let Some(HttpError { reason, .. }) = err.downcast_ref::<HttpError>() {
             *reason
         } else if err.is::<ResponseTimeout>() {
             Reason::ResponseTimeout
+        } else if err.is::<RequestBodyTimedOut>() {
+            Reason::RequestBodyTimeout
         } else if err.is::<FailFastError>() {
             Reason::FailFast
         } else if err.is::<tower::timeout::error::Elapsed>() {
             Reason::DispatchTimeout
         } else if err.is::<IdentityRequired>() {
             Reason::IdentityRequired
+        } else if err.is::<RequestBodyTooLarge>() {
+            Reason::BadRequest
         } else if let Some(e) = err.downcast_ref::<std::io::Error>() {
             Reason::Io(e.raw_os_error().map(Errno::from))
         } else if let Some(e) = err.source() {
@@ -453,22 +730,31 @@ impl error_metrics::LabelError<Error> for LabelError {
     }
 }
 
+impl Reason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Reason::FailFast => "failfast",
+            Reason::DispatchTimeout => "dispatch timeout",
+            Reason::ResponseTimeout => "response timeout",
+            Reason::RequestBodyTimeout => "request body timeout",
+            Reason::IdentityRequired => "identity required",
+            Reason::GatewayLoop => "gateway loop",
+            Reason::NotFound => "not found",
+            Reason::BadRequest => "bad request",
+            Reason::RateLimited => "rate limited",
+            Reason::CircuitOpen => "circuit open",
+            Reason::LoadShed => "load shed",
+            Reason::ConnectFailed => "connect failed",
+            Reason::UpstreamReset => "upstream reset",
+            Reason::Io(_) => "i/o",
+            Reason::Unexpected => "unexpected",
+        }
+    }
+}
+
 impl FmtLabels for Reason {
     fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "message=\"{}\"",
-            match self {
-                Reason::FailFast => "failfast",
-                Reason::DispatchTimeout => "dispatch timeout",
-                Reason::ResponseTimeout => "response timeout",
-                Reason::IdentityRequired => "identity required",
-                Reason::GatewayLoop => "gateway loop",
-                Reason::NotFound => "not found",
-                Reason::Io(_) => "i/o",
-                Reason::Unexpected => "unexpected",
-            }
-        )?;
+        write!(f, "message=\"{}\"", self.as_str())?;
 
         if let Reason::Io(Some(errno)) = self {
             write!(f, ",errno=\"{}\"", errno)?;
@@ -520,6 +806,96 @@ impl HttpError {
         }
     }
 
+    pub fn bad_request(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::BAD_REQUEST,
+            grpc: Code::InvalidArgument,
+            reason: Reason::BadRequest,
+        }
+    }
+
+    pub fn conflict(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::CONFLICT,
+            grpc: Code::AlreadyExists,
+            reason: Reason::BadRequest,
+        }
+    }
+
+    pub fn uri_too_long(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::URI_TOO_LONG,
+            grpc: Code::InvalidArgument,
+            reason: Reason::BadRequest,
+        }
+    }
+
+    pub fn header_fields_too_large(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            grpc: Code::InvalidArgument,
+            reason: Reason::BadRequest,
+        }
+    }
+
+    pub fn too_many_requests(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::TOO_MANY_REQUESTS,
+            grpc: Code::ResourceExhausted,
+            reason: Reason::RateLimited,
+        }
+    }
+
+    pub fn circuit_open(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::SERVICE_UNAVAILABLE,
+            grpc: Code::Unavailable,
+            reason: Reason::CircuitOpen,
+        }
+    }
+
+    pub fn load_shed(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::SERVICE_UNAVAILABLE,
+            grpc: Code::Unavailable,
+            reason: Reason::LoadShed,
+        }
+    }
+
+    pub fn connect_failed(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::BAD_GATEWAY,
+            grpc: Code::Unavailable,
+            reason: Reason::ConnectFailed,
+        }
+    }
+
+    pub fn upstream_reset(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::BAD_GATEWAY,
+            grpc: Code::Unavailable,
+            reason: Reason::UpstreamReset,
+        }
+    }
+
+    pub fn gateway_timeout(message: &'static str) -> Self {
+        Self {
+            message,
+            http: StatusCode::GATEWAY_TIMEOUT,
+            grpc: Code::DeadlineExceeded,
+            reason: Reason::ResponseTimeout,
+        }
+    }
+
     pub fn gateway_loop() -> Self {
         Self {
             message: "gateway loop detected",
@@ -534,6 +910,35 @@ impl HttpError {
     }
 }
 
+// === impl ErrorRateLimit ===
+
+impl ErrorRateLimit {
+    /// Permits up to `per_second` error responses each second, with bursts of
+    /// up to `per_second` responses.
+    pub fn per_second(per_second: u32) -> Self {
+        let per_second = f64::from(per_second);
+        Self(Arc::new(Mutex::new(Tokens {
+            per_second,
+            available: per_second,
+            updated: Instant::now(),
+        })))
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut tokens = self.0.lock();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(tokens.updated);
+        tokens.updated = now;
+        tokens.available =
+            (tokens.available + elapsed.as_secs_f64() * tokens.per_second).min(tokens.per_second);
+        if tokens.available < 1.0 {
+            return false;
+        }
+        tokens.available -= 1.0;
+        true
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ConnectTimeout(pub std::time::Duration);
 
@@ -544,3 +949,313 @@ impl fmt::Display for ConnectTimeout {
 }
 
 impl std::error::Error for ConnectTimeout {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{self, ServiceExt};
+    use linkerd_proxy_http::SetClientHandle;
+    use respond::Respond as _;
+
+    /// Builds a request that carries a client handle, as it would be when
+    /// received by a server.
+    async fn request(trace_id: Option<&'static str>) -> http::Request<()> {
+        let mut req = http::Request::builder().uri("http://example.com/");
+        if let Some(trace_id) = trace_id {
+            req = req
+                .header("x-b3-traceid", trace_id)
+                .header("x-b3-spanid", "0123456789abcdef")
+                .header("x-b3-sampled", "1");
+        }
+        let inner = svc::mk(|req: http::Request<()>| futures::future::ok::<_, Error>(req));
+        let (svc, _closed) = SetClientHandle::new(([192, 0, 2, 3], 50000).into(), inner);
+        svc.oneshot(req.body(()).unwrap()).await.unwrap()
+    }
+
+    fn respond(
+        echo_trace_id: bool,
+        req: &http::Request<()>,
+        rsp: Result<http::Response<hyper::Body>, Error>,
+    ) -> http::Response<ResponseBody<hyper::Body>> {
+        let new_respond = NewRespond {
+            echo_trace_id,
+            json_bodies: false,
+            error_header: proxy_error_header(),
+            grpc_trailers: false,
+        };
+        respond_with(&new_respond, req, rsp)
+    }
+
+    fn respond_with(
+        new_respond: &NewRespond,
+        req: &http::Request<()>,
+        rsp: Result<http::Response<hyper::Body>, Error>,
+    ) -> http::Response<ResponseBody<hyper::Body>> {
+        let respond =
+            respond::NewRespond::<_, http::Response<hyper::Body>>::new_respond(new_respond, req);
+        respond.respond(rsp).expect("must respond")
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn echoes_trace_id_on_errors() {
+        const TRACE_ID: &str = "0123456789abcdef0123456789abcdef";
+        let traced = request(Some(TRACE_ID)).await;
+        let not_found = || -> Error { HttpError::not_found("not found").into() };
+
+        let rsp = respond(true, &traced, Err(not_found()));
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(rsp.headers().get(L5D_PROXY_TRACE_ID).unwrap(), TRACE_ID);
+
+        // Successful responses are never modified.
+        let rsp = respond(true, &traced, Ok(http::Response::default()));
+        assert!(rsp.headers().get(L5D_PROXY_TRACE_ID).is_none());
+
+        // Requests without a trace context have no trace ID to echo.
+        let untraced = request(None).await;
+        let rsp = respond(true, &untraced, Err(not_found()));
+        assert!(rsp.headers().get(L5D_PROXY_TRACE_ID).is_none());
+
+        // Trace IDs are only echoed when enabled.
+        let rsp = respond(false, &traced, Err(not_found()));
+        assert!(rsp.headers().get(L5D_PROXY_TRACE_ID).is_none());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn throttles_error_responses() {
+        tokio::time::pause();
+        let mut req = request(None).await;
+        req.extensions_mut().insert(ErrorRateLimit::per_second(2));
+        let not_found = || -> Error { HttpError::not_found("not found").into() };
+
+        for _ in 0..2 {
+            let rsp = respond(false, &req, Err(not_found()));
+            assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+        }
+        let rsp = respond(false, &req, Err(not_found()));
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            rsp.headers().get(L5D_PROXY_ERROR).unwrap(),
+            "too many proxy errors"
+        );
+
+        // Successful responses are never throttled.
+        let rsp = respond(false, &req, Ok(http::Response::default()));
+        assert_eq!(rsp.status(), StatusCode::OK);
+
+        // The limit replenishes over time.
+        tokio::time::advance(std::time::Duration::from_millis(500)).await;
+        let rsp = respond(false, &req, Err(not_found()));
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+        let rsp = respond(false, &req, Err(not_found()));
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn explains_request_body_limits() {
+        let req = request(None).await;
+        let rsp = respond(false, &req, Err(RequestBodyTooLarge { limit: 1024 }.into()));
+        assert_eq!(rsp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            rsp.headers().get(L5D_PROXY_ERROR).unwrap(),
+            "request body exceeds the limit of 1024 bytes"
+        );
+    }
+
+    async fn grpc_request() -> http::Request<()> {
+        let mut req = request(None).await;
+        *req.version_mut() = http::Version::HTTP_2;
+        req.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static(GRPC_CONTENT_TYPE),
+        );
+        req
+    }
+
+    fn grpc_trailers() -> NewRespond {
+        NewRespond {
+            echo_trace_id: false,
+            json_bodies: false,
+            error_header: proxy_error_header(),
+            grpc_trailers: true,
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn sets_grpc_status_in_headers() {
+        use hyper::body::HttpBody;
+
+        let req = grpc_request().await;
+        let rsp = respond(false, &req, Err(ErrorResponsesThrottled(()).into()));
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert!(rsp.headers().contains_key(L5D_PROXY_ERROR));
+        assert_eq!(rsp.headers()[GRPC_STATUS], "14");
+        assert!(rsp.headers().contains_key(GRPC_MESSAGE));
+        assert_eq!(rsp.headers()[http::header::CONTENT_LENGTH], "0");
+        assert!(rsp.body().is_end_stream(), "response must be Trailers-Only");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn sets_grpc_status_in_trailers() {
+        use hyper::body::HttpBody;
+
+        let req = grpc_request().await;
+        let errors: Vec<(Error, &str)> = vec![
+            (ErrorResponsesThrottled(()).into(), "14"),
+            (HttpError::gateway_timeout("request timed out").into(), "4"),
+        ];
+        for (error, code) in errors {
+            let mut rsp = respond_with(&grpc_trailers(), &req, Err(error));
+            assert_eq!(rsp.status(), StatusCode::OK);
+            assert!(rsp.headers().contains_key(L5D_PROXY_ERROR));
+            assert!(rsp.headers().get(GRPC_STATUS).is_none());
+            assert!(rsp.headers().get(http::header::CONTENT_LENGTH).is_none());
+            assert!(!rsp.body().is_end_stream(), "trailers must be sent");
+            assert!(rsp.body_mut().data().await.is_none());
+            let trailers = rsp
+                .body_mut()
+                .trailers()
+                .await
+                .unwrap()
+                .expect("response must have trailers");
+            assert_eq!(trailers["grpc-status"], code);
+            assert!(trailers.contains_key("grpc-message"));
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn renders_json_error_bodies() {
+        use hyper::body::HttpBody;
+
+        let json = NewRespond {
+            echo_trace_id: false,
+            json_bodies: true,
+            error_header: proxy_error_header(),
+            grpc_trailers: false,
+        };
+        let timeout = || -> Error { HttpError::gateway_timeout("request timed out").into() };
+        let mut req = request(None).await;
+        req.headers_mut().insert(
+            http::header::ACCEPT,
+            HeaderValue::from_static("text/html, application/json;q=0.9"),
+        );
+
+        let rsp = respond_with(&json, &req, Err(timeout()));
+        assert_eq!(rsp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(rsp.headers()[L5D_PROXY_ERROR], "request timed out");
+        assert_eq!(rsp.headers()[http::header::CONTENT_TYPE], JSON_CONTENT_TYPE);
+        let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "request timed out", "code": "response timeout"})
+        );
+
+        // Errors that are not gateway errors have no body.
+        let not_found = HttpError::not_found("not found").into();
+        let rsp = respond_with(&json, &req, Err(not_found));
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+        assert!(rsp.body().is_end_stream());
+
+        // Clients that do not accept JSON receive empty bodies, as do all
+        // clients when JSON bodies are disabled.
+        req.headers_mut().insert(
+            http::header::ACCEPT,
+            HeaderValue::from_static("text/html, application/json;q=0"),
+        );
+        let rsp = respond_with(&json, &req, Err(timeout()));
+        assert!(rsp.headers().get(http::header::CONTENT_TYPE).is_none());
+        assert!(rsp.body().is_end_stream());
+        req.headers_mut().insert(
+            http::header::ACCEPT,
+            HeaderValue::from_static("application/json"),
+        );
+        let rsp = respond(false, &req, Err(timeout()));
+        assert!(rsp.body().is_end_stream());
+    }
+
+    #[test]
+    fn accepts_json_media_ranges() {
+        let accepts = |accept: &'static str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert(http::header::ACCEPT, HeaderValue::from_static(accept));
+            accepts_json(&headers)
+        };
+
+        assert!(accepts("application/json"));
+        assert!(accepts("Application/JSON; charset=utf-8"));
+        assert!(accepts("application/*"));
+        assert!(accepts("text/html, */*;q=0.8"));
+        assert!(accepts("application/json;q=0.5, application/*;q=0"));
+        assert!(!accepts("text/html"));
+        assert!(!accepts("application/xml"));
+        assert!(!accepts("*/*;q=0"));
+        assert!(!accepts("application/json;q=0, */*"));
+        assert!(!accepts("application/*;q=0, */*"));
+        assert!(!accepts(""));
+        assert!(!accepts("json"));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn renames_or_disables_error_header() {
+        let req = request(None).await;
+        let timeout = || -> Error { HttpError::gateway_timeout("request timed out").into() };
+
+        let renamed = NewRespond {
+            echo_trace_id: false,
+            json_bodies: false,
+            error_header: Some(HeaderName::from_static("x-proxy-error")),
+            grpc_trailers: false,
+        };
+        let rsp = respond_with(&renamed, &req, Err(timeout()));
+        assert_eq!(rsp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(rsp.headers()["x-proxy-error"], "request timed out");
+        assert!(rsp.headers().get(L5D_PROXY_ERROR).is_none());
+
+        // Statuses are still set when the header is disabled.
+        let disabled = NewRespond {
+            echo_trace_id: false,
+            json_bodies: false,
+            error_header: None,
+            grpc_trailers: false,
+        };
+        let rsp = respond_with(&disabled, &req, Err(timeout()));
+        assert_eq!(rsp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(rsp.headers().get(L5D_PROXY_ERROR).is_none());
+        assert!(rsp.headers().get("x-proxy-error").is_none());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn redacts_error_messages() {
+        use hyper::body::HttpBody;
+
+        let mut req = request(None).await;
+        req.extensions_mut().insert(RedactErrorMessages::default());
+        let timeout = || -> Error { HttpError::gateway_timeout("request timed out").into() };
+
+        let rsp = respond(false, &req, Err(timeout()));
+        assert_eq!(rsp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(rsp.headers()[L5D_PROXY_ERROR], REDACTED_MESSAGE);
+
+        // gRPC messages are redacted, but not their statuses.
+        *req.version_mut() = http::Version::HTTP_2;
+        req.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static(GRPC_CONTENT_TYPE),
+        );
+        let rsp = respond(false, &req, Err(timeout()));
+        assert_eq!(rsp.headers()[L5D_PROXY_ERROR], REDACTED_MESSAGE);
+        assert_eq!(rsp.headers()[GRPC_STATUS], "4");
+        assert_eq!(rsp.headers()[GRPC_MESSAGE], REDACTED_MESSAGE);
+
+        let mut rsp = respond_with(&grpc_trailers(), &req, Err(timeout()));
+        assert_eq!(rsp.headers()[L5D_PROXY_ERROR], REDACTED_MESSAGE);
+        let trailers = rsp
+            .body_mut()
+            .trailers()
+            .await
+            .unwrap()
+            .expect("response must have trailers");
+        assert_eq!(trailers[GRPC_STATUS], "4");
+        assert_eq!(trailers[GRPC_MESSAGE], REDACTED_MESSAGE);
+    }
+}
@@ -1,14 +1,24 @@
 use linkerd_error::Error;
-use linkerd_opencensus::proto::trace::v1 as oc;
+use linkerd_opencensus::{metrics, proto::trace::v1 as oc};
 use linkerd_stack::layer;
 use linkerd_trace_context::{self as trace_context, TraceContext};
 use std::{collections::HashMap, sync::Arc};
 use thiserror::Error;
 use tokio::sync::mpsc;
 
-pub type OpenCensusSink = Option<mpsc::Sender<oc::Span>>;
+pub use linkerd_trace_context::{Sample, Sampler, SpanAttributes};
+
+pub type OpenCensusSink = Option<SpanSink>;
 pub type Labels = Arc<HashMap<String, String>>;
 
+/// Sends OpenCensus spans to the collector, recording whether each traced
+/// request's span was emitted.
+#[derive(Clone, Debug)]
+pub struct SpanSink {
+    tx: mpsc::Sender<oc::Span>,
+    metrics: metrics::Registry,
+}
+
 /// SpanConverter converts trace_context::Span objects into OpenCensus agent
 /// protobuf span objects. SpanConverter receives trace_context::Span objects by
 /// implmenting the SpanSink trait. For each span that it receives, it converts
@@ -16,7 +26,7 @@ pub type Labels = Arc<HashMap<String, String>>;
 #[derive(Clone)]
 pub struct SpanConverter {
     kind: Kind,
-    sink: mpsc::Sender<oc::Span>,
+    sink: SpanSink,
     labels: Labels,
 }
 
@@ -48,6 +58,16 @@ enum Kind {
     Client = 2,
 }
 
+// === impl SpanSink ===
+
+impl SpanSink {
+    pub fn new(tx: mpsc::Sender<oc::Span>, metrics: metrics::Registry) -> Self {
+        Self { tx, metrics }
+    }
+}
+
+// === impl SpanConverter ===
+
 impl SpanConverter {
     fn layer<S>(
         kind: Kind,
@@ -81,6 +101,16 @@ impl SpanConverter {
                 },
             );
         }
+        // Request metadata does not override the proxy's own labels.
+        for (k, v) in span.attributes.iter() {
+            attributes
+                .entry(k.to_string())
+                .or_insert_with(|| oc::AttributeValue {
+                    value: Some(oc::attribute_value::Value::StringValue(truncatable(
+                        v.to_string(),
+                    ))),
+                });
+        }
         Ok(oc::Span {
             trace_id: into_bytes(span.trace_id, 16)?,
             span_id: into_bytes(span.span_id, 8)?,
@@ -113,7 +143,22 @@ impl trace_context::SpanSink for SpanConverter {
 
     fn try_send(&mut self, span: trace_context::Span) -> Result<(), Error> {
         let span = self.mk_span(span)?;
-        self.sink.try_send(span).map_err(Into::into)
+        match self.sink.tx.try_send(span) {
+            Ok(()) => {
+                self.sink.metrics.span_sampled();
+                Ok(())
+            }
+            Err(error) => {
+                if let mpsc::error::TrySendError::Full(_) = error {
+                    self.sink.metrics.span_overflowed();
+                }
+                Err(error.into())
+            }
+        }
+    }
+
+    fn record_unsampled(&mut self) {
+        self.sink.metrics.span_dropped();
     }
 }
 
@@ -137,3 +182,51 @@ fn truncatable(value: String) -> oc::TruncatableString {
         truncated_byte_count: 0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{self, Layer, Service, ServiceExt};
+    use linkerd_metrics::FmtMetrics;
+
+    fn request(sampled: bool) -> http::Request<()> {
+        http::Request::builder()
+            .uri("http://example.com/")
+            .header("x-b3-traceid", "0123456789abcdef0123456789abcdef")
+            .header("x-b3-spanid", "0123456789abcdef")
+            .header("x-b3-sampled", if sampled { "1" } else { "0" })
+            .body(())
+            .unwrap()
+    }
+
+    fn counter(report: &metrics::Report, name: &str) -> u64 {
+        let text = report.as_display().to_string();
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+            .unwrap_or_else(|| panic!("missing metric {}", name))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn counts_sampled_and_unsampled_spans() {
+        let (registry, report) = metrics::new();
+        // Leave room for only some of the sampled spans so that overflow is
+        // counted separately from unsampled requests.
+        let (tx, _rx) = mpsc::channel(2);
+        let sink = Some(SpanSink::new(tx, registry));
+
+        let inner = svc::mk(|_: http::Request<()>| {
+            futures::future::ok::<_, Error>(http::Response::new(()))
+        });
+        let mut svc = server(sink, Labels::default()).layer(inner);
+
+        // Sample one in every four requests.
+        for i in 0..12 {
+            let req = request(i % 4 == 0);
+            svc.ready().await.unwrap().call(req).await.unwrap();
+        }
+
+        assert_eq!(counter(&report, "spans_sampled_total"), 2);
+        assert_eq!(counter(&report, "spans_buffer_overflow_total"), 1);
+        assert_eq!(counter(&report, "spans_dropped_total"), 9);
+    }
+}
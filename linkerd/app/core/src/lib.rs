@@ -32,17 +32,22 @@ pub use linkerd_transport_header as transport_header;
 use thiserror::Error;
 
 mod addr_match;
+pub mod breaker;
 pub mod classify;
 pub mod config;
+pub mod connection_log;
 pub mod control;
 pub mod dns;
 pub mod dst;
 pub mod errors;
 pub mod http_tracing;
+pub mod memory_pressure;
 pub mod metrics;
 pub mod proxy;
 pub mod retry;
 pub mod serve;
+pub mod shutdown;
+pub mod soft_swap;
 pub mod svc;
 pub mod telemetry;
 pub mod transport;
@@ -59,7 +64,10 @@ pub struct ProxyRuntime {
     pub metrics: metrics::Proxy,
     pub tap: proxy::tap::Registry,
     pub span_sink: http_tracing::OpenCensusSink,
+    pub trace_sampler: http_tracing::Sampler,
     pub drain: drain::Watch,
+    pub shutdown: shutdown::ShutdownEvents,
+    pub shutdown_grace: serve::GracePeriod,
 }
 
 pub fn http_request_authority_addr<B>(req: &http::Request<B>) -> Result<Addr, addr::Error> {
@@ -0,0 +1,263 @@
+//! Pauses accepting connections while the proxy's memory usage is high.
+//!
+//! This is a last resort to avoid being killed for exceeding a memory limit.
+//! Once memory usage reaches a configured threshold, listeners stop accepting
+//! connections, so that new connections wait in the kernel's accept backlog
+//! (or are refused once it is full) instead of allocating more memory.
+//! Listeners resume once usage falls below a lower threshold, so that they do
+//! not flap while usage hovers around the limit.
+//!
+//! Memory usage is sampled periodically rather than when each connection is
+//! accepted. On Linux, usage is read from the process's cgroup (v2 or v1) or,
+//! if it is not available, from the process's resident set size. Memory
+//! pressure is not supported on other platforms.
+
+use futures::prelude::*;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time;
+use tracing::{info, warn};
+
+/// A source of the proxy's current memory usage.
+pub trait MemorySignal: fmt::Debug + Send + Sync + 'static {
+    /// Returns the number of bytes in use, if it can be determined.
+    fn memory_bytes(&self) -> Option<u64>;
+}
+
+/// Determines whether listeners pause while memory usage is high.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryPressure(Option<Arc<Thresholds>>);
+
+/// Reads the proxy's memory usage from the operating system.
+#[derive(Copy, Clone, Debug)]
+pub enum SystemMemory {
+    CgroupV2,
+    CgroupV1,
+    Rss,
+}
+
+/// A listener that stops accepting connections while memory usage is high.
+#[pin_project]
+#[derive(Debug)]
+pub struct Gated<S> {
+    #[pin]
+    inner: S,
+    check: Option<(Arc<Thresholds>, time::Interval)>,
+    paused: bool,
+}
+
+#[derive(Debug)]
+struct Thresholds {
+    signal: Box<dyn MemorySignal>,
+    pause_bytes: u64,
+    resume_bytes: u64,
+    interval: Duration,
+}
+
+// === impl MemoryPressure ===
+
+impl MemoryPressure {
+    /// Pauses listeners while `signal` reports at least `pause_bytes` in use,
+    /// until it reports at most `resume_bytes`.
+    pub fn new(
+        signal: impl MemorySignal,
+        pause_bytes: u64,
+        resume_bytes: u64,
+        interval: Duration,
+    ) -> Self {
+        Self(Some(Arc::new(Thresholds {
+            signal: Box::new(signal),
+            pause_bytes,
+            resume_bytes: resume_bytes.min(pause_bytes),
+            interval,
+        })))
+    }
+
+    /// Like `new`, but reads the proxy's memory usage from the operating
+    /// system. If it cannot be read, listeners are never paused.
+    pub fn system(pause_bytes: u64, resume_bytes: u64, interval: Duration) -> Self {
+        match SystemMemory::detect() {
+            Some(signal) => {
+                info!(
+                    ?signal,
+                    pause_bytes, resume_bytes, "Memory pressure enabled"
+                );
+                Self::new(signal, pause_bytes, resume_bytes, interval)
+            }
+            None => {
+                warn!("Memory usage cannot be determined; memory pressure is disabled");
+                Self::default()
+            }
+        }
+    }
+
+    /// Wraps a listener so that it stops accepting connections while memory
+    /// usage is high.
+    pub fn gate<S: Stream>(&self, listen: S) -> Gated<S> {
+        Gated {
+            inner: listen,
+            check: self
+                .0
+                .clone()
+                .map(|t| (t.clone(), time::interval(t.interval))),
+            paused: false,
+        }
+    }
+}
+
+// === impl Gated ===
+
+impl<S: Stream> Stream for Gated<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some((thresholds, interval)) = this.check.as_mut() {
+            // Sample memory usage on each tick. The interval is polled until
+            // it is pending so that a paused listener is woken to check
+            // whether it can resume.
+            while interval.poll_tick(cx).is_ready() {
+                *this.paused = thresholds.is_paused(*this.paused);
+            }
+            if *this.paused {
+                return Poll::Pending;
+            }
+        }
+        this.inner.poll_next(cx)
+    }
+}
+
+// === impl Thresholds ===
+
+impl Thresholds {
+    fn is_paused(&self, paused: bool) -> bool {
+        let bytes = match self.signal.memory_bytes() {
+            Some(bytes) => bytes,
+            None => return paused,
+        };
+        if !paused && bytes >= self.pause_bytes {
+            warn!(bytes, "Memory usage is high; pausing new connections");
+            return true;
+        }
+        if paused && bytes <= self.resume_bytes {
+            info!(
+                bytes,
+                "Memory usage has recovered; accepting new connections"
+            );
+            return false;
+        }
+        paused
+    }
+}
+
+// === impl SystemMemory ===
+
+#[cfg(target_os = "linux")]
+impl SystemMemory {
+    // XXX These assume that cgroup filesystems are mounted at their usual
+    // locations and that the proxy's cgroup is the namespace's root cgroup,
+    // as it is in containers.
+    const CGROUP_V2: &'static str = "/sys/fs/cgroup/memory.current";
+    const CGROUP_V1: &'static str = "/sys/fs/cgroup/memory/memory.usage_in_bytes";
+    const STATUS: &'static str = "/proc/self/status";
+
+    fn detect() -> Option<Self> {
+        [Self::CgroupV2, Self::CgroupV1, Self::Rss]
+            .iter()
+            .copied()
+            .find(|s| s.memory_bytes().is_some())
+    }
+
+    fn read_bytes(path: &str) -> Option<u64> {
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    fn read_rss() -> Option<u64> {
+        let status = std::fs::read_to_string(Self::STATUS).ok()?;
+        let kb = status
+            .lines()
+            .find_map(|l| l.strip_prefix("VmRSS:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kb * 1024)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl MemorySignal for SystemMemory {
+    fn memory_bytes(&self) -> Option<u64> {
+        match self {
+            Self::CgroupV2 => Self::read_bytes(Self::CGROUP_V2),
+            Self::CgroupV1 => Self::read_bytes(Self::CGROUP_V1),
+            Self::Rss => Self::read_rss(),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl SystemMemory {
+    fn detect() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl MemorySignal for SystemMemory {
+    fn memory_bytes(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Clone, Debug, Default)]
+    struct MockMemory(Arc<AtomicU64>);
+
+    impl MemorySignal for MockMemory {
+        fn memory_bytes(&self) -> Option<u64> {
+            Some(self.0.load(Ordering::Acquire))
+        }
+    }
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn pauses_accept_under_pressure() {
+        let memory = MockMemory::default();
+        let pressure = MemoryPressure::new(memory.clone(), 100, 80, INTERVAL);
+        let mut listen = tokio_test::task::spawn(pressure.gate(stream::iter(0u64..)));
+        assert_eq!(listen.poll_next(), Poll::Ready(Some(0)));
+
+        memory.0.store(100, Ordering::Release);
+        assert_eq!(
+            listen.poll_next(),
+            Poll::Ready(Some(1)),
+            "memory usage must only be sampled periodically"
+        );
+        time::advance(INTERVAL).await;
+        assert!(listen.poll_next().is_pending(), "accept must pause");
+
+        // Usage must fall to the lower threshold for accept to resume.
+        memory.0.store(90, Ordering::Release);
+        time::advance(INTERVAL).await;
+        assert!(listen.is_woken());
+        assert!(listen.poll_next().is_pending(), "accept must remain paused");
+
+        memory.0.store(80, Ordering::Release);
+        time::advance(INTERVAL).await;
+        assert!(listen.is_woken());
+        assert_eq!(listen.poll_next(), Poll::Ready(Some(2)));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_tcp_connection_rate_total: Counter {
+        "The total number of inbound TCP connections to ports with connection rate limits, by whether they were accepted or limited."
+    }
+}
+
+/// Counts, by target port, inbound connections that were accepted or limited
+/// by their port's connection rate limit.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<HashMap<(u16, Outcome), Counter>>>);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Accepted,
+    Limited,
+}
+
+struct Labels(u16, Outcome);
+
+// === impl Registry ===
+
+impl Registry {
+    pub fn incr(&self, port: u16, outcome: Outcome) {
+        self.0.lock().entry((port, outcome)).or_default().incr();
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = self.0.lock();
+        if counters.is_empty() {
+            return Ok(());
+        }
+
+        inbound_tcp_connection_rate_total.fmt_help(f)?;
+        for ((port, outcome), counter) in counters.iter() {
+            counter.fmt_metric_labeled(
+                f,
+                inbound_tcp_connection_rate_total.name,
+                Labels(*port, *outcome),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl FmtLabels for Labels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self.1 {
+            Outcome::Accepted => "accepted",
+            Outcome::Limited => "limited",
+        };
+        write!(f, "target_port=\"{}\",outcome=\"{}\"", self.0, outcome)
+    }
+}
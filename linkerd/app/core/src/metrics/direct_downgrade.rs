@@ -0,0 +1,29 @@
+use crate::metrics::{self, Counter, FmtMetric, FmtMetrics};
+use std::{fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_direct_alpn_downgrade_rejected_total: Counter {
+        "The total number of connections to the inbound mesh port that were rejected because the client did not negotiate the transport header protocol."
+    }
+}
+
+/// Counts mesh port connections that were rejected because their ALPN
+/// negotiation was downgraded.
+#[derive(Clone, Debug, Default)]
+pub struct Rejected(Arc<Counter>);
+
+// === impl Rejected ===
+
+impl Rejected {
+    pub fn incr(&self) {
+        self.0.incr();
+    }
+}
+
+impl FmtMetrics for Rejected {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        inbound_direct_alpn_downgrade_rejected_total.fmt_help(f)?;
+        self.0
+            .fmt_metric(f, inbound_direct_alpn_downgrade_rejected_total.name)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::metrics::{self, Counter, FmtMetric, FmtMetrics};
+use std::{fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_direct_plaintext_rejected_total: Counter {
+        "The total number of plaintext connections to the inbound mesh port that were rejected."
+    }
+}
+
+/// Counts plaintext connections that were rejected by the inbound mesh port.
+#[derive(Clone, Debug, Default)]
+pub struct Rejected(Arc<Counter>);
+
+// === impl Rejected ===
+
+impl Rejected {
+    pub fn incr(&self) {
+        self.0.incr();
+    }
+}
+
+impl FmtMetrics for Rejected {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        inbound_direct_plaintext_rejected_total.fmt_help(f)?;
+        self.0
+            .fmt_metric(f, inbound_direct_plaintext_rejected_total.name)
+    }
+}
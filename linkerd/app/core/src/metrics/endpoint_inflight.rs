@@ -0,0 +1,198 @@
+use crate::{
+    metrics::{self, FmtLabels, FmtMetric, FmtMetrics, Gauge},
+    svc,
+    transport::{Remote, ServerAddr},
+};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+metrics::metrics! {
+    outbound_endpoint_inflight_requests: Gauge {
+        "The number of requests dispatched to each outbound endpoint that are awaiting a response."
+    }
+}
+
+/// Tracks the number of requests in flight to each outbound endpoint.
+///
+/// An endpoint's gauge is shared by all of the services that dispatch requests
+/// to it. Once all of these services have been dropped (i.e., because the
+/// endpoint was removed from service discovery), its gauge is no longer
+/// reported.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    endpoints: metrics::SharedStore<Remote<ServerAddr>, Gauge>,
+}
+
+pub type NewTrackInflight<N, S> =
+    metrics::NewMetrics<N, Remote<ServerAddr>, Gauge, TrackInflight<S>>;
+
+#[derive(Clone, Debug)]
+pub struct TrackInflight<S> {
+    inner: S,
+    inflight: Arc<Gauge>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    _inflight: Inflight,
+}
+
+/// Decrements an endpoint's gauge when a request completes or is canceled.
+#[derive(Debug)]
+struct Inflight(Arc<Gauge>);
+
+struct EndpointLabel(SocketAddr);
+
+// === impl Registry ===
+
+impl Registry {
+    pub fn layer<T, N: svc::NewService<T>>(
+        &self,
+    ) -> impl svc::Layer<N, Service = NewTrackInflight<N, N::Service>> + Clone {
+        metrics::NewMetrics::layer(self.endpoints.clone())
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut endpoints = self.endpoints.lock();
+        endpoints.retain_referenced();
+        if endpoints.is_empty() {
+            return Ok(());
+        }
+
+        outbound_endpoint_inflight_requests.fmt_help(f)?;
+        for (Remote(ServerAddr(addr)), inflight) in endpoints.iter() {
+            inflight.fmt_metric_labeled(
+                f,
+                outbound_endpoint_inflight_requests.name,
+                EndpointLabel(*addr),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl EndpointLabel ===
+
+impl FmtLabels for EndpointLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "endpoint=\"{}\"", self.0)
+    }
+}
+
+// === impl TrackInflight ===
+
+impl<S> From<(S, Arc<Gauge>)> for TrackInflight<S> {
+    fn from((inner, inflight): (S, Arc<Gauge>)) -> Self {
+        Self { inner, inflight }
+    }
+}
+
+impl<Req, S> svc::Service<Req> for TrackInflight<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inflight.incr();
+        ResponseFuture {
+            inner: self.inner.call(req),
+            _inflight: Inflight(self.inflight.clone()),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F: Future> Future for ResponseFuture<F> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx)
+    }
+}
+
+// === impl Inflight ===
+
+impl Drop for Inflight {
+    fn drop(&mut self) {
+        self.0.decr();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::{Layer, NewService, ServiceExt};
+    use tokio::sync::oneshot;
+
+    fn endpoint(port: u16) -> Remote<ServerAddr> {
+        Remote(ServerAddr(([10, 0, 0, 1], port).into()))
+    }
+
+    fn gauge(report: &str, port: u16) -> Option<&str> {
+        let prefix = format!(
+            "outbound_endpoint_inflight_requests{{endpoint=\"10.0.0.1:{}\"}} ",
+            port
+        );
+        report
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix.as_str()))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tracks_inflight_requests() {
+        let registry = Registry::default();
+        let mut new_svc = registry
+            .layer()
+            .layer(|_: Remote<ServerAddr>| svc::mk(|rx: oneshot::Receiver<()>| rx));
+        let mut svc0 = new_svc.new_service(endpoint(8080));
+        let mut svc1 = new_svc.new_service(endpoint(8081));
+        assert_eq!(registry.as_display().to_string(), "");
+
+        let (tx0, rx0) = oneshot::channel();
+        let (_tx1, rx1) = oneshot::channel();
+        let (_tx2, rx2) = oneshot::channel();
+        let rsp0 = svc0.ready().await.unwrap().call(rx0);
+        let rsp1 = svc0.ready().await.unwrap().call(rx1);
+        let rsp2 = svc1.ready().await.unwrap().call(rx2);
+        let report = registry.as_display().to_string();
+        assert_eq!(gauge(&report, 8080), Some("2"));
+        assert_eq!(gauge(&report, 8081), Some("1"));
+
+        // Completed and canceled requests are no longer in flight.
+        tx0.send(()).unwrap();
+        rsp0.await.unwrap();
+        drop(rsp1);
+        let report = registry.as_display().to_string();
+        assert_eq!(gauge(&report, 8080), Some("0"));
+        assert_eq!(gauge(&report, 8081), Some("1"));
+
+        // Once an endpoint's services are dropped, its gauge is not reported.
+        drop((rsp2, svc1));
+        let report = registry.as_display().to_string();
+        assert_eq!(gauge(&report, 8080), Some("0"));
+        assert_eq!(gauge(&report, 8081), None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge};
+use linkerd_addr::NameAddr;
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+metrics::metrics! {
+    outbound_http_failover_total: Counter {
+        "The total number of times outbound HTTP traffic failed over from a logical service to its backup."
+    },
+
+    outbound_http_failover_active: Gauge {
+        "The number of outbound HTTP balancers whose traffic is currently routed to their logical service's backup."
+    }
+}
+
+/// Tracks failovers from each logical service to its backup.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<HashMap<FailoverLabels, Arc<Failovers>>>>);
+
+#[derive(Debug, Default)]
+pub struct Failovers {
+    total: Counter,
+    active: Gauge,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct FailoverLabels {
+    logical: NameAddr,
+    backup: NameAddr,
+}
+
+// === impl Registry ===
+
+impl Registry {
+    pub fn failovers(&self, logical: NameAddr, backup: NameAddr) -> Arc<Failovers> {
+        self.0
+            .lock()
+            .entry(FailoverLabels { logical, backup })
+            .or_default()
+            .clone()
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failovers = self.0.lock();
+        if failovers.is_empty() {
+            return Ok(());
+        }
+
+        outbound_http_failover_total.fmt_help(f)?;
+        for (labels, m) in failovers.iter() {
+            m.total
+                .fmt_metric_labeled(f, outbound_http_failover_total.name, labels)?;
+        }
+
+        outbound_http_failover_active.fmt_help(f)?;
+        for (labels, m) in failovers.iter() {
+            m.active
+                .fmt_metric_labeled(f, outbound_http_failover_active.name, labels)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Failovers ===
+
+impl Failovers {
+    /// Records that traffic has been routed to the backup service.
+    pub fn failed_over(&self) {
+        self.total.incr();
+        self.active.incr();
+    }
+
+    /// Records that traffic has been routed back to the logical service.
+    pub fn recovered(&self) {
+        self.active.decr();
+    }
+}
+
+// === impl FailoverLabels ===
+
+impl FmtLabels for FailoverLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "logical=\"{}\",backup=\"{}\"", self.logical, self.backup)
+    }
+}
@@ -0,0 +1,414 @@
+use crate::{
+    io,
+    metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics, LastUpdate},
+    svc::{self, Param},
+    tls,
+};
+use futures::{ready, TryFuture};
+use linkerd_errno::Errno;
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+metrics::metrics! {
+    inbound_tcp_forward_bytes_total: Counter {
+        "The total number of bytes forwarded on inbound TCP connections, by target port, client identity, and direction."
+    }
+}
+
+/// Counts the bytes forwarded on inbound TCP connections, by the port they
+/// target and by the identities of their clients.
+///
+/// The bytes read from each connection's client and the bytes written to the
+/// application are counted separately. These complement the transport
+/// metrics, which describe each connection without its client's identity.
+/// Ports and identities without open connections are dropped once they have
+/// been idle for the retention period.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    connections: metrics::SharedStore<Key, Bytes>,
+    retain_idle: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    port: u16,
+    client_id: Option<tls::ClientId>,
+}
+
+#[derive(Debug)]
+struct Bytes {
+    from_client: Counter,
+    to_app: Counter,
+    last_update: Mutex<Instant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewCountClientBytes<N> {
+    inner: N,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct CountClientBytes<S> {
+    inner: S,
+    bytes: Arc<Bytes>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CountAppBytes<C> {
+    inner: C,
+    registry: Registry,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct Connecting<F> {
+    #[pin]
+    inner: F,
+    bytes: Arc<Bytes>,
+}
+
+/// Counts the bytes read from a forwarded connection's client.
+#[derive(Debug)]
+pub struct ClientSensor(Arc<Bytes>);
+
+/// Counts the bytes written to the application on a forwarded connection.
+#[derive(Debug)]
+pub struct AppSensor(Arc<Bytes>);
+
+pub type ClientIo<I> = io::SensorIo<I, ClientSensor>;
+
+pub type AppIo<I> = io::SensorIo<I, AppSensor>;
+
+struct Direction(&'static str);
+
+// === impl Registry ===
+
+impl Registry {
+    pub(super) fn new(retain_idle: Duration) -> Self {
+        Self {
+            connections: Default::default(),
+            retain_idle,
+        }
+    }
+
+    /// Counts the bytes read from the clients of forwarded connections.
+    pub fn layer_client<N>(&self) -> impl svc::Layer<N, Service = NewCountClientBytes<N>> + Clone {
+        let registry = self.clone();
+        svc::layer::mk(move |inner| NewCountClientBytes {
+            inner,
+            registry: registry.clone(),
+        })
+    }
+
+    /// Counts the bytes written to the application on forwarded connections.
+    pub fn layer_connect<C>(&self) -> impl svc::Layer<C, Service = CountAppBytes<C>> + Clone {
+        let registry = self.clone();
+        svc::layer::mk(move |inner| CountAppBytes {
+            inner,
+            registry: registry.clone(),
+        })
+    }
+
+    fn bytes<T>(&self, target: &T) -> Arc<Bytes>
+    where
+        T: Param<u16> + Param<Option<tls::ClientId>>,
+    {
+        let key = Key {
+            port: target.param(),
+            client_id: target.param(),
+        };
+        self.connections.lock().get_or_default(key).clone()
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut connections = self.connections.lock();
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        inbound_tcp_forward_bytes_total.fmt_help(f)?;
+        for (key, bytes) in connections.iter() {
+            bytes.from_client.fmt_metric_labeled(
+                f,
+                inbound_tcp_forward_bytes_total.name,
+                (key, Direction("from_client")),
+            )?;
+            bytes.to_app.fmt_metric_labeled(
+                f,
+                inbound_tcp_forward_bytes_total.name,
+                (key, Direction("to_app")),
+            )?;
+        }
+
+        connections.retain_since(Instant::now() - self.retain_idle);
+        Ok(())
+    }
+}
+
+// === impl NewCountClientBytes ===
+
+impl<T, N> svc::NewService<T> for NewCountClientBytes<N>
+where
+    T: Param<u16> + Param<Option<tls::ClientId>>,
+    N: svc::NewService<T>,
+{
+    type Service = CountClientBytes<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let bytes = self.registry.bytes(&target);
+        CountClientBytes {
+            inner: self.inner.new_service(target),
+            bytes,
+        }
+    }
+}
+
+// === impl CountClientBytes ===
+
+impl<I, S> svc::Service<I> for CountClientBytes<S>
+where
+    S: svc::Service<ClientIo<I>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        self.inner
+            .call(io::SensorIo::new(io, ClientSensor(self.bytes.clone())))
+    }
+}
+
+// === impl CountAppBytes ===
+
+impl<T, C> svc::Service<T> for CountAppBytes<C>
+where
+    T: Param<u16> + Param<Option<tls::ClientId>>,
+    C: svc::Service<T>,
+{
+    type Response = AppIo<C::Response>;
+    type Error = C::Error;
+    type Future = Connecting<C::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let bytes = self.registry.bytes(&target);
+        Connecting {
+            inner: self.inner.call(target),
+            bytes,
+        }
+    }
+}
+
+// === impl Connecting ===
+
+impl<F: TryFuture> Future for Connecting<F> {
+    type Output = Result<AppIo<F::Ok>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let io = ready!(this.inner.try_poll(cx))?;
+        Poll::Ready(Ok(io::SensorIo::new(io, AppSensor(this.bytes.clone()))))
+    }
+}
+
+// === impl ClientSensor ===
+
+impl io::Sensor for ClientSensor {
+    fn record_read(&mut self, sz: usize) {
+        self.0.from_client.add(sz as u64);
+        *self.0.last_update.lock() = Instant::now();
+    }
+
+    fn record_write(&mut self, _: usize) {}
+
+    fn record_close(&mut self, _: Option<Errno>) {}
+
+    fn record_error<T>(&mut self, op: Poll<T>) -> Poll<T> {
+        op
+    }
+}
+
+// === impl AppSensor ===
+
+impl io::Sensor for AppSensor {
+    fn record_read(&mut self, _: usize) {}
+
+    fn record_write(&mut self, sz: usize) {
+        self.0.to_app.add(sz as u64);
+        *self.0.last_update.lock() = Instant::now();
+    }
+
+    fn record_close(&mut self, _: Option<Errno>) {}
+
+    fn record_error<T>(&mut self, op: Poll<T>) -> Poll<T> {
+        op
+    }
+}
+
+// === impl Bytes ===
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Self {
+            from_client: Counter::default(),
+            to_app: Counter::default(),
+            last_update: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl LastUpdate for Bytes {
+    fn last_update(&self) -> Instant {
+        *self.last_update.lock()
+    }
+}
+
+// === impl Key ===
+
+impl FmtLabels for Key {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port=\"{}\",", self.port)?;
+        match self.client_id.as_ref() {
+            Some(id) => write!(f, "client_id=\"{}\"", id),
+            None => f.write_str("client_id=\"\""),
+        }
+    }
+}
+
+// === impl Direction ===
+
+impl FmtLabels for Direction {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "direction=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{Layer, NewService, ServiceExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FOO: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+
+    #[derive(Clone)]
+    struct Target(u16, Option<&'static str>);
+
+    impl Param<u16> for Target {
+        fn param(&self) -> u16 {
+            self.0
+        }
+    }
+
+    impl Param<Option<tls::ClientId>> for Target {
+        fn param(&self) -> Option<tls::ClientId> {
+            self.1.map(|id| id.parse().unwrap())
+        }
+    }
+
+    fn counter<'r>(report: &'r str, labels: &str) -> Option<&'r str> {
+        let prefix = format!("inbound_tcp_forward_bytes_total{{{}}} ", labels);
+        report
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix.as_str()))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn counts_forwarded_bytes() {
+        let registry = Registry::new(Duration::from_secs(60));
+        let target = Target(8080, Some(FOO));
+
+        // The forwarder reads data from the client...
+        let (client_io, mut client) = tokio::io::duplex(64);
+        client.write_all(b"hello").await.unwrap();
+        registry
+            .layer_client()
+            .layer(|_: Target| {
+                svc::mk(|mut io: ClientIo<tokio::io::DuplexStream>| async move {
+                    io.read_exact(&mut [0; 5]).await?;
+                    Ok::<_, std::io::Error>(())
+                })
+            })
+            .new_service(target.clone())
+            .oneshot(client_io)
+            .await
+            .unwrap();
+
+        // ...and writes it to the application, which responds.
+        let (app_io, mut app) = tokio::io::duplex(64);
+        let mut app_io = Some(app_io);
+        let mut dst = registry
+            .layer_connect()
+            .layer(svc::mk(move |_: Target| {
+                futures::future::ok::<_, std::io::Error>(app_io.take().unwrap())
+            }))
+            .oneshot(target)
+            .await
+            .unwrap();
+        dst.write_all(b"hello").await.unwrap();
+        app.write_all(b"world").await.unwrap();
+        dst.read_exact(&mut [0; 5]).await.unwrap();
+
+        let report = registry.as_display().to_string();
+        let labels = format!("port=\"8080\",client_id=\"{}\"", FOO);
+        assert_eq!(
+            counter(&report, &format!("{},direction=\"from_client\"", labels)),
+            Some("5")
+        );
+        assert_eq!(
+            counter(&report, &format!("{},direction=\"to_app\"", labels)),
+            Some("5"),
+            "data read from the application must not be counted"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn drops_idle_connections() {
+        let registry = Registry::new(Duration::from_secs(0));
+        let (app_io, _app) = tokio::io::duplex(64);
+        let mut app_io = Some(app_io);
+        let dst = registry
+            .layer_connect()
+            .layer(svc::mk(move |_: Target| {
+                futures::future::ok::<_, std::io::Error>(app_io.take().unwrap())
+            }))
+            .oneshot(Target(8080, Some(FOO)))
+            .await
+            .unwrap();
+
+        // Counters are retained while their connections are open...
+        let labels = format!("port=\"8080\",client_id=\"{}\",direction=\"to_app\"", FOO);
+        let report = registry.as_display().to_string();
+        assert_eq!(counter(&report, &labels), Some("0"));
+        let report = registry.as_display().to_string();
+        assert_eq!(counter(&report, &labels), Some("0"));
+
+        // ...and dropped once they have been idle for the retention period,
+        // after they are last reported.
+        drop(dst);
+        let report = registry.as_display().to_string();
+        assert_eq!(counter(&report, &labels), Some("0"));
+        assert_eq!(registry.as_display().to_string(), "");
+    }
+}
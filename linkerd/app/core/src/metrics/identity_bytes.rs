@@ -0,0 +1,317 @@
+use crate::{
+    io,
+    metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics, LastUpdate},
+    svc::{self, Param},
+    tls,
+};
+use linkerd_errno::Errno;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+metrics::metrics! {
+    inbound_identity_bytes_total: Counter {
+        "The total number of bytes read from and written to inbound connections, by client identity."
+    }
+}
+
+/// Aggregates the bytes transferred on inbound connections by the identities
+/// of their clients.
+///
+/// Connections without a client identity (i.e. plaintext connections and TLS
+/// connections without a client certificate) are counted as `anonymous`.
+/// Identities without open connections are dropped once they have been idle
+/// for the retention period, so that identities that are only seen briefly do
+/// not accumulate.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    identities: metrics::SharedStore<ClientIdentity, Bytes>,
+    retain_idle: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ClientIdentity(Option<tls::ClientId>);
+
+#[derive(Debug)]
+struct Bytes {
+    read: Counter,
+    write: Counter,
+    last_update: Mutex<Instant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewCountBytes<N> {
+    inner: N,
+    identities: Option<metrics::SharedStore<ClientIdentity, Bytes>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CountBytes<S> {
+    inner: S,
+    bytes: Option<Arc<Bytes>>,
+}
+
+#[derive(Debug)]
+pub struct Sensor(Option<Arc<Bytes>>);
+
+pub type SensorIo<I> = io::SensorIo<I, Sensor>;
+
+struct Direction(&'static str);
+
+// === impl Registry ===
+
+impl Registry {
+    pub(super) fn new(retain_idle: Duration) -> Self {
+        Self {
+            identities: Default::default(),
+            retain_idle,
+        }
+    }
+
+    /// Counts the bytes transferred on accepted connections, if enabled.
+    pub fn layer<N>(
+        &self,
+        enabled: bool,
+    ) -> impl svc::Layer<N, Service = NewCountBytes<N>> + Clone {
+        let identities = if enabled {
+            Some(self.identities.clone())
+        } else {
+            None
+        };
+        svc::layer::mk(move |inner| NewCountBytes {
+            inner,
+            identities: identities.clone(),
+        })
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut identities = self.identities.lock();
+        if identities.is_empty() {
+            return Ok(());
+        }
+
+        inbound_identity_bytes_total.fmt_help(f)?;
+        for (id, bytes) in identities.iter() {
+            bytes.read.fmt_metric_labeled(
+                f,
+                inbound_identity_bytes_total.name,
+                (id, Direction("read")),
+            )?;
+            bytes.write.fmt_metric_labeled(
+                f,
+                inbound_identity_bytes_total.name,
+                (id, Direction("write")),
+            )?;
+        }
+
+        identities.retain_since(Instant::now() - self.retain_idle);
+        Ok(())
+    }
+}
+
+// === impl NewCountBytes ===
+
+impl<T, N> svc::NewService<T> for NewCountBytes<N>
+where
+    T: Param<tls::ConditionalServerTls>,
+    N: svc::NewService<T>,
+{
+    type Service = CountBytes<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let bytes = self.identities.as_ref().map(|identities| {
+            let id = match target.param() {
+                tls::ConditionalServerTls::Some(tls::ServerTls::Established {
+                    client_id, ..
+                }) => client_id,
+                _ => None,
+            };
+            identities.lock().get_or_default(ClientIdentity(id)).clone()
+        });
+        CountBytes {
+            inner: self.inner.new_service(target),
+            bytes,
+        }
+    }
+}
+
+// === impl CountBytes ===
+
+impl<I, S> svc::Service<I> for CountBytes<S>
+where
+    S: svc::Service<SensorIo<I>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        self.inner
+            .call(SensorIo::new(io, Sensor(self.bytes.clone())))
+    }
+}
+
+// === impl Sensor ===
+
+impl io::Sensor for Sensor {
+    fn record_read(&mut self, sz: usize) {
+        if let Some(bytes) = self.0.as_ref() {
+            bytes.read.add(sz as u64);
+            *bytes.last_update.lock() = Instant::now();
+        }
+    }
+
+    fn record_write(&mut self, sz: usize) {
+        if let Some(bytes) = self.0.as_ref() {
+            bytes.write.add(sz as u64);
+            *bytes.last_update.lock() = Instant::now();
+        }
+    }
+
+    fn record_close(&mut self, _: Option<Errno>) {}
+
+    fn record_error<T>(&mut self, op: Poll<T>) -> Poll<T> {
+        op
+    }
+}
+
+// === impl Bytes ===
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Self {
+            read: Counter::default(),
+            write: Counter::default(),
+            last_update: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl LastUpdate for Bytes {
+    fn last_update(&self) -> Instant {
+        *self.last_update.lock()
+    }
+}
+
+// === impl ClientIdentity ===
+
+impl FmtLabels for ClientIdentity {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_ref() {
+            Some(id) => write!(f, "identity=\"{}\"", id),
+            None => f.write_str("identity=\"anonymous\""),
+        }
+    }
+}
+
+// === impl Direction ===
+
+impl FmtLabels for Direction {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "direction=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        svc::{Layer, NewService, ServiceExt},
+        Conditional,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FOO: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+    const BAR: &str = "bar.ns1.serviceaccount.identity.linkerd.cluster.local";
+
+    fn tls(id: Option<&str>) -> tls::ConditionalServerTls {
+        Conditional::Some(tls::ServerTls::Established {
+            client_id: id.map(|id| id.parse().unwrap()),
+            negotiated_protocol: None,
+        })
+    }
+
+    /// Accepts a connection that sends `request` and is answered with
+    /// `response`.
+    async fn forward(
+        registry: &Registry,
+        tls: tls::ConditionalServerTls,
+        request: &'static [u8],
+        response: &'static [u8],
+    ) {
+        let server = svc::mk(
+            move |mut io: SensorIo<tokio::io::DuplexStream>| async move {
+                let mut buf = vec![0; request.len()];
+                io.read_exact(&mut buf).await?;
+                io.write_all(response).await?;
+                Ok::<_, std::io::Error>(())
+            },
+        );
+        let mut new = registry
+            .layer(true)
+            .layer(move |_: tls::ConditionalServerTls| server.clone());
+
+        let (mut client, io) = tokio::io::duplex(1024);
+        client.write_all(request).await.unwrap();
+        new.new_service(tls).oneshot(io).await.unwrap();
+        let mut buf = vec![0; response.len()];
+        client.read_exact(&mut buf).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn counts_bytes_by_identity() {
+        let registry = Registry::new(Duration::from_secs(60));
+
+        forward(&registry, tls(Some(FOO)), b"hello", b"world!").await;
+        forward(&registry, tls(Some(FOO)), b"hi", b"ok").await;
+        forward(&registry, tls(Some(BAR)), b"0123456789", b"").await;
+        forward(&registry, tls(None), b"abc", b"de").await;
+        forward(
+            &registry,
+            Conditional::None(tls::NoServerTls::NoClientHello),
+            b"fgh",
+            b"ij",
+        )
+        .await;
+
+        let metrics = registry.as_display().to_string();
+        for (id, direction, bytes) in &[
+            (FOO, "read", 7),
+            (FOO, "write", 8),
+            (BAR, "read", 10),
+            (BAR, "write", 0),
+            ("anonymous", "read", 6),
+            ("anonymous", "write", 4),
+        ] {
+            let line = format!(
+                "inbound_identity_bytes_total{{identity=\"{}\",direction=\"{}\"}} {}\n",
+                id, direction, bytes
+            );
+            assert!(metrics.contains(&line), "{} not in:\n{}", line, metrics);
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn disabled() {
+        let registry = Registry::new(Duration::from_secs(60));
+        let mut new = registry.layer(false).layer(|_: tls::ConditionalServerTls| {
+            svc::mk(|_: SensorIo<tokio::io::DuplexStream>| {
+                futures::future::ok::<_, std::io::Error>(())
+            })
+        });
+        let (_client, io) = tokio::io::duplex(1024);
+        new.new_service(tls(None)).oneshot(io).await.unwrap();
+        assert_eq!(registry.as_display().to_string(), "");
+    }
+}
@@ -0,0 +1,56 @@
+use crate::metrics::{self, FmtLabels, FmtMetric, FmtMetrics, Gauge};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, net::SocketAddr, sync::Arc};
+
+metrics::metrics! {
+    inbound_local_circuit_breaker_open: Gauge {
+        "Whether the circuit breaker of each inbound target address is open (1) or closed (0)."
+    }
+}
+
+/// Reports the state of the circuit breaker of each inbound target address.
+///
+/// A target's gauge is reported once its breaker has been created, even if it
+/// has never opened.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<HashMap<SocketAddr, Arc<Gauge>>>>);
+
+struct TargetAddr(SocketAddr);
+
+// === impl Registry ===
+
+impl Registry {
+    /// Returns the gauge of the target's breaker, which is incremented when the
+    /// breaker opens and decremented when it closes.
+    pub fn gauge(&self, addr: SocketAddr) -> Arc<Gauge> {
+        self.0.lock().entry(addr).or_default().clone()
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let breakers = self.0.lock();
+        if breakers.is_empty() {
+            return Ok(());
+        }
+
+        inbound_local_circuit_breaker_open.fmt_help(f)?;
+        for (addr, open) in breakers.iter() {
+            open.fmt_metric_labeled(
+                f,
+                inbound_local_circuit_breaker_open.name,
+                TargetAddr(*addr),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl TargetAddr ===
+
+impl FmtLabels for TargetAddr {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "target_addr=\"{}\"", self.0)
+    }
+}
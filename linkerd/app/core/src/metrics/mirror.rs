@@ -0,0 +1,110 @@
+use crate::metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use linkerd_addr::NameAddr;
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+metrics::metrics! {
+    outbound_http_mirror_comparisons_total: Counter {
+        "The total number of responses from mirrored candidate services that were compared against the logical service's responses."
+    }
+}
+
+/// Tracks comparisons of mirrored responses from each logical service's
+/// candidate.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<HashMap<MirrorLabels, Arc<Comparisons>>>>);
+
+#[derive(Debug, Default)]
+pub struct Comparisons {
+    matched: Counter,
+    mismatched: Counter,
+    failed: Counter,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct MirrorLabels {
+    logical: NameAddr,
+    candidate: NameAddr,
+}
+
+struct ResultLabel(&'static str);
+
+// === impl Registry ===
+
+impl Registry {
+    pub fn comparisons(&self, logical: NameAddr, candidate: NameAddr) -> Arc<Comparisons> {
+        self.0
+            .lock()
+            .entry(MirrorLabels { logical, candidate })
+            .or_default()
+            .clone()
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparisons = self.0.lock();
+        if comparisons.is_empty() {
+            return Ok(());
+        }
+
+        outbound_http_mirror_comparisons_total.fmt_help(f)?;
+        for (labels, m) in comparisons.iter() {
+            for &(result, counter) in &[
+                ("match", &m.matched),
+                ("mismatch", &m.mismatched),
+                ("failure", &m.failed),
+            ] {
+                counter.fmt_metric_labeled(
+                    f,
+                    outbound_http_mirror_comparisons_total.name,
+                    (labels, ResultLabel(result)),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Comparisons ===
+
+impl Comparisons {
+    /// Records that a mirrored response matched the logical service's
+    /// response.
+    pub fn matched(&self) {
+        self.matched.incr();
+    }
+
+    /// Records that a mirrored response differed from the logical service's
+    /// response.
+    pub fn mismatched(&self) {
+        self.mismatched.incr();
+    }
+
+    /// Records that a mirrored request failed or timed out, so that its
+    /// response could not be compared.
+    pub fn failed(&self) {
+        self.failed.incr();
+    }
+}
+
+// === impl MirrorLabels ===
+
+impl FmtLabels for MirrorLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "logical=\"{}\",candidate=\"{}\"",
+            self.logical, self.candidate
+        )
+    }
+}
+
+// === impl ResultLabel ===
+
+impl FmtLabels for ResultLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "result=\"{}\"", self.0)
+    }
+}
@@ -1,4 +1,17 @@
+pub mod connection_rate;
+mod direct_downgrade;
+mod direct_plaintext;
+mod endpoint_inflight;
+pub mod failover;
+mod forward_bytes;
+mod identity_bytes;
+pub mod local_breaker;
+pub mod mirror;
+pub mod policy_decisions;
+mod port_denied;
+mod source_rejected;
 mod tcp_accept_errors;
+mod tls_required;
 
 use crate::{
     classify::{Class, SuccessOrFailure},
@@ -16,9 +29,12 @@ pub use linkerd_metrics::*;
 use std::{
     fmt::{self, Write},
     net::SocketAddr,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
+pub use http_metrics::StatusLabels;
+
 pub type ControlHttp = http_metrics::Requests<ControlLabels, Class>;
 
 pub type HttpEndpoint = http_metrics::Requests<EndpointLabels, Class>;
@@ -35,10 +51,23 @@ pub struct Proxy {
     pub http_route_actual: HttpRoute,
     pub http_route_retry: HttpRouteRetry,
     pub http_endpoint: HttpEndpoint,
+    pub http_endpoint_inflight: endpoint_inflight::Registry,
     pub http_errors: errors::MetricsLayer,
     pub stack: Stack,
     pub transport: transport::Metrics,
     pub tcp_accept_errors: tcp_accept_errors::Registry,
+    pub direct_plaintext_rejected: direct_plaintext::Rejected,
+    pub direct_downgrade_rejected: direct_downgrade::Rejected,
+    pub tcp_source_rejected: source_rejected::Rejected,
+    pub tcp_port_denied: port_denied::Denied,
+    pub tcp_policy_decisions: policy_decisions::Registry,
+    pub tcp_tls_required_denied: tls_required::Denied,
+    pub tcp_connection_rate: connection_rate::Registry,
+    pub tcp_identity_bytes: identity_bytes::Registry,
+    pub tcp_forward_bytes: forward_bytes::Registry,
+    pub http_failover: failover::Registry,
+    pub http_mirror: mirror::Registry,
+    pub http_local_breakers: local_breaker::Registry,
 }
 
 #[derive(Clone, Debug)]
@@ -66,6 +95,7 @@ pub struct InboundEndpointLabels {
     pub tls: tls::ConditionalServerTls,
     pub authority: Option<http::uri::Authority>,
     pub target_addr: SocketAddr,
+    pub class: Option<PortClass>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -90,6 +120,13 @@ pub struct RouteLabels {
     labels: Option<String>,
 }
 
+/// An operator-defined class of inbound ports (e.g. `api` or `admin`), used to
+/// group the metrics of traffic on those ports.
+///
+/// Ports that are not assigned a class are labeled `unclassified`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PortClass(Option<Arc<str>>);
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     In,
@@ -115,25 +152,31 @@ where
 // === impl Metrics ===
 
 impl Metrics {
-    pub fn new(retain_idle: Duration) -> (Self, impl FmtMetrics + Clone + Send + 'static) {
+    pub fn new(
+        retain_idle: Duration,
+        status_labels: StatusLabels,
+    ) -> (Self, impl FmtMetrics + Clone + Send + 'static) {
         let process = telemetry::process::Report::new(SystemTime::now());
 
         let build_info = telemetry::build_info::Report::new();
 
         let (control, control_report) = {
-            let m = metrics::Requests::<ControlLabels, Class>::default();
+            let m = metrics::Requests::<ControlLabels, Class>::default()
+                .with_status_labels(status_labels.clone());
             let r = m.clone().into_report(retain_idle).with_prefix("control");
             (m, r)
         };
 
         let (http_endpoint, endpoint_report) = {
-            let m = metrics::Requests::<EndpointLabels, Class>::default();
+            let m = metrics::Requests::<EndpointLabels, Class>::default()
+                .with_status_labels(status_labels.clone());
             let r = m.clone().into_report(retain_idle);
             (m, r)
         };
 
         let (http_route, route_report) = {
-            let m = metrics::Requests::<RouteLabels, Class>::default();
+            let m = metrics::Requests::<RouteLabels, Class>::default()
+                .with_status_labels(status_labels.clone());
             let r = m.clone().into_report(retain_idle).with_prefix("route");
             (m, r)
         };
@@ -145,7 +188,8 @@ impl Metrics {
         };
 
         let (http_route_actual, actual_report) = {
-            let m = metrics::Requests::<RouteLabels, Class>::default();
+            let m = metrics::Requests::<RouteLabels, Class>::default()
+                .with_status_labels(status_labels);
             let r = m
                 .clone()
                 .into_report(retain_idle)
@@ -159,14 +203,33 @@ impl Metrics {
 
         let (transport, transport_report) = transport::metrics::new(retain_idle);
 
+        let http_endpoint_inflight = endpoint_inflight::Registry::default();
+
         let inbound_tcp_accept_errors = tcp_accept_errors::Registry::inbound();
         let outbound_tcp_accept_errors = tcp_accept_errors::Registry::outbound();
 
+        let direct_plaintext_rejected = direct_plaintext::Rejected::default();
+        let direct_downgrade_rejected = direct_downgrade::Rejected::default();
+        let tcp_source_rejected = source_rejected::Rejected::default();
+        let tcp_port_denied = port_denied::Denied::default();
+        let tcp_policy_decisions = policy_decisions::Registry::default();
+        let tcp_tls_required_denied = tls_required::Denied::default();
+        let tcp_connection_rate = connection_rate::Registry::default();
+        let tcp_identity_bytes = identity_bytes::Registry::new(retain_idle);
+        let tcp_forward_bytes = forward_bytes::Registry::new(retain_idle);
+
+        let http_failover = failover::Registry::default();
+        let http_mirror = mirror::Registry::default();
+
+        let http_local_breakers = local_breaker::Registry::default();
+
         let (opencensus, opencensus_report) = opencensus::metrics::new();
 
         let metrics = Metrics {
             inbound: Proxy {
                 http_endpoint: http_endpoint.clone(),
+                // Only the outbound proxy balances requests over endpoints.
+                http_endpoint_inflight: http_endpoint_inflight.clone(),
                 http_route: http_route.clone(),
                 http_route_actual: http_route_actual.clone(),
                 http_route_retry: http_route_retry.clone(),
@@ -174,9 +237,24 @@ impl Metrics {
                 stack: stack.clone(),
                 transport: transport.clone(),
                 tcp_accept_errors: inbound_tcp_accept_errors.clone(),
+                direct_plaintext_rejected: direct_plaintext_rejected.clone(),
+                direct_downgrade_rejected: direct_downgrade_rejected.clone(),
+                tcp_source_rejected: tcp_source_rejected.clone(),
+                tcp_port_denied: tcp_port_denied.clone(),
+                tcp_policy_decisions: tcp_policy_decisions.clone(),
+                tcp_tls_required_denied: tcp_tls_required_denied.clone(),
+                tcp_connection_rate: tcp_connection_rate.clone(),
+                tcp_identity_bytes: tcp_identity_bytes.clone(),
+                tcp_forward_bytes: tcp_forward_bytes.clone(),
+                // Only the outbound proxy fails over to backup services or
+                // mirrors requests to candidate services.
+                http_failover: http_failover.clone(),
+                http_mirror: http_mirror.clone(),
+                http_local_breakers: http_local_breakers.clone(),
             },
             outbound: Proxy {
                 http_endpoint,
+                http_endpoint_inflight: http_endpoint_inflight.clone(),
                 http_route,
                 http_route_retry,
                 http_route_actual,
@@ -184,6 +262,22 @@ impl Metrics {
                 stack: stack.clone(),
                 transport,
                 tcp_accept_errors: outbound_tcp_accept_errors.clone(),
+                // Only the inbound proxy has a mesh port or restricts
+                // connections by their source address, port, or rate.
+                direct_plaintext_rejected: direct_plaintext_rejected.clone(),
+                direct_downgrade_rejected: direct_downgrade_rejected.clone(),
+                tcp_source_rejected: tcp_source_rejected.clone(),
+                tcp_port_denied: tcp_port_denied.clone(),
+                tcp_policy_decisions: tcp_policy_decisions.clone(),
+                tcp_tls_required_denied: tcp_tls_required_denied.clone(),
+                tcp_connection_rate: tcp_connection_rate.clone(),
+                tcp_identity_bytes: tcp_identity_bytes.clone(),
+                tcp_forward_bytes: tcp_forward_bytes.clone(),
+                http_failover: http_failover.clone(),
+                http_mirror: http_mirror.clone(),
+                // Only the inbound proxy breaks circuits to the local
+                // application.
+                http_local_breakers: http_local_breakers.clone(),
             },
             control,
             opencensus,
@@ -191,6 +285,7 @@ impl Metrics {
 
         let report = (http_errors.report())
             .and_then(endpoint_report)
+            .and_then(http_endpoint_inflight)
             .and_then(route_report)
             .and_then(retry_report)
             .and_then(actual_report)
@@ -198,6 +293,18 @@ impl Metrics {
             .and_then(transport_report)
             .and_then(inbound_tcp_accept_errors)
             .and_then(outbound_tcp_accept_errors)
+            .and_then(direct_plaintext_rejected)
+            .and_then(direct_downgrade_rejected)
+            .and_then(tcp_source_rejected)
+            .and_then(tcp_port_denied)
+            .and_then(tcp_policy_decisions)
+            .and_then(tcp_tls_required_denied)
+            .and_then(tcp_connection_rate)
+            .and_then(tcp_identity_bytes)
+            .and_then(tcp_forward_bytes)
+            .and_then(http_failover)
+            .and_then(http_mirror)
+            .and_then(http_local_breakers)
             .and_then(opencensus_report)
             .and_then(stack)
             .and_then(process)
@@ -284,6 +391,11 @@ impl FmtLabels for InboundEndpointLabels {
 
         (TargetAddr(self.target_addr), TlsAccept::from(&self.tls)).fmt_labels(f)?;
 
+        if let Some(class) = self.class.as_ref() {
+            write!(f, ",")?;
+            class.fmt_labels(f)?;
+        }
+
         Ok(())
     }
 }
@@ -316,6 +428,25 @@ impl fmt::Display for Direction {
     }
 }
 
+// === impl PortClass ===
+
+impl PortClass {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self(Some(name.into()))
+    }
+
+    pub fn unclassified() -> Self {
+        Self(None)
+    }
+}
+
+impl FmtLabels for PortClass {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.0.as_deref().unwrap_or("unclassified");
+        write!(f, "port_class=\"{}\"", name)
+    }
+}
+
 impl FmtLabels for Direction {
     fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "direction=\"{}\"", self)
@@ -0,0 +1,129 @@
+use crate::metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_policy_decisions_total: Counter {
+        "The total number of inbound connections, by the policy that permitted or denied them."
+    }
+}
+
+/// Counts inbound connections by target port, by the policy that decided
+/// whether they were permitted, and by that decision.
+///
+/// Connections are also labeled by the outcome of protocol detection, so that
+/// decisions may be correlated with the kind of traffic they apply to.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<HashMap<Key, Counter>>>);
+
+/// The policy that decided whether a connection was permitted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Policy {
+    /// No policy restricts connections to the port.
+    Default,
+
+    /// Connections are denied by default, unless they target an allowed port.
+    AllowPorts,
+
+    /// Connections are restricted by their source networks.
+    SourceNetworks,
+
+    /// Connections must have a client identity.
+    RequireIdentity,
+
+    /// Connections must be secured by TLS.
+    RequireTls,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Decision {
+    Allow,
+    Deny,
+
+    /// The connection was denied because it was not secured by TLS or did not
+    /// have a client identity.
+    TlsRequired,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// HTTP was detected.
+    Http,
+
+    /// Protocol detection completed without detecting HTTP.
+    Tcp,
+
+    /// Protocol detection is disabled for the port.
+    Opaque,
+
+    /// The decision was made before protocol detection completed.
+    Undetected,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    port: u16,
+    policy: Policy,
+    decision: Decision,
+    protocol: Protocol,
+}
+
+// === impl Registry ===
+
+impl Registry {
+    pub fn incr(&self, port: u16, policy: Policy, decision: Decision, protocol: Protocol) {
+        let key = Key {
+            port,
+            policy,
+            decision,
+            protocol,
+        };
+        self.0.lock().entry(key).or_default().incr();
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decisions = self.0.lock();
+        if decisions.is_empty() {
+            return Ok(());
+        }
+
+        inbound_policy_decisions_total.fmt_help(f)?;
+        for (key, counter) in decisions.iter() {
+            counter.fmt_metric_labeled(f, inbound_policy_decisions_total.name, key)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Key ===
+
+impl FmtLabels for Key {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policy = match self.policy {
+            Policy::Default => "default",
+            Policy::AllowPorts => "allow_ports",
+            Policy::SourceNetworks => "source_networks",
+            Policy::RequireIdentity => "require_identity",
+            Policy::RequireTls => "require_tls",
+        };
+        let decision = match self.decision {
+            Decision::Allow => "allow",
+            Decision::Deny => "deny",
+            Decision::TlsRequired => "tls_required",
+        };
+        let protocol = match self.protocol {
+            Protocol::Http => "http",
+            Protocol::Tcp => "tcp",
+            Protocol::Opaque => "opaque",
+            Protocol::Undetected => "undetected",
+        };
+        write!(
+            f,
+            "port=\"{}\",policy=\"{}\",decision=\"{}\",protocol=\"{}\"",
+            self.port, policy, decision, protocol
+        )
+    }
+}
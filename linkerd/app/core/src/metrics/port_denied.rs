@@ -0,0 +1,32 @@
+use crate::metrics::{self, Counter, FmtMetric, FmtMetrics};
+use std::{fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_tcp_denied_unknown_port_total: Counter {
+        "The total number of inbound TCP connections that were closed because their target port is not allowed."
+    }
+}
+
+/// Counts inbound connections that were denied because their target ports
+/// are not explicitly allowed.
+///
+/// Connections are not labeled by their target ports, since any port may be
+/// targeted.
+#[derive(Clone, Debug, Default)]
+pub struct Denied(Arc<Counter>);
+
+// === impl Denied ===
+
+impl Denied {
+    pub fn incr(&self) {
+        self.0.incr();
+    }
+}
+
+impl FmtMetrics for Denied {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        inbound_tcp_denied_unknown_port_total.fmt_help(f)?;
+        self.0
+            .fmt_metric(f, inbound_tcp_denied_unknown_port_total.name)
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics},
+    transport::labels::TargetAddr,
+};
+use ipnet::IpNet;
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, net::SocketAddr, sync::Arc};
+
+metrics::metrics! {
+    inbound_tcp_source_rejected_total: Counter {
+        "The total number of inbound TCP connections that were closed because their source address is not permitted on the target port."
+    }
+}
+
+/// Counts, by target address, inbound connections that were rejected by their
+/// source address.
+///
+/// Connections rejected because their source address is in a denied network
+/// are also labeled by that network, as `denied_cidr`.
+#[derive(Clone, Debug, Default)]
+pub struct Rejected(Arc<Mutex<HashMap<(SocketAddr, Option<IpNet>), Counter>>>);
+
+struct DeniedCidr(IpNet);
+
+// === impl Rejected ===
+
+impl Rejected {
+    pub fn incr(&self, target_addr: SocketAddr, denied_cidr: Option<IpNet>) {
+        self.0
+            .lock()
+            .entry((target_addr, denied_cidr))
+            .or_default()
+            .incr();
+    }
+}
+
+impl FmtMetrics for Rejected {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rejected = self.0.lock();
+        if rejected.is_empty() {
+            return Ok(());
+        }
+
+        inbound_tcp_source_rejected_total.fmt_help(f)?;
+        for ((addr, denied), counter) in rejected.iter() {
+            counter.fmt_metric_labeled(
+                f,
+                inbound_tcp_source_rejected_total.name,
+                (TargetAddr(*addr), denied.map(DeniedCidr)),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl DeniedCidr ===
+
+impl FmtLabels for DeniedCidr {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "denied_cidr=\"{}\"", self.0)
+    }
+}
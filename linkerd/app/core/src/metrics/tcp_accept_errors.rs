@@ -1,5 +1,6 @@
 use crate::{
     metrics::{self, Counter, FmtMetrics},
+    proxy::{http::h2::peer_settings::SettingsRejected, tcp::forward::IdleTimeoutError},
     svc,
     transport::{labels, OrigDstAddr},
 };
@@ -36,6 +37,8 @@ pub struct LabelAcceptErrors(());
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum AcceptErrors {
     TlsDetectTimeout,
+    H2Settings,
+    IdleTimeout,
     Io,
     Other,
 }
@@ -91,6 +94,10 @@ impl LabelError<Error> for LabelAcceptErrors {
         while let Some(err) = curr {
             if err.is::<ServerTlsTimeoutError>() {
                 return AcceptErrors::TlsDetectTimeout;
+            } else if err.is::<SettingsRejected>() {
+                return AcceptErrors::H2Settings;
+            } else if err.is::<IdleTimeoutError>() {
+                return AcceptErrors::IdleTimeout;
             } else if err.is::<std::io::Error>() {
                 // We ignore the error code because we want all labels to be consistent.
                 return AcceptErrors::Io;
@@ -108,6 +115,8 @@ impl FmtLabels for AcceptErrors {
     fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::TlsDetectTimeout => fmt::Display::fmt("error=\"tls_detect_timeout\"", f),
+            Self::H2Settings => fmt::Display::fmt("error=\"h2_settings\"", f),
+            Self::IdleTimeout => fmt::Display::fmt("error=\"idle_timeout\"", f),
             Self::Io => fmt::Display::fmt("error=\"io\"", f),
             Self::Other => fmt::Display::fmt("error=\"other\"", f),
         }
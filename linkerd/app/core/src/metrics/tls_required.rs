@@ -0,0 +1,52 @@
+use crate::metrics::{self, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+metrics::metrics! {
+    inbound_tcp_tls_required_denied_total: Counter {
+        "The total number of inbound TCP connections that were closed because they were not secured by TLS on a port that requires it."
+    }
+}
+
+/// Counts, by target port, plaintext connections that were denied because
+/// their target ports require TLS.
+#[derive(Clone, Debug, Default)]
+pub struct Denied(Arc<Mutex<HashMap<u16, Counter>>>);
+
+struct Port(u16);
+
+// === impl Denied ===
+
+impl Denied {
+    pub fn incr(&self, port: u16) {
+        self.0.lock().entry(port).or_default().incr();
+    }
+}
+
+impl FmtMetrics for Denied {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let denied = self.0.lock();
+        if denied.is_empty() {
+            return Ok(());
+        }
+
+        inbound_tcp_tls_required_denied_total.fmt_help(f)?;
+        for (port, counter) in denied.iter() {
+            counter.fmt_metric_labeled(
+                f,
+                inbound_tcp_tls_required_denied_total.name,
+                Port(*port),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Port ===
+
+impl FmtLabels for Port {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port=\"{}\"", self.0)
+    }
+}
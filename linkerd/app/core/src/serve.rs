@@ -1,21 +1,58 @@
 use crate::{
+    connection_log::{self, ConnectionLog},
     io,
+    metrics::{metrics, Counter, FmtMetric, FmtMetrics},
     svc::{self, Param},
-    transport::{ClientAddr, Remote},
+    transport::{ClientAddr, OrigDstAddr, Remote},
 };
 use futures::prelude::*;
 use linkerd_error::Error;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::sync::watch;
 use tower::util::ServiceExt;
 use tracing::{debug, debug_span, info, instrument::Instrument, warn};
 
+metrics! {
+    process_shutdown_force_closed_connections_total: Counter {
+        "The total number of connections that were closed forcibly because they remained open after the shutdown grace period."
+    }
+}
+
+/// Bounds how long a server's connections may remain open once shutdown is
+/// signaled: connections that remain open after the grace period are closed
+/// forcibly. By default, connections may remain open indefinitely.
+#[derive(Clone, Debug, Default)]
+pub struct GracePeriod {
+    timeout: Option<Duration>,
+    force_closed: Arc<Counter>,
+}
+
 /// Spawns a task that binds an `L`-typed listener with an `A`-typed
 /// connection-accepting service.
 ///
 /// The task is driven until shutdown is signaled.
 pub async fn serve<M, S, I, A>(
+    listen: impl Stream<Item = std::io::Result<(A, I)>>,
+    new_accept: M,
+    shutdown: impl Future,
+) where
+    I: Send + 'static,
+    A: Param<Remote<ClientAddr>>,
+    M: svc::NewService<A, Service = S>,
+    S: tower::Service<io::ScopedIo<I>, Response = ()> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    serve_with_grace(listen, new_accept, shutdown, GracePeriod::default()).await
+}
+
+/// Like `serve`, but closes the connections that remain open once the grace
+/// period has elapsed after shutdown is signaled.
+pub async fn serve_with_grace<M, S, I, A>(
     listen: impl Stream<Item = std::io::Result<(A, I)>>,
     mut new_accept: M,
     shutdown: impl Future,
+    grace: GracePeriod,
 ) where
     I: Send + 'static,
     A: Param<Remote<ClientAddr>>,
@@ -24,6 +61,14 @@ pub async fn serve<M, S, I, A>(
     S::Error: Into<Error>,
     S::Future: Send + 'static,
 {
+    // Set once the grace period elapses. If there is no grace period, the
+    // sender is dropped and connections are never closed forcibly.
+    let (force_close_tx, force_close_rx) = watch::channel(false);
+    let force_closed = grace.force_closed.clone();
+    // Each connection's task holds a reference, so that the connections that
+    // remain open after the grace period can be counted.
+    let open = Arc::new(());
+    let open_conns = open.clone();
     let accept = async move {
         futures::pin_mut!(listen);
         loop {
@@ -43,17 +88,28 @@ pub async fn serve<M, S, I, A>(
                     let span = debug_span!("accept", client.addr = %addrs.param());
 
                     let accept = span.in_scope(|| new_accept.new_service(addrs));
+                    let force_close = force_close_rx.clone();
+                    let force_closed = force_closed.clone();
+                    let open = open_conns.clone();
 
                     // Dispatch all of the work for a given connection onto a connection-specific task.
                     tokio::spawn(
                         async move {
+                            let _open = open;
                             match accept.ready_oneshot().err_into::<Error>().await {
                                 Ok(mut accept) => {
-                                    match accept
+                                    let conn = accept
                                         .call(io::ScopedIo::server(io))
-                                        .err_into::<Error>()
-                                        .await
-                                    {
+                                        .err_into::<Error>();
+                                    let res = tokio::select! {
+                                        res = conn => res,
+                                        () = forced(force_close) => {
+                                            debug!("Connection closed after the shutdown grace period");
+                                            force_closed.incr();
+                                            return;
+                                        }
+                                    };
+                                    match res {
                                         Ok(()) => debug!("Connection closed"),
                                         Err(reason) if is_io(&*reason) => {
                                             debug!(%reason, "Connection closed")
@@ -84,8 +140,222 @@ pub async fn serve<M, S, I, A>(
         res = accept => { res }
         _ = shutdown => {}
     }
+
+    if let Some(timeout) = grace.timeout {
+        debug!(
+            ?timeout,
+            "Closing connections after the shutdown grace period"
+        );
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            // Connections are logged individually at debug, so that closing
+            // many connections does not flood the logs.
+            let connections = Arc::strong_count(&open) - 1;
+            if connections > 0 {
+                info!(
+                    connections,
+                    "Closing connections that remain open after the shutdown grace period"
+                );
+            }
+            let _ = force_close_tx.send(true);
+        });
+    }
+}
+
+/// Completes once connections must be closed forcibly.
+async fn forced(mut force_close: watch::Receiver<bool>) {
+    while !*force_close.borrow() {
+        if force_close.changed().await.is_err() {
+            // There is no grace period.
+            future::pending::<()>().await;
+        }
+    }
+}
+
+/// Like `serve`, but logs each connection as it is accepted and closed when
+/// the `ConnectionLog` is enabled.
+pub async fn serve_logged<M, S, I, A>(
+    listen: impl Stream<Item = std::io::Result<(A, I)>>,
+    new_accept: M,
+    log: ConnectionLog,
+    shutdown: impl Future,
+    grace: GracePeriod,
+) where
+    I: Send + 'static,
+    A: Param<Remote<ClientAddr>> + Param<OrigDstAddr>,
+    M: svc::NewService<A, Service = S>,
+    S: tower::Service<io::ScopedIo<connection_log::Io<I>>, Response = ()> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    let listen = listen.map_ok(move |(addrs, io)| log.accept(addrs, io));
+    serve_with_grace(
+        listen,
+        connection_log::NewConnectionLog::new(new_accept),
+        shutdown,
+        grace,
+    )
+    .await
+}
+
+// === impl GracePeriod ===
+
+impl GracePeriod {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            force_closed: Default::default(),
+        }
+    }
+}
+
+impl FmtMetrics for GracePeriod {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.timeout.is_none() {
+            return Ok(());
+        }
+        process_shutdown_force_closed_connections_total.fmt_help(f)?;
+        self.force_closed
+            .fmt_metric(f, process_shutdown_force_closed_connections_total.name)
+    }
 }
 
 fn is_io(e: &(dyn std::error::Error + 'static)) -> bool {
     e.is::<io::Error>() || e.source().map(is_io).unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_swap;
+    use futures::stream;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+        sync::{
+            mpsc,
+            oneshot::{self, error::TryRecvError},
+        },
+        time,
+    };
+
+    #[derive(Clone, Debug)]
+    struct Addrs;
+
+    impl Param<Remote<ClientAddr>> for Addrs {
+        fn param(&self) -> Remote<ClientAddr> {
+            Remote(ClientAddr(([192, 0, 2, 3], 50000).into()))
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn closes_connections_after_grace_period() {
+        time::pause();
+        let grace = GracePeriod::new(Some(Duration::from_secs(10)));
+
+        // Accept a single connection that never completes on its own. Its
+        // sender is dropped once the connection is closed.
+        let (io, _client) = tokio::io::duplex(64);
+        let listen = stream::iter(Some(Ok((Addrs, io)))).chain(stream::pending());
+        let (closed_tx, mut closed_rx) = oneshot::channel::<()>();
+        let mut closed_tx = Some(closed_tx);
+        let new_accept = move |_: Addrs| {
+            let mut closed_tx = closed_tx.take();
+            svc::mk(move |_: io::ScopedIo<DuplexStream>| {
+                let closed_tx = closed_tx.take();
+                async move {
+                    let _closed_tx = closed_tx;
+                    future::pending::<Result<(), Error>>().await
+                }
+            })
+        };
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_grace(
+            listen,
+            new_accept,
+            shutdown_rx,
+            grace.clone(),
+        ));
+        time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(closed_rx.try_recv(), Err(TryRecvError::Empty));
+
+        // Once shutdown is signaled, the server stops accepting connections,
+        // but the open connection is only closed after the grace period.
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+        time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(closed_rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(grace.force_closed.value() as u64, 0);
+
+        assert!(closed_rx.await.is_err(), "connection must be closed");
+        assert_eq!(grace.force_closed.value() as u64, 1);
+    }
+
+    /// Builds a stack whose connections respond to each byte they read with
+    /// `id`.
+    fn identify(
+        id: u8,
+    ) -> impl Fn(Addrs) -> svc::BoxTcp<io::ScopedIo<DuplexStream>> + Clone + Send + 'static {
+        move |_: Addrs| {
+            svc::BoxService::new(svc::mk(
+                move |mut io: io::ScopedIo<DuplexStream>| async move {
+                    let mut buf = [0u8; 1];
+                    while io.read(&mut buf).await? != 0 {
+                        io.write_all(&[id]).await?;
+                    }
+                    Ok::<_, Error>(())
+                },
+            ))
+        }
+    }
+
+    async fn served_by(client: &mut DuplexStream) -> u8 {
+        client.write_all(b"?").await.unwrap();
+        let mut id = [0u8; 1];
+        client.read_exact(&mut id).await.unwrap();
+        id[0]
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn soft_swap_serves_new_connections_with_new_stack() {
+        let (conns_tx, mut conns_rx) = mpsc::unbounded_channel();
+        let listen = stream::poll_fn(move |cx| conns_rx.poll_recv(cx));
+        let (swap, new_accept) = soft_swap::new(identify(0), soft_swap::Retention::new(1, None));
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_grace(
+            listen,
+            new_accept,
+            shutdown_rx,
+            GracePeriod::new(Some(Duration::from_secs(10))),
+        ));
+
+        let connect = || {
+            let (server, client) = tokio::io::duplex(64);
+            conns_tx.send(Ok((Addrs, server))).unwrap();
+            client
+        };
+
+        let mut old = connect();
+        assert_eq!(served_by(&mut old).await, 0);
+
+        swap.swap(identify(1));
+
+        // New connections are served by the new stack, while the existing
+        // connection continues to be served by the old stack.
+        let mut new = connect();
+        assert_eq!(served_by(&mut new).await, 1);
+        assert_eq!(served_by(&mut old).await, 0);
+
+        // Connections on the old stack are still closed by the shutdown grace
+        // period.
+        time::pause();
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+        assert_eq!(served_by(&mut old).await, 0);
+        time::sleep(Duration::from_secs(10)).await;
+        let mut buf = [0u8; 1];
+        assert_eq!(old.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(new.read(&mut buf).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{
+    metrics::{metrics, FmtLabels, FmtMetric, FmtMetrics, Gauge},
+    svc,
+};
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+metrics! {
+    process_shutdown_phase_timestamp_seconds: Gauge {
+        "The time, in seconds since the Unix epoch, at which each phase of shutdown occurred."
+    }
+}
+
+/// Configures how the phases of the proxy's shutdown are reported.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Config {
+    /// Whether each phase is logged as a structured event.
+    pub log: bool,
+
+    /// Whether the time at which each phase occurred is reported in the
+    /// `process_shutdown_phase_timestamp_seconds` metric.
+    pub timeline: bool,
+}
+
+/// Records the phases of the proxy's shutdown: when draining is initiated,
+/// as each listener stops accepting connections, as the number of open
+/// connections halves, and when all connections have closed.
+///
+/// The proxy is fully drained once draining has been initiated, every
+/// listener has stopped, and no connections remain open, so the `Drained`
+/// event is recorded exactly once, however many listeners there are.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownEvents(Option<Arc<Inner>>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    DrainInitiated {
+        connections: usize,
+    },
+    ListenerStopped {
+        listener: &'static str,
+        connections: usize,
+    },
+    ConnectionsRemaining(usize),
+    Drained,
+}
+
+/// Tracks the connections accepted by a single listener.
+#[derive(Clone, Debug)]
+pub struct Listener {
+    events: ShutdownEvents,
+    name: &'static str,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackConnections<N> {
+    inner: N,
+    events: ShutdownEvents,
+}
+
+/// Holds a connection open, for the purpose of shutdown events, until the
+/// connection's accept service is dropped.
+#[derive(Debug)]
+pub struct Tracked<S> {
+    inner: S,
+    _connection: Connection,
+}
+
+#[derive(Debug)]
+struct Connection(ShutdownEvents);
+
+#[derive(Debug)]
+struct Inner {
+    config: Config,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    listeners: usize,
+    connections: usize,
+    draining: bool,
+    drained: bool,
+    next_milestone: usize,
+    events: Vec<(Event, SystemTime)>,
+}
+
+struct PhaseLabels<'e>(&'e Event);
+
+// === impl Config ===
+
+impl Config {
+    pub fn build(self) -> ShutdownEvents {
+        if !self.log && !self.timeline {
+            return ShutdownEvents(None);
+        }
+        ShutdownEvents(Some(Arc::new(Inner {
+            config: self,
+            state: Default::default(),
+        })))
+    }
+}
+
+// === impl ShutdownEvents ===
+
+impl ShutdownEvents {
+    /// Registers a listener, which must be marked as stopped before the
+    /// proxy can be fully drained.
+    pub fn listener(&self, name: &'static str) -> Listener {
+        if let Some(inner) = self.0.as_ref() {
+            inner.state.lock().listeners += 1;
+        }
+        Listener {
+            events: self.clone(),
+            name,
+        }
+    }
+
+    pub fn drain_initiated(&self) {
+        self.update(|state| {
+            if state.draining {
+                return vec![];
+            }
+            state.draining = true;
+            state.next_milestone = state.connections / 2;
+            vec![Event::DrainInitiated {
+                connections: state.connections,
+            }]
+        });
+    }
+
+    /// Records that the proxy is fully drained, if it has not already been
+    /// recorded.
+    pub fn drained(&self) {
+        self.update(|state| {
+            if state.drained {
+                return vec![];
+            }
+            state.drained = true;
+            vec![Event::Drained]
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State) -> Vec<Event>) {
+        let inner = match self.0.as_ref() {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        let mut state = inner.state.lock();
+        let mut events = f(&mut state);
+        if state.draining && !state.drained && state.listeners == 0 && state.connections == 0 {
+            state.drained = true;
+            events.push(Event::Drained);
+        }
+
+        let now = SystemTime::now();
+        for event in events {
+            if inner.config.log {
+                log(&event);
+            }
+            state.events.push((event, now));
+        }
+    }
+
+    #[cfg(test)]
+    fn events(&self) -> Vec<Event> {
+        self.0
+            .as_ref()
+            .map(|inner| {
+                let state = inner.state.lock();
+                state.events.iter().map(|(e, _)| e.clone()).collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl FmtMetrics for ShutdownEvents {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = match self.0.as_ref() {
+            Some(inner) if inner.config.timeline => inner,
+            _ => return Ok(()),
+        };
+        let state = inner.state.lock();
+        if state.events.is_empty() {
+            return Ok(());
+        }
+
+        process_shutdown_phase_timestamp_seconds.fmt_help(f)?;
+        for (event, at) in state.events.iter() {
+            let secs = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            Gauge::from(secs).fmt_metric_labeled(
+                f,
+                process_shutdown_phase_timestamp_seconds.name,
+                PhaseLabels(event),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn log(event: &Event) {
+    match *event {
+        Event::DrainInitiated { connections } => {
+            info!(event = "drain_initiated", connections, "Shutdown initiated")
+        }
+        Event::ListenerStopped {
+            listener,
+            connections,
+        } => info!(
+            event = "listener_stopped",
+            listener, connections, "Listener stopped accepting connections"
+        ),
+        Event::ConnectionsRemaining(connections) => info!(
+            event = "connections_remaining",
+            connections, "Waiting for connections to close"
+        ),
+        Event::Drained => info!(event = "drained", "Shutdown complete"),
+    }
+}
+
+// === impl Listener ===
+
+impl Listener {
+    /// Tracks the connections accepted by `inner`'s services.
+    pub fn track<N>(&self, inner: N) -> TrackConnections<N> {
+        TrackConnections {
+            inner,
+            events: self.events.clone(),
+        }
+    }
+
+    pub fn stopped(&self) {
+        let listener = self.name;
+        self.events.update(|state| {
+            state.listeners = state.listeners.saturating_sub(1);
+            vec![Event::ListenerStopped {
+                listener,
+                connections: state.connections,
+            }]
+        });
+    }
+}
+
+// === impl TrackConnections ===
+
+impl<T, N: svc::NewService<T>> svc::NewService<T> for TrackConnections<N> {
+    type Service = Tracked<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        if let Some(inner) = self.events.0.as_ref() {
+            inner.state.lock().connections += 1;
+        }
+        Tracked {
+            inner: self.inner.new_service(target),
+            _connection: Connection(self.events.clone()),
+        }
+    }
+}
+
+// === impl Tracked ===
+
+impl<I, S: svc::Service<I>> svc::Service<I> for Tracked<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, io: I) -> Self::Future {
+        self.inner.call(io)
+    }
+}
+
+// === impl Connection ===
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.update(|state| {
+            state.connections = state.connections.saturating_sub(1);
+            if state.draining && state.connections > 0 && state.connections <= state.next_milestone
+            {
+                state.next_milestone = state.connections / 2;
+                return vec![Event::ConnectionsRemaining(state.connections)];
+            }
+            vec![]
+        });
+    }
+}
+
+// === impl PhaseLabels ===
+
+impl FmtLabels for PhaseLabels<'_> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Event::DrainInitiated { .. } => write!(f, "phase=\"drain_initiated\""),
+            Event::ListenerStopped { listener, .. } => {
+                write!(f, "phase=\"listener_stopped\",listener=\"{}\"", listener)
+            }
+            Event::ConnectionsRemaining(n) => {
+                write!(f, "phase=\"connections_remaining\",connections=\"{}\"", n)
+            }
+            Event::Drained => write!(f, "phase=\"drained\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::NewService;
+
+    fn events() -> ShutdownEvents {
+        Config {
+            log: true,
+            timeline: true,
+        }
+        .build()
+    }
+
+    #[test]
+    fn records_ordered_phases() {
+        let events = events();
+        let inbound = events.listener("inbound");
+        let outbound = events.listener("outbound");
+        let mut accept_in = inbound.track(|_: ()| ());
+        let mut accept_out = outbound.track(|_: ()| ());
+        let mut conns = (0..3)
+            .map(|_| accept_in.new_service(()))
+            .chain(Some(accept_out.new_service(())))
+            .collect::<Vec<_>>();
+
+        events.drain_initiated();
+        inbound.stopped();
+        conns.truncate(2);
+        outbound.stopped();
+        conns.truncate(1);
+        conns.clear();
+
+        assert_eq!(
+            events.events(),
+            vec![
+                Event::DrainInitiated { connections: 4 },
+                Event::ListenerStopped {
+                    listener: "inbound",
+                    connections: 4
+                },
+                Event::ConnectionsRemaining(2),
+                Event::ListenerStopped {
+                    listener: "outbound",
+                    connections: 2
+                },
+                Event::ConnectionsRemaining(1),
+                Event::Drained,
+            ]
+        );
+
+        // The proxy is only drained once, even when the drain completes.
+        events.drained();
+        assert_eq!(events.events().len(), 6);
+
+        let metrics = format!("{}", events.as_display());
+        for phase in &[
+            "phase=\"drain_initiated\"",
+            "phase=\"listener_stopped\",listener=\"inbound\"",
+            "phase=\"listener_stopped\",listener=\"outbound\"",
+            "phase=\"connections_remaining\",connections=\"2\"",
+            "phase=\"drained\"",
+        ] {
+            let metric = format!("process_shutdown_phase_timestamp_seconds{{{}}}", phase);
+            assert!(metrics.contains(&metric), "{} not in:\n{}", metric, metrics);
+        }
+    }
+
+    #[test]
+    fn waits_for_listeners() {
+        let events = events();
+        let inbound = events.listener("inbound");
+        let outbound = events.listener("outbound");
+        events.drain_initiated();
+        inbound.stopped();
+        assert!(!events.events().contains(&Event::Drained));
+        outbound.stopped();
+        assert_eq!(events.events().last(), Some(&Event::Drained));
+    }
+
+    #[test]
+    fn disabled() {
+        let events = Config::default().build();
+        let listener = events.listener("inbound");
+        events.drain_initiated();
+        listener.stopped();
+        events.drained();
+        assert!(events.events().is_empty());
+        assert_eq!(events.as_display().to_string(), "");
+    }
+}
@@ -0,0 +1,378 @@
+//! Replaces the stack that serves newly accepted connections without
+//! disturbing connections that are already being served.
+//!
+//! A soft swap is used for reconfiguration that does not require a full
+//! shutdown: once a new stack is installed, new accepts are served by it while
+//! existing connections continue to be served by the stack that accepted them,
+//! without a deadline. A [`NewSoftSwap`] is passed to `serve` in place of the
+//! stack it wraps, so that connections on replaced stacks are still closed by
+//! the server's shutdown grace period.
+//!
+//! Because a connection may never close on its own, only a bounded number of
+//! replaced stacks are retained: when more replaced stacks than that still
+//! have open connections, the connections on the oldest are closed. Replaced
+//! stacks may also be retained for a bounded amount of time.
+
+use crate::svc;
+use futures::prelude::*;
+use linkerd_error::Error;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::watch;
+use tracing::debug;
+
+/// Bounds how replaced stacks are retained for their existing connections.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Retention {
+    max_stacks: usize,
+    timeout: Option<Duration>,
+}
+
+/// Installs new stacks for a [`NewSoftSwap`].
+pub struct Handle<N> {
+    tx: watch::Sender<Generation<N>>,
+    stacks: Arc<Mutex<Stacks>>,
+    retention: Retention,
+}
+
+/// A `NewService` that builds services from the most recently installed stack.
+#[derive(Clone, Debug)]
+pub struct NewSoftSwap<N> {
+    rx: watch::Receiver<Generation<N>>,
+}
+
+/// A service built by an older or current generation of the stack.
+#[derive(Clone, Debug)]
+pub struct Retained<S> {
+    inner: S,
+    retired: drain::Watch,
+    conns: Arc<()>,
+}
+
+#[derive(Clone, Debug)]
+struct Generation<N> {
+    new: N,
+    retired: drain::Watch,
+    // Held by each of the generation's services and connections, so that
+    // replaced stacks without connections are released.
+    conns: Arc<()>,
+}
+
+struct Stacks {
+    current: Stack,
+    retired: VecDeque<Stack>,
+}
+
+struct Stack {
+    id: usize,
+    signal: drain::Signal,
+    conns: Weak<()>,
+}
+
+/// Creates a `NewSoftSwap` serving from `new` along with a [`Handle`] that
+/// replaces it.
+pub fn new<N>(new: N, retention: Retention) -> (Arc<Handle<N>>, NewSoftSwap<N>) {
+    let (signal, retired) = drain::channel();
+    let conns = Arc::new(());
+    let current = Stack {
+        id: 0,
+        signal,
+        conns: Arc::downgrade(&conns),
+    };
+    let (tx, rx) = watch::channel(Generation {
+        new,
+        retired,
+        conns,
+    });
+    let handle = Handle {
+        tx,
+        stacks: Arc::new(Mutex::new(Stacks {
+            current,
+            retired: VecDeque::new(),
+        })),
+        retention,
+    };
+    (Arc::new(handle), NewSoftSwap { rx })
+}
+
+// === impl Retention ===
+
+impl Retention {
+    /// Retains at most `max_stacks` replaced stacks that still have open
+    /// connections, each for at most `timeout`, if set.
+    ///
+    /// When `max_stacks` is zero, a replaced stack's connections are closed as
+    /// soon as it is replaced.
+    pub fn new(max_stacks: usize, timeout: Option<Duration>) -> Self {
+        Self {
+            max_stacks,
+            timeout,
+        }
+    }
+}
+
+// === impl Handle ===
+
+impl<N> Handle<N> {
+    /// Installs `new` to serve all subsequently accepted connections.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn swap(&self, new: N) {
+        let (signal, retired) = drain::channel();
+        let conns = Arc::new(());
+        let mut stacks = self.stacks.lock();
+        let id = stacks.current.id + 1;
+        let old = std::mem::replace(
+            &mut stacks.current,
+            Stack {
+                id,
+                signal,
+                conns: Arc::downgrade(&conns),
+            },
+        );
+        let _ = self.tx.send(Generation {
+            new,
+            retired,
+            conns,
+        });
+
+        // Stacks whose connections have all completed need not be retained.
+        stacks.retired.retain(Stack::is_serving);
+        if !old.is_serving() {
+            return;
+        }
+
+        if let Some(timeout) = self.retention.timeout {
+            let all = self.stacks.clone();
+            let id = old.id;
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                let expired = {
+                    let mut stacks = all.lock();
+                    let idx = stacks.retired.iter().position(|s| s.id == id);
+                    idx.and_then(|idx| stacks.retired.remove(idx))
+                };
+                if let Some(stack) = expired {
+                    debug!(?timeout, "Closing connections on replaced stack");
+                    stack.signal.drain().await;
+                }
+            });
+        }
+
+        debug!(
+            retained = stacks.retired.len() + 1,
+            "Retaining replaced stack"
+        );
+        stacks.retired.push_back(old);
+        while stacks.retired.len() > self.retention.max_stacks {
+            if let Some(stack) = stacks.retired.pop_front() {
+                debug!(
+                    max_stacks = self.retention.max_stacks,
+                    "Closing connections on oldest replaced stack"
+                );
+                tokio::spawn(stack.signal.drain());
+            }
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl Stack {
+    fn is_serving(&self) -> bool {
+        self.conns.strong_count() > 0
+    }
+}
+
+// === impl NewSoftSwap ===
+
+impl<T, N> svc::NewService<T> for NewSoftSwap<N>
+where
+    N: svc::NewService<T> + Clone,
+{
+    type Service = Retained<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let Generation {
+            mut new,
+            retired,
+            conns,
+        } = (*self.rx.borrow()).clone();
+        Retained {
+            inner: new.new_service(target),
+            retired,
+            conns,
+        }
+    }
+}
+
+// === impl Retained ===
+
+impl<S, I> svc::Service<I> for Retained<S>
+where
+    S: svc::Service<I, Response = ()>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let serve = self.inner.call(io).err_into::<Error>();
+        let retired = self.retired.clone();
+        let conn = self.conns.clone();
+        Box::pin(async move {
+            let _conn = conn;
+            tokio::select! {
+                res = serve => res,
+                _ = retired.signaled() => {
+                    debug!("Replaced stack is no longer retained");
+                    Ok(())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::{NewService, ServiceExt};
+    use tokio::{sync::oneshot, task::JoinHandle, time};
+
+    type Accept = (
+        oneshot::Receiver<usize>,
+        oneshot::Sender<()>,
+        JoinHandle<Result<(), Error>>,
+    );
+
+    /// Builds a stack whose services report `id` and then serve the
+    /// connection until it is closed.
+    fn stack(
+        id: usize,
+    ) -> impl Fn(oneshot::Sender<usize>) -> svc::BoxTcp<oneshot::Receiver<()>> + Clone {
+        move |tx| {
+            let mut tx = Some(tx);
+            svc::BoxService::new(svc::mk(move |closed: oneshot::Receiver<()>| {
+                if let Some(tx) = tx.take() {
+                    let _ = tx.send(id);
+                }
+                async move {
+                    let _ = closed.await;
+                    Ok::<_, Error>(())
+                }
+            }))
+        }
+    }
+
+    fn accept<N>(new: &mut NewSoftSwap<N>) -> Accept
+    where
+        N: NewService<oneshot::Sender<usize>, Service = svc::BoxTcp<oneshot::Receiver<()>>>,
+        N: Clone,
+    {
+        let (id_tx, id_rx) = oneshot::channel();
+        let (close_tx, close_rx) = oneshot::channel();
+        let svc = new.new_service(id_tx);
+        let task = tokio::spawn(svc.oneshot(close_rx));
+        (id_rx, close_tx, task)
+    }
+
+    async fn is_running(task: &mut JoinHandle<Result<(), Error>>) -> bool {
+        time::timeout(Duration::from_millis(10), task)
+            .await
+            .is_err()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn old_connections_keep_old_stack() {
+        let (handle, mut new) = super::new(stack(0), Retention::new(1, None));
+
+        let (old_id, old_close, mut old_task) = accept(&mut new);
+        assert_eq!(old_id.await.unwrap(), 0);
+
+        handle.swap(stack(1));
+
+        let (new_id, new_close, new_task) = accept(&mut new);
+        assert_eq!(new_id.await.unwrap(), 1);
+
+        // The old connection is still being served by the old stack.
+        assert!(is_running(&mut old_task).await);
+
+        old_close.send(()).unwrap();
+        old_task.await.unwrap().unwrap();
+        new_close.send(()).unwrap();
+        new_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn oldest_stack_closed_beyond_bound() {
+        let (handle, mut new) = super::new(stack(0), Retention::new(1, None));
+
+        let (id0, _close0, mut task0) = accept(&mut new);
+        assert_eq!(id0.await.unwrap(), 0);
+        handle.swap(stack(1));
+        let (id1, _close1, mut task1) = accept(&mut new);
+        assert_eq!(id1.await.unwrap(), 1);
+        assert!(is_running(&mut task0).await);
+
+        // Only one replaced stack is retained, so replacing the second stack
+        // closes the first stack's connection.
+        handle.swap(stack(2));
+        let (id2, _close2, mut task2) = accept(&mut new);
+        assert_eq!(id2.await.unwrap(), 2);
+        task0.await.unwrap().unwrap();
+        assert!(is_running(&mut task1).await);
+        assert!(is_running(&mut task2).await);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn stacks_without_connections_not_retained() {
+        let (handle, mut new) = super::new(stack(0), Retention::new(1, None));
+
+        let (id0, close0, task0) = accept(&mut new);
+        assert_eq!(id0.await.unwrap(), 0);
+        handle.swap(stack(1));
+        let (id1, _close1, mut task1) = accept(&mut new);
+        assert_eq!(id1.await.unwrap(), 1);
+
+        // Once the first stack's connection completes, it no longer counts
+        // against the bound, so the second stack's connection is retained.
+        close0.send(()).unwrap();
+        task0.await.unwrap().unwrap();
+        handle.swap(stack(2));
+        assert!(is_running(&mut task1).await);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn old_connections_closed_after_retention() {
+        let timeout = Duration::from_secs(10);
+        let (handle, mut new) = super::new(stack(0), Retention::new(1, Some(timeout)));
+
+        let (old_id, _old_close, mut old_task) = accept(&mut new);
+        assert_eq!(old_id.await.unwrap(), 0);
+
+        handle.swap(stack(1));
+        let (new_id, _new_close, mut new_task) = accept(&mut new);
+        assert_eq!(new_id.await.unwrap(), 1);
+
+        assert!(is_running(&mut old_task).await);
+        time::sleep(timeout).await;
+
+        // The old connection is closed once the retention bound elapses, but
+        // the connection on the new stack is unaffected.
+        old_task.await.unwrap().unwrap();
+        assert!(is_running(&mut new_task).await);
+    }
+}
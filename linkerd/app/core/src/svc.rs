@@ -154,7 +154,7 @@ impl<S> Stack<S> {
     }
 
     /// Buffer requests when when the next layer is out of capacity.
-    pub fn spawn_buffer<Req, Rsp>(
+    pub fn spawn_buffer<Req>(
         self,
         capacity: usize,
     ) -> Stack<Buffer<Req, S::Response, S::Error>>
@@ -1,4 +1,4 @@
-pub use crate::metrics::{Direction, OutboundEndpointLabels};
+pub use crate::metrics::{Direction, OutboundEndpointLabels, PortClass};
 use linkerd_conditional::Conditional;
 use linkerd_metrics::FmtLabels;
 use linkerd_tls as tls;
@@ -15,6 +15,7 @@ pub enum Key {
         direction: Direction,
         tls: tls::ConditionalServerTls,
         target_addr: SocketAddr,
+        class: Option<PortClass>,
     },
     OutboundConnect(OutboundEndpointLabels),
     InboundConnect,
@@ -36,11 +37,13 @@ impl Key {
         direction: Direction,
         tls: tls::ConditionalServerTls,
         target_addr: SocketAddr,
+        class: Option<PortClass>,
     ) -> Self {
         Self::Accept {
             direction,
             tls,
             target_addr,
+            class,
         }
     }
 }
@@ -52,10 +55,16 @@ impl FmtLabels for Key {
                 direction,
                 tls,
                 target_addr,
+                class,
             } => {
                 direction.fmt_labels(f)?;
                 f.write_str(",peer=\"src\",")?;
-                (TargetAddr(*target_addr), TlsAccept::from(tls)).fmt_labels(f)
+                (TargetAddr(*target_addr), TlsAccept::from(tls)).fmt_labels(f)?;
+                if let Some(class) = class {
+                    f.write_str(",")?;
+                    class.fmt_labels(f)?;
+                }
+                Ok(())
             }
             Self::OutboundConnect(endpoint) => {
                 Direction::Out.fmt_labels(f)?;
@@ -17,6 +17,7 @@ use linkerd_app_core::{
     },
     svc::{self, Param},
     tls,
+    transport::addrs::OrigDstAddr,
     transport_header::SessionProtocol,
     Error, Infallible, NameAddr, NameMatch,
 };
@@ -76,11 +77,17 @@ pub fn stack<I, O, P, R>(
     resolve: R,
 ) -> svc::BoxNewTcp<GatewayConnection, I>
 where
-    I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + fmt::Debug + Send + Sync + Unpin + 'static,
+    I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + io::Splice,
+    I: fmt::Debug + Send + Sync + Unpin + 'static,
     O: Clone + Send + Sync + Unpin + 'static,
     O: svc::Service<outbound::tcp::Connect, Error = io::Error>,
-    O::Response:
-        io::AsyncRead + io::AsyncWrite + tls::HasNegotiatedProtocol + Send + Unpin + 'static,
+    O::Response: io::AsyncRead
+        + io::AsyncWrite
+        + io::Splice
+        + tls::HasNegotiatedProtocol
+        + Send
+        + Unpin
+        + 'static,
     O::Future: Send + Unpin + 'static,
     P: profiles::GetProfile<profiles::LookupAddr> + Clone + Send + Sync + Unpin + 'static,
     P::Future: Send + 'static,
@@ -295,6 +302,12 @@ impl Param<Option<identity::Name>> for HttpTransportHeader {
     }
 }
 
+impl Param<OrigDstAddr> for HttpTransportHeader {
+    fn param(&self) -> OrigDstAddr {
+        OrigDstAddr(self.client.local_addr)
+    }
+}
+
 impl Param<http::Version> for HttpTransportHeader {
     fn param(&self) -> http::Version {
         self.version
@@ -341,6 +354,12 @@ impl Param<Option<identity::Name>> for HttpLegacy {
     }
 }
 
+impl Param<OrigDstAddr> for HttpLegacy {
+    fn param(&self) -> OrigDstAddr {
+        OrigDstAddr(self.client.local_addr)
+    }
+}
+
 impl Param<http::Version> for HttpLegacy {
     fn param(&self) -> http::Version {
         self.version
@@ -0,0 +1,103 @@
+use linkerd_app_core::{
+    config::PortSet,
+    metrics,
+    svc::{self, Param},
+    transport::OrigDstAddr,
+    Infallible,
+};
+use tracing::debug;
+
+/// Closes connections to ports that are not explicitly allowed, when the
+/// inbound proxy denies connections by default.
+///
+/// Connections that target the inbound proxy's own port are always permitted,
+/// so that meshed clients may reach the proxy's direct stack.
+#[derive(Clone, Debug)]
+pub(crate) struct AllowPorts {
+    allow: Option<PortSet>,
+    proxy_port: u16,
+    metrics: metrics::Proxy,
+}
+
+// === impl AllowPorts ===
+
+impl AllowPorts {
+    /// Permits connections to the `allow` ports if `default_deny` is set, or
+    /// to all ports otherwise.
+    pub(crate) fn new(
+        default_deny: bool,
+        allow: &PortSet,
+        proxy_port: u16,
+        metrics: metrics::Proxy,
+    ) -> Self {
+        Self {
+            allow: if default_deny {
+                Some(allow.clone())
+            } else {
+                None
+            },
+            proxy_port,
+            metrics,
+        }
+    }
+
+    pub(crate) fn filter<T>(&self, target: T) -> Result<svc::Either<T, OrigDstAddr>, Infallible>
+    where
+        T: Param<OrigDstAddr>,
+    {
+        let allow = match self.allow.as_ref() {
+            Some(allow) => allow,
+            None => return Ok(svc::Either::A(target)),
+        };
+
+        let OrigDstAddr(addr) = target.param();
+        if addr.port() == self.proxy_port || allow.contains(&addr.port()) {
+            return Ok(svc::Either::A(target));
+        }
+
+        debug!(
+            port = addr.port(),
+            "Denying connection to a port that is not allowed"
+        );
+        self.metrics.tcp_port_denied.incr();
+        Ok(svc::Either::B(OrigDstAddr(addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::metrics::FmtMetrics;
+    use std::net::SocketAddr;
+
+    fn is_denied(filter: &AllowPorts, port: u16) -> bool {
+        let target = OrigDstAddr(SocketAddr::from(([192, 0, 2, 2], port)));
+        matches!(filter.filter(target), Ok(svc::Either::B(_)))
+    }
+
+    #[test]
+    fn denies_unknown_ports() {
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let allow = vec![8080, 9090].into_iter().collect::<PortSet>();
+
+        let filter = AllowPorts::new(false, &allow, 4143, metrics.inbound.clone());
+        assert!(
+            !is_denied(&filter, 7070),
+            "all ports must be allowed unless connections are denied by default"
+        );
+
+        let filter = AllowPorts::new(true, &allow, 4143, metrics.inbound);
+        assert!(!is_denied(&filter, 8080));
+        assert!(!is_denied(&filter, 9090));
+        assert!(
+            !is_denied(&filter, 4143),
+            "the proxy's port must always be allowed"
+        );
+        assert!(is_denied(&filter, 7070));
+        assert!(is_denied(&filter, 22));
+
+        let metrics = report.as_display().to_string();
+        assert!(metrics.contains("inbound_tcp_denied_unknown_port_total 2"));
+    }
+}
@@ -0,0 +1,54 @@
+use linkerd_app_core::{
+    proxy::identity::LocalCrtKey,
+    svc::Param,
+    tls::{self, server::ClientAuth},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Determines, by port, how inbound TLS connections authenticate clients.
+///
+/// Ports without a configured mode use the default mode, which requests, but
+/// does not require, a client certificate.
+#[derive(Clone, Debug, Default)]
+pub struct ClientAuthForPorts {
+    default: ClientAuth,
+    ports: Arc<HashMap<u16, ClientAuth>>,
+}
+
+/// The local identity, as used to terminate TLS on a port.
+#[derive(Clone, Debug)]
+pub(crate) struct WithClientAuth {
+    crt_key: LocalCrtKey,
+    auth: ClientAuth,
+}
+
+// === impl ClientAuthForPorts ===
+
+impl ClientAuthForPorts {
+    pub fn new(default: ClientAuth, ports: impl IntoIterator<Item = (u16, ClientAuth)>) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+        }
+    }
+
+    pub(crate) fn identity(&self, crt_key: LocalCrtKey, port: u16) -> WithClientAuth {
+        let auth = self.ports.get(&port).copied().unwrap_or(self.default);
+        tracing::trace!(%port, ?auth);
+        WithClientAuth { crt_key, auth }
+    }
+}
+
+// === impl WithClientAuth ===
+
+impl Param<tls::server::Config> for WithClientAuth {
+    fn param(&self) -> tls::server::Config {
+        self.crt_key.server_config_for(self.auth)
+    }
+}
+
+impl Param<tls::LocalId> for WithClientAuth {
+    fn param(&self) -> tls::LocalId {
+        self.crt_key.id().clone()
+    }
+}
@@ -0,0 +1,289 @@
+use crate::target::{HttpAccept, TcpAccept};
+use futures::future;
+use linkerd_app_core::{
+    drain, io,
+    metrics::connection_rate::{Outcome, Registry},
+    proxy::http::{self, h2},
+    svc, Error, Infallible,
+};
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Limits, by port, the rate at which inbound connections are accepted.
+///
+/// Each port with a limit accepts, on average, up to `per_second` connections
+/// each second, with bursts of up to `burst` connections. Connections in
+/// excess of their port's limit are not forwarded to the application: those
+/// that are detected as HTTP are served 429 Too Many Requests responses, and
+/// all others are closed. Ports without a limit accept all connections.
+///
+/// Each instance tracks its own limits, so replacing the configuration resets
+/// every port's limit.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionRateLimitsForPorts {
+    ports: Arc<HashMap<u16, Mutex<Tokens>>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionRateLimit {
+    /// The average number of connections accepted each second.
+    pub per_second: u32,
+
+    /// The number of connections that may be accepted at once.
+    pub burst: u32,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectionRateFilter {
+    limits: ConnectionRateLimitsForPorts,
+    metrics: Registry,
+}
+
+#[derive(Debug)]
+struct Tokens {
+    per_second: f64,
+    burst: f64,
+    available: f64,
+    updated: Instant,
+}
+
+// === impl ConnectionRateLimitsForPorts ===
+
+impl ConnectionRateLimitsForPorts {
+    pub fn new(limits: impl IntoIterator<Item = (u16, ConnectionRateLimit)>) -> Self {
+        let ports = limits
+            .into_iter()
+            .map(|(port, limit)| (port, Mutex::new(Tokens::new(limit))))
+            .collect();
+        Self {
+            ports: Arc::new(ports),
+        }
+    }
+
+    pub(crate) fn filter(&self, metrics: Registry) -> ConnectionRateFilter {
+        ConnectionRateFilter {
+            limits: self.clone(),
+            metrics,
+        }
+    }
+}
+
+// === impl ConnectionRateFilter ===
+
+impl ConnectionRateFilter {
+    /// Limits connections whose protocol is not detected.
+    pub(crate) fn filter_tcp(
+        &self,
+        tcp: TcpAccept,
+    ) -> Result<svc::Either<TcpAccept, TcpAccept>, Infallible> {
+        if self.permits(tcp.target_addr.port()) {
+            return Ok(svc::Either::A(tcp));
+        }
+        Ok(svc::Either::B(tcp))
+    }
+
+    /// Limits connections once their protocol has been detected, so that
+    /// limited HTTP connections may be answered with HTTP responses.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn filter_detected(
+        &self,
+        (version, tcp): (Option<http::Version>, TcpAccept),
+    ) -> Result<
+        svc::Either<(Option<http::Version>, TcpAccept), (Option<http::Version>, TcpAccept)>,
+        Infallible,
+    > {
+        if self.permits(tcp.target_addr.port()) {
+            return Ok(svc::Either::A((version, tcp)));
+        }
+        Ok(svc::Either::B((version, tcp)))
+    }
+
+    fn permits(&self, port: u16) -> bool {
+        let tokens = match self.limits.ports.get(&port) {
+            Some(tokens) => tokens,
+            None => return true,
+        };
+        if tokens.lock().try_acquire() {
+            self.metrics.incr(port, Outcome::Accepted);
+            return true;
+        }
+        debug!(%port, "Connection rate limit exceeded");
+        self.metrics.incr(port, Outcome::Limited);
+        false
+    }
+}
+
+/// Builds a stack that serves connections that exceed their port's rate
+/// limit: HTTP requests fail with 429 Too Many Requests responses, after which
+/// the connection is closed, and other connections are closed without being
+/// read.
+pub(crate) fn limited<I>(
+    h2_settings: h2::Settings,
+    drain: drain::Watch,
+) -> impl svc::NewService<(Option<http::Version>, TcpAccept), Service = svc::BoxService<I, (), Error>>
+       + Clone
+where
+    I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + Send + Unpin + 'static,
+{
+    svc::stack(|_: HttpAccept| svc::mk(respond_limited::<http::UpgradeBody>))
+        .push(http::NewServeHttp::layer(h2_settings, drain))
+        .push_on_response(svc::BoxService::layer())
+        .push_map_target(HttpAccept::from)
+        .push(svc::UnwrapOr::layer(|_: TcpAccept| {
+            svc::BoxService::new(svc::mk(|_: I| future::ok::<(), Error>(())))
+        }))
+        .push_on_response(svc::BoxService::layer())
+        .into_inner()
+}
+
+/// Fails a request on a limited connection and closes the connection, so that
+/// HTTP/2 clients are sent a GOAWAY rather than reusing it.
+fn respond_limited<B>(
+    req: http::Request<B>,
+) -> future::Ready<Result<http::Response<http::BoxBody>, Error>> {
+    if let Some(http::ClientHandle { close, .. }) = req.extensions().get::<http::ClientHandle>() {
+        close.close();
+    }
+    future::ok(too_many_connections(req.version()))
+}
+
+fn too_many_connections(version: ::http::Version) -> http::Response<http::BoxBody> {
+    let mut rsp = http::Response::builder()
+        .status(http::StatusCode::TOO_MANY_REQUESTS)
+        .version(version)
+        .header(http::header::CONTENT_LENGTH, "0");
+    // Close HTTP/1 connections, so that clients reconnect once the limit
+    // permits them.
+    if version < ::http::Version::HTTP_2 {
+        rsp = rsp.header(http::header::CONNECTION, "close");
+    }
+    rsp.body(http::BoxBody::default())
+        .expect("response must be valid")
+}
+
+// === impl Tokens ===
+
+impl Tokens {
+    fn new(ConnectionRateLimit { per_second, burst }: ConnectionRateLimit) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            per_second: f64::from(per_second),
+            burst,
+            available: burst,
+            updated: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+        self.available = (self.available + elapsed.as_secs_f64() * self.per_second).min(self.burst);
+        if self.available < 1.0 {
+            return false;
+        }
+        self.available -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::FmtMetrics,
+        tls,
+        transport::{ClientAddr, Remote},
+        Conditional,
+    };
+    use std::time::Duration;
+
+    fn accept(port: u16) -> TcpAccept {
+        TcpAccept {
+            target_addr: ([192, 0, 2, 2], port).into(),
+            client_addr: Remote(ClientAddr(([192, 0, 2, 3], 40000).into())),
+            tls: Conditional::None(tls::NoServerTls::NoClientHello),
+            class: None,
+        }
+    }
+
+    fn accepted(filter: &ConnectionRateFilter, port: u16) -> usize {
+        (0..10)
+            .filter(|_| matches!(filter.filter_tcp(accept(port)), Ok(svc::Either::A(_))))
+            .count()
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn limits_each_port() {
+        let metrics = Registry::default();
+        let limits = ConnectionRateLimitsForPorts::new(vec![
+            (
+                8080,
+                ConnectionRateLimit {
+                    per_second: 2,
+                    burst: 5,
+                },
+            ),
+            (
+                9090,
+                ConnectionRateLimit {
+                    per_second: 1,
+                    burst: 1,
+                },
+            ),
+        ]);
+        let filter = limits.filter(metrics.clone());
+
+        assert_eq!(accepted(&filter, 8080), 5, "bursts must be permitted");
+        assert_eq!(accepted(&filter, 9090), 1);
+        assert_eq!(accepted(&filter, 7070), 10, "unlimited ports");
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(accepted(&filter, 8080), 2);
+        assert_eq!(accepted(&filter, 9090), 1);
+
+        let detected = filter.filter_detected((Some(http::Version::Http1), accept(9090)));
+        assert!(matches!(detected, Ok(svc::Either::B(_))));
+
+        let report = metrics.as_display().to_string();
+        assert!(report.contains(
+            "inbound_tcp_connection_rate_total{target_port=\"8080\",outcome=\"accepted\"} 7"
+        ));
+        assert!(report.contains(
+            "inbound_tcp_connection_rate_total{target_port=\"9090\",outcome=\"limited\"} 19"
+        ));
+        assert!(!report.contains("7070"), "unlimited ports are not counted");
+    }
+
+    #[test]
+    fn limited_http_responses() {
+        let rsp = too_many_connections(::http::Version::HTTP_11);
+        assert_eq!(rsp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rsp.headers()[http::header::CONNECTION], "close");
+
+        let rsp = too_many_connections(::http::Version::HTTP_2);
+        assert_eq!(rsp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        assert!(rsp.headers().get(http::header::CONNECTION).is_none());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn closes_limited_connections() {
+        use linkerd_app_core::svc::ServiceExt;
+
+        let (svc, closed) = http::SetClientHandle::new(
+            ([192, 0, 2, 3], 40000).into(),
+            svc::mk(respond_limited::<()>),
+        );
+        let req = http::Request::builder()
+            .version(::http::Version::HTTP_2)
+            .body(())
+            .unwrap();
+        let rsp = svc.oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        tokio::time::timeout(Duration::from_secs(1), closed)
+            .await
+            .expect("the connection must be closed");
+    }
+}
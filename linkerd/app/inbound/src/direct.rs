@@ -1,19 +1,117 @@
-use crate::{target::TcpEndpoint, Inbound};
+use crate::{
+    client_auth::{ClientAuthForPorts, WithClientAuth},
+    proxy_protocol::ProxyAddrs,
+    target::TcpEndpoint,
+    Inbound,
+};
+use futures::{future, prelude::*, ready};
 use linkerd_app_core::{
-    io,
+    connection_log, io, linkerd_dns, metrics,
     proxy::identity::LocalCrtKey,
     svc::{self, ExtractParam, InsertParam, Param},
     tls,
     transport::{self, metrics::SensorIo, ClientAddr, OrigDstAddr, Remote},
     transport_header::{self, NewTransportHeaderServer, SessionProtocol, TransportHeader},
-    Conditional, Error, Infallible, NameAddr,
+    Conditional, Error, Infallible, IpMatch, NameAddr, NameMatch,
+};
+use parking_lot::Mutex;
+use std::{
+    convert::TryFrom,
+    fmt::Debug,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
 };
-use std::{convert::TryFrom, fmt::Debug, net::SocketAddr};
 use thiserror::Error;
-use tracing::{debug_span, info_span};
+use tokio::time;
+use tracing::{debug, debug_span, info_span};
+
+/// Determines how plaintext connections (i.e. those without a TLS
+/// ClientHello) to the inbound mesh port are handled.
+#[derive(Clone, Debug)]
+pub enum PlaintextPolicy {
+    /// Plaintext connections are handled like any other connection that lacks
+    /// a client identity.
+    Permit,
+
+    /// Plaintext connections are closed as soon as they are detected, except
+    /// those from the given networks (e.g. health-check probes), which are
+    /// handled as if plaintext were permitted.
+    Reject { exempt: IpMatch },
+}
+
+/// Determines how mesh port clients that are expected to negotiate the
+/// transport header protocol via ALPN, but do not, are handled.
+///
+/// A client that does not negotiate the transport header is handled as a
+/// legacy gateway client, so a peer that strips the protocol from the
+/// handshake could otherwise force a downgrade.
+#[derive(Clone, Debug)]
+pub enum AlpnDowngradePolicy {
+    /// Clients that do not negotiate the transport header are handled as
+    /// legacy gateway clients.
+    Permit,
+
+    /// Connections from clients whose identities match these suffixes are
+    /// closed unless they negotiate the transport header. Other clients, which
+    /// may be proxies that do not support it, are handled as if downgrades
+    /// were permitted.
+    Reject { identities: NameMatch },
+}
+
+/// Determines how connections to the inbound mesh port are handled when they
+/// are accepted before the proxy's identity has been certified, since these
+/// connections cannot yet be authenticated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdentityPendingPolicy {
+    /// Connections are closed with an `IdentityNotCertified` error.
+    Reject,
+
+    /// Connections are held until the identity is certified. Connections that
+    /// are held for longer than the timeout are closed with an
+    /// `IdentityTimeout` error.
+    Wait { timeout: Duration },
+}
+
+/// Determines whether the TLS alerts that end mesh port connections are
+/// logged, at debug, and how many are logged per second. Alerts beyond the
+/// limit are counted and summarized once per second rather than logged, so
+/// that a flood of failing handshakes cannot overwhelm the logs. By default,
+/// alerts are not logged.
+#[derive(Clone, Debug, Default)]
+pub struct TlsAlertLogs(Option<Arc<Mutex<AlertLogWindow>>>);
+
+#[derive(Debug)]
+struct AlertLogWindow {
+    per_second: u32,
+    started: time::Instant,
+    logged: u32,
+    suppressed: u64,
+}
+
+#[derive(Clone, Debug)]
+struct PlaintextFilter {
+    policy: PlaintextPolicy,
+    metrics: metrics::Proxy,
+}
+
+#[derive(Clone, Debug)]
+struct DowngradeFilter {
+    policy: AlpnDowngradePolicy,
+    metrics: metrics::Proxy,
+}
 
 #[derive(Clone, Debug)]
-struct WithTransportHeaderAlpn(LocalCrtKey);
+struct WithTransportHeaderAlpn(WithClientAuth);
+
+/// Holds or closes each connection, as configured, until the proxy's identity
+/// is certified.
+struct AwaitIdentity<S> {
+    inner: S,
+    pending: Option<Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>>,
+}
 
 /// Creates I/O errors when a connection cannot be forwarded because no transport
 /// header was present.
@@ -28,6 +126,14 @@ pub struct RefusedNoIdentity(());
 #[error("a named target must be provided on gateway connections")]
 struct RefusedNoTarget;
 
+#[derive(Debug, Error)]
+#[error("the proxy's identity has not been certified")]
+pub struct IdentityNotCertified(());
+
+#[derive(Debug, Error)]
+#[error("the proxy's identity was not certified within {0:?}")]
+pub struct IdentityTimeout(Duration);
+
 /// Gateway connections come in two variants: those with a transport header, and
 /// legacy connections, without a transport header.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -58,7 +164,8 @@ pub type GatewayIo<I> = io::EitherIo<FwdIo<I>, SensorIo<tls::server::Io<I>>>;
 #[derive(Clone)]
 struct TlsParams {
     timeout: tls::server::Timeout,
-    identity: Option<WithTransportHeaderAlpn>,
+    identity: Option<LocalCrtKey>,
+    client_auth: ClientAuthForPorts,
 }
 
 impl<N> Inbound<N> {
@@ -95,6 +202,15 @@ impl<N> Inbound<N> {
     {
         self.map_stack(|config, rt, tcp| {
             let detect_timeout = config.proxy.detect_protocol_timeout;
+            let plaintext = PlaintextFilter {
+                policy: config.direct_plaintext.clone(),
+                metrics: rt.metrics.clone(),
+            };
+            let downgrade = DowngradeFilter {
+                policy: config.direct_alpn_downgrade.clone(),
+                metrics: rt.metrics.clone(),
+            };
+            let tls_alert_logs = config.direct_tls_alert_logs.clone();
 
             tcp.instrument(|_: &TcpEndpoint| debug_span!("opaque"))
                 // When the transport header is present, it may be used for either local
@@ -107,7 +223,14 @@ impl<N> Inbound<N> {
                             port,
                             name: None,
                             protocol: None,
-                        } => Ok(svc::Either::A(TcpEndpoint { port })),
+                        } => Ok(svc::Either::A(TcpEndpoint {
+                            port,
+                            proxy_addrs: Some(ProxyAddrs {
+                                client: client.client_addr,
+                                orig_dst: OrigDstAddr((client.local_addr.ip(), port).into()),
+                            }),
+                            client_id: Some(client.client_id.clone()),
+                        })),
                         TransportHeader {
                             port,
                             name: Some(name),
@@ -153,15 +276,42 @@ impl<N> Inbound<N> {
                         .instrument(|_: &GatewayConnection| info_span!("gateway", legacy = true))
                         .into_inner(),
                 )
+                // Close connections from clients that are expected to negotiate
+                // the transport header but did not, if so configured.
+                .push_switch(
+                    move |client: ClientInfo| downgrade.filter(client),
+                    |_: ClientInfo| {
+                        svc::mk(|_: SensorIo<tls::server::Io<I>>| future::ok::<(), Error>(()))
+                    },
+                )
                 .push(rt.metrics.transport.layer_accept())
                 // Build a ClientInfo target for each accepted connection. Refuse the
                 // connection if it doesn't include an mTLS identity.
                 .push_request_filter(ClientInfo::try_from)
+                // Close plaintext connections before they are refused for
+                // lacking an identity, if so configured.
+                .push_switch(
+                    move |target: (tls::ConditionalServerTls, T)| plaintext.filter(target),
+                    |_: Remote<ClientAddr>| {
+                        svc::mk(|_: tls::server::Io<I>| future::ok::<(), Error>(()))
+                    },
+                )
+                .push_on_response(connection_log::RecordTlsSession::layer())
                 .push(svc::BoxNewService::layer())
                 .push(tls::NewDetectTls::layer(TlsParams {
                     timeout: tls::server::Timeout(detect_timeout),
-                    identity: rt.identity.clone().map(WithTransportHeaderAlpn),
+                    identity: rt.identity.clone(),
+                    client_auth: config.client_auth.clone(),
                 }))
+                // Log the TLS alerts that end connections, if so configured.
+                .push_on_response(svc::MapErrLayer::new(move |e: Error| {
+                    tls_alert_logs.log(&e);
+                    e
+                }))
+                // Connections accepted before the proxy's identity is certified
+                // cannot be authenticated, so they are held or closed before
+                // TLS is detected.
+                .push_on_response(config.direct_identity_pending.layer(rt.identity.clone()))
                 .check_new_service::<T, I>()
                 .push_on_response(svc::BoxService::layer())
                 .push(svc::BoxNewService::layer())
@@ -169,6 +319,242 @@ impl<N> Inbound<N> {
     }
 }
 
+// === impl PlaintextPolicy ===
+
+impl Default for PlaintextPolicy {
+    fn default() -> Self {
+        Self::Permit
+    }
+}
+
+// === impl IdentityPendingPolicy ===
+
+impl Default for IdentityPendingPolicy {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+impl IdentityPendingPolicy {
+    fn layer<S>(
+        self,
+        identity: Option<LocalCrtKey>,
+    ) -> impl svc::Layer<S, Service = AwaitIdentity<S>> + Clone {
+        svc::layer::mk(move |inner| {
+            let pending = match identity.as_ref() {
+                Some(id) if !id.is_certified() => Some(self.pending(id.clone().await_crt())),
+                _ => None,
+            };
+            AwaitIdentity { inner, pending }
+        })
+    }
+
+    /// Returns a future that completes successfully if `certified` completes
+    /// successfully within the policy's timeout.
+    fn pending<F, T, E>(
+        self,
+        certified: F,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>
+    where
+        F: Future<Output = Result<T, E>> + Send + 'static,
+    {
+        match self {
+            Self::Reject => {
+                debug!("Closing connection accepted before the identity was certified");
+                Box::pin(future::err(IdentityNotCertified(()).into()))
+            }
+            Self::Wait { timeout } => {
+                debug!(?timeout, "Waiting for the identity to be certified");
+                Box::pin(async move {
+                    match time::timeout(timeout, certified).await {
+                        Ok(Ok(_)) => Ok(()),
+                        // The identity daemon has ended.
+                        Ok(Err(_)) => Err(IdentityNotCertified(()).into()),
+                        Err(_) => Err(IdentityTimeout(timeout).into()),
+                    }
+                })
+            }
+        }
+    }
+}
+
+// === impl AwaitIdentity ===
+
+impl<I, S> svc::Service<I> for AwaitIdentity<S>
+where
+    S: svc::Service<I>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::ErrInto<S::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(pending) = self.pending.as_mut() {
+            let res = ready!(pending.as_mut().poll(cx));
+            self.pending = None;
+            res?;
+        }
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        self.inner.call(io).err_into()
+    }
+}
+
+// === impl TlsAlertLogs ===
+
+impl TlsAlertLogs {
+    pub fn per_second(per_second: u32) -> Self {
+        Self(Some(Arc::new(Mutex::new(AlertLogWindow {
+            per_second,
+            started: time::Instant::now(),
+            logged: 0,
+            suppressed: 0,
+        }))))
+    }
+
+    /// Logs the TLS alert that caused the error, if any and if the limit
+    /// permits. Returns the logged alert.
+    fn log(&self, error: &Error) -> Option<tls::alert::Alert> {
+        let window = self.0.as_ref()?;
+        let alert = tls::alert::Alert::from_error(error)?;
+        if !Self::permit(window) {
+            return None;
+        }
+        debug!(%alert, "TLS connection failed");
+        Some(alert)
+    }
+
+    /// Returns true if an alert may be logged.
+    ///
+    /// When the first alert of a window is suppressed, a task is spawned to
+    /// summarize the window's suppressed alerts once it ends, so that the
+    /// summary is logged even if no more alerts follow.
+    fn permit(window: &Arc<Mutex<AlertLogWindow>>) -> bool {
+        let mut w = window.lock();
+        if w.permit(time::Instant::now()) {
+            return true;
+        }
+
+        if w.suppressed == 1 {
+            let started = w.started;
+            let window = window.clone();
+            tokio::spawn(async move {
+                time::sleep_until(started + AlertLogWindow::DURATION).await;
+                let mut w = window.lock();
+                // If the window has already ended, its summary was logged.
+                if w.started == started {
+                    w.flush();
+                }
+            });
+        }
+        false
+    }
+}
+
+impl AlertLogWindow {
+    const DURATION: Duration = Duration::from_secs(1);
+
+    fn permit(&mut self, now: time::Instant) -> bool {
+        if now.saturating_duration_since(self.started) >= Self::DURATION {
+            self.flush();
+            self.started = now;
+            self.logged = 0;
+        }
+
+        if self.logged >= self.per_second {
+            self.suppressed += 1;
+            return false;
+        }
+        self.logged += 1;
+        true
+    }
+
+    /// Logs the number of alerts suppressed since the last summary, if any.
+    fn flush(&mut self) {
+        if self.suppressed > 0 {
+            debug!(
+                suppressed = self.suppressed,
+                "TLS alert logs were rate limited"
+            );
+            self.suppressed = 0;
+        }
+    }
+}
+
+// === impl PlaintextFilter ===
+
+impl PlaintextFilter {
+    fn filter<T>(
+        &self,
+        (tls, target): (tls::ConditionalServerTls, T),
+    ) -> Result<svc::Either<(tls::ConditionalServerTls, T), Remote<ClientAddr>>, Infallible>
+    where
+        T: Param<Remote<ClientAddr>>,
+    {
+        let exempt = match (&self.policy, &tls) {
+            (
+                PlaintextPolicy::Reject { exempt },
+                Conditional::None(tls::NoServerTls::NoClientHello),
+            ) => exempt,
+            _ => return Ok(svc::Either::A((tls, target))),
+        };
+
+        let client: Remote<ClientAddr> = target.param();
+        let Remote(ClientAddr(addr)) = client;
+        if exempt.matches(addr.ip()) {
+            debug!(client.addr = %client, "Permitting plaintext connection from exempt client");
+            return Ok(svc::Either::A((tls, target)));
+        }
+
+        debug!(client.addr = %client, "Rejecting plaintext connection to the mesh port");
+        self.metrics.direct_plaintext_rejected.incr();
+        Ok(svc::Either::B(client))
+    }
+}
+
+// === impl AlpnDowngradePolicy ===
+
+impl Default for AlpnDowngradePolicy {
+    fn default() -> Self {
+        Self::Permit
+    }
+}
+
+// === impl DowngradeFilter ===
+
+impl DowngradeFilter {
+    fn filter(
+        &self,
+        client: ClientInfo,
+    ) -> Result<svc::Either<ClientInfo, ClientInfo>, Infallible> {
+        let identities = match &self.policy {
+            AlpnDowngradePolicy::Reject { identities } if !client.header_negotiated() => identities,
+            _ => return Ok(svc::Either::A(client)),
+        };
+
+        let tls::ClientId(id) = &client.client_id;
+        let expected = id
+            .as_ref()
+            .parse::<linkerd_dns::Name>()
+            .map(|name| identities.matches(&name))
+            .unwrap_or(false);
+        if !expected {
+            return Ok(svc::Either::A(client));
+        }
+
+        debug!(
+            client.id = %id,
+            alpn = ?client.alpn,
+            "Rejecting connection that did not negotiate the transport header"
+        );
+        self.metrics.direct_downgrade_rejected.incr();
+        Ok(svc::Either::B(client))
+    }
+}
+
 // === impl ClientInfo ===
 
 impl<T> TryFrom<(tls::ConditionalServerTls, T)> for ClientInfo
@@ -215,6 +601,7 @@ impl Param<transport::labels::Key> for ClientInfo {
                 negotiated_protocol: self.alpn.clone(),
             }),
             self.local_addr,
+            None,
         )
     }
 }
@@ -228,7 +615,8 @@ impl svc::Param<tls::server::Config> for WithTransportHeaderAlpn {
         // TODO: Avoid cloning the server config for every connection. It would
         // be preferable if rustls::ServerConfig wrapped individual fields in an
         // Arc so they could be overridden independently.
-        let mut config = self.0.server_config().as_ref().clone();
+        let config: tls::server::Config = self.0.param();
+        let mut config = config.as_ref().clone();
         config
             .alpn_protocols
             .push(transport_header::PROTOCOL.into());
@@ -238,7 +626,7 @@ impl svc::Param<tls::server::Config> for WithTransportHeaderAlpn {
 
 impl svc::Param<tls::LocalId> for WithTransportHeaderAlpn {
     fn param(&self) -> tls::LocalId {
-        self.0.id().clone()
+        self.0.param()
     }
 }
 
@@ -262,10 +650,14 @@ impl<T> ExtractParam<tls::server::Timeout, T> for TlsParams {
     }
 }
 
-impl<T> ExtractParam<Option<WithTransportHeaderAlpn>, T> for TlsParams {
+impl<T: Param<OrigDstAddr>> ExtractParam<Option<WithTransportHeaderAlpn>, T> for TlsParams {
     #[inline]
-    fn extract_param(&self, _: &T) -> Option<WithTransportHeaderAlpn> {
-        self.identity.clone()
+    fn extract_param(&self, t: &T) -> Option<WithTransportHeaderAlpn> {
+        let OrigDstAddr(addr) = t.param();
+        let crt_key = self.identity.clone()?;
+        Some(WithTransportHeaderAlpn(
+            self.client_auth.identity(crt_key, addr.port()),
+        ))
     }
 }
 
@@ -277,3 +669,279 @@ impl<T> InsertParam<tls::ConditionalServerTls, T> for TlsParams {
         (tls, target)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+    use svc::ServiceExt;
+
+    #[derive(Clone, Debug)]
+    struct Target(Remote<ClientAddr>);
+
+    impl Param<Remote<ClientAddr>> for Target {
+        fn param(&self) -> Remote<ClientAddr> {
+            self.0
+        }
+    }
+
+    fn client(ip: [u8; 4]) -> Target {
+        Target(Remote(ClientAddr((ip, 40000).into())))
+    }
+
+    fn filter(policy: PlaintextPolicy) -> PlaintextFilter {
+        let (metrics, _) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        PlaintextFilter {
+            policy,
+            metrics: metrics.inbound,
+        }
+    }
+
+    fn plaintext() -> tls::ConditionalServerTls {
+        Conditional::None(tls::NoServerTls::NoClientHello)
+    }
+
+    fn is_rejected(
+        filter: &PlaintextFilter,
+        tls: tls::ConditionalServerTls,
+        target: Target,
+    ) -> bool {
+        matches!(filter.filter((tls, target)), Ok(svc::Either::B(_)))
+    }
+
+    #[test]
+    fn rejects_plaintext() {
+        let reject = filter(PlaintextPolicy::Reject {
+            exempt: IpMatch::new(Some("10.1.0.0/16".parse().unwrap())),
+        });
+        assert!(is_rejected(&reject, plaintext(), client([10, 0, 0, 1])));
+        assert!(
+            !is_rejected(&reject, plaintext(), client([10, 1, 0, 1])),
+            "exempt clients must not be rejected"
+        );
+        let tls = Conditional::Some(tls::ServerTls::Established {
+            client_id: None,
+            negotiated_protocol: None,
+        });
+        assert!(
+            !is_rejected(&reject, tls, client([10, 0, 0, 1])),
+            "TLS connections must not be rejected"
+        );
+
+        let permit = filter(PlaintextPolicy::Permit);
+        assert!(!is_rejected(&permit, plaintext(), client([10, 0, 0, 1])));
+    }
+
+    fn downgrade_filter(policy: AlpnDowngradePolicy) -> DowngradeFilter {
+        let (metrics, _) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        DowngradeFilter {
+            policy,
+            metrics: metrics.inbound,
+        }
+    }
+
+    fn client_info(id: &str, alpn: Option<&[u8]>) -> ClientInfo {
+        ClientInfo {
+            client_id: tls::ClientId(id.parse().unwrap()),
+            alpn: alpn.map(|p| tls::NegotiatedProtocol(p.to_vec())),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 1], 40000).into())),
+            local_addr: ([10, 0, 0, 2], 4143).into(),
+        }
+    }
+
+    fn is_downgrade_rejected(filter: &DowngradeFilter, client: ClientInfo) -> bool {
+        matches!(filter.filter(client), Ok(svc::Either::B(_)))
+    }
+
+    #[test]
+    fn rejects_downgrades() {
+        let reject = downgrade_filter(AlpnDowngradePolicy::Reject {
+            identities: NameMatch::new(Some(
+                "ns.serviceaccount.identity.linkerd.cluster.local"
+                    .parse()
+                    .unwrap(),
+            )),
+        });
+        let meshed = "default.ns.serviceaccount.identity.linkerd.cluster.local";
+        assert!(
+            is_downgrade_rejected(&reject, client_info(meshed, None)),
+            "expected clients must negotiate a protocol"
+        );
+        assert!(
+            is_downgrade_rejected(&reject, client_info(meshed, Some(&b"h2"[..]))),
+            "expected clients must negotiate the transport header"
+        );
+        assert!(!is_downgrade_rejected(
+            &reject,
+            client_info(meshed, Some(transport_header::PROTOCOL))
+        ));
+        assert!(
+            !is_downgrade_rejected(
+                &reject,
+                client_info(
+                    "default.legacy.serviceaccount.identity.linkerd.cluster.local",
+                    None
+                )
+            ),
+            "other clients must not be rejected"
+        );
+
+        let permit = downgrade_filter(AlpnDowngradePolicy::Permit);
+        assert!(!is_downgrade_rejected(&permit, client_info(meshed, None)));
+    }
+
+    /// Returns a connection service that is pending on `certified`, and
+    /// whether its inner service has been called.
+    fn await_identity<F>(
+        policy: IdentityPendingPolicy,
+        certified: F,
+    ) -> (
+        AwaitIdentity<impl svc::Service<(), Response = (), Error = Error>>,
+        Arc<AtomicBool>,
+    )
+    where
+        F: Future<Output = Result<(), ()>> + Send + 'static,
+    {
+        let called = Arc::new(AtomicBool::new(false));
+        let inner = {
+            let called = called.clone();
+            svc::mk(move |()| {
+                called.store(true, Ordering::SeqCst);
+                future::ok::<(), Error>(())
+            })
+        };
+        let svc = AwaitIdentity {
+            inner,
+            pending: Some(policy.pending(certified)),
+        };
+        (svc, called)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_connections_before_identity_is_certified() {
+        let (svc, called) = await_identity(IdentityPendingPolicy::Reject, future::pending());
+        let error = svc.oneshot(()).await.unwrap_err();
+        assert!(error.is::<IdentityNotCertified>(), "{}", error);
+        assert!(!called.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn holds_connections_until_identity_is_certified() {
+        time::pause();
+        let policy = IdentityPendingPolicy::Wait {
+            timeout: Duration::from_secs(10),
+        };
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let (svc, called) = await_identity(policy, rx.map_err(|_| ()));
+        let conn = tokio::spawn(svc.oneshot(()));
+        time::sleep(Duration::from_secs(5)).await;
+        assert!(
+            !called.load(Ordering::SeqCst),
+            "connections must be held until the identity is certified"
+        );
+        tx.send(()).unwrap();
+        conn.await.unwrap().expect("connection must be served");
+        assert!(called.load(Ordering::SeqCst));
+
+        let (svc, called) = await_identity(policy, future::pending());
+        let error = svc.oneshot(()).await.unwrap_err();
+        assert!(error.is::<IdentityTimeout>(), "{}", error);
+        assert!(!called.load(Ordering::SeqCst));
+
+        let (svc, _) = await_identity(policy, future::err(()));
+        let error = svc.oneshot(()).await.unwrap_err();
+        assert!(
+            error.is::<IdentityNotCertified>(),
+            "connections must fail if the identity daemon ends: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn limits_tls_alert_logs() {
+        let start = time::Instant::now();
+        let mut window = AlertLogWindow {
+            per_second: 2,
+            started: start,
+            logged: 0,
+            suppressed: 0,
+        };
+        assert!(window.permit(start));
+        assert!(window.permit(start + Duration::from_millis(500)));
+        assert!(!window.permit(start + Duration::from_millis(900)));
+        assert_eq!(window.suppressed, 1);
+
+        // Logging resumes once the window elapses.
+        assert!(window.permit(start + Duration::from_secs(1)));
+        assert_eq!(window.suppressed, 0);
+
+        assert!(
+            TlsAlertLogs::default()
+                .log(&io::Error::new(io::ErrorKind::InvalidData, "bad").into())
+                .is_none(),
+            "alerts must not be logged by default"
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        fn lines(&self) -> Vec<String> {
+            let logs = self.0.lock().unwrap();
+            String::from_utf8_lossy(&*logs)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn summarizes_suppressed_tls_alert_logs() {
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .with_max_level(tracing::Level::DEBUG)
+            .without_time()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let alert_logs = TlsAlertLogs::per_second(1);
+        let window = alert_logs.0.as_ref().unwrap();
+        assert!(TlsAlertLogs::permit(window));
+        assert!(!TlsAlertLogs::permit(window));
+        assert!(!TlsAlertLogs::permit(window));
+        assert!(logs.lines().is_empty(), "{:#?}", logs.lines());
+
+        // The summary is logged once the window ends, even though no more
+        // alerts arrive.
+        time::sleep(Duration::from_secs(2)).await;
+        let lines = logs.lines();
+        assert_eq!(lines.len(), 1, "{:#?}", lines);
+        assert!(
+            lines[0].contains("TLS alert logs were rate limited"),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].contains("suppressed=2"), "{}", lines[0]);
+    }
+}
@@ -0,0 +1,232 @@
+use futures::future;
+use linkerd_app_core::{
+    dst,
+    proxy::http::{self, header::HeaderValue},
+    svc::{self, stack::Proxy},
+};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Determines which HTTP methods are permitted on each inbound route.
+///
+/// When a `label` is configured, a route whose metadata sets it to a
+/// comma-separated list of methods (e.g. `GET,HEAD`) only permits those
+/// methods. Requests with other methods fail with a 405 Method Not Allowed
+/// response whose `Allow` header lists the permitted methods. Routes without
+/// the label, or whose label lists no valid methods, permit all methods.
+#[derive(Clone, Debug)]
+pub struct AllowedMethods {
+    label: Option<Arc<str>>,
+    allow_options: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewAllowMethods<N> {
+    inner: N,
+    config: AllowedMethods,
+}
+
+#[derive(Clone, Debug)]
+pub struct AllowMethods<P> {
+    inner: P,
+    allow: Option<Arc<Allow>>,
+}
+
+#[derive(Debug)]
+struct Allow {
+    methods: Vec<http::Method>,
+    header: HeaderValue,
+}
+
+// === impl AllowedMethods ===
+
+impl AllowedMethods {
+    /// When `allow_options` is set, `OPTIONS` requests (e.g. CORS preflight
+    /// requests) are permitted on all routes, whether or not they are listed.
+    pub fn new(label: Option<String>, allow_options: bool) -> Self {
+        Self {
+            label: label.map(Arc::from),
+            allow_options,
+        }
+    }
+
+    fn allow(&self, route: &dst::Route) -> Option<Allow> {
+        let value = route.route.labels().get(&**self.label.as_ref()?)?;
+        let mut methods = Vec::new();
+        for method in value.split(',') {
+            let method = method.trim();
+            if method.is_empty() {
+                continue;
+            }
+            match http::Method::from_bytes(method.as_bytes()) {
+                Ok(method) => methods.push(method),
+                Err(_) => warn!(%method, "Ignoring invalid allowed method"),
+            }
+        }
+        // A label that lists no valid methods is a misconfiguration; rather
+        // than rejecting every request on the route, it is ignored.
+        if methods.is_empty() {
+            warn!(%value, "No valid allowed methods; permitting all methods");
+            return None;
+        }
+        if self.allow_options && !methods.contains(&http::Method::OPTIONS) {
+            methods.push(http::Method::OPTIONS);
+        }
+
+        let list = methods
+            .iter()
+            .map(http::Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let header = HeaderValue::from_str(&list).expect("methods must be valid header values");
+        Some(Allow { methods, header })
+    }
+}
+
+impl Default for AllowedMethods {
+    fn default() -> Self {
+        Self::new(None, true)
+    }
+}
+
+// === impl NewAllowMethods ===
+
+impl<N> NewAllowMethods<N> {
+    pub fn layer(config: AllowedMethods) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewAllowMethods<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = AllowMethods<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let allow = self.config.allow(&route).map(Arc::new);
+        AllowMethods {
+            inner: self.inner.new_service(route),
+            allow,
+        }
+    }
+}
+
+// === impl AllowMethods ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for AllowMethods<P>
+where
+    P: Proxy<http::Request<B>, S, Response = http::Response<http::BoxBody>>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = future::Either<P::Future, future::Ready<Result<P::Response, P::Error>>>;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        if let Some(allow) = self.allow.as_ref() {
+            if !allow.methods.contains(req.method()) {
+                debug!(method = %req.method(), "Method not allowed on route");
+                let rsp = http::Response::builder()
+                    .status(http::StatusCode::METHOD_NOT_ALLOWED)
+                    .version(req.version())
+                    .header(http::header::ALLOW, allow.header.clone())
+                    .header(http::header::CONTENT_LENGTH, "0")
+                    .body(http::BoxBody::default())
+                    .expect("response must be valid");
+                return future::Either::Right(future::ok(rsp));
+            }
+        }
+
+        future::Either::Left(self.inner.proxy(svc, req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::Direction,
+        profiles,
+        svc::{Layer, NewService},
+        Error,
+    };
+
+    const LABEL: &str = "allowed-methods";
+
+    fn route(methods: Option<&str>) -> dst::Route {
+        let labels = methods.map(|m| (LABEL.to_string(), m.to_string()));
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        }
+    }
+
+    async fn send(
+        config: AllowedMethods,
+        methods: Option<&str>,
+        method: http::Method,
+    ) -> http::Response<http::BoxBody> {
+        let mut new_proxy = NewAllowMethods::layer(config).layer(|_: dst::Route| ());
+        let proxy = new_proxy.new_service(route(methods));
+        let mut inner = svc::mk(|_: http::Request<()>| {
+            future::ok::<_, Error>(http::Response::new(http::BoxBody::default()))
+        });
+        let req = http::Request::builder().method(method).body(()).unwrap();
+        proxy.proxy(&mut inner, req).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_methods_not_allowed() {
+        let config = AllowedMethods::new(Some(LABEL.to_string()), false);
+
+        let rsp = send(config.clone(), Some("GET"), http::Method::GET).await;
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+
+        let rsp = send(config.clone(), Some("GET"), http::Method::POST).await;
+        assert_eq!(rsp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(rsp.headers()[http::header::ALLOW], "GET");
+
+        let rsp = send(config.clone(), Some("GET"), http::Method::OPTIONS).await;
+        assert_eq!(rsp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+
+        let rsp = send(config, None, http::Method::POST).await;
+        assert_eq!(
+            rsp.status(),
+            http::StatusCode::OK,
+            "routes without the label must permit all methods"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn allows_options() {
+        let config = AllowedMethods::new(Some(LABEL.to_string()), true);
+
+        let rsp = send(config.clone(), Some("GET, HEAD"), http::Method::OPTIONS).await;
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+
+        let rsp = send(config, Some("GET, HEAD"), http::Method::DELETE).await;
+        assert_eq!(rsp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(rsp.headers()[http::header::ALLOW], "GET, HEAD, OPTIONS");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn ignores_labels_without_valid_methods() {
+        let config = AllowedMethods::new(Some(LABEL.to_string()), true);
+
+        let rsp = send(config.clone(), Some("G(E)T, [POST]"), http::Method::DELETE).await;
+        assert_eq!(
+            rsp.status(),
+            http::StatusCode::OK,
+            "labels without valid methods must permit all methods"
+        );
+
+        let rsp = send(config, Some(" , "), http::Method::DELETE).await;
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+    }
+}
@@ -0,0 +1,178 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    proxy::http::{self, header::HeaderValue},
+    svc, Error,
+};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::debug;
+
+/// Determines which protocols inbound HTTP/1.1 requests may upgrade to.
+///
+/// WebSocket upgrades are always permitted. When a list of protocols is
+/// configured, requests that offer any other protocol in their `Upgrade`
+/// header fail with a 426 Upgrade Required response whose `Upgrade` header
+/// lists the permitted protocols. Otherwise, all upgrades are permitted.
+/// Permitted upgrades are tunneled to the application once it switches
+/// protocols.
+///
+/// `h2c` upgrades are never tunneled, so they are not subject to this policy:
+/// the proxy removes their `Upgrade` headers, so that the request is served as
+/// an ordinary HTTP/1.1 request. Clients that support HTTP/2 may still use it
+/// with prior knowledge, which the proxy detects on the connection.
+#[derive(Clone, Debug, Default)]
+pub struct AllowedUpgrades(Option<Arc<Allowed>>);
+
+#[derive(Clone, Debug)]
+pub struct AllowUpgrades<S> {
+    inner: S,
+    allowed: AllowedUpgrades,
+}
+
+#[derive(Debug)]
+struct Allowed {
+    protocols: Vec<String>,
+    header: HeaderValue,
+}
+
+const WEBSOCKET: &str = "websocket";
+
+// === impl AllowedUpgrades ===
+
+impl AllowedUpgrades {
+    /// Permits only WebSocket upgrades and upgrades to the given protocols.
+    ///
+    /// A protocol without a version (e.g. `foo`) permits all of its versions
+    /// (e.g. `foo/2`).
+    pub fn only(protocols: impl IntoIterator<Item = String>) -> Self {
+        let mut protocols = protocols
+            .into_iter()
+            .map(|p| p.trim().to_ascii_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>();
+        if !protocols.iter().any(|p| p == WEBSOCKET) {
+            protocols.insert(0, WEBSOCKET.to_string());
+        }
+        let header = HeaderValue::from_str(&protocols.join(", "))
+            .expect("protocols must be valid header values");
+        Self(Some(Arc::new(Allowed { protocols, header })))
+    }
+
+    fn permits(allowed: &Allowed, protocol: &str) -> bool {
+        let protocol = protocol.trim().to_ascii_lowercase();
+        let name = protocol.split('/').next().unwrap_or_default();
+        allowed
+            .protocols
+            .iter()
+            .any(|p| *p == protocol || *p == name)
+    }
+}
+
+// === impl AllowUpgrades ===
+
+impl<S> AllowUpgrades<S> {
+    pub fn layer(allowed: AllowedUpgrades) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            allowed: allowed.clone(),
+        })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for AllowUpgrades<S>
+where
+    S: svc::Service<http::Request<B>, Response = http::Response<http::BoxBody>>,
+    S::Error: Into<Error>,
+{
+    type Response = http::Response<http::BoxBody>;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<Self::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        // Only HTTP/1.1 requests may be upgraded, and `h2c` upgrade headers
+        // have already been removed.
+        if let Some(allowed) = self.allowed.0.as_ref() {
+            if req.version() == http::Version::HTTP_11 {
+                let denied = req
+                    .headers()
+                    .get_all(http::header::UPGRADE)
+                    .iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .flat_map(|v| v.split(','))
+                    .find(|p| !AllowedUpgrades::permits(allowed, p));
+                if let Some(protocol) = denied {
+                    debug!(protocol = %protocol.trim(), "Upgrade not permitted");
+                    let rsp = http::Response::builder()
+                        .status(http::StatusCode::UPGRADE_REQUIRED)
+                        .version(req.version())
+                        .header(http::header::UPGRADE, allowed.header.clone())
+                        .header(http::header::CONNECTION, "upgrade")
+                        .header(http::header::CONTENT_LENGTH, "0")
+                        .body(http::BoxBody::default())
+                        .expect("response must be valid");
+                    return future::Either::Right(future::ok(rsp));
+                }
+            }
+        }
+
+        future::Either::Left(self.inner.call(req).err_into::<Error>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::svc::ServiceExt;
+
+    async fn send(allowed: AllowedUpgrades, upgrade: &str) -> http::Response<http::BoxBody> {
+        let inner = svc::mk(|_: http::Request<()>| {
+            future::ok::<_, Error>(http::Response::new(http::BoxBody::default()))
+        });
+        let req = http::Request::builder()
+            .header(http::header::UPGRADE, upgrade)
+            .body(())
+            .unwrap();
+        AllowUpgrades { inner, allowed }.oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_upgrades_not_permitted() {
+        let allowed = AllowedUpgrades::only(vec!["Foo".to_string(), "bar/2".to_string()]);
+
+        for upgrade in &["foo", "FOO/1.1", "bar/2", "websocket", "foo, websocket"] {
+            let rsp = send(allowed.clone(), upgrade).await;
+            assert_eq!(rsp.status(), http::StatusCode::OK, "{}", upgrade);
+        }
+
+        for upgrade in &["baz", "bar/3", "foo, baz"] {
+            let rsp = send(allowed.clone(), upgrade).await;
+            assert_eq!(
+                rsp.status(),
+                http::StatusCode::UPGRADE_REQUIRED,
+                "{}",
+                upgrade
+            );
+            assert_eq!(
+                rsp.headers()[http::header::UPGRADE],
+                "websocket, foo, bar/2"
+            );
+        }
+
+        let rsp = send(AllowedUpgrades::default(), "baz").await;
+        assert_eq!(
+            rsp.status(),
+            http::StatusCode::OK,
+            "all upgrades must be permitted by default"
+        );
+    }
+}
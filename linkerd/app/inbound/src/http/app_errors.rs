@@ -0,0 +1,108 @@
+use linkerd_app_core::{errors::HttpError, io, Error};
+
+/// Converts I/O errors encountered while connecting to the application, e.g.
+/// because it is not listening, into 502 Bad Gateway responses that describe a
+/// failure to connect.
+///
+/// Connections that are reset before they are established (including during a
+/// TLS handshake) are also failures to connect. Timeouts are not affected.
+pub fn connect_failed(error: Error) -> Error {
+    if io_error(&*error).is_some() {
+        return HttpError::connect_failed("connect failed").into();
+    }
+    error
+}
+
+/// Converts errors caused by the application resetting an established
+/// connection into 502 Bad Gateway responses that describe the reset.
+pub fn upstream_reset(error: Error) -> Error {
+    if is_reset(&*error) {
+        return HttpError::upstream_reset("upstream reset").into();
+    }
+    error
+}
+
+fn is_reset(error: &(dyn std::error::Error + 'static)) -> bool {
+    // Connect failures are described as such, even if they were caused by a
+    // reset.
+    if error.is::<HttpError>() {
+        return false;
+    }
+    if let Some(e) = error.downcast_ref::<io::Error>() {
+        return matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+        );
+    }
+    error.source().map(is_reset).unwrap_or(false)
+}
+
+fn io_error(error: &(dyn std::error::Error + 'static)) -> Option<&io::Error> {
+    if let Some(e) = error.downcast_ref::<io::Error>() {
+        return Some(e);
+    }
+    error.source().and_then(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("wrapped")]
+    struct Wrapped(#[source] io::Error);
+
+    fn message(error: Error) -> Option<String> {
+        error.downcast_ref::<HttpError>().map(|e| e.to_string())
+    }
+
+    #[test]
+    fn classifies_connect_failures() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            message(connect_failed(refused.into())).as_deref(),
+            Some("connect failed")
+        );
+
+        let reset = Wrapped(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(
+            message(connect_failed(reset.into())).as_deref(),
+            Some("connect failed")
+        );
+
+        let other = HttpError::gateway_timeout("request timed out");
+        assert_eq!(
+            message(connect_failed(other.into())).as_deref(),
+            Some("request timed out")
+        );
+    }
+
+    #[test]
+    fn classifies_resets() {
+        for kind in [
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::ConnectionAborted,
+            io::ErrorKind::BrokenPipe,
+        ]
+        .iter()
+        {
+            let reset = Wrapped(io::Error::from(*kind));
+            assert_eq!(
+                message(upstream_reset(reset.into())).as_deref(),
+                Some("upstream reset")
+            );
+        }
+
+        let eof = io::Error::from(io::ErrorKind::UnexpectedEof);
+        assert!(message(upstream_reset(eof.into())).is_none());
+
+        // Connections that were never established are not reset.
+        let refused = connect_failed(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        assert_eq!(
+            message(upstream_reset(refused)).as_deref(),
+            Some("connect failed")
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::target::Target;
+use futures::future;
+use linkerd_app_core::{
+    dst,
+    proxy::http::{self, HttpBody},
+    svc::{self, stack::Proxy},
+};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::{debug, warn};
+
+/// Determines which inbound requests are routed to an alternate application
+/// port, by the size of their bodies.
+///
+/// When a `label` and an alternate `port` are configured, a route whose
+/// metadata sets the label to a number of bytes routes requests whose
+/// `Content-Length` exceeds it to the alternate port (e.g. a backend that is
+/// optimized for large uploads). The size of a streaming request without a
+/// `Content-Length` cannot be known before it is routed, so such requests are
+/// routed to the alternate port only if `route_unknown_length` is set.
+#[derive(Clone, Debug, Default)]
+pub struct BodySizeRouting {
+    label: Option<Arc<str>>,
+    port: Option<u16>,
+    route_unknown_length: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewBodySizeRoute<N> {
+    inner: N,
+    config: BodySizeRouting,
+}
+
+#[derive(Clone, Debug)]
+pub struct BodySizeRoute<P> {
+    inner: P,
+    threshold: Option<u64>,
+    route_unknown_length: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewBodySizeSwitch<N> {
+    inner: N,
+    port: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BodySizeSwitch<S> {
+    default: S,
+    large: Option<S>,
+}
+
+/// Marks requests that are routed to the alternate port.
+#[derive(Copy, Clone, Debug)]
+struct LargeBody;
+
+// === impl BodySizeRouting ===
+
+impl BodySizeRouting {
+    pub fn new(label: Option<String>, port: Option<u16>, route_unknown_length: bool) -> Self {
+        Self {
+            label: label.map(Arc::from),
+            port,
+            route_unknown_length,
+        }
+    }
+
+    fn threshold(&self, route: &dst::Route) -> Option<u64> {
+        self.port?;
+        let value = route.route.labels().get(&**self.label.as_ref()?)?;
+        match value.trim().parse() {
+            Ok(threshold) => Some(threshold),
+            Err(_) => {
+                warn!(%value, "Ignoring invalid body size threshold");
+                None
+            }
+        }
+    }
+}
+
+// === impl NewBodySizeRoute ===
+
+impl<N> NewBodySizeRoute<N> {
+    pub fn layer(config: BodySizeRouting) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewBodySizeRoute<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = BodySizeRoute<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let threshold = self.config.threshold(&route);
+        BodySizeRoute {
+            inner: self.inner.new_service(route),
+            threshold,
+            route_unknown_length: self.config.route_unknown_length,
+        }
+    }
+}
+
+// === impl BodySizeRoute ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for BodySizeRoute<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: svc::Service<P::Request>,
+    B: HttpBody,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if let Some(threshold) = self.threshold {
+            let length = req
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+            let large = match length {
+                Some(length) => length > threshold,
+                None => !req.body().is_end_stream() && self.route_unknown_length,
+            };
+            if large {
+                debug!(?length, threshold, "Routing request to the large body port");
+                req.extensions_mut().insert(LargeBody);
+            }
+        }
+
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl NewBodySizeSwitch ===
+
+impl<N> NewBodySizeSwitch<N> {
+    pub fn layer(config: &BodySizeRouting) -> impl svc::Layer<N, Service = Self> + Clone {
+        let port = config.label.as_ref().and(config.port);
+        svc::layer::mk(move |inner| Self { inner, port })
+    }
+}
+
+impl<N> svc::NewService<Target> for NewBodySizeSwitch<N>
+where
+    N: svc::NewService<Target>,
+{
+    type Service = BodySizeSwitch<N::Service>;
+
+    fn new_service(&mut self, target: Target) -> Self::Service {
+        let large = self.port.map(|port| {
+            let target_addr = SocketAddr::new(target.target_addr.ip(), port);
+            self.inner.new_service(Target {
+                target_addr,
+                ..target.clone()
+            })
+        });
+        BodySizeSwitch {
+            default: self.inner.new_service(target),
+            large,
+        }
+    }
+}
+
+// === impl BodySizeSwitch ===
+
+impl<S, B> svc::Service<http::Request<B>> for BodySizeSwitch<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Either service may be used for the next request, so both must be
+        // ready.
+        let default = self.default.poll_ready(cx)?;
+        if let Some(large) = self.large.as_mut() {
+            futures::ready!(large.poll_ready(cx))?;
+        }
+        default.map(Ok)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        match self.large.as_mut() {
+            Some(large) if req.extensions().get::<LargeBody>().is_some() => large.call(req),
+            _ => self.default.call(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::Direction,
+        profiles,
+        svc::{Layer, NewService, Service},
+        tls, Conditional, Error,
+    };
+
+    const LABEL: &str = "large-body-threshold";
+    const LARGE_PORT: u16 = 8081;
+
+    fn target() -> Target {
+        Target {
+            dst: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            target_addr: ([127, 0, 0, 1], 8080).into(),
+            http_version: http::Version::Http1,
+            tls: Conditional::None(tls::NoServerTls::Loopback),
+            log_client_port: false,
+            class: None,
+        }
+    }
+
+    fn route() -> dst::Route {
+        let labels = Some((LABEL.to_string(), "1024".to_string()));
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        }
+    }
+
+    /// Returns the application port to which a request is routed.
+    async fn route_request(config: BodySizeRouting, req: http::Request<http::BoxBody>) -> u16 {
+        let mut new_switch = NewBodySizeSwitch::layer(&config).layer(|t: Target| {
+            let port = t.target_addr.port();
+            svc::mk(move |_: http::Request<http::BoxBody>| future::ok::<_, Error>(port))
+        });
+        let mut switch = new_switch.new_service(target());
+        let proxy = NewBodySizeRoute::layer(config)
+            .layer(|_: dst::Route| ())
+            .new_service(route());
+
+        future::poll_fn(|cx| switch.poll_ready(cx)).await.unwrap();
+        proxy.proxy(&mut switch, req).await.unwrap()
+    }
+
+    fn request(content_length: Option<u64>, body: http::BoxBody) -> http::Request<http::BoxBody> {
+        let mut req = http::Request::builder();
+        if let Some(len) = content_length {
+            req = req.header(http::header::CONTENT_LENGTH, len);
+        }
+        req.body(body).unwrap()
+    }
+
+    fn streaming_body() -> http::BoxBody {
+        http::BoxBody::new(hyper::Body::from("streaming"))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_large_bodies() {
+        let config = BodySizeRouting::new(Some(LABEL.to_string()), Some(LARGE_PORT), false);
+
+        let port = route_request(config.clone(), request(Some(4096), streaming_body())).await;
+        assert_eq!(port, LARGE_PORT);
+
+        let port = route_request(config.clone(), request(Some(512), streaming_body())).await;
+        assert_eq!(port, 8080);
+
+        let port = route_request(config.clone(), request(None, http::BoxBody::default())).await;
+        assert_eq!(
+            port, 8080,
+            "requests without bodies must use the default port"
+        );
+
+        let port = route_request(config, request(None, streaming_body())).await;
+        assert_eq!(
+            port, 8080,
+            "requests of unknown length must use the default port by default"
+        );
+
+        let config = BodySizeRouting::new(Some(LABEL.to_string()), Some(LARGE_PORT), true);
+        let port = route_request(config, request(None, streaming_body())).await;
+        assert_eq!(port, LARGE_PORT);
+    }
+}
@@ -0,0 +1,457 @@
+use futures::future;
+use linkerd_app_core::{
+    errors::HttpError,
+    proxy::http::{
+        self,
+        header::{self, HeaderName, HeaderValue},
+    },
+    svc, Error,
+};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::{debug, warn};
+
+/// Configures which request headers have their duplicate values joined into a
+/// single value before requests are forwarded to the application.
+#[derive(Clone, Debug, Default)]
+pub struct DuplicateHeaders {
+    coalesce: Arc<Vec<HeaderName>>,
+}
+
+/// Determines how HTTP/1 requests that carry both a `Transfer-Encoding` and a
+/// `Content-Length` header are handled.
+///
+/// Intermediaries that disagree about which header frames such a request's
+/// body may be used to smuggle requests past the proxy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransferEncodingConflict {
+    /// Such requests fail with a 400 Bad Request.
+    Reject,
+
+    /// The `Content-Length` header is removed, so that the request is framed
+    /// by its `Transfer-Encoding`, as described by RFC 7230 section 3.3.3.
+    StripContentLength,
+}
+
+/// Determines how requests with several `Content-Length` headers that agree
+/// are handled.
+///
+/// Requests with `Content-Length` values that are not non-negative integers,
+/// or with several values that disagree, are always rejected, as
+/// intermediaries may frame their bodies differently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DuplicateContentLength {
+    /// Such requests fail with a 400 Bad Request.
+    Reject,
+
+    /// The headers are replaced by a single header, as permitted by RFC 7230
+    /// section 3.3.2.
+    Collapse,
+}
+
+/// Coalesces duplicate request headers and normalizes the headers that frame
+/// request bodies.
+///
+/// This is the only layer that handles `Content-Length` headers, so that the
+/// application sees at most one unambiguous length for each request.
+#[derive(Clone, Debug)]
+pub struct CoalesceHeaders<S> {
+    inner: S,
+    headers: DuplicateHeaders,
+    transfer_encoding: TransferEncodingConflict,
+    content_length: DuplicateContentLength,
+}
+
+// === impl DuplicateHeaders ===
+
+impl DuplicateHeaders {
+    /// Coalesces the given list-valued headers.
+    ///
+    /// `Set-Cookie` may not be combined into a single value, and duplicate
+    /// `Content-Length` headers are only ever collapsed when they agree, so
+    /// these are ignored if configured.
+    pub fn new(coalesce: impl IntoIterator<Item = HeaderName>) -> Self {
+        let coalesce = coalesce
+            .into_iter()
+            .filter(|name| {
+                if name == header::SET_COOKIE || name == header::CONTENT_LENGTH {
+                    warn!(header = %name, "Header cannot be coalesced");
+                    return false;
+                }
+                true
+            })
+            .collect();
+        Self {
+            coalesce: Arc::new(coalesce),
+        }
+    }
+
+    fn coalesce(&self, headers: &mut http::HeaderMap) {
+        for name in self.coalesce.iter() {
+            let mut values = headers.get_all(name).iter();
+            let first = match (values.next(), values.next()) {
+                (Some(first), Some(_)) => first,
+                _ => continue,
+            };
+
+            // Cookies are joined with semicolons rather than commas, as
+            // described by RFC 7540 section 8.1.2.5.
+            let sep: &[u8] = if name == header::COOKIE { b"; " } else { b", " };
+            let mut joined = first.as_bytes().to_vec();
+            for value in headers.get_all(name).iter().skip(1) {
+                joined.extend_from_slice(sep);
+                joined.extend_from_slice(value.as_bytes());
+            }
+
+            // Every part of the joined value is a valid header value.
+            let value =
+                HeaderValue::from_bytes(&joined).expect("joined header value must be valid");
+            debug!(header = %name, "Coalesced duplicate headers");
+            headers.insert(name, value);
+        }
+    }
+}
+
+// === impl TransferEncodingConflict ===
+
+impl Default for TransferEncodingConflict {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+// === impl DuplicateContentLength ===
+
+impl Default for DuplicateContentLength {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+// === impl CoalesceHeaders ===
+
+impl<S> CoalesceHeaders<S> {
+    pub fn layer(
+        headers: DuplicateHeaders,
+        transfer_encoding: TransferEncodingConflict,
+        content_length: DuplicateContentLength,
+    ) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            headers: headers.clone(),
+            transfer_encoding,
+            content_length,
+        })
+    }
+
+    /// Ensures that an HTTP/1 request's body is framed by either its
+    /// `Transfer-Encoding` or its `Content-Length`, but not both.
+    ///
+    /// HTTP/2 forbids `Transfer-Encoding`, so only HTTP/1 requests are checked.
+    fn frame_by_transfer_encoding(
+        &self,
+        version: http::Version,
+        headers: &mut http::HeaderMap,
+    ) -> Result<(), &'static str> {
+        let is_http1 = matches!(version, http::Version::HTTP_10 | http::Version::HTTP_11);
+        if !is_http1
+            || !headers.contains_key(header::TRANSFER_ENCODING)
+            || !headers.contains_key(header::CONTENT_LENGTH)
+        {
+            return Ok(());
+        }
+
+        match self.transfer_encoding {
+            TransferEncodingConflict::Reject => {
+                Err("request has both transfer-encoding and content-length headers")
+            }
+            TransferEncodingConflict::StripContentLength => {
+                debug!("Stripping content-length from request with a transfer-encoding");
+                headers.remove(header::CONTENT_LENGTH);
+                Ok(())
+            }
+        }
+    }
+
+    /// Ensures that a request has at most one valid `Content-Length` header,
+    /// collapsing duplicates that agree, if so configured.
+    fn validate_content_length(&self, headers: &mut http::HeaderMap) -> Result<(), &'static str> {
+        let mut lengths = headers.get_all(header::CONTENT_LENGTH).iter();
+        let first = match lengths.next() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        if !is_valid(first) {
+            return Err("request has an invalid content-length header");
+        }
+
+        let mut duplicated = false;
+        for len in lengths {
+            if self.content_length == DuplicateContentLength::Reject {
+                return Err("request has multiple content-length headers");
+            }
+            if len != first {
+                return Err("request has conflicting content-length headers");
+            }
+            duplicated = true;
+        }
+
+        if duplicated {
+            debug!("Collapsing duplicate content-length headers");
+            let len = first.clone();
+            headers.insert(header::CONTENT_LENGTH, len);
+        }
+        Ok(())
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for CoalesceHeaders<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        use futures::TryFutureExt;
+
+        let version = req.version();
+        let framed = self
+            .frame_by_transfer_encoding(version, req.headers_mut())
+            .and_then(|()| self.validate_content_length(req.headers_mut()));
+        if let Err(reason) = framed {
+            debug!(reason, "Rejecting request");
+            return future::Either::Right(future::err(HttpError::bad_request(reason).into()));
+        }
+
+        self.headers.coalesce(req.headers_mut());
+
+        future::Either::Left(self.inner.call(req).err_into())
+    }
+}
+
+/// Content lengths must consist solely of digits and fit in a `u64`.
+fn is_valid(len: &HeaderValue) -> bool {
+    let len = len.as_bytes();
+    !len.is_empty()
+        && len.iter().all(u8::is_ascii_digit)
+        && std::str::from_utf8(len)
+            .ok()
+            .and_then(|len| len.parse::<u64>().ok())
+            .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    fn request(headers: &[(HeaderName, &'static str)]) -> http::Request<()> {
+        let mut req = http::Request::builder().uri("http://foo.example.com/");
+        for (name, value) in headers {
+            req = req.header(name.clone(), *value);
+        }
+        req.body(()).unwrap()
+    }
+
+    async fn send(
+        headers: DuplicateHeaders,
+        req: http::Request<()>,
+    ) -> Result<http::HeaderMap, Error> {
+        send_with(headers, Default::default(), Default::default(), req).await
+    }
+
+    async fn send_with(
+        headers: DuplicateHeaders,
+        transfer_encoding: TransferEncodingConflict,
+        content_length: DuplicateContentLength,
+        req: http::Request<()>,
+    ) -> Result<http::HeaderMap, Error> {
+        let inner = svc::mk(|req: http::Request<()>| future::ok::<_, Error>(req.headers().clone()));
+        CoalesceHeaders {
+            inner,
+            headers,
+            transfer_encoding,
+            content_length,
+        }
+        .oneshot(req)
+        .await
+    }
+
+    fn assert_bad_request(res: Result<http::HeaderMap, Error>) {
+        let err = res.expect_err("request must be rejected");
+        let status = err
+            .downcast_ref::<HttpError>()
+            .expect("error must be an HttpError")
+            .status();
+        assert_eq!(status, http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn coalesces_configured_headers() {
+        let headers = DuplicateHeaders::new(vec![
+            header::ACCEPT,
+            header::COOKIE,
+            header::SET_COOKIE,
+            header::CONTENT_LENGTH,
+        ]);
+        let req = request(&[
+            (header::ACCEPT, "text/plain"),
+            (header::ACCEPT, "text/html"),
+            (header::COOKIE, "a=1"),
+            (header::COOKIE, "b=2"),
+            (header::SET_COOKIE, "c=3"),
+            (header::SET_COOKIE, "d=4"),
+            (header::VIA, "1.1 a"),
+            (header::VIA, "1.1 b"),
+        ]);
+
+        let headers = send(headers, req).await.unwrap();
+        let values = |name: HeaderName| {
+            headers
+                .get_all(name)
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(header::ACCEPT), vec!["text/plain, text/html"]);
+        assert_eq!(values(header::COOKIE), vec!["a=1; b=2"]);
+        assert_eq!(values(header::SET_COOKIE), vec!["c=3", "d=4"]);
+        assert_eq!(values(header::VIA), vec!["1.1 a", "1.1 b"]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_duplicate_content_lengths_by_default() {
+        let req = request(&[(header::CONTENT_LENGTH, "5"), (header::CONTENT_LENGTH, "5")]);
+        assert_bad_request(send(DuplicateHeaders::default(), req).await);
+
+        let req = request(&[(header::CONTENT_LENGTH, "5")]);
+        let headers = send(DuplicateHeaders::default(), req).await.unwrap();
+        assert_eq!(headers[header::CONTENT_LENGTH], "5");
+
+        let headers = send(DuplicateHeaders::default(), request(&[]))
+            .await
+            .unwrap();
+        assert!(!headers.contains_key(header::CONTENT_LENGTH));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn collapses_matching_content_lengths() {
+        let req = request(&[(header::CONTENT_LENGTH, "5"), (header::CONTENT_LENGTH, "5")]);
+        let headers = send_with(
+            DuplicateHeaders::default(),
+            TransferEncodingConflict::default(),
+            DuplicateContentLength::Collapse,
+            req,
+        )
+        .await
+        .unwrap();
+        assert_eq!(headers.get_all(header::CONTENT_LENGTH).iter().count(), 1);
+        assert_eq!(headers[header::CONTENT_LENGTH], "5");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_conflicting_content_lengths() {
+        for content_length in &[
+            DuplicateContentLength::Reject,
+            DuplicateContentLength::Collapse,
+        ] {
+            let req = request(&[(header::CONTENT_LENGTH, "5"), (header::CONTENT_LENGTH, "6")]);
+            let res = send_with(
+                DuplicateHeaders::default(),
+                TransferEncodingConflict::default(),
+                *content_length,
+                req,
+            )
+            .await;
+            assert_bad_request(res);
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_malformed_content_lengths() {
+        for len in &[
+            "-1",
+            "+5",
+            "five",
+            "5, 5",
+            " 5",
+            "0x10",
+            "",
+            "18446744073709551616",
+        ] {
+            for content_length in &[
+                DuplicateContentLength::Reject,
+                DuplicateContentLength::Collapse,
+            ] {
+                let req = request(&[(header::CONTENT_LENGTH, *len)]);
+                let res = send_with(
+                    DuplicateHeaders::default(),
+                    TransferEncodingConflict::default(),
+                    *content_length,
+                    req,
+                )
+                .await;
+                assert_bad_request(res);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_transfer_encoding_with_content_length_by_default() {
+        let req = request(&[
+            (header::TRANSFER_ENCODING, "chunked"),
+            (header::CONTENT_LENGTH, "5"),
+        ]);
+        assert_bad_request(send(DuplicateHeaders::default(), req).await);
+
+        // Requests with only one of the headers are unaffected.
+        let req = request(&[(header::CONTENT_LENGTH, "5")]);
+        let headers = send(DuplicateHeaders::default(), req).await.unwrap();
+        assert_eq!(headers[header::CONTENT_LENGTH], "5");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn strips_content_length_with_transfer_encoding() {
+        // Conflicting lengths are irrelevant once the request is framed by its
+        // transfer-encoding.
+        let req = request(&[
+            (header::TRANSFER_ENCODING, "chunked"),
+            (header::CONTENT_LENGTH, "5"),
+            (header::CONTENT_LENGTH, "6"),
+        ]);
+        let headers = send_with(
+            DuplicateHeaders::default(),
+            TransferEncodingConflict::StripContentLength,
+            DuplicateContentLength::default(),
+            req,
+        )
+        .await
+        .unwrap();
+        assert!(!headers.contains_key(header::CONTENT_LENGTH));
+        assert_eq!(headers[header::TRANSFER_ENCODING], "chunked");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn ignores_transfer_encoding_on_http2() {
+        let mut req = request(&[
+            (header::TRANSFER_ENCODING, "chunked"),
+            (header::CONTENT_LENGTH, "5"),
+        ]);
+        *req.version_mut() = http::Version::HTTP_2;
+        let headers = send(DuplicateHeaders::default(), req).await.unwrap();
+        assert_eq!(headers[header::CONTENT_LENGTH], "5");
+    }
+}
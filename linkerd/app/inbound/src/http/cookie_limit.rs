@@ -0,0 +1,246 @@
+use futures::future;
+use linkerd_app_core::{
+    errors::HttpError,
+    proxy::http::{
+        self,
+        header::{self, HeaderValue},
+    },
+    svc, Error,
+};
+use std::task::{Context, Poll};
+use tracing::debug;
+
+/// Limits the cookies that inbound requests may carry.
+///
+/// Cookies are limited by their total size, in bytes, and by their number,
+/// across all of a request's `Cookie` headers (HTTP/2 clients may send each
+/// cookie in its own header). These limits are independent of the limits on
+/// the size of request headers as a whole.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CookieLimits {
+    /// The maximum number of bytes of cookies, as they would be joined into a
+    /// single `Cookie` header value.
+    pub max_bytes: Option<usize>,
+
+    /// The maximum number of cookies.
+    pub max_cookies: Option<usize>,
+
+    pub oversized: OversizedCookies,
+}
+
+/// Determines how requests whose cookies exceed the limits are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OversizedCookies {
+    /// Such requests fail with a 431 Request Header Fields Too Large.
+    Reject,
+
+    /// Cookies are kept, in the order they were sent, until a limit is
+    /// reached; the remaining cookies are removed before the request is
+    /// forwarded to the application.
+    Strip,
+}
+
+#[derive(Clone, Debug)]
+pub struct LimitCookies<S> {
+    inner: S,
+    limits: CookieLimits,
+}
+
+// === impl CookieLimits ===
+
+impl CookieLimits {
+    fn is_limited(&self) -> bool {
+        self.max_bytes.is_some() || self.max_cookies.is_some()
+    }
+
+    /// Returns the number of `cookies` that fit within the limits.
+    fn permitted(&self, cookies: &[&[u8]]) -> usize {
+        let mut bytes = 0;
+        for (n, cookie) in cookies.iter().enumerate() {
+            if self.max_cookies.map_or(false, |max| n + 1 > max) {
+                return n;
+            }
+            // Cookies after the first are preceded by a `; ` separator.
+            bytes += cookie.len() + if n == 0 { 0 } else { 2 };
+            if self.max_bytes.map_or(false, |max| bytes > max) {
+                return n;
+            }
+        }
+        cookies.len()
+    }
+
+    /// Fails or strips the cookies in excess of the limits.
+    fn apply(&self, headers: &mut http::HeaderMap) -> Result<(), HttpError> {
+        let joined = {
+            let cookies = headers
+                .get_all(header::COOKIE)
+                .iter()
+                .flat_map(|value| value.as_bytes().split(|b| *b == b';'))
+                .map(trim)
+                .filter(|cookie| !cookie.is_empty())
+                .collect::<Vec<_>>();
+            let permitted = self.permitted(&cookies);
+            if permitted == cookies.len() {
+                return Ok(());
+            }
+
+            if self.oversized == OversizedCookies::Reject {
+                debug!(cookies = cookies.len(), "Request cookies exceed the limit");
+                return Err(HttpError::header_fields_too_large(
+                    "request cookies exceed the limit",
+                ));
+            }
+
+            debug!(
+                cookies = cookies.len(),
+                permitted, "Stripping cookies in excess of the limit"
+            );
+            cookies[..permitted].join(&b"; "[..])
+        };
+
+        if joined.is_empty() {
+            headers.remove(header::COOKIE);
+            return Ok(());
+        }
+        // The joined value consists of parts of valid header values.
+        let value = HeaderValue::from_bytes(&joined).expect("joined cookies must be valid");
+        headers.insert(header::COOKIE, value);
+        Ok(())
+    }
+}
+
+// === impl OversizedCookies ===
+
+impl Default for OversizedCookies {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+// === impl LimitCookies ===
+
+impl<S> LimitCookies<S> {
+    pub fn layer(limits: CookieLimits) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, limits })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for LimitCookies<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        use futures::TryFutureExt;
+
+        if self.limits.is_limited() {
+            if let Err(error) = self.limits.apply(req.headers_mut()) {
+                return future::Either::Right(future::err(error.into()));
+            }
+        }
+
+        future::Either::Left(self.inner.call(req).err_into())
+    }
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    fn request(cookies: &[&str]) -> http::Request<()> {
+        let mut req = http::Request::builder().uri("http://foo.example.com/");
+        for cookie in cookies {
+            req = req.header(header::COOKIE, *cookie);
+        }
+        req.body(()).unwrap()
+    }
+
+    /// Returns the request's cookies, as received by the inner service.
+    async fn send(limits: CookieLimits, req: http::Request<()>) -> Result<Vec<String>, Error> {
+        let inner = svc::mk(|req: http::Request<()>| {
+            let cookies = req
+                .headers()
+                .get_all(header::COOKIE)
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect();
+            future::ok::<_, Error>(cookies)
+        });
+        LimitCookies { inner, limits }.oneshot(req).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_oversized_cookies() {
+        let limits = CookieLimits {
+            max_bytes: Some(16),
+            max_cookies: Some(3),
+            oversized: OversizedCookies::Reject,
+        };
+
+        // "a=1; b=2; c=3" is 13 bytes long.
+        let cookies = send(limits, request(&["a=1; b=2", "c=3"])).await.unwrap();
+        assert_eq!(cookies, vec!["a=1; b=2", "c=3"], "cookies are unmodified");
+
+        for req in vec![
+            request(&["a=1; b=2; c=3; d=4"]),
+            request(&["session=0123456789abcdef"]),
+        ] {
+            let status = send(limits, req)
+                .await
+                .expect_err("request must be rejected")
+                .downcast_ref::<HttpError>()
+                .expect("error must be an HttpError")
+                .status();
+            assert_eq!(status, http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn strips_oversized_cookies() {
+        let limits = CookieLimits {
+            max_bytes: Some(16),
+            max_cookies: Some(3),
+            oversized: OversizedCookies::Strip,
+        };
+
+        let cookies = send(limits, request(&["a=1;b=2", " c=3 ", "d=4"]))
+            .await
+            .unwrap();
+        assert_eq!(cookies, vec!["a=1; b=2; c=3"], "cookies beyond the count");
+
+        let cookies = send(limits, request(&["a=1", "b=0123456789abcdef"]))
+            .await
+            .unwrap();
+        assert_eq!(cookies, vec!["a=1"], "cookies beyond the size");
+
+        let cookies = send(limits, request(&["session=0123456789abcdef"]))
+            .await
+            .unwrap();
+        assert!(cookies.is_empty(), "all cookies are stripped");
+    }
+}
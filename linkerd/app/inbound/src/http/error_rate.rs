@@ -0,0 +1,113 @@
+use linkerd_app_core::{
+    errors::ErrorRateLimit,
+    proxy::http,
+    svc::{self, Param},
+    transport::addrs::OrigDstAddr,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// Limits, by port, the rate at which proxy errors are described by error
+/// responses.
+///
+/// Each port is limited independently of other ports, but its limit is shared
+/// by all of its connections. Ports without a configured limit use the
+/// default limit, if one is set.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorRateLimits {
+    default: Option<u32>,
+    ports: Arc<HashMap<u16, u32>>,
+    limits: Arc<Mutex<HashMap<u16, ErrorRateLimit>>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewLimitErrorRate<N> {
+    inner: N,
+    limits: ErrorRateLimits,
+}
+
+/// Sets the port's `ErrorRateLimit`, if any, on each request.
+#[derive(Clone, Debug)]
+pub struct LimitErrorRate<S> {
+    inner: S,
+    limit: Option<ErrorRateLimit>,
+}
+
+// === impl ErrorRateLimits ===
+
+impl ErrorRateLimits {
+    pub fn new(default: Option<u32>, ports: impl IntoIterator<Item = (u16, u32)>) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+            limits: Default::default(),
+        }
+    }
+
+    fn for_port(&self, port: u16) -> Option<ErrorRateLimit> {
+        let per_second = self.ports.get(&port).copied().or(self.default)?;
+        let limit = self
+            .limits
+            .lock()
+            .entry(port)
+            .or_insert_with(|| ErrorRateLimit::per_second(per_second))
+            .clone();
+        Some(limit)
+    }
+}
+
+// === impl NewLimitErrorRate ===
+
+impl<N> NewLimitErrorRate<N> {
+    pub fn layer(limits: ErrorRateLimits) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            limits: limits.clone(),
+        })
+    }
+}
+
+impl<T, N> svc::NewService<T> for NewLimitErrorRate<N>
+where
+    T: Param<OrigDstAddr>,
+    N: svc::NewService<T>,
+{
+    type Service = LimitErrorRate<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let OrigDstAddr(addr) = target.param();
+        let limit = self.limits.for_port(addr.port());
+        tracing::trace!(port = %addr.port(), limited = limit.is_some());
+        LimitErrorRate {
+            inner: self.inner.new_service(target),
+            limit,
+        }
+    }
+}
+
+// === impl LimitErrorRate ===
+
+impl<S, B> svc::Service<http::Request<B>> for LimitErrorRate<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(limit) = self.limit.clone() {
+            req.extensions_mut().insert(limit);
+        }
+        self.inner.call(req)
+    }
+}
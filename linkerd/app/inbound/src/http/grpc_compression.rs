@@ -0,0 +1,466 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    proxy::http::{self, header::HeaderValue, HttpBody},
+    svc, Error,
+};
+use pin_project::pin_project;
+use std::{
+    io::Read,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tracing::debug;
+
+const GRPC_ENCODING: &str = "grpc-encoding";
+const GRPC_ACCEPT_ENCODING: &str = "grpc-accept-encoding";
+
+/// The length of the prefix that precedes each gRPC message: a one-byte
+/// compression flag followed by a four-byte, big-endian message length.
+const PREFIX_LEN: usize = 5;
+
+/// Configures the routes on which the proxy decompresses gRPC messages for
+/// peers that do not support their encoding.
+#[derive(Clone, Debug)]
+pub struct GrpcCompression {
+    routes: Arc<Vec<String>>,
+    max_message_bytes: usize,
+}
+
+/// Bridges gRPC clients and applications that support different message
+/// encodings.
+///
+/// When a request on a configured route is compressed with an encoding the
+/// proxy supports (`gzip` or `deflate`), its messages are decompressed before
+/// it is forwarded to the application. Likewise, a compressed response is
+/// decompressed when its encoding is not listed in the request's
+/// `grpc-accept-encoding` header. Uncompressed messages are always acceptable
+/// to gRPC peers, so messages are never recompressed.
+///
+/// Messages are decompressed as each one is received, so streams are not
+/// buffered. As decompression changes the length of the body, the
+/// `content-length` header is removed from decompressed messages. A message whose compressed or decompressed length exceeds the
+/// configured limit fails the stream.
+#[derive(Clone, Debug)]
+pub struct BridgeGrpcCompression<S> {
+    inner: S,
+    config: GrpcCompression,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct DecompressBody<B> {
+    #[pin]
+    inner: B,
+    encoding: Encoding,
+    buf: BytesMut,
+    max_message_bytes: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+#[derive(Debug, Error)]
+pub enum DecompressError {
+    #[error("gRPC message exceeds {0} bytes")]
+    TooLarge(usize),
+
+    #[error("gRPC message is truncated")]
+    Truncated,
+
+    #[error("failed to decompress gRPC message: {0}")]
+    Invalid(#[source] std::io::Error),
+}
+
+type BoxRequest = http::Request<http::BoxBody>;
+type BoxResponse = http::Response<http::BoxBody>;
+
+// === impl GrpcCompression ===
+
+impl GrpcCompression {
+    /// Bridges compression on routes whose paths start with one of the given
+    /// prefixes (e.g. `/helloworld.Greeter/`).
+    pub fn new(routes: impl IntoIterator<Item = String>, max_message_bytes: usize) -> Self {
+        Self {
+            routes: Arc::new(routes.into_iter().collect()),
+            max_message_bytes,
+        }
+    }
+
+    fn applies_to<B>(&self, req: &http::Request<B>) -> bool {
+        let path = req.uri().path();
+        self.routes
+            .iter()
+            .any(|route| path.starts_with(route.as_str()))
+            && is_grpc(req.headers())
+    }
+}
+
+impl Default for GrpcCompression {
+    fn default() -> Self {
+        // No routes are bridged unless they are configured.
+        Self::new(None, 4 * 1024 * 1024)
+    }
+}
+
+// === impl BridgeGrpcCompression ===
+
+impl<S> BridgeGrpcCompression<S> {
+    pub fn layer(config: GrpcCompression) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<S> svc::Service<BoxRequest> for BridgeGrpcCompression<S>
+where
+    S: svc::Service<BoxRequest, Response = BoxResponse>,
+{
+    type Response = BoxResponse;
+    type Error = S::Error;
+    type Future = future::Either<
+        S::Future,
+        future::MapOk<S::Future, Box<dyn FnOnce(BoxResponse) -> BoxResponse + Send>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: BoxRequest) -> Self::Future {
+        if !self.config.applies_to(&req) {
+            return future::Either::Left(self.inner.call(req));
+        }
+
+        let max = self.config.max_message_bytes;
+        let accepted = req.headers().get(GRPC_ACCEPT_ENCODING).cloned();
+        let req = match Encoding::from_headers(req.headers()) {
+            Some(encoding) => {
+                debug!(?encoding, "Decompressing gRPC request messages");
+                let (mut parts, body) = req.into_parts();
+                strip_encoding(&mut parts.headers);
+                let body = http::BoxBody::new(DecompressBody::new(body, encoding, max));
+                http::Request::from_parts(parts, body)
+            }
+            None => req,
+        };
+
+        let decompress: Box<dyn FnOnce(BoxResponse) -> BoxResponse + Send> =
+            Box::new(move |rsp: BoxResponse| {
+                let encoding = match Encoding::from_headers(rsp.headers()) {
+                    Some(encoding) if !encoding.is_accepted(accepted.as_ref()) => encoding,
+                    _ => return rsp,
+                };
+                debug!(?encoding, "Decompressing gRPC response messages");
+                let (mut parts, body) = rsp.into_parts();
+                strip_encoding(&mut parts.headers);
+                let body = http::BoxBody::new(DecompressBody::new(body, encoding, max));
+                http::Response::from_parts(parts, body)
+            });
+        future::Either::Right(self.inner.call(req).map_ok(decompress))
+    }
+}
+
+/// Removes the headers that describe a compressed body, which no longer apply
+/// once it is decompressed.
+fn strip_encoding(headers: &mut http::HeaderMap) {
+    headers.remove(GRPC_ENCODING);
+    headers.remove(http::header::CONTENT_LENGTH);
+}
+
+fn is_grpc(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |ct| ct.starts_with("application/grpc"))
+}
+
+// === impl Encoding ===
+
+impl Encoding {
+    fn from_headers(headers: &http::HeaderMap) -> Option<Self> {
+        match headers.get(GRPC_ENCODING)?.to_str().ok()?.trim() {
+            "gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    fn is_accepted(&self, accepted: Option<&HeaderValue>) -> bool {
+        accepted
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.split(',').any(|e| e.trim() == self.as_str()))
+    }
+
+    fn decompress(&self, compressed: &[u8], max: usize) -> Result<Vec<u8>, DecompressError> {
+        let mut message = Vec::with_capacity(compressed.len());
+        // Read at most one byte past the limit to detect oversized messages
+        // without decompressing them entirely.
+        let limit = max as u64 + 1;
+        let res = match self {
+            Self::Gzip => GzDecoder::new(compressed)
+                .take(limit)
+                .read_to_end(&mut message),
+            Self::Deflate => ZlibDecoder::new(compressed)
+                .take(limit)
+                .read_to_end(&mut message),
+        };
+        res.map_err(DecompressError::Invalid)?;
+        if message.len() > max {
+            return Err(DecompressError::TooLarge(max));
+        }
+        Ok(message)
+    }
+}
+
+// === impl DecompressBody ===
+
+impl<B> DecompressBody<B> {
+    fn new(inner: B, encoding: Encoding, max_message_bytes: usize) -> Self {
+        Self {
+            inner,
+            encoding,
+            buf: BytesMut::new(),
+            max_message_bytes,
+        }
+    }
+}
+
+/// Returns the next complete message in `buf`, decompressing it if necessary.
+fn next_message(
+    buf: &mut BytesMut,
+    encoding: Encoding,
+    max: usize,
+) -> Result<Option<Bytes>, DecompressError> {
+    if buf.len() < PREFIX_LEN {
+        return Ok(None);
+    }
+    let compressed = buf[0] == 1;
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    if len > max {
+        return Err(DecompressError::TooLarge(max));
+    }
+    if buf.len() < PREFIX_LEN + len {
+        return Ok(None);
+    }
+
+    let mut frame = buf.split_to(PREFIX_LEN + len);
+    if !compressed {
+        return Ok(Some(frame.freeze()));
+    }
+
+    let message = encoding.decompress(&frame[PREFIX_LEN..], max)?;
+    frame.clear();
+    frame.put_u8(0);
+    frame.put_u32(message.len() as u32);
+    frame.extend_from_slice(&message);
+    Ok(Some(frame.freeze()))
+}
+
+impl<B> HttpBody for DecompressBody<B>
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if let Some(message) = next_message(this.buf, *this.encoding, *this.max_message_bytes)?
+            {
+                return Poll::Ready(Some(Ok(message)));
+            }
+
+            match futures::ready!(this.inner.as_mut().poll_data(cx)) {
+                Some(Ok(mut data)) => {
+                    while data.has_remaining() {
+                        let chunk = data.chunk();
+                        let len = chunk.len();
+                        this.buf.extend_from_slice(chunk);
+                        data.advance(len);
+                    }
+                }
+                Some(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
+                None if this.buf.is_empty() => return Poll::Ready(None),
+                None => return Poll::Ready(Some(Err(DecompressError::Truncated.into()))),
+            }
+        }
+    }
+
+    #[inline]
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx).map_err(Into::into)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.buf.is_empty() && self.inner.is_end_stream()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use svc::ServiceExt;
+
+    const ROUTE: &str = "/helloworld.Greeter/";
+
+    fn frame(compressed: bool, message: &[u8]) -> Bytes {
+        let mut frame = BytesMut::new();
+        frame.put_u8(compressed as u8);
+        frame.put_u32(message.len() as u32);
+        frame.extend_from_slice(message);
+        frame.freeze()
+    }
+
+    fn gzip(message: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(message).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn request(path: &str, body: Bytes) -> BoxRequest {
+        http::Request::builder()
+            .uri(format!("http://example.com{}", path))
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .header(GRPC_ENCODING, "gzip")
+            .header(http::header::CONTENT_LENGTH, body.len())
+            .body(http::BoxBody::new(hyper::Body::from(body)))
+            .unwrap()
+    }
+
+    /// Sends a request to an application that does not support compression,
+    /// returning the body that it received.
+    ///
+    /// The application fails if the request's `content-length` does not
+    /// describe its body.
+    async fn send(
+        config: GrpcCompression,
+        req: BoxRequest,
+    ) -> Result<(Option<HeaderValue>, Bytes), Error> {
+        let app = svc::mk(|req: BoxRequest| async move {
+            let encoding = req.headers().get(GRPC_ENCODING).cloned();
+            let length = req.headers().get(http::header::CONTENT_LENGTH).cloned();
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            if let Some(length) = length {
+                assert_eq!(length, body.len().to_string(), "invalid content-length");
+            }
+            let mut rsp = http::Response::new(http::BoxBody::new(hyper::Body::from(body)));
+            if let Some(encoding) = encoding {
+                rsp.headers_mut().insert("x-app-grpc-encoding", encoding);
+            }
+            Ok::<_, Error>(rsp)
+        });
+        let rsp = BridgeGrpcCompression { inner: app, config }
+            .oneshot(req)
+            .await?;
+        let encoding = rsp.headers().get("x-app-grpc-encoding").cloned();
+        let body = hyper::body::to_bytes(rsp.into_body()).await?;
+        Ok((encoding, body))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn decompresses_requests_for_configured_routes() {
+        let compressed = frame(true, &gzip(b"hello"));
+        let config = GrpcCompression::new(vec![ROUTE.to_string()], 1024);
+        let (encoding, body) = send(config, request("/helloworld.Greeter/SayHello", compressed))
+            .await
+            .unwrap();
+        assert_eq!(encoding, None, "the app must not see an encoding");
+        assert_eq!(body, frame(false, b"hello"));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn preserves_messages_on_other_routes() {
+        let compressed = frame(true, &gzip(b"hello"));
+        let config = GrpcCompression::new(vec![ROUTE.to_string()], 1024);
+        let (encoding, body) = send(config, request("/other.Service/Call", compressed.clone()))
+            .await
+            .unwrap();
+        assert_eq!(encoding.unwrap(), "gzip");
+        assert_eq!(body, compressed);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn decompresses_streams_of_messages() {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&frame(true, &gzip(b"hello")));
+        buf.extend_from_slice(&frame(false, b"uncompressed"));
+        buf.extend_from_slice(&frame(true, &gzip(b"world")));
+        let config = GrpcCompression::new(vec![ROUTE.to_string()], 1024);
+        let (_, body) = send(config, request("/helloworld.Greeter/Chat", buf.freeze()))
+            .await
+            .unwrap();
+
+        let mut expected = BytesMut::new();
+        expected.extend_from_slice(&frame(false, b"hello"));
+        expected.extend_from_slice(&frame(false, b"uncompressed"));
+        expected.extend_from_slice(&frame(false, b"world"));
+        assert_eq!(body, expected.freeze());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_decompressed_messages() {
+        // A small compressed message that expands beyond the limit.
+        let bomb = frame(true, &gzip(&[0; 64 * 1024]));
+        assert!(bomb.len() < 1024);
+        let config = GrpcCompression::new(vec![ROUTE.to_string()], 1024);
+        send(config, request("/helloworld.Greeter/SayHello", bomb))
+            .await
+            .expect_err("oversized messages must fail");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn strips_content_length_from_decompressed_responses() {
+        let compressed = frame(true, &gzip(b"hello"));
+        let app = svc::mk(move |_: BoxRequest| {
+            let rsp = http::Response::builder()
+                .header(http::header::CONTENT_TYPE, "application/grpc")
+                .header(GRPC_ENCODING, "gzip")
+                .header(http::header::CONTENT_LENGTH, compressed.len())
+                .body(http::BoxBody::new(hyper::Body::from(compressed.clone())))
+                .unwrap();
+            future::ok::<_, Error>(rsp)
+        });
+        let config = GrpcCompression::new(vec![ROUTE.to_string()], 1024);
+        let req = http::Request::builder()
+            .uri(format!("http://example.com{}SayHello", ROUTE))
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(http::BoxBody::default())
+            .unwrap();
+        let rsp = BridgeGrpcCompression { inner: app, config }
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert!(rsp.headers().get(GRPC_ENCODING).is_none());
+        assert!(rsp.headers().get(http::header::CONTENT_LENGTH).is_none());
+        let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+        assert_eq!(body, frame(false, b"hello"));
+    }
+}
@@ -0,0 +1,323 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    errors::HttpError,
+    identity,
+    proxy::http,
+    svc::{self, Param},
+    Error,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+/// Limits the rate of inbound requests from each client identity.
+///
+/// Each identity has its own limit, shared by all of its connections.
+/// Identities without an override use the default limit, if one is set.
+/// Requests from clients without an identity share a single, separate limit.
+/// Requests in excess of a limit fail with a 429 Too Many Requests response.
+///
+/// A client's limit is retained while any of its connections are open or
+/// until its tokens are replenished, after which it is indistinguishable from
+/// a new limit and may be evicted.
+#[derive(Clone, Debug, Default)]
+pub struct IdentityRateLimits {
+    default: Option<u32>,
+    identities: Arc<HashMap<identity::Name, u32>>,
+    unauthenticated: Option<u32>,
+    limits: Arc<Mutex<HashMap<Option<identity::Name>, RequestRateLimit>>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewLimitIdentityRate<N> {
+    inner: N,
+    limits: IdentityRateLimits,
+}
+
+/// Sets the client's `RequestRateLimit`, if any, on each request.
+#[derive(Clone, Debug)]
+pub struct SetRequestRateLimit<S> {
+    inner: S,
+    limit: Option<RequestRateLimit>,
+}
+
+/// Fails requests that exceed their `RequestRateLimit`.
+#[derive(Clone, Debug)]
+pub struct LimitRequestRate<S> {
+    inner: S,
+}
+
+/// A token bucket that permits up to `per_second` requests each second, with
+/// bursts of up to `per_second` requests.
+#[derive(Clone, Debug)]
+pub struct RequestRateLimit(Arc<Mutex<Tokens>>);
+
+#[derive(Debug)]
+struct Tokens {
+    per_second: f64,
+    available: f64,
+    updated: Instant,
+}
+
+// === impl IdentityRateLimits ===
+
+impl IdentityRateLimits {
+    pub fn new(
+        default: Option<u32>,
+        identities: impl IntoIterator<Item = (identity::Name, u32)>,
+        unauthenticated: Option<u32>,
+    ) -> Self {
+        Self {
+            default,
+            identities: Arc::new(identities.into_iter().collect()),
+            unauthenticated,
+            limits: Default::default(),
+        }
+    }
+
+    fn for_client(&self, client: Option<identity::Name>) -> Option<RequestRateLimit> {
+        let per_second = match client.as_ref() {
+            Some(id) => self.identities.get(id).copied().or(self.default)?,
+            None => self.unauthenticated?,
+        };
+        let mut limits = self.limits.lock();
+        if let Some(limit) = limits.get(&client) {
+            return Some(limit.clone());
+        }
+
+        // Before tracking a new client, evict the limits of clients that are
+        // idle, so that the number of tracked clients remains bounded.
+        let now = Instant::now();
+        limits.retain(|_, limit| !limit.is_idle(now));
+        let limit = RequestRateLimit::per_second(per_second);
+        limits.insert(client, limit.clone());
+        Some(limit)
+    }
+}
+
+// === impl NewLimitIdentityRate ===
+
+impl<N> NewLimitIdentityRate<N> {
+    pub fn layer(limits: IdentityRateLimits) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            limits: limits.clone(),
+        })
+    }
+}
+
+impl<T, N> svc::NewService<T> for NewLimitIdentityRate<N>
+where
+    T: Param<Option<identity::Name>>,
+    N: svc::NewService<T>,
+{
+    type Service = SetRequestRateLimit<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let client: Option<identity::Name> = target.param();
+        let limit = self.limits.for_client(client);
+        trace!(limited = limit.is_some(), "Client request rate");
+        SetRequestRateLimit {
+            inner: self.inner.new_service(target),
+            limit,
+        }
+    }
+}
+
+// === impl SetRequestRateLimit ===
+
+impl<S, B> svc::Service<http::Request<B>> for SetRequestRateLimit<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(limit) = self.limit.clone() {
+            req.extensions_mut().insert(limit);
+        }
+        self.inner.call(req)
+    }
+}
+
+// === impl LimitRequestRate ===
+
+impl<S> LimitRequestRate<S> {
+    pub fn layer() -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for LimitRequestRate<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(limit) = req.extensions().get::<RequestRateLimit>() {
+            if !limit.try_acquire() {
+                debug!("Client exceeded its request rate limit");
+                return future::Either::Right(future::err(
+                    HttpError::too_many_requests("too many requests").into(),
+                ));
+            }
+        }
+        future::Either::Left(self.inner.call(req).err_into::<Error>())
+    }
+}
+
+// === impl RequestRateLimit ===
+
+impl RequestRateLimit {
+    fn per_second(per_second: u32) -> Self {
+        let per_second = f64::from(per_second);
+        Self(Arc::new(Mutex::new(Tokens {
+            per_second,
+            available: per_second,
+            updated: Instant::now(),
+        })))
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut tokens = self.0.lock();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(tokens.updated);
+        tokens.updated = now;
+        tokens.available =
+            (tokens.available + elapsed.as_secs_f64() * tokens.per_second).min(tokens.per_second);
+        if tokens.available < 1.0 {
+            return false;
+        }
+        tokens.available -= 1.0;
+        true
+    }
+
+    /// Returns true if no connections use this limit and all of its tokens
+    /// are available.
+    fn is_idle(&self, now: Instant) -> bool {
+        if Arc::strong_count(&self.0) > 1 {
+            return false;
+        }
+        let tokens = self.0.lock();
+        let elapsed = now.saturating_duration_since(tokens.updated);
+        tokens.available + elapsed.as_secs_f64() * tokens.per_second >= tokens.per_second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::svc::{Layer, NewService, Service, ServiceExt};
+    use std::{str::FromStr, time::Duration};
+
+    #[derive(Clone, Debug)]
+    struct Client(Option<identity::Name>);
+
+    impl Param<Option<identity::Name>> for Client {
+        fn param(&self) -> Option<identity::Name> {
+            self.0.clone()
+        }
+    }
+
+    fn id(name: &str) -> identity::Name {
+        identity::Name::from_str(name).unwrap()
+    }
+
+    /// Sends `n` requests on a new connection from `client`, returning the
+    /// number of requests that were permitted.
+    async fn send(limits: &IdentityRateLimits, client: Option<&str>, n: usize) -> usize {
+        let mut new_svc = NewLimitIdentityRate::layer(limits.clone()).layer(|_: Client| {
+            LimitRequestRate::layer().layer(svc::mk(|_: http::Request<()>| {
+                future::ok::<_, Error>(http::Response::new(()))
+            }))
+        });
+        let mut svc = new_svc.new_service(Client(client.map(id)));
+
+        let mut permitted = 0;
+        for _ in 0..n {
+            let req = http::Request::new(());
+            match svc.ready().await.unwrap().call(req).await {
+                Ok(_) => permitted += 1,
+                Err(error) => assert!(error.is::<HttpError>(), "{}", error),
+            }
+        }
+        permitted
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn limits_each_identity_independently() {
+        let limits = IdentityRateLimits::new(
+            Some(2),
+            vec![(
+                id("big.ns.serviceaccount.identity.linkerd.cluster.local"),
+                5,
+            )],
+            Some(1),
+        );
+
+        let big = Some("big.ns.serviceaccount.identity.linkerd.cluster.local");
+        let small = Some("small.ns.serviceaccount.identity.linkerd.cluster.local");
+
+        assert_eq!(send(&limits, big, 10).await, 5);
+        assert_eq!(send(&limits, small, 10).await, 2);
+        assert_eq!(send(&limits, None, 10).await, 1);
+
+        assert_eq!(
+            send(&limits, small, 1).await,
+            0,
+            "connections from the same identity must share its limit"
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(send(&limits, big, 10).await, 5);
+        assert_eq!(send(&limits, small, 10).await, 2);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn evicts_idle_limits() {
+        let limits = IdentityRateLimits::new(Some(2), vec![], Some(1));
+        let a = Some("a.ns.serviceaccount.identity.linkerd.cluster.local");
+        let b = Some("b.ns.serviceaccount.identity.linkerd.cluster.local");
+
+        assert_eq!(send(&limits, a, 10).await, 2);
+        assert_eq!(send(&limits, b, 10).await, 2);
+        assert_eq!(
+            limits.limits.lock().len(),
+            2,
+            "limits must be retained until they are replenished"
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(send(&limits, None, 1).await, 1);
+        assert_eq!(
+            limits.limits.lock().len(),
+            1,
+            "replenished limits must be evicted"
+        );
+        assert_eq!(send(&limits, a, 10).await, 2);
+    }
+}
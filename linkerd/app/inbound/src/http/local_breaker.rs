@@ -0,0 +1,319 @@
+use crate::target::Target;
+use futures::{future, ready, TryFutureExt};
+use linkerd_app_core::{
+    breaker::{Breaker, BreakerThresholds, Permit, Transition},
+    errors::HttpError,
+    metrics::{local_breaker, Gauge},
+    proxy::http,
+    svc, Error,
+};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// Configures a circuit breaker on each of the application's target addresses.
+///
+/// A target's breaker opens once `max_failures` of its requests have failed
+/// consecutively, either with an error or with a server error status. While a
+/// breaker is open, requests to its target fail immediately with a 503 Service
+/// Unavailable, without being dispatched to the application. After
+/// `open_timeout`, a single request is dispatched to probe the target: the
+/// breaker closes if it succeeds and remains open for another `open_timeout`
+/// if it fails.
+///
+/// Ports without configured thresholds use the default thresholds, if any are
+/// set. A breaker's state is shared by all of the connections to its target.
+#[derive(Clone, Debug, Default)]
+pub struct LocalBreakers {
+    default: Option<BreakerThresholds>,
+    ports: Arc<HashMap<u16, BreakerThresholds>>,
+    breakers: Arc<Mutex<HashMap<SocketAddr, Arc<LocalBreaker>>>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewBreakLocal<N> {
+    inner: N,
+    breakers: LocalBreakers,
+    metrics: local_breaker::Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct BreakLocal<S> {
+    inner: S,
+    breaker: Option<Arc<LocalBreaker>>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct BreakLocalFuture<F> {
+    #[pin]
+    inner: F,
+    breaker: Arc<LocalBreaker>,
+    permit: Permit,
+}
+
+#[derive(Debug)]
+struct LocalBreaker {
+    breaker: Breaker,
+    open: Arc<Gauge>,
+}
+
+// === impl LocalBreakers ===
+
+impl LocalBreakers {
+    pub fn new(
+        default: Option<BreakerThresholds>,
+        ports: impl IntoIterator<Item = (u16, BreakerThresholds)>,
+    ) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+            breakers: Default::default(),
+        }
+    }
+
+    fn for_target(
+        &self,
+        addr: SocketAddr,
+        metrics: &local_breaker::Registry,
+    ) -> Option<Arc<LocalBreaker>> {
+        let thresholds = self.ports.get(&addr.port()).copied().or(self.default)?;
+        if thresholds.max_failures == 0 {
+            return None;
+        }
+        let breaker = self
+            .breakers
+            .lock()
+            .entry(addr)
+            .or_insert_with(|| {
+                Arc::new(LocalBreaker {
+                    breaker: Breaker::new(thresholds),
+                    open: metrics.gauge(addr),
+                })
+            })
+            .clone();
+        Some(breaker)
+    }
+}
+
+// === impl NewBreakLocal ===
+
+impl<N> NewBreakLocal<N> {
+    pub fn layer(
+        breakers: LocalBreakers,
+        metrics: local_breaker::Registry,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            breakers: breakers.clone(),
+            metrics: metrics.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<Target> for NewBreakLocal<N>
+where
+    N: svc::NewService<Target>,
+{
+    type Service = BreakLocal<N::Service>;
+
+    fn new_service(&mut self, target: Target) -> Self::Service {
+        let breaker = self.breakers.for_target(target.target_addr, &self.metrics);
+        BreakLocal {
+            inner: self.inner.new_service(target),
+            breaker,
+        }
+    }
+}
+
+// === impl BreakLocal ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for BreakLocal<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::Ready<Result<http::Response<B>, Error>>,
+        future::Either<future::ErrInto<S::Future, Error>, BreakLocalFuture<S::Future>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let breaker = match self.breaker.as_ref() {
+            Some(breaker) => breaker,
+            None => {
+                return future::Either::Right(future::Either::Left(self.inner.call(req).err_into()))
+            }
+        };
+
+        let permit = match breaker.breaker.permit() {
+            Some(permit) => permit,
+            None => {
+                return future::Either::Left(future::err(
+                    HttpError::circuit_open("local service circuit open").into(),
+                ))
+            }
+        };
+        future::Either::Right(future::Either::Right(BreakLocalFuture {
+            inner: self.inner.call(req),
+            breaker: breaker.clone(),
+            permit,
+        }))
+    }
+}
+
+// === impl BreakLocalFuture ===
+
+impl<F, B, E> Future for BreakLocalFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    E: Into<Error>,
+{
+    type Output = Result<http::Response<B>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx)).map_err(Into::into);
+        let failed = match res.as_ref() {
+            Ok(rsp) => rsp.status().is_server_error(),
+            Err(_) => true,
+        };
+        match this.breaker.breaker.record(*this.permit, failed) {
+            Some(Transition::Opened) => this.breaker.open.incr(),
+            Some(Transition::Closed) => this.breaker.open.decr(),
+            None => {}
+        }
+        Poll::Ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::FmtMetrics,
+        svc::{Layer, NewService, ServiceExt},
+        tls, Conditional,
+    };
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    fn target(port: u16) -> Target {
+        Target {
+            dst: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            target_addr: ([127, 0, 0, 1], port).into(),
+            http_version: http::Version::Http1,
+            tls: Conditional::None(tls::NoServerTls::Loopback),
+            log_client_port: false,
+            class: None,
+        }
+    }
+
+    fn thresholds(max_failures: usize) -> BreakerThresholds {
+        BreakerThresholds {
+            max_failures,
+            open_timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn gauge(metrics: &local_breaker::Registry, port: u16) -> Option<String> {
+        let prefix = format!(
+            "inbound_local_circuit_breaker_open{{target_addr=\"127.0.0.1:{}\"}} ",
+            port
+        );
+        metrics
+            .as_display()
+            .to_string()
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix.as_str()).map(String::from))
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn opens_after_consecutive_failures() {
+        let dispatched = Arc::new(AtomicUsize::new(0));
+        let fail = Arc::new(AtomicBool::new(true));
+        let metrics = local_breaker::Registry::default();
+        let mut new_svc = {
+            let dispatched = dispatched.clone();
+            let fail = fail.clone();
+            NewBreakLocal::layer(
+                LocalBreakers::new(Some(thresholds(2)), Some((8081, thresholds(0)))),
+                metrics.clone(),
+            )
+            .layer(move |_: Target| {
+                let dispatched = dispatched.clone();
+                let fail = fail.clone();
+                svc::mk(move |_: http::Request<()>| {
+                    dispatched.fetch_add(1, Ordering::SeqCst);
+                    let status = if fail.load(Ordering::SeqCst) {
+                        http::StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        http::StatusCode::OK
+                    };
+                    let rsp = http::Response::builder().status(status).body(()).unwrap();
+                    future::ok::<_, Error>(rsp)
+                })
+            })
+        };
+
+        // Services for the same target share a breaker.
+        let svc0 = new_svc.new_service(target(8080));
+        let svc1 = new_svc.new_service(target(8080));
+        for svc in [svc0.clone(), svc1.clone()].iter() {
+            let rsp = svc.clone().oneshot(http::Request::new(())).await;
+            assert_eq!(
+                rsp.unwrap().status(),
+                http::StatusCode::INTERNAL_SERVER_ERROR
+            );
+        }
+        assert_eq!(gauge(&metrics, 8080).as_deref(), Some("1"));
+        let err = svc0
+            .clone()
+            .oneshot(http::Request::new(()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HttpError>().map(|e| e.to_string()),
+            Some("local service circuit open".to_string())
+        );
+        assert_eq!(dispatched.load(Ordering::SeqCst), 2);
+
+        // Ports may opt out of the default thresholds.
+        let disabled = new_svc.new_service(target(8081));
+        for _ in 0..3 {
+            let rsp = disabled.clone().oneshot(http::Request::new(())).await;
+            assert!(rsp.is_ok(), "the breaker must be disabled");
+        }
+        assert_eq!(gauge(&metrics, 8081), None);
+
+        // Once the timeout elapses, a single request probes the target.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(svc1.clone().oneshot(http::Request::new(())).await.is_ok());
+        assert!(svc1.clone().oneshot(http::Request::new(())).await.is_err());
+        assert_eq!(gauge(&metrics, 8080).as_deref(), Some("1"));
+
+        fail.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        for _ in 0..3 {
+            let rsp = svc0.clone().oneshot(http::Request::new(())).await;
+            assert_eq!(rsp.unwrap().status(), http::StatusCode::OK);
+        }
+        assert_eq!(gauge(&metrics, 8080).as_deref(), Some("0"));
+    }
+}
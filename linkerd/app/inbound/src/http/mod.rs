@@ -1,8 +1,70 @@
+mod allow_methods;
+mod allow_upgrades;
+mod app_errors;
+mod body_size_routing;
+mod coalesce_headers;
+mod cookie_limit;
+mod error_rate;
+mod grpc_compression;
+mod identity_rate_limit;
+mod local_breaker;
+mod proxy_elapsed;
+mod read_timeout;
+mod redact;
+mod redact_errors;
+mod replay_protection;
+mod request_body_limit;
+mod request_id;
+mod request_line;
+mod require_authority;
+mod response_headers_timeout;
 mod set_identity_header;
+mod stream_limit;
+mod strip_l5d_headers;
 #[cfg(test)]
 mod tests;
+mod trace_attributes;
 
-use self::set_identity_header::NewSetIdentityHeader;
+pub use self::coalesce_headers::{
+    DuplicateContentLength, DuplicateHeaders, TransferEncodingConflict,
+};
+pub use self::cookie_limit::{CookieLimits, OversizedCookies};
+pub use self::local_breaker::LocalBreakers;
+pub(crate) use self::request_id::RequestIdValue;
+pub use self::{
+    allow_methods::AllowedMethods, allow_upgrades::AllowedUpgrades,
+    body_size_routing::BodySizeRouting, error_rate::ErrorRateLimits,
+    grpc_compression::GrpcCompression, identity_rate_limit::IdentityRateLimits,
+    redact::RedactFields, replay_protection::ReplayProtection,
+    request_body_limit::RequestBodyLimits, require_authority::MissingAuthority,
+    stream_limit::H2StreamLimit, strip_l5d_headers::StripL5dHeaders,
+    trace_attributes::TraceAttributes,
+};
+use self::{
+    allow_methods::NewAllowMethods,
+    allow_upgrades::AllowUpgrades,
+    body_size_routing::{NewBodySizeRoute, NewBodySizeSwitch},
+    coalesce_headers::CoalesceHeaders,
+    cookie_limit::LimitCookies,
+    error_rate::NewLimitErrorRate,
+    grpc_compression::BridgeGrpcCompression,
+    identity_rate_limit::{LimitRequestRate, NewLimitIdentityRate},
+    local_breaker::NewBreakLocal,
+    proxy_elapsed::{MarkReceived, SetProxyElapsed},
+    read_timeout::ReadTimeout,
+    redact::NewRedactResponse,
+    redact_errors::NewRedactErrors,
+    replay_protection::NewProtectReplay,
+    request_body_limit::{LimitRequestBody, NewOverrideBodyLimit},
+    request_id::RequestId,
+    request_line::RequestLineLimit,
+    require_authority::RequireAuthority,
+    response_headers_timeout::ResponseHeadersTimeout,
+    set_identity_header::NewSetIdentityHeader,
+    stream_limit::LimitH2Streams,
+    strip_l5d_headers::NewStripL5dHeaders,
+    trace_attributes::NewTagSpan,
+};
 use crate::{
     allow_discovery::AllowProfile,
     target::{self, HttpAccept, HttpEndpoint, Logical, RequestTarget, Target, TcpEndpoint},
@@ -18,6 +80,7 @@ use linkerd_app_core::{
     dst, errors, http_tracing, identity, io, profiles,
     proxy::{http, tap},
     svc::{self, Param},
+    transport::addrs::OrigDstAddr,
     Error,
 };
 use tracing::debug_span;
@@ -27,7 +90,8 @@ impl<H> Inbound<H> {
     where
         T: Param<Version>
             + Param<http::normalize_uri::DefaultAuthority>
-            + Param<Option<identity::Name>>,
+            + Param<Option<identity::Name>>
+            + Param<OrigDstAddr>,
         T: Clone + Send + 'static,
         I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + Send + Unpin + 'static,
         H: svc::NewService<T, Service = HSvc> + Clone + Send + Sync + Unpin + 'static,
@@ -44,6 +108,8 @@ impl<H> Inbound<H> {
                 server: ServerConfig { h2_settings, .. },
                 dispatch_timeout,
                 max_in_flight_requests,
+                client_disconnect,
+                echo_trace_id,
                 ..
             } = config.proxy;
 
@@ -52,11 +118,37 @@ impl<H> Inbound<H> {
                 // `Client`. This must be below the `orig_proto::Downgrade` layer, since
                 // the request may have been downgraded from a HTTP/2 orig-proto request.
                 .push(http::NewNormalizeUri::layer())
+                // Rejects requests that lack an authority before one can be
+                // derived from the target, if so configured.
+                .push_on_response(RequireAuthority::layer(config.missing_authority))
+                // Joins duplicate list-valued headers and ensures that request
+                // bodies are framed unambiguously. This guards against request
+                // smuggling before requests are routed.
+                .push_on_response(CoalesceHeaders::layer(
+                    config.duplicate_headers.clone(),
+                    config.transfer_encoding_conflict,
+                    config.duplicate_content_length,
+                ))
+                // Rejects or strips cookies in excess of the configured
+                // limits.
+                .push_on_response(LimitCookies::layer(config.cookie_limits))
                 .push(NewSetIdentityHeader::layer())
                 .push_on_response(
                     svc::layers()
+                        // Decompresses gRPC messages on configured routes when
+                        // the peer does not support their encoding.
+                        .push(BridgeGrpcCompression::layer(
+                            config.grpc_compression.clone(),
+                        ))
                         // Downgrades the protocol if upgraded by an outbound proxy.
                         .push(http::orig_proto::Downgrade::layer())
+                        // Bounds the length of HTTP/1 request lines. This must be
+                        // above the `orig_proto::Downgrade` layer so that requests
+                        // received over HTTP/2 are not limited.
+                        .push(RequestLineLimit::layer(config.max_request_line_bytes))
+                        // Rejects HTTP/1.1 upgrades to protocols that are not
+                        // permitted, before they can be tunneled.
+                        .push(AllowUpgrades::layer(config.allowed_upgrades.clone()))
                         // Limit the number of in-flight requests. When the proxy is
                         // at capacity, go into failfast after a dispatch timeout.
                         // Note that the inner service _always_ returns ready (due
@@ -65,18 +157,74 @@ impl<H> Inbound<H> {
                         // for SpawnReady
                         .push(svc::ConcurrencyLimitLayer::new(max_in_flight_requests))
                         .push(svc::FailFast::layer("HTTP Server", dispatch_timeout))
+                        // Fails requests from clients that exceed their
+                        // identity's request rate limit.
+                        .push(LimitRequestRate::layer())
+                        // Fails requests whose bodies exceed the configured
+                        // size, aborting them as they are streamed.
+                        .push(LimitRequestBody::layer(
+                            config.max_request_body_bytes.clone(),
+                        ))
+                        // Refuses HTTP/2 streams when too many are active across
+                        // all connections. This must be above the
+                        // `orig_proto::Downgrade` layer so that upgraded
+                        // requests are counted as streams.
+                        .push(LimitH2Streams::layer(config.h2_stream_limit.clone()))
                         .push(rt.metrics.http_errors.clone())
-                        // Synthesizes responses for proxy errors.
-                        .push(errors::layer())
+                        // Synthesizes responses for proxy errors. gRPC
+                        // statuses are sent in trailers.
+                        .push(errors::layer(
+                            echo_trace_id,
+                            config.json_error_bodies,
+                            config.proxy_error_header.clone(),
+                            true,
+                        ))
+                        // Identifies the proxy in the `Via` headers of requests
+                        // and their responses, including error responses.
+                        .push(http::AddVia::layer(config.proxy.via.clone()))
+                        // Ensures that each request has an ID, so that it is
+                        // set on error responses as well.
+                        .push(RequestId::layer(config.request_id_header.clone()))
                         .push(http_tracing::server(rt.span_sink.clone(), trace_labels()))
+                        // Decides whether requests are traced before their
+                        // spans are recorded.
+                        .push(http_tracing::Sample::layer(config.trace_sampler.clone()))
                         // Record when an HTTP/1 URI was in absolute form
                         .push(http::normalize_uri::MarkAbsoluteForm::layer())
+                        // Records when each request was received, so that the
+                        // time spent in the proxy can be reported to the
+                        // application.
+                        .push(MarkReceived::layer(config.proxy_elapsed_header.clone()))
                         .push(http::BoxRequest::layer())
-                        .push(http::BoxResponse::layer()),
+                        .push(http::BoxResponse::layer())
+                        // Determines whether requests complete after their
+                        // clients disconnect.
+                        .push(http::HandleDisconnect::layer(client_disconnect)),
                 )
+                // Bounds the rate of error responses on each port, so that
+                // errors are cheap to serve when the application is down.
+                .push(NewLimitErrorRate::layer(config.error_rate_limits.clone()))
+                // Sets the request rate limit shared by all of each client
+                // identity's connections.
+                .push(NewLimitIdentityRate::layer(
+                    config.identity_rate_limits.clone(),
+                ))
+                // Removes the proxy's `l5d-*` headers from responses to
+                // external clients, if so configured.
+                .push(NewStripL5dHeaders::layer(
+                    config.strip_l5d_headers,
+                    config.proxy_error_header.clone(),
+                ))
+                // Replaces the messages of error responses to clients without
+                // a verified identity, if so configured.
+                .push(NewRedactErrors::layer(config.redact_unmeshed_errors))
                 .check_new_service::<T, http::Request<_>>()
                 .instrument(|t: &T| debug_span!("http", v=%Param::<Version>::param(t)))
-                .push(http::NewServeHttp::layer(h2_settings, rt.drain.clone()))
+                .push(http::NewServeHttp::layer_with_websocket_idle_timeout(
+                    h2_settings,
+                    config.websocket_idle_timeout,
+                    rt.drain.clone(),
+                ))
                 .push_on_response(svc::BoxService::layer())
                 .push(svc::BoxNewService::layer())
         })
@@ -110,8 +258,22 @@ where
         P::Error: Send,
     {
         self.map_stack(|config, rt, connect| {
+            let classify_app_errors = config.classify_app_connection_errors;
+            let endpoint_granularity = config.metrics_granularity.clone();
+            let route_granularity = config.metrics_granularity.clone();
+
             // Creates HTTP clients for each inbound port & HTTP settings.
             let endpoint = connect
+                // Describes failures to connect to the application, so that
+                // they are distinguished from connections that it resets.
+                .push(svc::MapErrLayer::new(move |e: C::Error| {
+                    let e: Error = e.into();
+                    if classify_app_errors {
+                        app_errors::connect_failed(e)
+                    } else {
+                        e
+                    }
+                }))
                 .push(svc::stack::BoxFuture::layer())
                 .push(rt.metrics.transport.layer_connect())
                 .push_map_target(TcpEndpoint::from)
@@ -120,6 +282,30 @@ where
                     config.proxy.connect.h2_settings,
                 ))
                 .push_on_response(svc::MapErrLayer::new(Into::into))
+                // Fails requests when the application stalls while a
+                // response is awaited.
+                .push_on_response(ReadTimeout::layer(config.app_read_timeout))
+                .push_on_response(svc::MapErrLayer::new(move |e: Error| {
+                    if classify_app_errors {
+                        app_errors::upstream_reset(e)
+                    } else {
+                        e
+                    }
+                }))
+                // Sets the time spent in the proxy on each request as it is
+                // dispatched to the application.
+                .push_on_response(SetProxyElapsed::layer(config.proxy_elapsed_header.clone()))
+                .push_on_response(http::HandleCloseDelimited::layer(
+                    config.proxy.connect.close_delimited,
+                ))
+                .push_on_response(http::ReadAhead::layer(
+                    config.proxy.connect.response_read_ahead_bytes,
+                ))
+                // Bounds the time the application takes to send response
+                // headers, but not the time taken to stream the body.
+                .push_on_response(ResponseHeadersTimeout::layer(
+                    config.response_headers_timeout,
+                ))
                 .into_new_service()
                 .push_new_reconnect(config.proxy.connect.backoff)
                 .check_new_service::<HttpEndpoint, http::Request<_>>();
@@ -128,14 +314,25 @@ where
                 .push_map_target(HttpEndpoint::from)
                 // Registers the stack to be tapped.
                 .push(tap::NewTapHttp::layer(rt.tap.clone()))
-                // Records metrics for each `Target`.
+                // Records metrics for each `Target`, unless the port's
+                // metrics granularity omits them.
                 .push(
                     rt.metrics
                         .http_endpoint
-                        .to_layer::<classify::Response, _, _>(),
+                        .to_layer_filtered::<classify::Response, _, _, _>(move |t: &Target| {
+                            endpoint_granularity.endpoint(t.target_addr.port())
+                        }),
                 )
                 .push_on_response(http_tracing::client(rt.span_sink.clone(), trace_labels()))
+                // Records the allowed request metadata on each request's span.
+                .push(NewTagSpan::layer(config.trace_attributes.clone()))
                 .push_on_response(http::BoxResponse::layer())
+                // Fails requests fast while the application is failing
+                // consistently, if a circuit breaker is configured.
+                .push(NewBreakLocal::layer(
+                    config.local_breakers.clone(),
+                    rt.metrics.http_local_breakers.clone(),
+                ))
                 .check_new_service::<Target, http::Request<_>>();
 
             let no_profile = target
@@ -149,6 +346,8 @@ where
             // request has not been received for `cache_max_idle_age`.
             target
                 .clone()
+                // Routes requests with large bodies to an alternate port.
+                .push(NewBodySizeSwitch::layer(&config.body_size_routing))
                 .check_new_service::<Target, http::Request<http::BoxBody>>()
                 .push_on_response(http::BoxRequest::layer())
                 // The target stack doesn't use the profile resolution, so drop it.
@@ -158,16 +357,41 @@ where
                         // Sets the route as a request extension so that it can be used
                         // by tap.
                         .push_http_insert_target::<dst::Route>()
-                        // Records per-route metrics.
+                        // Fails requests that replay a nonce on routes that are
+                        // protected from replay.
+                        .push(NewProtectReplay::layer(config.replay_protection.clone()))
+                        // Fails requests with methods that the route does not
+                        // permit.
+                        .push(NewAllowMethods::layer(config.allowed_methods.clone()))
+                        // Removes the route's sensitive fields from its JSON
+                        // responses.
+                        .push(NewRedactResponse::layer(config.redact_fields.clone()))
+                        // Marks requests whose bodies exceed the route's size
+                        // threshold so that they are routed to an alternate
+                        // port.
+                        .push(NewBodySizeRoute::layer(config.body_size_routing.clone()))
+                        // Overrides the request body size limit for the route,
+                        // if its metadata sets one.
+                        .push(NewOverrideBodyLimit::layer(
+                            config.max_request_body_bytes.clone(),
+                        ))
+                        // Fails requests that do not receive response headers
+                        // before the route's timeout, if it has one.
+                        .push(http::MakeTimeoutLayer::default())
+                        .push_map_target(|r: target::PortRoute| r.route)
+                        // Records per-route metrics, unless the port's metrics
+                        // granularity omits them.
                         .push(
                             rt.metrics
                                 .http_route
-                                .to_layer::<classify::Response, _, dst::Route>(),
+                                .to_layer_filtered::<classify::Response, _, _, _>(
+                                    move |r: &target::PortRoute| route_granularity.route(r.port),
+                                ),
                         )
                         // Sets the per-route response classifier as a request
                         // extension.
                         .push(classify::NewClassify::layer())
-                        .check_new_clone::<dst::Route>()
+                        .check_new_clone::<target::PortRoute>()
                         .push_map_target(target::route)
                         .into_inner(),
                 ))
@@ -218,7 +442,10 @@ where
                 // target, and dispatches the request.
                 .instrument_from_target()
                 .push(svc::BoxNewService::layer())
-                .push(svc::NewRouter::layer(RequestTarget::from))
+                .push(svc::NewRouter::layer({
+                    let log_client_port = config.log_client_port;
+                    move |accept| RequestTarget::new(accept, log_client_port)
+                }))
                 // Used by tap.
                 .push_http_insert_target::<HttpAccept>()
                 .push(svc::BoxNewService::layer())
@@ -274,6 +501,7 @@ pub mod fuzz_logic {
                 target_addr: ([127, 0, 0, 1], 5550).into(),
                 client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
                 tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+                class: None,
             },
         };
         let connect =
@@ -0,0 +1,146 @@
+use linkerd_app_core::{
+    proxy::http::{self, HeaderName, HeaderValue},
+    svc,
+};
+use std::task::{Context, Poll};
+use tokio::time::Instant;
+use tracing::trace;
+
+/// Records when each request was received by the server, so that the time the
+/// proxy spends on the request can be set on it when it is dispatched to the
+/// application.
+///
+/// Values that clients set on the configured header are always removed, since
+/// they cannot be trusted.
+#[derive(Clone, Debug)]
+pub struct MarkReceived<S> {
+    inner: S,
+    header: Option<HeaderName>,
+}
+
+/// Sets the number of microseconds between the time a request was received
+/// and the time it is dispatched to the application on the configured header.
+///
+/// The duration is measured with a monotonic clock, so it is not affected by
+/// clock skew between hosts.
+#[derive(Clone, Debug)]
+pub struct SetProxyElapsed<S> {
+    inner: S,
+    header: Option<HeaderName>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Received(Instant);
+
+// === impl MarkReceived ===
+
+impl<S> MarkReceived<S> {
+    pub fn layer(header: Option<HeaderName>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            header: header.clone(),
+        })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for MarkReceived<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(header) = self.header.as_ref() {
+            if req.headers_mut().remove(header).is_some() {
+                trace!(%header, "Removed client-provided elapsed time");
+            }
+            req.extensions_mut().insert(Received(Instant::now()));
+        }
+        self.inner.call(req)
+    }
+}
+
+// === impl SetProxyElapsed ===
+
+impl<S> SetProxyElapsed<S> {
+    pub fn layer(header: Option<HeaderName>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            header: header.clone(),
+        })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for SetProxyElapsed<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(header) = self.header.as_ref() {
+            if let Some(Received(received)) = req.extensions().get::<Received>().copied() {
+                let elapsed = received.elapsed().as_micros();
+                req.headers_mut()
+                    .insert(header.clone(), HeaderValue::from(elapsed as u64));
+            }
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd_app_core::{
+        svc::{Layer, ServiceExt},
+        Error,
+    };
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn sets_elapsed_time() {
+        let header = HeaderName::from_static("l5d-proxy-elapsed-us");
+
+        let app =
+            SetProxyElapsed::layer(Some(header.clone())).layer(svc::mk(|req: http::Request<()>| {
+                future::ok::<_, Error>(req.headers().get("l5d-proxy-elapsed-us").cloned())
+            }));
+        // Simulates the time the proxy spends before dispatching the request.
+        let proxy = MarkReceived::layer(Some(header.clone())).layer(svc::mk(
+            move |req: http::Request<()>| {
+                let app = app.clone();
+                async move {
+                    tokio::time::advance(Duration::from_millis(5)).await;
+                    app.oneshot(req).await
+                }
+            },
+        ));
+
+        let req = http::Request::builder()
+            .header(&header, "1")
+            .body(())
+            .unwrap();
+        let elapsed = proxy.oneshot(req).await.unwrap();
+        assert_eq!(
+            elapsed.expect("the application must receive the header"),
+            "5000",
+            "client-provided values must be replaced"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use super::response_headers_timeout::RequestBody;
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    errors::HttpError,
+    proxy::http::{self, HttpBody},
+    svc, Error,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::time::{self, Instant, Sleep};
+use tracing::debug;
+
+/// Fails requests if the application stalls while the proxy awaits its
+/// response.
+///
+/// The timeout only runs while a response is being awaited: it starts once
+/// the request body has been sent and restarts each time response data is
+/// read, so streaming responses are only interrupted when the application
+/// stalls between reads. Uploads and idle connections are never timed out. If
+/// the response headers are not received in time, the request fails with a
+/// 504 Gateway Timeout; if the response body stalls, the body fails.
+#[derive(Clone, Debug)]
+pub struct ReadTimeout<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseBody<B> {
+    #[pin]
+    inner: B,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    armed: bool,
+}
+
+#[derive(Debug, Error)]
+#[error("application read timed out after {0:?}")]
+pub struct AppReadTimeout(Duration);
+
+type ResponseFuture<B> =
+    Pin<Box<dyn Future<Output = Result<http::Response<ResponseBody<B>>, Error>> + Send + 'static>>;
+
+// === impl ReadTimeout ===
+
+impl<S> ReadTimeout<S> {
+    pub fn layer(timeout: Option<Duration>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, timeout })
+    }
+}
+
+impl<S, A, B> svc::Service<http::Request<A>> for ReadTimeout<S>
+where
+    S: svc::Service<http::Request<RequestBody<A>>, Response = http::Response<B>>,
+    S::Error: Into<Error> + Send + 'static,
+    S::Future: Send + 'static,
+    A: HttpBody,
+    B: Send + 'static,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = Error;
+    type Future = future::Either<
+        future::MapOk<
+            future::ErrInto<S::Future, Error>,
+            fn(http::Response<B>) -> http::Response<ResponseBody<B>>,
+        >,
+        ResponseFuture<B>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => {
+                let req = req.map(|inner| RequestBody::new(inner).0);
+                let pass: fn(http::Response<B>) -> http::Response<ResponseBody<B>> =
+                    |rsp| rsp.map(|inner| ResponseBody::new(inner, None));
+                return future::Either::Left(self.inner.call(req).err_into().map_ok(pass));
+            }
+        };
+
+        let (head, body) = req.into_parts();
+        let (body, sent) = RequestBody::new(body);
+        let rsp = self.inner.call(http::Request::from_parts(head, body));
+
+        future::Either::Right(Box::pin(async move {
+            // Nothing is awaited from the application until the request body
+            // has been sent, unless it responds early.
+            futures::pin_mut!(rsp);
+            let rsp = match future::select(rsp, sent).await {
+                future::Either::Left((rsp, _)) => rsp.map_err(Into::<Error>::into)?,
+                future::Either::Right((_, rsp)) => match time::timeout(timeout, rsp).await {
+                    Ok(rsp) => rsp.map_err(Into::<Error>::into)?,
+                    Err(_) => {
+                        debug!(?timeout, "Application read timed out");
+                        let error = HttpError::gateway_timeout("application read timed out");
+                        return Err(error.into());
+                    }
+                },
+            };
+            Ok(rsp.map(|inner| ResponseBody::new(inner, Some(timeout))))
+        }))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B> ResponseBody<B> {
+    fn new(inner: B, timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            timeout,
+            sleep: None,
+            armed: false,
+        }
+    }
+}
+
+impl<B: Default> Default for ResponseBody<B> {
+    fn default() -> Self {
+        Self::new(B::default(), None)
+    }
+}
+
+impl<B> HttpBody for ResponseBody<B>
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        if let Poll::Ready(data) = this.inner.as_mut().poll_data(cx) {
+            *this.armed = false;
+            return Poll::Ready(data.map(|d| d.map_err(Into::into)));
+        }
+        let error = futures::ready!(poll_timeout(this.timeout, this.sleep, this.armed, cx));
+        Poll::Ready(Some(Err(error)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let mut this = self.project();
+        if let Poll::Ready(trailers) = this.inner.as_mut().poll_trailers(cx) {
+            *this.armed = false;
+            return Poll::Ready(trailers.map_err(Into::into));
+        }
+        let error = futures::ready!(poll_timeout(this.timeout, this.sleep, this.armed, cx));
+        Poll::Ready(Err(error))
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Arms the timeout when the body is first pending after a read, and fails
+/// once it expires.
+fn poll_timeout(
+    timeout: &Option<Duration>,
+    sleep: &mut Option<Pin<Box<Sleep>>>,
+    armed: &mut bool,
+    cx: &mut Context<'_>,
+) -> Poll<Error> {
+    let timeout = match *timeout {
+        Some(timeout) => timeout,
+        None => return Poll::Pending,
+    };
+    let sleep = sleep.get_or_insert_with(|| Box::pin(time::sleep(timeout)));
+    if !*armed {
+        sleep.as_mut().reset(Instant::now() + timeout);
+        *armed = true;
+    }
+    futures::ready!(sleep.as_mut().poll(cx));
+
+    debug!(?timeout, "Application read timed out");
+    *armed = false;
+    Poll::Ready(AppReadTimeout(timeout).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Sends a request to an application that reads the request body and then
+    /// responds with `rsp`.
+    async fn send(
+        body: hyper::Body,
+        rsp: hyper::Body,
+    ) -> Result<http::Response<ResponseBody<hyper::Body>>, Error> {
+        let mut rsp = Some(rsp);
+        let app = svc::mk(move |req: http::Request<RequestBody<hyper::Body>>| {
+            let rsp = rsp.take().expect("only one request may be sent");
+            async move {
+                hyper::body::to_bytes(req.into_body()).await?;
+                Ok::<_, Error>(http::Response::new(rsp))
+            }
+        });
+        ReadTimeout {
+            inner: app,
+            timeout: Some(TIMEOUT),
+        }
+        .oneshot(http::Request::new(body))
+        .await
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn resets_on_each_read() {
+        // Data that trickles in within the timeout is read successfully.
+        let (mut tx, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..5 {
+                time::sleep(TIMEOUT / 2).await;
+                tx.send_data("data".into()).await.unwrap();
+            }
+            // Hold the response open without writing.
+            time::sleep(TIMEOUT * 10).await;
+        });
+        let mut body = send(hyper::Body::empty(), body)
+            .await
+            .expect("response must succeed")
+            .into_body();
+        for _ in 0..5 {
+            body.data()
+                .await
+                .expect("body must not end")
+                .expect("read must succeed");
+        }
+
+        // Once the application stalls, the read fails.
+        let start = Instant::now();
+        let err = body
+            .data()
+            .await
+            .expect("body must not end")
+            .expect_err("read must time out");
+        assert!(err.is::<AppReadTimeout>());
+        assert_eq!(start.elapsed(), TIMEOUT);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn ignores_slow_uploads() {
+        // The client takes longer than the timeout to send its body, but the
+        // application is not awaited until it has been sent.
+        let (mut tx, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            time::sleep(TIMEOUT * 2).await;
+            tx.send_data("hello".into()).await.unwrap();
+        });
+        let rsp = send(body, hyper::Body::from("world"))
+            .await
+            .expect("uploads must not time out");
+        let body = hyper::body::to_bytes(rsp.into_body())
+            .await
+            .expect("body must be read");
+        assert_eq!(body, "world");
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn ignores_idle_responses() {
+        // Once the response has been read, the body is not polled again and
+        // is never timed out, however long it is held.
+        let mut body = send(hyper::Body::empty(), hyper::Body::from("done"))
+            .await
+            .expect("response must succeed")
+            .into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "done");
+        time::sleep(TIMEOUT * 10).await;
+        assert!(body.data().await.is_none());
+    }
+}
@@ -0,0 +1,337 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{future, prelude::*};
+use linkerd_app_core::{
+    dst,
+    proxy::http::{self, header::HeaderValue, HttpBody},
+    svc::{self, stack::Proxy},
+    Error,
+};
+use std::{collections::HashSet, pin::Pin, sync::Arc};
+use thiserror::Error;
+use tracing::{debug, trace};
+
+/// Determines which fields are redacted from the JSON responses of each
+/// inbound route.
+///
+/// When a `label` is configured, a route whose metadata sets it to a
+/// comma-separated list of field names (e.g. `ssn,password`) has those fields
+/// removed from every object in its JSON responses, at any depth. Responses
+/// whose `content-type` is not JSON stream through unmodified.
+///
+/// A JSON document can only be redacted once it has been read in full, so
+/// JSON response bodies are buffered, up to `max_body_bytes`, and returned
+/// with an updated `content-length`. Responses that cannot be redacted (e.g.
+/// because they are too large, are not valid JSON, or are compressed) fail
+/// rather than exposing the fields. Clients' `accept-encoding` headers are
+/// removed on these routes so that the application does not compress its
+/// responses.
+#[derive(Clone, Debug)]
+pub struct RedactFields {
+    label: Option<Arc<str>>,
+    max_body_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewRedactResponse<N> {
+    inner: N,
+    config: RedactFields,
+}
+
+#[derive(Clone, Debug)]
+pub struct RedactResponse<P> {
+    inner: P,
+    redact: Option<Arc<Redact>>,
+}
+
+#[derive(Debug, Error)]
+pub enum RedactError {
+    #[error("response body exceeds {0} bytes and cannot be redacted")]
+    TooLarge(usize),
+
+    #[error("encoded response body cannot be redacted")]
+    Encoded,
+
+    #[error("response body is not valid JSON: {0}")]
+    InvalidJson(#[source] serde_json::Error),
+}
+
+#[derive(Debug)]
+struct Redact {
+    fields: HashSet<String>,
+    max_body_bytes: usize,
+}
+
+// === impl RedactFields ===
+
+impl RedactFields {
+    pub fn new(label: Option<String>, max_body_bytes: usize) -> Self {
+        Self {
+            label: label.map(Arc::from),
+            max_body_bytes,
+        }
+    }
+
+    fn redact(&self, route: &dst::Route) -> Option<Redact> {
+        let value = route.route.labels().get(&**self.label.as_ref()?)?;
+        let fields = value
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+            .collect::<HashSet<_>>();
+        if fields.is_empty() {
+            return None;
+        }
+        Some(Redact {
+            fields,
+            max_body_bytes: self.max_body_bytes,
+        })
+    }
+}
+
+impl Default for RedactFields {
+    fn default() -> Self {
+        Self::new(None, 0)
+    }
+}
+
+// === impl NewRedactResponse ===
+
+impl<N> NewRedactResponse<N> {
+    pub fn layer(config: RedactFields) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewRedactResponse<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = RedactResponse<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let redact = self.config.redact(&route).map(Arc::new);
+        RedactResponse {
+            inner: self.inner.new_service(route),
+            redact,
+        }
+    }
+}
+
+// === impl RedactResponse ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for RedactResponse<P>
+where
+    P: Proxy<http::Request<B>, S, Response = http::Response<http::BoxBody>>,
+    P::Error: Into<Error>,
+    P::Future: Send + 'static,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = http::Response<http::BoxBody>;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<P::Future, Error>,
+        Pin<Box<dyn Future<Output = Result<Self::Response, Error>> + Send + 'static>>,
+    >;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        let redact = match self.redact.clone() {
+            Some(redact) => redact,
+            None => return future::Either::Left(self.inner.proxy(svc, req).err_into::<Error>()),
+        };
+
+        req.headers_mut().remove(http::header::ACCEPT_ENCODING);
+        let rsp = self.inner.proxy(svc, req).err_into::<Error>();
+        future::Either::Right(Box::pin(async move {
+            let rsp = rsp.await?;
+            if !is_json(rsp.headers()) {
+                trace!("Not redacting non-JSON response");
+                return Ok(rsp);
+            }
+            let encoded = rsp
+                .headers()
+                .get(http::header::CONTENT_ENCODING)
+                .map(|e| e != "identity")
+                .unwrap_or(false);
+            if encoded {
+                return Err(RedactError::Encoded.into());
+            }
+
+            let (mut head, body) = rsp.into_parts();
+            let body = redact.read(body).await?;
+            if body.is_empty() {
+                // e.g. responses to HEAD requests.
+                return Ok(http::Response::from_parts(head, http::BoxBody::default()));
+            }
+
+            let mut json: serde_json::Value =
+                serde_json::from_slice(&body).map_err(RedactError::InvalidJson)?;
+            redact.redact(&mut json);
+            let body = serde_json::to_vec(&json).expect("JSON values must serialize");
+            debug!(bytes = body.len(), "Redacted response body");
+
+            head.headers.remove(http::header::TRANSFER_ENCODING);
+            head.headers
+                .insert(http::header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+            let body = http::BoxBody::new(http_body::Full::new(Bytes::from(body)));
+            Ok(http::Response::from_parts(head, body))
+        }))
+    }
+}
+
+fn is_json(headers: &http::HeaderMap) -> bool {
+    let content_type = match headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+// === impl Redact ===
+
+impl Redact {
+    async fn read(&self, mut body: http::BoxBody) -> Result<Bytes, Error> {
+        if body.size_hint().lower() > self.max_body_bytes as u64 {
+            return Err(RedactError::TooLarge(self.max_body_bytes).into());
+        }
+        let mut buf = BytesMut::new();
+        while let Some(data) = body.data().await {
+            let data = data?;
+            if buf.len() + data.remaining() > self.max_body_bytes {
+                return Err(RedactError::TooLarge(self.max_body_bytes).into());
+            }
+            buf.put(data);
+        }
+        Ok(buf.freeze())
+    }
+
+    fn redact(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                for field in self.fields.iter() {
+                    fields.remove(field);
+                }
+                for value in fields.values_mut() {
+                    self.redact(value);
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for value in values.iter_mut() {
+                    self.redact(value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::Direction,
+        profiles,
+        svc::{Layer, NewService},
+    };
+
+    const LABEL: &str = "redact-fields";
+
+    async fn send(
+        fields: Option<&str>,
+        content_type: &'static str,
+        body: &'static str,
+    ) -> Result<http::Response<http::BoxBody>, Error> {
+        let labels = fields.map(|f| (LABEL.to_string(), f.to_string()));
+        let route = dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        };
+        let config = RedactFields::new(Some(LABEL.to_string()), 64);
+        let proxy = NewRedactResponse::layer(config)
+            .layer(|_: dst::Route| ())
+            .new_service(route);
+        let mut inner = svc::mk(move |req: http::Request<()>| {
+            if fields.is_some() {
+                assert!(!req.headers().contains_key(http::header::ACCEPT_ENCODING));
+            }
+            let rsp = http::Response::builder()
+                .header(http::header::CONTENT_TYPE, content_type)
+                .header(http::header::CONTENT_LENGTH, body.len())
+                .body(http::BoxBody::new(hyper::Body::from(body)))
+                .unwrap();
+            future::ok::<_, Error>(rsp)
+        });
+        let req = http::Request::builder()
+            .header(http::header::ACCEPT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+        proxy.proxy(&mut inner, req).await
+    }
+
+    async fn body(rsp: http::Response<http::BoxBody>) -> String {
+        let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn redacts_json_fields() {
+        let rsp = send(
+            Some("ssn, password"),
+            "application/json; charset=utf-8",
+            r#"{"name":"a","ssn":"1","users":[{"password":"p","id":2}]}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rsp.headers()[http::header::CONTENT_LENGTH], "31");
+        assert_eq!(body(rsp).await, r#"{"name":"a","users":[{"id":2}]}"#);
+
+        let rsp = send(Some("ssn"), "text/plain", r#"{"ssn":"1"}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            body(rsp).await,
+            r#"{"ssn":"1"}"#,
+            "non-JSON responses must not be modified"
+        );
+
+        let rsp = send(None, "application/json", r#"{"ssn":"1"}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            body(rsp).await,
+            r#"{"ssn":"1"}"#,
+            "routes without the label must not be redacted"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn fails_unredactable_responses() {
+        let err = send(Some("ssn"), "application/json", r#"{"ssn":"1""#)
+            .await
+            .unwrap_err();
+        assert!(err.is::<RedactError>(), "{}", err);
+
+        let large = r#"{"ssn":"00000000000000000000000000000000000000000000000000000000000"}"#;
+        let err = send(Some("ssn"), "application/problem+json", large)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RedactError>(),
+            Some(RedactError::TooLarge(64))
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+use linkerd_app_core::{
+    errors::RedactErrorMessages,
+    identity,
+    proxy::http,
+    svc::{self, Param},
+};
+use std::task::{Context, Poll};
+use tracing::trace;
+
+/// Redacts the messages of proxy error responses to clients without a
+/// verified identity, when enabled.
+#[derive(Clone, Debug)]
+pub struct NewRedactErrors<N> {
+    inner: N,
+    enabled: bool,
+}
+
+/// Marks each request so that its error responses are redacted.
+#[derive(Clone, Debug)]
+pub struct RedactErrors<S> {
+    inner: S,
+    redact: bool,
+}
+
+// === impl NewRedactErrors ===
+
+impl<N> NewRedactErrors<N> {
+    pub fn layer(enabled: bool) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, enabled })
+    }
+}
+
+impl<T, N> svc::NewService<T> for NewRedactErrors<N>
+where
+    T: Param<Option<identity::Name>>,
+    N: svc::NewService<T>,
+{
+    type Service = RedactErrors<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let redact = self.enabled && Param::<Option<identity::Name>>::param(&target).is_none();
+        trace!(redact, "Error messages");
+        RedactErrors {
+            inner: self.inner.new_service(target),
+            redact,
+        }
+    }
+}
+
+// === impl RedactErrors ===
+
+impl<S, B> svc::Service<http::Request<B>> for RedactErrors<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if self.redact {
+            req.extensions_mut().insert(RedactErrorMessages::default());
+        }
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,472 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    dst,
+    errors::HttpError,
+    proxy::http::{self, HeaderName, HeaderValue},
+    svc::{self, stack::Proxy},
+    Addr, Error,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Protects inbound routes from replayed requests.
+///
+/// When a `label` is configured, requests on routes whose metadata sets it to
+/// `true` must carry a nonce in the configured header. A request whose nonce
+/// was already seen on its route within the window fails with a 409 Conflict
+/// response, and a request without a nonce fails with a 400 Bad Request
+/// response. Routes without the label are not protected.
+///
+/// Each route remembers its own nonces, forgetting them once the window
+/// elapses, as measured by a monotonic clock, so that changes to the system
+/// clock neither expire nor extend them. Replays after the window are not
+/// detected, so it should exceed the time for which the application considers
+/// a request valid. Nonces that may yet be replayed are never evicted: instead,
+/// each client may have at most `max_client_nonces` nonces remembered for a
+/// route, beyond which its requests fail with a 429 Too Many Requests
+/// response, so that one client cannot exhaust a route's `max_nonces`. While a
+/// route remembers `max_nonces` nonces, requests with fresh nonces fail with a
+/// 503 Service Unavailable response.
+#[derive(Clone, Debug)]
+pub struct ReplayProtection {
+    label: Option<Arc<str>>,
+    header: HeaderName,
+    window: Duration,
+    max_nonces: usize,
+    max_client_nonces: usize,
+    routes: Arc<Mutex<HashMap<RouteKey, Arc<Mutex<Nonces>>>>>,
+}
+
+/// Identifies a route by its target and labels, which, unlike the route
+/// itself, remain equal when the target's profile is updated.
+type RouteKey = (Addr, Arc<BTreeMap<String, String>>);
+
+#[derive(Clone, Debug)]
+pub struct NewProtectReplay<N> {
+    inner: N,
+    config: ReplayProtection,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProtectReplay<P> {
+    inner: P,
+    protect: Option<(HeaderName, Arc<Mutex<Nonces>>)>,
+}
+
+/// The nonces seen on a route within the window, in the order they were seen,
+/// with the number remembered for each client.
+#[derive(Debug)]
+struct Nonces {
+    window: Duration,
+    max: usize,
+    max_per_client: usize,
+    seen: HashSet<HeaderValue>,
+    by_time: VecDeque<(Instant, HeaderValue, Option<IpAddr>)>,
+    by_client: HashMap<Option<IpAddr>, usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Check {
+    Fresh,
+    Replayed,
+    ClientFull,
+    Full,
+}
+
+/// Nonces longer than this are rejected so that each remembered nonce's size
+/// is bounded.
+const MAX_NONCE_BYTES: usize = 256;
+
+// === impl ReplayProtection ===
+
+impl ReplayProtection {
+    pub fn new(
+        label: Option<String>,
+        header: HeaderName,
+        window: Duration,
+        max_nonces: usize,
+        max_client_nonces: usize,
+    ) -> Self {
+        Self {
+            label: label.map(Arc::from),
+            header,
+            window,
+            max_nonces,
+            max_client_nonces,
+            routes: Default::default(),
+        }
+    }
+
+    /// Returns the route's nonces, which are retained for as long as the
+    /// proxy runs so that they are not forgotten when the route's stack is
+    /// dropped while idle or its profile is updated.
+    fn nonces(&self, route: &dst::Route) -> Arc<Mutex<Nonces>> {
+        let key = (route.target.clone(), route.route.labels().clone());
+        self.routes
+            .lock()
+            .entry(key)
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(Nonces::new(
+                    self.window,
+                    self.max_nonces,
+                    self.max_client_nonces,
+                )))
+            })
+            .clone()
+    }
+
+    fn protects(&self, route: &dst::Route) -> bool {
+        self.label
+            .as_ref()
+            .and_then(|label| route.route.labels().get(&**label))
+            .map_or(false, |v| v.trim().eq_ignore_ascii_case("true"))
+    }
+}
+
+impl Default for ReplayProtection {
+    fn default() -> Self {
+        Self::new(
+            None,
+            HeaderName::from_static("x-request-nonce"),
+            Duration::from_secs(300),
+            100_000,
+            1_000,
+        )
+    }
+}
+
+// === impl NewProtectReplay ===
+
+impl<N> NewProtectReplay<N> {
+    pub fn layer(config: ReplayProtection) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewProtectReplay<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = ProtectReplay<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let protect = if self.config.protects(&route) {
+            Some((self.config.header.clone(), self.config.nonces(&route)))
+        } else {
+            None
+        };
+        ProtectReplay {
+            inner: self.inner.new_service(route),
+            protect,
+        }
+    }
+}
+
+// === impl ProtectReplay ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for ProtectReplay<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    P::Error: Into<Error>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<P::Future, Error>,
+        future::Ready<Result<P::Response, Error>>,
+    >;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        if let Some((header, nonces)) = self.protect.as_ref() {
+            let error = match req.headers().get(header) {
+                Some(nonce) if !nonce.is_empty() && nonce.len() <= MAX_NONCE_BYTES => {
+                    let client = req
+                        .extensions()
+                        .get::<http::ClientHandle>()
+                        .map(|c| c.addr.ip());
+                    match nonces.lock().check(nonce, client, Instant::now()) {
+                        Check::Fresh => None,
+                        Check::Replayed => {
+                            debug!(?nonce, "Request replayed");
+                            Some(HttpError::conflict("request nonce was replayed"))
+                        }
+                        Check::ClientFull => {
+                            debug!(?client, "Too many nonces from client");
+                            Some(HttpError::too_many_requests(
+                                "too many request nonces from client",
+                            ))
+                        }
+                        Check::Full => {
+                            debug!("Too many nonces to protect request from replay");
+                            Some(HttpError::load_shed("too many request nonces"))
+                        }
+                    }
+                }
+                _ => {
+                    debug!(%header, "Request lacks a valid nonce");
+                    Some(HttpError::bad_request("request lacks a valid nonce"))
+                }
+            };
+            if let Some(error) = error {
+                return future::Either::Right(future::err(error.into()));
+            }
+        }
+
+        future::Either::Left(self.inner.proxy(svc, req).err_into())
+    }
+}
+
+// === impl Nonces ===
+
+impl Nonces {
+    fn new(window: Duration, max: usize, max_per_client: usize) -> Self {
+        Self {
+            window,
+            max,
+            max_per_client,
+            seen: HashSet::new(),
+            by_time: VecDeque::new(),
+            by_client: HashMap::new(),
+        }
+    }
+
+    /// Records the client's nonce, unless it was already seen within the
+    /// window.
+    fn check(&mut self, nonce: &HeaderValue, client: Option<IpAddr>, now: Instant) -> Check {
+        while let Some((seen_at, _, _)) = self.by_time.front() {
+            if now.saturating_duration_since(*seen_at) < self.window {
+                break;
+            }
+            if let Some((_, expired, client)) = self.by_time.pop_front() {
+                self.seen.remove(&expired);
+                if let Some(n) = self.by_client.get_mut(&client) {
+                    *n -= 1;
+                    if *n == 0 {
+                        self.by_client.remove(&client);
+                    }
+                }
+            }
+        }
+
+        if self.seen.contains(nonce) {
+            return Check::Replayed;
+        }
+        let client_nonces = self.by_client.get(&client).copied().unwrap_or(0);
+        if client_nonces >= self.max_per_client {
+            return Check::ClientFull;
+        }
+        if self.seen.len() >= self.max {
+            return Check::Full;
+        }
+        self.seen.insert(nonce.clone());
+        self.by_time.push_back((now, nonce.clone(), client));
+        *self.by_client.entry(client).or_default() += 1;
+        Check::Fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::Direction,
+        profiles,
+        svc::{Layer, NewService, ServiceExt},
+    };
+    use std::net::SocketAddr;
+
+    const LABEL: &str = "replay-protection";
+
+    fn route(path: &str, protected: bool) -> dst::Route {
+        let labels = vec![
+            (LABEL.to_string(), protected.to_string()),
+            ("path".to_string(), path.to_string()),
+        ];
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        }
+    }
+
+    async fn send(
+        config: &ReplayProtection,
+        route: dst::Route,
+        client: SocketAddr,
+        nonce: Option<&'static str>,
+    ) -> http::StatusCode {
+        let mut new_proxy = NewProtectReplay::layer(config.clone()).layer(|_: dst::Route| ());
+        let proxy = new_proxy.new_service(route);
+        let mut inner = svc::mk(|_: http::Request<()>| {
+            future::ok::<_, Error>(http::Response::new(http::BoxBody::default()))
+        });
+        let mut req = http::Request::builder();
+        if let Some(nonce) = nonce {
+            req = req.header("x-request-nonce", nonce);
+        }
+        // Sets the client handle as the server would.
+        let (set_client, _closed) = http::SetClientHandle::new(
+            client,
+            svc::mk(|req: http::Request<()>| future::ok::<_, Error>(req)),
+        );
+        let req = set_client.oneshot(req.body(()).unwrap()).await.unwrap();
+        match proxy.proxy(&mut inner, req).await {
+            Ok(rsp) => rsp.status(),
+            Err(e) => e
+                .downcast_ref::<HttpError>()
+                .expect("rejections must be HTTP errors")
+                .status(),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_replayed_nonces() {
+        let config = ReplayProtection {
+            label: Some(LABEL.into()),
+            ..ReplayProtection::default()
+        };
+        let client = ([192, 0, 2, 3], 50000).into();
+        let protected = || route("/a", true);
+        let unprotected = || route("/b", false);
+
+        assert_eq!(
+            send(&config, protected(), client, Some("a")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&config, protected(), client, Some("a")).await,
+            http::StatusCode::CONFLICT
+        );
+        assert_eq!(
+            send(&config, protected(), client, Some("b")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&config, protected(), client, None).await,
+            http::StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(
+            send(&config, unprotected(), client, Some("a")).await,
+            http::StatusCode::OK,
+            "unprotected routes must not check nonces"
+        );
+        assert_eq!(
+            send(&config, unprotected(), client, None).await,
+            http::StatusCode::OK
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn scopes_nonces_to_routes() {
+        let config = ReplayProtection {
+            label: Some(LABEL.into()),
+            ..ReplayProtection::default()
+        };
+        let client = ([192, 0, 2, 3], 50000).into();
+
+        assert_eq!(
+            send(&config, route("/a", true), client, Some("a")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&config, route("/b", true), client, Some("a")).await,
+            http::StatusCode::OK,
+            "a nonce seen on one route must not be rejected on another"
+        );
+        assert_eq!(
+            send(&config, route("/a", true), client, Some("a")).await,
+            http::StatusCode::CONFLICT
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_nonces_per_client() {
+        let config = ReplayProtection::new(
+            Some(LABEL.to_string()),
+            HeaderName::from_static("x-request-nonce"),
+            Duration::from_secs(300),
+            3,
+            2,
+        );
+        let a = ([192, 0, 2, 3], 50000).into();
+        let b = ([192, 0, 2, 4], 50000).into();
+        let protected = || route("/a", true);
+
+        assert_eq!(
+            send(&config, protected(), a, Some("1")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&config, protected(), a, Some("2")).await,
+            http::StatusCode::OK
+        );
+        assert_eq!(
+            send(&config, protected(), a, Some("3")).await,
+            http::StatusCode::TOO_MANY_REQUESTS,
+            "a client must not exceed its share of nonces"
+        );
+        assert_eq!(
+            send(&config, protected(), b, Some("3")).await,
+            http::StatusCode::OK,
+            "other clients must not be denied"
+        );
+        assert_eq!(
+            send(&config, protected(), b, Some("4")).await,
+            http::StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn forgets_nonces_after_window() {
+        let mut nonces = Nonces::new(Duration::from_secs(10), 2, 2);
+        let t0 = Instant::now();
+        let client = Some([192, 0, 2, 3].into());
+        let a = HeaderValue::from_static("a");
+        let b = HeaderValue::from_static("b");
+        let c = HeaderValue::from_static("c");
+
+        assert_eq!(nonces.check(&a, client, t0), Check::Fresh);
+        assert_eq!(
+            nonces.check(&b, None, t0 + Duration::from_secs(5)),
+            Check::Fresh
+        );
+        assert_eq!(
+            nonces.check(&c, None, t0 + Duration::from_secs(5)),
+            Check::Full,
+            "nonces must not be evicted before the window elapses"
+        );
+        assert_eq!(
+            nonces.check(&a, None, t0 + Duration::from_secs(9)),
+            Check::Replayed
+        );
+
+        // Once the first nonce expires, there is room for another.
+        assert_eq!(
+            nonces.check(&c, None, t0 + Duration::from_secs(10)),
+            Check::Fresh
+        );
+        assert_eq!(
+            nonces.check(&a, None, t0 + Duration::from_secs(11)),
+            Check::ClientFull
+        );
+        assert_eq!(
+            nonces.check(&a, None, t0 + Duration::from_secs(15)),
+            Check::Fresh
+        );
+        assert_eq!(nonces.seen.len(), 2);
+        assert_eq!(nonces.by_client.get(&client), None);
+    }
+}
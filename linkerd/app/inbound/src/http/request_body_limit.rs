@@ -0,0 +1,494 @@
+use bytes::Buf;
+use futures::{future, ready, TryFuture};
+use linkerd_app_core::{
+    dst,
+    errors::RequestBodyTooLarge,
+    proxy::http::{self, HttpBody},
+    svc::{self, stack::Proxy},
+    Error,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tracing::{debug, warn};
+
+/// Limits the size of inbound request bodies.
+///
+/// Requests whose bodies exceed the `default` number of bytes fail with a 413
+/// Payload Too Large response. When a `label` is configured, a route whose
+/// metadata sets it to a number of bytes overrides the default for its
+/// requests. Bodies are counted as they are streamed to the application, so
+/// they are never buffered: once a body exceeds its limit, the request to
+/// the application is aborted. Requests whose `Content-Length` exceeds the
+/// limit fail before any of their body is forwarded and, unless their route
+/// may override the limit, before they are forwarded at all. When no limit is
+/// configured, requests are not inspected.
+#[derive(Clone, Debug, Default)]
+pub struct RequestBodyLimits {
+    default: Option<u64>,
+    label: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LimitRequestBody<S> {
+    inner: S,
+    limits: RequestBodyLimits,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct LimitRequestBodyFuture<F> {
+    #[pin]
+    inner: F,
+    limit: BodyLimit,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewOverrideBodyLimit<N> {
+    inner: N,
+    limits: RequestBodyLimits,
+}
+
+#[derive(Clone, Debug)]
+pub struct OverrideBodyLimit<P> {
+    inner: P,
+    max: Option<u64>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct LimitedBody<B> {
+    #[pin]
+    inner: B,
+    content_length: Option<u64>,
+    received: u64,
+    limit: BodyLimit,
+}
+
+/// The limit of a single request's body, which is shared by the body and the
+/// request's extensions so that it may be overridden as the request is
+/// routed.
+#[derive(Clone, Debug)]
+struct BodyLimit(Arc<Shared>);
+
+#[derive(Debug)]
+struct Shared {
+    max: AtomicU64,
+    exceeded: AtomicBool,
+}
+
+// === impl RequestBodyLimits ===
+
+impl RequestBodyLimits {
+    pub fn new(default: Option<u64>, label: Option<String>) -> Self {
+        Self {
+            default,
+            label: label.map(Arc::from),
+        }
+    }
+
+    fn route_limit(&self, route: &dst::Route) -> Option<u64> {
+        let value = route.route.labels().get(&**self.label.as_ref()?)?;
+        match value.trim().parse() {
+            Ok(max) => Some(max),
+            Err(_) => {
+                warn!(%value, "Ignoring invalid request body limit");
+                None
+            }
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.default.is_some() || self.label.is_some()
+    }
+}
+
+// === impl LimitRequestBody ===
+
+impl<S> LimitRequestBody<S> {
+    /// Limits request bodies, if any limit is configured.
+    pub fn layer(
+        limits: RequestBodyLimits,
+    ) -> impl svc::Layer<S, Service = svc::Either<Self, S>> + Clone {
+        svc::layer::mk(move |inner| {
+            if !limits.is_enabled() {
+                return svc::Either::B(inner);
+            }
+            svc::Either::A(Self {
+                inner,
+                limits: limits.clone(),
+            })
+        })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for LimitRequestBody<S>
+where
+    S: svc::Service<http::Request<http::BoxBody>>,
+    S::Error: Into<Error>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send + 'static,
+    B::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::Ready<Result<S::Response, Error>>,
+        LimitRequestBodyFuture<S::Future>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        let content_length = parts
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+
+        // Requests that are known to exceed the limit are failed without
+        // being forwarded, unless their route may override the limit.
+        if let (Some(len), Some(max), None) = (
+            content_length,
+            self.limits.default,
+            self.limits.label.as_ref(),
+        ) {
+            if len > max {
+                debug!(max, "Request body length exceeds limit");
+                return future::Either::Left(future::err(
+                    RequestBodyTooLarge { limit: max }.into(),
+                ));
+            }
+        }
+
+        let limit = BodyLimit::new(self.limits.default.unwrap_or(u64::MAX));
+        parts.extensions.insert(limit.clone());
+        let body = http::BoxBody::new(LimitedBody {
+            inner: body,
+            content_length,
+            received: 0,
+            limit: limit.clone(),
+        });
+        future::Either::Right(LimitRequestBodyFuture {
+            inner: self.inner.call(http::Request::from_parts(parts, body)),
+            limit,
+        })
+    }
+}
+
+// === impl LimitRequestBodyFuture ===
+
+impl<F> Future for LimitRequestBodyFuture<F>
+where
+    F: TryFuture,
+    F::Error: Into<Error>,
+{
+    type Output = Result<F::Ok, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.try_poll(cx)).map_err(Into::into);
+        // The application's response is discarded if the request's body was
+        // truncated, since it could not have processed the entire request.
+        if this.limit.is_exceeded() {
+            return Poll::Ready(Err(this.limit.error().into()));
+        }
+        Poll::Ready(res)
+    }
+}
+
+// === impl NewOverrideBodyLimit ===
+
+impl<N> NewOverrideBodyLimit<N> {
+    pub fn layer(limits: RequestBodyLimits) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            limits: limits.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewOverrideBodyLimit<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = OverrideBodyLimit<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let max = self.limits.route_limit(&route);
+        OverrideBodyLimit {
+            inner: self.inner.new_service(route),
+            max,
+        }
+    }
+}
+
+// === impl OverrideBodyLimit ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for OverrideBodyLimit<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        if let Some(max) = self.max {
+            if let Some(limit) = req.extensions().get::<BodyLimit>() {
+                debug!(max, "Overriding request body limit for route");
+                limit.0.max.store(max, Ordering::Release);
+            }
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl LimitedBody ===
+
+impl<B> HttpBody for LimitedBody<B>
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let max = this.limit.max();
+        let length = this
+            .content_length
+            .unwrap_or_else(|| this.inner.size_hint().lower());
+        if *this.received == 0 && length > max {
+            debug!(max, "Request body length exceeds limit");
+            return Poll::Ready(Some(Err(this.limit.exceed().into())));
+        }
+
+        let data = match ready!(this.inner.poll_data(cx)) {
+            Some(Ok(data)) => data,
+            Some(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
+            None => return Poll::Ready(None),
+        };
+        *this.received += data.remaining() as u64;
+        if *this.received > max {
+            debug!(max, "Request body exceeds limit");
+            return Poll::Ready(Some(Err(this.limit.exceed().into())));
+        }
+        Poll::Ready(Some(Ok(data)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx).map_err(Into::into)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// === impl BodyLimit ===
+
+impl BodyLimit {
+    fn new(max: u64) -> Self {
+        Self(Arc::new(Shared {
+            max: AtomicU64::new(max),
+            exceeded: AtomicBool::new(false),
+        }))
+    }
+
+    fn max(&self) -> u64 {
+        self.0.max.load(Ordering::Acquire)
+    }
+
+    fn exceed(&self) -> RequestBodyTooLarge {
+        self.0.exceeded.store(true, Ordering::Release);
+        self.error()
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.0.exceeded.load(Ordering::Acquire)
+    }
+
+    fn error(&self) -> RequestBodyTooLarge {
+        RequestBodyTooLarge { limit: self.max() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use linkerd_app_core::{
+        errors,
+        metrics::Direction,
+        profiles,
+        svc::{Layer, NewService, ServiceExt},
+    };
+
+    const LABEL: &str = "max-request-body-bytes";
+
+    fn route(max: Option<&str>) -> dst::Route {
+        let labels = max.map(|m| (LABEL.to_string(), m.to_string()));
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        }
+    }
+
+    /// Sends a request with the given body chunks through the server layer
+    /// and, if `route_max` is set, a route that overrides the limit. The
+    /// application reads the entire body before responding.
+    async fn send(
+        limits: RequestBodyLimits,
+        route_max: Option<&str>,
+        content_length: Option<usize>,
+        chunks: Vec<&'static str>,
+    ) -> Result<Vec<Bytes>, Error> {
+        let override_limit = NewOverrideBodyLimit::layer(limits.clone())
+            .layer(|_: dst::Route| ())
+            .new_service(route(route_max));
+        let app = svc::mk(|req: http::Request<http::BoxBody>| async move {
+            let mut body = req.into_body();
+            let mut received = Vec::new();
+            while let Some(data) = body.data().await {
+                let mut data = data?;
+                received.push(data.copy_to_bytes(data.remaining()));
+            }
+            Ok::<_, Error>(received)
+        });
+        let svc = LimitRequestBody::layer(limits).layer(svc::mk(
+            move |req: http::Request<http::BoxBody>| {
+                let mut app = app.clone();
+                override_limit.proxy(&mut app, req)
+            },
+        ));
+
+        let stream = futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>));
+        let mut req = http::Request::builder();
+        if let Some(len) = content_length {
+            req = req.header(http::header::CONTENT_LENGTH, len);
+        }
+        let req = req
+            .body(http::BoxBody::new(hyper::Body::wrap_stream(stream)))
+            .unwrap();
+        svc.oneshot(req).await
+    }
+
+    fn too_large(res: Result<Vec<Bytes>, Error>) -> u64 {
+        let error = res.expect_err("request must fail");
+        error
+            .downcast_ref::<RequestBodyTooLarge>()
+            .unwrap_or_else(|| panic!("unexpected error: {}", error))
+            .limit
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn streams_bodies_within_limit() {
+        let limits = RequestBodyLimits::new(Some(10), None);
+        let received = send(limits, None, None, vec!["hello", "world"])
+            .await
+            .unwrap();
+        assert_eq!(
+            received,
+            vec!["hello", "world"],
+            "chunks must not be buffered"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn aborts_bodies_that_exceed_limit() {
+        let limits = RequestBodyLimits::new(Some(8), None);
+        let res = send(limits.clone(), None, None, vec!["hello", "world"]).await;
+        assert_eq!(too_large(res), 8);
+
+        // Bodies whose length is known are not forwarded at all.
+        let res = send(limits, None, Some(10), vec!["hello", "world"]).await;
+        assert_eq!(too_large(res), 8);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_override_limit() {
+        let limits = RequestBodyLimits::new(Some(8), Some(LABEL.to_string()));
+        let received = send(limits.clone(), Some("16"), None, vec!["hello", "world"])
+            .await
+            .unwrap();
+        assert_eq!(received.len(), 2);
+
+        let res = send(limits.clone(), Some("4"), None, vec!["hello"]).await;
+        assert_eq!(too_large(res), 4);
+
+        let res = send(limits, Some("bogus"), None, vec!["hello", "world"]).await;
+        assert_eq!(too_large(res), 8, "invalid labels must be ignored");
+
+        // Routes may set limits without a default.
+        let limits = RequestBodyLimits::new(None, Some(LABEL.to_string()));
+        let res = send(limits.clone(), Some("4"), None, vec!["hello"]).await;
+        assert_eq!(too_large(res), 4);
+        assert!(send(limits, None, None, vec!["hello", "world"])
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn responds_with_payload_too_large() {
+        let limits = RequestBodyLimits::new(Some(4), None);
+        let svc = || {
+            // The application responds without reading the request body.
+            let app = svc::mk(|_: http::Request<http::BoxBody>| {
+                future::ok::<_, Error>(http::Response::new(hyper::Body::empty()))
+            });
+            let svc = errors::layer(false, false, errors::proxy_error_header(), true)
+                .layer(LimitRequestBody::layer(limits.clone()).layer(app));
+            let (svc, _) = http::SetClientHandle::new(([192, 0, 2, 3], 50000).into(), svc);
+            svc
+        };
+
+        let req = http::Request::builder()
+            .header(http::header::CONTENT_LENGTH, 11)
+            .body(http::BoxBody::new(hyper::Body::from("hello world")))
+            .unwrap();
+        let rsp = svc().oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = http::Request::builder()
+            .body(http::BoxBody::new(hyper::Body::from("hi")))
+            .unwrap();
+        let rsp = svc().oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+    }
+
+    #[test]
+    fn skipped_without_limits() {
+        let layer = LimitRequestBody::layer(RequestBodyLimits::default());
+        assert!(matches!(layer.layer(()), svc::Either::B(())));
+
+        let layer = LimitRequestBody::layer(RequestBodyLimits::new(Some(4), None));
+        assert!(matches!(layer.layer(()), svc::Either::A(_)));
+    }
+}
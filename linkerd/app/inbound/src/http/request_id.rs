@@ -0,0 +1,168 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    proxy::http::{self, HeaderName, HeaderValue},
+    svc,
+};
+use rand::Rng;
+use std::task::{Context, Poll};
+use tracing::{debug_span, Instrument};
+
+/// Ensures that each request carries a request ID in the configured header.
+///
+/// Requests that already have an ID are not modified. Otherwise, a random
+/// (version 4) UUID is generated. The request's ID is set on its tracing span
+/// and on its tap events, and is echoed on its response.
+#[derive(Clone, Debug)]
+pub struct RequestId<S> {
+    inner: S,
+    header: Option<HeaderName>,
+}
+
+/// A request extension that holds the request's ID, so that it may be
+/// reported by tap.
+#[derive(Clone, Debug)]
+pub struct RequestIdValue(HeaderValue);
+
+type Echo<B> = Box<dyn FnOnce(http::Response<B>) -> http::Response<B> + Send>;
+
+// === impl RequestIdValue ===
+
+impl RequestIdValue {
+    pub fn to_str(&self) -> Option<&str> {
+        self.0.to_str().ok()
+    }
+}
+
+// === impl RequestId ===
+
+impl<S> RequestId<S> {
+    pub fn layer(header: Option<HeaderName>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            header: header.clone(),
+        })
+    }
+}
+
+impl<S, A, B> svc::Service<http::Request<A>> for RequestId<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = future::Either<
+        S::Future,
+        tracing::instrument::Instrumented<future::MapOk<S::Future, Echo<B>>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        let header = match self.header.as_ref() {
+            Some(header) => header.clone(),
+            None => return future::Either::Left(self.inner.call(req)),
+        };
+
+        let id = match req.headers().get(&header) {
+            Some(id) => id.clone(),
+            None => {
+                let id = generate();
+                req.headers_mut().insert(header.clone(), id.clone());
+                id
+            }
+        };
+
+        req.extensions_mut().insert(RequestIdValue(id.clone()));
+        let span = debug_span!("request", id = ?id);
+        let echo: Echo<B> = Box::new(move |mut rsp: http::Response<B>| {
+            rsp.headers_mut().entry(header).or_insert(id);
+            rsp
+        });
+        future::Either::Right(self.inner.call(req).map_ok(echo).instrument(span))
+    }
+}
+
+/// Generates a random UUID, formatted as described in RFC 4122.
+fn generate() -> HeaderValue {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    // Set the version (4) and variant (RFC 4122) bits.
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let mut id = String::with_capacity(36);
+    for (i, b) in bytes.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            id.push('-');
+        }
+        id.push_str(&format!("{:02x}", b));
+    }
+    HeaderValue::from_str(&id).expect("UUIDs must be valid header values")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    const X_REQUEST_ID: &str = "x-request-id";
+    const APP_REQUEST_ID: &str = "x-app-request-id";
+
+    /// Sends a request and returns the request ID observed by the application
+    /// and the one set on the response.
+    async fn send(req: http::Request<()>) -> (Option<HeaderValue>, Option<HeaderValue>) {
+        // The application echoes the ID it received in another header.
+        let app = svc::mk(|req: http::Request<()>| {
+            let mut rsp = http::Response::new(());
+            if let Some(id) = req.headers().get(X_REQUEST_ID) {
+                rsp.headers_mut().insert(APP_REQUEST_ID, id.clone());
+            }
+            // The ID must also be available to tap.
+            let ext = req.extensions().get::<RequestIdValue>();
+            assert_eq!(
+                ext.and_then(RequestIdValue::to_str),
+                req.headers()
+                    .get(X_REQUEST_ID)
+                    .and_then(|id| id.to_str().ok()),
+            );
+            future::ok::<_, std::convert::Infallible>(rsp)
+        });
+        let svc = RequestId {
+            inner: app,
+            header: Some(HeaderName::from_static(X_REQUEST_ID)),
+        };
+        let rsp = svc.oneshot(req).await.unwrap();
+        let app_id = rsp.headers().get(APP_REQUEST_ID).cloned();
+        let rsp_id = rsp.headers().get(X_REQUEST_ID).cloned();
+        (app_id, rsp_id)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn generates_missing_ids() {
+        let req = http::Request::builder().body(()).unwrap();
+        let (app_id, rsp_id) = send(req).await;
+        let id = app_id.expect("request must have an ID");
+        assert_eq!(rsp_id.as_ref(), Some(&id));
+
+        let id = id.to_str().unwrap();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4", "must be a version 4 UUID");
+
+        let req = http::Request::builder().body(()).unwrap();
+        let (other, _) = send(req).await;
+        assert_ne!(other.unwrap(), id, "IDs must be unique");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn preserves_existing_ids() {
+        let req = http::Request::builder()
+            .header(X_REQUEST_ID, "abc123")
+            .body(())
+            .unwrap();
+        let (app_id, rsp_id) = send(req).await;
+        assert_eq!(app_id.unwrap(), "abc123");
+        assert_eq!(rsp_id.unwrap(), "abc123");
+    }
+}
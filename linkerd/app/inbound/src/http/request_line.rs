@@ -0,0 +1,174 @@
+use futures::future;
+use linkerd_app_core::{errors::HttpError, proxy::http, svc, Error};
+use std::task::{Context, Poll};
+use tracing::debug;
+
+/// Rejects HTTP/1 requests whose request line exceeds a configured length.
+///
+/// The request line is reconstructed from the parsed request: the method, the
+/// request target, and the protocol version, separated by single spaces. When
+/// the request target alone exceeds the limit, the request fails with a 414 URI
+/// Too Long; otherwise an oversized request line fails with a 400 Bad Request.
+///
+/// HTTP/2 requests have no request line and are not limited. This must be
+/// applied before HTTP/2 requests are downgraded to HTTP/1.
+///
+/// Requests are not limited unless a maximum is configured, since hyper already
+/// bounds the size of the request head it parses.
+#[derive(Clone, Debug)]
+pub struct RequestLineLimit<S> {
+    inner: S,
+    max_bytes: Option<usize>,
+}
+
+// === impl RequestLineLimit ===
+
+impl<S> RequestLineLimit<S> {
+    pub fn layer(max_bytes: Option<usize>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, max_bytes })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for RequestLineLimit<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        use futures::TryFutureExt;
+
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return future::Either::Left(self.inner.call(req).err_into()),
+        };
+        let version_len = match req.version() {
+            http::Version::HTTP_10 | http::Version::HTTP_11 => "HTTP/1.1".len(),
+            _ => return future::Either::Left(self.inner.call(req).err_into()),
+        };
+
+        let target_len = request_target_len(req.uri());
+        if target_len > max_bytes {
+            debug!(target_len, max = max_bytes, "Request target is too long");
+            return future::Either::Right(future::err(
+                HttpError::uri_too_long("request target exceeds the request line limit").into(),
+            ));
+        }
+
+        let line_len = req.method().as_str().len() + 1 + target_len + 1 + version_len;
+        if line_len > max_bytes {
+            debug!(line_len, max = max_bytes, "Request line is too long");
+            return future::Either::Right(future::err(
+                HttpError::bad_request("request line is too long").into(),
+            ));
+        }
+
+        future::Either::Left(self.inner.call(req).err_into())
+    }
+}
+
+/// Returns the length of the request target from which `uri` was parsed.
+fn request_target_len(uri: &http::uri::Uri) -> usize {
+    let mut len = uri.path_and_query().map_or(0, |pq| pq.as_str().len());
+    if let Some(authority) = uri.authority() {
+        len += authority.as_str().len();
+        if let Some(scheme) = uri.scheme() {
+            // Include the `://` separator.
+            len += scheme.as_str().len() + 3;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    async fn send(max_bytes: usize, req: http::Request<()>) -> Result<(), Error> {
+        let inner = svc::mk(|_: http::Request<()>| future::ok::<_, Error>(()));
+        RequestLineLimit {
+            inner,
+            max_bytes: Some(max_bytes),
+        }
+        .oneshot(req)
+        .await
+    }
+
+    fn status(error: Error) -> http::StatusCode {
+        error
+            .downcast_ref::<HttpError>()
+            .expect("error must be an HttpError")
+            .status()
+    }
+
+    fn request(method: &str, uri: &str, version: http::Version) -> http::Request<()> {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .version(version)
+            .body(())
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_http1_request_lines() {
+        // "GET /aaaa HTTP/1.1" is 18 bytes long.
+        let path = "/aaaa";
+        send(18, request("GET", path, http::Version::HTTP_11))
+            .await
+            .expect("request line fits within the limit");
+        let error = send(17, request("GET", path, http::Version::HTTP_11))
+            .await
+            .expect_err("request line exceeds the limit");
+        assert_eq!(status(error), http::StatusCode::BAD_REQUEST);
+
+        // A long method is rejected even though the target fits.
+        let error = send(18, request("GETTTTT", path, http::Version::HTTP_10))
+            .await
+            .expect_err("request line exceeds the limit");
+        assert_eq!(status(error), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_long_targets_as_uri_too_long() {
+        let error = send(
+            20,
+            request("GET", "http://foo.example.com/aaaa", http::Version::HTTP_11),
+        )
+        .await
+        .expect_err("request target exceeds the limit");
+        assert_eq!(status(error), http::StatusCode::URI_TOO_LONG);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn unlimited_by_default() {
+        let inner = svc::mk(|_: http::Request<()>| future::ok::<_, Error>(()));
+        let target = format!("/{}", "a".repeat(64 * 1024));
+        RequestLineLimit {
+            inner,
+            max_bytes: None,
+        }
+        .oneshot(request("GET", &target, http::Version::HTTP_11))
+        .await
+        .expect("requests must not be limited");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn ignores_http2() {
+        send(1, request("GET", "/aaaa", http::Version::HTTP_2))
+            .await
+            .expect("HTTP/2 requests are not limited");
+    }
+}
@@ -0,0 +1,75 @@
+use futures::future;
+use linkerd_app_core::{errors::HttpError, proxy::http, svc, Error};
+use std::task::{Context, Poll};
+use tracing::debug;
+
+/// Determines how requests that have neither a URI authority nor a `Host`
+/// header are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MissingAuthority {
+    /// Such requests fail with a 400 Bad Request.
+    Reject,
+
+    /// The authority is derived from the connection's original destination
+    /// address.
+    UseTarget,
+}
+
+#[derive(Clone, Debug)]
+pub struct RequireAuthority<S> {
+    inner: S,
+    missing: MissingAuthority,
+}
+
+// === impl MissingAuthority ===
+
+impl Default for MissingAuthority {
+    fn default() -> Self {
+        Self::UseTarget
+    }
+}
+
+// === impl RequireAuthority ===
+
+impl<S> RequireAuthority<S> {
+    pub fn layer(missing: MissingAuthority) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, missing })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for RequireAuthority<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        use futures::TryFutureExt;
+
+        // CONNECT requests always carry an authority-form URI and `OPTIONS *`
+        // requests still require a `Host` header, so neither needs special
+        // handling here.
+        if self.missing == MissingAuthority::Reject
+            && req.uri().authority().is_none()
+            && !req.headers().contains_key(http::header::HOST)
+        {
+            debug!(method = %req.method(), uri = %req.uri(), "Request is missing an authority");
+            return future::Either::Right(future::err(
+                HttpError::bad_request("request is missing an authority").into(),
+            ));
+        }
+
+        future::Either::Left(self.inner.call(req).err_into())
+    }
+}
@@ -0,0 +1,233 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    errors::HttpError,
+    proxy::http::{self, HttpBody},
+    svc, Error,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time};
+use tracing::debug;
+
+/// Fails requests with a 504 Gateway Timeout if the application does not
+/// respond with headers within a deadline.
+///
+/// The deadline starts once the request body has been sent to the
+/// application, so that slow uploads are not mistaken for slow responses.
+/// Once the response headers have been received, the response body may be
+/// streamed for any amount of time. This is distinct from route timeouts,
+/// which bound the time taken by the whole request including retries, and
+/// from the connect timeout, which fails separately. If the client has no
+/// connection to the application when the request is dispatched, the
+/// deadline includes the time taken to establish one.
+#[derive(Clone, Debug)]
+pub struct ResponseHeadersTimeout<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+/// A request body that notifies the timeout once it has been sent (or
+/// dropped).
+#[pin_project]
+#[derive(Debug)]
+pub struct RequestBody<B> {
+    #[pin]
+    inner: B,
+    sent: Option<oneshot::Sender<()>>,
+}
+
+type TimeoutFuture<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'static>>;
+
+// === impl ResponseHeadersTimeout ===
+
+impl<S> ResponseHeadersTimeout<S> {
+    pub fn layer(timeout: Option<Duration>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, timeout })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for ResponseHeadersTimeout<S>
+where
+    S: svc::Service<http::Request<RequestBody<B>>>,
+    S::Response: Send + 'static,
+    S::Error: Into<Error> + Send + 'static,
+    S::Future: Send + 'static,
+    B: HttpBody,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<future::ErrInto<S::Future, Error>, TimeoutFuture<S::Response>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => {
+                let req = req.map(|inner| RequestBody::new(inner).0);
+                return future::Either::Left(self.inner.call(req).err_into());
+            }
+        };
+
+        let (head, body) = req.into_parts();
+        let (body, rx) = RequestBody::new(body);
+        let rsp = self.inner.call(http::Request::from_parts(head, body));
+
+        future::Either::Right(Box::pin(async move {
+            // The application may respond before it has read the whole
+            // request body, in which case there is nothing to time out.
+            futures::pin_mut!(rsp);
+            let rsp = match future::select(rsp, rx).await {
+                future::Either::Left((rsp, _)) => return rsp.map_err(Into::into),
+                future::Either::Right((_, rsp)) => rsp,
+            };
+
+            match time::timeout(timeout, rsp).await {
+                Ok(rsp) => rsp.map_err(Into::into),
+                Err(_) => {
+                    debug!(?timeout, "Application did not send response headers");
+                    Err(HttpError::gateway_timeout("response headers timed out").into())
+                }
+            }
+        }))
+    }
+}
+
+// === impl RequestBody ===
+
+impl<B: HttpBody> RequestBody<B> {
+    /// Wraps a request body, returning a receiver that completes once it has
+    /// been sent.
+    pub(super) fn new(inner: B) -> (Self, oneshot::Receiver<()>) {
+        let (tx, rx) = oneshot::channel();
+        // An empty body has already been sent.
+        let sent = if inner.is_end_stream() {
+            None
+        } else {
+            Some(tx)
+        };
+        (Self { inner, sent }, rx)
+    }
+}
+
+impl<B: Default> Default for RequestBody<B> {
+    fn default() -> Self {
+        Self {
+            inner: B::default(),
+            sent: None,
+        }
+    }
+}
+
+impl<B: HttpBody> HttpBody for RequestBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = futures::ready!(this.inner.poll_data(cx));
+        if !matches!(data, Some(Ok(_))) || this.inner.is_end_stream() {
+            this.sent.take();
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = futures::ready!(this.inner.poll_trailers(cx));
+        this.sent.take();
+        Poll::Ready(trailers)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Sends a request to an application that reads the request body and
+    /// responds after `delay`.
+    async fn send(body: hyper::Body, delay: Duration) -> Result<http::Response<()>, Error> {
+        let app = svc::mk(
+            move |req: http::Request<RequestBody<hyper::Body>>| async move {
+                hyper::body::to_bytes(req.into_body()).await?;
+                time::sleep(delay).await;
+                Ok::<_, Error>(http::Response::new(()))
+            },
+        );
+        ResponseHeadersTimeout {
+            inner: app,
+            timeout: Some(TIMEOUT),
+        }
+        .oneshot(http::Request::new(body))
+        .await
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn fails_slow_response_headers() {
+        let error = send(hyper::Body::empty(), TIMEOUT * 2)
+            .await
+            .expect_err("response headers must time out");
+        let error = error
+            .downcast_ref::<HttpError>()
+            .expect("error must be an HttpError");
+        assert_eq!(error.status(), http::StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn allows_timely_response_headers() {
+        send(hyper::Body::empty(), TIMEOUT / 2)
+            .await
+            .expect("response headers arrive within the deadline");
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn starts_deadline_after_request_body() {
+        // The client takes longer than the deadline to send its body.
+        let (mut tx, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..4 {
+                time::sleep(TIMEOUT / 2).await;
+                tx.send_data("hello".into()).await.unwrap();
+            }
+        });
+        send(body, TIMEOUT / 2)
+            .await
+            .expect("the deadline must not include the time to send the body");
+
+        let (mut tx, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            time::sleep(TIMEOUT * 2).await;
+            tx.send_data("hello".into()).await.unwrap();
+        });
+        send(body, TIMEOUT * 2)
+            .await
+            .expect_err("response headers must time out after the body is sent");
+    }
+}
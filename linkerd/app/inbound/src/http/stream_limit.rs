@@ -0,0 +1,244 @@
+use futures::{future, TryFuture, TryFutureExt};
+use linkerd_app_core::{
+    proxy::http::{self, HttpBody},
+    svc, Error,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, trace};
+
+/// Limits the number of HTTP/2 streams that may be active at once across all
+/// inbound connections.
+///
+/// This complements per-connection stream limits: many connections may
+/// each open fewer streams than a per-connection limit while together
+/// exhausting the proxy's resources. When the limit is reached, new streams
+/// are refused with `REFUSED_STREAM`, so that clients know the request was
+/// not processed and may safely retry it. Streams are refused rather than
+/// queued, so that a connection with many pending streams cannot delay the
+/// streams of other connections once capacity is available.
+///
+/// A stream holds its share of the limit until its response completes, fails,
+/// or is dropped (e.g. because the client reset the stream).
+#[derive(Clone, Debug, Default)]
+pub struct H2StreamLimit(Option<Arc<Semaphore>>);
+
+#[derive(Clone, Debug)]
+pub struct LimitH2Streams<S> {
+    inner: S,
+    limit: H2StreamLimit,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: future::ErrInto<F, Error>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Holds a stream's permit until the response body completes.
+#[pin_project]
+#[derive(Debug)]
+pub struct PermitBody<B> {
+    #[pin]
+    inner: B,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+// === impl H2StreamLimit ===
+
+impl H2StreamLimit {
+    /// Permits at most `max` concurrent HTTP/2 streams. Each clone of the
+    /// returned limit shares the same streams.
+    pub fn new(max: usize) -> Self {
+        Self(Some(Arc::new(Semaphore::new(max))))
+    }
+}
+
+// === impl LimitH2Streams ===
+
+impl<S> LimitH2Streams<S> {
+    pub fn layer(limit: H2StreamLimit) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            limit: limit.clone(),
+        })
+    }
+}
+
+impl<S, B> svc::Service<http::Request<B>> for LimitH2Streams<S>
+where
+    S: svc::Service<http::Request<B>, Response = http::Response<http::BoxBody>>,
+    S::Error: Into<Error>,
+{
+    type Response = http::Response<http::BoxBody>;
+    type Error = Error;
+    type Future =
+        future::Either<ResponseFuture<S::Future>, future::Ready<Result<Self::Response, Error>>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let permit = match self.limit.0.as_ref() {
+            Some(streams) if req.version() == http::Version::HTTP_2 => {
+                match streams.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => {
+                        debug!("Refusing stream; too many concurrent HTTP/2 streams");
+                        return future::Either::Right(future::err(
+                            h2::Error::from(h2::Reason::REFUSED_STREAM).into(),
+                        ));
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        future::Either::Left(ResponseFuture {
+            inner: self.inner.call(req).err_into::<Error>(),
+            permit,
+        })
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: TryFuture<Ok = http::Response<http::BoxBody>>,
+    F::Error: Into<Error>,
+{
+    type Output = Result<http::Response<http::BoxBody>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let rsp = futures::ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(match this.permit.take() {
+            Some(permit) => rsp.map(|inner| {
+                http::BoxBody::new(PermitBody {
+                    inner,
+                    permit: Some(permit),
+                })
+            }),
+            None => rsp,
+        }))
+    }
+}
+
+// === impl PermitBody ===
+
+impl<B: HttpBody> HttpBody for PermitBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = futures::ready!(this.inner.poll_data(cx));
+        if !matches!(data, Some(Ok(_))) {
+            release(this.permit);
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = futures::ready!(this.inner.poll_trailers(cx));
+        release(this.permit);
+        Poll::Ready(trailers)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+fn release(permit: &mut Option<OwnedSemaphorePermit>) {
+    if permit.take().is_some() {
+        trace!("Stream complete");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        proxy::http::HasH2Reason,
+        svc::{Layer, Service, ServiceExt},
+    };
+
+    fn request() -> http::Request<()> {
+        http::Request::builder()
+            .version(http::Version::HTTP_2)
+            .body(())
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_streams_across_connections() {
+        let limit = H2StreamLimit::new(2);
+        let new_conn = || {
+            LimitH2Streams::layer(limit.clone()).layer(svc::mk(|_: http::Request<()>| {
+                future::ok::<_, Error>(http::Response::new(http::BoxBody::new(hyper::Body::from(
+                    "hello",
+                ))))
+            }))
+        };
+        let mut conn0 = new_conn();
+        let mut conn1 = new_conn();
+
+        let rsp0 = conn0.ready().await.unwrap().call(request()).await.unwrap();
+        let rsp1 = conn1.ready().await.unwrap().call(request()).await.unwrap();
+
+        for conn in &mut [&mut conn0, &mut conn1] {
+            let error = conn
+                .ready()
+                .await
+                .unwrap()
+                .call(request())
+                .await
+                .unwrap_err();
+            assert_eq!(error.h2_reason(), Some(h2::Reason::REFUSED_STREAM));
+        }
+
+        let rsp = conn0
+            .ready()
+            .await
+            .unwrap()
+            .call(http::Request::new(()))
+            .await;
+        assert!(rsp.is_ok(), "HTTP/1 requests must not be limited");
+
+        // Streams are released once their responses complete...
+        let mut body = rsp0.into_body();
+        while body.data().await.is_some() {}
+        let rsp2 = conn1.ready().await.unwrap().call(request()).await.unwrap();
+        assert!(conn0.ready().await.unwrap().call(request()).await.is_err());
+
+        // ...or are dropped.
+        drop(rsp1);
+        assert!(conn0.ready().await.unwrap().call(request()).await.is_ok());
+        drop(rsp2);
+    }
+}
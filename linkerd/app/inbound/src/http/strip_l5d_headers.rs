@@ -0,0 +1,180 @@
+use futures::{future, prelude::*, ready};
+use linkerd_app_core::{
+    identity,
+    proxy::http::{self, HeaderName},
+    svc::{self, Param},
+};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tracing::trace;
+
+/// Determines which clients' responses have the proxy's `l5d-*` headers, such
+/// as `l5d-proxy-error`, removed. The proxy error header is removed even when
+/// it has been configured with another name.
+///
+/// These headers describe the proxy's internals, which are useful when
+/// debugging meshed traffic but should not be exposed to external clients.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StripL5dHeaders {
+    /// `l5d-*` headers are returned to all clients.
+    Never,
+
+    /// `l5d-*` headers are removed from responses to clients that do not
+    /// have a mesh identity.
+    Unmeshed,
+
+    /// `l5d-*` headers are removed from all responses.
+    Always,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewStripL5dHeaders<N> {
+    inner: N,
+    strip: StripL5dHeaders,
+    error_header: Option<HeaderName>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StripL5dResponseHeaders<S> {
+    inner: S,
+    strip: bool,
+    error_header: Option<HeaderName>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct StripFuture<F> {
+    #[pin]
+    inner: F,
+    error_header: Option<HeaderName>,
+}
+
+// === impl StripL5dHeaders ===
+
+impl Default for StripL5dHeaders {
+    fn default() -> Self {
+        Self::Never
+    }
+}
+
+// === impl NewStripL5dHeaders ===
+
+impl<N> NewStripL5dHeaders<N> {
+    /// `error_header` is the name of the header in which the proxy describes
+    /// its errors, if any.
+    pub fn layer(
+        strip: StripL5dHeaders,
+        error_header: Option<HeaderName>,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            strip,
+            error_header: error_header.clone(),
+        })
+    }
+}
+
+impl<T, N> svc::NewService<T> for NewStripL5dHeaders<N>
+where
+    T: Param<Option<identity::Name>>,
+    N: svc::NewService<T>,
+{
+    type Service = StripL5dResponseHeaders<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let strip = match self.strip {
+            StripL5dHeaders::Never => false,
+            StripL5dHeaders::Unmeshed => Param::<Option<identity::Name>>::param(&target).is_none(),
+            StripL5dHeaders::Always => true,
+        };
+        trace!(strip, "l5d response headers");
+        StripL5dResponseHeaders {
+            inner: self.inner.new_service(target),
+            strip,
+            error_header: self.error_header.clone(),
+        }
+    }
+}
+
+// === impl StripL5dResponseHeaders ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for StripL5dResponseHeaders<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = future::Either<S::Future, StripFuture<S::Future>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let rsp = self.inner.call(req);
+        if self.strip {
+            future::Either::Right(StripFuture {
+                inner: rsp,
+                error_header: self.error_header.clone(),
+            })
+        } else {
+            future::Either::Left(rsp)
+        }
+    }
+}
+
+// === impl StripFuture ===
+
+impl<F, B, E> Future for StripFuture<F>
+where
+    F: TryFuture<Ok = http::Response<B>, Error = E>,
+{
+    type Output = Result<http::Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let rsp = ready!(this.inner.try_poll(cx))?;
+        Poll::Ready(Ok(strip_l5d_headers(rsp, this.error_header.as_ref())))
+    }
+}
+
+fn strip_l5d_headers<B>(
+    mut rsp: http::Response<B>,
+    error_header: Option<&HeaderName>,
+) -> http::Response<B> {
+    let names = rsp
+        .headers()
+        .keys()
+        .filter(|name| name.as_str().starts_with("l5d-") || Some(*name) == error_header)
+        .cloned()
+        .collect::<Vec<_>>();
+    for name in names {
+        trace!(header = name.as_str(), "Stripping response header");
+        rsp.headers_mut().remove(name);
+    }
+    rsp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_configured_error_header() {
+        let rsp = http::Response::builder()
+            .header("l5d-proxy-error", "connect timed out")
+            .header("x-proxy-error", "connect timed out")
+            .header("x-app", "foo")
+            .body(())
+            .unwrap();
+        let error_header = HeaderName::from_static("x-proxy-error");
+        let rsp = strip_l5d_headers(rsp, Some(&error_header));
+        assert!(rsp.headers().get("l5d-proxy-error").is_none());
+        assert!(rsp.headers().get("x-proxy-error").is_none());
+        assert_eq!(rsp.headers()["x-app"], "foo");
+    }
+}
@@ -54,6 +54,7 @@ async fn unmeshed_http1_hello_world() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
 
@@ -101,6 +102,7 @@ async fn downgrade_origin_form() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
 
@@ -149,6 +151,7 @@ async fn downgrade_absolute_form() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
 
@@ -194,6 +197,7 @@ async fn http1_bad_gateway_response_error_header() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
     let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, connect_error());
@@ -229,6 +233,368 @@ async fn http1_bad_gateway_response_error_header() {
     bg.await.expect("background task failed");
 }
 
+#[tokio::test(flavor = "current_thread")]
+async fn http1_app_connection_errors_are_distinguished() {
+    let _trace = trace_init();
+
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+
+    let error_header = |connect: Connect<Remote<ServerAddr>>| {
+        let accept = accept.clone();
+        async move {
+            let profiles = profile::resolver();
+            let profile_tx = profiles
+                .profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+            profile_tx.send(profile::Profile::default()).unwrap();
+            let cfg = Config {
+                classify_app_connection_errors: true,
+                ..default_config()
+            };
+            let (rt, _shutdown) = runtime();
+            let server = build_server(cfg, rt, profiles, connect).new_service(accept);
+            let mut client = ClientBuilder::new();
+            let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+            let req = Request::builder()
+                .method(http::Method::GET)
+                .uri("http://foo.svc.cluster.local:5550")
+                .body(Body::default())
+                .unwrap();
+            let rsp = http_util::http_request(&mut client, req).await.unwrap();
+            assert_eq!(rsp.status(), http::StatusCode::BAD_GATEWAY);
+            drop(client);
+            let _ = bg.await;
+            rsp.headers()
+                .get(L5D_PROXY_ERROR)
+                .cloned()
+                .expect("response did not contain L5D_PROXY_ERROR header")
+        }
+    };
+
+    // The application is not listening...
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, connect_error());
+    assert_eq!(error_header(connect).await, "connect failed");
+
+    // ...or it resets the connection while handling the request.
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, reset_server());
+    assert_eq!(error_header(connect).await, "upstream reset");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_l5d_headers_stripped_for_unmeshed_clients() {
+    let _trace = trace_init();
+
+    let unmeshed = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let meshed = HttpAccept {
+        tcp: TcpAccept {
+            tls: Conditional::Some(tls::ServerTls::Established {
+                client_id: Some(tls::ClientId(
+                    "foo.ns1.serviceaccount.identity.linkerd.cluster.local"
+                        .parse()
+                        .unwrap(),
+                )),
+                negotiated_protocol: None,
+            }),
+            ..unmeshed.tcp.clone()
+        },
+        ..unmeshed.clone()
+    };
+    let connect = support::connect().endpoint_fn_boxed(unmeshed.tcp.target_addr, connect_error());
+
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    profile_tx.send(profile::Profile::default()).unwrap();
+    let cfg = Config {
+        strip_l5d_headers: crate::http::StripL5dHeaders::Unmeshed,
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let mut server = build_server(cfg, rt, profiles, connect);
+
+    let mut error_header = |accept: HttpAccept| {
+        let server = server.new_service(accept);
+        async move {
+            let mut client = ClientBuilder::new();
+            let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+            let req = Request::builder()
+                .method(http::Method::GET)
+                .uri("http://foo.svc.cluster.local:5550")
+                .body(Body::default())
+                .unwrap();
+            let rsp = http_util::http_request(&mut client, req).await.unwrap();
+            assert_eq!(rsp.status(), http::StatusCode::BAD_GATEWAY);
+            drop(client);
+            let _ = bg.await;
+            rsp.headers().get(L5D_PROXY_ERROR).cloned()
+        }
+    };
+
+    // Meshed clients are told why the proxy failed the request...
+    let header = error_header(meshed).await;
+    assert_eq!(
+        header.expect("meshed clients must receive the header"),
+        "proxy received invalid response"
+    );
+
+    // ...but external clients are not.
+    let header = error_header(unmeshed).await;
+    assert!(
+        header.is_none(),
+        "unmeshed clients must not receive the header"
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_error_messages_redacted_for_unmeshed_clients() {
+    let _trace = trace_init();
+
+    let unmeshed = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let meshed = HttpAccept {
+        tcp: TcpAccept {
+            tls: Conditional::Some(tls::ServerTls::Established {
+                client_id: Some(tls::ClientId(
+                    "foo.ns1.serviceaccount.identity.linkerd.cluster.local"
+                        .parse()
+                        .unwrap(),
+                )),
+                negotiated_protocol: None,
+            }),
+            ..unmeshed.tcp.clone()
+        },
+        ..unmeshed.clone()
+    };
+    let connect = support::connect().endpoint_fn_boxed(unmeshed.tcp.target_addr, connect_error());
+
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    profile_tx.send(profile::Profile::default()).unwrap();
+    let cfg = Config {
+        redact_unmeshed_errors: true,
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let mut server = build_server(cfg, rt, profiles, connect);
+
+    let mut error_header = |accept: HttpAccept| {
+        let server = server.new_service(accept);
+        async move {
+            let mut client = ClientBuilder::new();
+            let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+            let req = Request::builder()
+                .method(http::Method::GET)
+                .uri("http://foo.svc.cluster.local:5550")
+                .body(Body::default())
+                .unwrap();
+            let rsp = http_util::http_request(&mut client, req).await.unwrap();
+            assert_eq!(rsp.status(), http::StatusCode::BAD_GATEWAY);
+            drop(client);
+            let _ = bg.await;
+            rsp.headers()
+                .get(L5D_PROXY_ERROR)
+                .cloned()
+                .expect("response did not contain L5D_PROXY_ERROR header")
+        }
+    };
+
+    // Meshed clients are told why the proxy failed the request...
+    let header = error_header(meshed).await;
+    assert_eq!(header, "proxy received invalid response");
+
+    // ...but external clients only learn that it failed.
+    let header = error_header(unmeshed).await;
+    assert_eq!(header, "upstream error");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_error_responses_are_throttled() {
+    let _trace = trace_init();
+    tokio::time::pause();
+
+    // Build a mock connect that always errors, as if the application is down.
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, connect_error());
+
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    profile_tx.send(profile::Profile::default()).unwrap();
+    let cfg = Config {
+        error_rate_limits: crate::http::ErrorRateLimits::new(None, vec![(5550, 2)]),
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let mut server = build_server(cfg, rt, profiles, connect);
+
+    // Each error closes its connection, so each request is sent on a new
+    // connection. The limit is shared by all of the port's connections.
+    let mut statuses = Vec::new();
+    for _ in 0..5 {
+        let mut client = ClientBuilder::new();
+        let (mut client, bg) =
+            http_util::connect_and_accept(&mut client, server.new_service(accept.clone())).await;
+        let req = Request::builder()
+            .method(http::Method::GET)
+            .uri("http://foo.svc.cluster.local:5550")
+            .body(Body::default())
+            .unwrap();
+        let rsp = http_util::http_request(&mut client, req).await.unwrap();
+        statuses.push((rsp.status(), rsp.headers().get(L5D_PROXY_ERROR).cloned()));
+        drop(client);
+        let _ = bg.await;
+    }
+
+    let bad_gateway = (
+        http::StatusCode::BAD_GATEWAY,
+        Some(http::HeaderValue::from_static(
+            "proxy received invalid response",
+        )),
+    );
+    let throttled = (
+        http::StatusCode::SERVICE_UNAVAILABLE,
+        Some(http::HeaderValue::from_static("too many proxy errors")),
+    );
+    assert_eq!(
+        statuses,
+        vec![
+            bad_gateway.clone(),
+            bad_gateway,
+            throttled.clone(),
+            throttled.clone(),
+            throttled
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_stalled_app_read_timeout() {
+    let _trace = trace_init();
+    tokio::time::pause();
+
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, stalled_server());
+
+    let mut client = ClientBuilder::new();
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    profile_tx.send(profile::Profile::default()).unwrap();
+    let cfg = Config {
+        app_read_timeout: Some(std::time::Duration::from_secs(1)),
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let server = build_server(cfg, rt, profiles, connect).new_service(accept);
+    let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+
+    let req = Request::builder()
+        .method(http::Method::GET)
+        .uri("http://foo.svc.cluster.local:5550")
+        .body(Body::default())
+        .unwrap();
+    let rsp = http_util::http_request(&mut client, req).await.unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::GATEWAY_TIMEOUT);
+    let message = rsp
+        .headers()
+        .get(L5D_PROXY_ERROR)
+        .expect("response did not contain L5D_PROXY_ERROR header");
+    assert_eq!(message, "application read timed out");
+
+    drop(client);
+    let _ = bg.await;
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_route_timeout() {
+    use linkerd_app_core::profiles::http::{RequestMatch, Route};
+
+    let _trace = trace_init();
+    tokio::time::pause();
+
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, stalled_server());
+
+    let mut client = ClientBuilder::new();
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    let mut route = Route::new(std::iter::empty(), vec![]);
+    route.set_timeout(std::time::Duration::from_secs(1));
+    profile_tx
+        .send(profile::Profile {
+            http_routes: vec![(RequestMatch::Method(http::Method::GET), route)],
+            ..profile::Profile::default()
+        })
+        .unwrap();
+    let (rt, _shutdown) = runtime();
+    let server = build_server(default_config(), rt, profiles, connect).new_service(accept);
+    let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+
+    let req = Request::builder()
+        .method(http::Method::GET)
+        .uri("http://foo.svc.cluster.local:5550")
+        .body(Body::default())
+        .unwrap();
+    let rsp = http_util::http_request(&mut client, req).await.unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::GATEWAY_TIMEOUT);
+    let message = rsp
+        .headers()
+        .get(L5D_PROXY_ERROR)
+        .expect("response did not contain L5D_PROXY_ERROR header");
+    assert_eq!(message, "request timed out");
+
+    drop(client);
+    let _ = bg.await;
+}
+
 #[tokio::test(flavor = "current_thread")]
 async fn http1_connect_timeout_response_error_header() {
     let _trace = trace_init();
@@ -243,6 +609,7 @@ async fn http1_connect_timeout_response_error_header() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
     let connect = support::connect().endpoint(accept.tcp.target_addr, connect_timeout(server));
@@ -289,6 +656,7 @@ async fn h2_response_error_header() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
     let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, connect_error());
@@ -338,6 +706,7 @@ async fn grpc_response_error_header() {
             target_addr: ([127, 0, 0, 1], 5550).into(),
             client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
             tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
         },
     };
     let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, connect_error());
@@ -370,6 +739,24 @@ async fn grpc_response_error_header() {
         .expect("response did not contain L5D_PROXY_ERROR header");
     assert_eq!(message, "HTTP Logical service in fail-fast");
 
+    // The gRPC status is sent in the response's trailers.
+    assert!(response.headers().get("grpc-status").is_none());
+    assert!(response
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .is_none());
+    let mut body = response.into_body();
+    while hyper::body::HttpBody::data(&mut body).await.is_some() {}
+    let trailers = hyper::body::HttpBody::trailers(&mut body)
+        .await
+        .unwrap()
+        .expect("response did not contain trailers");
+    assert_eq!(trailers["grpc-status"], "14");
+    assert_eq!(
+        trailers["grpc-message"],
+        "HTTP Logical service in fail-fast"
+    );
+
     // Drop the client and discard the result of awaiting the proxy background
     // task. The result is discarded because it hits an error that is related
     // to the mock implementation and has no significance to the test.
@@ -377,6 +764,232 @@ async fn grpc_response_error_header() {
     let _ = bg.await;
 }
 
+#[tokio::test(flavor = "current_thread")]
+async fn http1_missing_authority_rejected() {
+    let _trace = trace_init();
+
+    let mut server = hyper::server::conn::Http::new();
+    server.http1_only(true);
+    let mut client = ClientBuilder::new();
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect =
+        support::connect().endpoint_fn_boxed(accept.tcp.target_addr, hello_server(server));
+    let profiles = profile::resolver();
+    let cfg = Config {
+        missing_authority: crate::http::MissingAuthority::Reject,
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let server = build_server(cfg, rt, profiles, connect).new_service(accept);
+    let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+
+    // An origin-form request without a Host header has no authority.
+    let req = Request::builder()
+        .method(http::Method::GET)
+        .uri("/")
+        .body(Body::default())
+        .unwrap();
+    let rsp = http_util::http_request(&mut client, req).await.unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::BAD_REQUEST);
+    let message = rsp
+        .headers()
+        .get(L5D_PROXY_ERROR)
+        .expect("response did not contain L5D_PROXY_ERROR header");
+    assert_eq!(message, "request is missing an authority");
+
+    drop(client);
+    let _ = bg.await;
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_missing_authority_uses_target() {
+    let _trace = trace_init();
+
+    let mut server = hyper::server::conn::Http::new();
+    server.http1_only(true);
+    let mut client = ClientBuilder::new();
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect =
+        support::connect().endpoint_fn_boxed(accept.tcp.target_addr, hello_server(server));
+    let profiles = profile::resolver();
+    let cfg = Config {
+        missing_authority: crate::http::MissingAuthority::UseTarget,
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let server = build_server(cfg, rt, profiles, connect).new_service(accept);
+    let (mut client, bg) = http_util::connect_and_accept(&mut client, server).await;
+
+    let req = Request::builder()
+        .method(http::Method::GET)
+        .uri("/")
+        .body(Body::default())
+        .unwrap();
+    let rsp = http_util::http_request(&mut client, req).await.unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::OK);
+    let body = http_util::body_to_string(rsp.into_body()).await.unwrap();
+    assert_eq!(body, "Hello world!");
+
+    drop(client);
+    bg.await.expect("background task failed");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_upgrades_only_permitted_protocols() {
+    use io::{AsyncReadExt, AsyncWriteExt};
+
+    let _trace = trace_init();
+
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect = support::connect().endpoint_fn_boxed(accept.tcp.target_addr, upgrade_server());
+    let profiles = profile::resolver();
+    let cfg = Config {
+        allowed_upgrades: crate::http::AllowedUpgrades::only(vec!["foo".to_string()]),
+        ..default_config()
+    };
+    let (rt, _shutdown) = runtime();
+    let mut server = build_server(cfg, rt, profiles, connect);
+    let upgrade = |protocol: &'static str| {
+        Request::builder()
+            .method(http::Method::GET)
+            .uri("http://foo.svc.cluster.local:5550")
+            .header(http::header::CONNECTION, "upgrade")
+            .header(http::header::UPGRADE, protocol)
+            .body(Body::default())
+            .unwrap()
+    };
+
+    // A permitted upgrade is tunneled to the application.
+    let (mut client, _bg) = http_util::connect_and_accept(
+        &mut ClientBuilder::new(),
+        server.new_service(accept.clone()),
+    )
+    .await;
+    let rsp = http_util::http_request(&mut client, upgrade("foo"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::SWITCHING_PROTOCOLS);
+    let mut tunnel = hyper::upgrade::on(rsp).await.expect("upgrade must succeed");
+    tunnel.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    tunnel.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping", "the application must echo the tunneled data");
+
+    // Other upgrades are rejected.
+    let (mut client, bg) =
+        http_util::connect_and_accept(&mut ClientBuilder::new(), server.new_service(accept)).await;
+    let rsp = http_util::http_request(&mut client, upgrade("bar"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(rsp.headers()[http::header::UPGRADE], "websocket, foo");
+
+    drop(client);
+    let _ = bg.await;
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn http1_minimal_metrics_granularity() {
+    use crate::{MetricsGranularity, MetricsGranularityForPorts};
+    use linkerd_app_core::{
+        metrics::{FmtMetrics, Metrics},
+        profiles::http::{RequestMatch, Route},
+    };
+
+    let _trace = trace_init();
+
+    let accept = HttpAccept {
+        version: proxy::http::Version::Http1,
+        tcp: TcpAccept {
+            target_addr: ([127, 0, 0, 1], 5550).into(),
+            client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+            tls: Conditional::None(tls::server::NoServerTls::NoClientHello),
+            class: None,
+        },
+    };
+    let connect = support::connect().endpoint_fn_boxed(
+        accept.tcp.target_addr,
+        hello_server(hyper::server::conn::Http::new()),
+    );
+
+    // The request matches a profile route, so it would be counted by route if
+    // its port's metrics were detailed.
+    let profiles = profile::resolver();
+    let profile_tx =
+        profiles.profile_tx(NameAddr::from_str_and_port("foo.svc.cluster.local", 5550).unwrap());
+    let labels = Some(("name".to_string(), "hello".to_string()));
+    profile_tx
+        .send(profile::Profile {
+            http_routes: vec![(
+                RequestMatch::Method(http::Method::GET),
+                Route::new(labels.into_iter(), vec![]),
+            )],
+            ..profile::Profile::default()
+        })
+        .unwrap();
+
+    let cfg = Config {
+        metrics_granularity: MetricsGranularityForPorts::new(
+            MetricsGranularity::Detailed,
+            Some((5550, MetricsGranularity::Minimal)),
+        ),
+        ..default_config()
+    };
+    let (metrics, report) = Metrics::new(std::time::Duration::from_secs(10), Default::default());
+    let (rt, _shutdown) = runtime();
+    let rt = ProxyRuntime {
+        metrics: metrics.inbound,
+        ..rt
+    };
+    let server = build_server(cfg, rt, profiles, connect).new_service(accept);
+    let (mut client, bg) = http_util::connect_and_accept(&mut ClientBuilder::new(), server).await;
+
+    let req = Request::builder()
+        .method(http::Method::GET)
+        .uri("http://foo.svc.cluster.local:5550")
+        .body(Body::default())
+        .unwrap();
+    let rsp = http_util::http_request(&mut client, req).await.unwrap();
+    assert_eq!(rsp.status(), http::StatusCode::OK);
+    let body = http_util::body_to_string(rsp.into_body()).await.unwrap();
+    assert_eq!(body, "Hello world!");
+    drop(client);
+    bg.await.expect("background task failed");
+
+    let report = report.as_display().to_string();
+    let reported = |name: &str| {
+        let prefix = format!("{}{{", name);
+        report.lines().any(|l| l.starts_with(prefix.as_str()))
+    };
+    assert!(reported("tcp_open_total"), "{}", report);
+    assert!(reported("request_total"), "{}", report);
+    assert!(!reported("route_request_total"), "{}", report);
+}
+
 #[tracing::instrument]
 fn hello_server(
     http: hyper::server::conn::Http,
@@ -398,6 +1011,118 @@ fn hello_server(
     }
 }
 
+/// Switches protocols on each request, echoing the data sent on the upgraded
+/// connection.
+#[tracing::instrument]
+fn upgrade_server() -> impl Fn(Remote<ServerAddr>) -> io::Result<io::BoxedIo> {
+    move |_| {
+        let (client_io, server_io) = support::io::duplex(4096);
+        let svc = hyper::service::service_fn(|mut request: Request<Body>| async move {
+            tracing::info!(?request);
+            let upgrade = hyper::upgrade::on(&mut request);
+            tokio::spawn(
+                async move {
+                    use io::{AsyncReadExt, AsyncWriteExt};
+                    let mut io = upgrade.await.expect("upgrade must succeed");
+                    let mut buf = [0; 4];
+                    io.read_exact(&mut buf).await?;
+                    io.write_all(&buf).await?;
+                    Ok::<_, io::Error>(())
+                }
+                .in_current_span(),
+            );
+            let protocol = request.headers()[http::header::UPGRADE].clone();
+            let rsp = Response::builder()
+                .status(http::StatusCode::SWITCHING_PROTOCOLS)
+                .header(http::header::CONNECTION, "upgrade")
+                .header(http::header::UPGRADE, protocol)
+                .body(Body::empty())
+                .unwrap();
+            Ok::<_, io::Error>(rsp)
+        });
+        let mut http = hyper::server::conn::Http::new();
+        http.http1_only(true);
+        tokio::spawn(
+            http.serve_connection(server_io, svc)
+                .with_upgrades()
+                .in_current_span(),
+        );
+        Ok(io::BoxedIo::new(client_io))
+    }
+}
+
+/// Accepts connections without ever responding on them.
+#[tracing::instrument]
+fn stalled_server() -> impl Fn(Remote<ServerAddr>) -> io::Result<io::BoxedIo> {
+    move |_| {
+        let (client_io, server_io) = support::io::duplex(4096);
+        tokio::spawn(async move {
+            let _server_io = server_io;
+            futures::future::pending::<()>().await
+        });
+        Ok(io::BoxedIo::new(client_io))
+    }
+}
+
+/// Accepts connections and resets them once a request has been written.
+#[tracing::instrument]
+fn reset_server() -> impl Fn(Remote<ServerAddr>) -> io::Result<io::BoxedIo> {
+    move |_| Ok(io::BoxedIo::new(ResetIo::default()))
+}
+
+#[derive(Debug, Default)]
+struct ResetIo {
+    written: bool,
+    read_waker: Option<std::task::Waker>,
+}
+
+impl io::AsyncRead for ResetIo {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        _: &mut io::ReadBuf<'_>,
+    ) -> io::Poll<()> {
+        if !self.written {
+            self.read_waker = Some(cx.waker().clone());
+            return std::task::Poll::Pending;
+        }
+        std::task::Poll::Ready(Err(io::Error::from(io::ErrorKind::ConnectionReset)))
+    }
+}
+
+impl io::AsyncWrite for ResetIo {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> io::Poll<usize> {
+        self.written = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> io::Poll<()> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> io::Poll<()> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+impl io::PeerAddr for ResetIo {
+    fn peer_addr(&self) -> io::Result<std::net::SocketAddr> {
+        Ok(([127, 0, 0, 1], 5550).into())
+    }
+}
+
+impl io::Splice for ResetIo {}
+
 #[tracing::instrument]
 fn connect_error() -> impl Fn(Remote<ServerAddr>) -> io::Result<io::BoxedIo> {
     move |_| {
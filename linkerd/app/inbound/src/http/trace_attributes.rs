@@ -0,0 +1,319 @@
+use crate::target::Target;
+use linkerd_app_core::{
+    dst,
+    http_tracing::SpanAttributes,
+    proxy::http::{self, HeaderName},
+    svc, tls,
+};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::warn;
+
+/// Configures the request metadata that is recorded as attributes of the
+/// spans of requests to the application.
+///
+/// Only allowed metadata is recorded: the values of the allowed request
+/// headers, the labels of the request's profile route, and the client's
+/// identity. By default, no metadata is recorded.
+#[derive(Clone, Debug, Default)]
+pub struct TraceAttributes {
+    headers: Arc<Vec<HeaderName>>,
+    route: bool,
+    client_id: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewTagSpan<N> {
+    inner: N,
+    attributes: TraceAttributes,
+}
+
+/// Sets the `SpanAttributes` of each request that has allowed metadata.
+#[derive(Clone, Debug)]
+pub struct TagSpan<S> {
+    inner: S,
+    attributes: TraceAttributes,
+    client_id: Option<String>,
+}
+
+// === impl TraceAttributes ===
+
+impl TraceAttributes {
+    pub fn new(
+        headers: impl IntoIterator<Item = HeaderName>,
+        route: bool,
+        client_id: bool,
+    ) -> Self {
+        let headers = headers
+            .into_iter()
+            .filter(|h| {
+                let sensitive = is_sensitive(h);
+                if sensitive {
+                    warn!(header = %h, "Sensitive headers are not recorded on spans");
+                }
+                !sensitive
+            })
+            .collect();
+        Self {
+            headers: Arc::new(headers),
+            route,
+            client_id,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.headers.is_empty() && !self.route && !self.client_id
+    }
+}
+
+/// Headers that may carry credentials are never recorded on spans, even if
+/// they are allowed.
+fn is_sensitive(name: &HeaderName) -> bool {
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie"
+    )
+}
+
+// === impl NewTagSpan ===
+
+impl<N> NewTagSpan<N> {
+    pub fn layer(attributes: TraceAttributes) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            attributes: attributes.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<Target> for NewTagSpan<N>
+where
+    N: svc::NewService<Target>,
+{
+    type Service = TagSpan<N::Service>;
+
+    fn new_service(&mut self, target: Target) -> Self::Service {
+        let client_id = if self.attributes.client_id {
+            target.tls.value().and_then(|tls| match tls {
+                tls::ServerTls::Established {
+                    client_id: Some(id),
+                    ..
+                } => Some(id.to_string()),
+                _ => None,
+            })
+        } else {
+            None
+        };
+        TagSpan {
+            inner: self.inner.new_service(target),
+            attributes: self.attributes.clone(),
+            client_id,
+        }
+    }
+}
+
+// === impl TagSpan ===
+
+impl<S, B> svc::Service<http::Request<B>> for TagSpan<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if self.attributes.is_empty() {
+            return self.inner.call(req);
+        }
+
+        let mut attrs = SpanAttributes::default();
+        for name in self.attributes.headers.iter() {
+            if let Some(value) = req.headers().get(name).and_then(|v| v.to_str().ok()) {
+                attrs.insert(format!("http.request.header.{}", name), value);
+            }
+        }
+        if self.attributes.route {
+            if let Some(route) = req.extensions().get::<dst::Route>() {
+                for (k, v) in route.route.labels().iter() {
+                    attrs.insert(format!("route.{}", k), v.as_str());
+                }
+            }
+        }
+        if let Some(id) = self.client_id.as_ref() {
+            attrs.insert("client.id", id.as_str());
+        }
+
+        if !attrs.is_empty() {
+            req.extensions_mut().insert(attrs);
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd_app_core::{
+        http_tracing::{self, SpanSink},
+        metrics::Direction,
+        opencensus::{self, proto::trace::v1 as oc},
+        profiles,
+        svc::{Layer, NewService, ServiceExt},
+        Conditional, Error,
+    };
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
+
+    fn target() -> Target {
+        Target {
+            dst: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            target_addr: ([127, 0, 0, 1], 8080).into(),
+            http_version: http::Version::Http1,
+            tls: Conditional::Some(tls::ServerTls::Established {
+                client_id: Some(tls::ClientId(
+                    "foo.ns1.serviceaccount.identity.linkerd.cluster.local"
+                        .parse()
+                        .unwrap(),
+                )),
+                negotiated_protocol: None,
+            }),
+            log_client_port: false,
+            class: None,
+        }
+    }
+
+    async fn attributes(config: TraceAttributes, req: http::Request<()>) -> Vec<(String, String)> {
+        let svc = NewTagSpan::layer(config)
+            .layer(|_: Target| {
+                svc::mk(|req: http::Request<()>| {
+                    let attrs = req.extensions().get::<SpanAttributes>().cloned();
+                    future::ok::<_, Error>(attrs.unwrap_or_default())
+                })
+            })
+            .new_service(target());
+        let attrs = svc.oneshot(req).await.unwrap();
+        let mut attrs = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        attrs.sort();
+        attrs
+    }
+
+    fn request() -> http::Request<()> {
+        let mut req = http::Request::builder()
+            .header("x-tenant", "acme")
+            .header("x-other", "secret")
+            .header(http::header::AUTHORIZATION, "Bearer token")
+            .body(())
+            .unwrap();
+        let labels = Some(("name".to_string(), "GET /users".to_string()));
+        req.extensions_mut().insert(dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::In,
+        });
+        req
+    }
+
+    fn attr(k: &str, v: &str) -> (String, String) {
+        (k.to_string(), v.to_string())
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_allowed_metadata() {
+        let config = TraceAttributes::new(
+            vec![
+                HeaderName::from_static("x-tenant"),
+                http::header::AUTHORIZATION,
+            ],
+            true,
+            true,
+        );
+        assert_eq!(
+            attributes(config, request()).await,
+            vec![
+                attr(
+                    "client.id",
+                    "foo.ns1.serviceaccount.identity.linkerd.cluster.local"
+                ),
+                attr("http.request.header.x-tenant", "acme"),
+                attr("route.name", "GET /users"),
+            ]
+        );
+
+        let config = TraceAttributes::new(Some(HeaderName::from_static("x-tenant")), false, false);
+        assert_eq!(
+            attributes(config, request()).await,
+            vec![attr("http.request.header.x-tenant", "acme")]
+        );
+
+        assert!(attributes(TraceAttributes::default(), request())
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn exports_allowed_metadata_on_spans() {
+        let (registry, _report) = opencensus::metrics::new();
+        let (tx, mut rx) = mpsc::channel(1);
+        let sink = Some(SpanSink::new(tx, registry));
+        let mut labels = HashMap::new();
+        labels.insert("direction".to_string(), "inbound".to_string());
+        labels.insert("client.id".to_string(), "proxy".to_string());
+
+        let config = TraceAttributes::new(Some(HeaderName::from_static("x-tenant")), true, true);
+        let svc = NewTagSpan::layer(config)
+            .layer(move |_: Target| {
+                http_tracing::client(sink.clone(), labels.clone()).layer(svc::mk(
+                    |_: http::Request<()>| future::ok::<_, Error>(http::Response::new(())),
+                ))
+            })
+            .new_service(target());
+        let mut req = request();
+        req.headers_mut().insert(
+            "x-b3-traceid",
+            http::HeaderValue::from_static("0123456789abcdef0123456789abcdef"),
+        );
+        req.headers_mut().insert(
+            "x-b3-spanid",
+            http::HeaderValue::from_static("0123456789abcdef"),
+        );
+        req.headers_mut()
+            .insert("x-b3-sampled", http::HeaderValue::from_static("1"));
+        svc.oneshot(req).await.unwrap();
+
+        let span = rx.recv().await.expect("span must be exported");
+        let mut attrs = span
+            .attributes
+            .expect("span must have attributes")
+            .attribute_map
+            .into_iter()
+            .filter_map(|(k, v)| match v.value? {
+                oc::attribute_value::Value::StringValue(s) => Some((k, s.value)),
+                _ => None,
+            })
+            .filter(|(k, _)| !k.starts_with("http.") || k.starts_with("http.request.header."))
+            .collect::<Vec<_>>();
+        attrs.sort();
+        assert_eq!(
+            attrs,
+            vec![
+                // Request metadata does not override the proxy's labels.
+                attr("client.id", "proxy"),
+                attr("direction", "inbound"),
+                attr("http.request.header.x-tenant", "acme"),
+                attr("route.name", "GET /users"),
+            ]
+        );
+    }
+}
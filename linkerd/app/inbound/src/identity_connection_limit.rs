@@ -0,0 +1,375 @@
+use crate::target::TcpAccept;
+use futures::{future, ready};
+use linkerd_app_core::{identity, svc, tls, Conditional, Error};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tracing::debug;
+
+/// Limits the number of inbound connections that each client identity may
+/// hold open at once.
+///
+/// Each identity's limit is determined by the most specific trust tier that
+/// matches it: a tier that names the identity exactly is preferred over
+/// wildcard tiers, and wildcard tiers with longer suffixes are preferred over
+/// those with shorter suffixes. Identities that match no tier are limited by
+/// the default limit, if one is set. Clients without an identity share the
+/// unauthenticated limit. Connections in excess of their client's limit are
+/// closed as they are accepted.
+#[derive(Clone, Debug, Default)]
+pub struct IdentityConnectionLimits {
+    tiers: Arc<Vec<(IdentityPattern, usize)>>,
+    default: Option<usize>,
+    unauthenticated: Option<usize>,
+    active: Arc<Mutex<HashMap<Option<identity::Name>, usize>>>,
+}
+
+/// Matches client identities, either exactly or, when prefixed by `*.`, by
+/// their suffix (e.g. `*.ns.serviceaccount.identity.linkerd.cluster.local`
+/// matches all identities in the `ns` namespace).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdentityPattern {
+    Exact(identity::Name),
+    Suffix(String),
+}
+
+#[derive(Clone, Debug, Error)]
+#[error("invalid identity pattern")]
+pub struct InvalidIdentityPattern(());
+
+#[derive(Clone, Debug)]
+pub(crate) struct NewLimitIdentityConnections<N> {
+    inner: N,
+    limits: IdentityConnectionLimits,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LimitIdentityConnections<S> {
+    inner: S,
+    client: Option<identity::Name>,
+    limit: Option<usize>,
+    limits: IdentityConnectionLimits,
+}
+
+/// Holds one of a client's connections open until it is dropped.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    client: Option<identity::Name>,
+    limits: IdentityConnectionLimits,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub(crate) struct LimitedFuture<F> {
+    #[pin]
+    inner: F,
+    _permit: Option<Permit>,
+}
+
+// === impl IdentityConnectionLimits ===
+
+impl IdentityConnectionLimits {
+    pub fn new(
+        default: Option<usize>,
+        tiers: impl IntoIterator<Item = (IdentityPattern, usize)>,
+        unauthenticated: Option<usize>,
+    ) -> Self {
+        Self {
+            tiers: Arc::new(tiers.into_iter().collect()),
+            default,
+            unauthenticated,
+            active: Default::default(),
+        }
+    }
+
+    /// Returns the number of connections that `client` may hold open at once,
+    /// if it is limited.
+    fn limit(&self, client: Option<&identity::Name>) -> Option<usize> {
+        let client = match client {
+            Some(client) => client,
+            None => return self.unauthenticated,
+        };
+        self.tiers
+            .iter()
+            .filter_map(|(pattern, limit)| {
+                pattern
+                    .specificity(client)
+                    .map(|specificity| (specificity, *limit))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, limit)| limit)
+            .or(self.default)
+    }
+
+    fn acquire(&self, client: &Option<identity::Name>, limit: usize) -> Option<Permit> {
+        if limit == 0 {
+            return None;
+        }
+        let mut active = self.active.lock();
+        let count = active.entry(client.clone()).or_default();
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(Permit {
+            client: client.clone(),
+            limits: self.clone(),
+        })
+    }
+}
+
+// === impl IdentityPattern ===
+
+impl IdentityPattern {
+    /// If the pattern matches `name`, returns how specific the match is, so
+    /// that the most specific of several matching patterns may be preferred.
+    fn specificity(&self, name: &identity::Name) -> Option<usize> {
+        match self {
+            Self::Exact(exact) if exact == name => Some(usize::MAX),
+            Self::Exact(_) => None,
+            Self::Suffix(suffix) => {
+                let name: &str = name.as_ref();
+                let prefix = name.strip_suffix(suffix.as_str())?;
+                if prefix.ends_with('.') {
+                    Some(suffix.len())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for IdentityPattern {
+    type Err = InvalidIdentityPattern;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("*.") {
+            Some(suffix) => {
+                // The suffix must itself be a valid name.
+                let suffix =
+                    identity::Name::from_str(suffix).map_err(|_| InvalidIdentityPattern(()))?;
+                Ok(Self::Suffix(AsRef::<str>::as_ref(&suffix).to_string()))
+            }
+            None => identity::Name::from_str(s)
+                .map(Self::Exact)
+                .map_err(|_| InvalidIdentityPattern(())),
+        }
+    }
+}
+
+// === impl NewLimitIdentityConnections ===
+
+impl<N> NewLimitIdentityConnections<N> {
+    pub(crate) fn layer(
+        limits: IdentityConnectionLimits,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            limits: limits.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<TcpAccept> for NewLimitIdentityConnections<N>
+where
+    N: svc::NewService<TcpAccept>,
+{
+    type Service = LimitIdentityConnections<N::Service>;
+
+    fn new_service(&mut self, tcp: TcpAccept) -> Self::Service {
+        let client = match tcp.tls {
+            Conditional::Some(tls::ServerTls::Established {
+                client_id: Some(tls::ClientId(ref id)),
+                ..
+            }) => Some(id.clone()),
+            _ => None,
+        };
+        let limit = self.limits.limit(client.as_ref());
+        LimitIdentityConnections {
+            inner: self.inner.new_service(tcp),
+            client,
+            limit,
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+// === impl LimitIdentityConnections ===
+
+impl<I, S> svc::Service<I> for LimitIdentityConnections<S>
+where
+    S: svc::Service<I, Response = ()>,
+    S::Error: Into<Error>,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = future::Either<future::Ready<Result<(), Error>>, LimitedFuture<S::Future>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let permit = match self.limit {
+            Some(limit) => match self.limits.acquire(&self.client, limit) {
+                Some(permit) => Some(permit),
+                None => {
+                    debug!(client.id = ?self.client, limit, "Identity connection limit exceeded");
+                    // The connection is closed as it is dropped.
+                    drop(io);
+                    return future::Either::Left(future::ok(()));
+                }
+            },
+            None => None,
+        };
+        future::Either::Right(LimitedFuture {
+            inner: self.inner.call(io),
+            _permit: permit,
+        })
+    }
+}
+
+// === impl Permit ===
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut active = self.limits.active.lock();
+        if let Some(count) = active.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.client);
+            }
+        }
+    }
+}
+
+// === impl LimitedFuture ===
+
+impl<F, E> Future for LimitedFuture<F>
+where
+    F: Future<Output = Result<(), E>>,
+    E: Into<Error>,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.project().inner.poll(cx));
+        Poll::Ready(res.map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NS1: &str = "ns1.serviceaccount.identity.linkerd.cluster.local";
+    const NS2: &str = "ns2.serviceaccount.identity.linkerd.cluster.local";
+
+    fn name(s: &str) -> identity::Name {
+        identity::Name::from_str(s).unwrap()
+    }
+
+    /// Opens connections from `client` until one is refused, returning the
+    /// permits of the connections that were accepted.
+    fn connect(
+        limits: &IdentityConnectionLimits,
+        client: Option<identity::Name>,
+        max: usize,
+    ) -> Vec<Permit> {
+        let limit = match limits.limit(client.as_ref()) {
+            Some(limit) => limit,
+            None => return Vec::new(),
+        };
+        std::iter::from_fn(|| limits.acquire(&client, limit))
+            .take(max)
+            .collect()
+    }
+
+    #[test]
+    fn tiers_limit_their_identities() {
+        let limits = IdentityConnectionLimits::new(
+            Some(2),
+            vec![
+                (format!("*.{}", NS1).parse().unwrap(), 10),
+                ("*.identity.linkerd.cluster.local".parse().unwrap(), 5),
+                (format!("*.{}", NS2).parse().unwrap(), 3),
+                (format!("web.{}", NS2).parse().unwrap(), 4),
+            ],
+            Some(1),
+        );
+
+        let local = Some(name(&format!("web.{}", NS1)));
+        let held = connect(&limits, local.clone(), 100);
+        assert_eq!(held.len(), 10);
+        assert_eq!(
+            connect(&limits, Some(name(&format!("api.{}", NS1))), 100).len(),
+            10,
+            "each identity must have its own quota"
+        );
+        assert_eq!(
+            connect(&limits, Some(name(&format!("api.{}", NS2))), 100).len(),
+            3,
+            "the longest matching suffix must be preferred"
+        );
+        assert_eq!(
+            connect(&limits, Some(name(&format!("web.{}", NS2))), 100).len(),
+            4,
+            "exact matches must be preferred"
+        );
+        let other = name("web.ns3.serviceaccount.identity.linkerd.cluster.local");
+        assert_eq!(connect(&limits, Some(other), 100).len(), 5);
+        assert_eq!(
+            connect(&limits, Some(name("web.example.com")), 100).len(),
+            2,
+            "unmatched identities must be limited by the default"
+        );
+        assert_eq!(connect(&limits, None, 100).len(), 1);
+
+        // Connections are refused until one of the client's connections is
+        // closed.
+        assert!(connect(&limits, local.clone(), 100).is_empty());
+        drop(held);
+        assert!(limits.active.lock().is_empty());
+        assert_eq!(connect(&limits, local, 100).len(), 10);
+    }
+
+    #[test]
+    fn unlimited_without_default() {
+        let limits = IdentityConnectionLimits::new(
+            None,
+            vec![(format!("*.{}", NS1).parse().unwrap(), 1)],
+            None,
+        );
+        assert_eq!(limits.limit(Some(&name(&format!("web.{}", NS1)))), Some(1));
+        assert_eq!(limits.limit(Some(&name(&format!("web.{}", NS2)))), None);
+        assert_eq!(limits.limit(None), None);
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(
+            IdentityPattern::from_str("*.ns1.example.com").unwrap(),
+            IdentityPattern::Suffix("ns1.example.com".to_string())
+        );
+        assert!(IdentityPattern::from_str("*.").is_err());
+
+        let pattern = IdentityPattern::from_str("*.example.com").unwrap();
+        assert!(pattern.specificity(&name("web.example.com")).is_some());
+        assert!(pattern.specificity(&name("a.b.example.com")).is_some());
+        assert!(
+            pattern.specificity(&name("example.com")).is_none(),
+            "wildcards must match at least one label"
+        );
+        assert!(pattern.specificity(&name("webexample.com")).is_none());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::target::TcpEndpoint;
+use linkerd_app_core::{proxy::tcp, svc::ExtractParam};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// Configures, by port, how long forwarded TCP connections may be idle
+/// before they are closed.
+///
+/// Ports without a timeout of their own use the default timeout, if one is
+/// set. Connections closed by an idle timeout are recorded in the
+/// `inbound_tcp_accept_errors_total` metric with `error="idle_timeout"`.
+#[derive(Clone, Debug, Default)]
+pub struct IdleTimeoutsForPorts {
+    default: Option<Duration>,
+    ports: Arc<HashMap<u16, Duration>>,
+}
+
+// === impl IdleTimeoutsForPorts ===
+
+impl IdleTimeoutsForPorts {
+    pub fn new(
+        default: Option<Duration>,
+        ports: impl IntoIterator<Item = (u16, Duration)>,
+    ) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+        }
+    }
+
+    fn timeout(&self, port: u16) -> Option<Duration> {
+        self.ports.get(&port).copied().or(self.default)
+    }
+}
+
+impl ExtractParam<tcp::IdleTimeout, TcpEndpoint> for IdleTimeoutsForPorts {
+    #[inline]
+    fn extract_param(&self, TcpEndpoint { port, .. }: &TcpEndpoint) -> tcp::IdleTimeout {
+        tcp::IdleTimeout(self.timeout(*port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_fall_back_to_default() {
+        let timeouts = IdleTimeoutsForPorts::new(
+            Some(Duration::from_secs(60)),
+            vec![(5432, Duration::from_secs(600))],
+        );
+        assert_eq!(timeouts.timeout(5432), Some(Duration::from_secs(600)));
+        assert_eq!(timeouts.timeout(6379), Some(Duration::from_secs(60)));
+
+        let timeouts = IdleTimeoutsForPorts::new(None, vec![(5432, Duration::from_secs(600))]);
+        assert_eq!(timeouts.timeout(6379), None);
+    }
+}
@@ -7,29 +7,68 @@
 #![forbid(unsafe_code)]
 
 mod allow_discovery;
+mod allow_ports;
+mod client_auth;
+mod connection_rate;
 pub mod direct;
 pub mod http;
+mod identity_connection_limit;
+mod idle_timeout;
+mod loopback;
+mod metrics_granularity;
+mod port_class;
+mod port_policies;
+mod proxy_protocol;
 mod require_identity;
+mod require_tls;
+mod sni;
+mod source_networks;
 pub mod target;
+mod terminate_tls;
 #[cfg(any(test, fuzzing))]
 pub(crate) mod test_util;
 
-pub use self::target::{HttpEndpoint, Logical, RequestTarget, Target, TcpEndpoint};
+pub use self::{
+    client_auth::ClientAuthForPorts,
+    connection_rate::{ConnectionRateLimit, ConnectionRateLimitsForPorts},
+    identity_connection_limit::{IdentityConnectionLimits, IdentityPattern},
+    idle_timeout::IdleTimeoutsForPorts,
+    metrics_granularity::{MetricsGranularity, MetricsGranularityForPorts},
+    port_class::PortClasses,
+    proxy_protocol::ProxyAddrs,
+    sni::SniRoutes,
+    source_networks::SourceNetworksForPorts,
+    target::{HttpEndpoint, Logical, RequestTarget, Target, TcpEndpoint},
+    terminate_tls::TerminateTlsForPorts,
+};
 use self::{
+    client_auth::WithClientAuth,
+    identity_connection_limit::NewLimitIdentityConnections,
+    port_policies::{NewRecordAllowed, PortPolicies},
+    proxy_protocol::WriteProxyHeader,
     require_identity::RequireIdentityForPorts,
+    require_tls::RequireTlsForPorts,
+    sni::NewRouteSni,
     target::{HttpAccept, TcpAccept},
+    terminate_tls::TerminateParams,
 };
+use futures::future;
 use linkerd_app_core::{
     config::{ConnectConfig, PortSet, ProxyConfig, ServerConfig},
-    detect, drain, io, metrics, profiles,
-    proxy::{identity::LocalCrtKey, tcp},
+    connection_log, detect, drain, http_tracing, io,
+    metrics::{
+        self,
+        policy_decisions::{Decision, Policy},
+    },
+    profiles,
+    proxy::{http::HeaderName, identity::LocalCrtKey, tcp},
     serve,
-    svc::{self, ExtractParam, InsertParam},
+    svc::{self, ExtractParam, InsertParam, Predicate},
     tls,
     transport::{self, listen::Bind, ClientAddr, Local, OrigDstAddr, Remote, ServerAddr},
     Error, Infallible, NameMatch, ProxyRuntime,
 };
-use std::{convert::TryFrom, fmt::Debug, future::Future, time::Duration};
+use std::{convert::TryFrom, fmt::Debug, future::Future, net::IpAddr, time::Duration};
 use tracing::{debug_span, info_span};
 
 #[derive(Clone, Debug)]
@@ -37,8 +76,203 @@ pub struct Config {
     pub allow_discovery: NameMatch,
     pub proxy: ProxyConfig,
     pub require_identity_for_inbound_ports: RequireIdentityForPorts,
+
+    /// Ports on which plaintext connections are closed instead of being
+    /// forwarded to the application.
+    pub require_tls_for_inbound_ports: RequireTlsForPorts,
+
     pub disable_protocol_detection_for_ports: PortSet,
+
+    /// If true, connections are closed as they are accepted unless they
+    /// target one of the `allow_ports` or the inbound proxy's own port.
+    pub default_deny: bool,
+
+    /// The ports to which connections are permitted when `default_deny` is
+    /// set. Ignored otherwise.
+    pub allow_ports: PortSet,
     pub profile_idle_timeout: Duration,
+
+    /// Ports on which forwarded TCP connections begin with a PROXY protocol v2
+    /// header that describes the client's address and the original
+    /// destination address. HTTP connections are not affected.
+    pub proxy_protocol_ports: PortSet,
+
+    /// Routes connections on ports on which protocol detection is disabled to
+    /// other local ports, by the server name of their TLS ClientHello.
+    pub sni_routes: SniRoutes,
+
+    /// Whether the bytes transferred on accepted connections are reported by
+    /// the clients' identities.
+    pub identity_bytes_metrics: bool,
+
+    /// Whether the client's source port should be recorded in connection
+    /// spans and tap events. It is never used as a metric label.
+    pub log_client_port: bool,
+
+    pub missing_authority: http::MissingAuthority,
+
+    /// Request headers whose duplicate values are joined before requests are
+    /// forwarded to the application.
+    pub duplicate_headers: http::DuplicateHeaders,
+
+    /// Limits the size and number of each request's cookies.
+    pub cookie_limits: http::CookieLimits,
+
+    /// The maximum length, in bytes, of an HTTP/1 request line, if any.
+    pub max_request_line_bytes: Option<usize>,
+
+    /// Determines how HTTP/1 requests with both `Transfer-Encoding` and
+    /// `Content-Length` headers are handled.
+    pub transfer_encoding_conflict: http::TransferEncodingConflict,
+
+    /// Determines whether requests with several matching `Content-Length`
+    /// headers are rejected.
+    pub duplicate_content_length: http::DuplicateContentLength,
+
+    /// Limits, by port, the rate of proxy-generated error responses.
+    pub error_rate_limits: http::ErrorRateLimits,
+
+    /// Configures, by port, circuit breakers that fail requests fast while the
+    /// application fails consistently.
+    pub local_breakers: http::LocalBreakers,
+
+    /// The header in which proxy-generated error responses describe their
+    /// errors. If unset, errors are not described in a header.
+    pub proxy_error_header: Option<HeaderName>,
+
+    /// If true, failures to connect to the application and connections that
+    /// it resets are described by distinct error responses.
+    pub classify_app_connection_errors: bool,
+
+    /// If true, error responses to clients without a verified identity
+    /// describe all errors with a generic message.
+    pub redact_unmeshed_errors: bool,
+
+    /// Whether proxy-generated gateway errors are described in JSON bodies
+    /// for requests that accept `application/json`.
+    pub json_error_bodies: bool,
+
+    /// Limits the rate of requests from each client identity.
+    pub identity_rate_limits: http::IdentityRateLimits,
+
+    /// Limits the number of HTTP/2 streams that may be active at once across
+    /// all inbound connections.
+    pub h2_stream_limit: http::H2StreamLimit,
+
+    /// Determines which clients' responses have `l5d-*` headers removed.
+    pub strip_l5d_headers: http::StripL5dHeaders,
+
+    /// If set, requests without a value for this header are assigned a
+    /// generated request ID.
+    pub request_id_header: Option<HeaderName>,
+
+    /// If set, the number of microseconds each request spent in the proxy
+    /// before it was dispatched to the application is set on this header.
+    pub proxy_elapsed_header: Option<HeaderName>,
+
+    /// Decides whether requests that do not carry a sampling decision are
+    /// traced.
+    pub trace_sampler: http_tracing::Sampler,
+
+    /// The request metadata that is recorded on the spans of requests to the
+    /// application.
+    pub trace_attributes: http::TraceAttributes,
+
+    /// If set, connections that are upgraded to WebSockets are closed once no
+    /// data has been transferred on them for this long.
+    pub websocket_idle_timeout: Option<Duration>,
+
+    /// Determines which protocols HTTP/1.1 requests may upgrade to.
+    pub allowed_upgrades: http::AllowedUpgrades,
+
+    /// If set, requests fail with a 504 Gateway Timeout when the application
+    /// does not send response headers within this time.
+    pub response_headers_timeout: Option<Duration>,
+
+    /// If set, requests fail when no response data is read from the
+    /// application for this long while a response is awaited. Requests that
+    /// have not received response headers fail with a 504 Gateway Timeout.
+    pub app_read_timeout: Option<Duration>,
+
+    /// Routes on which gRPC messages are decompressed for clients and
+    /// applications that do not support their encoding.
+    pub grpc_compression: http::GrpcCompression,
+
+    /// Determines which HTTP methods are permitted on each route.
+    pub allowed_methods: http::AllowedMethods,
+
+    /// Determines which fields are redacted from each route's JSON responses.
+    pub redact_fields: http::RedactFields,
+
+    /// Determines which requests are routed to an alternate application port
+    /// by the size of their bodies.
+    pub body_size_routing: http::BodySizeRouting,
+
+    /// Limits the size of request bodies, globally and on each route.
+    pub max_request_body_bytes: http::RequestBodyLimits,
+
+    /// Determines which routes require requests to carry a nonce, and rejects
+    /// requests that replay one.
+    pub replay_protection: http::ReplayProtection,
+
+    /// Determines whether plaintext connections to the mesh port are closed
+    /// before they are processed.
+    pub direct_plaintext: direct::PlaintextPolicy,
+
+    /// Determines whether mesh port connections from clients that are
+    /// expected to negotiate the transport header, but do not, are closed.
+    pub direct_alpn_downgrade: direct::AlpnDowngradePolicy,
+
+    /// Determines whether mesh port connections that are accepted before the
+    /// proxy's identity is certified are closed or held until it is.
+    pub direct_identity_pending: direct::IdentityPendingPolicy,
+
+    /// Determines whether the TLS alerts that end mesh port connections are
+    /// logged, and at what rate.
+    pub direct_tls_alert_logs: direct::TlsAlertLogs,
+
+    /// Classes, by port, with which inbound traffic metrics are labeled.
+    pub port_classes: PortClasses,
+
+    /// Determines, by port, how much detail is recorded in HTTP metrics.
+    pub metrics_granularity: MetricsGranularityForPorts,
+
+    /// Determines, by port, whether TLS clients must present a certificate.
+    /// Note that ports that do not request client certificates never have a
+    /// client identity, so they should not require identity.
+    pub client_auth: ClientAuthForPorts,
+
+    /// Restricts, by port, the networks from which connections are accepted.
+    pub source_networks: SourceNetworksForPorts,
+
+    /// Limits, by port, the rate at which connections are accepted.
+    pub connection_rate_limits: ConnectionRateLimitsForPorts,
+
+    /// Limits the number of connections each client identity may hold open
+    /// at once, by the trust tier that matches the identity.
+    pub identity_connection_limits: IdentityConnectionLimits,
+
+    /// Determines, by port, how long forwarded TCP connections may be idle
+    /// before they are closed.
+    pub port_idle_timeouts: IdleTimeoutsForPorts,
+
+    /// Terminates TLS with non-mesh certificates on the configured ports.
+    pub terminate_tls: TerminateTlsForPorts,
+
+    /// If true, connections that appear to be HTTP/1 are only served as HTTP
+    /// once their first request head has been parsed. Connections with
+    /// malformed request heads are forwarded opaquely instead of failing.
+    pub opaque_on_http1_parse_failure: bool,
+
+    /// The IP address to which inbound connections are forwarded (e.g. `::1`
+    /// for applications that only listen on the IPv6 loopback interface).
+    pub connect_loopback: IpAddr,
+
+    /// If true, connections made over the loopback interface (e.g. from the
+    /// application or other containers in the pod) need not have a client
+    /// identity, even on ports that require one, and are accepted regardless
+    /// of the ports' source networks.
+    pub trust_loopback: bool,
 }
 
 #[derive(Clone)]
@@ -52,6 +286,7 @@ pub struct Inbound<S> {
 struct TlsParams {
     timeout: tls::server::Timeout,
     identity: Option<LocalCrtKey>,
+    client_auth: ClientAuthForPorts,
 }
 
 // === impl Inbound ===
@@ -108,7 +343,7 @@ impl Inbound<()> {
     ) -> Inbound<
         impl svc::Service<
                 T,
-                Response = impl io::AsyncRead + io::AsyncWrite + Send,
+                Response = impl io::AsyncRead + io::AsyncWrite + io::Splice + Send,
                 Error = Error,
                 Future = impl Send,
             > + Clone,
@@ -122,23 +357,17 @@ impl Inbound<()> {
             let ConnectConfig {
                 ref keepalive,
                 ref timeout,
+                ref dscp,
                 ..
             } = config.proxy.connect;
+            let loopback = config.connect_loopback;
 
-            #[derive(Debug, thiserror::Error)]
-            #[error("inbound connection must not target port {0}")]
-            struct Loop(u16);
-
-            svc::stack(transport::ConnectTcp::new(*keepalive))
+            svc::stack(transport::ConnectTcp::new(*keepalive, dscp.clone()))
                 // Limits the time we wait for a connection to be established.
                 .push_connect_timeout(*timeout)
                 // Prevent connections that would target the inbound proxy port from looping.
                 .push_request_filter(move |t: T| {
-                    let port = t.param();
-                    if port == proxy_port {
-                        return Err(Loop(port));
-                    }
-                    Ok(Remote(ServerAddr(([127, 0, 0, 1], port).into())))
+                    loopback::connect_addr(loopback, proxy_port, t.param())
                 })
         })
     }
@@ -148,7 +377,7 @@ impl Inbound<()> {
         bind: B,
         profiles: P,
         gateway: G,
-    ) -> (Local<ServerAddr>, impl Future<Output = ()> + Send)
+    ) -> Result<(Local<ServerAddr>, impl Future<Output = ()> + Send), Error>
     where
         B: Bind<ServerConfig>,
         B::Addrs: svc::Param<Remote<ClientAddr>>
@@ -156,34 +385,39 @@ impl Inbound<()> {
             + svc::Param<OrigDstAddr>,
         G: svc::NewService<direct::GatewayConnection, Service = GSvc>,
         G: Clone + Send + Sync + Unpin + 'static,
-        GSvc: svc::Service<direct::GatewayIo<io::ScopedIo<B::Io>>, Response = ()> + Send + 'static,
+        GSvc: svc::Service<direct::GatewayIo<io::ScopedIo<connection_log::Io<B::Io>>>, Response = ()>
+            + Send
+            + 'static,
         GSvc::Error: Into<Error>,
         GSvc::Future: Send,
         P: profiles::GetProfile<profiles::LookupAddr> + Clone + Send + Sync + Unpin + 'static,
         P::Error: Send,
         P::Future: Send,
     {
-        let (Local(ServerAddr(la)), listen) = bind
-            .bind(&self.config.proxy.server)
-            .expect("Failed to bind inbound listener");
+        let (Local(ServerAddr(la)), listen) = bind.bind(&self.config.proxy.server)?;
 
         let serve = async move {
             let shutdown = self.runtime.drain.clone().signaled();
+            let listener = self.runtime.shutdown.listener("inbound");
             let stack = self
                 .into_tcp_connect(la.port())
                 .push_server(la.port(), profiles, gateway)
                 .into_inner();
-            serve::serve(listen, stack, shutdown).await
+            let log = self.config.proxy.connection_log.clone();
+            let listen = self.config.proxy.memory_pressure.gate(listen);
+            let grace = self.runtime.shutdown_grace.clone();
+            serve::serve_logged(listen, listener.track(stack), log, shutdown, grace).await;
+            listener.stopped();
         };
 
-        (Local(ServerAddr(la)), serve)
+        Ok((Local(ServerAddr(la)), serve))
     }
 }
 
 impl<C> Inbound<C>
 where
     C: svc::Service<TcpEndpoint> + Clone + Send + Sync + Unpin + 'static,
-    C::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+    C::Response: io::AsyncRead + io::AsyncWrite + io::Splice + Send + Unpin + 'static,
     C::Error: Into<Error>,
     C::Future: Send,
 {
@@ -196,21 +430,30 @@ where
         >,
     >
     where
-        I: io::AsyncRead + io::AsyncWrite,
+        I: io::AsyncRead + io::AsyncWrite + io::Splice,
         I: Debug + Send + Sync + Unpin + 'static,
     {
-        self.map_stack(|_, rt, connect| {
+        self.map_stack(|config, rt, connect| {
             // Forwards TCP streams that cannot be decoded as HTTP.
             //
             // Looping is always prevented.
             connect
                 .push(rt.metrics.transport.layer_connect())
+                // Counts the bytes written to the application for each port
+                // and client identity.
+                .push(rt.metrics.tcp_forward_bytes.layer_connect())
+                // Describes the original connection to the application, on
+                // ports that are configured to expect it.
+                .push(WriteProxyHeader::layer(config.proxy_protocol_ports.clone()))
                 .push_make_thunk()
-                .push_on_response(
-                    svc::layers()
-                        .push(tcp::Forward::layer())
-                        .push(drain::Retain::layer(rt.drain.clone())),
-                )
+                .push(tcp::NewForward::layer_via(
+                    config.proxy.tcp_splice,
+                    config.port_idle_timeouts.clone(),
+                ))
+                // Counts the bytes read from each client for each port and
+                // client identity.
+                .push(rt.metrics.tcp_forward_bytes.layer_client())
+                .push_on_response(drain::Retain::layer(rt.drain.clone()))
                 .instrument(|_: &_| debug_span!("tcp"))
                 .push(svc::BoxNewService::layer())
                 .check_new::<TcpEndpoint>()
@@ -226,7 +469,7 @@ where
     where
         T: svc::Param<Remote<ClientAddr>> + svc::Param<OrigDstAddr>,
         T: Clone + Send + 'static,
-        I: io::AsyncRead + io::AsyncWrite + io::Peek + io::PeerAddr,
+        I: io::AsyncRead + io::AsyncWrite + io::Peek + io::PeerAddr + io::Splice,
         I: Debug + Send + Sync + Unpin + 'static,
         G: svc::NewService<direct::GatewayConnection, Service = GSvc>,
         G: Clone + Send + Sync + Unpin + 'static,
@@ -241,9 +484,42 @@ where
         let opaque = self
             .clone()
             .push_tcp_forward()
-            .map_stack(|_, rt, tcp| {
-                tcp.push_map_target(TcpEndpoint::from)
+            .map_stack(|cfg, rt, tcp| {
+                let rate_filter = cfg
+                    .connection_rate_limits
+                    .filter(rt.metrics.tcp_connection_rate.clone());
+                let identity_bytes = rt
+                    .metrics
+                    .tcp_identity_bytes
+                    .layer(cfg.identity_bytes_metrics);
+                let policies = PortPolicies::new(cfg, &rt.metrics);
+                let tls_policies = policies.clone();
+                let mut require_tls = cfg.require_tls_for_inbound_ports.filter(rt.metrics.clone());
+                // Routes TLS connections to local ports by their SNI, if so
+                // configured, without terminating TLS.
+                let sni =
+                    NewRouteSni::layer(cfg.sni_routes.clone(), cfg.proxy.detect_protocol_timeout);
+                tcp.push(sni)
+                    // Permitted connections are recorded once they pass all
+                    // of the limits below.
+                    .push(NewRecordAllowed::layer(policies))
                     .push(rt.metrics.transport.layer_accept())
+                    .push(identity_bytes)
+                    // Closes connections that exceed their port's rate limit.
+                    .push_switch(
+                        move |tcp: TcpAccept| rate_filter.filter_tcp(tcp),
+                        |_: TcpAccept| svc::mk(|_: I| future::ok::<(), Error>(())),
+                    )
+                    .push(NewLimitIdentityConnections::layer(
+                        cfg.identity_connection_limits.clone(),
+                    ))
+                    .push_request_filter(move |tcp: TcpAccept| {
+                        let port = tcp.target_addr.port();
+                        require_tls.check(tcp).map_err(|error| {
+                            tls_policies.denied(port, Policy::RequireTls, Decision::TlsRequired);
+                            error
+                        })
+                    })
                     .check_new_service::<TcpAccept, _>()
             })
             .into_stack();
@@ -263,9 +539,31 @@ where
             .push_http_server()
             .map_stack(|cfg, rt, http| {
                 let detect_timeout = cfg.proxy.detect_protocol_timeout;
-                let require_id = cfg.require_identity_for_inbound_ports.clone();
-
-                http.push_map_target(HttpAccept::from)
+                let require_id = cfg
+                    .require_identity_for_inbound_ports
+                    .clone()
+                    .trust_loopback(cfg.trust_loopback);
+                let port_classes = cfg.port_classes.clone();
+                let detect_http = if cfg.opaque_on_http1_parse_failure {
+                    http::DetectHttp::validate_http1()
+                } else {
+                    http::DetectHttp::default()
+                };
+
+                let rate_filter = cfg
+                    .connection_rate_limits
+                    .filter(rt.metrics.tcp_connection_rate.clone());
+                let identity_bytes = rt
+                    .metrics
+                    .tcp_identity_bytes
+                    .layer(cfg.identity_bytes_metrics);
+                let policies = PortPolicies::new(cfg, &rt.metrics);
+                let detect_policies = policies.clone();
+                let tls_policies = policies.clone();
+                let mut require_tls = cfg.require_tls_for_inbound_ports.filter(rt.metrics.clone());
+
+                let accept = http
+                    .push_map_target(HttpAccept::from)
                     .push(svc::UnwrapOr::layer(
                         // When HTTP detection fails, forward the connection to the application as
                         // an opaque TCP stream.
@@ -274,24 +572,95 @@ where
                             .push_on_response(svc::BoxService::layer())
                             .into_inner(),
                     ))
+                    // Permitted connections are recorded once they pass all
+                    // of the limits below.
+                    .push(NewRecordAllowed::layer(detect_policies))
+                    // Connections that exceed their port's rate limit are not
+                    // forwarded. Protocol detection has already completed, so
+                    // that HTTP clients may be told to retry later.
+                    .push_switch(
+                        move |t| rate_filter.filter_detected(t),
+                        connection_rate::limited(cfg.proxy.server.h2_settings, rt.drain.clone()),
+                    )
+                    .push_map_target(|(version, tcp): (Option<http::Version>, TcpAccept)| {
+                        match version {
+                            Some(version) => connection_log::record_protocol(version),
+                            None => connection_log::record_protocol("tcp"),
+                        }
+                        (version, tcp)
+                    })
                     .push_map_target(detect::allow_timeout)
                     .push(svc::BoxNewService::layer())
-                    .push(detect::NewDetectService::layer(
-                        detect_timeout,
-                        http::DetectHttp::default(),
-                    ))
+                    .push(detect::NewDetectService::layer(detect_timeout, detect_http))
                     .check_new_service::<TcpAccept, _>()
-                    .push_request_filter(require_id)
+                    .push_request_filter(move |tcp: TcpAccept| {
+                        let port = tcp.target_addr.port();
+                        require_id.check(tcp).map_err(|error| {
+                            policies.denied(port, Policy::RequireIdentity, Decision::TlsRequired);
+                            error
+                        })
+                    })
+                    .push_request_filter(move |tcp: TcpAccept| {
+                        let port = tcp.target_addr.port();
+                        require_tls.check(tcp).map_err(|error| {
+                            tls_policies.denied(port, Policy::RequireTls, Decision::TlsRequired);
+                            error
+                        })
+                    })
+                    // Closes connections once their client has as many open
+                    // connections as its identity's tier permits.
+                    .push(NewLimitIdentityConnections::layer(
+                        cfg.identity_connection_limits.clone(),
+                    ))
                     .push(rt.metrics.transport.layer_accept())
+                    .push(identity_bytes)
+                    .push_map_target(move |mut tcp: TcpAccept| {
+                        tcp.class = port_classes.class(tcp.target_addr.port());
+                        tcp
+                    })
+                    .push_map_target(|tcp: TcpAccept| {
+                        connection_log::record_client_identity(&tcp.tls);
+                        tcp
+                    })
                     .push_request_filter(TcpAccept::try_from)
-                    .push(svc::BoxNewService::layer())
+                    .push_on_response(connection_log::RecordTlsSession::layer())
+                    .push(svc::BoxNewService::layer());
+
+                // Connections to ports that serve clients outside of the mesh
+                // are terminated with the port's certificates instead of the
+                // proxy's identity.
+                let terminate_tls = cfg.terminate_tls.clone();
+                let terminate = accept
+                    .clone()
+                    .push(tls::server::NewTerminateTls::layer(TerminateParams {
+                        timeout: tls::server::Timeout(detect_timeout),
+                    }))
+                    .into_inner();
+
+                accept
                     .push(tls::NewDetectTls::layer(TlsParams {
                         timeout: tls::server::Timeout(detect_timeout),
                         identity: rt.identity.clone(),
+                        client_auth: cfg.client_auth.clone(),
                     }))
+                    .push_switch(move |t: T| terminate_tls.select(t), terminate)
             })
             .map_stack(|cfg, rt, detect| {
                 let disable_detect = cfg.disable_protocol_detection_for_ports.clone();
+                let port_classes = cfg.port_classes.clone();
+                let log_client_port = cfg.log_client_port;
+                let detect_timeout = cfg.proxy.detect_protocol_timeout;
+                let source_filter = cfg
+                    .source_networks
+                    .filter(rt.metrics.clone(), cfg.trust_loopback);
+                let port_filter = allow_ports::AllowPorts::new(
+                    cfg.default_deny,
+                    &cfg.allow_ports,
+                    proxy_port,
+                    rt.metrics.clone(),
+                );
+                let policies = PortPolicies::new(cfg, &rt.metrics);
+                let port_policies = policies.clone();
                 detect
                     .instrument(|_: &_| debug_span!("proxy"))
                     .push_switch(
@@ -301,7 +670,9 @@ where
                         move |t: T| -> Result<_, Infallible> {
                             let OrigDstAddr(addr) = t.param();
                             if disable_detect.contains(&addr.port()) {
-                                return Ok(svc::Either::B(TcpAccept::port_skipped(t)));
+                                let mut tcp = TcpAccept::port_skipped(t);
+                                tcp.class = port_classes.class(addr.port());
+                                return Ok(svc::Either::B(tcp));
                             }
                             Ok(svc::Either::A(t))
                         },
@@ -323,8 +694,51 @@ where
                         },
                         direct.into_inner(),
                     )
-                    .instrument(|a: &T| {
+                    // Close connections from clients that are not permitted to
+                    // connect to the target port before anything is read from
+                    // them.
+                    .push(source_filter.proxy_layer(policies.clone(), detect_timeout))
+                    .push_switch(
+                        move |t: T| {
+                            let OrigDstAddr(addr) = t.param();
+                            let filtered = source_filter.filter(t);
+                            if let Ok(svc::Either::B(_)) = &filtered {
+                                policies.denied(
+                                    addr.port(),
+                                    Policy::SourceNetworks,
+                                    Decision::Deny,
+                                );
+                            }
+                            filtered
+                        },
+                        |_: Remote<ClientAddr>| svc::mk(|_: I| future::ok::<(), Error>(())),
+                    )
+                    // If connections are denied by default, close connections
+                    // to ports that are not explicitly allowed.
+                    .push_switch(
+                        move |t: T| {
+                            let filtered = port_filter.filter(t);
+                            if let Ok(svc::Either::B(OrigDstAddr(addr))) = &filtered {
+                                port_policies.denied(
+                                    addr.port(),
+                                    Policy::AllowPorts,
+                                    Decision::Deny,
+                                );
+                            }
+                            filtered
+                        },
+                        |_: OrigDstAddr| svc::mk(|_: I| future::ok::<(), Error>(())),
+                    )
+                    .instrument(move |a: &T| {
                         let OrigDstAddr(target_addr) = a.param();
+                        if log_client_port {
+                            let Remote(ClientAddr(client_addr)) = a.param();
+                            return info_span!(
+                                "server",
+                                port = target_addr.port(),
+                                client.port = client_addr.port()
+                            );
+                        }
                         info_span!("server", port = target_addr.port())
                     })
                     .push(rt.metrics.tcp_accept_errors.layer())
@@ -347,10 +761,12 @@ impl<T> ExtractParam<tls::server::Timeout, T> for TlsParams {
     }
 }
 
-impl<T> ExtractParam<Option<LocalCrtKey>, T> for TlsParams {
+impl<T: svc::Param<OrigDstAddr>> ExtractParam<Option<WithClientAuth>, T> for TlsParams {
     #[inline]
-    fn extract_param(&self, _: &T) -> Option<LocalCrtKey> {
-        self.identity.clone()
+    fn extract_param(&self, t: &T) -> Option<WithClientAuth> {
+        let OrigDstAddr(addr) = t.param();
+        let crt_key = self.identity.clone()?;
+        Some(self.client_auth.identity(crt_key, addr.port()))
     }
 }
 
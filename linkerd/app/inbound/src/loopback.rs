@@ -0,0 +1,106 @@
+use linkerd_app_core::transport::{Remote, ServerAddr};
+use std::net::IpAddr;
+
+#[derive(Debug, thiserror::Error)]
+#[error("inbound connection must not target port {0}")]
+pub(crate) struct Loop(u16);
+
+/// Returns the address to which an inbound connection for `port` is made on
+/// the `loopback` address, which may be of either address family.
+///
+/// Connections that would target the inbound proxy's port fail, so that they
+/// cannot loop through the proxy.
+pub(crate) fn connect_addr(
+    loopback: IpAddr,
+    proxy_port: u16,
+    port: u16,
+) -> Result<Remote<ServerAddr>, Loop> {
+    if port == proxy_port {
+        return Err(Loop(port));
+    }
+    Ok(Remote(ServerAddr((loopback, port).into())))
+}
+
+/// Returns true if a connection was made from this host over its loopback
+/// interface, so that it may be trusted as a local connection.
+///
+/// Both the client's address and the connection's original destination must
+/// be loopback addresses. Packets with loopback source addresses are dropped
+/// when they arrive on any other interface, so remote clients cannot spoof
+/// them; and a connection from the local host to the pod's own address is
+/// not considered local, since its source address is not a loopback address
+/// either.
+pub(crate) fn is_local(client: IpAddr, target: IpAddr) -> bool {
+    is_loopback(client) && is_loopback(target)
+}
+
+fn is_loopback(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        // IPv4 clients may be accepted on dual-stack listeners, in which case
+        // their addresses are mapped into the IPv6 address space.
+        IpAddr::V6(ip) => match ip.segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, _] => hi >> 8 == 127,
+            _ => ip.is_loopback(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util, Inbound};
+    use linkerd_app_core::{svc::ServiceExt, Error};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn connect_addrs() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        let Remote(ServerAddr(addr)) = connect_addr(ip("127.0.0.1"), 4143, 8080).unwrap();
+        assert_eq!(addr, SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert!(addr.is_ipv4());
+
+        let Remote(ServerAddr(addr)) = connect_addr(ip("::1"), 4143, 8080).unwrap();
+        assert_eq!(addr, "[::1]:8080".parse::<SocketAddr>().unwrap());
+        assert!(addr.is_ipv6());
+
+        // Connections to the proxy's port are refused in either address
+        // family.
+        for loopback in &[ip("127.0.0.1"), ip("::1")] {
+            let Loop(port) = connect_addr(*loopback, 4143, 4143).unwrap_err();
+            assert_eq!(port, 4143);
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tcp_connect_refuses_loops() {
+        let mut cfg = test_util::default_config();
+        cfg.connect_loopback = "::1".parse().unwrap();
+        let (rt, _shutdown) = test_util::runtime();
+        let connect = Inbound::new(cfg, rt)
+            .into_tcp_connect::<u16>(4143)
+            .into_inner();
+        let error: Error = match connect.oneshot(4143).await {
+            Ok(_) => panic!("connection to the proxy's port must fail"),
+            Err(error) => error,
+        };
+        assert!(error.is::<Loop>(), "unexpected error: {}", error);
+    }
+
+    #[test]
+    fn local() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(is_local(ip("127.0.0.1"), ip("127.0.0.1")));
+        assert!(is_local(ip("127.1.2.3"), ip("127.0.0.1")));
+        assert!(is_local(ip("::1"), ip("::1")));
+        assert!(is_local(ip("::ffff:127.0.0.1"), ip("::1")));
+
+        assert!(
+            !is_local(ip("127.0.0.1"), ip("10.0.0.1")),
+            "connections to the pod's address are not local"
+        );
+        assert!(!is_local(ip("10.0.0.1"), ip("127.0.0.1")));
+        assert!(!is_local(ip("::ffff:10.0.0.1"), ip("::1")));
+    }
+}
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, sync::Arc};
+
+/// Determines how much detail is recorded in the HTTP metrics of traffic on
+/// an inbound port.
+///
+/// Transport metrics, e.g. the number of connections opened and closed on a
+/// port, are always recorded, regardless of the port's granularity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MetricsGranularity {
+    /// Requests are counted by endpoint and by profile route.
+    Detailed,
+
+    /// Requests are counted by endpoint, but not by route.
+    Minimal,
+
+    /// Requests are not counted.
+    Off,
+}
+
+/// Configures, by port, the granularity of inbound HTTP metrics.
+///
+/// Ports without a granularity of their own use the default granularity,
+/// which is `Detailed` unless otherwise configured.
+#[derive(Clone, Debug, Default)]
+pub struct MetricsGranularityForPorts {
+    default: MetricsGranularity,
+    ports: Arc<HashMap<u16, MetricsGranularity>>,
+}
+
+// === impl MetricsGranularity ===
+
+impl Default for MetricsGranularity {
+    fn default() -> Self {
+        Self::Detailed
+    }
+}
+
+// === impl MetricsGranularityForPorts ===
+
+impl MetricsGranularityForPorts {
+    pub fn new(
+        default: MetricsGranularity,
+        ports: impl IntoIterator<Item = (u16, MetricsGranularity)>,
+    ) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+        }
+    }
+
+    fn granularity(&self, port: u16) -> MetricsGranularity {
+        self.ports.get(&port).copied().unwrap_or(self.default)
+    }
+
+    /// Whether requests on the port are counted by endpoint.
+    pub(crate) fn endpoint(&self, port: u16) -> bool {
+        self.granularity(port) != MetricsGranularity::Off
+    }
+
+    /// Whether requests on the port are counted by profile route.
+    pub(crate) fn route(&self, port: u16) -> bool {
+        self.granularity(port) == MetricsGranularity::Detailed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_fall_back_to_default() {
+        let granularity = MetricsGranularityForPorts::new(
+            MetricsGranularity::Minimal,
+            vec![
+                (8080, MetricsGranularity::Detailed),
+                (5432, MetricsGranularity::Off),
+            ],
+        );
+        assert!(granularity.endpoint(8080) && granularity.route(8080));
+        assert!(granularity.endpoint(9090) && !granularity.route(9090));
+        assert!(!granularity.endpoint(5432) && !granularity.route(5432));
+
+        let granularity = MetricsGranularityForPorts::default();
+        assert!(granularity.endpoint(9090) && granularity.route(9090));
+    }
+}
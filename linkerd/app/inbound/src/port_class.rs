@@ -0,0 +1,93 @@
+use linkerd_app_core::metrics::PortClass;
+use std::{collections::HashMap, sync::Arc};
+
+/// Assigns inbound ports to operator-defined classes, which label the metrics
+/// of all traffic on those ports.
+///
+/// When no classes are configured, metrics are not labeled with a class at
+/// all. Otherwise, ports without a class are labeled `unclassified`.
+#[derive(Clone, Debug, Default)]
+pub struct PortClasses {
+    ports: Arc<HashMap<u16, PortClass>>,
+}
+
+// === impl PortClasses ===
+
+impl PortClasses {
+    pub(crate) fn class(&self, port: u16) -> Option<PortClass> {
+        if self.ports.is_empty() {
+            return None;
+        }
+        let class = self.ports.get(&port).cloned();
+        Some(class.unwrap_or_else(PortClass::unclassified))
+    }
+}
+
+impl<T: IntoIterator<Item = (u16, String)>> From<T> for PortClasses {
+    fn from(ports: T) -> Self {
+        // Ports in the same class share a name.
+        let mut classes = HashMap::<String, PortClass>::new();
+        let ports = ports
+            .into_iter()
+            .map(|(port, name)| {
+                let class = classes
+                    .entry(name)
+                    .or_insert_with_key(|name| PortClass::new(name.as_str()))
+                    .clone();
+                (port, class)
+            })
+            .collect();
+        Self {
+            ports: Arc::new(ports),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::TcpAccept;
+    use linkerd_app_core::{
+        metrics::FmtLabels,
+        svc::Param,
+        tls,
+        transport::{labels::Key, ClientAddr, Remote},
+        Conditional,
+    };
+    use std::fmt;
+
+    struct Labels(Key);
+
+    impl fmt::Display for Labels {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_labels(f)
+        }
+    }
+
+    fn labels(classes: &PortClasses, port: u16) -> String {
+        let tcp = TcpAccept {
+            target_addr: ([192, 0, 2, 2], port).into(),
+            client_addr: Remote(ClientAddr(([192, 0, 2, 3], 50000).into())),
+            tls: Conditional::None(tls::NoServerTls::NoClientHello),
+            class: classes.class(port),
+        };
+        Labels(tcp.param()).to_string()
+    }
+
+    #[test]
+    fn labels_ports_by_class() {
+        let classes = PortClasses::from(vec![
+            (8080, "api".to_string()),
+            (8081, "api".to_string()),
+            (9990, "admin".to_string()),
+        ]);
+
+        assert!(labels(&classes, 8080).ends_with(",port_class=\"api\""));
+        assert!(labels(&classes, 8081).ends_with(",port_class=\"api\""));
+        assert!(labels(&classes, 9990).ends_with(",port_class=\"admin\""));
+        assert!(labels(&classes, 5432).ends_with(",port_class=\"unclassified\""));
+
+        let unconfigured = labels(&PortClasses::default(), 8080);
+        assert!(!unconfigured.contains("port_class"), "{}", unconfigured);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    target::TcpAccept, Config, RequireIdentityForPorts, RequireTlsForPorts, SourceNetworksForPorts,
+};
+use linkerd_app_core::{
+    config::PortSet,
+    metrics::{
+        self,
+        policy_decisions::{Decision, Policy, Protocol},
+    },
+    proxy::http,
+    svc,
+};
+use std::task::{Context, Poll};
+
+/// Describes the policies that govern inbound connections on each port, so
+/// that the decision made for each connection may be recorded in the
+/// `inbound_policy_decisions_total` metric.
+///
+/// Each connection is recorded once: denied connections are recorded by the
+/// policy that denied them and permitted connections are recorded, once they
+/// are forwarded, by the most specific policy that applies to their target
+/// port. Connections that target the inbound proxy's own port are not
+/// recorded, since they are handled by the direct stack.
+#[derive(Clone, Debug)]
+pub(crate) struct PortPolicies {
+    default_deny: bool,
+    require_identity: RequireIdentityForPorts,
+    require_tls: RequireTlsForPorts,
+    source_networks: SourceNetworksForPorts,
+    opaque: PortSet,
+    decisions: metrics::policy_decisions::Registry,
+}
+
+/// Records connections as permitted once they are dispatched to the inner
+/// service, after all of the filters and limits that may close them.
+#[derive(Clone, Debug)]
+pub(crate) struct NewRecordAllowed<N> {
+    inner: N,
+    policies: PortPolicies,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RecordAllowed<S> {
+    inner: S,
+    policies: PortPolicies,
+    port: u16,
+    protocol: Protocol,
+}
+
+// === impl PortPolicies ===
+
+impl PortPolicies {
+    pub(crate) fn new(config: &Config, metrics: &metrics::Proxy) -> Self {
+        Self {
+            default_deny: config.default_deny,
+            require_identity: config.require_identity_for_inbound_ports.clone(),
+            require_tls: config.require_tls_for_inbound_ports.clone(),
+            source_networks: config.source_networks.clone(),
+            opaque: config.disable_protocol_detection_for_ports.clone(),
+            decisions: metrics.tcp_policy_decisions.clone(),
+        }
+    }
+
+    /// Records a connection that was permitted and forwarded as `protocol`.
+    pub(crate) fn allowed(&self, port: u16, protocol: Protocol) {
+        self.decisions
+            .incr(port, self.policy(port), Decision::Allow, protocol);
+    }
+
+    /// Records a connection that was denied by `policy`, before protocol
+    /// detection.
+    pub(crate) fn denied(&self, port: u16, policy: Policy, decision: Decision) {
+        let protocol = if self.opaque.contains(&port) {
+            Protocol::Opaque
+        } else {
+            Protocol::Undetected
+        };
+        self.decisions.incr(port, policy, decision, protocol);
+    }
+
+    /// Returns the most specific policy that applies to connections on `port`.
+    fn policy(&self, port: u16) -> Policy {
+        if self.require_identity.requires(port) {
+            return Policy::RequireIdentity;
+        }
+        if self.require_tls.requires(port) {
+            return Policy::RequireTls;
+        }
+        if self.source_networks.restricts(port) {
+            return Policy::SourceNetworks;
+        }
+        if self.default_deny {
+            return Policy::AllowPorts;
+        }
+        Policy::Default
+    }
+}
+
+// === impl NewRecordAllowed ===
+
+impl<N> NewRecordAllowed<N> {
+    pub(crate) fn layer(policies: PortPolicies) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            policies: policies.clone(),
+        })
+    }
+
+    fn record<S>(&self, inner: S, port: u16, protocol: Protocol) -> RecordAllowed<S> {
+        RecordAllowed {
+            inner,
+            policies: self.policies.clone(),
+            port,
+            protocol,
+        }
+    }
+}
+
+/// Connections that target opaque ports.
+impl<N: svc::NewService<TcpAccept>> svc::NewService<TcpAccept> for NewRecordAllowed<N> {
+    type Service = RecordAllowed<N::Service>;
+
+    fn new_service(&mut self, tcp: TcpAccept) -> Self::Service {
+        let port = tcp.target_addr.port();
+        let inner = self.inner.new_service(tcp);
+        self.record(inner, port, Protocol::Opaque)
+    }
+}
+
+/// Connections whose protocol has been detected.
+impl<N> svc::NewService<(Option<http::Version>, TcpAccept)> for NewRecordAllowed<N>
+where
+    N: svc::NewService<(Option<http::Version>, TcpAccept)>,
+{
+    type Service = RecordAllowed<N::Service>;
+
+    fn new_service(&mut self, (version, tcp): (Option<http::Version>, TcpAccept)) -> Self::Service {
+        let port = tcp.target_addr.port();
+        let protocol = match version {
+            Some(_) => Protocol::Http,
+            None => Protocol::Tcp,
+        };
+        let inner = self.inner.new_service((version, tcp));
+        self.record(inner, port, protocol)
+    }
+}
+
+// === impl RecordAllowed ===
+
+impl<I, S: svc::Service<I>> svc::Service<I> for RecordAllowed<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        self.policies.allowed(self.port, self.protocol);
+        self.inner.call(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use linkerd_app_core::metrics::FmtMetrics;
+
+    #[test]
+    fn records_decisions_by_policy() {
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let mut config = test_util::default_config();
+        config.default_deny = true;
+        config.require_identity_for_inbound_ports = vec![8080].into();
+        config.require_tls_for_inbound_ports = vec![8080, 8443].into();
+        config.source_networks =
+            SourceNetworksForPorts::new(vec![(9090, "10.0.0.0/8".parse().unwrap())], vec![]);
+        config.disable_protocol_detection_for_ports = vec![5432].into_iter().collect();
+        let policies = PortPolicies::new(&config, &metrics.inbound);
+
+        policies.allowed(8080, Protocol::Http);
+        policies.denied(8080, Policy::RequireIdentity, Decision::TlsRequired);
+        policies.allowed(8443, Protocol::Http);
+        policies.denied(8443, Policy::RequireTls, Decision::TlsRequired);
+        policies.allowed(9090, Protocol::Tcp);
+        policies.denied(9090, Policy::SourceNetworks, Decision::Deny);
+        policies.allowed(5432, Protocol::Opaque);
+        policies.denied(5432, Policy::AllowPorts, Decision::Deny);
+        policies.denied(7070, Policy::AllowPorts, Decision::Deny);
+
+        let metrics = report.as_display().to_string();
+        for labels in &[
+            "port=\"8080\",policy=\"require_identity\",decision=\"allow\",protocol=\"http\"",
+            "port=\"8080\",policy=\"require_identity\",decision=\"tls_required\",protocol=\"undetected\"",
+            "port=\"8443\",policy=\"require_tls\",decision=\"allow\",protocol=\"http\"",
+            "port=\"8443\",policy=\"require_tls\",decision=\"tls_required\",protocol=\"undetected\"",
+            "port=\"9090\",policy=\"source_networks\",decision=\"allow\",protocol=\"tcp\"",
+            "port=\"9090\",policy=\"source_networks\",decision=\"deny\",protocol=\"undetected\"",
+            "port=\"5432\",policy=\"allow_ports\",decision=\"allow\",protocol=\"opaque\"",
+            "port=\"5432\",policy=\"allow_ports\",decision=\"deny\",protocol=\"opaque\"",
+            "port=\"7070\",policy=\"allow_ports\",decision=\"deny\",protocol=\"undetected\"",
+        ] {
+            let line = format!("inbound_policy_decisions_total{{{}}} 1\n", labels);
+            assert!(metrics.contains(&line), "{} not in:\n{}", line, metrics);
+        }
+
+        let policies = PortPolicies {
+            default_deny: false,
+            ..policies
+        };
+        assert_eq!(policies.policy(7070), Policy::Default);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_allowed_connections_once_dispatched() {
+        use linkerd_app_core::{
+            svc::{Layer, NewService, ServiceExt},
+            tls,
+            transport::{ClientAddr, Remote},
+            Conditional, Error,
+        };
+
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let mut config = test_util::default_config();
+        config.default_deny = true;
+        let policies = PortPolicies::new(&config, &metrics.inbound);
+        let mut new_svc =
+            NewRecordAllowed::layer(policies).layer(|_: (Option<http::Version>, TcpAccept)| {
+                svc::mk(|_: ()| futures::future::ok::<(), Error>(()))
+            });
+        let tcp = TcpAccept {
+            target_addr: ([192, 0, 2, 2], 8080).into(),
+            client_addr: Remote(ClientAddr(([192, 0, 2, 3], 40000).into())),
+            tls: Conditional::None(tls::NoServerTls::NoClientHello),
+            class: None,
+        };
+
+        // Connections that are closed before they are dispatched, e.g. by
+        // rate limits, are not recorded.
+        let svc = new_svc.new_service((Some(http::Version::Http1), tcp));
+        let line = "inbound_policy_decisions_total{port=\"8080\",policy=\"allow_ports\",decision=\"allow\",protocol=\"http\"} 1\n";
+        assert!(!report.as_display().to_string().contains(line));
+
+        svc.oneshot(()).await.unwrap();
+        let metrics = report.as_display().to_string();
+        assert!(metrics.contains(line), "{} not in:\n{}", line, metrics);
+    }
+}
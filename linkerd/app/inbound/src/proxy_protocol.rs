@@ -0,0 +1,377 @@
+use crate::target::TcpEndpoint;
+use futures::{prelude::*, ready};
+use linkerd_app_core::{
+    config::PortSet,
+    io, svc,
+    transport::{ClientAddr, OrigDstAddr, Remote},
+    Error,
+};
+use pin_project::pin_project;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::debug;
+
+/// The addresses of a forwarded connection, which are described to the
+/// application by a PROXY protocol header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProxyAddrs {
+    pub client: Remote<ClientAddr>,
+    pub orig_dst: OrigDstAddr,
+}
+
+/// Prepends a PROXY protocol v2 header to the forwarded connections of the
+/// configured ports, so that applications may learn the addresses of the
+/// connections' original clients.
+///
+/// Applications that do not expect the header would interpret it as data, so
+/// headers are only written on ports that are explicitly configured.
+#[derive(Clone, Debug)]
+pub(crate) struct WriteProxyHeader<C> {
+    inner: C,
+    ports: Arc<PortSet>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub(crate) struct WriteProxyHeaderFuture<F, I> {
+    #[pin]
+    connect: F,
+    header: Vec<u8>,
+    written: usize,
+    io: Option<I>,
+}
+
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Protocol version 2, with the `PROXY` command.
+const VERSION_COMMAND: u8 = 0x21;
+
+const TCP_OVER_IPV4: u8 = 0x11;
+const TCP_OVER_IPV6: u8 = 0x21;
+
+/// The maximum length of a PROXY protocol v1 header, which is long enough to
+/// hold the addresses of any v2 header for a TCP connection.
+pub(crate) const MAX_HEADER_LEN: usize = 107;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+// === impl WriteProxyHeader ===
+
+impl<C> WriteProxyHeader<C> {
+    pub(crate) fn layer(ports: PortSet) -> impl svc::Layer<C, Service = Self> + Clone {
+        let ports = Arc::new(ports);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            ports: ports.clone(),
+        })
+    }
+}
+
+impl<C> svc::Service<TcpEndpoint> for WriteProxyHeader<C>
+where
+    C: svc::Service<TcpEndpoint>,
+    C::Response: io::AsyncWrite + Unpin,
+    C::Error: Into<Error>,
+{
+    type Response = C::Response;
+    type Error = Error;
+    type Future = WriteProxyHeaderFuture<C::Future, C::Response>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, endpoint: TcpEndpoint) -> Self::Future {
+        let header = match endpoint.proxy_addrs {
+            Some(addrs) if self.ports.contains(&endpoint.port) => {
+                debug!(client.addr = %addrs.client, "Writing PROXY protocol header");
+                encode(addrs)
+            }
+            _ => Vec::new(),
+        };
+        WriteProxyHeaderFuture {
+            connect: self.inner.call(endpoint),
+            header,
+            written: 0,
+            io: None,
+        }
+    }
+}
+
+// === impl WriteProxyHeaderFuture ===
+
+impl<F, I, E> Future for WriteProxyHeaderFuture<F, I>
+where
+    F: Future<Output = Result<I, E>>,
+    I: io::AsyncWrite + Unpin,
+    E: Into<Error>,
+{
+    type Output = Result<I, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            if let Some(io) = this.io.as_mut() {
+                while *this.written < this.header.len() {
+                    let buf = &this.header[*this.written..];
+                    let n = ready!(io::AsyncWrite::poll_write(Pin::new(&mut *io), cx, buf))?;
+                    if n == 0 {
+                        return Poll::Ready(Err(std::io::Error::from(
+                            std::io::ErrorKind::WriteZero,
+                        )
+                        .into()));
+                    }
+                    *this.written += n;
+                }
+                ready!(io::AsyncWrite::poll_flush(Pin::new(&mut *io), cx))?;
+                return Poll::Ready(Ok(this.io.take().expect("polled after completion")));
+            }
+
+            let io = ready!(this.connect.as_mut().poll(cx)).map_err(Into::into)?;
+            if this.header.is_empty() {
+                return Poll::Ready(Ok(io));
+            }
+            *this.io = Some(io);
+        }
+    }
+}
+
+/// Encodes a PROXY protocol v2 header describing a TCP connection between the
+/// given addresses.
+///
+/// IPv4 addresses are mapped into the IPv6 address space if the other address
+/// is an IPv6 address, since both addresses must have the same family.
+fn encode(
+    ProxyAddrs {
+        client: Remote(ClientAddr(src)),
+        orig_dst: OrigDstAddr(dst),
+    }: ProxyAddrs,
+) -> Vec<u8> {
+    let mut header = SIGNATURE.to_vec();
+    header.push(VERSION_COMMAND);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+            header.push(TCP_OVER_IPV4);
+            header.extend_from_slice(&12u16.to_be_bytes());
+            header.extend_from_slice(&src_ip.octets());
+            header.extend_from_slice(&dst_ip.octets());
+        }
+        (src_ip, dst_ip) => {
+            header.push(TCP_OVER_IPV6);
+            header.extend_from_slice(&36u16.to_be_bytes());
+            header.extend_from_slice(&to_ipv6(src_ip));
+            header.extend_from_slice(&to_ipv6(dst_ip));
+        }
+    }
+    header.extend_from_slice(&src.port().to_be_bytes());
+    header.extend_from_slice(&dst.port().to_be_bytes());
+    header
+}
+
+/// Decodes the client address described by the PROXY protocol header at the
+/// start of `buf`.
+///
+/// Returns `None` if `buf` does not begin with a complete v1 or v2 header
+/// describing a TCP connection.
+pub(crate) fn decode_client(buf: &[u8]) -> Option<SocketAddr> {
+    if buf.starts_with(&SIGNATURE) {
+        return decode_v2_client(buf);
+    }
+    if buf.starts_with(V1_PREFIX) {
+        return decode_v1_client(buf);
+    }
+    None
+}
+
+fn decode_v2_client(buf: &[u8]) -> Option<SocketAddr> {
+    let header = buf.get(12..16)?;
+    // Headers with the `LOCAL` command describe connections that the sender
+    // made on its own behalf, so they do not describe a client.
+    if header[0] != VERSION_COMMAND {
+        return None;
+    }
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+    let addrs = buf.get(16..16 + len)?;
+    match header[1] {
+        TCP_OVER_IPV4 => {
+            let ip = addrs.get(0..4)?;
+            let port = addrs.get(8..10)?;
+            let ip = Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]);
+            Some(SocketAddr::new(
+                ip.into(),
+                u16::from_be_bytes([port[0], port[1]]),
+            ))
+        }
+        TCP_OVER_IPV6 => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(addrs.get(0..16)?);
+            let port = addrs.get(32..34)?;
+            let ip = Ipv6Addr::from(ip);
+            Some(SocketAddr::new(
+                ip.into(),
+                u16::from_be_bytes([port[0], port[1]]),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn decode_v1_client(buf: &[u8]) -> Option<SocketAddr> {
+    let len = buf
+        .windows(2)
+        .take(MAX_HEADER_LEN - 1)
+        .position(|w| w == b"\r\n")?;
+    let line = std::str::from_utf8(&buf[V1_PREFIX.len()..len]).ok()?;
+    let mut fields = line.split(' ');
+    match fields.next()? {
+        "TCP4" | "TCP6" => {}
+        _ => return None,
+    }
+    let ip = fields.next()?.parse::<IpAddr>().ok()?;
+    let _dst_ip = fields.next()?;
+    let port = fields.next()?.parse::<u16>().ok()?;
+    Some(SocketAddr::new(ip, port))
+}
+
+fn to_ipv6(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::ServiceExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn addrs(client: SocketAddr, orig_dst: SocketAddr) -> ProxyAddrs {
+        ProxyAddrs {
+            client: Remote(ClientAddr(client)),
+            orig_dst: OrigDstAddr(orig_dst),
+        }
+    }
+
+    #[test]
+    fn encodes_ipv4() {
+        let header = encode(addrs(
+            ([192, 0, 2, 3], 40000).into(),
+            ([192, 0, 2, 2], 8080).into(),
+        ));
+        let mut expected = SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        expected.extend_from_slice(&[192, 0, 2, 3, 192, 0, 2, 2]);
+        expected.extend_from_slice(&[0x9c, 0x40, 0x1f, 0x90]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn encodes_ipv6() {
+        let client = "[2001:db8::3]:40000".parse::<SocketAddr>().unwrap();
+        let header = encode(addrs(client, ([192, 0, 2, 2], 8080).into()));
+        assert_eq!(&header[12..16], &[0x21, 0x21, 0, 36]);
+        assert_eq!(&header[16..32], &to_ipv6(client.ip()));
+        assert_eq!(
+            &header[32..48],
+            &"::ffff:192.0.2.2"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+            "IPv4 addresses must be mapped to IPv6 addresses"
+        );
+        assert_eq!(&header[48..], &[0x9c, 0x40, 0x1f, 0x90]);
+    }
+
+    #[test]
+    fn decodes_clients() {
+        let client = ([192, 0, 2, 3], 40000).into();
+        let header = encode(addrs(client, ([192, 0, 2, 2], 8080).into()));
+        assert_eq!(decode_client(&header), Some(client));
+        assert_eq!(
+            decode_client(&header[..header.len() - 1]),
+            None,
+            "truncated headers must not be decoded"
+        );
+
+        let client = "[2001:db8::3]:40000".parse().unwrap();
+        let header = encode(addrs(client, ([192, 0, 2, 2], 8080).into()));
+        assert_eq!(decode_client(&header), Some(client));
+
+        let mut local = encode(addrs(client, ([192, 0, 2, 2], 8080).into()));
+        local[12] = 0x20;
+        assert_eq!(decode_client(&local), None);
+
+        assert_eq!(
+            decode_client(b"PROXY TCP4 192.0.2.3 192.0.2.2 40000 8080\r\nhello"),
+            Some(([192, 0, 2, 3], 40000).into())
+        );
+        assert_eq!(
+            decode_client(b"PROXY TCP6 2001:db8::3 2001:db8::2 40000 8080\r\n"),
+            Some(client)
+        );
+        assert_eq!(decode_client(b"PROXY UNKNOWN\r\n"), None);
+        assert_eq!(decode_client(b"PROXY TCP4 192.0.2.3"), None);
+        assert_eq!(decode_client(b"GET / HTTP/1.1\r\n"), None);
+    }
+
+    /// Connects to `endpoint` and returns the first `n` bytes received by the
+    /// application, after `data` is written to the connection.
+    async fn connect(ports: PortSet, endpoint: TcpEndpoint, data: &[u8], n: usize) -> Vec<u8> {
+        let (io, mut app) = tokio::io::duplex(1024);
+        let mut io = Some(io);
+        let inner = svc::mk(move |_: TcpEndpoint| future::ok::<_, Error>(io.take().unwrap()));
+        let mut connect = svc::Layer::layer(&WriteProxyHeader::layer(ports), inner);
+        let mut io = connect.ready().await.unwrap().call(endpoint).await.unwrap();
+        io.write_all(data).await.unwrap();
+
+        let mut buf = vec![0; n];
+        app.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn writes_headers_on_configured_ports() {
+        let ports = vec![8080].into_iter().collect::<PortSet>();
+        let proxy_addrs = Some(addrs(
+            ([192, 0, 2, 3], 40000).into(),
+            ([192, 0, 2, 2], 8080).into(),
+        ));
+
+        let header = encode(proxy_addrs.unwrap());
+        let received = connect(
+            ports.clone(),
+            TcpEndpoint {
+                port: 8080,
+                proxy_addrs,
+                client_id: None,
+            },
+            b"hello",
+            header.len() + 5,
+        )
+        .await;
+        assert_eq!(&received[..header.len()], &header[..]);
+        assert_eq!(&received[header.len()..], b"hello");
+
+        let received = connect(
+            ports,
+            TcpEndpoint {
+                port: 9090,
+                proxy_addrs,
+                client_id: None,
+            },
+            b"hello",
+            5,
+        )
+        .await;
+        assert_eq!(
+            received, b"hello",
+            "headers must not be written on other ports"
+        );
+    }
+}
@@ -1,13 +1,17 @@
-use crate::target::TcpAccept;
+use crate::{loopback, target::TcpAccept};
 use linkerd_app_core::{config::PortSet, svc::stack::Predicate, tls, Conditional, Error};
 use std::sync::Arc;
 use thiserror::Error;
 
 /// A connection policy that fails connections that don't have a client identity
 /// if they target one of the configured local ports.
+///
+/// Connections made over the loopback interface may be trusted, so that they
+/// need not have a client identity.
 #[derive(Clone, Debug)]
 pub struct RequireIdentityForPorts {
     ports: Arc<PortSet>,
+    trust_loopback: bool,
 }
 
 #[derive(Debug, Error)]
@@ -20,16 +24,35 @@ impl<T: IntoIterator<Item = u16>> From<T> for RequireIdentityForPorts {
     fn from(ports: T) -> Self {
         Self {
             ports: Arc::new(ports.into_iter().collect()),
+            trust_loopback: false,
         }
     }
 }
 
+impl RequireIdentityForPorts {
+    /// Permits connections made over the loopback interface without a client
+    /// identity.
+    pub fn trust_loopback(self, trust_loopback: bool) -> Self {
+        Self {
+            trust_loopback,
+            ..self
+        }
+    }
+
+    /// Returns true if connections to `port` must have a client identity.
+    pub(crate) fn requires(&self, port: u16) -> bool {
+        self.ports.contains(&port)
+    }
+}
+
 impl Predicate<TcpAccept> for RequireIdentityForPorts {
     type Request = TcpAccept;
 
     fn check(&mut self, meta: TcpAccept) -> Result<TcpAccept, Error> {
         let port = meta.target_addr.port();
-        let id_required = self.ports.contains(&port);
+        let id_required = self.requires(port)
+            && !(self.trust_loopback
+                && loopback::is_local(meta.client_addr.as_ref().ip(), meta.target_addr.ip()));
 
         tracing::debug!(%port, tls = ?meta.tls, %id_required);
         if id_required {
@@ -44,3 +67,47 @@ impl Predicate<TcpAccept> for RequireIdentityForPorts {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::transport::{ClientAddr, Remote};
+    use std::net::SocketAddr;
+
+    fn accept(client: SocketAddr, target: SocketAddr) -> TcpAccept {
+        TcpAccept {
+            target_addr: target,
+            client_addr: Remote(ClientAddr(client)),
+            tls: Conditional::None(tls::NoServerTls::NoClientHello),
+            class: None,
+        }
+    }
+
+    #[test]
+    fn loopback_bypasses_identity_requirement() {
+        let mut require = RequireIdentityForPorts::from(vec![8080]).trust_loopback(true);
+        let local = accept(
+            ([127, 0, 0, 1], 40000).into(),
+            ([127, 0, 0, 1], 8080).into(),
+        );
+        assert!(require.check(local.clone()).is_ok());
+
+        let remote = accept(([10, 0, 0, 2], 40000).into(), ([10, 0, 0, 1], 8080).into());
+        assert!(
+            require.check(remote).unwrap_err().is::<IdentityRequired>(),
+            "remote connections must still require identity"
+        );
+
+        let spoofed = accept(([127, 0, 0, 1], 40000).into(), ([10, 0, 0, 1], 8080).into());
+        assert!(
+            require.check(spoofed).unwrap_err().is::<IdentityRequired>(),
+            "loopback clients must connect to a loopback address"
+        );
+
+        let mut untrusted = RequireIdentityForPorts::from(vec![8080]);
+        assert!(
+            untrusted.check(local).unwrap_err().is::<IdentityRequired>(),
+            "loopback connections must not be trusted by default"
+        );
+    }
+}
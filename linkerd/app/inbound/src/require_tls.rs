@@ -0,0 +1,119 @@
+use crate::target::TcpAccept;
+use linkerd_app_core::{config::PortSet, metrics, svc::stack::Predicate, Conditional, Error};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::debug;
+
+/// A connection policy that closes plaintext connections if they target one of
+/// the configured local ports.
+///
+/// Connections are checked once their TLS status is known, so connections
+/// that are secured by TLS are permitted whether or not they have a client
+/// identity. Denied connections are counted by the
+/// `inbound_tcp_tls_required_denied_total` metric. Ports that are not
+/// configured accept plaintext connections.
+#[derive(Clone, Debug, Default)]
+pub struct RequireTlsForPorts {
+    ports: Arc<PortSet>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RequireTls {
+    ports: RequireTlsForPorts,
+    metrics: metrics::Proxy,
+}
+
+#[derive(Debug, Error)]
+#[error("TLS required")]
+pub struct TlsRequired(());
+
+// === impl RequireTlsForPorts ===
+
+impl<T: IntoIterator<Item = u16>> From<T> for RequireTlsForPorts {
+    fn from(ports: T) -> Self {
+        Self {
+            ports: Arc::new(ports.into_iter().collect()),
+        }
+    }
+}
+
+impl RequireTlsForPorts {
+    /// Returns true if connections to `port` must be secured by TLS.
+    pub(crate) fn requires(&self, port: u16) -> bool {
+        self.ports.contains(&port)
+    }
+
+    pub(crate) fn filter(&self, metrics: metrics::Proxy) -> RequireTls {
+        RequireTls {
+            ports: self.clone(),
+            metrics,
+        }
+    }
+}
+
+// === impl RequireTls ===
+
+impl Predicate<TcpAccept> for RequireTls {
+    type Request = TcpAccept;
+
+    fn check(&mut self, tcp: TcpAccept) -> Result<TcpAccept, Error> {
+        let port = tcp.target_addr.port();
+        if !self.ports.requires(port) {
+            return Ok(tcp);
+        }
+
+        if let Conditional::None(reason) = &tcp.tls {
+            debug!(%port, %reason, "Denying a plaintext connection to a port that requires TLS");
+            self.metrics.tcp_tls_required_denied.incr(port);
+            return Err(TlsRequired(()).into());
+        }
+        Ok(tcp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{
+        metrics::FmtMetrics,
+        tls,
+        transport::{ClientAddr, Remote},
+    };
+
+    fn accept(port: u16, tls: tls::ConditionalServerTls) -> TcpAccept {
+        TcpAccept {
+            target_addr: ([192, 0, 2, 2], port).into(),
+            client_addr: Remote(ClientAddr(([192, 0, 2, 3], 40000).into())),
+            tls,
+            class: None,
+        }
+    }
+
+    #[test]
+    fn denies_plaintext_connections() {
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let mut require = RequireTlsForPorts::from(vec![8443]).filter(metrics.inbound);
+
+        let plaintext = Conditional::None(tls::NoServerTls::NoClientHello);
+        let secured = Conditional::Some(tls::ServerTls::Established {
+            client_id: None,
+            negotiated_protocol: None,
+        });
+        assert!(require
+            .check(accept(8443, plaintext.clone()))
+            .unwrap_err()
+            .is::<TlsRequired>());
+        assert!(
+            require.check(accept(8443, secured)).is_ok(),
+            "TLS connections need not have a client identity"
+        );
+        assert!(
+            require.check(accept(8080, plaintext)).is_ok(),
+            "other ports must accept plaintext connections"
+        );
+
+        let metrics = report.as_display().to_string();
+        assert!(metrics.contains("inbound_tcp_tls_required_denied_total{port=\"8443\"} 1\n"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::target::{TcpAccept, TcpEndpoint};
+use futures::TryFutureExt;
+use linkerd_app_core::{
+    identity, io, svc,
+    tls::{self, server::DetectIo, ServerId},
+    Error,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::time::Duration;
+use tower::util::ServiceExt;
+use tracing::debug;
+
+/// Routes connections on opaque ports to local ports by the server name of
+/// their TLS ClientHello.
+///
+/// TLS is not terminated: the ClientHello is peeked (or buffered, if it cannot
+/// be peeked) and the connection is forwarded as-is to the port configured
+/// for its server name. Connections without a configured server name,
+/// including those that do not begin with a ClientHello, are forwarded to
+/// their original destination port, as are connections that do not send a
+/// ClientHello before the detection timeout. Only ports on which protocol
+/// detection is disabled are routed.
+#[derive(Clone, Debug, Default)]
+pub struct SniRoutes(Arc<HashMap<u16, Arc<HashMap<ServerId, u16>>>>);
+
+#[derive(Clone, Debug)]
+pub(crate) struct NewRouteSni<N> {
+    inner: N,
+    routes: SniRoutes,
+    timeout: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RouteSni<N> {
+    inner: N,
+    endpoint: TcpEndpoint,
+    routes: Option<Arc<HashMap<ServerId, u16>>>,
+    timeout: Duration,
+}
+
+// === impl SniRoutes ===
+
+impl<T: IntoIterator<Item = (u16, identity::Name, u16)>> From<T> for SniRoutes {
+    fn from(routes: T) -> Self {
+        let mut ports = HashMap::<u16, HashMap<ServerId, u16>>::new();
+        for (port, name, target) in routes {
+            ports
+                .entry(port)
+                .or_default()
+                .insert(ServerId(name), target);
+        }
+        let ports = ports
+            .into_iter()
+            .map(|(port, routes)| (port, Arc::new(routes)))
+            .collect();
+        Self(Arc::new(ports))
+    }
+}
+
+// === impl NewRouteSni ===
+
+impl<N> NewRouteSni<N> {
+    /// Bounds the time spent reading each connection's ClientHello by
+    /// `timeout`.
+    pub(crate) fn layer(
+        routes: SniRoutes,
+        timeout: Duration,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            routes: routes.clone(),
+            timeout,
+        })
+    }
+}
+
+impl<N> svc::NewService<TcpAccept> for NewRouteSni<N>
+where
+    N: svc::NewService<TcpEndpoint> + Clone,
+{
+    type Service = RouteSni<N>;
+
+    fn new_service(&mut self, tcp: TcpAccept) -> Self::Service {
+        let routes = self.routes.0.get(&tcp.target_addr.port()).cloned();
+        RouteSni {
+            inner: self.inner.clone(),
+            endpoint: TcpEndpoint::from(tcp),
+            routes,
+            timeout: self.timeout,
+        }
+    }
+}
+
+// === impl RouteSni ===
+
+impl<I, N, S> svc::Service<I> for RouteSni<N>
+where
+    I: io::Peek + io::AsyncRead + io::AsyncWrite + Send + Sync + Unpin + 'static,
+    N: svc::NewService<TcpEndpoint, Service = S> + Clone + Send + 'static,
+    S: svc::Service<DetectIo<I>, Response = ()> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let mut endpoint = self.endpoint.clone();
+        let routes = match self.routes.clone() {
+            Some(routes) => routes,
+            None => {
+                let svc = inner.new_service(endpoint);
+                return Box::pin(svc.oneshot(io::EitherIo::Left(io)).err_into::<Error>());
+            }
+        };
+
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let (sni, io) = tls::server::detect_sni_within(io, timeout).await?;
+            match sni {
+                Some(sni) => match routes.get(&sni) {
+                    Some(&port) => {
+                        debug!(%sni, port, "Routing connection by SNI");
+                        endpoint.port = port;
+                    }
+                    None => debug!(%sni, "No route for SNI"),
+                },
+                None => debug!("No SNI detected"),
+            }
+            inner
+                .new_service(endpoint)
+                .oneshot(io)
+                .err_into::<Error>()
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd_app_core::{
+        transport::{ClientAddr, Remote},
+        Conditional,
+    };
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use tokio::io::AsyncWriteExt;
+
+    /// A ClientHello with the server name `example.com`.
+    const CLIENT_HELLO: &[u8] =
+        include_bytes!("../../../tls/src/server/testdata/curl-example-com-client-hello.bin");
+
+    fn accept(port: u16) -> TcpAccept {
+        TcpAccept {
+            target_addr: ([192, 0, 2, 2], port).into(),
+            client_addr: Remote(ClientAddr(([192, 0, 2, 3], 40000).into())),
+            tls: Conditional::None(tls::NoServerTls::PortSkipped),
+            class: None,
+        }
+    }
+
+    /// Routes a connection to `port` on which the client sends `data`,
+    /// returning the port to which the connection is forwarded.
+    async fn route(port: u16, data: &[u8]) -> Result<u16, Error> {
+        let routes = SniRoutes::from(vec![
+            (443, identity::Name::from_str("example.com").unwrap(), 8443),
+            (443, identity::Name::from_str("example.org").unwrap(), 9443),
+        ]);
+        let forwarded = Arc::new(Mutex::new(None));
+        let mut new_route = {
+            let forwarded = forwarded.clone();
+            svc::Layer::layer(
+                &NewRouteSni::layer(routes, Duration::from_secs(1)),
+                move |endpoint: TcpEndpoint| {
+                    *forwarded.lock() = Some(endpoint.port);
+                    svc::mk(|_: DetectIo<tokio::io::DuplexStream>| future::ok::<(), Error>(()))
+                },
+            )
+        };
+
+        // The client remains open until the connection is forwarded.
+        let (mut client, server) = tokio::io::duplex(4096);
+        client.write_all(data).await.unwrap();
+        svc::NewService::new_service(&mut new_route, accept(port))
+            .oneshot(server)
+            .await?;
+        let port = forwarded.lock().take();
+        Ok(port.expect("connection must be forwarded"))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_by_sni() {
+        assert_eq!(route(443, CLIENT_HELLO).await.unwrap(), 8443);
+        assert_eq!(
+            route(8080, CLIENT_HELLO).await.unwrap(),
+            8080,
+            "other ports must not be routed"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn falls_back_without_sni() {
+        let port = route(443, b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(port, 443);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn falls_back_when_detection_times_out() {
+        // The client stalls after sending part of its ClientHello.
+        let port = route(443, &CLIENT_HELLO[..64]).await.unwrap();
+        assert_eq!(port, 443);
+    }
+}
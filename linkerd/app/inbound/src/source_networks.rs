@@ -0,0 +1,519 @@
+use crate::{loopback, port_policies::PortPolicies, proxy_protocol};
+use futures::TryFutureExt;
+use linkerd_app_core::{
+    io,
+    metrics::{
+        self,
+        policy_decisions::{Decision, Policy},
+    },
+    svc::{self, Param},
+    transport::{ClientAddr, OrigDstAddr, Remote},
+    Error, Infallible, IpMatch,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::time::{self, Duration};
+use tower::util::ServiceExt;
+use tracing::debug;
+
+/// Restricts, by port, the source addresses from which inbound connections are
+/// accepted.
+///
+/// A connection targeting a port with an allowlist is only accepted if its
+/// client address is in one of the allowed networks; a connection whose client
+/// address is in one of its target port's denied networks is never accepted.
+/// Ports without any configured networks accept connections from all clients.
+/// Rejected connections are closed immediately and counted by the
+/// `inbound_tcp_source_rejected_total` metric, which labels connections from
+/// denied networks with the first matching network, as `denied_cidr`.
+///
+/// Source addresses are checked as soon as connections are accepted, before
+/// TLS or protocol detection, so they are the addresses of the connections'
+/// peers rather than of the clients described by any HTTP headers. Connections
+/// that are forwarded by a load balancer may begin with a PROXY protocol
+/// header that describes their original clients, in which case both the peer
+/// and the client described by the header must be permitted.
+///
+/// Connections made over the loopback interface may be trusted, so that they
+/// are accepted regardless of their target ports' networks.
+#[derive(Clone, Debug, Default)]
+pub struct SourceNetworksForPorts {
+    ports: Arc<HashMap<u16, SourceNetworks>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SourceNetworks {
+    allow: Option<IpMatch>,
+    deny: IpMatch,
+}
+
+/// Why a client is not permitted on a port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Rejection {
+    Denied(ipnet::IpNet),
+    NotAllowed,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SourceFilter {
+    ports: SourceNetworksForPorts,
+    metrics: metrics::Proxy,
+    trust_loopback: bool,
+}
+
+/// Checks the clients described by the PROXY protocol headers of connections
+/// to restricted ports.
+///
+/// Headers are peeked rather than read, so they are left on the connections
+/// for the application. Clients that do not send anything before `timeout`
+/// elapses are checked by their peer addresses alone.
+#[derive(Clone, Debug)]
+pub(crate) struct NewProxySourceFilter<N> {
+    inner: N,
+    filter: SourceFilter,
+    policies: PortPolicies,
+    timeout: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ProxySourceFilter<N, T> {
+    inner: N,
+    target: T,
+    /// The target address of a connection to a restricted port.
+    restricted: Option<SocketAddr>,
+    filter: SourceFilter,
+    policies: PortPolicies,
+    timeout: Duration,
+}
+
+// === impl SourceNetworksForPorts ===
+
+impl SourceNetworksForPorts {
+    pub fn new(
+        allow: impl IntoIterator<Item = (u16, ipnet::IpNet)>,
+        deny: impl IntoIterator<Item = (u16, ipnet::IpNet)>,
+    ) -> Self {
+        let mut nets = HashMap::<u16, (Option<Vec<_>>, Vec<_>)>::new();
+        for (port, net) in allow {
+            let (allow, _) = nets.entry(port).or_default();
+            allow.get_or_insert_with(Vec::new).push(net);
+        }
+        for (port, net) in deny {
+            let (_, deny) = nets.entry(port).or_default();
+            deny.push(net);
+        }
+
+        let ports = nets
+            .into_iter()
+            .map(|(port, (allow, deny))| {
+                let nets = SourceNetworks {
+                    allow: allow.map(IpMatch::new),
+                    deny: IpMatch::new(deny),
+                };
+                (port, nets)
+            })
+            .collect();
+        Self {
+            ports: Arc::new(ports),
+        }
+    }
+
+    /// Returns true if connections to `port` are restricted by their source
+    /// networks.
+    pub(crate) fn restricts(&self, port: u16) -> bool {
+        self.ports.contains_key(&port)
+    }
+
+    pub(crate) fn filter(&self, metrics: metrics::Proxy, trust_loopback: bool) -> SourceFilter {
+        SourceFilter {
+            ports: self.clone(),
+            metrics,
+            trust_loopback,
+        }
+    }
+
+    fn check(&self, port: u16, client: IpAddr) -> Result<(), Rejection> {
+        let nets = match self.ports.get(&port) {
+            Some(nets) => nets,
+            None => return Ok(()),
+        };
+
+        // IPv4 clients may be accepted on dual-stack listeners, in which case
+        // their addresses are mapped into the IPv6 address space.
+        let client = match client {
+            IpAddr::V6(ip) => match ip.segments() {
+                [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                    let [a, b] = hi.to_be_bytes();
+                    let [c, d] = lo.to_be_bytes();
+                    IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+                }
+                _ => client,
+            },
+            client => client,
+        };
+
+        if let Some(net) = nets.deny.matching(client) {
+            return Err(Rejection::Denied(*net));
+        }
+        match nets.allow.as_ref() {
+            Some(allow) if !allow.matches(client) => Err(Rejection::NotAllowed),
+            _ => Ok(()),
+        }
+    }
+}
+
+// === impl SourceFilter ===
+
+impl SourceFilter {
+    pub(crate) fn filter<T>(
+        &self,
+        target: T,
+    ) -> Result<svc::Either<T, Remote<ClientAddr>>, Infallible>
+    where
+        T: Param<Remote<ClientAddr>> + Param<OrigDstAddr>,
+    {
+        let OrigDstAddr(target_addr) = target.param();
+        let client: Remote<ClientAddr> = target.param();
+        if self.is_trusted(client, target_addr) || self.permits(client, target_addr) {
+            return Ok(svc::Either::A(target));
+        }
+        Ok(svc::Either::B(client))
+    }
+
+    pub(crate) fn proxy_layer<N>(
+        &self,
+        policies: PortPolicies,
+        timeout: Duration,
+    ) -> impl svc::Layer<N, Service = NewProxySourceFilter<N>> + Clone {
+        let filter = self.clone();
+        svc::layer::mk(move |inner| NewProxySourceFilter {
+            inner,
+            filter: filter.clone(),
+            policies: policies.clone(),
+            timeout,
+        })
+    }
+
+    fn is_trusted(
+        &self,
+        Remote(ClientAddr(client)): Remote<ClientAddr>,
+        target: SocketAddr,
+    ) -> bool {
+        self.trust_loopback && loopback::is_local(client.ip(), target.ip())
+    }
+
+    /// Returns true if `client` may connect to `target`, recording the
+    /// connection as rejected otherwise.
+    fn permits(&self, client: Remote<ClientAddr>, target: SocketAddr) -> bool {
+        let Remote(ClientAddr(client_addr)) = client;
+        match self.ports.check(target.port(), client_addr.ip()) {
+            Ok(()) => true,
+            Err(Rejection::Denied(net)) => {
+                debug!(client.addr = %client, %net, "Rejecting connection from a denied network");
+                self.metrics.tcp_source_rejected.incr(target, Some(net));
+                false
+            }
+            Err(Rejection::NotAllowed) => {
+                debug!(client.addr = %client, "Rejecting connection from a source that is not permitted");
+                self.metrics.tcp_source_rejected.incr(target, None);
+                false
+            }
+        }
+    }
+}
+
+// === impl NewProxySourceFilter ===
+
+impl<N, T> svc::NewService<T> for NewProxySourceFilter<N>
+where
+    T: Param<Remote<ClientAddr>> + Param<OrigDstAddr>,
+    N: Clone,
+{
+    type Service = ProxySourceFilter<N, T>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let OrigDstAddr(target_addr) = target.param();
+        let client = target.param();
+        let restricted = if self.filter.ports.restricts(target_addr.port())
+            && !self.filter.is_trusted(client, target_addr)
+        {
+            Some(target_addr)
+        } else {
+            None
+        };
+        ProxySourceFilter {
+            inner: self.inner.clone(),
+            target,
+            restricted,
+            filter: self.filter.clone(),
+            policies: self.policies.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+// === impl ProxySourceFilter ===
+
+impl<I, N, S, T> svc::Service<I> for ProxySourceFilter<N, T>
+where
+    I: io::Peek + Send + Sync + 'static,
+    T: Clone + Send + 'static,
+    N: svc::NewService<T, Service = S> + Clone + Send + 'static,
+    S: svc::Service<I, Response = ()> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let svc = self.inner.new_service(self.target.clone());
+        let target_addr = match self.restricted {
+            Some(addr) => addr,
+            None => return Box::pin(svc.oneshot(io).err_into::<Error>()),
+        };
+
+        let filter = self.filter.clone();
+        let policies = self.policies.clone();
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let mut buf = [0; proxy_protocol::MAX_HEADER_LEN];
+            let client = match time::timeout(timeout, io.peek(&mut buf)).await {
+                Ok(sz) => proxy_protocol::decode_client(&buf[..sz?]),
+                Err(_) => None,
+            };
+            if let Some(client) = client {
+                debug!(%client, "Checking the client described by a PROXY protocol header");
+                if !filter.permits(Remote(ClientAddr(client)), target_addr) {
+                    policies.denied(target_addr.port(), Policy::SourceNetworks, Decision::Deny);
+                    return Ok(());
+                }
+            }
+            svc.oneshot(io).err_into::<Error>().await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use futures::future;
+    use linkerd_app_core::metrics::FmtMetrics;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    #[derive(Clone, Debug)]
+    struct Target {
+        client: SocketAddr,
+        target: SocketAddr,
+    }
+
+    impl Param<Remote<ClientAddr>> for Target {
+        fn param(&self) -> Remote<ClientAddr> {
+            Remote(ClientAddr(self.client))
+        }
+    }
+
+    impl Param<OrigDstAddr> for Target {
+        fn param(&self) -> OrigDstAddr {
+            OrigDstAddr(self.target)
+        }
+    }
+
+    fn is_rejected(filter: &SourceFilter, client: SocketAddr, port: u16) -> bool {
+        let target = Target {
+            client,
+            target: ([192, 0, 2, 2], port).into(),
+        };
+        matches!(filter.filter(target), Ok(svc::Either::B(_)))
+    }
+
+    #[test]
+    fn rejects_connections_from_unpermitted_sources() {
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let ports = SourceNetworksForPorts::new(
+            vec![
+                (8080, "10.0.0.0/8".parse().unwrap()),
+                (8080, "192.168.0.0/16".parse().unwrap()),
+            ],
+            vec![
+                (8080, "10.1.0.0/16".parse().unwrap()),
+                (9090, "10.2.0.0/16".parse().unwrap()),
+                (9090, "2001:db8:1::/48".parse().unwrap()),
+            ],
+        );
+        let filter = ports.filter(metrics.inbound, false);
+
+        assert!(!is_rejected(&filter, ([10, 0, 0, 1], 40000).into(), 8080));
+        assert!(!is_rejected(
+            &filter,
+            ([192, 168, 0, 1], 40000).into(),
+            8080
+        ));
+        assert!(
+            is_rejected(&filter, ([172, 16, 0, 1], 40000).into(), 8080),
+            "clients outside of the allowed networks must be rejected"
+        );
+        assert!(
+            is_rejected(&filter, ([10, 1, 0, 1], 40000).into(), 8080),
+            "denied networks must take precedence over allowed networks"
+        );
+        assert!(
+            is_rejected(&filter, "[::ffff:172.16.0.1]:40000".parse().unwrap(), 8080),
+            "IPv4-mapped clients must be matched as IPv4 clients"
+        );
+        assert!(
+            is_rejected(&filter, "[2001:db8::1]:40000".parse().unwrap(), 8080),
+            "IPv6 clients must not match IPv4 networks"
+        );
+
+        assert!(!is_rejected(&filter, ([172, 16, 0, 1], 40000).into(), 9090));
+        assert!(is_rejected(&filter, ([10, 2, 0, 1], 40000).into(), 9090));
+        assert!(is_rejected(
+            &filter,
+            "[2001:db8:1::1]:40000".parse().unwrap(),
+            9090
+        ));
+        assert!(!is_rejected(
+            &filter,
+            "[2001:db8:2::1]:40000".parse().unwrap(),
+            9090
+        ));
+
+        assert!(
+            !is_rejected(&filter, ([10, 2, 0, 1], 40000).into(), 7070),
+            "ports without networks must permit all clients"
+        );
+
+        let metrics = report.as_display().to_string();
+        assert!(
+            metrics.contains("inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:8080\"} 3")
+        );
+        assert!(metrics.contains(
+            "inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:8080\",denied_cidr=\"10.1.0.0/16\"} 1"
+        ));
+        assert!(metrics.contains(
+            "inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:9090\",denied_cidr=\"10.2.0.0/16\"} 1"
+        ));
+        assert!(metrics.contains(
+            "inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:9090\",denied_cidr=\"2001:db8:1::/48\"} 1"
+        ));
+    }
+
+    /// Accepts a connection from a client at 192.0.2.3 on `port`, on which
+    /// `data` is sent, returning true if the connection is forwarded.
+    async fn is_forwarded(filter: &SourceFilter, port: u16, data: &[u8]) -> bool {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        client.write_all(data).await.unwrap();
+        let (io, _) = listener.accept().await.unwrap();
+
+        let config = test_util::default_config();
+        let policies = PortPolicies::new(&config, &filter.metrics);
+        let forwarded = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let forwarded = forwarded.clone();
+            move |_: Target| {
+                let forwarded = forwarded.clone();
+                svc::mk(move |_: TcpStream| {
+                    forwarded.fetch_add(1, Ordering::SeqCst);
+                    future::ok::<(), Error>(())
+                })
+            }
+        };
+        let mut new_filter = svc::Layer::layer(
+            &filter.proxy_layer(policies, Duration::from_millis(100)),
+            inner,
+        );
+        let target = Target {
+            client: ([192, 0, 2, 3], 40000).into(),
+            target: ([192, 0, 2, 2], port).into(),
+        };
+        svc::NewService::new_service(&mut new_filter, target)
+            .oneshot(io)
+            .await
+            .unwrap();
+        forwarded.load(Ordering::SeqCst) == 1
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn checks_clients_described_by_proxy_headers() {
+        let (metrics, report) =
+            metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
+        let ports = SourceNetworksForPorts::new(
+            vec![(8080, "192.0.2.0/24".parse().unwrap())],
+            vec![(8080, "192.0.2.128/25".parse().unwrap())],
+        );
+        let filter = ports.filter(metrics.inbound, false);
+
+        assert!(
+            is_forwarded(
+                &filter,
+                8080,
+                b"PROXY TCP4 192.0.2.4 192.0.2.2 40000 8080\r\nhello"
+            )
+            .await
+        );
+        assert!(
+            !is_forwarded(
+                &filter,
+                8080,
+                b"PROXY TCP4 192.0.2.200 192.0.2.2 40000 8080\r\nhello"
+            )
+            .await,
+            "clients in denied networks must be rejected"
+        );
+        assert!(
+            !is_forwarded(
+                &filter,
+                8080,
+                b"PROXY TCP4 172.16.0.1 192.0.2.2 40000 8080\r\nhello"
+            )
+            .await,
+            "clients outside of the allowed networks must be rejected"
+        );
+        assert!(
+            is_forwarded(&filter, 8080, b"hello").await,
+            "connections without headers must be checked by their peers"
+        );
+        assert!(
+            is_forwarded(&filter, 8080, b"").await,
+            "clients that do not send anything must be checked by their peers"
+        );
+        assert!(
+            is_forwarded(
+                &filter,
+                9090,
+                b"PROXY TCP4 172.16.0.1 192.0.2.2 40000 9090\r\nhello"
+            )
+            .await,
+            "headers must not be checked on unrestricted ports"
+        );
+
+        let metrics = report.as_display().to_string();
+        assert!(
+            metrics.contains("inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:8080\"} 1")
+        );
+        assert!(metrics.contains(
+            "inbound_tcp_source_rejected_total{target_addr=\"192.0.2.2:8080\",denied_cidr=\"192.0.2.128/25\"} 1"
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::proxy_protocol::ProxyAddrs;
 use linkerd_app_core::{
     classify, dst, http_request_authority_addr, http_request_host_addr, identity, metrics,
     profiles,
@@ -17,6 +18,7 @@ pub struct TcpAccept {
     pub target_addr: SocketAddr,
     pub client_addr: Remote<ClientAddr>,
     pub tls: tls::ConditionalServerTls,
+    pub class: Option<metrics::PortClass>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -31,6 +33,8 @@ pub struct Target {
     pub target_addr: SocketAddr,
     pub http_version: http::Version,
     pub tls: tls::ConditionalServerTls,
+    pub log_client_port: bool,
+    pub class: Option<metrics::PortClass>,
 }
 
 #[derive(Clone, Debug)]
@@ -39,6 +43,14 @@ pub struct Logical {
     profiles: profiles::Receiver,
 }
 
+/// A profile route, along with the inbound port on which its requests were
+/// received, so that the route's metrics may be configured by port.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PortRoute {
+    pub port: u16,
+    pub route: dst::Route,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct HttpEndpoint {
     pub port: u16,
@@ -49,11 +61,20 @@ pub struct HttpEndpoint {
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TcpEndpoint {
     pub port: u16,
+
+    /// The addresses of the forwarded connection, if it is forwarded for a
+    /// single client.
+    pub proxy_addrs: Option<ProxyAddrs>,
+
+    /// The identity of the forwarded connection's client, if it is forwarded
+    /// for a single client that has one.
+    pub client_id: Option<tls::ClientId>,
 }
 
 #[derive(Clone, Debug)]
 pub struct RequestTarget {
     accept: HttpAccept,
+    log_client_port: bool,
 }
 
 // === impl TcpAccept ===
@@ -68,6 +89,7 @@ impl TcpAccept {
             target_addr,
             client_addr: tcp.param(),
             tls: Conditional::None(tls::NoServerTls::PortSkipped),
+            class: None,
         }
     }
 }
@@ -82,6 +104,7 @@ where
             target_addr,
             client_addr: addrs.param(),
             tls,
+            class: None,
         }
     }
 }
@@ -98,12 +121,19 @@ impl Param<OrigDstAddr> for TcpAccept {
     }
 }
 
+impl Param<tls::ConditionalServerTls> for TcpAccept {
+    fn param(&self) -> tls::ConditionalServerTls {
+        self.tls.clone()
+    }
+}
+
 impl Param<transport::labels::Key> for TcpAccept {
     fn param(&self) -> transport::labels::Key {
         transport::labels::Key::accept(
             transport::labels::Direction::In,
             self.tls.clone(),
             self.target_addr,
+            self.class.clone(),
         )
     }
 }
@@ -131,6 +161,12 @@ impl Param<http::normalize_uri::DefaultAuthority> for HttpAccept {
     }
 }
 
+impl Param<OrigDstAddr> for HttpAccept {
+    fn param(&self) -> OrigDstAddr {
+        OrigDstAddr(self.tcp.target_addr)
+    }
+}
+
 impl Param<Option<identity::Name>> for HttpAccept {
     fn param(&self) -> Option<identity::Name> {
         self.tcp
@@ -170,19 +206,36 @@ impl From<TcpAccept> for TcpEndpoint {
     fn from(tcp: TcpAccept) -> Self {
         Self {
             port: tcp.target_addr.port(),
+            proxy_addrs: Some(ProxyAddrs {
+                client: tcp.client_addr,
+                orig_dst: OrigDstAddr(tcp.target_addr),
+            }),
+            client_id: client_id(&tcp.tls),
         }
     }
 }
 
 impl From<(TransportHeader, TcpAccept)> for TcpEndpoint {
-    fn from((header, _): (TransportHeader, TcpAccept)) -> Self {
-        Self { port: header.port }
+    fn from((header, tcp): (TransportHeader, TcpAccept)) -> Self {
+        Self {
+            port: header.port,
+            proxy_addrs: Some(ProxyAddrs {
+                client: tcp.client_addr,
+                orig_dst: OrigDstAddr((tcp.target_addr.ip(), header.port).into()),
+            }),
+            client_id: client_id(&tcp.tls),
+        }
     }
 }
 
 impl From<HttpEndpoint> for TcpEndpoint {
     fn from(h: HttpEndpoint) -> Self {
-        Self { port: h.port }
+        // HTTP connections are shared by requests from many clients.
+        Self {
+            port: h.port,
+            proxy_addrs: None,
+            client_id: None,
+        }
     }
 }
 
@@ -192,6 +245,12 @@ impl Param<u16> for TcpEndpoint {
     }
 }
 
+impl Param<Option<tls::ClientId>> for TcpEndpoint {
+    fn param(&self) -> Option<tls::ClientId> {
+        self.client_id.clone()
+    }
+}
+
 impl Param<transport::labels::Key> for TcpEndpoint {
     fn param(&self) -> transport::labels::Key {
         transport::labels::Key::InboundConnect
@@ -206,13 +265,42 @@ impl From<TcpEndpoint> for SocketAddr {
     }
 }
 
+fn client_id(tls: &tls::ConditionalServerTls) -> Option<tls::ClientId> {
+    match tls {
+        tls::ConditionalServerTls::Some(tls::ServerTls::Established {
+            client_id: Some(id),
+            ..
+        }) => Some(id.clone()),
+        _ => None,
+    }
+}
+
 // === impl Profile ===
 
-pub(super) fn route((route, logical): (profiles::http::Route, Logical)) -> dst::Route {
-    dst::Route {
-        route,
-        target: logical.target.dst,
-        direction: metrics::Direction::In,
+pub(super) fn route((route, logical): (profiles::http::Route, Logical)) -> PortRoute {
+    PortRoute {
+        port: logical.target.target_addr.port(),
+        route: dst::Route {
+            route,
+            target: logical.target.dst,
+            direction: metrics::Direction::In,
+        },
+    }
+}
+
+// === impl PortRoute ===
+
+impl Param<metrics::RouteLabels> for PortRoute {
+    fn param(&self) -> metrics::RouteLabels {
+        self.route.param()
+    }
+}
+
+impl classify::CanClassify for PortRoute {
+    type Classify = classify::Request;
+
+    fn classify(&self) -> classify::Request {
+        classify::CanClassify::classify(&self.route)
     }
 }
 
@@ -225,6 +313,8 @@ impl From<HttpAccept> for Target {
             target_addr: tcp.target_addr,
             http_version: version,
             tls: tcp.tls,
+            log_client_port: false,
+            class: tcp.class,
         }
     }
 }
@@ -253,6 +343,7 @@ impl Param<metrics::EndpointLabels> for Target {
             tls: self.tls.clone(),
             authority: self.dst.name_addr().map(|d| d.as_http_authority()),
             target_addr: self.target_addr,
+            class: self.class.clone(),
         }
         .into()
     }
@@ -273,6 +364,15 @@ impl tap::Inspect for Target {
             .map(|s| s.tcp.client_addr.into())
     }
 
+    fn src_port<B>(&self, req: &http::Request<B>) -> Option<u16> {
+        if !self.log_client_port {
+            return None;
+        }
+        req.extensions()
+            .get::<HttpAccept>()
+            .map(|s| s.tcp.client_addr.as_ref().port())
+    }
+
     fn src_tls<B>(&self, req: &http::Request<B>) -> tls::ConditionalServerTls {
         req.extensions()
             .get::<HttpAccept>()
@@ -280,6 +380,13 @@ impl tap::Inspect for Target {
             .unwrap_or_else(|| Conditional::None(tls::NoServerTls::Disabled))
     }
 
+    fn request_id<B>(&self, req: &http::Request<B>) -> Option<String> {
+        req.extensions()
+            .get::<crate::http::RequestIdValue>()
+            .and_then(|id| id.to_str())
+            .map(|id| id.to_owned())
+    }
+
     fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
         Some(self.target_addr)
     }
@@ -322,9 +429,18 @@ impl stack_tracing::GetSpan<()> for Target {
 
 // === impl RequestTarget ===
 
+impl RequestTarget {
+    pub fn new(accept: HttpAccept, log_client_port: bool) -> Self {
+        Self {
+            accept,
+            log_client_port,
+        }
+    }
+}
+
 impl From<HttpAccept> for RequestTarget {
     fn from(accept: HttpAccept) -> Self {
-        Self { accept }
+        Self::new(accept, false)
     }
 }
 
@@ -351,6 +467,8 @@ impl<A> svc::stack::RecognizeRoute<http::Request<A>> for RequestTarget {
             dst,
             target_addr: self.accept.tcp.target_addr,
             tls: self.accept.tcp.tls.clone(),
+            log_client_port: self.log_client_port,
+            class: self.accept.tcp.class.clone(),
             // The HttpAccept target version reflects the inbound transport
             // protocol, but it may have changed due to orig-proto downgrading.
             http_version: req
@@ -374,3 +492,48 @@ impl Param<profiles::Receiver> for Logical {
         self.profiles.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{proxy::tap::Inspect, svc::stack::RecognizeRoute};
+
+    fn accept() -> HttpAccept {
+        HttpAccept {
+            tcp: TcpAccept {
+                target_addr: ([127, 0, 0, 1], 5550).into(),
+                client_addr: Remote(ClientAddr(([10, 0, 0, 41], 6894).into())),
+                tls: Conditional::None(tls::NoServerTls::NoClientHello),
+                class: None,
+            },
+            version: http::Version::Http1,
+        }
+    }
+
+    fn request() -> http::Request<()> {
+        let mut req = http::Request::builder()
+            .uri("http://foo.svc.cluster.local:5550")
+            .body(())
+            .unwrap();
+        req.extensions_mut().insert(accept());
+        req
+    }
+
+    #[test]
+    fn tap_src_port_when_enabled() {
+        let req = request();
+        let target = RequestTarget::new(accept(), true)
+            .recognize(&req)
+            .expect("must recognize");
+        assert_eq!(target.src_port(&req), Some(6894));
+    }
+
+    #[test]
+    fn tap_src_port_when_disabled() {
+        let req = request();
+        let target = RequestTarget::from(accept())
+            .recognize(&req)
+            .expect("must recognize");
+        assert_eq!(target.src_port(&req), None);
+    }
+}
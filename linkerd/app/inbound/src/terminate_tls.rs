@@ -0,0 +1,75 @@
+use linkerd_app_core::{
+    svc::{self, ExtractParam, InsertParam, Param},
+    tls::{self, external::ServerConfig},
+    transport::OrigDstAddr,
+    Infallible,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Determines, by port, which inbound ports terminate TLS for clients outside
+/// of the mesh, with certificates that are distinct from the proxy's identity.
+///
+/// Connections to these ports must begin with a TLS handshake: the proxy does
+/// not detect whether clients use TLS or whether they target the proxy's
+/// identity. Once TLS is terminated, connections are served as though they
+/// were not authenticated by the mesh.
+#[derive(Clone, Debug, Default)]
+pub struct TerminateTlsForPorts(Arc<HashMap<u16, ServerConfig>>);
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TerminateParams {
+    pub timeout: tls::server::Timeout,
+}
+
+// === impl TerminateTlsForPorts ===
+
+impl TerminateTlsForPorts {
+    pub fn new(ports: impl IntoIterator<Item = (u16, ServerConfig)>) -> Self {
+        Self(Arc::new(ports.into_iter().collect()))
+    }
+
+    /// Pairs connections that target a port that terminates TLS with the
+    /// port's configuration.
+    pub(crate) fn select<T: Param<OrigDstAddr>>(
+        &self,
+        target: T,
+    ) -> Result<svc::Either<T, (ServerConfig, T)>, Infallible> {
+        let OrigDstAddr(addr) = target.param();
+        match self.0.get(&addr.port()) {
+            Some(config) => {
+                tracing::trace!(port = %addr.port(), "Terminating TLS");
+                Ok(svc::Either::B((config.clone(), target)))
+            }
+            None => Ok(svc::Either::A(target)),
+        }
+    }
+}
+
+// === impl TerminateParams ===
+
+impl<T> ExtractParam<tls::server::Timeout, (ServerConfig, T)> for TerminateParams {
+    #[inline]
+    fn extract_param(&self, _: &(ServerConfig, T)) -> tls::server::Timeout {
+        self.timeout
+    }
+}
+
+impl<T> ExtractParam<tls::server::Config, (ServerConfig, T)> for TerminateParams {
+    #[inline]
+    fn extract_param(&self, (config, _): &(ServerConfig, T)) -> tls::server::Config {
+        config.server_config()
+    }
+}
+
+impl<T> InsertParam<tls::ConditionalServerTls, (ServerConfig, T)> for TerminateParams {
+    type Target = (tls::ConditionalServerTls, T);
+
+    #[inline]
+    fn insert_param(
+        &self,
+        tls: tls::ConditionalServerTls,
+        (_, target): (ServerConfig, T),
+    ) -> Self::Target {
+        (tls, target)
+    }
+}
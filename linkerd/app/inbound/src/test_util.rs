@@ -1,9 +1,9 @@
-use crate::{Config, RequireIdentityForPorts};
+use crate::{Config, RequireIdentityForPorts, RequireTlsForPorts};
 pub use futures::prelude::*;
 use linkerd_app_core::{
     config,
     dns::Suffix,
-    drain, exp_backoff, metrics,
+    drain, errors, exp_backoff, metrics,
     proxy::{
         http::{h1, h2},
         tap,
@@ -24,10 +24,12 @@ pub fn default_config() -> Config {
             server: config::ServerConfig {
                 addr: ListenAddr(([0, 0, 0, 0], 0).into()),
                 keepalive: Keepalive(None),
+                dscp: Default::default(),
                 h2_settings: h2::Settings::default(),
             },
             connect: config::ConnectConfig {
                 keepalive: Keepalive(None),
+                dscp: Default::default(),
                 timeout: Duration::from_secs(1),
                 backoff: exp_backoff::ExponentialBackoff::new(
                     Duration::from_millis(100),
@@ -40,29 +42,92 @@ pub fn default_config() -> Config {
                     idle_timeout: Duration::from_secs(1),
                 },
                 h2_settings: h2::Settings::default(),
+                close_delimited: Default::default(),
+                response_read_ahead_bytes: 0,
             },
             buffer_capacity: 10_000,
             cache_max_idle_age: Duration::from_secs(20),
             dispatch_timeout: Duration::from_secs(1),
             max_in_flight_requests: 10_000,
             detect_protocol_timeout: Duration::from_secs(10),
+            client_disconnect: Default::default(),
+            connection_log: Default::default(),
+            echo_trace_id: false,
+            tcp_splice: false,
+            memory_pressure: Default::default(),
+            via: None,
         },
         require_identity_for_inbound_ports: RequireIdentityForPorts::from(None),
+        require_tls_for_inbound_ports: RequireTlsForPorts::from(None),
         disable_protocol_detection_for_ports: Default::default(),
         profile_idle_timeout: Duration::from_millis(500),
+        proxy_protocol_ports: Default::default(),
+        sni_routes: Default::default(),
+        identity_bytes_metrics: false,
+        log_client_port: false,
+        missing_authority: Default::default(),
+        duplicate_headers: Default::default(),
+        max_request_line_bytes: None,
+        transfer_encoding_conflict: Default::default(),
+        duplicate_content_length: Default::default(),
+        error_rate_limits: Default::default(),
+        local_breakers: Default::default(),
+        json_error_bodies: false,
+        identity_rate_limits: Default::default(),
+        h2_stream_limit: Default::default(),
+        strip_l5d_headers: Default::default(),
+        request_id_header: None,
+        proxy_elapsed_header: None,
+        websocket_idle_timeout: None,
+        allowed_upgrades: Default::default(),
+        response_headers_timeout: None,
+        app_read_timeout: None,
+        grpc_compression: Default::default(),
+        allowed_methods: Default::default(),
+        redact_fields: Default::default(),
+        body_size_routing: Default::default(),
+        max_request_body_bytes: Default::default(),
+        direct_plaintext: Default::default(),
+        direct_alpn_downgrade: Default::default(),
+        direct_identity_pending: Default::default(),
+        direct_tls_alert_logs: Default::default(),
+        port_classes: Default::default(),
+        replay_protection: Default::default(),
+        metrics_granularity: Default::default(),
+        client_auth: Default::default(),
+        source_networks: Default::default(),
+        default_deny: false,
+        allow_ports: Default::default(),
+        connection_rate_limits: Default::default(),
+        identity_connection_limits: Default::default(),
+        port_idle_timeouts: Default::default(),
+        cookie_limits: Default::default(),
+        trace_sampler: Default::default(),
+        trace_attributes: Default::default(),
+        proxy_error_header: errors::proxy_error_header(),
+        redact_unmeshed_errors: false,
+        classify_app_connection_errors: false,
+        terminate_tls: Default::default(),
+        opaque_on_http1_parse_failure: false,
+        connect_loopback: [127, 0, 0, 1].into(),
+        trust_loopback: false,
     }
 }
 
 pub fn runtime() -> (ProxyRuntime, drain::Signal) {
-    let (metrics, _) = metrics::Metrics::new(std::time::Duration::from_secs(10));
+    let (metrics, _) =
+        metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
     let (drain_tx, drain) = drain::channel();
-    let (tap, _) = tap::new();
+    let (tap, _) = tap::new(None);
     let runtime = ProxyRuntime {
         identity: None,
         metrics: metrics.outbound,
         tap,
         span_sink: None,
+        trace_sampler: Default::default(),
         drain,
+        shutdown: Default::default(),
+        shutdown_grace: Default::default(),
     };
     (runtime, drain_tx)
 }
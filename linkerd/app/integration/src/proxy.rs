@@ -2,7 +2,7 @@ use super::*;
 use app_core::transport::OrigDstAddr;
 use linkerd_app_core::{
     svc::Param,
-    transport::{listen, orig_dst, Keepalive, ListenAddr},
+    transport::{listen, orig_dst, DscpMarking, Keepalive, ListenAddr},
 };
 use std::{fmt, future::Future, net::SocketAddr, pin::Pin, task::Poll, thread};
 use tokio::net::TcpStream;
@@ -61,7 +61,7 @@ enum MockOrigDst {
 
 impl<T> listen::Bind<T> for MockOrigDst
 where
-    T: Param<Keepalive> + Param<ListenAddr>,
+    T: Param<Keepalive> + Param<ListenAddr> + Param<DscpMarking>,
 {
     type Addrs = orig_dst::Addrs;
     type Io = tokio::net::TcpStream;
@@ -0,0 +1,237 @@
+use futures::{future, prelude::*};
+use linkerd_app_core::{profiles, svc, Error};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{sync::Semaphore, time};
+
+/// Limits the number of logical stacks that may be built--i.e., that may
+/// await profile discovery--concurrently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BuildLimit {
+    /// The maximum number of builds that may be in progress at once.
+    pub max_concurrent: usize,
+
+    /// The maximum number of builds that may wait for a slot. Builds fail
+    /// when this queue is full.
+    pub max_pending: usize,
+
+    /// How long a build may wait for a slot and for discovery to complete.
+    pub timeout: Duration,
+}
+
+/// Wraps a `GetProfile` to enforce an optional `BuildLimit`.
+#[derive(Clone, Debug)]
+pub(crate) struct LimitBuilds<P> {
+    inner: P,
+    limit: Option<Arc<Limit>>,
+}
+
+#[derive(Debug, Error)]
+#[error("too many logical stacks are being built")]
+pub struct BuildsExhausted(());
+
+#[derive(Debug, Error)]
+#[error("logical stack was not built after {0:?}")]
+pub struct BuildTimeout(Duration);
+
+#[derive(Debug)]
+struct Limit {
+    builds: Semaphore,
+    in_progress: AtomicUsize,
+    max_in_progress: usize,
+    timeout: Duration,
+}
+
+/// Releases a build's place in the queue when it completes.
+struct InProgress(Arc<Limit>);
+
+type BuildFuture = Pin<Box<dyn Future<Output = Result<Option<profiles::Receiver>, Error>> + Send>>;
+
+// === impl LimitBuilds ===
+
+impl<P> LimitBuilds<P> {
+    pub(crate) fn new(limit: Option<BuildLimit>, inner: P) -> Self {
+        let limit = limit.map(|l| {
+            Arc::new(Limit {
+                builds: Semaphore::new(l.max_concurrent),
+                in_progress: AtomicUsize::new(0),
+                max_in_progress: l.max_concurrent + l.max_pending,
+                timeout: l.timeout,
+            })
+        });
+        Self { inner, limit }
+    }
+}
+
+impl<T, P> svc::Service<T> for LimitBuilds<P>
+where
+    T: Send + 'static,
+    P: profiles::GetProfile<T> + Clone + Send + 'static,
+    P::Future: Send + 'static,
+{
+    type Response = Option<profiles::Receiver>;
+    type Error = Error;
+    type Future = future::Either<future::ErrInto<P::Future, Error>, BuildFuture>;
+
+    #[inline]
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let limit = match self.limit.clone() {
+            Some(limit) => limit,
+            None => return future::Either::Left(self.inner.get_profile(target).err_into()),
+        };
+
+        let in_progress = match InProgress::enter(limit) {
+            Some(in_progress) => in_progress,
+            None => {
+                tracing::debug!("Too many logical stacks are being built");
+                let err = BuildsExhausted(()).into();
+                let rsp = future::err::<Option<profiles::Receiver>, Error>(err);
+                return future::Either::Right(Box::pin(rsp));
+            }
+        };
+
+        let mut inner = self.inner.clone();
+        future::Either::Right(Box::pin(async move {
+            let limit = in_progress.0.clone();
+            let build = async {
+                let _permit = limit
+                    .builds
+                    .acquire()
+                    .await
+                    .expect("semaphore must not close");
+                inner.get_profile(target).err_into::<Error>().await
+            };
+            let res = time::timeout(limit.timeout, build).await;
+            drop(in_progress);
+            match res {
+                Ok(res) => res,
+                Err(_) => Err(BuildTimeout(limit.timeout).into()),
+            }
+        }))
+    }
+}
+
+// === impl InProgress ===
+
+impl InProgress {
+    fn enter(limit: Arc<Limit>) -> Option<Self> {
+        let prior = limit.in_progress.fetch_add(1, Ordering::AcqRel);
+        // Construct the guard first so the count is released if we're at
+        // capacity.
+        let in_progress = Self(limit);
+        if prior >= in_progress.0.max_in_progress {
+            return None;
+        }
+        Some(in_progress)
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        self.0.in_progress.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::{Service, ServiceExt};
+
+    /// Discovers each target after a second, tracking the number of discoveries in progress.
+    fn discover(
+        in_progress: Arc<AtomicUsize>,
+        max_in_progress: Arc<AtomicUsize>,
+    ) -> impl svc::Service<
+        usize,
+        Response = Option<profiles::Receiver>,
+        Error = Error,
+        Future = impl Send,
+    > + Clone
+           + Send
+           + 'static {
+        svc::mk(move |_: usize| {
+            let in_progress = in_progress.clone();
+            let max_in_progress = max_in_progress.clone();
+            async move {
+                let n = in_progress.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_progress.fetch_max(n, Ordering::SeqCst);
+                time::sleep(Duration::from_secs(1)).await;
+                in_progress.fetch_sub(1, Ordering::SeqCst);
+                Ok::<_, Error>(None)
+            }
+        })
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn bounds_concurrent_builds() {
+        let in_progress = Arc::new(AtomicUsize::new(0));
+        let max_in_progress = Arc::new(AtomicUsize::new(0));
+        let limit = BuildLimit {
+            max_concurrent: 2,
+            max_pending: 100,
+            timeout: Duration::from_secs(60),
+        };
+        let builds = LimitBuilds::new(
+            Some(limit),
+            discover(in_progress.clone(), max_in_progress.clone()),
+        );
+
+        let rsps = future::join_all((0..10_usize).map(|dst| builds.clone().oneshot(dst))).await;
+        assert!(rsps.iter().all(Result::is_ok));
+        assert_eq!(max_in_progress.load(Ordering::SeqCst), 2);
+        assert_eq!(in_progress.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn rejects_excess_builds() {
+        let limit = BuildLimit {
+            max_concurrent: 1,
+            max_pending: 1,
+            timeout: Duration::from_secs(60),
+        };
+        let in_progress = Arc::new(AtomicUsize::new(0));
+        let mut builds = LimitBuilds::new(Some(limit), discover(in_progress, Default::default()));
+
+        // Builds are admitted as they are called.
+        let first = builds.call(0_usize);
+        let second = builds.call(1_usize);
+        let err = builds.call(2_usize).await.unwrap_err();
+        assert!(err.is::<BuildsExhausted>(), "{}", err);
+
+        let (first, second) = future::join(first, second).await;
+        assert!(first.is_ok() && second.is_ok());
+
+        // Completed builds free their slots.
+        builds.oneshot(3_usize).await.expect("build must succeed");
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn times_out_hung_builds() {
+        let limit = BuildLimit {
+            max_concurrent: 1,
+            max_pending: 1,
+            timeout: Duration::from_secs(5),
+        };
+        let hung =
+            svc::mk(|_: usize| future::pending::<Result<Option<profiles::Receiver>, Error>>());
+        let builds = LimitBuilds::new(Some(limit), hung);
+
+        let first = builds.clone().oneshot(0_usize);
+        let second = builds.clone().oneshot(1_usize);
+        let (first, second) = future::join(first, second).await;
+        assert!(first.unwrap_err().is::<BuildTimeout>());
+        assert!(second.unwrap_err().is::<BuildTimeout>());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{tcp, Outbound};
+use crate::{build_limit::LimitBuilds, tcp, Outbound};
 use linkerd_app_core::{
     io, profiles,
     svc::{self, stack::Param},
@@ -39,7 +39,7 @@ impl<N> Outbound<N> {
             let allow = config.allow_discovery.clone();
             accept
                 .push(profiles::discover::layer(
-                    profiles,
+                    LimitBuilds::new(config.build_limit, profiles),
                     move |a: tcp::Accept| {
                         let OrigDstAddr(addr) = a.orig_dst;
                         if allow.matches_ip(addr.ip()) {
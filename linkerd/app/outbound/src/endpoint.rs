@@ -187,6 +187,24 @@ impl<P: Copy + std::fmt::Debug> MapEndpoint<Concrete<P>, Metadata> for FromMetad
     }
 }
 
+impl MapEndpoint<http::Concrete, Metadata> for FromMetadata {
+    type Out = http::Endpoint;
+
+    fn map_endpoint(
+        &self,
+        concrete: &http::Concrete,
+        addr: SocketAddr,
+        metadata: Metadata,
+    ) -> Self::Out {
+        MapEndpoint::<Concrete<http::Version>, Metadata>::map_endpoint(
+            self,
+            &concrete.inner,
+            addr,
+            metadata,
+        )
+    }
+}
+
 // === Outbound ===
 
 impl<S> Outbound<S> {
@@ -194,10 +212,15 @@ impl<S> Outbound<S> {
     where
         Self: Clone + 'static,
         S: svc::Service<tcp::Connect, Error = io::Error> + Clone + Send + Sync + Unpin + 'static,
-        S::Response:
-            tls::HasNegotiatedProtocol + io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+        S::Response: tls::HasNegotiatedProtocol
+            + io::AsyncRead
+            + io::AsyncWrite
+            + io::Splice
+            + Send
+            + Unpin
+            + 'static,
         S::Future: Send + Unpin,
-        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr,
+        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + io::Splice,
         I: fmt::Debug + Send + Sync + Unpin + 'static,
     {
         let http = self
@@ -0,0 +1,363 @@
+use super::route_timeout::parse_duration;
+use futures::{future, ready, TryFutureExt};
+use linkerd_app_core::{
+    breaker::{Breaker, BreakerThresholds, Permit},
+    dst,
+    errors::HttpError,
+    profiles,
+    proxy::http,
+    svc, Error,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tracing::warn;
+
+/// Configures a circuit breaker on each outbound route.
+///
+/// A route's breaker opens once `max_failures` of its requests have failed
+/// consecutively. While a breaker is open, requests on its route fail
+/// immediately with a 503 Service Unavailable, without being dispatched. After
+/// `open_timeout`, a single request is dispatched to probe the route: the
+/// breaker closes if it succeeds and remains open for another `open_timeout`
+/// if it fails.
+///
+/// Each route has its own breaker, so one route tripping does not affect the
+/// other routes of its logical service, even though they share endpoints. A
+/// route's thresholds are, in order of precedence:
+///
+/// 1. The thresholds in the route's metadata, when a `label` is configured;
+/// 2. The `default` thresholds.
+///
+/// Thresholds are written as `<max_failures>/<open_timeout>`, e.g. `5/10s`. A
+/// route whose thresholds are `0` has no breaker, so a route may opt out of
+/// the default. Thresholds that cannot be parsed are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct RouteBreakers {
+    /// The route metadata label holding the route's thresholds.
+    pub label: Option<String>,
+
+    /// The thresholds of routes whose metadata doesn't set thresholds.
+    pub default: Option<BreakerThresholds>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewBreakRoute<N> {
+    inner: N,
+    breakers: Arc<RouteBreakers>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BreakRoute<P> {
+    inner: P,
+    breaker: Option<Arc<RouteBreaker>>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct BreakRouteFuture<F> {
+    #[pin]
+    inner: F,
+    breaker: Arc<RouteBreaker>,
+    permit: Permit,
+}
+
+#[derive(Debug)]
+struct RouteBreaker {
+    breaker: Breaker,
+    response_classes: profiles::http::ResponseClasses,
+}
+
+// === impl RouteBreakers ===
+
+impl RouteBreakers {
+    fn thresholds(&self, route: &profiles::http::Route) -> Option<BreakerThresholds> {
+        let thresholds = self.hint(route).or(self.default)?;
+        if thresholds.max_failures == 0 {
+            return None;
+        }
+        Some(thresholds)
+    }
+
+    fn hint(&self, route: &profiles::http::Route) -> Option<BreakerThresholds> {
+        let value = route.labels().get(self.label.as_ref()?)?;
+        let hint = parse_thresholds(value);
+        if hint.is_none() {
+            warn!(%value, "Ignoring invalid route circuit breaker thresholds");
+        }
+        hint
+    }
+}
+
+fn parse_thresholds(s: &str) -> Option<BreakerThresholds> {
+    let s = s.trim();
+    if s == "0" {
+        return Some(BreakerThresholds {
+            max_failures: 0,
+            open_timeout: Duration::from_secs(0),
+        });
+    }
+    let (max_failures, open_timeout) = s.split_once('/')?;
+    Some(BreakerThresholds {
+        max_failures: max_failures.trim().parse().ok()?,
+        open_timeout: parse_duration(open_timeout)?,
+    })
+}
+
+// === impl NewBreakRoute ===
+
+impl<N> NewBreakRoute<N> {
+    pub fn layer(breakers: RouteBreakers) -> impl svc::Layer<N, Service = Self> + Clone {
+        let breakers = Arc::new(breakers);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            breakers: breakers.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewBreakRoute<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = BreakRoute<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let breaker = self.breakers.thresholds(&route.route).map(|thresholds| {
+            Arc::new(RouteBreaker {
+                breaker: Breaker::new(thresholds),
+                response_classes: route.route.response_classes().clone(),
+            })
+        });
+        BreakRoute {
+            inner: self.inner.new_service(route),
+            breaker,
+        }
+    }
+}
+
+// === impl BreakRoute ===
+
+impl<P, S, A, B> svc::stack::Proxy<http::Request<A>, S> for BreakRoute<P>
+where
+    P: svc::stack::Proxy<http::Request<A>, S, Response = http::Response<B>>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::Ready<Result<http::Response<B>, Error>>,
+        future::Either<future::ErrInto<P::Future, Error>, BreakRouteFuture<P::Future>>,
+    >;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<A>) -> Self::Future {
+        let breaker = match self.breaker.as_ref() {
+            Some(breaker) => breaker,
+            None => {
+                return future::Either::Right(future::Either::Left(
+                    self.inner.proxy(svc, req).err_into(),
+                ))
+            }
+        };
+
+        let permit = match breaker.breaker.permit() {
+            Some(permit) => permit,
+            None => {
+                return future::Either::Left(future::err(
+                    HttpError::circuit_open("route circuit breaker is open").into(),
+                ))
+            }
+        };
+        future::Either::Right(future::Either::Right(BreakRouteFuture {
+            inner: self.inner.proxy(svc, req),
+            breaker: breaker.clone(),
+            permit,
+        }))
+    }
+}
+
+// === impl BreakRouteFuture ===
+
+impl<F, B, E> Future for BreakRouteFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    E: Into<Error>,
+{
+    type Output = Result<http::Response<B>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx)).map_err(Into::into);
+        let failed = match res.as_ref() {
+            Ok(rsp) => this.breaker.is_failure(rsp),
+            Err(_) => true,
+        };
+        this.breaker.breaker.record(*this.permit, failed);
+        Poll::Ready(res)
+    }
+}
+
+// === impl RouteBreaker ===
+
+impl RouteBreaker {
+    /// Classifies responses by the route's response classes. Responses that
+    /// match no class are failures if their status is a server error.
+    fn is_failure<B>(&self, rsp: &http::Response<B>) -> bool {
+        self.response_classes
+            .iter()
+            .find(|class| class.is_match(rsp))
+            .map(|class| class.is_failure())
+            .unwrap_or_else(|| rsp.status().is_server_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::metrics::Direction;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use svc::{stack::Proxy, NewService};
+
+    const LABEL: &str = "circuit-breaker";
+
+    fn route(thresholds: Option<&str>) -> dst::Route {
+        let labels = thresholds.map(|t| (LABEL.to_string(), t.to_string()));
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::Out,
+        }
+    }
+
+    fn breakers(default: Option<&str>) -> NewBreakRoute<fn(dst::Route)> {
+        let breakers = RouteBreakers {
+            label: Some(LABEL.to_string()),
+            default: default.map(|d| parse_thresholds(d).unwrap()),
+        };
+        let route: fn(dst::Route) = |_| ();
+        svc::Layer::layer(&NewBreakRoute::layer(breakers), route)
+    }
+
+    #[test]
+    fn thresholds() {
+        let default = RouteBreakers {
+            label: Some(LABEL.to_string()),
+            default: parse_thresholds("5/10s"),
+        };
+        let thresholds = |t: Option<&str>| default.thresholds(&route(t).route);
+        assert_eq!(
+            thresholds(None),
+            Some(BreakerThresholds {
+                max_failures: 5,
+                open_timeout: Duration::from_secs(10),
+            })
+        );
+        assert_eq!(
+            thresholds(Some("2/500ms")),
+            Some(BreakerThresholds {
+                max_failures: 2,
+                open_timeout: Duration::from_millis(500),
+            })
+        );
+        assert_eq!(thresholds(Some("0")), None, "routes may opt out");
+        assert_eq!(thresholds(Some("bogus")), thresholds(None));
+        assert_eq!(
+            RouteBreakers::default().thresholds(&route(None).route),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn routes_trip_independently() {
+        // Both routes share an endpoint that always fails.
+        let dispatched = Arc::new(AtomicUsize::new(0));
+        let mut endpoint = {
+            let dispatched = dispatched.clone();
+            svc::mk(move |_: http::Request<()>| {
+                dispatched.fetch_add(1, Ordering::SeqCst);
+                let rsp = http::Response::builder()
+                    .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(())
+                    .unwrap();
+                future::ok::<_, Error>(rsp)
+            })
+        };
+        let mut new_route = breakers(Some("3/10s"));
+        let strict = new_route.new_service(route(Some("1/10s")));
+        let lenient = new_route.new_service(route(None));
+
+        let rsp = strict.proxy(&mut endpoint, http::Request::new(())).await;
+        assert_eq!(
+            rsp.unwrap().status(),
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let err = strict
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .unwrap_err();
+        assert!(err.is::<HttpError>(), "the strict route must be open");
+        assert_eq!(dispatched.load(Ordering::SeqCst), 1);
+
+        for _ in 0..3 {
+            let rsp = lenient.proxy(&mut endpoint, http::Request::new(())).await;
+            assert!(rsp.is_ok(), "the lenient route must remain closed");
+        }
+        assert!(lenient
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .is_err());
+        assert_eq!(dispatched.load(Ordering::SeqCst), 4);
+
+        // Once the timeout elapses, a single request probes each route.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(strict
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .is_ok());
+        assert!(strict
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .is_err());
+        assert_eq!(dispatched.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn closes_after_successful_probe() {
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut endpoint = {
+            let fail = fail.clone();
+            svc::mk(move |_: http::Request<()>| {
+                let status = if fail.load(Ordering::SeqCst) {
+                    http::StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    http::StatusCode::OK
+                };
+                let rsp = http::Response::builder().status(status).body(()).unwrap();
+                future::ok::<_, Error>(rsp)
+            })
+        };
+        let route = breakers(Some("1/1s")).new_service(route(None));
+
+        assert!(route
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .is_ok());
+        assert!(route
+            .proxy(&mut endpoint, http::Request::new(()))
+            .await
+            .is_err());
+
+        fail.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        for _ in 0..3 {
+            let rsp = route.proxy(&mut endpoint, http::Request::new(())).await;
+            assert_eq!(rsp.unwrap().status(), http::StatusCode::OK);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use super::{Concrete, Logical};
+use crate::stable_hash::StableHasher;
+use futures::{future, prelude::*, ready};
+use linkerd_app_core::{
+    proxy::{
+        api_resolve::{ConcreteAddr, Metadata},
+        core::Update,
+        http,
+    },
+    svc, Error,
+};
+use pin_project::pin_project;
+use rand::{rngs::SmallRng, thread_rng, Rng, SeedableRng};
+use std::{
+    collections::HashSet,
+    hash::Hasher,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tracing::trace;
+
+/// Routes a share of each service's requests to its canary endpoints.
+///
+/// Canary endpoints are those with the configured metadata label. Each of a
+/// service's concrete addresses is balanced as two subsets of endpoints: the
+/// canary endpoints and the remaining, stable endpoints. While a service has
+/// no canary endpoints, all of its requests are routed to the stable subset.
+#[derive(Clone, Debug)]
+pub struct CanarySplit {
+    /// The endpoint label that identifies canary endpoints.
+    pub label: String,
+
+    /// The value of `label` on canary endpoints.
+    pub value: String,
+
+    /// Determines which requests are routed to canary endpoints.
+    pub selector: CanarySelector,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanarySelector {
+    /// Routes a percentage of requests, chosen at random.
+    Percent(u8),
+
+    /// Routes a percentage of a request header's values, so that requests
+    /// with the same value are consistently routed to the same subset.
+    /// Requests without the header are routed to stable endpoints.
+    Header { name: http::HeaderName, percent: u8 },
+}
+
+/// The subset of a concrete address's endpoints that is resolved for a
+/// balancer.
+#[derive(Clone, Debug)]
+pub struct Subset {
+    split: Arc<CanarySplit>,
+    canary: bool,
+    endpoints: Arc<AtomicUsize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewCanarySplit<N> {
+    inner: N,
+    split: Option<Arc<CanarySplit>>,
+}
+
+#[derive(Debug)]
+pub struct CanaryRoute<S> {
+    stable: S,
+    canary: Option<Canary<S>>,
+}
+
+#[derive(Debug)]
+struct Canary<S> {
+    service: S,
+    selector: CanarySelector,
+    endpoints: Arc<AtomicUsize>,
+    ready: bool,
+    rng: SmallRng,
+}
+
+/// Resolves the endpoints in a concrete target's subset.
+#[derive(Clone, Debug)]
+pub struct ResolveSubset<R>(R);
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResolveSubsetFuture<F> {
+    #[pin]
+    future: F,
+    subset: Option<Option<Subset>>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct SubsetResolution<R> {
+    #[pin]
+    resolution: R,
+    subset: Option<Subset>,
+    addrs: HashSet<SocketAddr>,
+}
+
+// === impl Subset ===
+
+impl Subset {
+    fn new(split: Arc<CanarySplit>, canary: bool) -> Self {
+        Self {
+            split,
+            canary,
+            endpoints: Default::default(),
+        }
+    }
+
+    /// Returns true if this subset holds the canary endpoints.
+    pub(crate) fn is_canary(&self) -> bool {
+        self.canary
+    }
+
+    fn contains(&self, meta: &Metadata) -> bool {
+        let is_canary = meta.labels().get(&self.split.label) == Some(&self.split.value);
+        is_canary == self.canary
+    }
+}
+
+// === impl NewCanarySplit ===
+
+impl<N> NewCanarySplit<N> {
+    pub fn layer(split: Option<CanarySplit>) -> impl svc::Layer<N, Service = Self> + Clone {
+        let split = split.map(Arc::new);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            split: split.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<(ConcreteAddr, Logical)> for NewCanarySplit<N>
+where
+    N: svc::NewService<Concrete>,
+{
+    type Service = CanaryRoute<N::Service>;
+
+    fn new_service(&mut self, (resolve, logical): (ConcreteAddr, Logical)) -> Self::Service {
+        let split = match self.split.clone() {
+            Some(split) => split,
+            None => {
+                let stable = self.inner.new_service(Concrete::from((resolve, logical)));
+                return CanaryRoute {
+                    stable,
+                    canary: None,
+                };
+            }
+        };
+
+        let stable = Subset::new(split.clone(), false);
+        let canary = Subset::new(split.clone(), true);
+        let endpoints = canary.endpoints.clone();
+        let mut new_service = |subset| {
+            self.inner.new_service(Concrete {
+                inner: (resolve.clone(), logical.clone()).into(),
+                subset: Some(subset),
+            })
+        };
+        CanaryRoute {
+            stable: new_service(stable),
+            canary: Some(Canary {
+                service: new_service(canary),
+                selector: split.selector.clone(),
+                endpoints,
+                ready: false,
+                rng: SmallRng::from_rng(&mut thread_rng()).expect("RNG must initialize"),
+            }),
+        }
+    }
+}
+
+// === impl CanaryRoute ===
+
+impl<B, S> svc::Service<http::Request<B>> for CanaryRoute<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::ErrInto<S::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // The canary is always polled so that its endpoints are discovered,
+        // but requests only wait for the stable subset to become ready.
+        if let Some(canary) = self.canary.as_mut() {
+            canary.ready = canary
+                .service
+                .poll_ready(cx)
+                .map_err(Into::into)?
+                .is_ready();
+        }
+        self.stable.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(canary) = self.canary.as_mut() {
+            if canary.ready && canary.endpoints.load(Ordering::Acquire) > 0 && canary.selects(&req)
+            {
+                trace!("Routing request to canary endpoints");
+                canary.ready = false;
+                return canary.service.call(req).err_into();
+            }
+        }
+        self.stable.call(req).err_into()
+    }
+}
+
+// === impl Canary ===
+
+impl<S> Canary<S> {
+    fn selects<B>(&mut self, req: &http::Request<B>) -> bool {
+        match self.selector {
+            CanarySelector::Percent(percent) => self.rng.gen_range(0..100) < percent,
+            CanarySelector::Header { ref name, percent } => match req.headers().get(name) {
+                Some(value) => {
+                    // Values must be routed to the same subset by all proxies.
+                    let mut hasher = StableHasher::default();
+                    hasher.write(value.as_bytes());
+                    hasher.finish() % 100 < u64::from(percent)
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+// === impl ResolveSubset ===
+
+impl<R> ResolveSubset<R> {
+    pub fn new(resolve: R) -> Self {
+        Self(resolve)
+    }
+}
+
+impl<R, S> svc::Service<Concrete> for ResolveSubset<R>
+where
+    R: svc::Service<ConcreteAddr, Response = S>,
+    S: TryStream<Ok = Update<Metadata>, Error = R::Error>,
+{
+    type Response = SubsetResolution<S>;
+    type Error = R::Error;
+    type Future = ResolveSubsetFuture<R::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, concrete: Concrete) -> Self::Future {
+        ResolveSubsetFuture {
+            future: self.0.call(concrete.inner.resolve),
+            subset: Some(concrete.subset),
+        }
+    }
+}
+
+impl<F, S, E> Future for ResolveSubsetFuture<F>
+where
+    F: TryFuture<Ok = S, Error = E>,
+{
+    type Output = Result<SubsetResolution<S>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let resolution = ready!(this.future.try_poll(cx))?;
+        let subset = this.subset.take().expect("polled after ready");
+        Poll::Ready(Ok(SubsetResolution {
+            resolution,
+            subset,
+            addrs: HashSet::new(),
+        }))
+    }
+}
+
+// === impl SubsetResolution ===
+
+impl<R> Stream for SubsetResolution<R>
+where
+    R: TryStream<Ok = Update<Metadata>>,
+{
+    type Item = Result<Update<Metadata>, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let update = match ready!(this.resolution.try_poll_next(cx)) {
+            Some(update) => update?,
+            None => return Poll::Ready(None),
+        };
+        let subset = match this.subset.as_ref() {
+            Some(subset) => subset,
+            None => return Poll::Ready(Some(Ok(update))),
+        };
+
+        let update = match update {
+            Update::Add(eps) => {
+                let eps = eps
+                    .into_iter()
+                    .filter(|(_, meta)| subset.contains(meta))
+                    .collect::<Vec<_>>();
+                this.addrs.extend(eps.iter().map(|(addr, _)| *addr));
+                Update::Add(eps)
+            }
+            Update::Reset(eps) => {
+                let eps = eps
+                    .into_iter()
+                    .filter(|(_, meta)| subset.contains(meta))
+                    .collect::<Vec<_>>();
+                *this.addrs = eps.iter().map(|(addr, _)| *addr).collect();
+                Update::Reset(eps)
+            }
+            Update::Remove(addrs) => {
+                let addrs = addrs
+                    .into_iter()
+                    .filter(|addr| this.addrs.remove(addr))
+                    .collect();
+                Update::Remove(addrs)
+            }
+            Update::DoesNotExist => {
+                this.addrs.clear();
+                Update::DoesNotExist
+            }
+        };
+        subset.endpoints.store(this.addrs.len(), Ordering::Release);
+        trace!(
+            canary = subset.canary,
+            endpoints = this.addrs.len(),
+            "Updated subset"
+        );
+        Poll::Ready(Some(Ok(update)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::support::profile;
+    use linkerd_app_core::{profiles, proxy::api_resolve::ProtocolHint, NameAddr};
+    use std::str::FromStr;
+    use svc::ServiceExt;
+
+    type Route = CanaryRoute<svc::BoxService<http::Request<()>, &'static str, Error>>;
+
+    fn split(selector: CanarySelector) -> Arc<CanarySplit> {
+        Arc::new(CanarySplit {
+            label: "track".to_string(),
+            value: "canary".to_string(),
+            selector,
+        })
+    }
+
+    fn endpoint(port: u16, track: &str) -> (SocketAddr, Metadata) {
+        let labels = Some(("track".to_string(), track.to_string()));
+        let meta = Metadata::new(labels, ProtocolHint::Unknown, None, None, None);
+        (([10, 0, 0, 1], port).into(), meta)
+    }
+
+    /// Builds a route whose services respond with the name of their subset.
+    fn route(selector: CanarySelector, canary_endpoints: usize) -> Route {
+        let subset = |name| svc::BoxService::new(svc::mk(move |_| future::ok::<_, Error>(name)));
+        CanaryRoute {
+            stable: subset("stable"),
+            canary: Some(Canary {
+                service: subset("canary"),
+                selector,
+                endpoints: Arc::new(AtomicUsize::new(canary_endpoints)),
+                ready: false,
+                rng: SmallRng::seed_from_u64(0),
+            }),
+        }
+    }
+
+    async fn send(route: &mut Route, req: http::Request<()>) -> &'static str {
+        route.ready().await.unwrap().call(req).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn splits_by_percent() {
+        let mut route = route(CanarySelector::Percent(10), 1);
+        let mut canary = 0;
+        for _ in 0..10_000 {
+            if send(&mut route, http::Request::new(())).await == "canary" {
+                canary += 1;
+            }
+        }
+        assert!((800..1200).contains(&canary), "{} canary requests", canary);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn splits_by_header() {
+        let name = http::HeaderName::from_static("x-user");
+        let mut route = route(
+            CanarySelector::Header {
+                name: name.clone(),
+                percent: 10,
+            },
+            1,
+        );
+        let req = |user: usize| {
+            http::Request::builder()
+                .header(&name, user.to_string())
+                .body(())
+                .unwrap()
+        };
+
+        let mut canary = 0;
+        for user in 0..10_000 {
+            let subset = send(&mut route, req(user)).await;
+            if subset == "canary" {
+                canary += 1;
+            }
+            // Each value is consistently routed to the same subset.
+            assert_eq!(send(&mut route, req(user)).await, subset);
+        }
+        assert!((800..1200).contains(&canary), "{} canary requests", canary);
+
+        // Requests without the header are routed to stable endpoints.
+        for _ in 0..100 {
+            assert_eq!(send(&mut route, http::Request::new(())).await, "stable");
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn empty_canary_routes_to_stable() {
+        let mut route = route(CanarySelector::Percent(100), 0);
+        for _ in 0..100 {
+            assert_eq!(send(&mut route, http::Request::new(())).await, "stable");
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn resolves_subsets() {
+        let split = split(CanarySelector::Percent(10));
+        let addr = NameAddr::from_str("foo.ns.svc.cluster.local:80").unwrap();
+        let updates = vec![
+            Update::Reset(vec![endpoint(1, "stable"), endpoint(2, "canary")]),
+            Update::Add(vec![endpoint(3, "canary"), endpoint(4, "stable")]),
+            Update::Remove(vec![([10, 0, 0, 1], 1).into(), ([10, 0, 0, 1], 2).into()]),
+        ];
+        let resolve = |subset: &Subset| {
+            let updates = updates.clone();
+            let resolve = svc::mk(move |_: ConcreteAddr| {
+                let updates = updates.clone().into_iter().map(Ok::<_, Error>);
+                future::ok::<_, Error>(stream::iter(updates))
+            });
+            let logical = Logical {
+                profile: profile::only_default(),
+                logical_addr: profiles::LogicalAddr(addr.clone()),
+                protocol: http::Version::Http1,
+            };
+            ResolveSubset::new(resolve).oneshot(Concrete {
+                inner: (ConcreteAddr(addr.clone()), logical).into(),
+                subset: Some(subset.clone()),
+            })
+        };
+
+        let canary = Subset::new(split.clone(), true);
+        let resolution = resolve(&canary).await.unwrap();
+        let canary_updates = resolution.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(
+            canary_updates,
+            vec![
+                Update::Reset(vec![endpoint(2, "canary")]),
+                Update::Add(vec![endpoint(3, "canary")]),
+                Update::Remove(vec![([10, 0, 0, 1], 2).into()]),
+            ]
+        );
+        assert_eq!(canary.endpoints.load(Ordering::Acquire), 1);
+
+        let stable = Subset::new(split, false);
+        let resolution = resolve(&stable).await.unwrap();
+        let stable_updates = resolution.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(
+            stable_updates,
+            vec![
+                Update::Reset(vec![endpoint(1, "stable")]),
+                Update::Add(vec![endpoint(4, "stable")]),
+                Update::Remove(vec![([10, 0, 0, 1], 1).into()]),
+            ]
+        );
+        assert_eq!(stable.endpoints.load(Ordering::Acquire), 1);
+    }
+}
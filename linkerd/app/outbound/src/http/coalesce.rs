@@ -0,0 +1,588 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::prelude::*;
+use linkerd_app_core::{
+    proxy::http::{self, HttpBody},
+    svc::{self, ServiceExt},
+    Error,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time::Instant};
+use tracing::{debug, trace};
+
+/// Configures the coalescing of concurrent, identical requests to each logical
+/// service.
+///
+/// A `GET` or `HEAD` request without a body is coalesced with an identical
+/// request to the same service that was dispatched at most `window` ago and
+/// that has not yet received a response: rather than being dispatched, it waits
+/// for that request's response and receives a copy of it. Requests are
+/// identical when they have the same method, URI, and values for each of the
+/// `vary_headers`. Credentials (i.e. `authorization`, `proxy-authorization`,
+/// and `cookie` headers) are always compared, so responses are never shared
+/// between clients with different credentials, and so are range and
+/// conditional headers, so that ranged and conditional requests only share
+/// their partial or not-modified responses with identical requests. Requests
+/// with a `no-cache` directive (or `pragma: no-cache`) are never coalesced.
+///
+/// A response is only shared if its body has a known length no greater than
+/// `max_body_bytes`, if it is not private to its client (i.e. it does not set
+/// cookies or a `private` or `no-store` cache directive), and with requests
+/// that match the original request in each header named by the response's
+/// `vary` header. Requests that cannot be sent a copy, including those waiting
+/// on a request that fails, are dispatched themselves.
+///
+/// At most `max_pending` requests to each service may be awaited at once, each
+/// by at most `max_waiters` requests; further requests are dispatched without
+/// being coalesced.
+#[derive(Clone, Debug)]
+pub struct CoalesceConfig {
+    pub window: Duration,
+    pub vary_headers: Vec<http::HeaderName>,
+    pub max_pending: usize,
+    pub max_waiters: usize,
+    pub max_body_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Coalesce<S> {
+    inner: S,
+    state: Option<Arc<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    window: Duration,
+    key_headers: Vec<http::HeaderName>,
+    max_pending: usize,
+    max_waiters: usize,
+    max_body_bytes: usize,
+    pending: Mutex<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    next_id: u64,
+    requests: HashMap<Key, InFlight>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    method: http::Method,
+    uri: http::uri::Uri,
+    headers: Vec<Vec<http::HeaderValue>>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    id: u64,
+    dispatched: Instant,
+    waiters: Vec<oneshot::Sender<Arc<Shared>>>,
+}
+
+/// Removes a dispatched request from the pending requests when it completes
+/// or is canceled, so that requests waiting on it are dispatched themselves.
+struct Original {
+    state: Arc<State>,
+    key: Option<Key>,
+    id: u64,
+}
+
+/// A response that may be copied to the requests that waited on it.
+#[derive(Debug)]
+struct Shared {
+    status: http::StatusCode,
+    headers: http::header::HeaderMap,
+    body: Bytes,
+    trailers: Option<http::header::HeaderMap>,
+    request_headers: http::header::HeaderMap,
+}
+
+#[derive(Debug)]
+struct SharedBody {
+    data: Option<Bytes>,
+    trailers: Option<http::header::HeaderMap>,
+}
+
+enum Role {
+    Original(Original),
+    Waiting(oneshot::Receiver<Arc<Shared>>),
+}
+
+type ResponseFuture =
+    Pin<Box<dyn Future<Output = Result<http::Response<http::BoxBody>, Error>> + Send + 'static>>;
+
+// === impl Coalesce ===
+
+impl<S> Coalesce<S> {
+    pub fn layer(config: Option<CoalesceConfig>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            // Each service coalesces its own requests.
+            state: config.clone().map(|c| Arc::new(State::new(c))),
+        })
+    }
+}
+
+impl<S> svc::Service<http::Request<http::BoxBody>> for Coalesce<S>
+where
+    S: svc::Service<http::Request<http::BoxBody>, Response = http::Response<http::BoxBody>>,
+    S: Clone + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<http::BoxBody>;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<http::BoxBody>) -> Self::Future {
+        let role = self
+            .state
+            .as_ref()
+            .and_then(|state| state.key(&req).and_then(|key| state.join(key)));
+        match role {
+            None => Box::pin(self.inner.call(req).err_into::<Error>()),
+
+            Some(Role::Original(original)) => {
+                let request_headers = req.headers().clone();
+                let rsp = self.inner.call(req);
+                Box::pin(async move {
+                    let rsp = rsp.await.map_err(Into::into)?;
+                    original.share(rsp, request_headers).await
+                })
+            }
+
+            Some(Role::Waiting(shared)) => {
+                let inner = self.inner.clone();
+                Box::pin(async move {
+                    if let Ok(shared) = shared.await {
+                        if let Some(rsp) = shared.response_for(req.headers()) {
+                            trace!("Coalesced request");
+                            return Ok(rsp);
+                        }
+                    }
+                    debug!("Dispatching request that could not be coalesced");
+                    inner.oneshot(req).err_into::<Error>().await
+                })
+            }
+        }
+    }
+}
+
+// === impl State ===
+
+impl State {
+    fn new(config: CoalesceConfig) -> Self {
+        let mut key_headers = vec![
+            http::header::AUTHORIZATION,
+            http::header::PROXY_AUTHORIZATION,
+            http::header::COOKIE,
+            http::header::RANGE,
+            http::header::IF_RANGE,
+            http::header::IF_MATCH,
+            http::header::IF_NONE_MATCH,
+            http::header::IF_MODIFIED_SINCE,
+            http::header::IF_UNMODIFIED_SINCE,
+        ];
+        for name in config.vary_headers {
+            if !key_headers.contains(&name) {
+                key_headers.push(name);
+            }
+        }
+        Self {
+            window: config.window,
+            key_headers,
+            max_pending: config.max_pending,
+            max_waiters: config.max_waiters,
+            max_body_bytes: config.max_body_bytes,
+            pending: Default::default(),
+        }
+    }
+
+    /// Returns the key by which the request may be coalesced, if it is safe
+    /// to coalesce.
+    fn key(&self, req: &http::Request<http::BoxBody>) -> Option<Key> {
+        let safe = req.method() == http::Method::GET || req.method() == http::Method::HEAD;
+        if !safe || !req.body().is_end_stream() || req.headers().contains_key(http::header::UPGRADE)
+        {
+            return None;
+        }
+
+        // Clients that ask for a fresh response must not receive a copy of a
+        // response to a request that was dispatched before theirs.
+        let no_cache = header_tokens(req.headers(), http::header::CACHE_CONTROL)
+            .chain(header_tokens(req.headers(), http::header::PRAGMA))
+            .any(|d| d.eq_ignore_ascii_case("no-cache"));
+        if no_cache {
+            return None;
+        }
+
+        let headers = self
+            .key_headers
+            .iter()
+            .map(|name| req.headers().get_all(name).iter().cloned().collect())
+            .collect();
+        Some(Key {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers,
+        })
+    }
+
+    fn join(self: &Arc<Self>, key: Key) -> Option<Role> {
+        let now = Instant::now();
+        let mut pending = self.pending.lock();
+        if let Some(in_flight) = pending.requests.get_mut(&key) {
+            if now.saturating_duration_since(in_flight.dispatched) > self.window {
+                return None;
+            }
+            if in_flight.waiters.len() >= self.max_waiters {
+                trace!("Too many requests waiting to be coalesced");
+                return None;
+            }
+            let (tx, rx) = oneshot::channel();
+            in_flight.waiters.push(tx);
+            return Some(Role::Waiting(rx));
+        }
+
+        if pending.requests.len() >= self.max_pending {
+            trace!("Too many pending requests to coalesce");
+            return None;
+        }
+        let id = pending.next_id;
+        pending.next_id += 1;
+        pending.requests.insert(
+            key.clone(),
+            InFlight {
+                id,
+                dispatched: now,
+                waiters: Vec::new(),
+            },
+        );
+        Some(Role::Original(Original {
+            state: self.clone(),
+            key: Some(key),
+            id,
+        }))
+    }
+}
+
+// === impl Original ===
+
+impl Original {
+    /// Stops coalescing requests with the original request, returning the
+    /// senders of the requests that are waiting on it.
+    fn complete(&mut self) -> Vec<oneshot::Sender<Arc<Shared>>> {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return Vec::new(),
+        };
+        let mut pending = self.state.pending.lock();
+        let current = pending
+            .requests
+            .get(&key)
+            .map_or(false, |f| f.id == self.id);
+        if !current {
+            return Vec::new();
+        }
+        pending
+            .requests
+            .remove(&key)
+            .map(|in_flight| in_flight.waiters)
+            .unwrap_or_default()
+    }
+
+    /// Copies the response to the requests that waited on it, if it may be
+    /// shared.
+    async fn share(
+        mut self,
+        rsp: http::Response<http::BoxBody>,
+        request_headers: http::header::HeaderMap,
+    ) -> Result<http::Response<http::BoxBody>, Error> {
+        let waiters = self.complete();
+        if waiters.is_empty() {
+            return Ok(rsp);
+        }
+
+        let (head, mut body) = rsp.into_parts();
+        let len = match body.size_hint().exact() {
+            Some(len)
+                if len as usize <= self.state.max_body_bytes && is_shareable(&head.headers) =>
+            {
+                len as usize
+            }
+            // Dropping the waiters' senders causes them to be dispatched.
+            _ => return Ok(http::Response::from_parts(head, body)),
+        };
+
+        let mut data = BytesMut::with_capacity(len);
+        while let Some(chunk) = body.data().await {
+            data.put(chunk?);
+        }
+        let trailers = body.trailers().await?;
+
+        let shared = Arc::new(Shared {
+            status: head.status,
+            headers: head.headers.clone(),
+            body: data.freeze(),
+            trailers,
+            request_headers,
+        });
+        debug!(waiters = waiters.len(), "Sharing response");
+        for tx in waiters {
+            let _ = tx.send(shared.clone());
+        }
+
+        let body = SharedBody {
+            data: Some(shared.body.clone()),
+            trailers: shared.trailers.clone(),
+        };
+        Ok(http::Response::from_parts(head, http::BoxBody::new(body)))
+    }
+}
+
+impl Drop for Original {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
+
+/// Responses that set cookies or are marked private must not be shared.
+fn is_shareable(headers: &http::header::HeaderMap) -> bool {
+    if headers.contains_key(http::header::SET_COOKIE) {
+        return false;
+    }
+    let private = header_tokens(headers, http::header::CACHE_CONTROL)
+        .any(|d| d.eq_ignore_ascii_case("private") || d.eq_ignore_ascii_case("no-store"));
+    let vary_all = header_tokens(headers, http::header::VARY).any(|v| v == "*");
+    !private && !vary_all
+}
+
+fn header_tokens(
+    headers: &http::header::HeaderMap,
+    name: http::HeaderName,
+) -> impl Iterator<Item = &str> + '_ {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+// === impl Shared ===
+
+impl Shared {
+    /// Copies the response for a request that waited on it, unless the
+    /// response varies on a header in which the request differs from the
+    /// original request.
+    fn response_for(
+        &self,
+        headers: &http::header::HeaderMap,
+    ) -> Option<http::Response<http::BoxBody>> {
+        for name in header_tokens(&self.headers, http::header::VARY) {
+            let name = http::HeaderName::from_bytes(name.as_bytes()).ok()?;
+            if !headers
+                .get_all(&name)
+                .iter()
+                .eq(self.request_headers.get_all(&name).iter())
+            {
+                return None;
+            }
+        }
+
+        let mut rsp = http::Response::new(http::BoxBody::new(SharedBody {
+            data: Some(self.body.clone()),
+            trailers: self.trailers.clone(),
+        }));
+        *rsp.status_mut() = self.status;
+        *rsp.headers_mut() = self.headers.clone();
+        Some(rsp)
+    }
+}
+
+// === impl SharedBody ===
+
+impl HttpBody for SharedBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().filter(|d| !d.is_empty()).map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::header::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let len = self.data.as_ref().map(|d| d.len() as u64).unwrap_or(0);
+        http_body::SizeHint::with_exact(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use svc::Layer;
+
+    fn config() -> CoalesceConfig {
+        CoalesceConfig {
+            window: Duration::from_millis(100),
+            vary_headers: vec![http::header::ACCEPT],
+            max_pending: 10,
+            max_waiters: 10,
+            max_body_bytes: 1024,
+        }
+    }
+
+    /// Returns a service that counts its requests and responds to each after
+    /// a second.
+    fn upstream(
+        calls: Arc<AtomicUsize>,
+        vary: Option<&'static str>,
+    ) -> impl svc::Service<
+        http::Request<http::BoxBody>,
+        Response = http::Response<http::BoxBody>,
+        Error = Error,
+    > + Clone {
+        Coalesce::layer(Some(config())).layer(svc::mk(move |_: http::Request<http::BoxBody>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                let mut rsp = http::Response::builder().header("x-call", call.to_string());
+                if let Some(vary) = vary {
+                    rsp = rsp.header(http::header::VARY, vary);
+                }
+                Ok::<_, Error>(rsp.body(http::BoxBody::from(Bytes::from("hello")))?)
+            }
+        }))
+    }
+
+    fn get(headers: &[(&'static str, &'static str)]) -> http::Request<http::BoxBody> {
+        let mut req = http::Request::get("http://foo.ns.svc.cluster.local/users");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(http::BoxBody::default()).unwrap()
+    }
+
+    async fn send<S>(svc: S, reqs: Vec<http::Request<http::BoxBody>>) -> Vec<(String, Bytes)>
+    where
+        S: svc::Service<
+                http::Request<http::BoxBody>,
+                Response = http::Response<http::BoxBody>,
+                Error = Error,
+            > + Clone,
+    {
+        let rsps = future::join_all(reqs.into_iter().map(|req| svc.clone().oneshot(req))).await;
+        let mut out = Vec::new();
+        for rsp in rsps {
+            let rsp = rsp.expect("request must succeed");
+            let call = rsp.headers()["x-call"].to_str().unwrap().to_string();
+            let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+            out.push((call, body));
+        }
+        out
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn coalesces_identical_gets() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = upstream(calls.clone(), None);
+
+        let rsps = send(svc.clone(), (0..10).map(|_| get(&[])).collect()).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(rsps
+            .iter()
+            .all(|(call, body)| call == "0" && body == &Bytes::from("hello")));
+
+        // Requests with different credentials or vary-relevant headers, and
+        // unsafe requests, are not coalesced.
+        let mut post = get(&[]);
+        *post.method_mut() = http::Method::POST;
+        let reqs = vec![
+            get(&[("authorization", "Bearer a")]),
+            get(&[("authorization", "Bearer b")]),
+            get(&[("accept", "text/plain")]),
+            post,
+        ];
+        send(svc.clone(), reqs).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn respects_response_vary() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = upstream(calls.clone(), Some("accept-language"));
+
+        let reqs = vec![
+            get(&[("accept-language", "en")]),
+            get(&[("accept-language", "en")]),
+            get(&[("accept-language", "fr")]),
+        ];
+        let rsps = send(svc, reqs).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(rsps[1].0, "0", "matching requests must share a response");
+        assert_eq!(rsps[2].0, "1", "differing requests must be dispatched");
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn only_coalesces_identical_ranges_and_conditions() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = upstream(calls.clone(), None);
+
+        let reqs = vec![
+            get(&[]),
+            get(&[("range", "bytes=0-1")]),
+            get(&[("range", "bytes=0-1")]),
+            get(&[("if-none-match", "\"abc\"")]),
+            get(&[("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT")]),
+            get(&[("if-range", "\"abc\""), ("range", "bytes=0-1")]),
+            get(&[("cache-control", "no-cache")]),
+            get(&[("cache-control", "max-age=0, no-cache")]),
+        ];
+        let rsps = send(svc, reqs).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+        assert_eq!(
+            rsps[1].0, rsps[2].0,
+            "identical ranged requests must share a response"
+        );
+        assert_ne!(rsps[0].0, rsps[1].0);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn bounds_waiters() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = upstream(calls.clone(), None);
+
+        // The original request may be awaited by at most 10 requests.
+        let rsps = send(svc, (0..15).map(|_| get(&[])).collect()).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert!(rsps[..11].iter().all(|(call, _)| call == "0"));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{http, Outbound};
 use linkerd_app_core::{
     config::{ProxyConfig, ServerConfig},
-    detect, io,
+    connection_log, detect, io,
     svc::{self, Param},
     Error,
 };
@@ -56,6 +56,13 @@ impl<N> Outbound<N> {
                 ))
                 .push_on_response(svc::BoxService::layer())
                 .check_new_service::<(Option<http::Version>, T), _>()
+                .push_map_target(|(version, target): (Option<http::Version>, T)| {
+                    match version {
+                        Some(version) => connection_log::record_protocol(version),
+                        None => connection_log::record_protocol("tcp"),
+                    }
+                    (version, target)
+                })
                 .push_map_target(detect::allow_timeout)
                 .push(svc::BoxNewService::layer())
                 .push(detect::NewDetectService::layer(
@@ -3,10 +3,28 @@ use crate::Outbound;
 use linkerd_app_core::{
     classify, config, http_tracing, metrics,
     proxy::{http, tap},
-    svc, tls, Error, CANONICAL_DST_HEADER,
+    svc::{self, Layer},
+    tls, Error, CANONICAL_DST_HEADER,
 };
+use std::time::Duration;
 use tokio::io;
 
+/// Configures a buffer in front of each endpoint's HTTP client, so that
+/// requests wait for a briefly-stalled endpoint rather than failing.
+///
+/// This is distinct from the buffer in front of each logical service: when an
+/// endpoint is balanced, the balancer only considers it unavailable once its
+/// buffer is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EndpointBuffer {
+    /// The maximum number of requests that may wait for the endpoint.
+    pub capacity: usize,
+
+    /// How long the endpoint may remain unavailable before buffered requests
+    /// fail.
+    pub max_wait: Duration,
+}
+
 impl<C> Outbound<C> {
     pub fn push_http_endpoint<T, B>(self) -> Outbound<svc::BoxNewHttp<T, B>>
     where
@@ -24,10 +42,13 @@ impl<C> Outbound<C> {
         C::Future: Send + Unpin + 'static,
     {
         self.map_stack(|config, rt, connect| {
+            let endpoint_buffer = config.endpoint_buffer;
             let config::ConnectConfig {
                 h1_settings,
                 h2_settings,
                 backoff,
+                close_delimited,
+                response_read_ahead_bytes,
                 ..
             } = config.proxy.connect;
 
@@ -36,10 +57,18 @@ impl<C> Outbound<C> {
             // HTTP/1.x fallback is supported as needed.
             connect
                 .push(http::client::layer(h1_settings, h2_settings))
-                .push_on_response(svc::MapErrLayer::new(Into::<Error>::into))
+                .push_on_response(
+                    svc::layers()
+                        .push(svc::MapErrLayer::new(Into::<Error>::into))
+                        .push(http::HandleCloseDelimited::layer(close_delimited))
+                        .push(http::ReadAhead::layer(response_read_ahead_bytes)),
+                )
                 .check_service::<T>()
                 .into_new_service()
                 .push_new_reconnect(backoff)
+                .push_on_response(svc::layer::mk(move |client| {
+                    EndpointBuffer::buffered(endpoint_buffer, client)
+                }))
                 .push(tap::NewTapHttp::layer(rt.tap.clone()))
                 .push(
                     rt.metrics
@@ -65,6 +94,25 @@ impl<C> Outbound<C> {
     }
 }
 
+// === impl EndpointBuffer ===
+
+impl EndpointBuffer {
+    fn buffered<S, Req>(buffer: Option<Self>, inner: S) -> svc::BoxService<Req, S::Response, Error>
+    where
+        Req: Send + 'static,
+        S: svc::Service<Req, Error = Error> + Send + 'static,
+        S::Response: Send + 'static,
+        S::Future: Send + 'static,
+    {
+        let Self { capacity, max_wait } = match buffer {
+            Some(buffer) => buffer,
+            None => return svc::BoxService::new(inner),
+        };
+        let inner = svc::FailFast::layer("HTTP Endpoint", max_wait).layer(inner);
+        svc::BoxService::new(svc::stack(inner).spawn_buffer(capacity).into_inner())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -75,7 +123,11 @@ mod test {
         svc::{NewService, ServiceExt},
         Infallible,
     };
-    use std::net::SocketAddr;
+    use std::{
+        net::SocketAddr,
+        pin::Pin,
+        task::{Context, Poll},
+    };
 
     static WAS_ORIG_PROTO: &str = "request-orig-proto";
 
@@ -243,6 +295,56 @@ mod test {
         assert!(rsp.headers().get(WAS_ORIG_PROTO).is_none());
     }
 
+    /// Tests that requests sent to a briefly-stalled endpoint are buffered until it becomes
+    /// ready, and that they fail once the endpoint has been stalled past the buffer's max wait.
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn buffers_stalled_endpoint() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let burst = |buffer: EndpointBuffer, stall: Duration| async move {
+            let stalled = Stalled(Box::pin(tokio::time::sleep(stall)));
+            let mut svc = EndpointBuffer::buffered(Some(buffer), stalled);
+            let mut rsps = Vec::new();
+            for _ in 0..buffer.capacity {
+                svc.ready().await.expect("buffer must have capacity");
+                rsps.push(svc.call(()));
+            }
+            future::join_all(rsps).await
+        };
+
+        let buffer = EndpointBuffer {
+            capacity: 10,
+            max_wait: Duration::from_secs(5),
+        };
+        let rsps = burst(buffer, Duration::from_secs(1)).await;
+        assert!(rsps.iter().all(Result::is_ok), "{:?}", rsps);
+
+        let rsps = burst(buffer, Duration::from_secs(10)).await;
+        assert!(
+            rsps.iter()
+                .all(|r| matches!(r, Err(e) if e.is::<svc::timeout::FailFastError>())),
+            "{:?}",
+            rsps
+        );
+    }
+
+    /// An endpoint that does not become ready until its timer elapses.
+    struct Stalled(Pin<Box<tokio::time::Sleep>>);
+
+    impl svc::Service<()> for Stalled {
+        type Response = ();
+        type Error = Error;
+        type Future = future::Ready<Result<(), Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            self.0.as_mut().poll(cx).map(Ok)
+        }
+
+        fn call(&mut self, (): ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+
     /// Helper server that reads the l5d-orig-proto header on requests and uses it to set the header
     /// value in `WAS_ORIG_PROTO`.
     #[allow(clippy::unnecessary_wraps)]
@@ -0,0 +1,411 @@
+use super::{selection::Rerouted, Logical};
+use futures::{future, prelude::*};
+use linkerd_app_core::{
+    metrics::failover::{Failovers, Registry},
+    profiles::LogicalAddr,
+    proxy::{api_resolve::ConcreteAddr, http},
+    svc, Error, NameAddr,
+};
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{self, Instant, Sleep};
+use tracing::info;
+
+/// Configures backup services to which a logical service's HTTP traffic is
+/// routed while its balancer has no available endpoints.
+#[derive(Clone, Debug, Default)]
+pub struct FailoverConfig {
+    /// Maps logical service names to the names of their backup services.
+    pub backups: HashMap<NameAddr, NameAddr>,
+
+    /// The amount of time that a logical service's balancer may be
+    /// unavailable before traffic fails over to its backup. This must be less
+    /// than the dispatch timeout, after which the balancer fails fast.
+    pub max_unavailable: Duration,
+
+    /// The minimum amount of time that traffic remains routed to a backup
+    /// service, so that a flapping primary service does not cause traffic to
+    /// alternate rapidly between services.
+    pub min_backup_duration: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewFailover<N> {
+    inner: N,
+    backups: Arc<HashMap<NameAddr, NameAddr>>,
+    min_backup_duration: Duration,
+    max_unavailable: Duration,
+    metrics: Registry,
+}
+
+/// Routes requests to a backup service once the primary service has been
+/// unavailable for `max_unavailable`.
+///
+/// The primary service fails fast once it has been unavailable for the
+/// dispatch timeout, at which point it is ready again. So, traffic also fails
+/// over as soon as the primary service fails a request fast.
+#[derive(Debug)]
+pub struct Failover<S> {
+    primary: S,
+    backup: Option<Backup<S>>,
+}
+
+#[derive(Debug)]
+struct Backup<S> {
+    service: S,
+    max_unavailable: Duration,
+    min_backup_duration: Duration,
+    state: State,
+    wait: Pin<Box<Sleep>>,
+    failovers: Arc<Failovers>,
+    primary_failed_fast: Arc<AtomicBool>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    failed_fast: Option<Arc<AtomicBool>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Primary,
+    Waiting,
+    Backup,
+}
+
+// === impl NewFailover ===
+
+impl<N> NewFailover<N> {
+    pub fn layer(
+        config: FailoverConfig,
+        metrics: Registry,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        let backups = Arc::new(config.backups);
+        let min_backup_duration = config.min_backup_duration;
+        let max_unavailable = config.max_unavailable;
+        svc::layer::mk(move |inner| Self {
+            inner,
+            backups: backups.clone(),
+            min_backup_duration,
+            max_unavailable,
+            metrics: metrics.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<(ConcreteAddr, Logical)> for NewFailover<N>
+where
+    N: svc::NewService<(ConcreteAddr, Logical)>,
+{
+    type Service = Failover<N::Service>;
+
+    fn new_service(&mut self, (concrete, logical): (ConcreteAddr, Logical)) -> Self::Service {
+        let LogicalAddr(logical_addr) = logical.logical_addr.clone();
+        let backup = self
+            .backups
+            .get(&logical_addr)
+            .filter(|backup| **backup != concrete.0)
+            .cloned();
+        let primary = self.inner.new_service((concrete, logical.clone()));
+        let backup = backup.map(|addr| Backup {
+            service: self
+                .inner
+                .new_service((ConcreteAddr(addr.clone()), logical)),
+            max_unavailable: self.max_unavailable,
+            min_backup_duration: self.min_backup_duration,
+            state: State::Primary,
+            // The sleep is reset whenever the primary service becomes
+            // unavailable, so it's okay to start it now.
+            wait: Box::pin(time::sleep(Duration::default())),
+            failovers: self.metrics.failovers(logical_addr, addr),
+            primary_failed_fast: Arc::new(AtomicBool::new(false)),
+        });
+        Failover { primary, backup }
+    }
+}
+
+// === impl Failover ===
+
+impl<B, S> svc::Service<http::Request<B>> for Failover<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let backup = match self.backup.as_mut() {
+            Some(backup) => backup,
+            None => return self.primary.poll_ready(cx).map_err(Into::into),
+        };
+
+        // The backup is always polled so that its endpoints are discovered
+        // before traffic fails over to it.
+        let backup_ready = backup
+            .service
+            .poll_ready(cx)
+            .map_err(Into::into)?
+            .is_ready();
+        let primary_ready = self.primary.poll_ready(cx).map_err(Into::into)?.is_ready();
+        let primary_failed_fast = backup.primary_failed_fast.swap(false, Ordering::AcqRel);
+
+        loop {
+            let state = backup.state;
+            backup.state = match state {
+                // The primary service has already been unavailable for longer
+                // than `max_unavailable`, so fail over immediately.
+                State::Primary | State::Waiting if primary_failed_fast => {
+                    info!("Failing over to backup service after the primary failed fast");
+                    backup.failed_over()
+                }
+
+                State::Primary if primary_ready => return Poll::Ready(Ok(())),
+
+                // The primary service just became unavailable, so initiate a
+                // new timeout.
+                State::Primary => {
+                    let deadline = Instant::now() + backup.max_unavailable;
+                    backup.wait.as_mut().reset(deadline);
+                    State::Waiting
+                }
+
+                State::Waiting if primary_ready => State::Primary,
+
+                State::Waiting => {
+                    futures::ready!(backup.wait.as_mut().poll(cx));
+                    info!(
+                        "Failing over to backup service after {:?}",
+                        backup.max_unavailable
+                    );
+                    backup.failed_over()
+                }
+
+                // Traffic only returns to the primary service once it has been
+                // routed to the backup for the minimum duration.
+                State::Backup if primary_ready && backup.wait.as_mut().poll(cx).is_ready() => {
+                    info!("Primary service has recovered");
+                    backup.failovers.recovered();
+                    State::Primary
+                }
+
+                State::Backup if backup_ready => return Poll::Ready(Ok(())),
+
+                State::Backup => return Poll::Pending,
+            };
+        }
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let failed_fast = match self.backup.as_mut() {
+            Some(backup) if backup.state == State::Backup => {
+                req.extensions_mut().insert(Rerouted::FAILOVER);
+                return ResponseFuture {
+                    inner: backup.service.call(req),
+                    failed_fast: None,
+                };
+            }
+            Some(backup) => Some(backup.primary_failed_fast.clone()),
+            None => None,
+        };
+        ResponseFuture {
+            inner: self.primary.call(req),
+            failed_fast,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let error = match futures::ready!(this.inner.poll(cx)) {
+            Ok(rsp) => return Poll::Ready(Ok(rsp)),
+            Err(error) => error.into(),
+        };
+        if let Some(failed_fast) = this.failed_fast.take() {
+            if error.is::<svc::timeout::FailFastError>() {
+                failed_fast.store(true, Ordering::Release);
+            }
+        }
+        Poll::Ready(Err(error))
+    }
+}
+
+// === impl Backup ===
+
+impl<S> Backup<S> {
+    fn failed_over(&mut self) -> State {
+        self.failovers.failed_over();
+        let deadline = Instant::now() + self.min_backup_duration;
+        self.wait.as_mut().reset(deadline);
+        State::Backup
+    }
+}
+
+impl<S> Drop for Backup<S> {
+    fn drop(&mut self) {
+        if self.state == State::Backup {
+            self.failovers.recovered();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::metrics::FmtMetrics;
+    use std::sync::atomic::AtomicUsize;
+    use tokio_test::{assert_pending, assert_ready_ok, task};
+
+    const MAX_UNAVAILABLE: Duration = Duration::from_secs(1);
+    const DISPATCH_TIMEOUT: Duration = Duration::from_secs(3);
+    const MIN_BACKUP_DURATION: Duration = Duration::from_secs(10);
+    const LABELS: &str =
+        "{logical=\"foo.ns.svc.cluster.local:80\",backup=\"foo.backup.svc.cluster.local:80\"}";
+
+    /// A balancer that is only ready while it has endpoints and that responds
+    /// with its name.
+    #[derive(Clone, Debug)]
+    struct Balancer {
+        name: &'static str,
+        endpoints: Arc<AtomicUsize>,
+    }
+
+    type Balanced = svc::FailFast<Balancer>;
+
+    impl svc::Service<http::Request<()>> for Balancer {
+        type Response = &'static str;
+        type Error = Error;
+        type Future = future::Ready<Result<&'static str, Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            if self.endpoints.load(Ordering::Acquire) == 0 {
+                return Poll::Pending;
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            future::ok(self.name)
+        }
+    }
+
+    /// Builds a balancer that fails fast, as the balancer stack does.
+    fn balancer(name: &'static str) -> (Balanced, Arc<AtomicUsize>) {
+        use svc::layer::Layer;
+        let endpoints = Arc::new(AtomicUsize::new(1));
+        let balancer = Balancer {
+            name,
+            endpoints: endpoints.clone(),
+        };
+        let balancer = svc::FailFast::layer("Test", DISPATCH_TIMEOUT).layer(balancer);
+        (balancer, endpoints)
+    }
+
+    fn failover(primary: Balanced, backup: Balanced, registry: &Registry) -> Failover<Balanced> {
+        Failover {
+            primary,
+            backup: Some(Backup {
+                service: backup,
+                max_unavailable: MAX_UNAVAILABLE,
+                min_backup_duration: MIN_BACKUP_DURATION,
+                state: State::Primary,
+                wait: Box::pin(time::sleep(Duration::default())),
+                failovers: registry.failovers(
+                    NameAddr::from_str_and_port("foo.ns.svc.cluster.local", 80).unwrap(),
+                    NameAddr::from_str_and_port("foo.backup.svc.cluster.local", 80).unwrap(),
+                ),
+                primary_failed_fast: Arc::new(AtomicBool::new(false)),
+            }),
+        }
+    }
+
+    fn send(
+        task: &mut task::Spawn<()>,
+        failover: &mut Failover<Balanced>,
+    ) -> Result<&'static str, Error> {
+        assert_ready_ok!(task.enter(|cx, _| failover.poll_ready(cx)));
+        failover
+            .call(http::Request::new(()))
+            .now_or_never()
+            .expect("response must be ready")
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn fails_over_while_primary_is_drained() {
+        let (primary, primary_endpoints) = balancer("primary");
+        let (backup, _) = balancer("backup");
+        let registry = Registry::default();
+        let mut failover = failover(primary, backup, &registry);
+        let mut task = task::spawn(());
+
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "primary");
+
+        // Requests wait for the drained primary until `max_unavailable`
+        // elapses, before it would enter fail-fast, at which point they are
+        // routed to the backup.
+        primary_endpoints.store(0, Ordering::Release);
+        assert_pending!(task.enter(|cx, _| failover.poll_ready(cx)));
+        time::sleep(MAX_UNAVAILABLE).await;
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "backup");
+        let metrics = registry.as_display().to_string();
+        assert!(metrics.contains(&format!("outbound_http_failover_total{} 1", LABELS)));
+        assert!(metrics.contains(&format!("outbound_http_failover_active{} 1", LABELS)));
+
+        // Traffic remains on the backup until it has been routed there for the
+        // minimum duration, even though the primary has recovered.
+        primary_endpoints.store(1, Ordering::Release);
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "backup");
+        time::sleep(MIN_BACKUP_DURATION).await;
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "primary");
+        let metrics = registry.as_display().to_string();
+        assert!(metrics.contains(&format!("outbound_http_failover_total{} 1", LABELS)));
+        assert!(metrics.contains(&format!("outbound_http_failover_active{} 0", LABELS)));
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn fails_over_when_primary_fails_fast() {
+        let (primary, primary_endpoints) = balancer("primary");
+        let (backup, _) = balancer("backup");
+        let registry = Registry::default();
+        let mut failover = failover(primary, backup, &registry);
+        let mut task = task::spawn(());
+
+        primary_endpoints.store(0, Ordering::Release);
+        assert_pending!(task.enter(|cx, _| failover.poll_ready(cx)));
+        time::sleep(MAX_UNAVAILABLE).await;
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "backup");
+
+        // The primary is still drained, but has entered fail-fast, so it
+        // appears to have recovered once the minimum duration elapses. The
+        // request that it fails causes traffic to fail over again immediately.
+        time::sleep(MIN_BACKUP_DURATION).await;
+        let error = send(&mut task, &mut failover).expect_err("primary must fail fast");
+        assert!(error.is::<svc::timeout::FailFastError>());
+        assert_eq!(send(&mut task, &mut failover).unwrap(), "backup");
+        let metrics = registry.as_display().to_string();
+        assert!(metrics.contains(&format!("outbound_http_failover_total{} 2", LABELS)));
+        assert!(metrics.contains(&format!("outbound_http_failover_active{} 1", LABELS)));
+    }
+}
@@ -0,0 +1,495 @@
+use futures::{future, TryFuture};
+use linkerd_app_core::{dst, errors::HttpError, proxy::http, svc, Error};
+use parking_lot::Mutex;
+use pin_project::{pin_project, pinned_drop};
+use rand::Rng;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+/// Configures adaptive load shedding for each logical service.
+///
+/// Every `interval`, the p99 latency of the service's responses during the
+/// interval is compared with the `latency_slo`. When it exceeds the SLO, a
+/// fraction of the service's requests, proportional to the excess, fail
+/// immediately with a 503 Service Unavailable, without being dispatched, so
+/// that the service may recover. For example, a p99 latency of twice the SLO
+/// calls for half of the requests to be shed. At most `max_shed` of the
+/// requests are shed.
+///
+/// Requests that fail or are canceled, such as by a timeout, before they
+/// receive a response count as having taken at least the SLO, so that a
+/// service whose requests time out is not mistaken for one that is idle.
+///
+/// So that shedding does not oscillate as latency responds to it, each
+/// interval only moves the shed fraction halfway towards the fraction its
+/// latency calls for. Shedding therefore ramps up while the SLO is violated
+/// and winds down gradually as latency recovers.
+///
+/// Requests on routes whose metadata sets the `critical_label` to `true` are
+/// never shed.
+#[derive(Clone, Debug)]
+pub struct LoadShedConfig {
+    pub latency_slo: Duration,
+    pub interval: Duration,
+    pub max_shed: f64,
+    pub critical_label: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LoadShed<S> {
+    inner: S,
+    controller: Option<Arc<Controller>>,
+}
+
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    measure: Option<(Arc<Controller>, Instant)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewMarkCritical<N> {
+    inner: N,
+    label: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MarkCritical<P> {
+    inner: P,
+    critical: bool,
+}
+
+/// Marks requests that are never shed.
+#[derive(Copy, Clone, Debug)]
+struct Critical;
+
+/// Determines the fraction of a logical service's requests that are shed.
+#[derive(Debug)]
+struct Controller {
+    latency_slo: Duration,
+    interval: Duration,
+    max_shed: f64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// When the current interval started.
+    started: Instant,
+
+    /// The latencies of the responses received during the current interval.
+    latencies: Vec<Duration>,
+
+    /// The number of responses received during the current interval, some of
+    /// which may not be in `latencies`.
+    responses: usize,
+
+    shed: f64,
+}
+
+/// The number of latencies sampled in each interval to estimate its p99.
+const MAX_SAMPLES: usize = 1_000;
+
+/// Shed fractions smaller than this are rounded down, so that shedding stops
+/// once latency recovers.
+const MIN_SHED: f64 = 0.01;
+
+// === impl LoadShed ===
+
+impl<S> LoadShed<S> {
+    pub fn layer(config: Option<LoadShedConfig>) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            // Each service sheds load according to its own latency.
+            controller: config
+                .as_ref()
+                .map(|c| Arc::new(Controller::new(c, Instant::now()))),
+        })
+    }
+}
+
+impl<B, S> svc::Service<http::Request<B>> for LoadShed<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future =
+        future::Either<future::Ready<Result<S::Response, Error>>, ResponseFuture<S::Future>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let measure = match self.controller.as_ref() {
+            None => None,
+            Some(controller) => {
+                let now = Instant::now();
+                let critical = req.extensions().get::<Critical>().is_some();
+                if !critical && controller.sheds(now, rand::thread_rng().gen()) {
+                    debug!("Shedding load");
+                    return future::Either::Left(future::err(
+                        HttpError::load_shed("service latency exceeds its SLO").into(),
+                    ));
+                }
+                Some((controller.clone(), now))
+            }
+        };
+
+        future::Either::Right(ResponseFuture {
+            inner: self.inner.call(req),
+            measure,
+        })
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: TryFuture,
+    F::Error: Into<Error>,
+{
+    type Output = Result<F::Ok, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = futures::ready!(this.inner.try_poll(cx));
+        if let Some((controller, start)) = this.measure.take() {
+            let now = Instant::now();
+            let latency = now.saturating_duration_since(start);
+            if res.is_ok() {
+                controller.record(latency, now);
+            } else {
+                controller.record_failure(latency, now);
+            }
+        }
+        Poll::Ready(res.map_err(Into::into))
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for ResponseFuture<F> {
+    fn drop(self: Pin<&mut Self>) {
+        // The request was canceled before it completed, e.g. by a timeout.
+        if let Some((controller, start)) = self.project().measure.take() {
+            let now = Instant::now();
+            controller.record_failure(now.saturating_duration_since(start), now);
+        }
+    }
+}
+
+// === impl NewMarkCritical ===
+
+impl<N> NewMarkCritical<N> {
+    pub fn layer(config: Option<LoadShedConfig>) -> impl svc::Layer<N, Service = Self> + Clone {
+        let label = config.and_then(|c| c.critical_label).map(Arc::from);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            label: label.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewMarkCritical<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = MarkCritical<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let critical = self
+            .label
+            .as_ref()
+            .and_then(|label| route.route.labels().get(&**label))
+            .map_or(false, |v| v.trim().eq_ignore_ascii_case("true"));
+        MarkCritical {
+            inner: self.inner.new_service(route),
+            critical,
+        }
+    }
+}
+
+// === impl MarkCritical ===
+
+impl<P, S, B> svc::stack::Proxy<http::Request<B>, S> for MarkCritical<P>
+where
+    P: svc::stack::Proxy<http::Request<B>, S>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if self.critical {
+            req.extensions_mut().insert(Critical);
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl Controller ===
+
+impl Controller {
+    fn new(config: &LoadShedConfig, now: Instant) -> Self {
+        Self {
+            latency_slo: config.latency_slo,
+            // Intervals must be nonzero so that updates terminate.
+            interval: config.interval.max(Duration::from_millis(1)),
+            max_shed: config.max_shed,
+            state: Mutex::new(State {
+                started: now,
+                latencies: Vec::new(),
+                responses: 0,
+                shed: 0.0,
+            }),
+        }
+    }
+
+    /// Returns true if a request should be shed, given a uniformly random
+    /// value in [0, 1).
+    fn sheds(&self, now: Instant, random: f64) -> bool {
+        let mut state = self.state.lock();
+        self.update(&mut state, now);
+        random < state.shed
+    }
+
+    /// Records the latency of a request that failed or was canceled before it
+    /// received a response, counting it as at least the SLO.
+    fn record_failure(&self, latency: Duration, now: Instant) {
+        self.record(latency.max(self.latency_slo), now)
+    }
+
+    fn record(&self, latency: Duration, now: Instant) {
+        let mut state = self.state.lock();
+        self.update(&mut state, now);
+        state.responses += 1;
+        if state.latencies.len() < MAX_SAMPLES {
+            state.latencies.push(latency);
+        } else {
+            // Sample the interval's latencies uniformly.
+            let i = rand::thread_rng().gen_range(0..state.responses);
+            if let Some(l) = state.latencies.get_mut(i) {
+                *l = latency;
+            }
+        }
+    }
+
+    /// Adjusts the shed fraction for each interval that has elapsed.
+    fn update(&self, state: &mut State, now: Instant) {
+        let mut elapsed = now.saturating_duration_since(state.started);
+        while elapsed >= self.interval {
+            // Intervals without responses call for no shedding, so shedding
+            // winds down while the service is idle.
+            let target = self.target(&mut state.latencies);
+            state.latencies.clear();
+            state.responses = 0;
+
+            let shed = state.shed + (target - state.shed) / 2.0;
+            if shed < MIN_SHED {
+                trace!(target, "Stopped shedding");
+                state.shed = 0.0;
+                // Further idle intervals cannot change the shed fraction.
+                state.started = now;
+                return;
+            }
+            trace!(shed, target, "Updated shed fraction");
+            state.shed = shed;
+            state.started += self.interval;
+            elapsed -= self.interval;
+        }
+    }
+
+    /// Returns the fraction of requests that the latencies call for shedding.
+    fn target(&self, latencies: &mut Vec<Duration>) -> f64 {
+        if latencies.is_empty() {
+            return 0.0;
+        }
+        latencies.sort_unstable();
+        let rank = (latencies.len() * 99 + 99) / 100;
+        let p99 = latencies[rank - 1];
+        if p99 <= self.latency_slo {
+            return 0.0;
+        }
+        let excess = 1.0 - self.latency_slo.as_secs_f64() / p99.as_secs_f64();
+        excess.min(self.max_shed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::{metrics::Direction, profiles};
+    use svc::{stack::Proxy, Layer, NewService, Service, ServiceExt};
+
+    const LABEL: &str = "critical";
+
+    fn config(max_shed: f64) -> LoadShedConfig {
+        LoadShedConfig {
+            latency_slo: Duration::from_millis(100),
+            interval: Duration::from_secs(1),
+            max_shed,
+            critical_label: Some(LABEL.to_string()),
+        }
+    }
+
+    fn route(critical: bool) -> dst::Route {
+        let labels = Some((LABEL.to_string(), critical.to_string()));
+        dst::Route {
+            target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+            route: profiles::http::Route::new(labels.into_iter(), vec![]),
+            direction: Direction::Out,
+        }
+    }
+
+    /// Records a second of responses with the given latency.
+    fn interval(controller: &Controller, t: &mut Instant, latency: Duration) {
+        for _ in 0..100 {
+            controller.record(latency, *t);
+        }
+        *t += Duration::from_secs(1);
+    }
+
+    fn shed(controller: &Controller, t: Instant) -> f64 {
+        controller.sheds(t, 0.0);
+        controller.state.lock().shed
+    }
+
+    fn assert_shed(controller: &Controller, t: Instant, expected: f64) {
+        let shed = shed(controller, t);
+        assert!((shed - expected).abs() < 1e-9, "shed {}", shed);
+    }
+
+    #[test]
+    fn sheds_in_proportion_to_latency() {
+        let mut t = Instant::now();
+        let controller = Controller::new(&config(0.9), t);
+
+        // A p99 latency of twice the SLO calls for half of requests to be shed,
+        // but shedding ramps up towards it.
+        interval(&controller, &mut t, Duration::from_millis(200));
+        assert_shed(&controller, t, 0.25);
+        interval(&controller, &mut t, Duration::from_millis(200));
+        assert_shed(&controller, t, 0.375);
+        assert!(controller.sheds(t, 0.3));
+        assert!(!controller.sheds(t, 0.4));
+
+        // Shedding is limited.
+        for _ in 0..10 {
+            interval(&controller, &mut t, Duration::from_secs(10));
+        }
+        let limited = shed(&controller, t);
+        assert!(limited > 0.85 && limited <= 0.9, "shed {}", limited);
+
+        // Shedding winds down as latency recovers.
+        interval(&controller, &mut t, Duration::from_millis(50));
+        assert!(shed(&controller, t) < limited / 2.0 + 0.01);
+        for _ in 0..10 {
+            interval(&controller, &mut t, Duration::from_millis(50));
+        }
+        assert_shed(&controller, t, 0.0);
+        assert!(!controller.sheds(t, 0.0));
+    }
+
+    #[test]
+    fn idle_services_stop_shedding() {
+        let mut t = Instant::now();
+        let controller = Controller::new(&config(1.0), t);
+        for _ in 0..4 {
+            interval(&controller, &mut t, Duration::from_secs(1));
+        }
+        assert!(shed(&controller, t) > 0.5);
+
+        assert_shed(&controller, t + Duration::from_secs(60), 0.0);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn critical_requests_are_not_shed() {
+        let config = config(1.0);
+        let mut svc =
+            LoadShed::layer(Some(config.clone())).layer(svc::mk(|_: http::Request<()>| {
+                future::ok::<_, Error>(http::Response::new(()))
+            }));
+        svc.controller.as_ref().unwrap().state.lock().shed = 1.0;
+
+        let mut new_route = NewMarkCritical::layer(Some(config)).layer(|_: dst::Route| ());
+        let critical = new_route.new_service(route(true));
+        let other = new_route.new_service(route(false));
+
+        svc.ready().await.unwrap();
+        let rsp = critical.proxy(&mut svc, http::Request::new(())).await;
+        assert!(rsp.is_ok(), "critical requests must not be shed");
+
+        svc.ready().await.unwrap();
+        let err = other
+            .proxy(&mut svc, http::Request::new(()))
+            .await
+            .unwrap_err();
+        assert!(err.is::<HttpError>());
+        svc.ready().await.unwrap();
+        assert_eq!(
+            svc.call(http::Request::new(()))
+                .await
+                .unwrap_err()
+                .to_string(),
+            "service latency exceeds its SLO"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn records_failed_and_canceled_requests() {
+        let inner = svc::mk(|req: http::Request<()>| {
+            let fail = req.headers().contains_key("fail");
+            async move {
+                if fail {
+                    return Err(Error::from(HttpError::bad_request("failed")));
+                }
+                future::pending::<()>().await;
+                Ok(http::Response::new(()))
+            }
+        });
+        let mut svc = LoadShed::layer(Some(config(1.0))).layer(inner);
+        let latencies = |svc: &LoadShed<_>| {
+            svc.controller
+                .as_ref()
+                .unwrap()
+                .state
+                .lock()
+                .latencies
+                .clone()
+        };
+
+        // Failures count as at least the SLO.
+        svc.ready().await.unwrap();
+        let req = http::Request::builder()
+            .header("fail", "1")
+            .body(())
+            .unwrap();
+        assert!(svc.call(req).await.is_err());
+        assert_eq!(latencies(&svc), vec![Duration::from_millis(100)]);
+
+        // Canceled requests, such as those that time out, count as well.
+        svc.ready().await.unwrap();
+        let rsp = svc.call(http::Request::new(()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        drop(rsp);
+        assert_eq!(
+            latencies(&svc),
+            vec![Duration::from_millis(100), Duration::from_millis(300)]
+        );
+    }
+}
@@ -1,5 +1,16 @@
-use super::{CanonicalDstHeader, Concrete, Endpoint, Logical};
-use crate::{endpoint, resolve, stack_labels, Outbound};
+use super::{
+    breaker::NewBreakRoute,
+    canary::{NewCanarySplit, ResolveSubset},
+    coalesce::Coalesce,
+    failover::NewFailover,
+    load_shed::{LoadShed, NewMarkCritical},
+    mirror::{NewMirror, NewMirrorRoute},
+    prewarm::Prewarm,
+    priority::{NewSetPriority, RoutePriority},
+    selection::NewSetSelection,
+    CanonicalDstHeader, Concrete, Endpoint, Logical,
+};
+use crate::{endpoint, resolve, stack_labels, subset::SubsetEndpoints, Outbound};
 use linkerd_app_core::{
     classify, config, dst, profiles,
     proxy::{
@@ -8,7 +19,7 @@ use linkerd_app_core::{
         http,
         resolve::map_endpoint,
     },
-    retry, svc, Error, Infallible,
+    retry, svc, Error,
 };
 use tracing::debug_span;
 
@@ -35,7 +46,19 @@ impl<E> Outbound<E> {
                 dispatch_timeout,
                 ..
             } = config.proxy;
+            // Endpoints are assumed to keep their connections open for as long
+            // as HTTP/1 clients keep idle connections in their pools.
+            let idle_timeout = config.proxy.connect.h1_settings.idle_timeout;
+            let affinity = config
+                .balance_connection_affinity
+                .map(|bias| http::balance::ConnectionAffinity { bias, idle_timeout });
             let watchdog = cache_max_idle_age * 2;
+            let route_timeouts = config.route_timeouts.clone();
+            let mirror_route_label = config.mirror.route_label.clone();
+            let route_priority = config.route_priority.clone();
+            let route_breakers = config.route_breakers.clone();
+            let coalesce = config.coalesce.clone();
+            let load_shed = config.load_shed.clone();
 
             let endpoint =
                 endpoint.instrument(|e: &Endpoint| debug_span!("endpoint", server.addr = %e.addr));
@@ -43,7 +66,12 @@ impl<E> Outbound<E> {
             let identity_disabled = rt.identity.is_none();
             let resolve = svc::stack(resolve.into_service())
                 .check_service::<ConcreteAddr>()
-                .push_request_filter(|c: Concrete| Ok::<_, Infallible>(c.resolve))
+                // Resolves only the target's subset of endpoints when a canary
+                // split is configured.
+                .push(svc::layer::mk(ResolveSubset::new))
+                // Limits the balancer to a subset of the service's endpoints,
+                // if so configured.
+                .push(SubsetEndpoints::layer(config.endpoint_subsetting))
                 .push(svc::layer::mk(move |inner| {
                     map_endpoint::Resolve::new(endpoint::FromMetadata { identity_disabled }, inner)
                 }))
@@ -53,6 +81,9 @@ impl<E> Outbound<E> {
             endpoint
                 .clone()
                 .check_new_service::<Endpoint, http::Request<http::BoxBody>>()
+                // Tracks the requests the balancer has dispatched to each
+                // endpoint that have not yet received a response.
+                .push(rt.metrics.http_endpoint_inflight.layer())
                 .push_on_response(
                     svc::layers()
                         .push(http::BoxRequest::layer())
@@ -74,24 +105,51 @@ impl<E> Outbound<E> {
                 .push(resolve::layer(resolve, watchdog))
                 .push_on_response(
                     svc::layers()
-                        .push(http::balance::layer(
+                        .push(http::balance::layer_with(
+                            config.balance_algorithm,
                             crate::EWMA_DEFAULT_RTT,
                             crate::EWMA_DECAY,
+                            config.balance_failure_penalty,
+                            affinity,
                         ))
                         .push(rt.metrics.stack.layer(stack_labels("http", "balancer")))
                         .push(svc::layer::mk(svc::SpawnReady::new))
-                        .push(svc::FailFast::layer("HTTP Balancer", dispatch_timeout))
-                        .push(http::BoxResponse::layer()),
+                        .push(svc::FailFast::layer("HTTP Balancer", dispatch_timeout)),
                 )
                 .check_make_service::<Concrete, http::Request<_>>()
                 .push(svc::MapErrLayer::new(Into::into))
                 // Drives the initial resolution via the service's readiness.
                 .into_new_service()
+                // Resolves and connects to endpoints as soon as the profile is
+                // received, if so configured, rather than on the first request.
+                .push_on_response(Prewarm::layer::<http::Request<http::BoxBody>>(
+                    config.prewarm_endpoints,
+                ))
+                // Records why requests were dispatched to this balancer's
+                // endpoints, if tap events should report it.
+                .push(NewSetSelection::layer(
+                    config.balance_algorithm,
+                    config.tap_endpoint_selection,
+                ))
                 // The concrete address is only set when the profile could be
                 // resolved. Endpoint resolution is skipped when there is no
                 // concrete address.
-                .instrument(|c: &Concrete| debug_span!("concrete", addr = %c.resolve))
-                .push_map_target(Concrete::from)
+                .instrument(|c: &Concrete| debug_span!("concrete", addr = %c.inner.resolve))
+                // Splits requests between the stable and canary subsets of each
+                // concrete address's endpoints, if so configured.
+                .push(NewCanarySplit::layer(config.canary.clone()))
+                // Routes requests to the logical service's backup, if one is
+                // configured, while its balancer is unavailable.
+                .push(NewFailover::layer(
+                    config.failover.clone(),
+                    rt.metrics.http_failover.clone(),
+                ))
+                // Mirrors requests to the logical service's candidate, if one
+                // is configured, comparing its responses with the primary's.
+                .push(NewMirror::layer(
+                    config.mirror.clone(),
+                    rt.metrics.http_mirror.clone(),
+                ))
                 .push(svc::BoxNewService::layer())
                 // Distribute requests over a distribution of balancers via a
                 // traffic split.
@@ -106,17 +164,49 @@ impl<E> Outbound<E> {
                         .push(svc::layer::mk(svc::SpawnReady::new))
                         .push(rt.metrics.stack.layer(stack_labels("http", "logical")))
                         .push(svc::FailFast::layer("HTTP Logical", dispatch_timeout))
-                        .push_spawn_buffer(buffer_capacity),
+                        // Dispatches buffered requests in order of their
+                        // routes' priorities, if so configured.
+                        .push(svc::layer::mk({
+                            let route_priority = route_priority.clone();
+                            move |inner| {
+                                RoutePriority::buffered(
+                                    route_priority.clone(),
+                                    buffer_capacity,
+                                    inner,
+                                )
+                            }
+                        }))
+                        // Shares responses among concurrent, identical GET
+                        // requests, if so configured.
+                        .push(Coalesce::layer(coalesce))
+                        // Fails a fraction of requests while the service's
+                        // latency exceeds its SLO, if so configured.
+                        .push(LoadShed::layer(load_shed.clone())),
                 )
                 .push_cache(cache_max_idle_age)
                 // Note: routes can't exert backpressure.
                 .push(profiles::http::route_request::layer(
                     svc::proxies()
+                        // Marks requests on routes that opt into mirroring.
+                        // Retries are mirrored, too.
+                        .push(NewMirrorRoute::layer(mirror_route_label))
+                        // Marks requests with their routes' priorities, so
+                        // that the logical buffer may dispatch them in order.
+                        .push(NewSetPriority::layer(route_priority))
+                        // Marks requests on critical routes so that they are
+                        // never shed.
+                        .push(NewMarkCritical::layer(load_shed))
                         .push(
                             rt.metrics
                                 .http_route_actual
                                 .to_layer::<classify::Response, _, dst::Route>(),
                         )
+                        // Cancels requests whose bodies are not received in
+                        // time. Each retry's replayed body is timed
+                        // separately.
+                        .push(http::NewTimeoutRequestBody::layer(
+                            config.request_body_timeout,
+                        ))
                         // Depending on whether or not the request can be retried,
                         // it may have one of two `Body` types. This layer unifies
                         // any `Body` type into `BoxBody` so that the rest of the
@@ -127,12 +217,18 @@ impl<E> Outbound<E> {
                         .push(retry::layer(rt.metrics.http_route_retry.clone()))
                         // Sets an optional request timeout.
                         .push(http::MakeTimeoutLayer::default())
+                        // Fails requests without dispatching them while the
+                        // route's circuit breaker is open. Each route has its
+                        // own breaker.
+                        .push(NewBreakRoute::layer(route_breakers))
                         // Records per-route metrics.
                         .push(rt.metrics.http_route.to_layer::<classify::Response, _, _>())
                         // Sets the per-route response classifier as a request
                         // extension.
                         .push(classify::NewClassify::layer())
-                        .push_map_target(Logical::mk_route)
+                        .push_map_target(move |(route, logical)| {
+                            Logical::mk_route((route_timeouts.apply(route), logical))
+                        })
                         .into_inner(),
                 ))
                 // Strips headers that may be set by this proxy and add an outbound
@@ -146,3 +242,161 @@ impl<E> Outbound<E> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{LoadShedConfig, RouteTimeouts},
+        test_util::*,
+    };
+    use linkerd_app_core::{
+        errors::HttpError,
+        metrics::FmtMetrics,
+        profiles::{LogicalAddr, Profile},
+        svc::{NewService, Service, ServiceExt},
+    };
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
+    use tokio::{sync::Notify, time};
+
+    fn logical(addr: &LogicalAddr) -> (Logical, tokio::sync::watch::Sender<Profile>) {
+        let (tx, rx) = tokio::sync::watch::channel(Profile {
+            addr: Some(addr.clone()),
+            ..Default::default()
+        });
+        let logical = Logical {
+            profile: rx.into(),
+            logical_addr: addr.clone(),
+            protocol: http::Version::Http1,
+        };
+        (logical, tx)
+    }
+
+    fn request() -> http::Request<http::BoxBody> {
+        http::Request::builder()
+            .uri("http://xyz.example.com:4444/")
+            .body(http::BoxBody::default())
+            .unwrap()
+    }
+
+    /// Waits for `f` to hold, polling it as the stack's background tasks make
+    /// progress.
+    async fn eventually(mut f: impl FnMut() -> bool) {
+        time::timeout(Duration::from_secs(5), async {
+            while !f() {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("condition must hold");
+    }
+
+    /// Tests that the logical stack reports the requests its balancer has
+    /// dispatched to each endpoint while they await a response.
+    #[tokio::test(flavor = "current_thread")]
+    async fn reports_inflight_requests_per_endpoint() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let logical_addr = LogicalAddr("xyz.example.com:4444".parse().unwrap());
+        let (logical, _profile) = logical(&logical_addr);
+        let ep_addr = SocketAddr::new([192, 0, 2, 30].into(), 3333);
+        let resolve =
+            support::resolver().endpoint_exists(logical_addr.clone(), ep_addr, Default::default());
+
+        // The endpoint only responds once the test releases it.
+        let release = Arc::new(Notify::new());
+        let (rt, _shutdown) = runtime();
+        let inflight = rt.metrics.http_endpoint_inflight.clone();
+        let svc = Outbound::new(default_config(), rt)
+            .with_stack({
+                let release = release.clone();
+                move |ep: Endpoint| {
+                    assert_eq!(*ep.addr.as_ref(), ep_addr);
+                    let release = release.clone();
+                    svc::mk(move |_: http::Request<http::BoxBody>| {
+                        let release = release.clone();
+                        async move {
+                            release.notified().await;
+                            Ok::<_, Error>(http::Response::new(http::BoxBody::default()))
+                        }
+                    })
+                }
+            })
+            .push_http_logical(resolve)
+            .into_inner()
+            .new_service(logical);
+
+        let gauge = move || {
+            let report = inflight.as_display().to_string();
+            report
+                .lines()
+                .find_map(|l| {
+                    l.strip_prefix(
+                        "outbound_endpoint_inflight_requests{endpoint=\"192.0.2.30:3333\"} ",
+                    )
+                })
+                .map(String::from)
+        };
+
+        let rsp = tokio::spawn(svc.oneshot(request()));
+        eventually(|| gauge().as_deref() == Some("1")).await;
+
+        release.notify_one();
+        let rsp = rsp.await.unwrap().expect("request must succeed");
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+        eventually(|| gauge().as_deref() == Some("0")).await;
+    }
+
+    /// Tests that requests that time out inform the logical stack's load
+    /// shedding, even though the service never responds to them.
+    #[tokio::test(flavor = "current_thread")]
+    async fn sheds_load_when_requests_time_out() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let logical_addr = LogicalAddr("xyz.example.com:4444".parse().unwrap());
+        let (logical, _profile) = logical(&logical_addr);
+        let ep_addr = SocketAddr::new([192, 0, 2, 30].into(), 3333);
+        let resolve =
+            support::resolver().endpoint_exists(logical_addr.clone(), ep_addr, Default::default());
+
+        let mut config = default_config();
+        config.route_timeouts = RouteTimeouts {
+            label: None,
+            default: Some(Duration::from_millis(50)),
+        };
+        config.load_shed = Some(LoadShedConfig {
+            latency_slo: Duration::from_millis(10),
+            interval: Duration::from_millis(200),
+            max_shed: 1.0,
+            critical_label: None,
+        });
+        // The endpoint never responds, so every request times out.
+        let (rt, _shutdown) = runtime();
+        let mut svc = Outbound::new(config, rt)
+            .with_stack(|_: Endpoint| {
+                svc::mk(|_: http::Request<http::BoxBody>| {
+                    future::pending::<Result<http::Response<http::BoxBody>, Error>>()
+                })
+            })
+            .push_http_logical(resolve)
+            .into_inner()
+            .new_service(logical);
+
+        let is_shed = |e: &Error| {
+            e.downcast_ref::<HttpError>().map_or(false, |e| {
+                e.status() == http::StatusCode::SERVICE_UNAVAILABLE
+            })
+        };
+        time::timeout(Duration::from_secs(5), async move {
+            loop {
+                svc.ready().await.expect("service must become ready");
+                let err = svc.call(request()).await.expect_err("requests must fail");
+                if is_shed(&err) {
+                    return;
+                }
+            }
+        })
+        .await
+        .expect("requests must be shed once they time out");
+    }
+}
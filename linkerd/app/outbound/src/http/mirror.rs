@@ -0,0 +1,966 @@
+use super::{selection::Rerouted, Logical};
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{prelude::*, ready};
+use linkerd_app_core::{
+    dst,
+    metrics::mirror::{Comparisons, Registry},
+    profiles::LogicalAddr,
+    proxy::{
+        api_resolve::ConcreteAddr,
+        http::{self, HttpBody},
+    },
+    svc, Error, NameAddr, NameMatch,
+};
+use pin_project::pin_project;
+use rand::Rng;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time};
+use tracing::{debug, trace, Instrument};
+
+/// Configures candidate services to which a logical service's HTTP requests
+/// are mirrored, so that their responses may be compared against those of the
+/// logical service.
+///
+/// Mirroring never affects the responses returned to clients: requests are
+/// only mirrored while the candidate has capacity, and the candidate's
+/// responses are discarded once they have been compared. A request's body is
+/// copied, up to `max_request_body_bytes`, as the primary service reads it;
+/// the candidate receives the copy only once the primary has read the body
+/// completely.
+///
+/// A candidate may be a service in another cluster, i.e. a mirrored service
+/// whose endpoints are a remote cluster's gateway. This permits migrations to
+/// be validated by shadowing a sample of production requests to the remote
+/// cluster.
+#[derive(Clone, Debug, Default)]
+pub struct MirrorConfig {
+    /// Maps logical service names to the names of their candidate services.
+    pub candidates: HashMap<NameAddr, NameAddr>,
+
+    /// If set, requests are only mirrored to candidates whose names match
+    /// these suffixes. Otherwise, all candidates are permitted.
+    pub allowed_candidates: Option<NameMatch>,
+
+    /// If set, only this percentage of eligible requests are mirrored.
+    /// Otherwise, all eligible requests are mirrored.
+    pub sample_percent: Option<u8>,
+
+    /// The maximum size of request bodies that are mirrored. Requests with
+    /// bodies are only mirrored if they have a `content-length` no greater
+    /// than this limit.
+    pub max_request_body_bytes: usize,
+
+    /// If set, only requests on routes whose metadata sets this label to
+    /// `true` are mirrored. Otherwise, all requests are mirrored.
+    pub route_label: Option<String>,
+
+    /// The response headers whose values are compared.
+    pub compare_headers: Vec<http::HeaderName>,
+
+    /// The maximum number of response body bytes that are hashed for
+    /// comparison. Bodies are not compared when either response exceeds
+    /// this limit.
+    pub max_body_bytes: usize,
+
+    /// The maximum number of mirrored requests that may be in flight to each
+    /// candidate. Requests are not mirrored while the candidate is at
+    /// capacity.
+    pub max_in_flight: usize,
+
+    /// The amount of time a candidate has to respond to a mirrored request.
+    pub timeout: Duration,
+}
+
+/// Marks the requests on routes that have opted into mirroring.
+#[derive(Clone, Debug)]
+pub struct NewMirrorRoute<N> {
+    inner: N,
+    label: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MirrorRoute<P> {
+    inner: P,
+    mirrored: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct MirroredRoute;
+
+#[derive(Clone, Debug)]
+pub struct NewMirror<N> {
+    inner: N,
+    config: Arc<MirrorConfig>,
+    metrics: Registry,
+}
+
+/// Mirrors requests to a candidate service and compares the candidate's
+/// responses against the primary service's.
+pub struct Mirror<S> {
+    primary: S,
+    candidate: Option<Candidate>,
+}
+
+struct Candidate {
+    service: svc::Buffer<http::Request<http::BoxBody>, http::Response<http::BoxBody>, Error>,
+    ready: bool,
+    config: Arc<MirrorConfig>,
+    comparisons: Arc<Comparisons>,
+}
+
+/// The parts of a response that are compared.
+#[derive(Debug)]
+struct Summary {
+    status: http::StatusCode,
+    headers: Vec<Option<http::HeaderValue>>,
+    body: Option<u64>,
+}
+
+/// Hashes a bounded number of body bytes.
+#[derive(Debug)]
+struct BodyHasher {
+    hasher: Option<DefaultHasher>,
+    remaining: usize,
+}
+
+/// Copies the primary request's body as it is read, so that it may be sent to
+/// the candidate once it has been read completely.
+#[pin_project]
+struct TeeBody<B> {
+    #[pin]
+    inner: B,
+    copy: BytesMut,
+    max_bytes: usize,
+    tx: Option<oneshot::Sender<Bytes>>,
+}
+
+/// The body of a mirrored request, which becomes available once the primary
+/// service has read the original body.
+struct MirrorBody(Option<oneshot::Receiver<Bytes>>);
+
+#[derive(Debug, thiserror::Error)]
+#[error("the primary request's body was not read completely")]
+struct IncompleteBody;
+
+/// Summarizes the primary response's body as it is read by the client.
+#[pin_project]
+struct PrimaryBody<B> {
+    #[pin]
+    inner: B,
+    pending: Option<PendingSummary>,
+}
+
+struct PendingSummary {
+    status: http::StatusCode,
+    headers: Vec<Option<http::HeaderValue>>,
+    body: BodyHasher,
+    tx: oneshot::Sender<Summary>,
+}
+
+// === impl NewMirrorRoute ===
+
+impl<N> NewMirrorRoute<N> {
+    pub fn layer(label: Option<String>) -> impl svc::Layer<N, Service = Self> + Clone {
+        let label = label.map(Arc::from);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            label: label.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewMirrorRoute<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = MirrorRoute<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let mirrored = match self.label.as_ref() {
+            Some(label) => route.route.labels().get(&**label).map(String::as_str) == Some("true"),
+            None => false,
+        };
+        MirrorRoute {
+            inner: self.inner.new_service(route),
+            mirrored,
+        }
+    }
+}
+
+// === impl MirrorRoute ===
+
+impl<P, S, B> svc::stack::Proxy<http::Request<B>, S> for MirrorRoute<P>
+where
+    P: svc::stack::Proxy<http::Request<B>, S>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if self.mirrored {
+            req.extensions_mut().insert(MirroredRoute);
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl NewMirror ===
+
+impl<N> NewMirror<N> {
+    pub fn layer(
+        config: MirrorConfig,
+        metrics: Registry,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        let config = Arc::new(config);
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+            metrics: metrics.clone(),
+        })
+    }
+}
+
+impl<N, S> svc::NewService<(ConcreteAddr, Logical)> for NewMirror<N>
+where
+    N: svc::NewService<(ConcreteAddr, Logical), Service = S>,
+    S: svc::Service<http::Request<http::BoxBody>, Response = http::Response<http::BoxBody>>,
+    S: Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Service = Mirror<S>;
+
+    fn new_service(&mut self, (concrete, logical): (ConcreteAddr, Logical)) -> Self::Service {
+        let LogicalAddr(logical_addr) = logical.logical_addr.clone();
+        let candidate = self
+            .config
+            .candidates
+            .get(&logical_addr)
+            .filter(|candidate| **candidate != concrete.0)
+            .cloned();
+        let candidate = candidate.filter(|addr| {
+            let allowed = self.config.allows(addr);
+            if !allowed {
+                debug!(candidate = %addr, "Candidate is not permitted; not mirroring");
+            }
+            allowed
+        });
+        let primary = self.inner.new_service((concrete, logical.clone()));
+        let candidate = candidate.map(|addr| {
+            let service = self
+                .inner
+                .new_service((ConcreteAddr(addr.clone()), logical));
+            Candidate {
+                service: svc::stack(service)
+                    .push(svc::MapErrLayer::new(Into::<Error>::into))
+                    .spawn_buffer(self.config.max_in_flight)
+                    .into_inner(),
+                ready: false,
+                config: self.config.clone(),
+                comparisons: self.metrics.comparisons(logical_addr, addr),
+            }
+        });
+        Mirror { primary, candidate }
+    }
+}
+
+// === impl Mirror ===
+
+impl<S> svc::Service<http::Request<http::BoxBody>> for Mirror<S>
+where
+    S: svc::Service<http::Request<http::BoxBody>, Response = http::Response<http::BoxBody>>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<http::BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // The candidate never exerts backpressure on the primary service:
+        // requests are simply not mirrored while the candidate is not ready.
+        if let Some(candidate) = self.candidate.as_mut() {
+            if !candidate.ready {
+                match candidate.service.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => candidate.ready = true,
+                    Poll::Ready(Err(error)) => {
+                        debug!(%error, "Candidate service failed");
+                        self.candidate = None;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+        }
+
+        self.primary.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<http::BoxBody>) -> Self::Future {
+        let pending = self
+            .candidate
+            .as_mut()
+            .and_then(|candidate| candidate.mirror(&mut req));
+        let rsp = self.primary.call(req).err_into::<Error>();
+        match pending {
+            None => Box::pin(rsp),
+            Some((config, tx)) => Box::pin(rsp.map_ok(move |rsp| {
+                let (head, body) = rsp.into_parts();
+                let body = PrimaryBody::new(body, head.status, &head.headers, &*config, tx);
+                http::Response::from_parts(head, http::BoxBody::new(body))
+            })),
+        }
+    }
+}
+
+// === impl MirrorConfig ===
+
+impl MirrorConfig {
+    fn allows(&self, candidate: &NameAddr) -> bool {
+        match self.allowed_candidates.as_ref() {
+            Some(allowed) => allowed.matches(candidate.name()),
+            None => true,
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        match self.sample_percent {
+            Some(percent) => rand::thread_rng().gen_ratio(u32::from(percent.min(100)), 100),
+            None => true,
+        }
+    }
+}
+
+// === impl Candidate ===
+
+impl Candidate {
+    /// Dispatches a copy of the request to the candidate, returning a channel
+    /// on which the primary response's summary is sent for comparison.
+    ///
+    /// If the request has a body, it is replaced with one that copies the
+    /// body for the candidate.
+    fn mirror(
+        &mut self,
+        req: &mut http::Request<http::BoxBody>,
+    ) -> Option<(Arc<MirrorConfig>, oneshot::Sender<Summary>)> {
+        if !self.ready {
+            trace!("Candidate service is not ready; not mirroring");
+            return None;
+        }
+        if self.config.route_label.is_some() && req.extensions().get::<MirroredRoute>().is_none() {
+            return None;
+        }
+        let has_body = !req.body().is_end_stream();
+        if has_body {
+            let content_length = req
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok()?.parse::<usize>().ok());
+            match content_length {
+                Some(len) if len <= self.config.max_request_body_bytes => {}
+                _ => {
+                    trace!(?content_length, "Request body is too large; not mirroring");
+                    return None;
+                }
+            }
+        }
+        if !self.config.sampled() {
+            trace!("Request not sampled; not mirroring");
+            return None;
+        }
+
+        let body = if has_body {
+            let (tx, rx) = oneshot::channel();
+            let primary = std::mem::take(req.body_mut());
+            *req.body_mut() = http::BoxBody::new(TeeBody {
+                inner: primary,
+                copy: BytesMut::new(),
+                max_bytes: self.config.max_request_body_bytes,
+                tx: Some(tx),
+            });
+            http::BoxBody::new(MirrorBody(Some(rx)))
+        } else {
+            http::BoxBody::default()
+        };
+
+        let mut mirror = http::Request::new(body);
+        *mirror.method_mut() = req.method().clone();
+        *mirror.uri_mut() = req.uri().clone();
+        *mirror.headers_mut() = req.headers().clone();
+        *mirror.version_mut() = req.version();
+        mirror.extensions_mut().insert(Rerouted::MIRROR);
+
+        self.ready = false;
+        let rsp = self.service.call(mirror);
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(
+            compare(rsp, rx, self.config.clone(), self.comparisons.clone()).in_current_span(),
+        );
+        Some((self.config.clone(), tx))
+    }
+}
+
+async fn compare(
+    rsp: impl Future<Output = Result<http::Response<http::BoxBody>, Error>>,
+    primary: oneshot::Receiver<Summary>,
+    config: Arc<MirrorConfig>,
+    comparisons: Arc<Comparisons>,
+) {
+    let mirrored = time::timeout(config.timeout, async {
+        let (head, mut body) = rsp.await?.into_parts();
+        let mut hasher = BodyHasher::new(config.max_body_bytes);
+        while let Some(data) = body.data().await {
+            if !hasher.update(&data?) {
+                break;
+            }
+        }
+        Ok::<_, Error>(Summary::new(
+            head.status,
+            &head.headers,
+            &*config,
+            hasher.finish(),
+        ))
+    })
+    .await;
+    let mirrored = match mirrored {
+        Ok(Ok(summary)) => summary,
+        Ok(Err(error)) => {
+            debug!(%error, "Mirrored request failed");
+            comparisons.failed();
+            return;
+        }
+        Err(_) => {
+            debug!(timeout = ?config.timeout, "Mirrored request timed out");
+            comparisons.failed();
+            return;
+        }
+    };
+
+    // If the primary response's body is not read to completion, there is
+    // nothing to compare against.
+    let primary = match primary.await {
+        Ok(summary) => summary,
+        Err(_) => {
+            trace!("Primary response did not complete; not comparing");
+            return;
+        }
+    };
+
+    let mismatches = primary.mismatches(&mirrored, &*config);
+    if mismatches.is_empty() {
+        trace!("Mirrored response matches");
+        comparisons.matched();
+    } else {
+        debug!(
+            ?mismatches,
+            "Mirrored response differs from primary response"
+        );
+        comparisons.mismatched();
+    }
+}
+
+// === impl Summary ===
+
+impl Summary {
+    fn new(
+        status: http::StatusCode,
+        headers: &http::header::HeaderMap,
+        config: &MirrorConfig,
+        body: Option<u64>,
+    ) -> Self {
+        Self {
+            status,
+            headers: Self::headers(headers, config),
+            body,
+        }
+    }
+
+    fn headers(
+        headers: &http::header::HeaderMap,
+        config: &MirrorConfig,
+    ) -> Vec<Option<http::HeaderValue>> {
+        config
+            .compare_headers
+            .iter()
+            .map(|name| headers.get(name).cloned())
+            .collect()
+    }
+
+    /// Describes the parts of the responses that differ. Bodies are only
+    /// compared when both could be hashed.
+    fn mismatches<'c>(&self, mirrored: &Self, config: &'c MirrorConfig) -> Vec<&'c str> {
+        let mut mismatches = Vec::new();
+        if self.status != mirrored.status {
+            mismatches.push("status");
+        }
+        for ((name, primary), mirrored) in config
+            .compare_headers
+            .iter()
+            .zip(&self.headers)
+            .zip(&mirrored.headers)
+        {
+            if primary != mirrored {
+                mismatches.push(name.as_str());
+            }
+        }
+        if let (Some(primary), Some(mirrored)) = (self.body, mirrored.body) {
+            if primary != mirrored {
+                mismatches.push("body");
+            }
+        }
+        mismatches
+    }
+}
+
+// === impl BodyHasher ===
+
+impl BodyHasher {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            hasher: Some(DefaultHasher::new()),
+            remaining: max_bytes,
+        }
+    }
+
+    /// Hashes the data, returning false once the body can no longer be
+    /// compared because it has exceeded the limit.
+    fn update(&mut self, data: &impl bytes::Buf) -> bool {
+        if let Some(hasher) = self.hasher.as_mut() {
+            // Data is only hashed when it is contiguous, so that it need not
+            // be copied.
+            let chunk = data.chunk();
+            if chunk.len() == data.remaining() && chunk.len() <= self.remaining {
+                self.remaining -= chunk.len();
+                hasher.write(chunk);
+                return true;
+            }
+            self.hasher = None;
+        }
+        false
+    }
+
+    fn finish(self) -> Option<u64> {
+        self.hasher.map(|h| h.finish())
+    }
+}
+
+// === impl TeeBody ===
+
+impl<B> HttpBody for TeeBody<B>
+where
+    B: HttpBody,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        match ready!(this.inner.poll_data(cx)) {
+            Some(Ok(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                if this.tx.is_some() {
+                    if this.copy.len() + data.len() <= *this.max_bytes {
+                        this.copy.extend_from_slice(&data);
+                    } else {
+                        // The body is longer than its `content-length`
+                        // claimed, so the mirrored request is abandoned.
+                        drop(this.tx.take());
+                    }
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            Some(Err(e)) => {
+                drop(this.tx.take());
+                Poll::Ready(Some(Err(e)))
+            }
+            None => {
+                if let Some(tx) = this.tx.take() {
+                    let _ = tx.send(std::mem::take(this.copy).freeze());
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    #[inline]
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::header::HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// === impl MirrorBody ===
+
+impl HttpBody for MirrorBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let rx = match self.0.as_mut() {
+            Some(rx) => rx,
+            None => return Poll::Ready(None),
+        };
+        let res = ready!(rx.poll_unpin(cx));
+        self.0 = None;
+        match res {
+            Ok(data) => Poll::Ready(Some(Ok(data))),
+            Err(_) => Poll::Ready(Some(Err(IncompleteBody.into()))),
+        }
+    }
+
+    #[inline]
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::header::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+// === impl PrimaryBody ===
+
+impl<B: HttpBody> PrimaryBody<B> {
+    fn new(
+        inner: B,
+        status: http::StatusCode,
+        headers: &http::header::HeaderMap,
+        config: &MirrorConfig,
+        tx: oneshot::Sender<Summary>,
+    ) -> Self {
+        let mut pending = Some(PendingSummary {
+            status,
+            headers: Summary::headers(headers, config),
+            body: BodyHasher::new(config.max_body_bytes),
+            tx,
+        });
+        // Empty bodies may never be polled.
+        if inner.is_end_stream() {
+            if let Some(p) = pending.take() {
+                p.complete();
+            }
+        }
+        Self { inner, pending }
+    }
+}
+
+impl<B: HttpBody> HttpBody for PrimaryBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = ready!(this.inner.poll_data(cx));
+        match data {
+            Some(Ok(ref data)) => {
+                if let Some(pending) = this.pending.as_mut() {
+                    pending.body.update(data);
+                }
+            }
+            // The summary is dropped so that the response is not compared.
+            Some(Err(_)) => drop(this.pending.take()),
+            None => {
+                if let Some(pending) = this.pending.take() {
+                    pending.complete();
+                }
+            }
+        }
+        Poll::Ready(data)
+    }
+
+    #[inline]
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::header::HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// === impl PendingSummary ===
+
+impl PendingSummary {
+    fn complete(self) {
+        let summary = Summary {
+            status: self.status,
+            headers: self.headers,
+            body: self.body.finish(),
+        };
+        let _ = self.tx.send(summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd_app_core::{metrics::FmtMetrics, svc::Service};
+
+    fn config() -> Arc<MirrorConfig> {
+        Arc::new(MirrorConfig {
+            compare_headers: vec![http::HeaderName::from_static("x-version")],
+            max_body_bytes: 1024,
+            max_in_flight: 10,
+            timeout: Duration::from_secs(1),
+            ..Default::default()
+        })
+    }
+
+    fn response(
+        status: u16,
+        version: &'static str,
+        body: &'static str,
+    ) -> http::Response<http::BoxBody> {
+        http::Response::builder()
+            .status(status)
+            .header("x-version", version)
+            .body(http::BoxBody::new(hyper::Body::from(body)))
+            .unwrap()
+    }
+
+    fn new_mirror<S>(
+        primary: svc::BoxHttp,
+        candidate: S,
+        config: Arc<MirrorConfig>,
+        registry: &Registry,
+    ) -> Mirror<svc::BoxHttp>
+    where
+        S: svc::Service<
+                http::Request<http::BoxBody>,
+                Response = http::Response<http::BoxBody>,
+                Error = Error,
+            > + Send
+            + 'static,
+        S::Future: Send + 'static,
+    {
+        Mirror {
+            primary,
+            candidate: Some(Candidate {
+                service: svc::stack(candidate)
+                    .spawn_buffer(config.max_in_flight)
+                    .into_inner(),
+                ready: false,
+                config,
+                comparisons: registry.comparisons(
+                    NameAddr::from_str_and_port("foo.ns.svc.cluster.local", 80).unwrap(),
+                    NameAddr::from_str_and_port("foo-v2.ns.svc.cluster.local", 80).unwrap(),
+                ),
+            }),
+        }
+    }
+
+    async fn send(mirror: &mut Mirror<svc::BoxHttp>, path: &str) -> bytes::Bytes {
+        future::poll_fn(|cx| mirror.poll_ready(cx))
+            .await
+            .expect("mirror must be ready");
+        let req = http::Request::get(path)
+            .body(http::BoxBody::default())
+            .unwrap();
+        let rsp = mirror.call(req).await.expect("request must succeed");
+        hyper::body::to_bytes(rsp.into_body()).await.unwrap()
+    }
+
+    /// Allows the spawned comparisons to complete.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_mismatched_responses() {
+        let primary = svc::BoxService::new(svc::mk(|_: http::Request<http::BoxBody>| {
+            future::ok::<_, Error>(response(200, "v1", "hello"))
+        }));
+        let candidate = svc::mk(|req: http::Request<http::BoxBody>| {
+            let rsp = match req.uri().path() {
+                "/same" => response(200, "v1", "hello"),
+                "/status" => response(500, "v1", "hello"),
+                _ => response(200, "v2", "goodbye"),
+            };
+            future::ok::<_, Error>(rsp)
+        });
+        let registry = Registry::default();
+        let mut mirror = new_mirror(primary, candidate, config(), &registry);
+        let labels =
+            "logical=\"foo.ns.svc.cluster.local:80\",candidate=\"foo-v2.ns.svc.cluster.local:80\"";
+
+        // Clients always receive the primary's response.
+        for path in &["/same", "/status", "/different"] {
+            assert_eq!(send(&mut mirror, path).await, "hello");
+            settle().await;
+        }
+
+        let metrics = registry.as_display().to_string();
+        assert!(metrics.contains(&format!(
+            "outbound_http_mirror_comparisons_total{{{},result=\"match\"}} 1",
+            labels
+        )));
+        assert!(metrics.contains(&format!(
+            "outbound_http_mirror_comparisons_total{{{},result=\"mismatch\"}} 2",
+            labels
+        )));
+        assert!(metrics.contains(&format!(
+            "outbound_http_mirror_comparisons_total{{{},result=\"failure\"}} 0",
+            labels
+        )));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn shadows_sampled_requests_with_bodies() {
+        // Both services echo the request body.
+        let echo = || {
+            svc::mk(|req: http::Request<http::BoxBody>| async move {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                Ok::<_, Error>(http::Response::new(http::BoxBody::new(hyper::Body::from(
+                    body,
+                ))))
+            })
+        };
+        let (remote_tx, mut remote_rx) = tokio::sync::mpsc::unbounded_channel();
+        let remote = svc::mk(move |req: http::Request<http::BoxBody>| {
+            let remote_tx = remote_tx.clone();
+            async move {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                let _ = remote_tx.send(body.clone());
+                Ok::<_, Error>(http::Response::new(http::BoxBody::new(hyper::Body::from(
+                    body,
+                ))))
+            }
+        });
+        let config = Arc::new(MirrorConfig {
+            max_request_body_bytes: 8,
+            ..(*config()).clone()
+        });
+        let registry = Registry::default();
+        let mut mirror = new_mirror(
+            svc::BoxService::new(echo()),
+            remote,
+            config.clone(),
+            &registry,
+        );
+
+        let post = |body: &'static str| {
+            http::Request::post("/")
+                .header(http::header::CONTENT_LENGTH, body.len())
+                .body(http::BoxBody::new(hyper::Body::from(body)))
+                .unwrap()
+        };
+        for body in &["hello", "too long for the mirror"] {
+            future::poll_fn(|cx| mirror.poll_ready(cx))
+                .await
+                .expect("mirror must be ready");
+            let rsp = mirror.call(post(body)).await.expect("request must succeed");
+            let rsp = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+            assert_eq!(rsp, *body, "the primary must receive the entire body");
+            settle().await;
+        }
+
+        assert_eq!(remote_rx.recv().await.unwrap(), "hello");
+        settle().await;
+        assert!(
+            remote_rx.try_recv().is_err(),
+            "bodies larger than the limit must not be mirrored"
+        );
+        assert!(registry.as_display().to_string().contains(
+            "outbound_http_mirror_comparisons_total{logical=\"foo.ns.svc.cluster.local:80\",candidate=\"foo-v2.ns.svc.cluster.local:80\",result=\"match\"} 1"
+        ));
+
+        // Requests that aren't sampled are never mirrored.
+        let config = Arc::new(MirrorConfig {
+            sample_percent: Some(0),
+            ..(*config).clone()
+        });
+        let (remote_tx, mut remote_rx) = tokio::sync::mpsc::unbounded_channel();
+        let remote = svc::mk(move |_: http::Request<http::BoxBody>| {
+            let _ = remote_tx.send(());
+            future::ok::<_, Error>(response(200, "v1", "hello"))
+        });
+        let mut mirror = new_mirror(svc::BoxService::new(echo()), remote, config, &registry);
+        for _ in 0..10 {
+            future::poll_fn(|cx| mirror.poll_ready(cx)).await.unwrap();
+            mirror.call(post("hello")).await.unwrap();
+        }
+        settle().await;
+        assert!(remote_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn allowed_candidates() {
+        let config = MirrorConfig {
+            allowed_candidates: Some(NameMatch::new(Some("cluster-west.local".parse().unwrap()))),
+            ..Default::default()
+        };
+        let addr = |s: &str| NameAddr::from_str_and_port(s, 80).unwrap();
+        assert!(config.allows(&addr("foo.ns.svc.cluster-west.local")));
+        assert!(!config.allows(&addr("foo.ns.svc.cluster.local")));
+        assert!(MirrorConfig::default().allows(&addr("foo.ns.svc.cluster.local")));
+    }
+
+    #[test]
+    fn mismatches() {
+        let config = config();
+        let summary = |status: u16, version: &'static str, body: Option<u64>| Summary {
+            status: http::StatusCode::from_u16(status).unwrap(),
+            headers: vec![Some(http::HeaderValue::from_static(version))],
+            body,
+        };
+
+        let primary = summary(200, "v1", Some(1));
+        assert!(primary
+            .mismatches(&summary(200, "v1", Some(1)), &config)
+            .is_empty());
+        assert_eq!(
+            primary.mismatches(&summary(503, "v2", Some(2)), &config),
+            vec!["status", "x-version", "body"]
+        );
+        // Bodies that exceeded the limit are not compared.
+        assert!(primary
+            .mismatches(&summary(200, "v1", None), &config)
+            .is_empty());
+    }
+}
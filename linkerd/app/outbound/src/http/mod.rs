@@ -1,15 +1,40 @@
+mod breaker;
+pub(crate) mod canary;
+mod coalesce;
 pub mod detect;
 mod endpoint;
+mod failover;
+mod load_shed;
 pub mod logical;
+mod mirror;
+mod prewarm;
+mod priority;
 mod require_id_header;
+mod route_timeout;
+pub(crate) mod selection;
 mod server;
 
+pub use self::{
+    breaker::RouteBreakers,
+    canary::{CanarySelector, CanarySplit},
+    coalesce::CoalesceConfig,
+    endpoint::EndpointBuffer,
+    failover::FailoverConfig,
+    load_shed::LoadShedConfig,
+    mirror::MirrorConfig,
+    priority::RoutePriority,
+    route_timeout::RouteTimeouts,
+};
+
 use crate::tcp;
 pub use linkerd_app_core::proxy::http::*;
 use linkerd_app_core::{
     dst,
     profiles::{self, LogicalAddr},
-    proxy::{api_resolve::ProtocolHint, tap},
+    proxy::{
+        api_resolve::{ConcreteAddr, ProtocolHint},
+        tap,
+    },
     svc::Param,
     tls,
     transport_header::SessionProtocol,
@@ -19,9 +44,18 @@ use std::{net::SocketAddr, str::FromStr, sync::Arc};
 
 pub type Accept = crate::Accept<Version>;
 pub type Logical = crate::logical::Logical<Version>;
-pub type Concrete = crate::logical::Concrete<Version>;
 pub type Endpoint = crate::endpoint::Endpoint<Version>;
 
+/// An HTTP concrete target.
+///
+/// When a canary split is configured, each concrete address is balanced as
+/// two subsets of its endpoints, each of which has its own target.
+#[derive(Clone, Debug)]
+pub struct Concrete {
+    pub inner: crate::logical::Concrete<Version>,
+    pub subset: Option<canary::Subset>,
+}
+
 #[derive(Clone, Debug)]
 pub struct CanonicalDstHeader(pub Addr);
 
@@ -97,6 +131,23 @@ impl Param<normalize_uri::DefaultAuthority> for Logical {
     }
 }
 
+// === impl Concrete ===
+
+impl From<(ConcreteAddr, Logical)> for Concrete {
+    fn from(target: (ConcreteAddr, Logical)) -> Self {
+        Self {
+            inner: target.into(),
+            subset: None,
+        }
+    }
+}
+
+impl Param<ConcreteAddr> for Concrete {
+    fn param(&self) -> ConcreteAddr {
+        self.inner.resolve.clone()
+    }
+}
+
 // === impl Endpoint ===
 
 impl From<(Version, tcp::Endpoint)> for Endpoint {
@@ -186,6 +237,12 @@ impl tap::Inspect for Endpoint {
             .map(|r| r.route.labels().clone())
     }
 
+    fn dst_selection<B>(&self, req: &Request<B>) -> Option<String> {
+        req.extensions()
+            .get::<selection::Selection>()
+            .map(|s| s.to_string())
+    }
+
     fn is_outbound<B>(&self, _: &Request<B>) -> bool {
         true
     }
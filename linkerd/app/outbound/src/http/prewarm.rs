@@ -0,0 +1,171 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{
+    svc::{self, ServiceExt},
+    Error,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, Instrument};
+
+/// Drives a balancer to readiness as soon as it is built, rather than when
+/// its first request is dispatched.
+///
+/// Balancers are built when a profile is received for a logical target, so
+/// this resolves the balancer's endpoints and connects to them before the
+/// first request arrives. Endpoints are connected just as they would be for a
+/// request, so prewarming never opens more connections to an endpoint than
+/// serving requests would.
+///
+/// If the balancer is dropped before it becomes ready (e.g. because a
+/// short-lived profile no longer references it), prewarming is canceled.
+#[derive(Debug)]
+pub struct Prewarm<S>(State<S>);
+
+#[derive(Debug)]
+enum State<S> {
+    Service(S),
+    Warming(JoinHandle<Result<S, Error>>),
+}
+
+// === impl Prewarm ===
+
+impl<S> Prewarm<S> {
+    pub fn layer<Req>(enabled: bool) -> impl svc::Layer<S, Service = Self> + Clone
+    where
+        S: svc::Service<Req> + Send + 'static,
+        S::Error: Into<Error>,
+        Req: 'static,
+    {
+        svc::layer::mk(move |inner: S| {
+            if !enabled {
+                return Self(State::Service(inner));
+            }
+            debug!("Prewarming");
+            let task = ServiceExt::<Req>::ready_oneshot(inner).err_into::<Error>();
+            Self(State::Warming(tokio::spawn(task.in_current_span())))
+        })
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Prewarm<S>
+where
+    S: svc::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::ErrInto<S::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        loop {
+            self.0 = match self.0 {
+                State::Service(ref mut svc) => return svc.poll_ready(cx).map_err(Into::into),
+                State::Warming(ref mut task) => {
+                    let svc = futures::ready!(Pin::new(task).poll(cx))??;
+                    debug!("Prewarmed");
+                    State::Service(svc)
+                }
+            };
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        match self.0 {
+            State::Service(ref mut svc) => svc.call(req).err_into::<Error>(),
+            State::Warming(_) => panic!("poll_ready must be called first"),
+        }
+    }
+}
+
+impl<S> Drop for Prewarm<S> {
+    fn drop(&mut self) {
+        if let State::Warming(ref task) = self.0 {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::svc::Layer;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    /// A balancer that becomes ready once it is polled, recording that it
+    /// connected to its endpoints.
+    #[derive(Clone, Debug, Default)]
+    struct Balancer {
+        connected: Arc<AtomicBool>,
+        has_endpoints: bool,
+    }
+
+    impl svc::Service<()> for Balancer {
+        type Response = ();
+        type Error = Error;
+        type Future = future::Ready<Result<(), Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            if !self.has_endpoints {
+                return Poll::Pending;
+            }
+            self.connected.store(true, Ordering::Release);
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn connects_before_requests() {
+        let balancer = Balancer {
+            has_endpoints: true,
+            ..Default::default()
+        };
+        let connected = balancer.connected.clone();
+        let mut prewarm = Prewarm::layer::<()>(true).layer(balancer);
+        tokio::task::yield_now().await;
+        assert!(
+            connected.load(Ordering::Acquire),
+            "balancer must connect before a request is sent"
+        );
+        prewarm.ready().await.unwrap().call(()).await.unwrap();
+
+        let balancer = Balancer {
+            has_endpoints: true,
+            ..Default::default()
+        };
+        let connected = balancer.connected.clone();
+        let _prewarm = Prewarm::layer::<()>(false).layer(balancer);
+        tokio::task::yield_now().await;
+        assert!(
+            !connected.load(Ordering::Acquire),
+            "balancer must not connect unless prewarming is enabled"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_when_dropped() {
+        let balancer = Balancer::default();
+        let connected = balancer.connected.clone();
+        let prewarm = Prewarm::layer::<()>(true).layer(balancer);
+        tokio::task::yield_now().await;
+        assert_eq!(Arc::strong_count(&connected), 2);
+
+        drop(prewarm);
+        tokio::task::yield_now().await;
+        assert_eq!(
+            Arc::strong_count(&connected),
+            1,
+            "prewarming must be canceled when the balancer is dropped"
+        );
+    }
+}
@@ -0,0 +1,581 @@
+use futures::{future, TryFutureExt};
+use linkerd_app_core::{dst, proxy::http, svc, Error};
+use parking_lot::Mutex;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, Notify},
+    time::Instant,
+};
+use tracing::{debug, trace, warn, Instrument};
+
+/// Dispatches each logical service's requests in order of their routes'
+/// priorities, rather than in the order they were received.
+///
+/// A route's priority is an integer from 0 to 255 set in its metadata under
+/// the configured `label`; requests on routes without a priority have
+/// priority 0. Priorities only matter while requests wait for the logical
+/// service to become ready: when a request can be dispatched as soon as it is
+/// received, it is, regardless of its priority.
+///
+/// So that requests with a low priority are not starved by a steady stream of
+/// requests with a higher priority, a waiting request's priority increases by
+/// one every `aging` interval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutePriority {
+    /// The route metadata label holding the route's priority.
+    pub label: String,
+
+    /// How long a request waits before its priority increases by one.
+    pub aging: Duration,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Priority(u8);
+
+#[derive(Clone, Debug)]
+pub struct NewSetPriority<N> {
+    inner: N,
+    label: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetPriority<P> {
+    inner: P,
+    priority: Option<Priority>,
+}
+
+/// A buffer that dispatches waiting requests with the highest priority first.
+///
+/// Like `svc::Buffer`, the inner service is driven in a background task, and
+/// at most `capacity` requests may wait for it.
+pub struct PriorityBuffer<Req, Rsp> {
+    handle: Arc<Handle<Req, Rsp>>,
+    reserved: bool,
+    waiter: u64,
+}
+
+/// Closes the buffer once all of its clones are dropped.
+struct Handle<Req, Rsp> {
+    shared: Arc<Shared<Req, Rsp>>,
+}
+
+struct Shared<Req, Rsp> {
+    queue: Mutex<Queue<Req, Rsp>>,
+    notify: Notify,
+    capacity: usize,
+    aging: Duration,
+}
+
+struct Queue<Req, Rsp> {
+    waiting: Vec<Waiting<Req, Rsp>>,
+    reserved: usize,
+    next_seq: u64,
+    next_waiter: u64,
+    /// Wakes each buffer clone that is waiting for capacity.
+    blocked: HashMap<u64, Waker>,
+    closed: bool,
+    failed: Option<Arc<Error>>,
+}
+
+struct Waiting<Req, Rsp> {
+    req: Req,
+    priority: Priority,
+    since: Instant,
+    seq: u64,
+    tx: oneshot::Sender<Result<ResponseFuture<Rsp>, Error>>,
+}
+
+type ResponseFuture<Rsp> = Pin<Box<dyn Future<Output = Result<Rsp, Error>> + Send + 'static>>;
+
+/// Indicates that the logical service failed, so the buffer can no longer
+/// dispatch requests.
+#[derive(Clone, Debug)]
+pub struct ServiceError(Arc<Error>);
+
+#[derive(Debug, thiserror::Error)]
+#[error("priority buffer closed")]
+pub struct Closed(());
+
+// === impl RoutePriority ===
+
+impl RoutePriority {
+    /// Buffers requests to `inner`, prioritizing them if a route priority is
+    /// configured.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn buffered<S, B>(
+        config: Option<Self>,
+        capacity: usize,
+        inner: S,
+    ) -> svc::Either<
+        PriorityBuffer<http::Request<B>, S::Response>,
+        svc::Buffer<http::Request<B>, S::Response, Error>,
+    >
+    where
+        B: Send + 'static,
+        S: svc::Service<http::Request<B>> + Send + 'static,
+        S::Response: Send + 'static,
+        S::Error: Into<Error> + Send + Sync,
+        S::Future: Send + 'static,
+    {
+        match config {
+            Some(Self { aging, .. }) => svc::Either::A(PriorityBuffer::new(inner, capacity, aging)),
+            None => svc::Either::B(
+                svc::stack(inner)
+                    .push(svc::MapErrLayer::new(Into::into))
+                    .spawn_buffer(capacity)
+                    .into_inner(),
+            ),
+        }
+    }
+}
+
+// === impl Priority ===
+
+impl Priority {
+    fn parse(value: &str) -> Option<Self> {
+        value.trim().parse().ok().map(Self)
+    }
+}
+
+// === impl NewSetPriority ===
+
+impl<N> NewSetPriority<N> {
+    pub fn layer(config: Option<RoutePriority>) -> impl svc::Layer<N, Service = Self> + Clone {
+        let label = config.map(|RoutePriority { label, .. }| Arc::from(label));
+        svc::layer::mk(move |inner| Self {
+            inner,
+            label: label.clone(),
+        })
+    }
+}
+
+impl<N> svc::NewService<dst::Route> for NewSetPriority<N>
+where
+    N: svc::NewService<dst::Route>,
+{
+    type Service = SetPriority<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        let priority = self.label.as_ref().and_then(|label| {
+            let value = route.route.labels().get(&**label)?;
+            let priority = Priority::parse(value);
+            if priority.is_none() {
+                warn!(%value, "Ignoring invalid route priority");
+            }
+            priority
+        });
+        SetPriority {
+            inner: self.inner.new_service(route),
+            priority,
+        }
+    }
+}
+
+// === impl SetPriority ===
+
+impl<P, S, B> svc::stack::Proxy<http::Request<B>, S> for SetPriority<P>
+where
+    P: svc::stack::Proxy<http::Request<B>, S>,
+    S: svc::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if let Some(priority) = self.priority {
+            req.extensions_mut().insert(priority);
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl PriorityBuffer ===
+
+impl<B, Rsp> PriorityBuffer<http::Request<B>, Rsp>
+where
+    B: Send + 'static,
+    Rsp: Send + 'static,
+{
+    fn new<S>(inner: S, capacity: usize, aging: Duration) -> Self
+    where
+        S: svc::Service<http::Request<B>, Response = Rsp> + Send + 'static,
+        S::Error: Into<Error>,
+        S::Future: Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                waiting: Vec::new(),
+                reserved: 0,
+                next_seq: 0,
+                next_waiter: 1,
+                blocked: HashMap::new(),
+                closed: false,
+                failed: None,
+            }),
+            notify: Notify::new(),
+            capacity,
+            aging,
+        });
+        tokio::spawn(dispatch(shared.clone(), inner).in_current_span());
+        Self {
+            handle: Arc::new(Handle { shared }),
+            reserved: false,
+            waiter: 0,
+        }
+    }
+}
+
+impl<B, Rsp> svc::Service<http::Request<B>> for PriorityBuffer<http::Request<B>, Rsp>
+where
+    Rsp: Send + 'static,
+{
+    type Response = Rsp;
+    type Error = Error;
+    type Future = ResponseFuture<Rsp>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.reserved {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut queue = self.handle.shared.queue.lock();
+        if let Some(e) = queue.failed.as_ref() {
+            return Poll::Ready(Err(ServiceError(e.clone()).into()));
+        }
+        if queue.waiting.len() + queue.reserved >= self.handle.shared.capacity {
+            // Each waiter holds at most one waker, however often it is polled.
+            match queue.blocked.entry(self.waiter) {
+                Entry::Occupied(mut e) => {
+                    if !e.get().will_wake(cx.waker()) {
+                        e.insert(cx.waker().clone());
+                    }
+                }
+                Entry::Vacant(e) => {
+                    e.insert(cx.waker().clone());
+                }
+            }
+            return Poll::Pending;
+        }
+        queue.reserved += 1;
+        self.reserved = true;
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        assert!(self.reserved, "poll_ready must be called first");
+        self.reserved = false;
+
+        let mut queue = self.handle.shared.queue.lock();
+        queue.reserved -= 1;
+        if let Some(e) = queue.failed.as_ref() {
+            return Box::pin(future::err(ServiceError(e.clone()).into()));
+        }
+
+        let priority = req
+            .extensions()
+            .get::<Priority>()
+            .copied()
+            .unwrap_or_default();
+        let (tx, rx) = oneshot::channel();
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        queue.waiting.push(Waiting {
+            req,
+            priority,
+            since: Instant::now(),
+            seq,
+            tx,
+        });
+        drop(queue);
+        self.handle.shared.notify.notify_one();
+
+        Box::pin(async move {
+            let rsp = rx.await.map_err(|_| Closed(()))??;
+            rsp.await
+        })
+    }
+}
+
+impl<Req, Rsp> Clone for PriorityBuffer<Req, Rsp> {
+    fn clone(&self) -> Self {
+        let waiter = {
+            let mut queue = self.handle.shared.queue.lock();
+            let waiter = queue.next_waiter;
+            queue.next_waiter += 1;
+            waiter
+        };
+        Self {
+            handle: self.handle.clone(),
+            reserved: false,
+            waiter,
+        }
+    }
+}
+
+impl<Req, Rsp> Drop for PriorityBuffer<Req, Rsp> {
+    fn drop(&mut self) {
+        let mut queue = self.handle.shared.queue.lock();
+        queue.blocked.remove(&self.waiter);
+        if self.reserved {
+            queue.reserved -= 1;
+            queue.wake_blocked();
+        }
+    }
+}
+
+impl<Req, Rsp> std::fmt::Debug for PriorityBuffer<Req, Rsp> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PriorityBuffer")
+            .field("capacity", &self.handle.shared.capacity)
+            .field("aging", &self.handle.shared.aging)
+            .finish()
+    }
+}
+
+/// Dispatches waiting requests to the inner service as it becomes ready.
+async fn dispatch<S, B, Rsp>(shared: Arc<Shared<http::Request<B>, Rsp>>, mut inner: S)
+where
+    S: svc::Service<http::Request<B>, Response = Rsp>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+{
+    loop {
+        // Wait for a request to be buffered.
+        loop {
+            {
+                let queue = shared.queue.lock();
+                if !queue.waiting.is_empty() {
+                    break;
+                }
+                if queue.closed {
+                    trace!("Buffer closed");
+                    return;
+                }
+            }
+            shared.notify.notified().await;
+        }
+
+        // Requests are only prioritized once the service is ready, so that
+        // requests received while it is unavailable are dispatched in order
+        // of their priorities.
+        if let Err(e) = future::poll_fn(|cx| inner.poll_ready(cx)).await {
+            let error = Arc::new(e.into());
+            debug!(%error, "Service failed");
+            let mut queue = shared.queue.lock();
+            for Waiting { tx, .. } in queue.waiting.drain(..) {
+                let _ = tx.send(Err(ServiceError(error.clone()).into()));
+            }
+            queue.failed = Some(error);
+            queue.wake_blocked();
+            return;
+        }
+
+        let next = shared.queue.lock().next(shared.aging);
+        if let Some(Waiting {
+            req, priority, tx, ..
+        }) = next
+        {
+            trace!(?priority, "Dispatching request");
+            let rsp = inner.call(req).err_into::<Error>();
+            let _ = tx.send(Ok(Box::pin(rsp)));
+        }
+    }
+}
+
+// === impl Handle ===
+
+impl<Req, Rsp> Drop for Handle<Req, Rsp> {
+    fn drop(&mut self) {
+        self.shared.queue.lock().closed = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+// === impl Queue ===
+
+impl<Req, Rsp> Queue<Req, Rsp> {
+    /// Removes the waiting request with the highest priority, after aging,
+    /// preferring the request that has waited longest.
+    fn next(&mut self, aging: Duration) -> Option<Waiting<Req, Rsp>> {
+        // Requests whose callers are no longer waiting are discarded.
+        self.waiting.retain(|w| !w.tx.is_closed());
+        let now = Instant::now();
+        let effective = |w: &Waiting<Req, Rsp>| {
+            let waited = now.saturating_duration_since(w.since).as_nanos();
+            let boost = waited.checked_div(aging.as_nanos()).unwrap_or(0);
+            u128::from(w.priority.0) + boost
+        };
+        let (i, _) = self
+            .waiting
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, w)| (effective(w), std::cmp::Reverse(w.seq)))?;
+        let next = self.waiting.swap_remove(i);
+        self.wake_blocked();
+        Some(next)
+    }
+
+    fn wake_blocked(&mut self) {
+        for (_, waker) in self.blocked.drain() {
+            waker.wake();
+        }
+    }
+}
+
+// === impl ServiceError ===
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "buffered service failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for ServiceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::svc::ServiceExt;
+
+    /// A balancer that is unavailable until it is no longer saturated, and
+    /// that records the priorities of the requests dispatched to it.
+    #[derive(Clone, Default)]
+    struct Saturated {
+        available: Arc<Mutex<(bool, Option<Waker>)>>,
+        dispatched: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Saturated {
+        fn unsaturate(&self) {
+            let mut available = self.available.lock();
+            available.0 = true;
+            if let Some(waker) = available.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl svc::Service<http::Request<()>> for Saturated {
+        type Response = ();
+        type Error = Error;
+        type Future = future::Ready<Result<(), Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            let mut available = self.available.lock();
+            if available.0 {
+                return Poll::Ready(Ok(()));
+            }
+            available.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+
+        fn call(&mut self, req: http::Request<()>) -> Self::Future {
+            let Priority(p) = req.extensions().get().copied().unwrap_or_default();
+            self.dispatched.lock().push(p);
+            future::ok(())
+        }
+    }
+
+    fn request(priority: Option<u8>) -> http::Request<()> {
+        let mut req = http::Request::new(());
+        if let Some(p) = priority {
+            req.extensions_mut().insert(Priority(p));
+        }
+        req
+    }
+
+    async fn send(
+        buffer: &mut PriorityBuffer<http::Request<()>, ()>,
+        priorities: &[Option<u8>],
+    ) -> Vec<tokio::task::JoinHandle<Result<(), Error>>> {
+        let mut rsps = Vec::new();
+        for p in priorities {
+            let rsp = buffer.ready().await.unwrap().call(request(*p));
+            rsps.push(tokio::spawn(rsp));
+        }
+        rsps
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn dispatches_high_priority_first() {
+        let service = Saturated::default();
+        let mut buffer = PriorityBuffer::new(service.clone(), 10, Duration::from_secs(10));
+
+        let rsps = send(&mut buffer, &[Some(1), None, Some(5), Some(1), Some(9)]).await;
+        tokio::task::yield_now().await;
+        assert!(service.dispatched.lock().is_empty());
+
+        service.unsaturate();
+        for rsp in rsps {
+            rsp.await.unwrap().unwrap();
+        }
+        assert_eq!(*service.dispatched.lock(), vec![9, 5, 1, 1, 0]);
+
+        // Requests are dispatched in order when the service is not saturated.
+        service.dispatched.lock().clear();
+        for p in &[Some(1), None, Some(5)] {
+            buffer
+                .ready()
+                .await
+                .unwrap()
+                .call(request(*p))
+                .await
+                .unwrap();
+        }
+        assert_eq!(*service.dispatched.lock(), vec![1, 0, 5]);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn keeps_one_waker_per_waiter() {
+        let service = Saturated::default();
+        let mut buffer = PriorityBuffer::new(service.clone(), 1, Duration::from_secs(10));
+        let _rsps = send(&mut buffer, &[None]).await;
+
+        let mut blocked = buffer.clone();
+        future::poll_fn(|cx| {
+            for _ in 0..10 {
+                assert!(svc::Service::poll_ready(&mut blocked, cx).is_pending());
+            }
+            Poll::Ready(())
+        })
+        .await;
+        assert_eq!(buffer.handle.shared.queue.lock().blocked.len(), 1);
+
+        drop(blocked);
+        assert!(buffer.handle.shared.queue.lock().blocked.is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn ages_low_priority_requests() {
+        let service = Saturated::default();
+        let mut buffer = PriorityBuffer::new(service.clone(), 10, Duration::from_secs(1));
+
+        let mut rsps = send(&mut buffer, &[None]).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        rsps.extend(send(&mut buffer, &[Some(2), Some(4)]).await);
+        tokio::task::yield_now().await;
+
+        service.unsaturate();
+        for rsp in rsps {
+            rsp.await.unwrap().unwrap();
+        }
+        assert_eq!(
+            *service.dispatched.lock(),
+            vec![4, 0, 2],
+            "requests must not be starved by higher priorities"
+        );
+    }
+}
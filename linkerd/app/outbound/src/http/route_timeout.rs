@@ -0,0 +1,153 @@
+use linkerd_app_core::profiles;
+use std::time::Duration;
+use tracing::warn;
+
+/// Determines the request timeout applied to each outbound route.
+///
+/// A route's timeout is, in order of precedence:
+///
+/// 1. The timeout set by the route's service profile;
+/// 2. The timeout hint in the route's metadata, when a `label` is configured;
+/// 3. The `default` timeout.
+///
+/// A timeout of zero disables the route's timeout, so a profile that sets a
+/// zero timeout overrides a route's hint and the default. Hints that cannot be
+/// parsed are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTimeouts {
+    /// The route metadata label holding a timeout hint, e.g. `500ms` or `2s`.
+    pub label: Option<String>,
+
+    /// The timeout applied to routes that have neither a profile timeout nor
+    /// a hint.
+    pub default: Option<Duration>,
+}
+
+// === impl RouteTimeouts ===
+
+impl RouteTimeouts {
+    /// Sets the route's effective timeout.
+    pub(crate) fn apply(&self, mut route: profiles::http::Route) -> profiles::http::Route {
+        match self.timeout(&route) {
+            Some(timeout) => route.set_timeout(timeout),
+            None => route.clear_timeout(),
+        }
+        route
+    }
+
+    fn timeout(&self, route: &profiles::http::Route) -> Option<Duration> {
+        let timeout = route
+            .timeout()
+            .or_else(|| self.hint(route))
+            .or(self.default)?;
+        if timeout == Duration::from_secs(0) {
+            return None;
+        }
+        Some(timeout)
+    }
+
+    fn hint(&self, route: &profiles::http::Route) -> Option<Duration> {
+        let value = route.labels().get(self.label.as_ref()?)?;
+        let hint = parse_duration(value);
+        if hint.is_none() {
+            warn!(%value, "Ignoring invalid route timeout hint");
+        }
+        hint
+    }
+}
+
+pub(super) fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let unit = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    if unit == 0 {
+        return None;
+    }
+    let magnitude = s[..unit].parse::<u64>().ok()?;
+    match &s[unit..] {
+        "" if magnitude == 0 => Some(Duration::from_secs(0)),
+        "ms" => Some(Duration::from_millis(magnitude)),
+        "s" => Some(Duration::from_secs(magnitude)),
+        "m" => Some(Duration::from_secs(magnitude * 60)),
+        "h" => Some(Duration::from_secs(magnitude * 60 * 60)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http;
+    use futures::future;
+    use linkerd_app_core::{dst, metrics::Direction, svc, Error};
+    use svc::{Layer, NewService, ServiceExt};
+
+    const LABEL: &str = "timeout";
+
+    fn route(hint: Option<&str>, timeout: Option<Duration>) -> profiles::http::Route {
+        let labels = hint.map(|h| (LABEL.to_string(), h.to_string()));
+        let mut route = profiles::http::Route::new(labels.into_iter(), vec![]);
+        if let Some(t) = timeout {
+            route.set_timeout(t);
+        }
+        route
+    }
+
+    fn timeouts(default: Option<Duration>) -> RouteTimeouts {
+        RouteTimeouts {
+            label: Some(LABEL.to_string()),
+            default,
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let secs = Duration::from_secs;
+        let default = timeouts(Some(secs(10)));
+
+        assert_eq!(default.timeout(&route(None, None)), Some(secs(10)));
+        assert_eq!(
+            default.timeout(&route(Some("500ms"), None)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            default.timeout(&route(Some("2s"), Some(secs(1)))),
+            Some(secs(1))
+        );
+        assert_eq!(default.timeout(&route(Some("bogus"), None)), Some(secs(10)));
+
+        // Zero disables the timeout at each level.
+        assert_eq!(default.timeout(&route(Some("0"), None)), None);
+        assert_eq!(default.timeout(&route(Some("2s"), Some(secs(0)))), None);
+        assert_eq!(timeouts(Some(secs(0))).timeout(&route(None, None)), None);
+
+        // Hints are ignored unless a label is configured.
+        let unlabeled = RouteTimeouts {
+            label: None,
+            default: None,
+        };
+        assert_eq!(unlabeled.timeout(&route(Some("2s"), None)), None);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn enforces_hints() {
+        let mut new_svc = http::MakeTimeoutLayer::default()
+            .layer(|_: dst::Route| svc::mk(|_: ()| future::pending::<Result<(), Error>>()));
+        let mut send = |route: profiles::http::Route| {
+            let svc = new_svc.new_service(dst::Route {
+                target: "foo.ns.svc.cluster.local:80".parse().unwrap(),
+                route: timeouts(None).apply(route),
+                direction: Direction::Out,
+            });
+            tokio::time::timeout(Duration::from_secs(10), svc.oneshot(()))
+        };
+
+        let rsp = send(route(Some("1s"), None)).await;
+        assert!(
+            matches!(rsp, Ok(Err(_))),
+            "request must fail after the hinted timeout"
+        );
+
+        let rsp = send(route(None, None)).await;
+        assert!(rsp.is_err(), "request must not time out without a hint");
+    }
+}
@@ -0,0 +1,309 @@
+use super::Concrete;
+use linkerd_app_core::{
+    proxy::http::{self, balance},
+    svc::{self, Param},
+    transport::{Remote, ServerAddr},
+};
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+use tracing::info;
+
+/// Records why each request was dispatched to its balancer's endpoints, so
+/// that the decision is reported on tap events.
+///
+/// Requests are labeled with the reason they were routed away from their
+/// primary balancer (e.g. to a failover backup), with the canary subset when
+/// they are routed to canary endpoints, or otherwise with the balancer's
+/// algorithm.
+#[derive(Clone, Debug)]
+pub struct NewSetSelection<N> {
+    inner: N,
+    algorithm: balance::Algorithm,
+    enabled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetSelection<S> {
+    inner: S,
+    selection: Option<Selection>,
+}
+
+/// Logs the selection of each opaque TCP connection's endpoint.
+///
+/// Tap only observes HTTP requests, so the endpoints selected for opaque
+/// connections are reported in the proxy's log as they are connected.
+#[derive(Clone, Debug)]
+pub struct NewLogSelection<N> {
+    inner: N,
+    selection: Option<Selection>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogSelection<S> {
+    inner: S,
+    addr: Remote<ServerAddr>,
+    selection: Option<Selection>,
+}
+
+/// Describes why a request was dispatched to its endpoint.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Selection(&'static str);
+
+/// Marks requests that are dispatched to a balancer other than their logical
+/// service's primary balancer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Rerouted(&'static str);
+
+// === impl NewSetSelection ===
+
+impl<N> NewSetSelection<N> {
+    pub fn layer(
+        algorithm: balance::Algorithm,
+        enabled: bool,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            algorithm,
+            enabled,
+        })
+    }
+}
+
+impl<N: svc::NewService<Concrete>> svc::NewService<Concrete> for NewSetSelection<N> {
+    type Service = SetSelection<N::Service>;
+
+    fn new_service(&mut self, concrete: Concrete) -> Self::Service {
+        let selection = if !self.enabled {
+            None
+        } else if concrete.subset.as_ref().map(|s| s.is_canary()) == Some(true) {
+            Some(Selection("canary"))
+        } else {
+            Some(Selection::balanced(self.algorithm))
+        };
+        SetSelection {
+            inner: self.inner.new_service(concrete),
+            selection,
+        }
+    }
+}
+
+// === impl SetSelection ===
+
+impl<S, B> svc::Service<http::Request<B>> for SetSelection<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let rerouted = req.extensions_mut().remove::<Rerouted>();
+        if let Some(selection) = self.selection {
+            let selection = rerouted
+                .map(|Rerouted(r)| Selection(r))
+                .unwrap_or(selection);
+            req.extensions_mut().insert(selection);
+        }
+        self.inner.call(req)
+    }
+}
+
+// === impl NewLogSelection ===
+
+impl<N> NewLogSelection<N> {
+    pub fn layer(
+        algorithm: balance::Algorithm,
+        enabled: bool,
+    ) -> impl svc::Layer<N, Service = Self> + Clone {
+        let selection = if enabled {
+            Some(Selection::balanced(algorithm))
+        } else {
+            None
+        };
+        svc::layer::mk(move |inner| Self { inner, selection })
+    }
+}
+
+impl<T, N> svc::NewService<T> for NewLogSelection<N>
+where
+    T: Param<Remote<ServerAddr>>,
+    N: svc::NewService<T>,
+{
+    type Service = LogSelection<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        LogSelection {
+            addr: target.param(),
+            inner: self.inner.new_service(target),
+            selection: self.selection,
+        }
+    }
+}
+
+// === impl LogSelection ===
+
+impl<Req, S> svc::Service<Req> for LogSelection<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        if let Some(selection) = self.selection {
+            info!(server.addr = %self.addr, %selection, "Selected endpoint");
+        }
+        self.inner.call(req)
+    }
+}
+
+// === impl Selection ===
+
+impl Selection {
+    fn balanced(algorithm: balance::Algorithm) -> Self {
+        Self(match algorithm {
+            balance::Algorithm::PeakEwma => "peak-ewma",
+            balance::Algorithm::LeastRequest => "least-request",
+            balance::Algorithm::LatencyEwma => "latency-ewma",
+            balance::Algorithm::RoundRobin => "round-robin",
+            balance::Algorithm::Random => "random",
+        })
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// === impl Rerouted ===
+
+impl Rerouted {
+    pub(crate) const FAILOVER: Self = Self("failover");
+    pub(crate) const MIRROR: Self = Self("mirror");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd_app_core::{
+        svc::{Layer, NewService, ServiceExt},
+        Error,
+    };
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    async fn selection(
+        selection: Option<Selection>,
+        rerouted: Option<Rerouted>,
+    ) -> Option<Selection> {
+        let inner = svc::mk(|req: http::Request<()>| {
+            future::ok::<_, Error>(req.extensions().get::<Selection>().copied())
+        });
+        let mut req = http::Request::new(());
+        if let Some(rerouted) = rerouted {
+            req.extensions_mut().insert(rerouted);
+        }
+        SetSelection { inner, selection }
+            .oneshot(req)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_selection() {
+        let balanced = Selection::balanced(balance::Algorithm::LeastRequest);
+        assert_eq!(balanced.to_string(), "least-request");
+        assert_eq!(selection(Some(balanced), None).await, Some(balanced));
+        assert_eq!(
+            selection(Some(balanced), Some(Rerouted::FAILOVER)).await,
+            Some(Selection("failover")),
+            "rerouted requests must be labeled with the reason they were rerouted"
+        );
+        assert_eq!(
+            selection(None, Some(Rerouted::MIRROR)).await,
+            None,
+            "selections must only be recorded when enabled"
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Endpoint;
+
+    impl Param<Remote<ServerAddr>> for Endpoint {
+        fn param(&self) -> Remote<ServerAddr> {
+            Remote(ServerAddr(([10, 0, 0, 2], 8080).into()))
+        }
+    }
+
+    /// Connects to an endpoint, returning the lines that are logged.
+    async fn connect(enabled: bool) -> Vec<String> {
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .without_time()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut new_connect = NewLogSelection::layer(balance::Algorithm::PeakEwma, enabled)
+            .layer(|_: Endpoint| svc::mk(|()| future::ok::<_, Error>(())));
+        new_connect.new_service(Endpoint).oneshot(()).await.unwrap();
+
+        let logs = logs.0.lock().unwrap();
+        String::from_utf8_lossy(&*logs)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn logs_tcp_selection() {
+        let lines = connect(true).await;
+        assert_eq!(lines.len(), 1, "{:#?}", lines);
+        assert!(lines[0].contains("Selected endpoint"), "{}", lines[0]);
+        assert!(
+            lines[0].contains("server.addr=10.0.0.2:8080"),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].contains("selection=peak-ewma"), "{}", lines[0]);
+
+        assert!(
+            connect(false).await.is_empty(),
+            "selections must only be logged when enabled"
+        );
+    }
+}
@@ -28,6 +28,8 @@ impl<N> Outbound<N> {
                 dispatch_timeout,
                 max_in_flight_requests,
                 buffer_capacity,
+                client_disconnect,
+                echo_trace_id,
                 ..
             } = config.proxy;
 
@@ -46,10 +48,24 @@ impl<N> Outbound<N> {
                         .push_spawn_buffer(buffer_capacity)
                         .push(rt.metrics.http_errors.clone())
                         // Synthesizes responses for proxy errors.
-                        .push(errors::layer())
+                        .push(errors::layer(
+                            echo_trace_id,
+                            false,
+                            errors::proxy_error_header(),
+                            false,
+                        ))
+                        // Identifies the proxy in the `Via` headers of requests
+                        // and their responses, including error responses.
+                        .push(http::AddVia::layer(config.proxy.via.clone()))
                         // Initiates OpenCensus tracing.
                         .push(http_tracing::server(rt.span_sink.clone(), trace_labels()))
-                        .push(http::BoxResponse::layer()),
+                        // Decides whether requests are traced before their
+                        // spans are recorded.
+                        .push(http_tracing::Sample::layer(rt.trace_sampler.clone()))
+                        .push(http::BoxResponse::layer())
+                        // Determines whether requests complete after their
+                        // clients disconnect.
+                        .push(http::HandleDisconnect::layer(client_disconnect)),
                 )
                 // Convert origin form HTTP/1 URIs to absolute form for Hyper's
                 // `Client`.
@@ -1,4 +1,4 @@
-use crate::{http, stack_labels, tcp, trace_labels, Config, Outbound};
+use crate::{build_limit::LimitBuilds, http, stack_labels, tcp, trace_labels, Config, Outbound};
 use linkerd_app_core::{
     config::{ProxyConfig, ServerConfig},
     detect, errors, http_tracing, io, profiles,
@@ -72,6 +72,7 @@ impl Outbound<svc::BoxNewHttp<http::Endpoint>> {
 
         let Config {
             allow_discovery,
+            build_limit,
             proxy:
                 ProxyConfig {
                     server: ServerConfig { h2_settings, .. },
@@ -80,6 +81,9 @@ impl Outbound<svc::BoxNewHttp<http::Endpoint>> {
                     detect_protocol_timeout,
                     buffer_capacity,
                     cache_max_idle_age,
+                    client_disconnect,
+                    echo_trace_id,
+                    ref via,
                     ..
                 },
             ..
@@ -106,7 +110,7 @@ impl Outbound<svc::BoxNewHttp<http::Endpoint>> {
                 },
             )
             .push(profiles::discover::layer(
-                profiles,
+                LimitBuilds::new(build_limit, profiles),
                 move |h: Http<NameAddr>| {
                     // Lookup the profile if the override header was set and it is in the configured
                     // profile domains. Otherwise, profile discovery is skipped.
@@ -205,10 +209,18 @@ impl Outbound<svc::BoxNewHttp<http::Endpoint>> {
                     .push(svc::ConcurrencyLimitLayer::new(max_in_flight_requests))
                     .push(svc::FailFast::layer("Ingress server", dispatch_timeout))
                     .push(rt.metrics.http_errors.clone())
-                    .push(errors::layer())
+                    .push(errors::layer(
+                        echo_trace_id,
+                        false,
+                        errors::proxy_error_header(),
+                        false,
+                    ))
+                    .push(http::AddVia::layer(via.clone()))
                     .push(http_tracing::server(rt.span_sink, trace_labels()))
+                    .push(http_tracing::Sample::layer(rt.trace_sampler))
                     .push(http::BoxResponse::layer())
-                    .push(http::BoxRequest::layer()),
+                    .push(http::BoxRequest::layer())
+                    .push(http::HandleDisconnect::layer(client_disconnect)),
             )
             .instrument(|a: &http::Accept| debug_span!("http", v = %a.protocol))
             .push(http::NewServeHttp::layer(h2_settings, rt.drain))
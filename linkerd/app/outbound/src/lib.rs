@@ -5,23 +5,27 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
+mod build_limit;
 mod discover;
 pub mod endpoint;
 pub mod http;
 mod ingress;
 pub mod logical;
 mod resolve;
+mod stable_hash;
+mod subset;
 mod switch_logical;
 pub mod tcp;
 #[cfg(test)]
 pub(crate) mod test_util;
 
+pub use self::{build_limit::BuildLimit, subset::EndpointSubsetting};
 use linkerd_app_core::{
     config::{ProxyConfig, ServerConfig},
     metrics, profiles,
     proxy::{
         api_resolve::{ConcreteAddr, Metadata},
-        core::Resolve,
+        core::{balance, Resolve},
     },
     serve,
     svc::{self, stack::Param},
@@ -44,6 +48,83 @@ pub struct Config {
     // not perform per-target-address discovery. Non-HTTP connections are
     // forwarded without discovery/routing/mTLS.
     pub ingress_mode: bool,
+
+    /// Client TLS settings for off-mesh destinations that are not provided an
+    /// identity by discovery.
+    pub external_tls: tcp::external_tls::Config,
+
+    /// The algorithm used to balance requests and connections over a
+    /// service's endpoints. Changes apply to balancers as they are built.
+    pub balance_algorithm: balance::Algorithm,
+
+    /// If set, balancers deprioritize endpoints for this long after a request
+    /// or connection to them fails. Does not apply to round-robin balancers.
+    pub balance_failure_penalty: Option<Duration>,
+
+    /// If set, HTTP balancers prefer endpoints that are likely to have an idle
+    /// connection with this probability, bounded by
+    /// `http::balance::MAX_AFFINITY_BIAS`, so that fewer connections are
+    /// opened. Does not apply to round-robin balancers.
+    pub balance_connection_affinity: Option<f64>,
+
+    /// Determines the request timeout for each route, from its profile, its
+    /// metadata, or a default.
+    pub route_timeouts: http::RouteTimeouts,
+
+    /// Determines the circuit breaker thresholds of each route, from its
+    /// metadata or a default.
+    pub route_breakers: http::RouteBreakers,
+
+    /// Determines how long clients may take to send the bodies of requests
+    /// on each route.
+    pub request_body_timeout: http::RequestBodyTimeout,
+
+    /// If set, requests are buffered in front of each HTTP endpoint while it
+    /// is unavailable.
+    pub endpoint_buffer: Option<http::EndpointBuffer>,
+
+    /// If set, limits the number of logical stacks that may await discovery
+    /// concurrently.
+    pub build_limit: Option<BuildLimit>,
+
+    /// If set, routes a share of each service's HTTP requests to its canary
+    /// endpoints.
+    pub canary: Option<http::CanarySplit>,
+
+    /// If set, limits each balancer to a stable subset of its service's
+    /// endpoints.
+    pub endpoint_subsetting: Option<EndpointSubsetting>,
+
+    /// Configures the backup services to which HTTP traffic fails over.
+    pub failover: http::FailoverConfig,
+
+    /// Configures the candidate services to which HTTP requests are mirrored
+    /// for comparison.
+    pub mirror: http::MirrorConfig,
+
+    /// If set, concurrent, identical GET requests to each logical service are
+    /// coalesced so that only one of them is dispatched.
+    pub coalesce: Option<http::CoalesceConfig>,
+
+    /// If set, requests waiting for a logical service are dispatched in order
+    /// of their routes' priorities, rather than in the order they were
+    /// received.
+    pub route_priority: Option<http::RoutePriority>,
+
+    /// If set, a fraction of the requests to each logical service fail
+    /// without being dispatched while the service's latency exceeds its SLO.
+    pub load_shed: Option<http::LoadShedConfig>,
+
+    /// If true, balancers resolve and connect to their endpoints as soon as
+    /// they are built for a profile, rather than when they receive their
+    /// first request.
+    pub prewarm_endpoints: bool,
+
+    /// If true, tap events for HTTP requests report why each request's
+    /// endpoint was selected (e.g. by the balancer, for a canary, or for a
+    /// failover backup). Tap does not observe opaque TCP connections, so
+    /// their endpoints' selections are logged instead.
+    pub tap_endpoint_selection: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -124,7 +205,7 @@ impl Outbound<()> {
         bind: B,
         profiles: P,
         resolve: R,
-    ) -> (Local<ServerAddr>, impl Future<Output = ()>)
+    ) -> Result<(Local<ServerAddr>, impl Future<Output = ()>), Error>
     where
         B: Bind<ServerConfig>,
         B::Addrs: Param<Remote<ClientAddr>> + Param<OrigDstAddr>,
@@ -136,11 +217,10 @@ impl Outbound<()> {
         P::Future: Send,
         P::Error: Send,
     {
-        let (listen_addr, listen) = bind
-            .bind(&self.config.proxy.server)
-            .expect("Failed to bind outbound listener");
+        let (listen_addr, listen) = bind.bind(&self.config.proxy.server)?;
 
         let serve = async move {
+            let listener = self.runtime.shutdown.listener("outbound");
             if self.config.ingress_mode {
                 info!("Outbound routing in ingress-mode");
                 let stack = self
@@ -148,8 +228,11 @@ impl Outbound<()> {
                     .push_tcp_endpoint()
                     .push_http_endpoint()
                     .into_ingress(profiles, resolve);
+                let log = self.config.proxy.connection_log.clone();
+                let listen = self.config.proxy.memory_pressure.gate(listen);
                 let shutdown = self.runtime.drain.signaled();
-                serve::serve(listen, stack, shutdown).await;
+                let grace = self.runtime.shutdown_grace.clone();
+                serve::serve_logged(listen, listener.track(stack), log, shutdown, grace).await;
             } else {
                 let logical = self.to_tcp_connect().push_logical(resolve);
                 let endpoint = self.to_tcp_connect().push_endpoint();
@@ -157,12 +240,16 @@ impl Outbound<()> {
                     .push_switch_logical(logical.into_inner())
                     .push_discover(profiles)
                     .into_inner();
+                let log = self.config.proxy.connection_log.clone();
+                let listen = self.config.proxy.memory_pressure.gate(listen);
                 let shutdown = self.runtime.drain.signaled();
-                serve::serve(listen, server, shutdown).await;
+                let grace = self.runtime.shutdown_grace.clone();
+                serve::serve_logged(listen, listener.track(server), log, shutdown, grace).await;
             }
+            listener.stopped();
         };
 
-        (listen_addr, serve)
+        Ok((listen_addr, serve))
     }
 }
 
@@ -175,6 +262,7 @@ impl<P> Param<transport::labels::Key> for Accept<P> {
             transport::labels::Direction::Out,
             NO_TLS,
             self.orig_dst.into(),
+            None,
         )
     }
 }
@@ -119,14 +119,19 @@ impl<C> Outbound<C> {
         Self: Clone + 'static,
         C: Clone + Send + Sync + Unpin + 'static,
         C: svc::Service<tcp::Connect, Error = io::Error>,
-        C::Response:
-            tls::HasNegotiatedProtocol + io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+        C::Response: tls::HasNegotiatedProtocol
+            + io::AsyncRead
+            + io::AsyncWrite
+            + io::Splice
+            + Send
+            + Unpin
+            + 'static,
         C::Future: Send + Unpin,
         R: Clone + Send + 'static,
         R: Resolve<ConcreteAddr, Endpoint = Metadata, Error = Error> + Sync,
         R::Resolution: Send,
         R::Future: Send + Unpin,
-        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr,
+        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + io::Splice,
         I: fmt::Debug + Send + Sync + Unpin + 'static,
     {
         let http = self
@@ -0,0 +1,382 @@
+use crate::stable_hash::StableHasher;
+use futures::{prelude::*, ready};
+use linkerd_app_core::{proxy::core::Update, svc};
+use pin_project::pin_project;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::Hasher,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tracing::{debug, warn};
+
+/// Limits each balancer to a stable subset of its service's endpoints, so that
+/// proxies need not connect to every endpoint of a large service.
+///
+/// Endpoints are selected by rendezvous hashing: each endpoint is ranked by a
+/// hash of its address and this proxy's seed, and the highest-ranked endpoints
+/// are used. Because each proxy has its own seed, the fleet's connections are
+/// spread evenly over all of a service's endpoints; and because an endpoint's
+/// rank does not depend on the other endpoints, changes to the endpoint set
+/// only change the subset as much as is necessary to keep it full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EndpointSubsetting {
+    size: usize,
+    seed: u64,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SubsetEndpoints<R> {
+    inner: R,
+    subsetting: Option<EndpointSubsetting>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub(crate) struct SubsetEndpointsFuture<F> {
+    #[pin]
+    future: F,
+    subsetting: Option<EndpointSubsetting>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub(crate) struct EndpointSubset<R, E> {
+    #[pin]
+    resolution: R,
+    subsetting: Option<EndpointSubsetting>,
+    endpoints: HashMap<SocketAddr, E>,
+    selected: HashSet<SocketAddr>,
+    pending: VecDeque<Update<E>>,
+}
+
+// === impl EndpointSubsetting ===
+
+impl EndpointSubsetting {
+    /// Subsets smaller than this are too easily exhausted by endpoint
+    /// failures.
+    pub const MIN_SIZE: usize = 3;
+
+    /// Selects up to `size` endpoints for each balancer, ranked by a seed
+    /// derived from `id`, which should identify this proxy instance.
+    pub fn new(size: usize, id: &str) -> Self {
+        let size = if size < Self::MIN_SIZE {
+            warn!(
+                size,
+                min = Self::MIN_SIZE,
+                "Endpoint subset size is too small"
+            );
+            Self::MIN_SIZE
+        } else {
+            size
+        };
+        let mut hasher = StableHasher::default();
+        hasher.write(id.as_bytes());
+        Self {
+            size,
+            seed: hasher.finish(),
+        }
+    }
+
+    /// Ranks are hashed from the address's bytes so that they agree across
+    /// proxy versions.
+    fn rank(&self, addr: &SocketAddr) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write(&self.seed.to_be_bytes());
+        match addr.ip() {
+            IpAddr::V4(ip) => hasher.write(&ip.octets()),
+            IpAddr::V6(ip) => hasher.write(&ip.octets()),
+        }
+        hasher.write(&addr.port().to_be_bytes());
+        hasher.finish()
+    }
+
+    fn select<'a>(&self, addrs: impl Iterator<Item = &'a SocketAddr>) -> HashSet<SocketAddr> {
+        let mut ranked = addrs
+            .map(|addr| (self.rank(addr), *addr))
+            .collect::<Vec<_>>();
+        if ranked.len() > self.size {
+            ranked.sort_unstable_by(|a, b| b.cmp(a));
+            ranked.truncate(self.size);
+        }
+        ranked.into_iter().map(|(_, addr)| addr).collect()
+    }
+}
+
+// === impl SubsetEndpoints ===
+
+impl<R> SubsetEndpoints<R> {
+    pub(crate) fn layer(
+        subsetting: Option<EndpointSubsetting>,
+    ) -> impl svc::Layer<R, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self { inner, subsetting })
+    }
+}
+
+impl<T, R, S, E> svc::Service<T> for SubsetEndpoints<R>
+where
+    R: svc::Service<T, Response = S>,
+    S: TryStream<Ok = Update<E>, Error = R::Error>,
+{
+    type Response = EndpointSubset<S, E>;
+    type Error = R::Error;
+    type Future = SubsetEndpointsFuture<R::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        SubsetEndpointsFuture {
+            future: self.inner.call(target),
+            subsetting: self.subsetting,
+        }
+    }
+}
+
+impl<F, S, E, Err> Future for SubsetEndpointsFuture<F>
+where
+    F: TryFuture<Ok = S, Error = Err>,
+    S: TryStream<Ok = Update<E>, Error = Err>,
+{
+    type Output = Result<EndpointSubset<S, E>, Err>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let resolution = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(EndpointSubset::new(resolution, *this.subsetting)))
+    }
+}
+
+// === impl EndpointSubset ===
+
+impl<R, E> EndpointSubset<R, E> {
+    fn new(resolution: R, subsetting: Option<EndpointSubsetting>) -> Self {
+        Self {
+            resolution,
+            subsetting,
+            endpoints: HashMap::new(),
+            selected: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R, E> Stream for EndpointSubset<R, E>
+where
+    R: TryStream<Ok = Update<E>>,
+    E: Clone,
+{
+    type Item = Result<Update<E>, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(update) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(update)));
+            }
+
+            let update = match ready!(this.resolution.as_mut().try_poll_next(cx)) {
+                Some(update) => update?,
+                None => return Poll::Ready(None),
+            };
+            let subsetting = match this.subsetting.as_ref() {
+                Some(subsetting) => subsetting,
+                None => return Poll::Ready(Some(Ok(update))),
+            };
+
+            // Endpoints whose metadata has been updated must be re-added if
+            // they remain selected.
+            let mut updated = HashSet::new();
+            let reset = match update {
+                Update::Add(eps) => {
+                    for (addr, ep) in eps {
+                        updated.insert(addr);
+                        this.endpoints.insert(addr, ep);
+                    }
+                    false
+                }
+                Update::Remove(addrs) => {
+                    for addr in addrs {
+                        this.endpoints.remove(&addr);
+                    }
+                    false
+                }
+                Update::Reset(eps) => {
+                    *this.endpoints = eps.into_iter().collect();
+                    true
+                }
+                Update::DoesNotExist => {
+                    this.endpoints.clear();
+                    this.selected.clear();
+                    return Poll::Ready(Some(Ok(Update::DoesNotExist)));
+                }
+            };
+
+            let selected = subsetting.select(this.endpoints.keys());
+            let endpoints = &*this.endpoints;
+            let endpoint = |addr: &SocketAddr| (*addr, endpoints[addr].clone());
+            if reset {
+                this.pending
+                    .push_back(Update::Reset(selected.iter().map(endpoint).collect()));
+            } else {
+                let removed = this
+                    .selected
+                    .difference(&selected)
+                    .copied()
+                    .collect::<Vec<_>>();
+                if !removed.is_empty() {
+                    this.pending.push_back(Update::Remove(removed));
+                }
+                let added = selected
+                    .iter()
+                    .filter(|addr| updated.contains(addr) || !this.selected.contains(addr))
+                    .map(endpoint)
+                    .collect::<Vec<_>>();
+                if !added.is_empty() {
+                    this.pending.push_back(Update::Add(added));
+                }
+            }
+            debug!(
+                endpoints = endpoints.len(),
+                selected = selected.len(),
+                "Updated endpoint subset"
+            );
+            *this.selected = selected;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_app_core::Error;
+
+    fn addr(i: u16) -> SocketAddr {
+        ([10, 0, (i >> 8) as u8, i as u8], 8080).into()
+    }
+
+    fn add(addrs: impl IntoIterator<Item = SocketAddr>) -> Update<()> {
+        Update::Add(addrs.into_iter().map(|addr| (addr, ())).collect())
+    }
+
+    /// Returns the subset's updates, with their addresses sorted.
+    async fn subset(subsetting: EndpointSubsetting, updates: Vec<Update<()>>) -> Vec<Update<()>> {
+        let resolution = stream::iter(updates.into_iter().map(Ok::<_, Error>));
+        EndpointSubset::new(resolution, Some(subsetting))
+            .map_ok(|update| match update {
+                Update::Add(mut eps) => {
+                    eps.sort_by_key(|(addr, _)| *addr);
+                    Update::Add(eps)
+                }
+                Update::Reset(mut eps) => {
+                    eps.sort_by_key(|(addr, _)| *addr);
+                    Update::Reset(eps)
+                }
+                Update::Remove(mut addrs) => {
+                    addrs.sort();
+                    Update::Remove(addrs)
+                }
+                Update::DoesNotExist => Update::DoesNotExist,
+            })
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    fn addrs(update: &Update<()>) -> Vec<SocketAddr> {
+        match update {
+            Update::Add(eps) | Update::Reset(eps) => eps.iter().map(|(addr, _)| *addr).collect(),
+            Update::Remove(addrs) => addrs.clone(),
+            Update::DoesNotExist => vec![],
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_endpoints_to_subset() {
+        let subsetting = EndpointSubsetting::new(10, "proxy-a");
+        let all = (0..1000).map(addr).collect::<Vec<_>>();
+        let updates = subset(subsetting, vec![add(all.clone())]).await;
+        assert_eq!(updates.len(), 1);
+        let selected = addrs(&updates[0]);
+        assert_eq!(selected.len(), 10);
+
+        // Subsets are stable for each proxy, regardless of the order in which
+        // endpoints are discovered...
+        let mut reversed = all.clone();
+        reversed.reverse();
+        let updates = subset(subsetting, vec![add(reversed)]).await;
+        assert_eq!(addrs(&updates[0]), selected);
+
+        // ...but differ across proxies.
+        let other = EndpointSubsetting::new(10, "proxy-b");
+        let updates = subset(other, vec![add(all)]).await;
+        assert_ne!(addrs(&updates[0]), selected);
+    }
+
+    #[test]
+    fn ranks_are_stable() {
+        // Ranks must not change across proxy versions, or upgrading proxies
+        // would reshuffle their subsets.
+        let subsetting = EndpointSubsetting::new(10, "proxy-a");
+        let mut seed = StableHasher::default();
+        seed.write(b"proxy-a");
+        assert_eq!(subsetting.seed, seed.finish());
+
+        let mut rank = StableHasher::default();
+        rank.write(&subsetting.seed.to_be_bytes());
+        rank.write(&[10, 0, 0, 1]);
+        rank.write(&[0x1f, 0x90]);
+        assert_eq!(subsetting.rank(&addr(1)), rank.finish());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rebalances_with_minimal_churn() {
+        let subsetting = EndpointSubsetting::new(5, "proxy-a");
+        let all = (0..50).map(addr).collect::<Vec<_>>();
+        let selected = addrs(&subset(subsetting, vec![add(all.clone())]).await[0]);
+        let unselected = all.iter().find(|a| !selected.contains(a)).copied().unwrap();
+
+        let updates = subset(
+            subsetting,
+            vec![
+                add(all.clone()),
+                // Removing an unselected endpoint does not change the subset.
+                Update::Remove(vec![unselected]),
+                // Removing a selected endpoint replaces only that endpoint.
+                Update::Remove(vec![selected[0]]),
+            ],
+        )
+        .await;
+        assert_eq!(updates.len(), 3, "{:?}", updates);
+        assert_eq!(updates[1], Update::Remove(vec![selected[0]]));
+        let replacement = addrs(&updates[2]);
+        assert_eq!(replacement.len(), 1);
+        assert!(!selected.contains(&replacement[0]));
+
+        // Adding endpoints only displaces as many endpoints as are added to
+        // the subset.
+        let updates = subset(subsetting, vec![add(all.clone()), add((50..100).map(addr))]).await;
+        let added = updates.get(2).map(addrs).unwrap_or_default();
+        let removed = updates.get(1).map(addrs).unwrap_or_default();
+        assert_eq!(added.len(), removed.len());
+        assert!(added.iter().all(|addr| !all.contains(addr)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn small_services_are_not_subset() {
+        let subsetting = EndpointSubsetting::new(1, "proxy-a");
+        let updates = subset(subsetting, vec![add((0..4).map(addr))]).await;
+        assert_eq!(
+            addrs(&updates[0]).len(),
+            EndpointSubsetting::MIN_SIZE,
+            "subsets must not be smaller than the minimum size"
+        );
+
+        let subsetting = EndpointSubsetting::new(10, "proxy-a");
+        let updates = subset(subsetting, vec![add((0..4).map(addr))]).await;
+        assert_eq!(addrs(&updates[0]), (0..4).map(addr).collect::<Vec<_>>());
+    }
+}
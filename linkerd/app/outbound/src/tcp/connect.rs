@@ -1,4 +1,7 @@
-use super::opaque_transport::{self, OpaqueTransport};
+use super::{
+    external_tls::ExternalTls,
+    opaque_transport::{self, OpaqueTransport},
+};
 use crate::Outbound;
 use futures::future;
 use linkerd_app_core::{
@@ -27,7 +30,8 @@ pub struct PreventLoopback<S>(S);
 
 impl Outbound<()> {
     pub fn to_tcp_connect(&self) -> Outbound<PreventLoopback<ConnectTcp>> {
-        let connect = PreventLoopback(ConnectTcp::new(self.config.proxy.connect.keepalive));
+        let config = &self.config.proxy.connect;
+        let connect = PreventLoopback(ConnectTcp::new(config.keepalive, config.dscp.clone()));
         self.clone().with_stack(connect)
     }
 }
@@ -38,7 +42,7 @@ impl<C> Outbound<C> {
     ) -> Outbound<
         impl svc::Service<
                 T,
-                Response = impl io::AsyncRead + io::AsyncWrite + Send + Unpin,
+                Response = impl io::AsyncRead + io::AsyncWrite + io::Splice + Send + Unpin,
                 Error = Error,
                 Future = impl Send,
             > + Clone,
@@ -52,11 +56,14 @@ impl<C> Outbound<C> {
             + svc::Param<transport::labels::Key>,
         C: svc::Service<Connect, Error = io::Error> + Clone + Send + 'static,
         C::Response: tls::HasNegotiatedProtocol,
-        C::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+        C::Response: io::AsyncRead + io::AsyncWrite + io::Splice + Send + Unpin + 'static,
         C::Future: Send + 'static,
     {
         self.map_stack(|config, rt, connect| {
             connect
+                // Originates TLS to off-mesh destinations that are configured
+                // with their own client identity.
+                .push(ExternalTls::layer(config.external_tls.clone()))
                 // Initiates mTLS if the target is configured with identity. The
                 // endpoint configures ALPN when there is an opaque transport hint OR
                 // when an authority override is present (indicating the target is a
@@ -82,15 +89,16 @@ impl<C> Outbound<C> {
     >
     where
         T: Clone + Send + 'static,
-        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + std::fmt::Debug + Send + Unpin + 'static,
+        I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + io::Splice,
+        I: std::fmt::Debug + Send + Unpin + 'static,
         C: svc::Service<T> + Clone + Send + Sync + 'static,
-        C::Response: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin,
+        C::Response: io::AsyncRead + io::AsyncWrite + io::Splice + Send + Unpin,
         C::Error: Into<Error>,
         C::Future: Send,
     {
-        self.map_stack(|_, _, conn| {
+        self.map_stack(|config, _, conn| {
             conn.push_make_thunk()
-                .push_on_response(super::Forward::layer())
+                .push_on_response(super::Forward::layer_with_splice(config.proxy.tcp_splice))
                 .instrument(|_: &_| debug_span!("tcp.forward"))
                 .push(svc::BoxNewService::layer())
                 .check_new_service::<T, I>()
@@ -0,0 +1,106 @@
+use super::Connect;
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
+use linkerd_app_core::{
+    io, svc,
+    tls::{self, external::ClientConfig},
+    transport::{Remote, ServerAddr},
+    Conditional, IpMatch,
+};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::{debug, trace};
+
+/// Client TLS settings for off-mesh destinations.
+///
+/// Each destination is matched by the networks on which it resides. When a
+/// connection's address matches more than one destination, the first
+/// configured destination is used.
+#[derive(Clone, Debug, Default)]
+pub struct Config(Arc<Vec<(IpMatch, ClientConfig)>>);
+
+/// Originates TLS to off-mesh destinations using the destination's configured
+/// client identity.
+///
+/// Connections for which the mesh provides an identity are not modified.
+#[derive(Clone, Debug)]
+pub struct ExternalTls<S> {
+    inner: S,
+    config: Config,
+}
+
+pub type Io<I> = io::EitherIo<I, tls::external::TlsStream<I>>;
+
+type Handshake<I> = Pin<Box<dyn Future<Output = io::Result<Io<I>>> + Send + 'static>>;
+
+// === impl Config ===
+
+impl Config {
+    pub fn new(destinations: impl IntoIterator<Item = (IpMatch, ClientConfig)>) -> Self {
+        Self(Arc::new(destinations.into_iter().collect()))
+    }
+
+    fn lookup(&self, Remote(ServerAddr(addr)): Remote<ServerAddr>) -> Option<&ClientConfig> {
+        self.0
+            .iter()
+            .find(|(nets, _)| nets.matches(addr.ip()))
+            .map(|(_, config)| config)
+    }
+}
+
+// === impl ExternalTls ===
+
+impl<S> ExternalTls<S> {
+    pub fn layer(config: Config) -> impl svc::Layer<S, Service = Self> + Clone {
+        svc::layer::mk(move |inner| Self {
+            inner,
+            config: config.clone(),
+        })
+    }
+}
+
+impl<S> svc::Service<Connect> for ExternalTls<S>
+where
+    S: svc::Service<Connect, Error = io::Error>,
+    S::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Io<S::Response>;
+    type Error = io::Error;
+    type Future = Either<
+        future::MapOk<S::Future, fn(S::Response) -> Io<S::Response>>,
+        Handshake<S::Response>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: Connect) -> Self::Future {
+        let config = match target.tls {
+            Conditional::None(_) => self.config.lookup(target.addr).cloned(),
+            Conditional::Some(_) => None,
+        };
+        let config = match config {
+            Some(config) => config,
+            None => {
+                trace!("No off-mesh TLS configured");
+                return Either::Left(self.inner.call(target).map_ok(io::EitherIo::Left));
+            }
+        };
+
+        debug!(server.name = %config.server_name(), "Initiating off-mesh TLS connection");
+        let connect = self.inner.call(target);
+        Either::Right(Box::pin(async move {
+            let io = connect.await?;
+            let io = config.connect(io).await?;
+            Ok(io::EitherIo::Right(io))
+        }))
+    }
+}
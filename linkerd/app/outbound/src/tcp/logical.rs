@@ -1,5 +1,7 @@
 use super::{Concrete, Endpoint, Logical};
-use crate::{endpoint, resolve, Outbound};
+use crate::{
+    endpoint, http::selection::NewLogSelection, resolve, subset::SubsetEndpoints, Outbound,
+};
 use linkerd_app_core::{
     config, drain, io, profiles,
     proxy::{
@@ -15,7 +17,7 @@ use tracing::debug_span;
 impl<C> Outbound<C>
 where
     C: svc::Service<Endpoint> + Clone + Send + 'static,
-    C::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin,
+    C::Response: io::AsyncRead + io::AsyncWrite + io::Splice + Send + Unpin,
     C::Error: Into<Error>,
     C::Future: Send,
 {
@@ -30,7 +32,7 @@ where
         >,
     >
     where
-        I: io::AsyncRead + io::AsyncWrite + std::fmt::Debug + Send + Unpin + 'static,
+        I: io::AsyncRead + io::AsyncWrite + io::Splice + std::fmt::Debug + Send + Unpin + 'static,
         R: Resolve<ConcreteAddr, Endpoint = Metadata, Error = Error>
             + Clone
             + Send
@@ -45,6 +47,7 @@ where
                 buffer_capacity,
                 cache_max_idle_age,
                 dispatch_timeout,
+                tcp_splice,
                 ..
             } = config.proxy;
 
@@ -52,6 +55,7 @@ where
             let resolve = svc::stack(resolve.into_service())
                 .check_service::<ConcreteAddr>()
                 .push_request_filter(|c: Concrete| Ok::<_, Infallible>(c.resolve))
+                .push(SubsetEndpoints::layer(config.endpoint_subsetting))
                 .push(svc::layer::mk(move |inner| {
                     map_endpoint::Resolve::new(endpoint::FromMetadata { identity_disabled }, inner)
                 }))
@@ -60,6 +64,13 @@ where
 
             connect
                 .push_make_thunk()
+                // Logs why each connection's endpoint was selected, if tap
+                // events report selections, since tap does not observe opaque
+                // connections.
+                .push(NewLogSelection::layer(
+                    config.balance_algorithm,
+                    config.tap_endpoint_selection,
+                ))
                 .instrument(|t: &Endpoint| match t.tls.as_ref() {
                     Conditional::Some(tls) => {
                         debug_span!("endpoint", server.addr = %t.addr, server.id = ?tls.server_id)
@@ -71,16 +82,18 @@ where
                 .push(resolve::layer(resolve, config.proxy.cache_max_idle_age * 2))
                 .push_on_response(
                     svc::layers()
-                        .push(tcp::balance::layer(
+                        .push(tcp::balance::layer_with(
+                            config.balance_algorithm,
                             crate::EWMA_DEFAULT_RTT,
                             crate::EWMA_DECAY,
+                            config.balance_failure_penalty,
                         ))
                         .push(
                             rt.metrics
                                 .stack
                                 .layer(crate::stack_labels("tcp", "balancer")),
                         )
-                        .push(tcp::Forward::layer())
+                        .push(tcp::Forward::layer_with_splice(tcp_splice))
                         .push(drain::Retain::layer(rt.drain.clone())),
                 )
                 .into_new_service()
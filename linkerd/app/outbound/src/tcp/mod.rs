@@ -1,4 +1,5 @@
 pub mod connect;
+pub mod external_tls;
 pub mod logical;
 pub mod opaque_transport;
 
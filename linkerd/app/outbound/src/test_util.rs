@@ -16,15 +16,35 @@ use std::{str::FromStr, time::Duration};
 pub fn default_config() -> Config {
     Config {
         ingress_mode: false,
+        external_tls: Default::default(),
+        balance_algorithm: Default::default(),
+        balance_failure_penalty: None,
+        balance_connection_affinity: None,
+        route_timeouts: Default::default(),
+        route_breakers: Default::default(),
+        request_body_timeout: Default::default(),
+        endpoint_buffer: None,
+        build_limit: None,
+        canary: None,
+        endpoint_subsetting: None,
+        failover: Default::default(),
+        mirror: Default::default(),
+        coalesce: None,
+        route_priority: None,
+        load_shed: None,
+        prewarm_endpoints: false,
+        tap_endpoint_selection: false,
         allow_discovery: IpMatch::new(Some(IpNet::from_str("0.0.0.0/0").unwrap())).into(),
         proxy: config::ProxyConfig {
             server: config::ServerConfig {
                 addr: ListenAddr(([0, 0, 0, 0], 0).into()),
                 keepalive: Keepalive(None),
+                dscp: Default::default(),
                 h2_settings: h2::Settings::default(),
             },
             connect: config::ConnectConfig {
                 keepalive: Keepalive(None),
+                dscp: Default::default(),
                 timeout: Duration::from_secs(1),
                 backoff: exp_backoff::ExponentialBackoff::new(
                     Duration::from_millis(100),
@@ -37,26 +57,38 @@ pub fn default_config() -> Config {
                     idle_timeout: Duration::from_secs(1),
                 },
                 h2_settings: h2::Settings::default(),
+                close_delimited: Default::default(),
+                response_read_ahead_bytes: 0,
             },
             buffer_capacity: 10_000,
             cache_max_idle_age: Duration::from_secs(60),
             dispatch_timeout: Duration::from_secs(3),
             max_in_flight_requests: 10_000,
             detect_protocol_timeout: Duration::from_secs(3),
+            client_disconnect: Default::default(),
+            connection_log: Default::default(),
+            echo_trace_id: false,
+            tcp_splice: false,
+            memory_pressure: Default::default(),
+            via: None,
         },
     }
 }
 
 pub fn runtime() -> (ProxyRuntime, drain::Signal) {
-    let (metrics, _) = metrics::Metrics::new(std::time::Duration::from_secs(10));
+    let (metrics, _) =
+        metrics::Metrics::new(std::time::Duration::from_secs(10), Default::default());
     let (drain_tx, drain) = drain::channel();
-    let (tap, _) = tap::new();
+    let (tap, _) = tap::new(None);
     let runtime = ProxyRuntime {
         identity: None,
         metrics: metrics.outbound,
         tap,
         span_sink: None,
+        trace_sampler: Default::default(),
         drain,
+        shutdown: Default::default(),
+        shutdown_grace: Default::default(),
     };
     (runtime, drain_tx)
 }
@@ -1,17 +1,25 @@
 use crate::core::{
     addr,
+    breaker::BreakerThresholds,
     config::*,
+    connection_log::{self, ConnectionLog},
     control::{Config as ControlConfig, ControlAddr},
-    proxy::http::{h1, h2},
+    errors, http_tracing,
+    memory_pressure::MemoryPressure,
+    metrics::StatusLabels,
+    proxy::{
+        core::balance,
+        http::{h1, h2, ClientDisconnect, CloseDelimited, HeaderName, StatusCode, Via},
+    },
     tls,
-    transport::{Keepalive, ListenAddr},
-    Addr, AddrMatch, Conditional, NameMatch,
+    transport::{Dscp, DscpMarking, Keepalive, ListenAddr},
+    Addr, AddrMatch, Conditional, IpMatch, NameAddr, NameMatch,
 };
 use crate::{dns, gateway, identity, inbound, oc_collector, outbound};
 use std::{
     collections::{HashMap, HashSet},
     fs,
-    net::SocketAddr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
     str::FromStr,
     time::Duration,
@@ -65,6 +73,8 @@ pub enum ParseError {
     ),
     #[error("not a valid subnet mask")]
     NotANetwork,
+    #[error("unsupported value: {0}")]
+    UnsupportedValue(String),
     #[error("host is not an IP address")]
     HostIsNotAnIpAddress,
     #[error(transparent)]
@@ -85,8 +95,469 @@ pub const ENV_ADMIN_LISTEN_ADDR: &str = "LINKERD2_PROXY_ADMIN_LISTEN_ADDR";
 
 pub const ENV_METRICS_RETAIN_IDLE: &str = "LINKERD2_PROXY_METRICS_RETAIN_IDLE";
 
+/// When true, HTTP response metrics label status codes by class (e.g. `5xx`)
+/// rather than by code.
+const ENV_METRICS_STATUS_CLASSES: &str = "LINKERD2_PROXY_METRICS_STATUS_CLASSES";
+
+/// A comma-separated list of status codes, e.g. `429,503`, that keep their own
+/// labels when `LINKERD2_PROXY_METRICS_STATUS_CLASSES` is enabled.
+const ENV_METRICS_DISTINCT_STATUS_CODES: &str = "LINKERD2_PROXY_METRICS_DISTINCT_STATUS_CODES";
+
+/// The `host:port` address of a Prometheus Pushgateway. When set, the proxy
+/// periodically pushes its metrics to the gateway, in addition to serving
+/// them from the admin server.
+const ENV_METRICS_PUSH_GATEWAY_ADDR: &str = "LINKERD2_PROXY_METRICS_PUSH_GATEWAY_ADDR";
+
+/// The `job` grouping label of pushed metrics. Defaults to `linkerd-proxy`.
+const ENV_METRICS_PUSH_JOB: &str = "LINKERD2_PROXY_METRICS_PUSH_JOB";
+
+/// The `instance` grouping label of pushed metrics. When unset, pushed
+/// metrics are grouped by job alone.
+const ENV_METRICS_PUSH_INSTANCE: &str = "LINKERD2_PROXY_METRICS_PUSH_INSTANCE";
+
+const ENV_METRICS_PUSH_INTERVAL: &str = "LINKERD2_PROXY_METRICS_PUSH_INTERVAL";
+const ENV_METRICS_PUSH_TIMEOUT: &str = "LINKERD2_PROXY_METRICS_PUSH_TIMEOUT";
+
 const ENV_INGRESS_MODE: &str = "LINKERD2_PROXY_INGRESS_MODE";
 
+/// If set, the client's source port is included in inbound connection spans
+/// and tap events. The port is never used as a metric label.
+const ENV_INBOUND_LOG_CLIENT_PORT: &str = "LINKERD2_PROXY_INBOUND_LOG_CLIENT_PORT";
+
+/// If set, the bytes transferred on inbound connections are reported in the
+/// `inbound_identity_bytes_total` metric, labeled by client identity.
+const ENV_INBOUND_IDENTITY_BYTES_METRICS: &str = "LINKERD2_PROXY_INBOUND_IDENTITY_BYTES_METRICS";
+
+/// Configures how inbound HTTP requests without a URI authority or `Host`
+/// header are handled.
+///
+/// Either `reject`, to fail such requests with a 400, or `target`, to derive
+/// the authority from the connection's original destination address. If
+/// unspecified, `target` is used.
+const ENV_INBOUND_MISSING_AUTHORITY: &str = "LINKERD2_PROXY_INBOUND_MISSING_AUTHORITY";
+
+/// Configures how inbound HTTP/1 requests with both `Transfer-Encoding` and
+/// `Content-Length` headers are handled.
+///
+/// Either `reject`, to fail such requests with a 400, or `strip`, to remove
+/// the `Content-Length` header. If unspecified, `reject` is used.
+const ENV_INBOUND_TRANSFER_ENCODING_CONFLICT: &str =
+    "LINKERD2_PROXY_INBOUND_TRANSFER_ENCODING_CONFLICT";
+
+/// Configures how inbound requests with several `Content-Length` headers that
+/// share a value are handled.
+///
+/// Either `reject`, to fail such requests with a 400, or `collapse`, to replace
+/// them with a single header. If unspecified, `reject` is used. Requests with
+/// conflicting or malformed lengths are always rejected.
+const ENV_INBOUND_DUPLICATE_CONTENT_LENGTH: &str =
+    "LINKERD2_PROXY_INBOUND_DUPLICATE_CONTENT_LENGTH";
+
+/// The maximum number of error responses the inbound proxy generates each
+/// second on each port. Errors beyond this rate are answered with a generic
+/// 503 and are not logged. If unspecified, error responses are not limited.
+const ENV_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND";
+
+/// Configures which clients' inbound responses have the proxy's `l5d-*`
+/// headers, like `l5d-proxy-error`, removed.
+///
+/// Either `never`, `unmeshed`, to strip them from responses to clients without
+/// a mesh identity, or `always`. If unspecified, `never` is used.
+const ENV_INBOUND_STRIP_L5D_HEADERS: &str = "LINKERD2_PROXY_INBOUND_STRIP_L5D_HEADERS";
+
+/// Names the header in which the inbound proxy's error responses describe
+/// their errors. Defaults to `l5d-proxy-error`. If empty, errors are not
+/// described in a header, though response statuses are still set.
+const ENV_INBOUND_PROXY_ERROR_HEADER: &str = "LINKERD2_PROXY_INBOUND_PROXY_ERROR_HEADER";
+
+/// If true, the inbound proxy's error responses distinguish failures to
+/// connect to the application (`connect failed`) from connections that the
+/// application resets after they are established (`upstream reset`).
+const ENV_INBOUND_CLASSIFY_APP_CONNECTION_ERRORS: &str =
+    "LINKERD2_PROXY_INBOUND_CLASSIFY_APP_CONNECTION_ERRORS";
+
+/// If true, the inbound proxy's error responses to clients without a verified
+/// identity describe all errors as an "upstream error", so that they do not
+/// reveal internal service names. Meshed clients still see detailed errors.
+const ENV_INBOUND_REDACT_UNMESHED_ERRORS: &str = "LINKERD2_PROXY_INBOUND_REDACT_UNMESHED_ERRORS";
+
+/// Configures a circuit breaker on each of the application's target addresses.
+/// Thresholds are written as `<max_failures>/<open_timeout>`, e.g. `5/10s`:
+/// once `max_failures` requests to a target fail consecutively, its requests
+/// fail with a 503 for `open_timeout` before a single request probes it. If
+/// unspecified, or `0`, the application has no circuit breakers.
+const ENV_INBOUND_LOCAL_BREAKER_DEFAULT: &str = "LINKERD2_PROXY_INBOUND_LOCAL_BREAKER_DEFAULT";
+
+/// A comma-separated list of `port=thresholds` pairs, e.g. `8080=3/5s`, that
+/// override `LINKERD2_PROXY_INBOUND_LOCAL_BREAKER_DEFAULT` for the given
+/// ports. A port whose thresholds are `0` has no circuit breaker.
+const ENV_INBOUND_LOCAL_BREAKER_PORTS: &str = "LINKERD2_PROXY_INBOUND_LOCAL_BREAKER_PORTS";
+
+/// If true, the inbound proxy's 502, 503, and 504 error responses describe
+/// the error in a JSON body when the request accepts `application/json`.
+const ENV_INBOUND_JSON_ERROR_BODIES: &str = "LINKERD2_PROXY_INBOUND_JSON_ERROR_BODIES";
+
+/// A comma-separated list of `port=rate` pairs, e.g. `8080=100`, that override
+/// `LINKERD2_PROXY_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND` for the given ports.
+const ENV_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND_PORTS: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND_PORTS";
+
+/// A comma-separated list of `port=rate` or `port=rate/burst` pairs, e.g.
+/// `8080=100/200`, that limit the number of connections accepted on each port
+/// each second. Bursts of up to `burst` connections are accepted, which
+/// defaults to the rate. Connections beyond this rate are closed, or, if they
+/// are HTTP connections, served 429 Too Many Requests responses.
+const ENV_INBOUND_PORTS_CONNECTION_RATE: &str = "LINKERD2_PROXY_INBOUND_PORTS_CONNECTION_RATE";
+
+/// The maximum number of inbound connections that each client identity may
+/// hold open at once, if its identity matches none of the tiers in
+/// `LINKERD2_PROXY_INBOUND_MAX_CONNECTIONS_IDENTITY_TIERS`. If unspecified,
+/// such identities are not limited.
+const ENV_INBOUND_MAX_CONNECTIONS_PER_IDENTITY: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_CONNECTIONS_PER_IDENTITY";
+
+/// A comma-separated list of `pattern=max` pairs that limit the number of
+/// inbound connections held open by each client identity that matches the
+/// pattern. Patterns are either identities or, e.g.
+/// `*.ns.serviceaccount.identity.linkerd.cluster.local`, suffixes of
+/// identities. When several patterns match an identity, an exact match is
+/// preferred, and otherwise the longest suffix.
+const ENV_INBOUND_MAX_CONNECTIONS_IDENTITY_TIERS: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_CONNECTIONS_IDENTITY_TIERS";
+
+/// The maximum number of inbound connections that clients without an
+/// identity may hold open at once, in aggregate.
+const ENV_INBOUND_MAX_CONNECTIONS_UNAUTHENTICATED: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_CONNECTIONS_UNAUTHENTICATED";
+
+/// If set, forwarded TCP connections (e.g. on opaque ports) are closed once no
+/// data has been transferred on them for this long, unless their port has a
+/// timeout in `LINKERD2_PROXY_INBOUND_PORTS_TCP_IDLE_TIMEOUT`.
+const ENV_INBOUND_TCP_IDLE_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_TCP_IDLE_TIMEOUT";
+
+/// A comma-separated list of `port=duration` pairs, e.g. `5432=10m`, that
+/// configure the idle timeout of forwarded TCP connections on each port.
+const ENV_INBOUND_PORTS_TCP_IDLE_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_PORTS_TCP_IDLE_TIMEOUT";
+
+/// The maximum number of inbound requests each client identity may send each
+/// second, across all of its connections. Requests beyond this rate fail with
+/// a 429 Too Many Requests response. If unspecified, requests are not limited.
+const ENV_INBOUND_MAX_REQUESTS_PER_SECOND_PER_IDENTITY: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_REQUESTS_PER_SECOND_PER_IDENTITY";
+
+/// A comma-separated list of `identity=rate` pairs that override
+/// `LINKERD2_PROXY_INBOUND_MAX_REQUESTS_PER_SECOND_PER_IDENTITY` for the given
+/// client identities.
+const ENV_INBOUND_MAX_REQUESTS_PER_SECOND_IDENTITIES: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_REQUESTS_PER_SECOND_IDENTITIES";
+
+/// The maximum number of inbound requests that clients without an identity
+/// may send each second, in aggregate.
+const ENV_INBOUND_MAX_REQUESTS_PER_SECOND_UNAUTHENTICATED: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_REQUESTS_PER_SECOND_UNAUTHENTICATED";
+
+/// A comma-separated list of list-valued request headers whose duplicate
+/// values are joined into a single header before inbound requests are
+/// forwarded to the application. `Set-Cookie` is never coalesced.
+const ENV_INBOUND_COALESCE_HEADERS: &str = "LINKERD2_PROXY_INBOUND_COALESCE_HEADERS";
+
+/// Determines whether HTTP requests are canceled when their clients disconnect
+/// (`cancel`, the default) or are allowed to complete (`complete`). Completed
+/// requests' response bodies are also read to completion when their clients
+/// disconnect while the bodies are streamed.
+const ENV_INBOUND_CLIENT_DISCONNECT: &str = "LINKERD2_PROXY_INBOUND_CLIENT_DISCONNECT";
+const ENV_OUTBOUND_CLIENT_DISCONNECT: &str = "LINKERD2_PROXY_OUTBOUND_CLIENT_DISCONNECT";
+
+/// Determines how HTTP/1 responses that lack both a `content-length` and a
+/// `transfer-encoding` are forwarded: as-is (`pass-through`, the default),
+/// with a `content-length` if their bodies are small enough to be buffered
+/// (`buffer`), or not at all (`reject`).
+const ENV_INBOUND_CLOSE_DELIMITED_RESPONSES: &str =
+    "LINKERD2_PROXY_INBOUND_CLOSE_DELIMITED_RESPONSES";
+const ENV_OUTBOUND_CLOSE_DELIMITED_RESPONSES: &str =
+    "LINKERD2_PROXY_OUTBOUND_CLOSE_DELIMITED_RESPONSES";
+
+/// Limits the size of the close-delimited response bodies that are buffered.
+/// Larger bodies are forwarded as-is.
+const ENV_CLOSE_DELIMITED_BUFFER_MAX_BYTES: &str =
+    "LINKERD2_PROXY_CLOSE_DELIMITED_BUFFER_MAX_BYTES";
+
+/// Limits how many bytes of each streaming response body may be read from the
+/// upstream ahead of the client. By default, response bodies are not buffered,
+/// so they are read only as quickly as the client consumes them.
+const ENV_INBOUND_RESPONSE_READ_AHEAD_BYTES: &str =
+    "LINKERD2_PROXY_INBOUND_RESPONSE_READ_AHEAD_BYTES";
+const ENV_OUTBOUND_RESPONSE_READ_AHEAD_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_RESPONSE_READ_AHEAD_BYTES";
+
+/// Enables a log line for each inbound and outbound connection as it is
+/// accepted and closed.
+const ENV_CONNECTION_LOG: &str = "LINKERD2_PROXY_CONNECTION_LOG";
+
+/// Limits the number of connections logged each second, so that connection
+/// logs cannot overwhelm the proxy when connections churn.
+const ENV_CONNECTION_LOG_MAX_PER_SECOND: &str = "LINKERD2_PROXY_CONNECTION_LOG_MAX_PER_SECOND";
+
+/// Configures how connections are logged: either `plain`, the default, which
+/// logs each connection as it is accepted and closed, or `json`, which writes
+/// a JSON record with each connection's metadata to stdout when it closes.
+const ENV_CONNECTION_LOG_FORMAT: &str = "LINKERD2_PROXY_CONNECTION_LOG_FORMAT";
+
+/// A DSCP value, from 0 to 63, with which all accepted and established
+/// connections are marked for QoS.
+const ENV_DSCP: &str = "LINKERD2_PROXY_DSCP";
+
+/// A comma-separated list of `port=dscp` pairs, e.g. `5432=46`, that override
+/// `LINKERD2_PROXY_DSCP` for connections to (or accepted for) the given ports.
+const ENV_DSCP_PORTS: &str = "LINKERD2_PROXY_DSCP_PORTS";
+
+/// Enables an `l5d-proxy-trace-id` header on proxy error responses, carrying
+/// the ID of the trace propagated with the failed request.
+const ENV_ERROR_RESPONSE_TRACE_ID: &str = "LINKERD2_PROXY_ERROR_RESPONSE_TRACE_ID";
+
+/// If set, the proxy appends an entry with this pseudonym (e.g. `linkerd`) to
+/// the `Via` headers of the HTTP requests and responses it forwards, in both
+/// directions. Pseudonyms must be HTTP tokens.
+pub const ENV_VIA_PSEUDONYM: &str = "LINKERD2_PROXY_VIA_PSEUDONYM";
+
+/// Enables forwarding TCP connections with `splice(2)`, so that data is not
+/// copied through userspace. This only applies on Linux and to connections
+/// that are not TLS-encrypted by the proxy.
+const ENV_TCP_SPLICE: &str = "LINKERD2_PROXY_TCP_SPLICE";
+
+/// If set, the inbound and outbound proxies stop accepting connections while
+/// the proxy's memory usage (as reported by its cgroup or, otherwise, its
+/// resident set size) is at least this many bytes. They resume once usage
+/// falls to `LINKERD2_PROXY_ACCEPT_MEMORY_RESUME_BYTES`, which defaults to 90%
+/// of the threshold. Usage is sampled every
+/// `LINKERD2_PROXY_ACCEPT_MEMORY_CHECK_INTERVAL`. This is only supported on
+/// Linux.
+const ENV_ACCEPT_MEMORY_PAUSE_BYTES: &str = "LINKERD2_PROXY_ACCEPT_MEMORY_PAUSE_BYTES";
+const ENV_ACCEPT_MEMORY_RESUME_BYTES: &str = "LINKERD2_PROXY_ACCEPT_MEMORY_RESUME_BYTES";
+const ENV_ACCEPT_MEMORY_CHECK_INTERVAL: &str = "LINKERD2_PROXY_ACCEPT_MEMORY_CHECK_INTERVAL";
+
+/// Names a header that carries each inbound request's ID. When set, an ID is
+/// generated for requests that lack one.
+const ENV_INBOUND_REQUEST_ID_HEADER: &str = "LINKERD2_PROXY_INBOUND_REQUEST_ID_HEADER";
+
+/// Names a header on which the number of microseconds each inbound request
+/// spent in the proxy, from the time it was received until it was dispatched
+/// to the application, is set. Client-provided values are removed.
+const ENV_INBOUND_PROXY_ELAPSED_HEADER: &str = "LINKERD2_PROXY_INBOUND_PROXY_ELAPSED_HEADER";
+
+/// The fraction, from 0 to 1, of inbound requests without a sampling decision
+/// that are traced. Sampled requests without a trace context start a new
+/// trace. Upstream sampling decisions are always honored. If unspecified,
+/// `LINKERD2_PROXY_TRACE_COLLECTOR_SAMPLE_RATIO` applies when a trace
+/// collector is configured.
+const ENV_INBOUND_TRACE_SAMPLE_RATIO: &str = "LINKERD2_PROXY_INBOUND_TRACE_SAMPLE_RATIO";
+
+/// Names a header that forces inbound requests to be traced, regardless of
+/// the sample ratio or any upstream sampling decision. If unspecified,
+/// `LINKERD2_PROXY_TRACE_FORCE_SAMPLE_HEADER` applies when a trace collector
+/// is configured.
+const ENV_INBOUND_TRACE_FORCE_SAMPLE_HEADER: &str =
+    "LINKERD2_PROXY_INBOUND_TRACE_FORCE_SAMPLE_HEADER";
+
+/// A comma-separated allowlist of the request metadata that is recorded on the
+/// spans of inbound requests: `route` records the labels of the request's
+/// profile route, `client_id` records the client's identity, and
+/// `header:<name>` records the value of a request header. Headers that may
+/// carry credentials are never recorded. If unspecified, no metadata is
+/// recorded.
+const ENV_INBOUND_TRACE_ATTRIBUTES: &str = "LINKERD2_PROXY_INBOUND_TRACE_ATTRIBUTES";
+
+/// Bounds the length, in bytes, of inbound HTTP/1 request lines. If
+/// unspecified, request lines are only bounded by the HTTP/1 parser's limit on
+/// the size of request heads.
+const ENV_INBOUND_MAX_REQUEST_LINE_BYTES: &str = "LINKERD2_PROXY_INBOUND_MAX_REQUEST_LINE_BYTES";
+
+/// Bounds the total size, in bytes, of each inbound request's cookies.
+const ENV_INBOUND_MAX_COOKIE_BYTES: &str = "LINKERD2_PROXY_INBOUND_MAX_COOKIE_BYTES";
+
+/// Bounds the number of cookies on each inbound request.
+const ENV_INBOUND_MAX_COOKIES: &str = "LINKERD2_PROXY_INBOUND_MAX_COOKIES";
+
+/// Configures how inbound requests with cookies beyond the configured limits
+/// are handled: `reject` (the default) fails them with a 431 Request Header
+/// Fields Too Large, and `strip` removes the excess cookies.
+const ENV_INBOUND_OVERSIZED_COOKIES: &str = "LINKERD2_PROXY_INBOUND_OVERSIZED_COOKIES";
+
+/// Configures the amount of time after which an inbound WebSocket connection
+/// that has not transferred any data is closed. WebSockets are not closed
+/// when idle if this is unset.
+const ENV_INBOUND_WEBSOCKET_IDLE_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_WEBSOCKET_IDLE_TIMEOUT";
+
+/// A comma-separated list of protocols (e.g. `foo` or `foo/2`) to which
+/// inbound HTTP/1.1 requests may upgrade, in addition to WebSockets. Requests
+/// that offer other protocols fail with a 426 Upgrade Required response. If
+/// unset, all upgrades are permitted.
+const ENV_INBOUND_ALLOWED_UPGRADE_PROTOCOLS: &str =
+    "LINKERD2_PROXY_INBOUND_ALLOWED_UPGRADE_PROTOCOLS";
+
+/// Bounds the time taken by the application to send response headers, once the
+/// request body has been sent to it. The response body is not bounded.
+const ENV_INBOUND_RESPONSE_HEADERS_TIMEOUT: &str =
+    "LINKERD2_PROXY_INBOUND_RESPONSE_HEADERS_TIMEOUT";
+
+/// Bounds the time the proxy waits to read a response from the application,
+/// once the request body has been sent. The timeout restarts with each read of
+/// the response, so streaming responses are only interrupted when the
+/// application stalls; idle connections are not timed out.
+const ENV_INBOUND_APP_READ_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_APP_READ_TIMEOUT";
+
+/// A comma-separated list of gRPC path prefixes (e.g. `/helloworld.Greeter/`)
+/// on which compressed messages are decompressed for peers that do not
+/// support their encoding.
+const ENV_INBOUND_GRPC_DECOMPRESS_ROUTES: &str = "LINKERD2_PROXY_INBOUND_GRPC_DECOMPRESS_ROUTES";
+
+/// If true, plaintext connections to the inbound mesh port are closed as soon
+/// as they are detected. Connections from the networks listed in
+/// `LINKERD2_PROXY_INBOUND_PLAINTEXT_EXEMPT_NETWORKS` (e.g. health-check
+/// probes) are exempt.
+const ENV_INBOUND_REJECT_PLAINTEXT: &str = "LINKERD2_PROXY_INBOUND_REJECT_PLAINTEXT";
+
+/// If true, inbound connections that appear to be HTTP/1 but whose first
+/// request head cannot be parsed are forwarded to the application opaquely,
+/// rather than failing in the proxy's HTTP server.
+const ENV_INBOUND_OPAQUE_ON_HTTP1_PARSE_FAILURE: &str =
+    "LINKERD2_PROXY_INBOUND_OPAQUE_ON_HTTP1_PARSE_FAILURE";
+const ENV_INBOUND_PLAINTEXT_EXEMPT_NETWORKS: &str =
+    "LINKERD2_PROXY_INBOUND_PLAINTEXT_EXEMPT_NETWORKS";
+
+/// A comma-separated list of identity suffixes. Connections to the inbound
+/// mesh port from clients with matching identities are closed unless the
+/// clients negotiate the transport header protocol via ALPN. Clients that may
+/// be proxies without transport header support must not be listed.
+const ENV_INBOUND_REJECT_ALPN_DOWNGRADE_IDENTITIES: &str =
+    "LINKERD2_PROXY_INBOUND_REJECT_ALPN_DOWNGRADE_IDENTITIES";
+
+/// If set, connections to the mesh port that are accepted before the proxy's
+/// identity is certified are held for up to this duration, until the identity
+/// is certified, rather than being closed immediately.
+const ENV_INBOUND_IDENTITY_PENDING_TIMEOUT: &str =
+    "LINKERD2_PROXY_INBOUND_IDENTITY_PENDING_TIMEOUT";
+
+/// If set, the TLS alerts that end connections to the mesh port are logged at
+/// debug, at most this many times per second.
+const ENV_INBOUND_TLS_ALERT_LOGS_PER_SECOND: &str =
+    "LINKERD2_PROXY_INBOUND_TLS_ALERT_LOGS_PER_SECOND";
+
+/// A comma-separated list of `port=class` pairs, e.g. `8080=api,9990=admin`.
+/// Inbound metrics are labeled with the class of the port on which traffic
+/// was received; when any classes are set, other ports are `unclassified`.
+const ENV_INBOUND_PORT_CLASSES: &str = "LINKERD2_PROXY_INBOUND_PORT_CLASSES";
+
+/// Configures how much detail is recorded in inbound HTTP metrics: `detailed`
+/// (the default) counts requests by endpoint and by route, `minimal` counts
+/// them only by endpoint, and `off` does not count them. Connection metrics
+/// are always recorded.
+///
+/// `LINKERD2_PROXY_INBOUND_PORTS_METRICS_GRANULARITY` overrides the
+/// granularity for specific ports as a comma-separated list of
+/// `port=granularity` pairs.
+const ENV_INBOUND_METRICS_GRANULARITY: &str = "LINKERD2_PROXY_INBOUND_METRICS_GRANULARITY";
+const ENV_INBOUND_PORTS_METRICS_GRANULARITY: &str =
+    "LINKERD2_PROXY_INBOUND_PORTS_METRICS_GRANULARITY";
+
+/// Configures how inbound TLS connections authenticate clients: `require`
+/// fails handshakes without a client certificate, `request` (the default)
+/// permits anonymous clients, and `none` does not ask for a certificate.
+///
+/// `LINKERD2_PROXY_INBOUND_TLS_CLIENT_AUTH_PORTS` overrides the mode for
+/// specific ports as a comma-separated list of `port=mode` pairs.
+const ENV_INBOUND_TLS_CLIENT_AUTH: &str = "LINKERD2_PROXY_INBOUND_TLS_CLIENT_AUTH";
+const ENV_INBOUND_TLS_CLIENT_AUTH_PORTS: &str = "LINKERD2_PROXY_INBOUND_TLS_CLIENT_AUTH_PORTS";
+
+/// Configures inbound ports that terminate TLS for clients outside of the
+/// mesh, e.g. for an ingress, as a comma-separated list of `port=name` pairs.
+/// A port may be listed with several names.
+///
+/// Each name must have a directory with that name in
+/// `LINKERD2_PROXY_INBOUND_TLS_TERMINATE_DIR` holding a certificate chain
+/// (`crt.pem`) and PKCS#8 key (`key.pem`). Clients are presented the port's
+/// certificate that is valid for the name they request via SNI or, otherwise,
+/// the port's first certificate.
+const ENV_INBOUND_TLS_TERMINATE_PORTS: &str = "LINKERD2_PROXY_INBOUND_TLS_TERMINATE_PORTS";
+const ENV_INBOUND_TLS_TERMINATE_DIR: &str = "LINKERD2_PROXY_INBOUND_TLS_TERMINATE_DIR";
+
+/// Comma-separated lists of `port=network` pairs, e.g.
+/// `8080=10.0.0.0/8,8080=192.168.0.0/16`. Connections to a port with allowed
+/// networks are closed unless the client is in one of them; connections from
+/// a port's denied networks are always closed.
+const ENV_INBOUND_SOURCE_ALLOW_NETWORKS: &str = "LINKERD2_PROXY_INBOUND_SOURCE_ALLOW_NETWORKS";
+const ENV_INBOUND_SOURCE_DENY_NETWORKS: &str = "LINKERD2_PROXY_INBOUND_SOURCE_DENY_NETWORKS";
+
+/// Bounds the size of each gRPC message decompressed by the proxy.
+const ENV_INBOUND_GRPC_MAX_DECOMPRESSED_MESSAGE_BYTES: &str =
+    "LINKERD2_PROXY_INBOUND_GRPC_MAX_DECOMPRESSED_MESSAGE_BYTES";
+
+/// Configures the route metadata label that lists the HTTP methods permitted
+/// on an inbound route (e.g. `GET,HEAD`). Requests with other methods fail
+/// with a 405 Method Not Allowed response.
+///
+/// `OPTIONS` requests, such as CORS preflight requests, are permitted on all
+/// routes unless `LINKERD2_PROXY_INBOUND_ROUTE_ALLOW_OPTIONS` is false.
+const ENV_INBOUND_ROUTE_ALLOWED_METHODS_LABEL: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_ALLOWED_METHODS_LABEL";
+const ENV_INBOUND_ROUTE_ALLOW_OPTIONS: &str = "LINKERD2_PROXY_INBOUND_ROUTE_ALLOW_OPTIONS";
+
+/// Configures the route metadata label that lists the fields redacted from an
+/// inbound route's JSON responses (e.g. `ssn,password`).
+///
+/// JSON responses on these routes are buffered so that they can be redacted.
+/// Responses with bodies larger than
+/// `LINKERD2_PROXY_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES` fail.
+const ENV_INBOUND_ROUTE_REDACT_FIELDS_LABEL: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_REDACT_FIELDS_LABEL";
+const ENV_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES";
+
+/// Configures the route metadata label that sets an inbound route's body size
+/// threshold, in bytes. Requests whose `Content-Length` exceeds the threshold
+/// are routed to the application's `LINKERD2_PROXY_INBOUND_LARGE_BODY_PORT`.
+///
+/// Requests that stream bodies without a `Content-Length` are only routed to
+/// the large body port if `LINKERD2_PROXY_INBOUND_ROUTE_UNKNOWN_BODY_SIZE_AS_LARGE`
+/// is true.
+const ENV_INBOUND_ROUTE_BODY_SIZE_THRESHOLD_LABEL: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_BODY_SIZE_THRESHOLD_LABEL";
+const ENV_INBOUND_LARGE_BODY_PORT: &str = "LINKERD2_PROXY_INBOUND_LARGE_BODY_PORT";
+const ENV_INBOUND_ROUTE_UNKNOWN_BODY_SIZE_AS_LARGE: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_UNKNOWN_BODY_SIZE_AS_LARGE";
+
+/// The maximum size, in bytes, of inbound request bodies. Requests whose
+/// bodies exceed it fail with a 413 Payload Too Large response. If
+/// unspecified, request bodies are not limited.
+const ENV_INBOUND_MAX_REQUEST_BODY_BYTES: &str = "LINKERD2_PROXY_INBOUND_MAX_REQUEST_BODY_BYTES";
+
+/// Configures the route metadata label that overrides
+/// `LINKERD2_PROXY_INBOUND_MAX_REQUEST_BODY_BYTES` for an inbound route.
+const ENV_INBOUND_ROUTE_MAX_REQUEST_BODY_BYTES_LABEL: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_MAX_REQUEST_BODY_BYTES_LABEL";
+
+/// Configures the route metadata label that protects an inbound route from
+/// replayed requests when it is set to `true`. Requests on these routes must
+/// carry a nonce in the `LINKERD2_PROXY_INBOUND_REPLAY_NONCE_HEADER` header
+/// (`x-request-nonce` by default); requests that reuse a nonce fail with a
+/// 409 Conflict response.
+///
+/// Each route remembers its nonces for
+/// `LINKERD2_PROXY_INBOUND_REPLAY_NONCE_WINDOW`. Requests from a client that
+/// has `LINKERD2_PROXY_INBOUND_REPLAY_MAX_CLIENT_NONCES` nonces remembered for
+/// the route fail with a 429 Too Many Requests response. While
+/// `LINKERD2_PROXY_INBOUND_REPLAY_MAX_NONCES` nonces are remembered for the
+/// route, requests with fresh nonces fail with a 503 Service Unavailable
+/// response.
+const ENV_INBOUND_ROUTE_REPLAY_PROTECTION_LABEL: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_REPLAY_PROTECTION_LABEL";
+const ENV_INBOUND_REPLAY_NONCE_HEADER: &str = "LINKERD2_PROXY_INBOUND_REPLAY_NONCE_HEADER";
+const ENV_INBOUND_REPLAY_NONCE_WINDOW: &str = "LINKERD2_PROXY_INBOUND_REPLAY_NONCE_WINDOW";
+const ENV_INBOUND_REPLAY_MAX_NONCES: &str = "LINKERD2_PROXY_INBOUND_REPLAY_MAX_NONCES";
+const ENV_INBOUND_REPLAY_MAX_CLIENT_NONCES: &str =
+    "LINKERD2_PROXY_INBOUND_REPLAY_MAX_CLIENT_NONCES";
+
 const ENV_INBOUND_DISPATCH_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_DISPATCH_TIMEOUT";
 const ENV_OUTBOUND_DISPATCH_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_DISPATCH_TIMEOUT";
 
@@ -102,6 +573,23 @@ const ENV_OUTBOUND_ACCEPT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_ACCEPT_KEEP
 const ENV_INBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_INBOUND_CONNECT_KEEPALIVE";
 const ENV_OUTBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_KEEPALIVE";
 
+/// The IP address on which inbound connections are forwarded to the
+/// application, e.g. `::1` for applications that only listen on the IPv6
+/// loopback interface. Defaults to `127.0.0.1`.
+pub const ENV_INBOUND_CONNECT_LOOPBACK: &str = "LINKERD2_PROXY_INBOUND_CONNECT_LOOPBACK";
+
+/// If true, inbound connections made over the loopback interface (i.e. whose
+/// source and destination addresses are both loopback addresses) are trusted:
+/// they need not have a client identity on ports that require one, and they
+/// are accepted regardless of their ports' source networks.
+pub const ENV_INBOUND_TRUST_LOOPBACK: &str = "LINKERD2_PROXY_INBOUND_TRUST_LOOPBACK";
+
+/// If true, inbound connections are closed as they are accepted unless they
+/// target one of the ports in `LINKERD2_PROXY_INBOUND_PORTS_ALLOW` (a
+/// comma-separated list of ports) or one of the proxy's own ports.
+pub const ENV_INBOUND_DEFAULT_DENY: &str = "LINKERD2_PROXY_INBOUND_DEFAULT_DENY";
+pub const ENV_INBOUND_PORTS_ALLOW: &str = "LINKERD2_PROXY_INBOUND_PORTS_ALLOW";
+
 pub const ENV_BUFFER_CAPACITY: &str = "LINKERD2_PROXY_BUFFER_CAPACITY";
 
 pub const ENV_INBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_INBOUND_ROUTER_MAX_IDLE_AGE";
@@ -114,6 +602,227 @@ const ENV_OUTBOUND_MAX_IDLE_CONNS_PER_ENDPOINT: &str =
 pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
 pub const ENV_OUTBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT";
 
+/// Configures client TLS for off-mesh destinations.
+///
+/// The value is a comma-separated list of `<server-name>=<network>` entries.
+/// Connections to an address in the network that are not provided an identity
+/// by discovery use TLS, with the server name used for SNI and to verify the
+/// server. A server name may be listed with several networks.
+///
+/// Each server name must have a directory with that name in
+/// `LINKERD2_PROXY_OUTBOUND_EXTERNAL_TLS_DIR` holding the trust anchors
+/// (`ca.pem`), client certificate chain (`crt.pem`), and PKCS#8 client key
+/// (`key.pem`) to use for the destination.
+pub const ENV_OUTBOUND_EXTERNAL_TLS_DESTINATIONS: &str =
+    "LINKERD2_PROXY_OUTBOUND_EXTERNAL_TLS_DESTINATIONS";
+pub const ENV_OUTBOUND_EXTERNAL_TLS_DIR: &str = "LINKERD2_PROXY_OUTBOUND_EXTERNAL_TLS_DIR";
+
+/// Configures the algorithm used by outbound balancers: one of `peak-ewma`
+/// (the default), `least-request`, `latency-ewma`, `round-robin`, or `random`.
+pub const ENV_OUTBOUND_BALANCE_ALGORITHM: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_ALGORITHM";
+
+/// Configures how long outbound balancers deprioritize an endpoint after it
+/// fails. Failed endpoints are not deprioritized if this is unset.
+pub const ENV_OUTBOUND_BALANCE_FAILURE_PENALTY: &str =
+    "LINKERD2_PROXY_OUTBOUND_BALANCE_FAILURE_PENALTY";
+
+/// Configures the probability, between 0 and 1, with which outbound HTTP
+/// balancers prefer an endpoint that is likely to have an idle connection over
+/// a less loaded endpoint. The probability is bounded at 0.9 so that load is
+/// still spread over all endpoints. Endpoints are not preferred for their
+/// connections if this is unset.
+pub const ENV_OUTBOUND_BALANCE_CONNECTION_AFFINITY: &str =
+    "LINKERD2_PROXY_OUTBOUND_BALANCE_CONNECTION_AFFINITY";
+
+/// Configures the route metadata label that holds a per-route timeout hint
+/// (e.g. `500ms`). A timeout set by the service profile takes precedence over
+/// the hint, and a hint of `0` disables the route's timeout.
+pub const ENV_OUTBOUND_ROUTE_TIMEOUT_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_TIMEOUT_LABEL";
+
+/// Configures the timeout applied to outbound routes that have neither a
+/// profile timeout nor a timeout hint. Routes have no timeout if this is unset.
+pub const ENV_OUTBOUND_ROUTE_TIMEOUT_DEFAULT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ROUTE_TIMEOUT_DEFAULT";
+
+/// Configures the route metadata label that holds a route's circuit breaker
+/// thresholds, as `<max_failures>/<open_timeout>` (e.g. `5/10s`). A route
+/// whose thresholds are `0` has no circuit breaker.
+pub const ENV_OUTBOUND_ROUTE_BREAKER_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_BREAKER_LABEL";
+
+/// Configures the circuit breaker thresholds of outbound routes whose metadata
+/// doesn't set thresholds, in the same format as route labels. Routes have no
+/// circuit breaker if this is unset.
+pub const ENV_OUTBOUND_ROUTE_BREAKER_DEFAULT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ROUTE_BREAKER_DEFAULT";
+
+/// Bounds the time clients may take to send outbound request bodies. If set to
+/// `total`, a request's body must be received within its route's timeout. If
+/// set to a duration, requests fail when no body data is received for that
+/// long. Requests whose bodies time out fail with a 408 Request Timeout.
+pub const ENV_OUTBOUND_REQUEST_BODY_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_REQUEST_BODY_TIMEOUT";
+
+/// If set, each outbound HTTP endpoint buffers up to this many requests while
+/// it is unavailable, rather than exerting backpressure on its balancer.
+pub const ENV_OUTBOUND_ENDPOINT_BUFFER_CAPACITY: &str =
+    "LINKERD2_PROXY_OUTBOUND_ENDPOINT_BUFFER_CAPACITY";
+
+/// Configures how long requests buffered for an unavailable outbound endpoint
+/// may wait before they fail.
+pub const ENV_OUTBOUND_ENDPOINT_BUFFER_MAX_WAIT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ENDPOINT_BUFFER_MAX_WAIT";
+
+/// If set, limits the number of outbound logical stacks that may await
+/// discovery at once. Additional builds wait for a slot, up to
+/// `LINKERD2_PROXY_OUTBOUND_MAX_PENDING_BUILDS`, and all builds fail if they
+/// do not complete within `LINKERD2_PROXY_OUTBOUND_BUILD_TIMEOUT`.
+pub const ENV_OUTBOUND_MAX_CONCURRENT_BUILDS: &str =
+    "LINKERD2_PROXY_OUTBOUND_MAX_CONCURRENT_BUILDS";
+pub const ENV_OUTBOUND_MAX_PENDING_BUILDS: &str = "LINKERD2_PROXY_OUTBOUND_MAX_PENDING_BUILDS";
+pub const ENV_OUTBOUND_BUILD_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_BUILD_TIMEOUT";
+
+/// If set, a `label=value` endpoint label that identifies canary endpoints.
+/// Each service with canary endpoints has a share of its HTTP requests routed
+/// to them, as configured by `LINKERD2_PROXY_OUTBOUND_CANARY_PERCENT`.
+pub const ENV_OUTBOUND_CANARY_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_CANARY_LABEL";
+
+/// The percentage, from 0 (the default) to 100, of requests routed to canary
+/// endpoints.
+pub const ENV_OUTBOUND_CANARY_PERCENT: &str = "LINKERD2_PROXY_OUTBOUND_CANARY_PERCENT";
+
+/// If set, requests are assigned to canary endpoints by the value of this
+/// header rather than at random, so that requests with the same value are
+/// consistently routed to the same endpoints. Requests without the header are
+/// routed to stable endpoints.
+pub const ENV_OUTBOUND_CANARY_HEADER: &str = "LINKERD2_PROXY_OUTBOUND_CANARY_HEADER";
+
+/// If set, the maximum number of each service's endpoints to which the
+/// outbound proxy connects. Each proxy selects a stable subset of endpoints,
+/// seeded by its hostname (or, without one, its identity), so that connections
+/// are spread across all of a service's endpoints by the fleet. Subsets have at
+/// least three endpoints.
+pub const ENV_OUTBOUND_ENDPOINT_SUBSET_SIZE: &str = "LINKERD2_PROXY_OUTBOUND_ENDPOINT_SUBSET_SIZE";
+
+/// A comma-separated list of `logical=backup` pairs of `name:port` addresses.
+/// While a logical service's balancer has no available endpoints, its HTTP
+/// traffic is routed to its backup service.
+pub const ENV_OUTBOUND_FAILOVER_BACKUPS: &str = "LINKERD2_PROXY_OUTBOUND_FAILOVER_BACKUPS";
+
+/// The amount of time that a logical service's balancer may have no available
+/// endpoints before its traffic fails over to its backup service. Must be less
+/// than the outbound dispatch timeout, after which the balancer fails fast.
+pub const ENV_OUTBOUND_FAILOVER_MAX_UNAVAILABLE: &str =
+    "LINKERD2_PROXY_OUTBOUND_FAILOVER_MAX_UNAVAILABLE";
+
+/// The minimum amount of time that traffic remains routed to a backup service
+/// before it returns to a recovered logical service.
+pub const ENV_OUTBOUND_FAILOVER_MIN_DURATION: &str =
+    "LINKERD2_PROXY_OUTBOUND_FAILOVER_MIN_DURATION";
+
+/// A comma-separated list of `logical=candidate` pairs of `name:port`
+/// addresses. A logical service's HTTP requests without bodies are mirrored to
+/// its candidate service, whose responses are compared against the logical
+/// service's.
+pub const ENV_OUTBOUND_MIRROR_CANDIDATES: &str = "LINKERD2_PROXY_OUTBOUND_MIRROR_CANDIDATES";
+
+/// If set, only requests on routes whose metadata sets this label to `true`
+/// are mirrored.
+pub const ENV_OUTBOUND_MIRROR_ROUTE_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_MIRROR_ROUTE_LABEL";
+
+/// A comma-separated list of response headers whose values are compared
+/// between mirrored and primary responses.
+pub const ENV_OUTBOUND_MIRROR_COMPARE_HEADERS: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_COMPARE_HEADERS";
+
+/// The maximum number of response body bytes hashed for comparison.
+pub const ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_MAX_BODY_BYTES";
+
+/// The maximum number of mirrored requests in flight to each candidate.
+pub const ENV_OUTBOUND_MIRROR_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MIRROR_MAX_IN_FLIGHT";
+
+/// A comma-separated list of DNS suffixes. If set, requests are only mirrored
+/// to candidates whose names match one of these suffixes (e.g. the names of
+/// services mirrored from another cluster).
+pub const ENV_OUTBOUND_MIRROR_ALLOWED_SUFFIXES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_ALLOWED_SUFFIXES";
+
+/// The percentage of eligible requests that are mirrored. If unset, all
+/// eligible requests are mirrored.
+pub const ENV_OUTBOUND_MIRROR_SAMPLE_PERCENT: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_SAMPLE_PERCENT";
+
+/// The maximum `content-length` of request bodies that are copied to
+/// candidates. If unset, only requests without bodies are mirrored.
+pub const ENV_OUTBOUND_MIRROR_MAX_REQUEST_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_MAX_REQUEST_BODY_BYTES";
+
+/// The amount of time a candidate has to respond to a mirrored request.
+pub const ENV_OUTBOUND_MIRROR_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_MIRROR_TIMEOUT";
+
+/// If set, concurrent, identical GET and HEAD requests to a logical service
+/// that are dispatched within this window of each other are coalesced: only
+/// the first is dispatched, and its response is shared with the others.
+pub const ENV_OUTBOUND_COALESCE_WINDOW: &str = "LINKERD2_PROXY_OUTBOUND_COALESCE_WINDOW";
+
+/// A comma-separated list of request headers that must match for requests to
+/// be coalesced. Credential headers must always match.
+pub const ENV_OUTBOUND_COALESCE_VARY_HEADERS: &str =
+    "LINKERD2_PROXY_OUTBOUND_COALESCE_VARY_HEADERS";
+
+/// The maximum number of requests to each logical service that may be awaited
+/// by coalesced requests at once.
+pub const ENV_OUTBOUND_COALESCE_MAX_PENDING: &str = "LINKERD2_PROXY_OUTBOUND_COALESCE_MAX_PENDING";
+
+/// The maximum number of coalesced requests that may wait on each request.
+pub const ENV_OUTBOUND_COALESCE_MAX_WAITERS: &str = "LINKERD2_PROXY_OUTBOUND_COALESCE_MAX_WAITERS";
+
+/// The maximum size of response bodies that are shared with coalesced
+/// requests.
+pub const ENV_OUTBOUND_COALESCE_MAX_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_COALESCE_MAX_BODY_BYTES";
+
+/// Configures the route metadata label that holds each outbound route's
+/// priority, from 0 to 255. If set, requests waiting for a service are
+/// dispatched in order of their routes' priorities. Routes without a priority
+/// have priority 0.
+pub const ENV_OUTBOUND_ROUTE_PRIORITY_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_PRIORITY_LABEL";
+
+/// Configures how long an outbound request may wait before its priority is
+/// increased by one, so that low-priority requests are eventually dispatched.
+pub const ENV_OUTBOUND_ROUTE_PRIORITY_AGING: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_PRIORITY_AGING";
+
+/// If set, a fraction of the requests to each outbound logical service fail
+/// with a 503 Service Unavailable, without being dispatched, while the p99
+/// latency of the service's responses exceeds this SLO. The fraction is
+/// proportional to the excess latency, and is adjusted gradually so that
+/// shedding does not oscillate.
+pub const ENV_OUTBOUND_LOAD_SHED_LATENCY_SLO: &str =
+    "LINKERD2_PROXY_OUTBOUND_LOAD_SHED_LATENCY_SLO";
+
+/// How often each outbound logical service's p99 latency is evaluated to
+/// adjust the fraction of its requests that are shed.
+pub const ENV_OUTBOUND_LOAD_SHED_INTERVAL: &str = "LINKERD2_PROXY_OUTBOUND_LOAD_SHED_INTERVAL";
+
+/// The largest fraction, from 0 to 1, of requests to an outbound logical
+/// service that may be shed.
+pub const ENV_OUTBOUND_LOAD_SHED_MAX_FRACTION: &str =
+    "LINKERD2_PROXY_OUTBOUND_LOAD_SHED_MAX_FRACTION";
+
+/// Configures the route metadata label that marks outbound routes as critical.
+/// Requests on routes that set it to `true` are never shed.
+pub const ENV_OUTBOUND_LOAD_SHED_CRITICAL_LABEL: &str =
+    "LINKERD2_PROXY_OUTBOUND_LOAD_SHED_CRITICAL_LABEL";
+
+/// If true, connections are established to a service's endpoints as soon as
+/// its profile is received, so that its first request need not wait for them.
+pub const ENV_OUTBOUND_PREWARM_ENDPOINTS: &str = "LINKERD2_PROXY_OUTBOUND_PREWARM_ENDPOINTS";
+
+/// If true, outbound tap events include a `selection` label on their
+/// destination that describes why the request's endpoint was selected. The
+/// endpoints selected for opaque TCP connections are logged as they connect.
+pub const ENV_OUTBOUND_TAP_ENDPOINT_SELECTION: &str =
+    "LINKERD2_PROXY_OUTBOUND_TAP_ENDPOINT_SELECTION";
+
 pub const ENV_TRACE_ATTRIBUTES_PATH: &str = "LINKERD2_PROXY_TRACE_ATTRIBUTES_PATH";
 
 /// Constrains which destination names may be used for profile/route discovery.
@@ -150,6 +859,22 @@ pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
 pub const ENV_INBOUND_PORTS_REQUIRE_IDENTITY: &str =
     "LINKERD2_PROXY_INBOUND_PORTS_REQUIRE_IDENTITY";
 
+/// Plaintext connections to these ports are closed instead of being forwarded
+/// to the application.
+pub const ENV_INBOUND_PORTS_REQUIRE_TLS: &str = "LINKERD2_PROXY_INBOUND_PORTS_REQUIRE_TLS";
+
+/// Forwarded TCP connections to these ports begin with a PROXY protocol v2
+/// header describing the client's address. Applications on these ports must
+/// expect the header.
+pub const ENV_INBOUND_PORTS_PROXY_PROTOCOL: &str = "LINKERD2_PROXY_INBOUND_PORTS_PROXY_PROTOCOL";
+
+/// Routes TLS connections on opaque inbound ports to other local ports by the
+/// server name of their ClientHello, as a comma-separated list of
+/// `<port>:<server-name>=<target-port>` entries. TLS is not terminated, and
+/// connections without a matching server name are forwarded to their original
+/// port.
+pub const ENV_INBOUND_PORTS_SNI_ROUTES: &str = "LINKERD2_PROXY_INBOUND_PORTS_SNI_ROUTES";
+
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
 pub const ENV_IDENTITY_TRUST_ANCHORS: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS";
@@ -166,11 +891,59 @@ pub const ENV_HOSTNAME: &str = "HOSTNAME";
 
 pub const ENV_TRACE_COLLECTOR_SVC_BASE: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_SVC";
 
+/// The protocol with which spans are exported to the trace collector, either
+/// `opencensus` or `otlp`.
+///
+/// If unspecified, spans are exported via OpenCensus.
+pub const ENV_TRACE_COLLECTOR_PROTOCOL: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_PROTOCOL";
+
+/// The fraction, from 0 (the default) to 1, of requests without an upstream
+/// sampling decision that are traced when a trace collector is configured. The
+/// decision is made as requests enter the proxy, inbound or outbound, and is
+/// propagated downstream with the trace context. Sampled requests without a
+/// trace context start a new trace. Upstream sampling decisions are always
+/// honored.
+pub const ENV_TRACE_COLLECTOR_SAMPLE_RATIO: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_SAMPLE_RATIO";
+
+/// Names a header that forces requests entering the proxy to be traced when a
+/// trace collector is configured, regardless of the sample ratio or any
+/// upstream sampling decision.
+pub const ENV_TRACE_FORCE_SAMPLE_HEADER: &str = "LINKERD2_PROXY_TRACE_FORCE_SAMPLE_HEADER";
+
 pub const ENV_DESTINATION_CONTEXT: &str = "LINKERD2_PROXY_DESTINATION_CONTEXT";
 pub const ENV_DESTINATION_PROFILE_INITIAL_TIMEOUT: &str =
     "LINKERD2_PROXY_DESTINATION_PROFILE_INITIAL_TIMEOUT";
 
 pub const ENV_TAP_SVC_NAME: &str = "LINKERD2_PROXY_TAP_SVC_NAME";
+
+/// If set, limits the number of tap subscriptions that may be active at once.
+pub const ENV_TAP_MAX_SUBSCRIPTIONS: &str = "LINKERD2_PROXY_TAP_MAX_SUBSCRIPTIONS";
+
+/// If set, background tasks that can be rebuilt, like the trace collector's
+/// span exporter, are restarted after this delay when they stop. Otherwise,
+/// stopped tasks are only logged and counted.
+pub const ENV_WATCHDOG_RESTART_DELAY: &str = "LINKERD2_PROXY_WATCHDOG_RESTART_DELAY";
+
+/// If set, background tasks that are not polled within this timeout, e.g.
+/// because they block their thread, are logged and counted as hung.
+pub const ENV_WATCHDOG_HEARTBEAT_TIMEOUT: &str = "LINKERD2_PROXY_WATCHDOG_HEARTBEAT_TIMEOUT";
+
+/// If true, each phase of the proxy's shutdown (draining, each listener
+/// stopping, the number of remaining connections halving, and the proxy
+/// being fully drained) is logged as a structured event.
+const ENV_SHUTDOWN_EVENTS_LOG: &str = "LINKERD2_PROXY_SHUTDOWN_EVENTS_LOG";
+
+/// If true, the time at which each phase of the proxy's shutdown occurred is
+/// reported in the `process_shutdown_phase_timestamp_seconds` metric.
+const ENV_SHUTDOWN_EVENTS_METRICS: &str = "LINKERD2_PROXY_SHUTDOWN_EVENTS_METRICS";
+
+/// Bounds how long the inbound and outbound proxies' connections may remain
+/// open once the proxy begins draining. Connections that are still open after
+/// this grace period are closed forcibly and counted in the
+/// `process_shutdown_force_closed_connections_total` metric. If unset,
+/// connections may remain open until they complete.
+const ENV_SHUTDOWN_GRACE_PERIOD: &str = "LINKERD2_PROXY_SHUTDOWN_GRACE_PERIOD";
+
 const ENV_RESOLV_CONF: &str = "LINKERD2_PROXY_RESOLV_CONF";
 
 /// Configures a minimum value for the TTL of DNS lookups.
@@ -189,15 +962,38 @@ const ENV_INITIAL_STREAM_WINDOW_SIZE: &str = "LINKERD2_PROXY_HTTP2_INITIAL_STREA
 const ENV_INITIAL_CONNECTION_WINDOW_SIZE: &str =
     "LINKERD2_PROXY_HTTP2_INITIAL_CONNECTION_WINDOW_SIZE";
 
+/// Bounds applied to the SETTINGS frame sent by inbound HTTP/2 clients.
+///
+/// Header table, frame, and header list sizes above these values are clamped.
+/// Connections advertising an initial window size below the minimum, or
+/// illegal settings, are refused with a GOAWAY.
+const ENV_INBOUND_HTTP2_MAX_HEADER_TABLE_SIZE: &str =
+    "LINKERD2_PROXY_INBOUND_HTTP2_MAX_HEADER_TABLE_SIZE";
+const ENV_INBOUND_HTTP2_MAX_FRAME_SIZE: &str = "LINKERD2_PROXY_INBOUND_HTTP2_MAX_FRAME_SIZE";
+const ENV_INBOUND_HTTP2_MAX_HEADER_LIST_SIZE: &str =
+    "LINKERD2_PROXY_INBOUND_HTTP2_MAX_HEADER_LIST_SIZE";
+const ENV_INBOUND_HTTP2_MIN_INITIAL_WINDOW_SIZE: &str =
+    "LINKERD2_PROXY_INBOUND_HTTP2_MIN_INITIAL_WINDOW_SIZE";
+
+/// Limits the number of HTTP/2 streams that may be active at once across all
+/// inbound connections. Streams in excess of the limit are refused with
+/// REFUSED_STREAM. By default, streams are not limited across connections.
+const ENV_INBOUND_HTTP2_MAX_CONCURRENT_STREAMS: &str =
+    "LINKERD2_PROXY_INBOUND_HTTP2_MAX_CONCURRENT_STREAMS";
+
 // Default values for various configuration fields
 const DEFAULT_OUTBOUND_LISTEN_ADDR: &str = "127.0.0.1:4140";
 pub const DEFAULT_INBOUND_LISTEN_ADDR: &str = "0.0.0.0:4143";
 pub const DEFAULT_CONTROL_LISTEN_ADDR: &str = "0.0.0.0:4190";
 const DEFAULT_ADMIN_LISTEN_ADDR: &str = "127.0.0.1:4191";
 const DEFAULT_METRICS_RETAIN_IDLE: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_METRICS_PUSH_JOB: &str = "linkerd-proxy";
+const DEFAULT_METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_METRICS_PUSH_TIMEOUT: Duration = Duration::from_secs(5);
 const DEFAULT_INBOUND_DISPATCH_TIMEOUT: Duration = Duration::from_secs(1);
 const DEFAULT_INBOUND_DETECT_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_INBOUND_CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
+const DEFAULT_INBOUND_CONNECT_LOOPBACK: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
 const DEFAULT_INBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff {
     min: Duration::from_millis(100),
     max: Duration::from_millis(500),
@@ -206,6 +1002,20 @@ const DEFAULT_INBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff {
 const DEFAULT_OUTBOUND_DISPATCH_TIMEOUT: Duration = Duration::from_secs(3);
 const DEFAULT_OUTBOUND_DETECT_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_OUTBOUND_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_ENDPOINT_BUFFER_MAX_WAIT: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_MAX_PENDING_BUILDS: usize = 1_000;
+const DEFAULT_OUTBOUND_BUILD_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_FAILOVER_MAX_UNAVAILABLE: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_FAILOVER_MIN_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_OUTBOUND_MIRROR_MAX_IN_FLIGHT: usize = 100;
+const DEFAULT_OUTBOUND_MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_COALESCE_MAX_PENDING: usize = 1_000;
+const DEFAULT_OUTBOUND_COALESCE_MAX_WAITERS: usize = 100;
+const DEFAULT_OUTBOUND_COALESCE_MAX_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_OUTBOUND_ROUTE_PRIORITY_AGING: Duration = Duration::from_millis(100);
+const DEFAULT_OUTBOUND_LOAD_SHED_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_OUTBOUND_LOAD_SHED_MAX_FRACTION: f64 = 0.5;
 const DEFAULT_OUTBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff {
     min: Duration::from_millis(100),
     max: Duration::from_millis(500),
@@ -243,6 +1053,21 @@ const DEFAULT_OUTBOUND_MAX_IDLE_CONNS_PER_ENDPOINT: usize = std::usize::MAX;
 const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = 100_000;
 const DEFAULT_OUTBOUND_MAX_IN_FLIGHT: usize = 100_000;
 
+const DEFAULT_CONNECTION_LOG_MAX_PER_SECOND: u64 = 100;
+
+const DEFAULT_CLOSE_DELIMITED_BUFFER_MAX_BYTES: usize = 64 * 1024;
+const DEFAULT_ACCEPT_MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// gRPC implementations limit received messages to 4MB by default.
+const DEFAULT_INBOUND_GRPC_MAX_DECOMPRESSED_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+const DEFAULT_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+const DEFAULT_INBOUND_REPLAY_NONCE_HEADER: &str = "x-request-nonce";
+const DEFAULT_INBOUND_REPLAY_NONCE_WINDOW: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_INBOUND_REPLAY_MAX_NONCES: usize = 100_000;
+const DEFAULT_INBOUND_REPLAY_MAX_CLIENT_NONCES: usize = 1_000;
+
 // This value should be large enough to admit requests without exerting
 // backpressure so that requests implicitly buffer in the executor; but it
 // should be small enough that callers can't force the proxy to consume an
@@ -312,6 +1137,11 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
     let outbound_max_in_flight = parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT, parse_number);
 
     let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
+    let metrics_push_gateway_addr = parse(strings, ENV_METRICS_PUSH_GATEWAY_ADDR, parse_addr);
+    let metrics_push_job = parse(strings, ENV_METRICS_PUSH_JOB, parse_push_label);
+    let metrics_push_instance = parse(strings, ENV_METRICS_PUSH_INSTANCE, parse_push_label);
+    let metrics_push_interval = parse(strings, ENV_METRICS_PUSH_INTERVAL, parse_duration);
+    let metrics_push_timeout = parse(strings, ENV_METRICS_PUSH_TIMEOUT, parse_duration);
 
     // DNS
 
@@ -343,6 +1173,18 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         parse_control_addr(strings, ENV_TRACE_COLLECTOR_SVC_BASE)
     };
 
+    let trace_collector_protocol = parse(
+        strings,
+        ENV_TRACE_COLLECTOR_PROTOCOL,
+        parse_trace_collector_protocol,
+    );
+
+    // Sampling is validated even when no collector is configured, so that a
+    // misconfiguration is not silently ignored.
+    let trace_sample_ratio = parse(strings, ENV_TRACE_COLLECTOR_SAMPLE_RATIO, parse_probability)?;
+    let trace_force_sample_header =
+        parse(strings, ENV_TRACE_FORCE_SAMPLE_HEADER, parse_header_name)?;
+
     let dst_token = strings.get(ENV_DESTINATION_CONTEXT);
 
     let gateway_suffixes = parse(strings, ENV_INBOUND_GATEWAY_SUFFIXES, parse_dns_suffixes);
@@ -362,6 +1204,23 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
     let initial_connection_window_size =
         parse(strings, ENV_INITIAL_CONNECTION_WINDOW_SIZE, parse_number);
 
+    let inbound_h2_max_header_table_size = parse(
+        strings,
+        ENV_INBOUND_HTTP2_MAX_HEADER_TABLE_SIZE,
+        parse_number,
+    );
+    let inbound_h2_max_frame_size = parse(strings, ENV_INBOUND_HTTP2_MAX_FRAME_SIZE, parse_number);
+    let inbound_h2_max_header_list_size = parse(
+        strings,
+        ENV_INBOUND_HTTP2_MAX_HEADER_LIST_SIZE,
+        parse_number,
+    );
+    let inbound_h2_min_initial_window_size = parse(
+        strings,
+        ENV_INBOUND_HTTP2_MIN_INITIAL_WINDOW_SIZE,
+        parse_number,
+    );
+
     let tap = parse_tap_config(strings, id_disabled);
 
     let h2_settings = h2::Settings {
@@ -380,6 +1239,45 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         .unwrap_or_else(|| parse_dns_suffixes(DEFAULT_DESTINATION_PROFILE_SUFFIXES).unwrap());
     let dst_profile_networks = dst_profile_networks?.unwrap_or_default();
 
+    let connection_log = if parse(strings, ENV_CONNECTION_LOG, parse_bool)?.unwrap_or(false) {
+        let max = parse(strings, ENV_CONNECTION_LOG_MAX_PER_SECOND, parse_number)?
+            .unwrap_or(DEFAULT_CONNECTION_LOG_MAX_PER_SECOND);
+        let format = parse(
+            strings,
+            ENV_CONNECTION_LOG_FORMAT,
+            parse_connection_log_format,
+        )?
+        .unwrap_or(connection_log::Format::Plain);
+        ConnectionLog::new(max, format)
+    } else {
+        ConnectionLog::disabled()
+    };
+
+    let dscp = DscpMarking::new(
+        parse(strings, ENV_DSCP, parse_dscp)?,
+        parse(strings, ENV_DSCP_PORTS, parse_dscp_ports)?.unwrap_or_default(),
+    );
+
+    let echo_trace_id = parse(strings, ENV_ERROR_RESPONSE_TRACE_ID, parse_bool)?.unwrap_or(false);
+
+    let via = parse(strings, ENV_VIA_PSEUDONYM, parse_via)?;
+
+    let tcp_splice = parse(strings, ENV_TCP_SPLICE, parse_bool)?.unwrap_or(false);
+
+    let memory_pressure = match parse(strings, ENV_ACCEPT_MEMORY_PAUSE_BYTES, parse_number)? {
+        Some(pause_bytes) => {
+            let resume_bytes = parse(strings, ENV_ACCEPT_MEMORY_RESUME_BYTES, parse_number)?
+                .unwrap_or(pause_bytes / 10 * 9);
+            let interval = parse(strings, ENV_ACCEPT_MEMORY_CHECK_INTERVAL, parse_duration)?
+                .unwrap_or(DEFAULT_ACCEPT_MEMORY_CHECK_INTERVAL);
+            MemoryPressure::system(pause_bytes, resume_bytes, interval)
+        }
+        None => MemoryPressure::default(),
+    };
+
+    let close_delimited_max = parse(strings, ENV_CLOSE_DELIMITED_BUFFER_MAX_BYTES, parse_number)?
+        .unwrap_or(DEFAULT_CLOSE_DELIMITED_BUFFER_MAX_BYTES);
+
     let outbound = {
         let ingress_mode = parse(strings, ENV_INGRESS_MODE, parse_bool)?.unwrap_or(false);
 
@@ -391,6 +1289,7 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let server = ServerConfig {
             addr,
             keepalive,
+            dscp: dscp.clone(),
             h2_settings,
         };
         let cache_max_idle_age =
@@ -400,6 +1299,7 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let keepalive = Keepalive(outbound_connect_keepalive?);
         let connect = ConnectConfig {
             keepalive,
+            dscp: dscp.clone(),
             timeout: outbound_connect_timeout?.unwrap_or(DEFAULT_OUTBOUND_CONNECT_TIMEOUT),
             backoff: parse_backoff(
                 strings,
@@ -411,6 +1311,16 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 max_idle,
                 idle_timeout: cache_max_idle_age,
             },
+            close_delimited: parse(strings, ENV_OUTBOUND_CLOSE_DELIMITED_RESPONSES, |s| {
+                parse_close_delimited(s, close_delimited_max)
+            })?
+            .unwrap_or_default(),
+            response_read_ahead_bytes: parse(
+                strings,
+                ENV_OUTBOUND_RESPONSE_READ_AHEAD_BYTES,
+                parse_number,
+            )?
+            .unwrap_or_default(),
         };
 
         let detect_protocol_timeout =
@@ -418,8 +1328,243 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let dispatch_timeout =
             outbound_dispatch_timeout?.unwrap_or(DEFAULT_OUTBOUND_DISPATCH_TIMEOUT);
 
+        let external_tls = parse_external_tls_config(strings)?;
+        let balance_algorithm = parse(
+            strings,
+            ENV_OUTBOUND_BALANCE_ALGORITHM,
+            parse_balance_algorithm,
+        )?
+        .unwrap_or_default();
+        let balance_failure_penalty = parse(
+            strings,
+            ENV_OUTBOUND_BALANCE_FAILURE_PENALTY,
+            parse_duration,
+        )?;
+        let balance_connection_affinity = parse(
+            strings,
+            ENV_OUTBOUND_BALANCE_CONNECTION_AFFINITY,
+            parse_probability,
+        )?;
+        let route_timeouts = outbound::http::RouteTimeouts {
+            label: strings
+                .get(ENV_OUTBOUND_ROUTE_TIMEOUT_LABEL)?
+                .filter(|l| !l.is_empty()),
+            default: parse(strings, ENV_OUTBOUND_ROUTE_TIMEOUT_DEFAULT, parse_duration)?,
+        };
+        let route_breakers = outbound::http::RouteBreakers {
+            label: strings
+                .get(ENV_OUTBOUND_ROUTE_BREAKER_LABEL)?
+                .filter(|l| !l.is_empty()),
+            default: parse(
+                strings,
+                ENV_OUTBOUND_ROUTE_BREAKER_DEFAULT,
+                parse_breaker_thresholds,
+            )?,
+        };
+        let request_body_timeout = parse(
+            strings,
+            ENV_OUTBOUND_REQUEST_BODY_TIMEOUT,
+            parse_request_body_timeout,
+        )?
+        .unwrap_or_default();
+        let endpoint_buffer = match parse(
+            strings,
+            ENV_OUTBOUND_ENDPOINT_BUFFER_CAPACITY,
+            parse_number::<usize>,
+        )? {
+            Some(capacity) if capacity > 0 => Some(outbound::http::EndpointBuffer {
+                capacity,
+                max_wait: parse(
+                    strings,
+                    ENV_OUTBOUND_ENDPOINT_BUFFER_MAX_WAIT,
+                    parse_duration,
+                )?
+                .unwrap_or(DEFAULT_OUTBOUND_ENDPOINT_BUFFER_MAX_WAIT),
+            }),
+            _ => None,
+        };
+        let build_limit = match parse(
+            strings,
+            ENV_OUTBOUND_MAX_CONCURRENT_BUILDS,
+            parse_number::<usize>,
+        )? {
+            Some(max_concurrent) if max_concurrent > 0 => Some(outbound::BuildLimit {
+                max_concurrent,
+                max_pending: parse(strings, ENV_OUTBOUND_MAX_PENDING_BUILDS, parse_number)?
+                    .unwrap_or(DEFAULT_OUTBOUND_MAX_PENDING_BUILDS),
+                timeout: parse(strings, ENV_OUTBOUND_BUILD_TIMEOUT, parse_duration)?
+                    .unwrap_or(DEFAULT_OUTBOUND_BUILD_TIMEOUT),
+            }),
+            _ => None,
+        };
+        let canary = match parse(strings, ENV_OUTBOUND_CANARY_LABEL, parse_label)? {
+            Some((label, value)) => {
+                let percent =
+                    parse(strings, ENV_OUTBOUND_CANARY_PERCENT, parse_percent)?.unwrap_or(0);
+                let selector = match parse(strings, ENV_OUTBOUND_CANARY_HEADER, parse_header_name)?
+                {
+                    Some(name) => outbound::http::CanarySelector::Header { name, percent },
+                    None => outbound::http::CanarySelector::Percent(percent),
+                };
+                Some(outbound::http::CanarySplit {
+                    label,
+                    value,
+                    selector,
+                })
+            }
+            None => None,
+        };
+        let endpoint_subsetting = match parse(
+            strings,
+            ENV_OUTBOUND_ENDPOINT_SUBSET_SIZE,
+            parse_number::<usize>,
+        )? {
+            Some(size) => {
+                // Proxies without a hostname are seeded by their identity, so
+                // that their subsets do not change when they restart.
+                let id = match strings.get(ENV_HOSTNAME)? {
+                    Some(hostname) => Some(hostname),
+                    None => strings.get(ENV_IDENTITY_IDENTITY_LOCAL_NAME)?,
+                };
+                let id = id.unwrap_or_default();
+                Some(outbound::EndpointSubsetting::new(size, &id))
+            }
+            None => None,
+        };
+        let failover = outbound::http::FailoverConfig {
+            backups: parse(strings, ENV_OUTBOUND_FAILOVER_BACKUPS, parse_addr_pairs)?
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            max_unavailable: parse(
+                strings,
+                ENV_OUTBOUND_FAILOVER_MAX_UNAVAILABLE,
+                parse_duration,
+            )?
+            .unwrap_or(DEFAULT_OUTBOUND_FAILOVER_MAX_UNAVAILABLE),
+            min_backup_duration: parse(
+                strings,
+                ENV_OUTBOUND_FAILOVER_MIN_DURATION,
+                parse_duration,
+            )?
+            .unwrap_or(DEFAULT_OUTBOUND_FAILOVER_MIN_DURATION),
+        };
+        // Traffic must fail over before the balancer fails fast.
+        if !failover.backups.is_empty() && failover.max_unavailable >= dispatch_timeout {
+            error!(
+                "{} must be less than {}",
+                ENV_OUTBOUND_FAILOVER_MAX_UNAVAILABLE, ENV_OUTBOUND_DISPATCH_TIMEOUT
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+        let mirror = outbound::http::MirrorConfig {
+            candidates: parse(strings, ENV_OUTBOUND_MIRROR_CANDIDATES, parse_addr_pairs)?
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            allowed_candidates: parse(
+                strings,
+                ENV_OUTBOUND_MIRROR_ALLOWED_SUFFIXES,
+                parse_dns_suffixes,
+            )?
+            .map(NameMatch::new),
+            sample_percent: parse(strings, ENV_OUTBOUND_MIRROR_SAMPLE_PERCENT, parse_percent)?,
+            max_request_body_bytes: parse(
+                strings,
+                ENV_OUTBOUND_MIRROR_MAX_REQUEST_BODY_BYTES,
+                parse_number,
+            )?
+            .unwrap_or_default(),
+            route_label: strings
+                .get(ENV_OUTBOUND_MIRROR_ROUTE_LABEL)?
+                .filter(|l| !l.is_empty()),
+            compare_headers: parse(
+                strings,
+                ENV_OUTBOUND_MIRROR_COMPARE_HEADERS,
+                parse_header_names,
+            )?
+            .unwrap_or_default(),
+            max_body_bytes: parse(strings, ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES, parse_number)?
+                .unwrap_or(DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES),
+            max_in_flight: parse(strings, ENV_OUTBOUND_MIRROR_MAX_IN_FLIGHT, parse_number)?
+                .unwrap_or(DEFAULT_OUTBOUND_MIRROR_MAX_IN_FLIGHT),
+            timeout: parse(strings, ENV_OUTBOUND_MIRROR_TIMEOUT, parse_duration)?
+                .unwrap_or(DEFAULT_OUTBOUND_MIRROR_TIMEOUT),
+        };
+        let coalesce = match parse(strings, ENV_OUTBOUND_COALESCE_WINDOW, parse_duration)? {
+            Some(window) => Some(outbound::http::CoalesceConfig {
+                window,
+                vary_headers: parse(
+                    strings,
+                    ENV_OUTBOUND_COALESCE_VARY_HEADERS,
+                    parse_header_names,
+                )?
+                .unwrap_or_default(),
+                max_pending: parse(strings, ENV_OUTBOUND_COALESCE_MAX_PENDING, parse_number)?
+                    .unwrap_or(DEFAULT_OUTBOUND_COALESCE_MAX_PENDING),
+                max_waiters: parse(strings, ENV_OUTBOUND_COALESCE_MAX_WAITERS, parse_number)?
+                    .unwrap_or(DEFAULT_OUTBOUND_COALESCE_MAX_WAITERS),
+                max_body_bytes: parse(strings, ENV_OUTBOUND_COALESCE_MAX_BODY_BYTES, parse_number)?
+                    .unwrap_or(DEFAULT_OUTBOUND_COALESCE_MAX_BODY_BYTES),
+            }),
+            None => None,
+        };
+        let route_priority = match strings
+            .get(ENV_OUTBOUND_ROUTE_PRIORITY_LABEL)?
+            .filter(|l| !l.is_empty())
+        {
+            Some(label) => Some(outbound::http::RoutePriority {
+                label,
+                aging: parse(strings, ENV_OUTBOUND_ROUTE_PRIORITY_AGING, parse_duration)?
+                    .unwrap_or(DEFAULT_OUTBOUND_ROUTE_PRIORITY_AGING),
+            }),
+            None => None,
+        };
+
+        let load_shed = match parse(strings, ENV_OUTBOUND_LOAD_SHED_LATENCY_SLO, parse_duration)? {
+            Some(latency_slo) => Some(outbound::http::LoadShedConfig {
+                latency_slo,
+                interval: parse(strings, ENV_OUTBOUND_LOAD_SHED_INTERVAL, parse_duration)?
+                    .unwrap_or(DEFAULT_OUTBOUND_LOAD_SHED_INTERVAL),
+                max_shed: parse(
+                    strings,
+                    ENV_OUTBOUND_LOAD_SHED_MAX_FRACTION,
+                    parse_probability,
+                )?
+                .unwrap_or(DEFAULT_OUTBOUND_LOAD_SHED_MAX_FRACTION),
+                critical_label: strings
+                    .get(ENV_OUTBOUND_LOAD_SHED_CRITICAL_LABEL)?
+                    .filter(|l| !l.is_empty()),
+            }),
+            None => None,
+        };
+
         outbound::Config {
             ingress_mode,
+            external_tls,
+            balance_algorithm,
+            balance_failure_penalty,
+            balance_connection_affinity,
+            route_timeouts,
+            route_breakers,
+            request_body_timeout,
+            endpoint_buffer,
+            build_limit,
+            canary,
+            endpoint_subsetting,
+            failover,
+            mirror,
+            coalesce,
+            route_priority,
+            load_shed,
+            prewarm_endpoints: parse(strings, ENV_OUTBOUND_PREWARM_ENDPOINTS, parse_bool)?
+                .unwrap_or(false),
+            tap_endpoint_selection: parse(
+                strings,
+                ENV_OUTBOUND_TAP_ENDPOINT_SELECTION,
+                parse_bool,
+            )?
+            .unwrap_or(false),
             allow_discovery: AddrMatch::new(dst_profile_suffixes.clone(), dst_profile_networks),
             proxy: ProxyConfig {
                 server,
@@ -430,6 +1575,17 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 max_in_flight_requests: outbound_max_in_flight?
                     .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT),
                 detect_protocol_timeout,
+                client_disconnect: parse(
+                    strings,
+                    ENV_OUTBOUND_CLIENT_DISCONNECT,
+                    parse_client_disconnect,
+                )?
+                .unwrap_or_default(),
+                connection_log: connection_log.clone(),
+                echo_trace_id,
+                tcp_splice,
+                memory_pressure: memory_pressure.clone(),
+                via: via.clone(),
             },
         }
     };
@@ -438,16 +1594,33 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         allow_discovery: NameMatch::new(gateway_suffixes?.unwrap_or_default()),
     };
 
-    let inbound = {
+    let mut inbound = {
         let addr = ListenAddr(
             inbound_listener_addr?
                 .unwrap_or_else(|| parse_socket_addr(DEFAULT_INBOUND_LISTEN_ADDR).unwrap()),
         );
         let keepalive = Keepalive(inbound_accept_keepalive?);
+        let peer_settings = {
+            let defaults = h2::peer_settings::Bounds::default();
+            h2::peer_settings::Bounds {
+                max_header_table_size: inbound_h2_max_header_table_size?
+                    .unwrap_or(defaults.max_header_table_size),
+                max_frame_size: inbound_h2_max_frame_size?.unwrap_or(defaults.max_frame_size),
+                max_header_list_size: inbound_h2_max_header_list_size?
+                    .unwrap_or(defaults.max_header_list_size),
+                min_initial_window_size: inbound_h2_min_initial_window_size?
+                    .unwrap_or(defaults.min_initial_window_size),
+                ..defaults
+            }
+        };
         let server = ServerConfig {
             addr,
             keepalive,
-            h2_settings,
+            dscp: dscp.clone(),
+            h2_settings: h2::Settings {
+                peer_settings: Some(peer_settings),
+                ..h2_settings
+            },
         };
         let cache_max_idle_age =
             inbound_cache_max_idle_age?.unwrap_or(DEFAULT_INBOUND_ROUTER_MAX_IDLE_AGE);
@@ -456,6 +1629,7 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let keepalive = Keepalive(inbound_connect_keepalive?);
         let connect = ConnectConfig {
             keepalive,
+            dscp: dscp.clone(),
             timeout: inbound_connect_timeout?.unwrap_or(DEFAULT_INBOUND_CONNECT_TIMEOUT),
             backoff: parse_backoff(
                 strings,
@@ -467,6 +1641,16 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 max_idle,
                 idle_timeout: cache_max_idle_age,
             },
+            close_delimited: parse(strings, ENV_INBOUND_CLOSE_DELIMITED_RESPONSES, |s| {
+                parse_close_delimited(s, close_delimited_max)
+            })?
+            .unwrap_or_default(),
+            response_read_ahead_bytes: parse(
+                strings,
+                ENV_INBOUND_RESPONSE_READ_AHEAD_BYTES,
+                parse_number,
+            )?
+            .unwrap_or_default(),
         };
 
         let detect_protocol_timeout =
@@ -485,6 +1669,13 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
             return Err(EnvError::InvalidEnvVar);
         }
 
+        let require_tls_for_inbound_ports =
+            parse(strings, ENV_INBOUND_PORTS_REQUIRE_TLS, parse_port_set)?.unwrap_or_default();
+        let proxy_protocol_ports =
+            parse(strings, ENV_INBOUND_PORTS_PROXY_PROTOCOL, parse_port_set)?.unwrap_or_default();
+        let sni_routes =
+            parse(strings, ENV_INBOUND_PORTS_SNI_ROUTES, parse_sni_routes)?.unwrap_or_default();
+
         // Ensure that connections thaat directly target the inbound port are
         // secured (unless identity is disabled).
         let inbound_port = server.addr.as_ref().port();
@@ -507,6 +1698,335 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
             return Err(EnvError::InvalidEnvVar);
         }
 
+        let log_client_port =
+            parse(strings, ENV_INBOUND_LOG_CLIENT_PORT, parse_bool)?.unwrap_or(false);
+        let missing_authority = parse(
+            strings,
+            ENV_INBOUND_MISSING_AUTHORITY,
+            parse_missing_authority,
+        )?
+        .unwrap_or_default();
+        let transfer_encoding_conflict = parse(
+            strings,
+            ENV_INBOUND_TRANSFER_ENCODING_CONFLICT,
+            parse_transfer_encoding_conflict,
+        )?
+        .unwrap_or_default();
+        let duplicate_content_length = parse(
+            strings,
+            ENV_INBOUND_DUPLICATE_CONTENT_LENGTH,
+            parse_duplicate_content_length,
+        )?
+        .unwrap_or_default();
+        let error_rate_limits = inbound::http::ErrorRateLimits::new(
+            parse(
+                strings,
+                ENV_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND,
+                parse_number,
+            )?,
+            parse(
+                strings,
+                ENV_INBOUND_MAX_ERROR_RESPONSES_PER_SECOND_PORTS,
+                parse_port_rates,
+            )?
+            .unwrap_or_default(),
+        );
+        let local_breakers = inbound::http::LocalBreakers::new(
+            parse(
+                strings,
+                ENV_INBOUND_LOCAL_BREAKER_DEFAULT,
+                parse_breaker_thresholds,
+            )?,
+            parse(
+                strings,
+                ENV_INBOUND_LOCAL_BREAKER_PORTS,
+                parse_port_local_breakers,
+            )?
+            .unwrap_or_default(),
+        );
+        let identity_rate_limits = inbound::http::IdentityRateLimits::new(
+            parse(
+                strings,
+                ENV_INBOUND_MAX_REQUESTS_PER_SECOND_PER_IDENTITY,
+                parse_number,
+            )?,
+            parse(
+                strings,
+                ENV_INBOUND_MAX_REQUESTS_PER_SECOND_IDENTITIES,
+                parse_identity_rates,
+            )?
+            .unwrap_or_default(),
+            parse(
+                strings,
+                ENV_INBOUND_MAX_REQUESTS_PER_SECOND_UNAUTHENTICATED,
+                parse_number,
+            )?,
+        );
+        let strip_l5d_headers = parse(
+            strings,
+            ENV_INBOUND_STRIP_L5D_HEADERS,
+            parse_strip_l5d_headers,
+        )?
+        .unwrap_or_default();
+        let duplicate_headers = parse(strings, ENV_INBOUND_COALESCE_HEADERS, parse_header_names)?
+            .map(inbound::http::DuplicateHeaders::new)
+            .unwrap_or_default();
+        let max_request_line_bytes =
+            parse(strings, ENV_INBOUND_MAX_REQUEST_LINE_BYTES, parse_number)?;
+        let cookie_limits = inbound::http::CookieLimits {
+            max_bytes: parse(strings, ENV_INBOUND_MAX_COOKIE_BYTES, parse_number)?,
+            max_cookies: parse(strings, ENV_INBOUND_MAX_COOKIES, parse_number)?,
+            oversized: parse(
+                strings,
+                ENV_INBOUND_OVERSIZED_COOKIES,
+                parse_oversized_cookies,
+            )?
+            .unwrap_or_default(),
+        };
+        let request_id_header = parse(strings, ENV_INBOUND_REQUEST_ID_HEADER, parse_header_name)?;
+        let proxy_elapsed_header =
+            parse(strings, ENV_INBOUND_PROXY_ELAPSED_HEADER, parse_header_name)?;
+        // Inbound requests are sampled like outbound requests unless the
+        // inbound configuration overrides it, even without a collector.
+        let collector_enabled = matches!(trace_collector_addr, Ok(Some(_)));
+        let trace_sampler = http_tracing::Sampler::new(
+            parse(strings, ENV_INBOUND_TRACE_SAMPLE_RATIO, parse_probability)?
+                .or_else(|| trace_sample_ratio.filter(|_| collector_enabled))
+                .unwrap_or(0.0),
+            parse(
+                strings,
+                ENV_INBOUND_TRACE_FORCE_SAMPLE_HEADER,
+                parse_header_name,
+            )?
+            .or_else(|| {
+                trace_force_sample_header
+                    .clone()
+                    .filter(|_| collector_enabled)
+            }),
+        );
+        let trace_attributes = parse(
+            strings,
+            ENV_INBOUND_TRACE_ATTRIBUTES,
+            parse_trace_attributes,
+        )?
+        .unwrap_or_default();
+        let websocket_idle_timeout =
+            parse(strings, ENV_INBOUND_WEBSOCKET_IDLE_TIMEOUT, parse_duration)?;
+        let allowed_upgrades = parse(
+            strings,
+            ENV_INBOUND_ALLOWED_UPGRADE_PROTOCOLS,
+            parse_upgrade_protocols,
+        )?
+        .map(inbound::http::AllowedUpgrades::only)
+        .unwrap_or_default();
+        let response_headers_timeout = parse(
+            strings,
+            ENV_INBOUND_RESPONSE_HEADERS_TIMEOUT,
+            parse_duration,
+        )?;
+        let app_read_timeout = parse(strings, ENV_INBOUND_APP_READ_TIMEOUT, parse_duration)?;
+        let grpc_compression = {
+            let routes = parse(
+                strings,
+                ENV_INBOUND_GRPC_DECOMPRESS_ROUTES,
+                parse_grpc_routes,
+            )?
+            .unwrap_or_default();
+            let max_message_bytes = parse(
+                strings,
+                ENV_INBOUND_GRPC_MAX_DECOMPRESSED_MESSAGE_BYTES,
+                parse_number,
+            )?
+            .unwrap_or(DEFAULT_INBOUND_GRPC_MAX_DECOMPRESSED_MESSAGE_BYTES);
+            inbound::http::GrpcCompression::new(routes, max_message_bytes)
+        };
+        let allowed_methods = inbound::http::AllowedMethods::new(
+            strings
+                .get(ENV_INBOUND_ROUTE_ALLOWED_METHODS_LABEL)?
+                .filter(|l| !l.is_empty()),
+            parse(strings, ENV_INBOUND_ROUTE_ALLOW_OPTIONS, parse_bool)?.unwrap_or(true),
+        );
+        let redact_fields = inbound::http::RedactFields::new(
+            strings
+                .get(ENV_INBOUND_ROUTE_REDACT_FIELDS_LABEL)?
+                .filter(|l| !l.is_empty()),
+            parse(
+                strings,
+                ENV_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES,
+                parse_number::<usize>,
+            )?
+            .unwrap_or(DEFAULT_INBOUND_ROUTE_REDACT_MAX_BODY_BYTES),
+        );
+        // Large requests must be routed to the application rather than back
+        // to the proxy.
+        let large_body_port = parse(strings, ENV_INBOUND_LARGE_BODY_PORT, parse_port)?;
+        if large_body_port == Some(inbound_port) {
+            error!(
+                "{} must not be the port of {} ({})",
+                ENV_INBOUND_LARGE_BODY_PORT, ENV_INBOUND_LISTEN_ADDR, inbound_port
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+        let body_size_routing = inbound::http::BodySizeRouting::new(
+            strings
+                .get(ENV_INBOUND_ROUTE_BODY_SIZE_THRESHOLD_LABEL)?
+                .filter(|l| !l.is_empty()),
+            large_body_port,
+            parse(
+                strings,
+                ENV_INBOUND_ROUTE_UNKNOWN_BODY_SIZE_AS_LARGE,
+                parse_bool,
+            )?
+            .unwrap_or(false),
+        );
+        let max_request_body_bytes = inbound::http::RequestBodyLimits::new(
+            parse(
+                strings,
+                ENV_INBOUND_MAX_REQUEST_BODY_BYTES,
+                parse_number::<u64>,
+            )?,
+            strings
+                .get(ENV_INBOUND_ROUTE_MAX_REQUEST_BODY_BYTES_LABEL)?
+                .filter(|l| !l.is_empty()),
+        );
+        let replay_protection = inbound::http::ReplayProtection::new(
+            strings
+                .get(ENV_INBOUND_ROUTE_REPLAY_PROTECTION_LABEL)?
+                .filter(|l| !l.is_empty()),
+            parse(strings, ENV_INBOUND_REPLAY_NONCE_HEADER, parse_header_name)?
+                .unwrap_or_else(|| HeaderName::from_static(DEFAULT_INBOUND_REPLAY_NONCE_HEADER)),
+            parse(strings, ENV_INBOUND_REPLAY_NONCE_WINDOW, parse_duration)?
+                .unwrap_or(DEFAULT_INBOUND_REPLAY_NONCE_WINDOW),
+            parse(
+                strings,
+                ENV_INBOUND_REPLAY_MAX_NONCES,
+                parse_number::<usize>,
+            )?
+            .unwrap_or(DEFAULT_INBOUND_REPLAY_MAX_NONCES),
+            parse(
+                strings,
+                ENV_INBOUND_REPLAY_MAX_CLIENT_NONCES,
+                parse_number::<usize>,
+            )?
+            .unwrap_or(DEFAULT_INBOUND_REPLAY_MAX_CLIENT_NONCES),
+        );
+        let direct_plaintext =
+            if parse(strings, ENV_INBOUND_REJECT_PLAINTEXT, parse_bool)?.unwrap_or(false) {
+                let exempt = parse(
+                    strings,
+                    ENV_INBOUND_PLAINTEXT_EXEMPT_NETWORKS,
+                    parse_networks,
+                )?
+                .unwrap_or_default();
+                inbound::direct::PlaintextPolicy::Reject {
+                    exempt: IpMatch::new(exempt),
+                }
+            } else {
+                inbound::direct::PlaintextPolicy::Permit
+            };
+        let direct_alpn_downgrade = match parse(
+            strings,
+            ENV_INBOUND_REJECT_ALPN_DOWNGRADE_IDENTITIES,
+            parse_dns_suffixes,
+        )? {
+            Some(identities) if !identities.is_empty() => {
+                inbound::direct::AlpnDowngradePolicy::Reject {
+                    identities: NameMatch::new(identities),
+                }
+            }
+            _ => inbound::direct::AlpnDowngradePolicy::Permit,
+        };
+        let direct_identity_pending = match parse(
+            strings,
+            ENV_INBOUND_IDENTITY_PENDING_TIMEOUT,
+            parse_duration,
+        )? {
+            Some(timeout) => inbound::direct::IdentityPendingPolicy::Wait { timeout },
+            None => inbound::direct::IdentityPendingPolicy::Reject,
+        };
+        let direct_tls_alert_logs = parse(
+            strings,
+            ENV_INBOUND_TLS_ALERT_LOGS_PER_SECOND,
+            parse_number::<u32>,
+        )?
+        .map(inbound::direct::TlsAlertLogs::per_second)
+        .unwrap_or_default();
+        let port_classes =
+            parse(strings, ENV_INBOUND_PORT_CLASSES, parse_port_classes)?.unwrap_or_default();
+        let metrics_granularity = inbound::MetricsGranularityForPorts::new(
+            parse(
+                strings,
+                ENV_INBOUND_METRICS_GRANULARITY,
+                parse_metrics_granularity,
+            )?
+            .unwrap_or_default(),
+            parse(
+                strings,
+                ENV_INBOUND_PORTS_METRICS_GRANULARITY,
+                parse_ports_metrics_granularity,
+            )?
+            .unwrap_or_default(),
+        );
+        let client_auth = inbound::ClientAuthForPorts::new(
+            parse(strings, ENV_INBOUND_TLS_CLIENT_AUTH, parse_client_auth)?.unwrap_or_default(),
+            parse(
+                strings,
+                ENV_INBOUND_TLS_CLIENT_AUTH_PORTS,
+                parse_client_auth_ports,
+            )?
+            .unwrap_or_default(),
+        );
+        let source_networks = inbound::SourceNetworksForPorts::new(
+            parse(
+                strings,
+                ENV_INBOUND_SOURCE_ALLOW_NETWORKS,
+                parse_port_networks,
+            )?
+            .unwrap_or_default(),
+            parse(
+                strings,
+                ENV_INBOUND_SOURCE_DENY_NETWORKS,
+                parse_port_networks,
+            )?
+            .unwrap_or_default(),
+        );
+        let connection_rate_limits = inbound::ConnectionRateLimitsForPorts::new(
+            parse(
+                strings,
+                ENV_INBOUND_PORTS_CONNECTION_RATE,
+                parse_port_connection_rates,
+            )?
+            .unwrap_or_default(),
+        );
+        let identity_connection_limits = inbound::IdentityConnectionLimits::new(
+            parse(
+                strings,
+                ENV_INBOUND_MAX_CONNECTIONS_PER_IDENTITY,
+                parse_number,
+            )?,
+            parse(
+                strings,
+                ENV_INBOUND_MAX_CONNECTIONS_IDENTITY_TIERS,
+                parse_identity_tiers,
+            )?
+            .unwrap_or_default(),
+            parse(
+                strings,
+                ENV_INBOUND_MAX_CONNECTIONS_UNAUTHENTICATED,
+                parse_number,
+            )?,
+        );
+        let port_idle_timeouts = inbound::IdleTimeoutsForPorts::new(
+            parse(strings, ENV_INBOUND_TCP_IDLE_TIMEOUT, parse_duration)?,
+            parse(
+                strings,
+                ENV_INBOUND_PORTS_TCP_IDLE_TIMEOUT,
+                parse_port_durations,
+            )?
+            .unwrap_or_default(),
+        );
+        let terminate_tls = parse_terminate_tls_config(strings)?;
+
         inbound::Config {
             allow_discovery: NameMatch::new(dst_profile_suffixes),
             proxy: ProxyConfig {
@@ -518,11 +2038,102 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 max_in_flight_requests: inbound_max_in_flight?
                     .unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT),
                 detect_protocol_timeout,
+                client_disconnect: parse(
+                    strings,
+                    ENV_INBOUND_CLIENT_DISCONNECT,
+                    parse_client_disconnect,
+                )?
+                .unwrap_or_default(),
+                connection_log,
+                echo_trace_id,
+                tcp_splice,
+                memory_pressure,
+                via,
             },
             require_identity_for_inbound_ports: require_identity_for_inbound_ports.into(),
+            require_tls_for_inbound_ports: require_tls_for_inbound_ports.into(),
             profile_idle_timeout: dst_profile_idle_timeout?
                 .unwrap_or(DEFAULT_DESTINATION_PROFILE_IDLE_TIMEOUT),
             disable_protocol_detection_for_ports: inbound_opaque_ports.into_iter().collect(),
+            default_deny: parse(strings, ENV_INBOUND_DEFAULT_DENY, parse_bool)?.unwrap_or(false),
+            allow_ports: parse(strings, ENV_INBOUND_PORTS_ALLOW, parse_port_set)?
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            proxy_protocol_ports: proxy_protocol_ports.into_iter().collect(),
+            sni_routes: sni_routes.into(),
+            identity_bytes_metrics: parse(strings, ENV_INBOUND_IDENTITY_BYTES_METRICS, parse_bool)?
+                .unwrap_or(false),
+            log_client_port,
+            missing_authority,
+            duplicate_headers,
+            max_request_line_bytes,
+            cookie_limits,
+            transfer_encoding_conflict,
+            duplicate_content_length,
+            error_rate_limits,
+            local_breakers,
+            proxy_error_header: parse(
+                strings,
+                ENV_INBOUND_PROXY_ERROR_HEADER,
+                parse_optional_header_name,
+            )?
+            .unwrap_or_else(errors::proxy_error_header),
+            classify_app_connection_errors: parse(
+                strings,
+                ENV_INBOUND_CLASSIFY_APP_CONNECTION_ERRORS,
+                parse_bool,
+            )?
+            .unwrap_or(false),
+            redact_unmeshed_errors: parse(strings, ENV_INBOUND_REDACT_UNMESHED_ERRORS, parse_bool)?
+                .unwrap_or(false),
+            json_error_bodies: parse(strings, ENV_INBOUND_JSON_ERROR_BODIES, parse_bool)?
+                .unwrap_or(false),
+            identity_rate_limits,
+            h2_stream_limit: parse(
+                strings,
+                ENV_INBOUND_HTTP2_MAX_CONCURRENT_STREAMS,
+                parse_number,
+            )?
+            .map(inbound::http::H2StreamLimit::new)
+            .unwrap_or_default(),
+            strip_l5d_headers,
+            request_id_header,
+            proxy_elapsed_header,
+            trace_sampler,
+            trace_attributes,
+            websocket_idle_timeout,
+            allowed_upgrades,
+            response_headers_timeout,
+            app_read_timeout,
+            grpc_compression,
+            allowed_methods,
+            redact_fields,
+            body_size_routing,
+            max_request_body_bytes,
+            replay_protection,
+            direct_plaintext,
+            direct_alpn_downgrade,
+            direct_identity_pending,
+            direct_tls_alert_logs,
+            port_classes: port_classes.into(),
+            metrics_granularity,
+            client_auth,
+            source_networks,
+            connection_rate_limits,
+            identity_connection_limits,
+            port_idle_timeouts,
+            terminate_tls,
+            opaque_on_http1_parse_failure: parse(
+                strings,
+                ENV_INBOUND_OPAQUE_ON_HTTP1_PARSE_FAILURE,
+                parse_bool,
+            )?
+            .unwrap_or(false),
+            connect_loopback: parse(strings, ENV_INBOUND_CONNECT_LOOPBACK, parse_ip_addr)?
+                .unwrap_or(DEFAULT_INBOUND_CONNECT_LOOPBACK),
+            trust_loopback: parse(strings, ENV_INBOUND_TRUST_LOOPBACK, parse_bool)?
+                .unwrap_or(false),
         }
     };
 
@@ -543,14 +2154,39 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         }
     };
 
+    let metrics_push = match metrics_push_gateway_addr? {
+        Some(addr) => Some(super::admin::PushConfig {
+            addr,
+            job: metrics_push_job?.unwrap_or_else(|| DEFAULT_METRICS_PUSH_JOB.to_string()),
+            instance: metrics_push_instance?,
+            interval: metrics_push_interval?.unwrap_or(DEFAULT_METRICS_PUSH_INTERVAL),
+            timeout: metrics_push_timeout?.unwrap_or(DEFAULT_METRICS_PUSH_TIMEOUT),
+        }),
+        None => None,
+    };
+
     let admin = super::admin::Config {
         metrics_retain_idle: metrics_retain_idle?.unwrap_or(DEFAULT_METRICS_RETAIN_IDLE),
+        metrics_status_labels: if parse(strings, ENV_METRICS_STATUS_CLASSES, parse_bool)?
+            .unwrap_or(false)
+        {
+            let distinct = parse(
+                strings,
+                ENV_METRICS_DISTINCT_STATUS_CODES,
+                parse_status_codes,
+            )?;
+            StatusLabels::classes(distinct.unwrap_or_default())
+        } else {
+            StatusLabels::default()
+        },
+        metrics_push,
         server: ServerConfig {
             addr: ListenAddr(
                 admin_listener_addr?
                     .unwrap_or_else(|| parse_socket_addr(DEFAULT_ADMIN_LISTEN_ADDR).unwrap()),
             ),
             keepalive: inbound.proxy.server.keepalive,
+            dscp: inbound.proxy.server.dscp.clone(),
             h2_settings,
         },
     };
@@ -582,6 +2218,9 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
             oc_collector::Config::Enabled(Box::new(oc_collector::EnabledConfig {
                 attributes,
                 hostname: hostname?,
+                protocol: trace_collector_protocol?.unwrap_or(oc_collector::Protocol::OpenCensus),
+                sample_ratio: trace_sample_ratio.unwrap_or(0.0),
+                force_sample_header: trace_force_sample_header,
                 control: ControlConfig {
                     addr,
                     connect,
@@ -591,17 +2230,42 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         }
     };
 
+    let tap_max_subscriptions = parse(strings, ENV_TAP_MAX_SUBSCRIPTIONS, parse_number::<usize>)?;
     let tap = tap?
         .map(|(addr, ids)| super::tap::Config::Enabled {
             permitted_client_ids: ids,
+            max_subscriptions: tap_max_subscriptions,
             config: ServerConfig {
                 addr: ListenAddr(addr),
                 keepalive: inbound.proxy.server.keepalive,
+                dscp: inbound.proxy.server.dscp.clone(),
                 h2_settings,
             },
         })
         .unwrap_or(super::tap::Config::Disabled);
 
+    // Connections to the proxy's admin and tap servers are never denied, in
+    // case they are redirected through the inbound proxy.
+    if inbound.default_deny {
+        inbound
+            .allow_ports
+            .insert(admin.server.addr.as_ref().port());
+        if let super::tap::Config::Enabled { ref config, .. } = tap {
+            inbound.allow_ports.insert(config.addr.as_ref().port());
+        }
+    }
+
+    let watchdog = super::watchdog::Config {
+        restart_delay: parse(strings, ENV_WATCHDOG_RESTART_DELAY, parse_duration)?,
+        heartbeat_timeout: parse(strings, ENV_WATCHDOG_HEARTBEAT_TIMEOUT, parse_duration)?,
+    };
+
+    let shutdown_events = crate::core::shutdown::Config {
+        log: parse(strings, ENV_SHUTDOWN_EVENTS_LOG, parse_bool)?.unwrap_or(false),
+        timeline: parse(strings, ENV_SHUTDOWN_EVENTS_METRICS, parse_bool)?.unwrap_or(false),
+    };
+    let shutdown_grace_period = parse(strings, ENV_SHUTDOWN_GRACE_PERIOD, parse_duration)?;
+
     let identity = identity_config?
         .map(|(addr, certify)| {
             // If the address doesn't have a server identity, then we're on localhost.
@@ -631,6 +2295,9 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         outbound,
         gateway,
         inbound,
+        watchdog,
+        shutdown_events,
+        shutdown_grace_period,
     })
 }
 
@@ -760,6 +2427,13 @@ fn parse_socket_addr(s: &str) -> Result<SocketAddr, ParseError> {
     }
 }
 
+fn parse_ip_addr(s: &str) -> Result<IpAddr, ParseError> {
+    s.trim().parse().map_err(|_| {
+        error!("Expected an IP address; found: {}", s);
+        ParseError::HostIsNotAnIpAddress
+    })
+}
+
 fn parse_addr(s: &str) -> Result<Addr, ParseError> {
     Addr::from_str(s).map_err(|e| {
         error!("Not a valid address: {}", s);
@@ -767,98 +2441,789 @@ fn parse_addr(s: &str) -> Result<Addr, ParseError> {
     })
 }
 
-fn parse_port_set(s: &str) -> Result<HashSet<u16>, ParseError> {
-    let mut set = HashSet::new();
-    for num in s.split(',') {
-        set.insert(parse_number::<u16>(num)?);
-    }
-    Ok(set)
-}
-
-pub(super) fn parse_identity(s: &str) -> Result<identity::Name, ParseError> {
-    identity::Name::from_str(s).map_err(|identity::InvalidName| {
-        error!("Not a valid identity name: {}", s);
-        ParseError::NameError
-    })
+fn parse_header_name(s: &str) -> Result<HeaderName, ParseError> {
+    let s = s.trim();
+    HeaderName::from_str(s).map_err(|_| ParseError::UnsupportedValue(s.to_string()))
 }
 
-pub(super) fn parse<T, Parse>(
-    strings: &dyn Strings,
-    name: &str,
-    parse: Parse,
-) -> Result<Option<T>, EnvError>
-where
-    Parse: FnOnce(&str) -> Result<T, ParseError>,
-{
-    match strings.get(name)? {
-        Some(ref s) => {
-            let r = parse(s).map_err(|parse_error| {
-                error!("{}={:?} is not valid: {:?}", name, s, parse_error);
-                EnvError::InvalidEnvVar
-            })?;
-            Ok(Some(r))
+fn parse_trace_attributes(list: &str) -> Result<inbound::http::TraceAttributes, ParseError> {
+    let mut headers = Vec::new();
+    let mut route = false;
+    let mut client_id = false;
+    for item in list.split(',') {
+        match item.trim() {
+            "" => {}
+            "route" => route = true,
+            "client_id" => client_id = true,
+            item => match item.strip_prefix("header:") {
+                Some(name) => headers.push(parse_header_name(name)?),
+                None => return Err(ParseError::UnsupportedValue(item.to_string())),
+            },
         }
-        None => Ok(None),
     }
+    Ok(inbound::http::TraceAttributes::new(
+        headers, route, client_id,
+    ))
 }
 
-#[allow(dead_code)]
-fn parse_deprecated<T, Parse>(
-    strings: &dyn Strings,
-    name: &str,
-    deprecated_name: &str,
-    f: Parse,
-) -> Result<Option<T>, EnvError>
-where
-    Parse: Copy,
-    Parse: Fn(&str) -> Result<T, ParseError>,
-{
-    match parse(strings, name, f)? {
-        Some(v) => Ok(Some(v)),
-        None => {
-            let v = parse(strings, deprecated_name, f)?;
-            if v.is_some() {
-                warn!("{} has been deprecated; use {}", deprecated_name, name);
-            }
-            Ok(v)
-        }
+fn parse_optional_header_name(s: &str) -> Result<Option<HeaderName>, ParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
     }
+    parse_header_name(s).map(Some)
 }
 
-fn parse_dns_suffixes(list: &str) -> Result<HashSet<dns::Suffix>, ParseError> {
-    let mut suffixes = HashSet::new();
+fn parse_header_names(list: &str) -> Result<Vec<HeaderName>, ParseError> {
+    let mut names = Vec::new();
     for item in list.split(',') {
         let item = item.trim();
         if !item.is_empty() {
-            let sfx = parse_dns_suffix(item)?;
-            suffixes.insert(sfx);
+            names.push(parse_header_name(item)?);
         }
     }
-
-    Ok(suffixes)
+    Ok(names)
 }
 
-fn parse_dns_suffix(s: &str) -> Result<dns::Suffix, ParseError> {
-    if s == "." {
-        return Ok(dns::Suffix::Root);
+fn parse_request_body_timeout(s: &str) -> Result<outbound::http::RequestBodyTimeout, ParseError> {
+    use outbound::http::RequestBodyTimeout;
+
+    if s.trim().eq_ignore_ascii_case("total") {
+        return Ok(RequestBodyTimeout::Total);
+    }
+    parse_duration(s).map(RequestBodyTimeout::Idle)
+}
+
+fn parse_upgrade_protocols(list: &str) -> Result<Vec<String>, ParseError> {
+    let mut protocols = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        if !item.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(ParseError::UnsupportedValue(item.to_string()));
+        }
+        protocols.push(item.to_string());
+    }
+    Ok(protocols)
+}
+
+fn parse_grpc_routes(list: &str) -> Result<Vec<String>, ParseError> {
+    let mut routes = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        if !item.starts_with('/') {
+            return Err(ParseError::UnsupportedValue(item.to_string()));
+        }
+        routes.push(item.to_string());
+    }
+    Ok(routes)
+}
+
+fn parse_missing_authority(s: &str) -> Result<inbound::http::MissingAuthority, ParseError> {
+    match s.trim() {
+        "reject" => Ok(inbound::http::MissingAuthority::Reject),
+        "target" => Ok(inbound::http::MissingAuthority::UseTarget),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_transfer_encoding_conflict(
+    s: &str,
+) -> Result<inbound::http::TransferEncodingConflict, ParseError> {
+    match s.trim() {
+        "reject" => Ok(inbound::http::TransferEncodingConflict::Reject),
+        "strip" => Ok(inbound::http::TransferEncodingConflict::StripContentLength),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_duplicate_content_length(
+    s: &str,
+) -> Result<inbound::http::DuplicateContentLength, ParseError> {
+    match s.trim() {
+        "reject" => Ok(inbound::http::DuplicateContentLength::Reject),
+        "collapse" => Ok(inbound::http::DuplicateContentLength::Collapse),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_oversized_cookies(s: &str) -> Result<inbound::http::OversizedCookies, ParseError> {
+    match s.trim() {
+        "reject" => Ok(inbound::http::OversizedCookies::Reject),
+        "strip" => Ok(inbound::http::OversizedCookies::Strip),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_strip_l5d_headers(s: &str) -> Result<inbound::http::StripL5dHeaders, ParseError> {
+    match s.trim() {
+        "never" => Ok(inbound::http::StripL5dHeaders::Never),
+        "unmeshed" => Ok(inbound::http::StripL5dHeaders::Unmeshed),
+        "always" => Ok(inbound::http::StripL5dHeaders::Always),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_client_disconnect(s: &str) -> Result<ClientDisconnect, ParseError> {
+    match s.trim() {
+        "cancel" => Ok(ClientDisconnect::Cancel),
+        "complete" => Ok(ClientDisconnect::Complete),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_breaker_thresholds(s: &str) -> Result<BreakerThresholds, ParseError> {
+    let s = s.trim();
+    if s == "0" {
+        return Ok(BreakerThresholds {
+            max_failures: 0,
+            open_timeout: Duration::from_secs(0),
+        });
+    }
+    match s.split_once('/') {
+        Some((max_failures, open_timeout)) => Ok(BreakerThresholds {
+            max_failures: parse_number(max_failures.trim())?,
+            open_timeout: parse_duration(open_timeout)?,
+        }),
+        None => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_close_delimited(s: &str, max_bytes: usize) -> Result<CloseDelimited, ParseError> {
+    match s.trim() {
+        "pass-through" => Ok(CloseDelimited::PassThrough),
+        "buffer" => Ok(CloseDelimited::Buffer { max_bytes }),
+        "reject" => Ok(CloseDelimited::Reject),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_connection_log_format(s: &str) -> Result<connection_log::Format, ParseError> {
+    match s.trim() {
+        "plain" => Ok(connection_log::Format::Plain),
+        "json" => Ok(connection_log::Format::Json),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_balance_algorithm(s: &str) -> Result<balance::Algorithm, ParseError> {
+    match s.trim() {
+        "peak-ewma" => Ok(balance::Algorithm::PeakEwma),
+        "least-request" => Ok(balance::Algorithm::LeastRequest),
+        "latency-ewma" => Ok(balance::Algorithm::LatencyEwma),
+        "round-robin" => Ok(balance::Algorithm::RoundRobin),
+        "random" => Ok(balance::Algorithm::Random),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_via(s: &str) -> Result<Via, ParseError> {
+    s.trim()
+        .parse()
+        .map_err(|_| ParseError::UnsupportedValue(s.to_string()))
+}
+
+fn parse_probability(s: &str) -> Result<f64, ParseError> {
+    let p = parse_number::<f64>(s.trim())?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(ParseError::UnsupportedValue(s.to_string()));
+    }
+    Ok(p)
+}
+
+fn parse_status_codes(s: &str) -> Result<Vec<StatusCode>, ParseError> {
+    let mut codes = Vec::new();
+    for code in s.split(',') {
+        let code = code.trim();
+        if code.is_empty() {
+            continue;
+        }
+        let code = StatusCode::from_u16(parse_number(code)?)
+            .map_err(|_| ParseError::UnsupportedValue(code.to_string()))?;
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+fn parse_port(s: &str) -> Result<u16, ParseError> {
+    match parse_number::<u16>(s)? {
+        0 => Err(ParseError::UnsupportedValue(s.to_string())),
+        port => Ok(port),
+    }
+}
+
+fn parse_port_set(s: &str) -> Result<HashSet<u16>, ParseError> {
+    let mut set = HashSet::new();
+    for num in s.split(',') {
+        set.insert(parse_number::<u16>(num)?);
+    }
+    Ok(set)
+}
+
+pub(super) fn parse_identity(s: &str) -> Result<identity::Name, ParseError> {
+    identity::Name::from_str(s).map_err(|identity::InvalidName| {
+        error!("Not a valid identity name: {}", s);
+        ParseError::NameError
+    })
+}
+
+pub(super) fn parse<T, Parse>(
+    strings: &dyn Strings,
+    name: &str,
+    parse: Parse,
+) -> Result<Option<T>, EnvError>
+where
+    Parse: FnOnce(&str) -> Result<T, ParseError>,
+{
+    match strings.get(name)? {
+        Some(ref s) => {
+            let r = parse(s).map_err(|parse_error| {
+                error!("{}={:?} is not valid: {:?}", name, s, parse_error);
+                EnvError::InvalidEnvVar
+            })?;
+            Ok(Some(r))
+        }
+        None => Ok(None),
+    }
+}
+
+#[allow(dead_code)]
+fn parse_deprecated<T, Parse>(
+    strings: &dyn Strings,
+    name: &str,
+    deprecated_name: &str,
+    f: Parse,
+) -> Result<Option<T>, EnvError>
+where
+    Parse: Copy,
+    Parse: Fn(&str) -> Result<T, ParseError>,
+{
+    match parse(strings, name, f)? {
+        Some(v) => Ok(Some(v)),
+        None => {
+            let v = parse(strings, deprecated_name, f)?;
+            if v.is_some() {
+                warn!("{} has been deprecated; use {}", deprecated_name, name);
+            }
+            Ok(v)
+        }
+    }
+}
+
+fn parse_dns_suffixes(list: &str) -> Result<HashSet<dns::Suffix>, ParseError> {
+    let mut suffixes = HashSet::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if !item.is_empty() {
+            let sfx = parse_dns_suffix(item)?;
+            suffixes.insert(sfx);
+        }
+    }
+
+    Ok(suffixes)
+}
+
+fn parse_dns_suffix(s: &str) -> Result<dns::Suffix, ParseError> {
+    if s == "." {
+        return Ok(dns::Suffix::Root);
     }
 
     dns::Suffix::from_str(s).map_err(|_| ParseError::NotADomainSuffix)
 }
 
-fn parse_networks(list: &str) -> Result<HashSet<ipnet::IpNet>, ParseError> {
-    let mut nets = HashSet::new();
-    for input in list.split(',') {
-        let input = input.trim();
-        if !input.is_empty() {
-            let net = ipnet::IpNet::from_str(input).map_err(|error| {
-                error!(%input, %error, "Invalid network");
-                ParseError::NotANetwork
-            })?;
-            nets.insert(net);
+fn parse_networks(list: &str) -> Result<HashSet<ipnet::IpNet>, ParseError> {
+    let mut nets = HashSet::new();
+    for input in list.split(',') {
+        let input = input.trim();
+        if !input.is_empty() {
+            let net = ipnet::IpNet::from_str(input).map_err(|error| {
+                error!(%input, %error, "Invalid network");
+                ParseError::NotANetwork
+            })?;
+            nets.insert(net);
+        }
+    }
+    Ok(nets)
+}
+
+fn parse_external_tls_destinations(
+    list: &str,
+) -> Result<Vec<(identity::Name, ipnet::IpNet)>, ParseError> {
+    let mut destinations = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (name, net) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let name = parse_identity(name.trim())?;
+        let net = ipnet::IpNet::from_str(net.trim()).map_err(|error| {
+            error!(input = %net, %error, "Invalid network");
+            ParseError::NotANetwork
+        })?;
+        destinations.push((name, net));
+    }
+    Ok(destinations)
+}
+
+fn parse_client_auth(s: &str) -> Result<tls::server::ClientAuth, ParseError> {
+    match s.trim() {
+        "require" => Ok(tls::server::ClientAuth::Require),
+        "request" => Ok(tls::server::ClientAuth::Request),
+        "none" => Ok(tls::server::ClientAuth::None),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_client_auth_ports(list: &str) -> Result<Vec<(u16, tls::server::ClientAuth)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, auth) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((parse_number::<u16>(port.trim())?, parse_client_auth(auth)?));
+    }
+    Ok(ports)
+}
+
+fn parse_trace_collector_protocol(s: &str) -> Result<oc_collector::Protocol, ParseError> {
+    match s.trim() {
+        "opencensus" => Ok(oc_collector::Protocol::OpenCensus),
+        "otlp" => Ok(oc_collector::Protocol::Otlp),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_metrics_granularity(s: &str) -> Result<inbound::MetricsGranularity, ParseError> {
+    match s.trim() {
+        "detailed" => Ok(inbound::MetricsGranularity::Detailed),
+        "minimal" => Ok(inbound::MetricsGranularity::Minimal),
+        "off" => Ok(inbound::MetricsGranularity::Off),
+        s => Err(ParseError::UnsupportedValue(s.to_string())),
+    }
+}
+
+fn parse_ports_metrics_granularity(
+    list: &str,
+) -> Result<Vec<(u16, inbound::MetricsGranularity)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, granularity) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((
+            parse_number::<u16>(port.trim())?,
+            parse_metrics_granularity(granularity)?,
+        ));
+    }
+    Ok(ports)
+}
+
+fn parse_port_networks(list: &str) -> Result<Vec<(u16, ipnet::IpNet)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, net) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let net = ipnet::IpNet::from_str(net.trim()).map_err(|error| {
+            error!(input = %net, %error, "Invalid network");
+            ParseError::NotANetwork
+        })?;
+        ports.push((parse_number::<u16>(port.trim())?, net));
+    }
+    Ok(ports)
+}
+
+fn parse_port_names(list: &str) -> Result<Vec<(u16, String)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, name) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        // Names are used as directory names, so they may not refer to other
+        // directories.
+        let name = name.trim();
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.';
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            return Err(ParseError::UnsupportedValue(name.to_string()));
+        }
+        ports.push((parse_number::<u16>(port.trim())?, name.to_string()));
+    }
+    Ok(ports)
+}
+
+fn parse_sni_routes(list: &str) -> Result<Vec<(u16, identity::Name, u16)>, ParseError> {
+    let mut routes = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let invalid = || ParseError::UnsupportedValue(item.to_string());
+        let (port, route) = item.split_once(':').ok_or_else(invalid)?;
+        let (name, target) = route.split_once('=').ok_or_else(invalid)?;
+        routes.push((
+            parse_port(port.trim())?,
+            parse_identity(name.trim())?,
+            parse_port(target.trim())?,
+        ));
+    }
+    Ok(routes)
+}
+
+fn parse_label(s: &str) -> Result<(String, String), ParseError> {
+    let (label, value) = s
+        .split_once('=')
+        .ok_or_else(|| ParseError::UnsupportedValue(s.to_string()))?;
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(ParseError::UnsupportedValue(s.to_string()));
+    }
+    Ok((label.to_string(), value.trim().to_string()))
+}
+
+/// Parses a Pushgateway grouping label value, which is used as a URI path
+/// segment.
+fn parse_push_label(s: &str) -> Result<String, ParseError> {
+    let s = s.trim();
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':');
+    if s.is_empty() || !s.chars().all(valid) {
+        error!("Not a valid Pushgateway label value: {}", s);
+        return Err(ParseError::UnsupportedValue(s.to_string()));
+    }
+    Ok(s.to_string())
+}
+
+fn parse_percent(s: &str) -> Result<u8, ParseError> {
+    let percent = parse_number::<u8>(s.trim())?;
+    if percent > 100 {
+        return Err(ParseError::UnsupportedValue(s.to_string()));
+    }
+    Ok(percent)
+}
+
+fn parse_dscp(s: &str) -> Result<Dscp, ParseError> {
+    Dscp::new(parse_number(s.trim())?).map_err(|error| {
+        error!(%error, "Invalid DSCP");
+        ParseError::UnsupportedValue(s.to_string())
+    })
+}
+
+fn parse_dscp_ports(list: &str) -> Result<Vec<(u16, Dscp)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, dscp) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((parse_number::<u16>(port.trim())?, parse_dscp(dscp)?));
+    }
+    Ok(ports)
+}
+
+fn parse_port_rates(list: &str) -> Result<Vec<(u16, u32)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, rate) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((parse_number(port.trim())?, parse_number(rate.trim())?));
+    }
+    Ok(ports)
+}
+
+fn parse_port_durations(list: &str) -> Result<Vec<(u16, Duration)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, duration) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((parse_number(port.trim())?, parse_duration(duration.trim())?));
+    }
+    Ok(ports)
+}
+
+fn parse_port_local_breakers(list: &str) -> Result<Vec<(u16, BreakerThresholds)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, thresholds) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        ports.push((
+            parse_number(port.trim())?,
+            parse_breaker_thresholds(thresholds)?,
+        ));
+    }
+    Ok(ports)
+}
+
+fn parse_port_connection_rates(
+    list: &str,
+) -> Result<Vec<(u16, inbound::ConnectionRateLimit)>, ParseError> {
+    let mut ports = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, limit) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let (per_second, burst) = match limit.split_once('/') {
+            Some((rate, burst)) => (parse_number(rate.trim())?, parse_number(burst.trim())?),
+            None => {
+                let rate = parse_number(limit.trim())?;
+                (rate, rate)
+            }
+        };
+        ports.push((
+            parse_number(port.trim())?,
+            inbound::ConnectionRateLimit { per_second, burst },
+        ));
+    }
+    Ok(ports)
+}
+
+fn parse_identity_rates(list: &str) -> Result<Vec<(identity::Name, u32)>, ParseError> {
+    let mut identities = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (name, rate) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        identities.push((parse_identity(name.trim())?, parse_number(rate.trim())?));
+    }
+    Ok(identities)
+}
+
+fn parse_identity_tiers(list: &str) -> Result<Vec<(inbound::IdentityPattern, usize)>, ParseError> {
+    let mut tiers = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (pattern, max) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let pattern = pattern
+            .trim()
+            .parse()
+            .map_err(|_| ParseError::UnsupportedValue(pattern.to_string()))?;
+        tiers.push((pattern, parse_number(max.trim())?));
+    }
+    Ok(tiers)
+}
+
+fn parse_addr_pairs(list: &str) -> Result<Vec<(NameAddr, NameAddr)>, ParseError> {
+    let mut pairs = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (a, b) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let parse_addr = |s: &str| {
+            NameAddr::from_str(s.trim()).map_err(|_| ParseError::UnsupportedValue(s.to_string()))
+        };
+        pairs.push((parse_addr(a)?, parse_addr(b)?));
+    }
+    Ok(pairs)
+}
+
+fn parse_port_classes(list: &str) -> Result<Vec<(u16, String)>, ParseError> {
+    let mut classes = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let (port, class) = item
+            .split_once('=')
+            .ok_or_else(|| ParseError::UnsupportedValue(item.to_string()))?;
+        let port = parse_number::<u16>(port.trim())?;
+        // Classes are used as metric label values, so they are restricted to
+        // a conservative set of characters.
+        let class = class.trim();
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if class.is_empty() || !class.chars().all(valid) {
+            return Err(ParseError::UnsupportedValue(class.to_string()));
+        }
+        classes.push((port, class.to_string()));
+    }
+    Ok(classes)
+}
+
+fn parse_external_tls_config<S: Strings>(
+    strings: &S,
+) -> Result<outbound::tcp::external_tls::Config, EnvError> {
+    use crate::core::tls::external::ClientConfig;
+
+    let destinations = parse(
+        strings,
+        ENV_OUTBOUND_EXTERNAL_TLS_DESTINATIONS,
+        parse_external_tls_destinations,
+    )?;
+    let dir = parse(strings, ENV_OUTBOUND_EXTERNAL_TLS_DIR, |s| {
+        Ok(PathBuf::from(s))
+    })?;
+    let (destinations, dir) = match (destinations, dir) {
+        (None, _) => return Ok(Default::default()),
+        (Some(destinations), Some(dir)) => (destinations, dir),
+        (Some(_), None) => {
+            error!(
+                "{} must be set when {} is set",
+                ENV_OUTBOUND_EXTERNAL_TLS_DIR, ENV_OUTBOUND_EXTERNAL_TLS_DESTINATIONS
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+    };
+
+    // Group networks by server name, preserving the configured order so that
+    // the first matching destination is used.
+    let mut by_name = Vec::<(identity::Name, Vec<ipnet::IpNet>)>::new();
+    for (name, net) in destinations {
+        match by_name.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, nets)) => nets.push(net),
+            None => by_name.push((name, vec![net])),
+        }
+    }
+
+    let mut configs = Vec::with_capacity(by_name.len());
+    for (name, nets) in by_name {
+        let read = |file: &str| {
+            let path = dir.join(name.as_ref()).join(file);
+            fs::read_to_string(&path).map_err(|e| {
+                error!(server.name = %name, "Failed to read {}: {}", path.display(), e);
+                EnvError::InvalidEnvVar
+            })
+        };
+        let trust_anchors = read("ca.pem")?;
+        let crt = read("crt.pem")?;
+        let key = read("key.pem")?;
+        let config =
+            ClientConfig::from_pem(name.clone(), &trust_anchors, &crt, &key).map_err(|e| {
+                error!(server.name = %name, "Invalid off-mesh TLS configuration: {}", e);
+                EnvError::InvalidEnvVar
+            })?;
+        configs.push((IpMatch::new(nets), config));
+    }
+
+    Ok(outbound::tcp::external_tls::Config::new(configs))
+}
+
+fn parse_terminate_tls_config<S: Strings>(
+    strings: &S,
+) -> Result<inbound::TerminateTlsForPorts, EnvError> {
+    use crate::core::tls::external::ServerConfig;
+
+    let ports = parse(strings, ENV_INBOUND_TLS_TERMINATE_PORTS, parse_port_names)?;
+    let dir = parse(strings, ENV_INBOUND_TLS_TERMINATE_DIR, |s| {
+        Ok(PathBuf::from(s))
+    })?;
+    let (ports, dir) = match (ports, dir) {
+        (None, _) => return Ok(Default::default()),
+        (Some(ports), Some(dir)) => (ports, dir),
+        (Some(_), None) => {
+            error!(
+                "{} must be set when {} is set",
+                ENV_INBOUND_TLS_TERMINATE_DIR, ENV_INBOUND_TLS_TERMINATE_PORTS
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+    };
+
+    // Group names by port, preserving the configured order so that the first
+    // certificate is used by default.
+    let mut by_port = Vec::<(u16, Vec<String>)>::new();
+    for (port, name) in ports {
+        match by_port.iter_mut().find(|(p, _)| *p == port) {
+            Some((_, names)) => names.push(name),
+            None => by_port.push((port, vec![name])),
         }
     }
-    Ok(nets)
+
+    let mut configs = Vec::with_capacity(by_port.len());
+    for (port, names) in by_port {
+        let mut pems = Vec::with_capacity(names.len());
+        for name in names.iter() {
+            let read = |file: &str| {
+                let path = dir.join(name).join(file);
+                fs::read_to_string(&path).map_err(|e| {
+                    error!(%port, %name, "Failed to read {}: {}", path.display(), e);
+                    EnvError::InvalidEnvVar
+                })
+            };
+            pems.push((read("crt.pem")?, read("key.pem")?));
+        }
+        let config =
+            ServerConfig::from_pem(pems.iter().map(|(crt, key)| (crt.as_str(), key.as_str())))
+                .map_err(|e| {
+                    error!(%port, "Invalid TLS termination configuration: {}", e);
+                    EnvError::InvalidEnvVar
+                })?;
+        configs.push((port, config));
+    }
+
+    Ok(inbound::TerminateTlsForPorts::new(configs))
 }
 
 pub fn parse_backoff<S: Strings>(
@@ -1217,4 +3582,561 @@ mod tests {
             "names are coerced to lowercase"
         );
     }
+
+    #[test]
+    fn grpc_routes() {
+        assert_eq!(parse_grpc_routes(""), Ok(vec![]));
+        assert_eq!(
+            parse_grpc_routes(" /helloworld.Greeter/ ,/foo.Bar/Baz"),
+            Ok(vec![
+                "/helloworld.Greeter/".to_owned(),
+                "/foo.Bar/Baz".to_owned()
+            ])
+        );
+        assert_eq!(
+            parse_grpc_routes("helloworld.Greeter"),
+            Err(ParseError::UnsupportedValue(
+                "helloworld.Greeter".to_owned()
+            )),
+            "routes must be paths"
+        );
+    }
+
+    #[test]
+    fn close_delimited() {
+        assert_eq!(
+            parse_close_delimited("pass-through", 10),
+            Ok(CloseDelimited::PassThrough)
+        );
+        assert_eq!(
+            parse_close_delimited(" buffer ", 10),
+            Ok(CloseDelimited::Buffer { max_bytes: 10 })
+        );
+        assert_eq!(
+            parse_close_delimited("reject", 10),
+            Ok(CloseDelimited::Reject)
+        );
+        assert_eq!(
+            parse_close_delimited("close", 10),
+            Err(ParseError::UnsupportedValue("close".to_owned()))
+        );
+    }
+
+    #[test]
+    fn breaker_thresholds() {
+        assert_eq!(
+            parse_breaker_thresholds("5/10s"),
+            Ok(BreakerThresholds {
+                max_failures: 5,
+                open_timeout: Duration::from_secs(10),
+            })
+        );
+        assert_eq!(
+            parse_breaker_thresholds(" 0 ").map(|t| t.max_failures),
+            Ok(0)
+        );
+        assert_eq!(
+            parse_breaker_thresholds("5"),
+            Err(ParseError::UnsupportedValue("5".to_owned()))
+        );
+        assert!(parse_breaker_thresholds("5/soon").is_err());
+    }
+
+    #[test]
+    fn port_local_breakers() {
+        assert_eq!(
+            parse_port_local_breakers("8080=3/5s, 9090=0"),
+            Ok(vec![
+                (
+                    8080,
+                    BreakerThresholds {
+                        max_failures: 3,
+                        open_timeout: Duration::from_secs(5),
+                    }
+                ),
+                (
+                    9090,
+                    BreakerThresholds {
+                        max_failures: 0,
+                        open_timeout: Duration::from_secs(0),
+                    }
+                ),
+            ])
+        );
+        assert_eq!(parse_port_local_breakers(""), Ok(vec![]));
+        assert!(parse_port_local_breakers("8080").is_err());
+        assert!(parse_port_local_breakers("8080=5").is_err());
+    }
+
+    #[test]
+    fn balance_algorithm() {
+        assert_eq!(
+            parse_balance_algorithm("peak-ewma"),
+            Ok(balance::Algorithm::PeakEwma)
+        );
+        assert_eq!(
+            parse_balance_algorithm("least-request"),
+            Ok(balance::Algorithm::LeastRequest)
+        );
+        assert_eq!(
+            parse_balance_algorithm("latency-ewma"),
+            Ok(balance::Algorithm::LatencyEwma)
+        );
+        assert_eq!(
+            parse_balance_algorithm(" round-robin "),
+            Ok(balance::Algorithm::RoundRobin)
+        );
+        assert_eq!(
+            parse_balance_algorithm("random"),
+            Ok(balance::Algorithm::Random)
+        );
+        assert_eq!(
+            parse_balance_algorithm("fastest"),
+            Err(ParseError::UnsupportedValue("fastest".to_owned()))
+        );
+    }
+
+    #[test]
+    fn via_pseudonyms() {
+        assert_eq!(parse_via(" linkerd "), Ok(Via::new("linkerd").unwrap()));
+        assert_eq!(
+            parse_via("linkerd proxy"),
+            Err(ParseError::UnsupportedValue("linkerd proxy".to_owned()))
+        );
+    }
+
+    #[test]
+    fn optional_header_names() {
+        assert_eq!(
+            parse_optional_header_name(" x-proxy-error "),
+            Ok(Some(HeaderName::from_static("x-proxy-error")))
+        );
+        assert_eq!(parse_optional_header_name(""), Ok(None));
+        assert_eq!(parse_optional_header_name("  "), Ok(None));
+        assert!(parse_optional_header_name("bad header").is_err());
+    }
+
+    #[test]
+    fn trace_attributes() {
+        assert!(parse_trace_attributes("route, client_id, header:x-tenant").is_ok());
+        assert!(parse_trace_attributes("").is_ok());
+        assert!(parse_trace_attributes("x-tenant").is_err());
+        assert!(parse_trace_attributes("header:bad header").is_err());
+    }
+
+    #[test]
+    fn probabilities() {
+        assert_eq!(parse_probability("0"), Ok(0.0));
+        assert_eq!(parse_probability(" 0.5 "), Ok(0.5));
+        assert_eq!(parse_probability("1"), Ok(1.0));
+        assert_eq!(
+            parse_probability("1.5"),
+            Err(ParseError::UnsupportedValue("1.5".to_owned()))
+        );
+        assert_eq!(
+            parse_probability("-0.1"),
+            Err(ParseError::UnsupportedValue("-0.1".to_owned()))
+        );
+        assert!(parse_probability("NaN").is_err());
+    }
+
+    #[test]
+    fn external_tls_destinations() {
+        fn p(s: &str) -> Result<Vec<(String, String)>, ParseError> {
+            let destinations = parse_external_tls_destinations(s)?;
+            Ok(destinations
+                .into_iter()
+                .map(|(name, net)| (name.to_string(), net.to_string()))
+                .collect())
+        }
+
+        assert_eq!(p(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            p(" api.example.com = 192.0.2.0/24 , db.example.org=198.51.100.7/32 "),
+            Ok(vec![
+                ("api.example.com".to_owned(), "192.0.2.0/24".to_owned()),
+                ("db.example.org".to_owned(), "198.51.100.7/32".to_owned()),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            p("api.example.com"),
+            Err(ParseError::UnsupportedValue("api.example.com".to_owned())),
+            "a network is required"
+        );
+        assert_eq!(
+            p("api.example.com=192.0.2.1"),
+            Err(ParseError::NotANetwork),
+            "the network must have a prefix length"
+        );
+    }
+
+    #[test]
+    fn port_classes() {
+        assert_eq!(parse_port_classes(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_classes(" 8080 = api , 9990=admin "),
+            Ok(vec![(8080, "api".to_owned()), (9990, "admin".to_owned())]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_port_classes("8080"),
+            Err(ParseError::UnsupportedValue("8080".to_owned())),
+            "a class is required"
+        );
+        assert_eq!(
+            parse_port_classes("8080=\"api\""),
+            Err(ParseError::UnsupportedValue("\"api\"".to_owned())),
+            "classes must be valid label values"
+        );
+        assert!(
+            parse_port_classes("http=api").is_err(),
+            "ports must be numbers"
+        );
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(parse_status_codes(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_status_codes(" 429, 503 "),
+            Ok(vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::SERVICE_UNAVAILABLE
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_status_codes("1000"),
+            Err(ParseError::UnsupportedValue("1000".to_owned())),
+            "codes must be valid status codes"
+        );
+        assert!(parse_status_codes("5xx").is_err(), "codes must be numbers");
+    }
+
+    #[test]
+    fn canary() {
+        assert_eq!(
+            parse_label(" track = canary "),
+            Ok(("track".to_string(), "canary".to_string()))
+        );
+        assert!(parse_label("track").is_err(), "a value is required");
+        assert!(parse_label("=canary").is_err(), "a label is required");
+        assert_eq!(parse_percent("10"), Ok(10));
+        assert_eq!(parse_percent("100"), Ok(100));
+        assert!(
+            parse_percent("101").is_err(),
+            "percentages must not exceed 100"
+        );
+    }
+
+    #[test]
+    fn addr_pairs() {
+        let addr = |s| NameAddr::from_str(s).unwrap();
+        assert_eq!(parse_addr_pairs(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_addr_pairs(" web.ns.svc.cluster.local:80 = web.backup.svc.cluster.local:80 ,"),
+            Ok(vec![(
+                addr("web.ns.svc.cluster.local:80"),
+                addr("web.backup.svc.cluster.local:80")
+            )]),
+            "whitespace is ignored"
+        );
+        assert!(
+            parse_addr_pairs("web.ns.svc.cluster.local:80").is_err(),
+            "a pair is required"
+        );
+        assert!(
+            parse_addr_pairs("web.ns.svc.cluster.local=web.backup.svc.cluster.local").is_err(),
+            "ports are required"
+        );
+    }
+
+    #[test]
+    fn dscp_ports() {
+        let dscp = |v| Dscp::new(v).unwrap();
+        assert_eq!(parse_dscp_ports(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_dscp_ports(" 5432 = 46, 8080=0 "),
+            Ok(vec![(5432, dscp(46)), (8080, dscp(0))]),
+            "whitespace is ignored"
+        );
+        assert_eq!(parse_dscp("63"), Ok(dscp(63)));
+        assert_eq!(
+            parse_dscp("64"),
+            Err(ParseError::UnsupportedValue("64".to_owned())),
+            "DSCP values must fit in six bits"
+        );
+        assert!(parse_dscp("-1").is_err(), "DSCP values must be positive");
+        assert!(
+            parse_dscp_ports("5432").is_err(),
+            "a DSCP value is required"
+        );
+    }
+
+    #[test]
+    fn port_rates() {
+        assert_eq!(parse_port_rates(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_rates(" 8080 = 100, 9090=0 "),
+            Ok(vec![(8080, 100), (9090, 0)]),
+            "whitespace is ignored"
+        );
+        assert!(parse_port_rates("8080").is_err(), "a rate is required");
+        assert!(
+            parse_port_rates("8080=-1").is_err(),
+            "rates must be positive"
+        );
+    }
+
+    #[test]
+    fn push_labels() {
+        assert_eq!(parse_push_label(" pod-0.ns "), Ok("pod-0.ns".to_string()));
+        assert_eq!(
+            parse_push_label("10.0.0.1:4191"),
+            Ok("10.0.0.1:4191".to_string())
+        );
+        assert_eq!(
+            parse_push_label(""),
+            Err(ParseError::UnsupportedValue("".to_string()))
+        );
+        assert_eq!(
+            parse_push_label("a/b"),
+            Err(ParseError::UnsupportedValue("a/b".to_string())),
+            "labels are URI path segments"
+        );
+    }
+
+    #[test]
+    fn port_durations() {
+        assert_eq!(parse_port_durations(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_durations(" 5432 = 10m, 6379=30s "),
+            Ok(vec![
+                (5432, Duration::from_secs(600)),
+                (6379, Duration::from_secs(30))
+            ]),
+        );
+        assert_eq!(
+            parse_port_durations("5432=10"),
+            Err(ParseError::NotADuration),
+            "durations require units"
+        );
+        assert!(
+            parse_port_durations("5432").is_err(),
+            "a duration is required"
+        );
+    }
+
+    #[test]
+    fn identity_tiers() {
+        use inbound::IdentityPattern;
+
+        assert_eq!(parse_identity_tiers(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_identity_tiers(" *.ns.example.com = 100, web.ns.example.com=10 "),
+            Ok(vec![
+                (IdentityPattern::Suffix("ns.example.com".to_string()), 100),
+                (
+                    IdentityPattern::Exact(identity::Name::from_str("web.ns.example.com").unwrap()),
+                    10
+                ),
+            ])
+        );
+        assert!(
+            parse_identity_tiers("*.ns.example.com").is_err(),
+            "a limit is required"
+        );
+        assert!(
+            parse_identity_tiers("*=10").is_err(),
+            "wildcards must have a suffix"
+        );
+    }
+
+    #[test]
+    fn port_connection_rates() {
+        use inbound::ConnectionRateLimit;
+
+        assert_eq!(parse_port_connection_rates(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_connection_rates(" 8080 = 100 / 200, 9090=10 "),
+            Ok(vec![
+                (
+                    8080,
+                    ConnectionRateLimit {
+                        per_second: 100,
+                        burst: 200
+                    }
+                ),
+                (
+                    9090,
+                    ConnectionRateLimit {
+                        per_second: 10,
+                        burst: 10
+                    }
+                ),
+            ]),
+            "bursts default to the rate"
+        );
+        assert!(
+            parse_port_connection_rates("8080").is_err(),
+            "a rate is required"
+        );
+        assert!(
+            parse_port_connection_rates("8080=10/").is_err(),
+            "bursts must be numbers"
+        );
+    }
+
+    #[test]
+    fn client_auth_ports() {
+        use tls::server::ClientAuth;
+
+        assert_eq!(parse_client_auth_ports(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_client_auth_ports(" 8080 = require, 9090=request ,4191=none"),
+            Ok(vec![
+                (8080, ClientAuth::Require),
+                (9090, ClientAuth::Request),
+                (4191, ClientAuth::None),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_client_auth_ports("8080=optional"),
+            Err(ParseError::UnsupportedValue("optional".to_owned())),
+            "modes must be known"
+        );
+        assert_eq!(
+            parse_client_auth_ports("8080"),
+            Err(ParseError::UnsupportedValue("8080".to_owned())),
+            "a mode is required"
+        );
+    }
+
+    #[test]
+    fn ports_metrics_granularity() {
+        use inbound::MetricsGranularity;
+
+        assert_eq!(
+            parse_ports_metrics_granularity(""),
+            Ok(vec![]),
+            "empty string"
+        );
+        assert_eq!(
+            parse_ports_metrics_granularity(" 8080 = detailed, 9090=minimal ,5432=off"),
+            Ok(vec![
+                (8080, MetricsGranularity::Detailed),
+                (9090, MetricsGranularity::Minimal),
+                (5432, MetricsGranularity::Off),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_ports_metrics_granularity("8080=verbose"),
+            Err(ParseError::UnsupportedValue("verbose".to_owned())),
+            "granularities must be known"
+        );
+    }
+
+    #[test]
+    fn port_names() {
+        assert_eq!(parse_port_names(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_names(" 443 = example.com, 443=example.org ,8443=admin"),
+            Ok(vec![
+                (443, "example.com".to_owned()),
+                (443, "example.org".to_owned()),
+                (8443, "admin".to_owned()),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_port_names("443=../certs"),
+            Err(ParseError::UnsupportedValue("../certs".to_owned())),
+            "names may not refer to other directories"
+        );
+        assert_eq!(
+            parse_port_names("443"),
+            Err(ParseError::UnsupportedValue("443".to_owned())),
+            "a name is required"
+        );
+    }
+
+    #[test]
+    fn ports() {
+        assert_eq!(parse_port("8080"), Ok(8080));
+        assert_eq!(
+            parse_port("0"),
+            Err(ParseError::UnsupportedValue("0".to_owned())),
+            "port 0 is not a valid destination"
+        );
+        assert!(parse_port("65536").is_err());
+        assert!(parse_port("").is_err());
+    }
+
+    #[test]
+    fn sni_routes() {
+        assert_eq!(parse_sni_routes(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_sni_routes(" 443:example.com = 8443, 443:example.org=9443"),
+            Ok(vec![
+                (443, "example.com".parse().unwrap(), 8443),
+                (443, "example.org".parse().unwrap(), 9443),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_sni_routes("443=8443"),
+            Err(ParseError::UnsupportedValue("443=8443".to_owned())),
+            "a server name is required"
+        );
+        assert!(parse_sni_routes("443:example.com").is_err());
+        assert_eq!(
+            parse_sni_routes("443:example.com=0"),
+            Err(ParseError::UnsupportedValue("0".to_owned())),
+            "target ports must be valid"
+        );
+        assert_eq!(
+            parse_sni_routes("0:example.com=8443"),
+            Err(ParseError::UnsupportedValue("0".to_owned())),
+            "routed ports must be valid"
+        );
+        assert!(parse_sni_routes("70000:example.com=8443").is_err());
+    }
+
+    #[test]
+    fn port_networks() {
+        assert_eq!(parse_port_networks(""), Ok(vec![]), "empty string");
+        assert_eq!(
+            parse_port_networks(" 8080 = 10.0.0.0/8, 8080=fd00::/8 ,9090=192.168.0.0/16"),
+            Ok(vec![
+                (8080, "10.0.0.0/8".parse().unwrap()),
+                (8080, "fd00::/8".parse().unwrap()),
+                (9090, "192.168.0.0/16".parse().unwrap()),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            parse_port_networks("8080=10.0.0.0/33"),
+            Err(ParseError::NotANetwork),
+            "networks must be valid"
+        );
+        assert_eq!(
+            parse_port_networks("8080"),
+            Err(ParseError::UnsupportedValue("8080".to_owned())),
+            "a network is required"
+        );
+    }
+
+    #[test]
+    fn ip_addrs() {
+        assert_eq!(parse_ip_addr("127.0.0.1"), Ok(Ipv4Addr::LOCALHOST.into()));
+        assert_eq!(parse_ip_addr(" ::1 "), Ok("::1".parse().unwrap()));
+        assert_eq!(
+            parse_ip_addr("localhost"),
+            Err(ParseError::HostIsNotAnIpAddress),
+            "names are not IP addresses"
+        );
+    }
 }
@@ -8,6 +8,7 @@ pub mod env;
 pub mod identity;
 pub mod oc_collector;
 pub mod tap;
+pub mod watchdog;
 
 pub use self::metrics::Metrics;
 use futures::{future, FutureExt, TryFutureExt};
@@ -16,7 +17,8 @@ pub use linkerd_app_core::{self as core, metrics, trace};
 use linkerd_app_core::{
     config::ServerConfig,
     control::ControlAddr,
-    dns, drain,
+    dns, drain, serve,
+    shutdown::{self, ShutdownEvents},
     svc::Param,
     transport::{listen::Bind, ClientAddr, Local, OrigDstAddr, Remote, ServerAddr},
     Error, ProxyRuntime,
@@ -56,6 +58,13 @@ pub struct Config {
     pub admin: admin::Config,
     pub tap: tap::Config,
     pub oc_collector: oc_collector::Config,
+    pub watchdog: watchdog::Config,
+    pub shutdown_events: shutdown::Config,
+
+    /// How long connections may remain open once the proxy begins draining
+    /// before they are closed forcibly. If unset, connections may remain open
+    /// until they complete.
+    pub shutdown_grace_period: Option<Duration>,
 }
 
 pub struct App {
@@ -66,8 +75,17 @@ pub struct App {
     inbound_addr: Local<ServerAddr>,
     oc_collector: oc_collector::OcCollector,
     outbound_addr: Local<ServerAddr>,
+    shutdown: ShutdownEvents,
     start_proxy: Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>,
     tap: tap::Tap,
+    watchdog: watchdog::Watchdog,
+}
+
+/// Drains the proxy once it is spawned, recording each phase of its shutdown.
+pub struct Drain {
+    signal: drain::Signal,
+    shutdown: ShutdownEvents,
+    watchdog: watchdog::Watchdog,
 }
 
 impl Config {
@@ -109,9 +127,15 @@ impl Config {
             outbound,
             gateway,
             tap,
+            watchdog,
+            shutdown_events,
+            shutdown_grace_period,
         } = self;
         debug!("building app");
-        let (metrics, report) = Metrics::new(admin.metrics_retain_idle);
+        let (metrics, report) = Metrics::new(
+            admin.metrics_retain_idle,
+            admin.metrics_status_labels.clone(),
+        );
 
         let dns = dns.build();
 
@@ -119,12 +143,21 @@ impl Config {
             .in_scope(|| identity.build(dns.resolver.clone(), metrics.control.clone()))?;
         let report = identity.metrics().and_then(report);
 
+        let shutdown = shutdown_events.build();
+        let report = shutdown.clone().and_then(report);
+        let shutdown_grace = serve::GracePeriod::new(shutdown_grace_period);
+        let report = shutdown_grace.clone().and_then(report);
+
         let (drain_tx, drain_rx) = drain::channel();
 
         let tap = {
             let bind = bind_admin.clone();
             info_span!("tap").in_scope(|| tap.build(bind, identity.local(), drain_rx.clone()))?
         };
+        let report = tap.registry().and_then(report);
+
+        let watchdog = watchdog.build();
+        let report = watchdog.metrics().and_then(report);
 
         let dst = {
             let metrics = metrics.control.clone();
@@ -167,7 +200,10 @@ impl Config {
                 metrics: metrics.inbound,
                 tap: tap.registry(),
                 span_sink: oc_collector.span_sink(),
+                trace_sampler: oc_collector.trace_sampler(),
                 drain: drain_rx.clone(),
+                shutdown: shutdown.clone(),
+                shutdown_grace: shutdown_grace.clone(),
             },
         );
 
@@ -178,7 +214,10 @@ impl Config {
                 metrics: metrics.outbound,
                 tap: tap.registry(),
                 span_sink: oc_collector.span_sink(),
+                trace_sampler: oc_collector.trace_sampler(),
                 drain: drain_rx,
+                shutdown: shutdown.clone(),
+                shutdown_grace: shutdown_grace.clone(),
             },
         );
 
@@ -191,8 +230,9 @@ impl Config {
         );
 
         let (inbound_addr, inbound_serve) =
-            inbound.serve(bind_in, dst.profiles.clone(), gateway_stack);
-        let (outbound_addr, outbound_serve) = outbound.serve(bind_out, dst.profiles, dst.resolve);
+            inbound.serve(bind_in, dst.profiles.clone(), gateway_stack)?;
+        let (outbound_addr, outbound_serve) =
+            outbound.serve(bind_out, dst.profiles, dst.resolve)?;
 
         let start_proxy = Box::pin(async move {
             tokio::spawn(outbound_serve.instrument(info_span!("outbound")));
@@ -207,8 +247,10 @@ impl Config {
             inbound_addr,
             oc_collector,
             outbound_addr,
+            shutdown,
             start_proxy,
             tap,
+            watchdog,
         })
     }
 }
@@ -258,17 +300,24 @@ impl App {
         }
     }
 
-    pub fn spawn(self) -> drain::Signal {
+    pub fn spawn(self) -> Drain {
         let App {
             admin,
             drain,
             identity,
             oc_collector,
+            shutdown,
             start_proxy,
             tap,
+            watchdog,
             ..
         } = self;
 
+        // The background tasks run on the admin thread, so they are monitored
+        // from the main runtime.
+        let drain_watchdog = watchdog.clone();
+        tokio::spawn(watchdog.clone().monitor());
+
         // Run a daemon thread for all administrative tasks.
         //
         // The main reactor holds `admin_shutdown_tx` until the reactor drops
@@ -288,15 +337,26 @@ impl App {
                         debug!("running admin thread");
 
                         // Start the admin server to serve the readiness endpoint.
-                        tokio::spawn(
+                        watchdog.spawn(
+                            "admin",
                             admin
                                 .serve
                                 .instrument(info_span!("admin", listen.addr = %admin.listen_addr)),
                         );
 
+                        // The push task completes once the proxy is drained,
+                        // which the watchdog doesn't report as a failure.
+                        if let Some(push) = admin.push {
+                            watchdog
+                                .spawn("metrics_push", push.instrument(info_span!("metrics_push")));
+                        }
+
                         // Kick off the identity so that the process can become ready.
                         if let identity::Identity::Enabled { local, task, .. } = identity {
-                            tokio::spawn(task.instrument(info_span!("identity")));
+                            // The proxy cannot serve meshed traffic without
+                            // its identity, so its task is never restarted.
+                            watchdog
+                                .spawn_fatal("identity", task.instrument(info_span!("identity")));
 
                             let latch = admin.latch;
                             tokio::spawn(
@@ -320,14 +380,22 @@ impl App {
                             registry, serve, ..
                         } = tap
                         {
-                            let clean = time::interval(Duration::from_secs(60));
-                            let clean = tokio_stream::wrappers::IntervalStream::new(clean);
-                            tokio::spawn(registry.clean(clean).instrument(info_span!("tap_clean")));
-                            tokio::spawn(serve.instrument(info_span!("tap")));
+                            watchdog.spawn_restartable("tap_clean", move || {
+                                let clean = time::interval(Duration::from_secs(60));
+                                let clean = tokio_stream::wrappers::IntervalStream::new(clean);
+                                registry
+                                    .clone()
+                                    .clean(clean)
+                                    .instrument(info_span!("tap_clean"))
+                            });
+                            watchdog.spawn("tap", serve.instrument(info_span!("tap")));
                         }
 
                         if let oc_collector::OcCollector::Enabled(oc) = oc_collector {
-                            tokio::spawn(oc.task.instrument(info_span!("opencensus")));
+                            let mut new_task = oc.new_task;
+                            watchdog.spawn_restartable("opencensus", move || {
+                                new_task().instrument(info_span!("opencensus"))
+                            });
                         }
 
                         // we don't care if the admin shutdown channel is
@@ -341,6 +409,21 @@ impl App {
 
         tokio::spawn(start_proxy);
 
-        drain
+        Drain {
+            signal: drain,
+            shutdown,
+            watchdog: drain_watchdog,
+        }
+    }
+}
+
+impl Drain {
+    /// Signals all listeners to stop accepting connections and completes once
+    /// all connections have closed.
+    pub async fn drain(self) {
+        self.watchdog.shutdown();
+        self.shutdown.drain_initiated();
+        self.signal.drain().await;
+        self.shutdown.drained();
     }
 }
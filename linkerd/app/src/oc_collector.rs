@@ -1,9 +1,11 @@
 use crate::{dns, identity::LocalCrtKey};
-use linkerd_app_core::{control, metrics::ControlHttp as HttpMetrics, svc::NewService, Error};
+use linkerd_app_core::{
+    control, http_tracing, metrics::ControlHttp as HttpMetrics, proxy::http::HeaderName,
+    svc::NewService, Error,
+};
 use linkerd_opencensus::{self as opencensus, metrics, proto};
-use std::{collections::HashMap, future::Future, pin::Pin, time::SystemTime};
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::SystemTime};
+use tokio::sync::{mpsc, Mutex};
 use tracing::Instrument;
 
 #[derive(Clone, Debug)]
@@ -17,11 +19,31 @@ pub struct EnabledConfig {
     pub control: control::Config,
     pub attributes: HashMap<String, String>,
     pub hostname: Option<String>,
+    pub protocol: Protocol,
+    /// The fraction, from 0 to 1, of requests without an upstream sampling
+    /// decision that are traced.
+    pub sample_ratio: f64,
+    /// Forces requests with this header to be traced.
+    pub force_sample_header: Option<HeaderName>,
+}
+
+/// The protocol with which spans are exported to the collector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// Spans are streamed to an OpenCensus agent.
+    OpenCensus,
+
+    /// Spans are sent in batches to an OpenTelemetry collector via OTLP.
+    Otlp,
 }
 
 pub type Task = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
-pub type SpanSink = mpsc::Sender<proto::trace::v1::Span>;
+/// Builds a new span export task each time it is called, so that the task may
+/// be restarted if it stops.
+pub type NewTask = Box<dyn FnMut() -> Task + Send + 'static>;
+
+pub type SpanSink = http_tracing::SpanSink;
 
 pub enum OcCollector {
     Disabled,
@@ -31,7 +53,8 @@ pub enum OcCollector {
 pub struct EnabledCollector {
     pub addr: control::ControlAddr,
     pub span_sink: SpanSink,
-    pub task: Task,
+    pub trace_sampler: http_tracing::Sampler,
+    pub new_task: NewTask,
 }
 
 impl Config {
@@ -42,7 +65,7 @@ impl Config {
         self,
         identity: Option<LocalCrtKey>,
         dns: dns::Resolver,
-        metrics: metrics::Registry,
+        mut metrics: metrics::Registry,
         client_metrics: HttpMetrics,
     ) -> Result<OcCollector, Error> {
         match self {
@@ -54,10 +77,19 @@ impl Config {
                     .build(dns, client_metrics, identity)
                     .new_service(());
 
-                let (span_sink, spans_rx) = mpsc::channel(Self::SPAN_BUFFER_CAPACITY);
-                let spans_rx = ReceiverStream::new(spans_rx);
+                let (span_tx, spans_rx) = mpsc::channel(Self::SPAN_BUFFER_CAPACITY);
+                // Each export task holds the receiver while it runs so that a
+                // restarted task resumes exporting from the same channel.
+                let spans_rx = Arc::new(Mutex::new(spans_rx));
+                let span_sink = SpanSink::new(span_tx, metrics.clone());
+
+                // Sampling decisions are made when requests enter the proxy
+                // and are propagated downstream with the trace context.
+                metrics.set_sample_ratio(inner.sample_ratio);
+                let trace_sampler =
+                    http_tracing::Sampler::new(inner.sample_ratio, inner.force_sample_header);
 
-                let task = {
+                let new_task = {
                     use self::proto::agent::common::v1 as oc;
 
                     let node = oc::Node {
@@ -74,16 +106,39 @@ impl Config {
                     };
 
                     let addr = addr.clone();
-                    Box::pin(
-                        opencensus::export_spans(svc, node, spans_rx, metrics)
-                            .instrument(tracing::debug_span!("opencensus", peer.addr = %addr)),
-                    )
+                    let protocol = inner.protocol;
+                    Box::new(move || -> Task {
+                        let svc = svc.clone();
+                        let node = node.clone();
+                        let spans_rx = spans_rx.clone();
+                        let metrics = metrics.clone();
+                        let export = async move {
+                            let mut spans_rx = spans_rx.lock_owned().await;
+                            let spans = futures::stream::poll_fn(move |cx| spans_rx.poll_recv(cx));
+                            match protocol {
+                                Protocol::OpenCensus => {
+                                    opencensus::export_spans(svc, node, spans, metrics).await
+                                }
+                                Protocol::Otlp => {
+                                    opencensus::otlp::export_spans(svc, node, spans, metrics).await
+                                }
+                            }
+                        };
+                        let span = match protocol {
+                            Protocol::OpenCensus => {
+                                tracing::debug_span!("opencensus", peer.addr = %addr)
+                            }
+                            Protocol::Otlp => tracing::debug_span!("otlp", peer.addr = %addr),
+                        };
+                        Box::pin(export.instrument(span))
+                    })
                 };
 
                 Ok(OcCollector::Enabled(Box::new(EnabledCollector {
                     addr,
-                    task,
+                    new_task,
                     span_sink,
+                    trace_sampler,
                 })))
             }
         }
@@ -97,4 +152,13 @@ impl OcCollector {
             OcCollector::Enabled(inner) => Some(inner.span_sink.clone()),
         }
     }
+
+    /// Decides whether requests that do not carry a sampling decision are
+    /// traced. Requests are never sampled when the collector is disabled.
+    pub fn trace_sampler(&self) -> http_tracing::Sampler {
+        match self {
+            OcCollector::Disabled => http_tracing::Sampler::default(),
+            OcCollector::Enabled(inner) => inner.trace_sampler.clone(),
+        }
+    }
 }
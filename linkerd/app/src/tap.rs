@@ -19,6 +19,10 @@ pub enum Config {
     Enabled {
         config: ServerConfig,
         permitted_client_ids: HashSet<tls::server::ClientId>,
+
+        /// If set, limits the number of tap subscriptions that may be active
+        /// at once. Additional subscriptions are rejected.
+        max_subscriptions: Option<usize>,
     },
 }
 
@@ -49,7 +53,13 @@ impl Config {
         B: Bind<ServerConfig>,
         B::Addrs: Param<Remote<ClientAddr>>,
     {
-        let (registry, server) = tap::new();
+        let max_taps = match self {
+            Config::Disabled => None,
+            Config::Enabled {
+                max_subscriptions, ..
+            } => max_subscriptions,
+        };
+        let (registry, server) = tap::new(max_taps);
         match self {
             Config::Disabled => {
                 drop(server);
@@ -58,6 +68,7 @@ impl Config {
             Config::Enabled {
                 config,
                 permitted_client_ids,
+                ..
             } => {
                 let (listen_addr, listen) = bind.bind(&config)?;
                 let accept = svc::stack(server)
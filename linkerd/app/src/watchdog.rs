@@ -0,0 +1,485 @@
+//! Supervises the proxy's long-lived background tasks.
+//!
+//! Each supervised task runs in its own Tokio task so that the watchdog
+//! observes it stopping, whether it completes, panics, or is aborted. Stopped
+//! tasks are logged and counted; tasks that can be rebuilt are restarted when
+//! restarts are enabled, and tasks without which the proxy cannot function are
+//! reported as fatal. Tasks that stop once the proxy has begun to shut down
+//! are expected to stop, so they are neither reported nor restarted.
+//!
+//! When a heartbeat timeout is configured, each supervised task also sends
+//! heartbeats as it is polled. A task that blocks its thread, or that never
+//! returns from a poll, stops sending heartbeats and is reported as hung.
+
+use futures::future;
+use linkerd_app_core::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+metrics! {
+    process_task_stopped_total: Counter {
+        "The total number of times a background task stopped unexpectedly."
+    },
+    process_task_restarts_total: Counter {
+        "The total number of times a stopped background task was restarted."
+    },
+    process_task_hung_total: Counter {
+        "The total number of times a background task stopped sending heartbeats."
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// If set, restartable tasks are restarted after this delay when they
+    /// stop. Otherwise, stopped tasks are only reported.
+    pub restart_delay: Option<Duration>,
+
+    /// If set, tasks that do not send a heartbeat within this timeout are
+    /// reported as hung.
+    pub heartbeat_timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    restart_delay: Option<Duration>,
+    heartbeat_timeout: Option<Duration>,
+    heartbeats: Arc<Mutex<HashMap<&'static str, Arc<Heartbeat>>>>,
+    shutdown: Arc<AtomicBool>,
+    metrics: Metrics,
+}
+
+/// Records when a supervised task last sent a heartbeat.
+#[derive(Debug)]
+struct Heartbeat {
+    last: Mutex<Instant>,
+    fatal: bool,
+    hung: AtomicBool,
+}
+
+/// Reports the number of times each supervised task stopped, restarted, and
+/// hung.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Mutex<HashMap<&'static str, TaskMetrics>>>);
+
+#[derive(Debug, Default)]
+struct TaskMetrics {
+    stopped: Counter,
+    restarts: Counter,
+    hung: Counter,
+}
+
+struct TaskLabel(&'static str);
+
+// === impl Config ===
+
+impl Config {
+    pub fn build(self) -> Watchdog {
+        Watchdog {
+            restart_delay: self.restart_delay,
+            heartbeat_timeout: self.heartbeat_timeout,
+            heartbeats: Default::default(),
+            shutdown: Default::default(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+// === impl Watchdog ===
+
+impl Watchdog {
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Indicates that the proxy is shutting down, so that tasks that stop
+    /// are not reported.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    /// Reports tasks that stop sending heartbeats, until the proxy shuts
+    /// down.
+    ///
+    /// This should run on a different runtime than the supervised tasks, so
+    /// that it is not blocked by a hung task.
+    pub async fn monitor(self) {
+        let timeout = match self.heartbeat_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        let mut interval = time::interval(Self::heartbeat_interval(timeout));
+        while !self.shutdown.load(Ordering::Acquire) {
+            interval.tick().await;
+            let now = Instant::now();
+            for (name, heartbeat) in self.heartbeats.lock().iter() {
+                let elapsed = now.saturating_duration_since(*heartbeat.last.lock());
+                if elapsed < timeout {
+                    if heartbeat.hung.swap(false, Ordering::AcqRel) {
+                        info!(task = name, "Background task recovered");
+                    }
+                    continue;
+                }
+                if heartbeat.hung.swap(true, Ordering::AcqRel) {
+                    continue;
+                }
+                self.metrics.hung(name);
+                if heartbeat.fatal {
+                    error!(
+                        task = name,
+                        ?elapsed,
+                        "Essential background task is hung; the proxy must be restarted"
+                    );
+                } else {
+                    warn!(task = name, ?elapsed, "Background task is hung");
+                }
+            }
+        }
+    }
+
+    /// Spawns a task that is reported, but never restarted, if it stops.
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let watchdog = self.clone();
+        self.metrics.register(name);
+        tokio::spawn(async move {
+            if let Some(reason) = watchdog.run(name, false, task).await {
+                watchdog.metrics.stopped(name);
+                warn!(task = name, %reason, "Background task stopped");
+            }
+        });
+    }
+
+    /// Spawns a task without which the proxy cannot function.
+    ///
+    /// Such tasks are never restarted: if one stops, the proxy is no longer
+    /// operable and must be restarted.
+    pub fn spawn_fatal<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let watchdog = self.clone();
+        self.metrics.register(name);
+        tokio::spawn(async move {
+            if let Some(reason) = watchdog.run(name, true, task).await {
+                watchdog.metrics.stopped(name);
+                error!(
+                    task = name,
+                    %reason,
+                    "Essential background task stopped; the proxy must be restarted"
+                );
+            }
+        });
+    }
+
+    /// Spawns a task that is rebuilt with `new_task` and restarted, if restarts
+    /// are enabled, each time it stops.
+    pub fn spawn_restartable<N, F>(&self, name: &'static str, mut new_task: N)
+    where
+        N: FnMut() -> F + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let watchdog = self.clone();
+        self.metrics.register(name);
+        tokio::spawn(async move {
+            loop {
+                let reason = match watchdog.run(name, false, new_task()).await {
+                    Some(reason) => reason,
+                    None => return,
+                };
+                watchdog.metrics.stopped(name);
+                let delay = match watchdog.restart_delay {
+                    Some(delay) => delay,
+                    None => {
+                        warn!(task = name, %reason, "Background task stopped");
+                        return;
+                    }
+                };
+
+                warn!(task = name, %reason, ?delay, "Background task stopped; restarting");
+                time::sleep(delay).await;
+                if watchdog.shutdown.load(Ordering::Acquire) {
+                    return;
+                }
+                watchdog.metrics.restarted(name);
+                info!(task = name, "Restarted background task");
+            }
+        });
+    }
+
+    /// Runs `task` to completion, sending heartbeats while it runs.
+    ///
+    /// Returns a description of why the task stopped, or `None` if it stopped
+    /// once the proxy began to shut down.
+    async fn run<F>(&self, name: &'static str, fatal: bool, task: F) -> Option<String>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let stopped = match self.heartbeat_timeout {
+            None => tokio::spawn(task).await,
+            Some(timeout) => {
+                let heartbeat = Arc::new(Heartbeat {
+                    last: Mutex::new(Instant::now()),
+                    fatal,
+                    hung: AtomicBool::new(false),
+                });
+                self.heartbeats.lock().insert(name, heartbeat.clone());
+
+                // Heartbeats are sent from the same Tokio task as the
+                // supervised task, so that they stop if it blocks.
+                let interval = Self::heartbeat_interval(timeout);
+                let beats = async move {
+                    loop {
+                        *heartbeat.last.lock() = Instant::now();
+                        time::sleep(interval).await;
+                    }
+                };
+                let stopped = tokio::spawn(async move {
+                    future::select(Box::pin(task), Box::pin(beats)).await;
+                })
+                .await;
+                self.heartbeats.lock().remove(name);
+                stopped
+            }
+        };
+
+        let reason = match stopped {
+            Ok(()) => "completed".to_string(),
+            Err(e) if e.is_panic() => "panicked".to_string(),
+            Err(e) => e.to_string(),
+        };
+        if self.shutdown.load(Ordering::Acquire) {
+            debug!(task = name, %reason, "Background task stopped during shutdown");
+            return None;
+        }
+        Some(reason)
+    }
+
+    fn heartbeat_interval(timeout: Duration) -> Duration {
+        timeout / 4
+    }
+}
+
+// === impl Metrics ===
+
+impl Metrics {
+    fn register(&self, name: &'static str) {
+        self.0.lock().entry(name).or_default();
+    }
+
+    fn stopped(&self, name: &'static str) {
+        self.0.lock().entry(name).or_default().stopped.incr();
+    }
+
+    fn restarted(&self, name: &'static str) {
+        self.0.lock().entry(name).or_default().restarts.incr();
+    }
+
+    fn hung(&self, name: &'static str) {
+        self.0.lock().entry(name).or_default().hung.incr();
+    }
+}
+
+impl FmtMetrics for Metrics {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tasks = self.0.lock();
+        if tasks.is_empty() {
+            return Ok(());
+        }
+
+        process_task_stopped_total.fmt_help(f)?;
+        for (name, task) in tasks.iter() {
+            task.stopped
+                .fmt_metric_labeled(f, process_task_stopped_total.name, TaskLabel(name))?;
+        }
+
+        process_task_restarts_total.fmt_help(f)?;
+        for (name, task) in tasks.iter() {
+            task.restarts.fmt_metric_labeled(
+                f,
+                process_task_restarts_total.name,
+                TaskLabel(name),
+            )?;
+        }
+
+        process_task_hung_total.fmt_help(f)?;
+        for (name, task) in tasks.iter() {
+            task.hung
+                .fmt_metric_labeled(f, process_task_hung_total.name, TaskLabel(name))?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl TaskLabel ===
+
+impl FmtLabels for TaskLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detects_and_restarts_stopped_tasks() {
+        let watchdog = Config {
+            restart_delay: Some(Duration::from_millis(1)),
+            ..Default::default()
+        }
+        .build();
+        let report = watchdog.metrics();
+
+        // The first instance of the task panics when it is killed; later
+        // instances never stop.
+        let (kill_tx, kill_rx) = oneshot::channel::<()>();
+        let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut kill_rx = Some(kill_rx);
+        let starts = Arc::new(AtomicUsize::new(0));
+        let restartable = {
+            let starts = starts.clone();
+            move || {
+                starts.fetch_add(1, Ordering::SeqCst);
+                let started_tx = started_tx.clone();
+                let kill_rx = kill_rx.take();
+                async move {
+                    let _ = started_tx.send(());
+                    match kill_rx {
+                        Some(kill) => {
+                            let _ = kill.await;
+                            panic!("killed");
+                        }
+                        None => futures::future::pending::<()>().await,
+                    }
+                }
+            }
+        };
+        watchdog.spawn_restartable("restartable", restartable);
+        watchdog.spawn_fatal("fatal", async {});
+
+        started_rx.recv().await.expect("task must start");
+        kill_tx.send(()).expect("task must be running");
+        started_rx.recv().await.expect("task must restart");
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+
+        tokio::task::yield_now().await;
+        let metrics = report.as_display().to_string();
+        assert!(metrics.contains("process_task_stopped_total{task=\"restartable\"} 1"));
+        assert!(metrics.contains("process_task_restarts_total{task=\"restartable\"} 1"));
+        assert!(
+            metrics.contains("process_task_stopped_total{task=\"fatal\"} 1"),
+            "fatal tasks must be reported when they stop"
+        );
+        assert!(metrics.contains("process_task_restarts_total{task=\"fatal\"} 0"));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detects_hung_tasks() {
+        let watchdog = Config {
+            heartbeat_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }
+        .build();
+        let report = watchdog.metrics();
+        tokio::spawn(watchdog.clone().monitor());
+
+        // The supervised tasks run on another runtime, whose thread is blocked
+        // by the hung task.
+        let (done_tx, done_rx) = oneshot::channel::<()>();
+        let thread = {
+            let watchdog = watchdog.clone();
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                rt.block_on(async move {
+                    watchdog.spawn("hung", async {
+                        tokio::task::yield_now().await;
+                        std::thread::sleep(Duration::from_millis(500));
+                        futures::future::pending::<()>().await;
+                    });
+                    let _ = done_rx.await;
+                })
+            })
+        };
+
+        time::sleep(Duration::from_millis(400)).await;
+        let metrics = report.as_display().to_string();
+        assert!(
+            metrics.contains("process_task_hung_total{task=\"hung\"} 1"),
+            "tasks that block their thread must be reported as hung"
+        );
+        assert!(metrics.contains("process_task_stopped_total{task=\"hung\"} 0"));
+
+        // Once the task is polled again, it recovers, and it is not reported
+        // as hung again.
+        time::sleep(Duration::from_millis(400)).await;
+        let metrics = report.as_display().to_string();
+        assert!(metrics.contains("process_task_hung_total{task=\"hung\"} 1"));
+
+        watchdog.shutdown();
+        done_tx.send(()).unwrap();
+        thread.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn ignores_tasks_stopped_during_shutdown() {
+        let watchdog = Config {
+            restart_delay: Some(Duration::from_millis(1)),
+            ..Default::default()
+        }
+        .build();
+        let report = watchdog.metrics();
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let mut stop_rx = Some(stop_rx);
+        let starts = Arc::new(AtomicUsize::new(0));
+        let restartable = {
+            let starts = starts.clone();
+            move || {
+                starts.fetch_add(1, Ordering::SeqCst);
+                let stop_rx = stop_rx.take();
+                async move {
+                    if let Some(stop) = stop_rx {
+                        let _ = stop.await;
+                    }
+                }
+            }
+        };
+        watchdog.spawn_restartable("drained", restartable);
+        tokio::task::yield_now().await;
+
+        watchdog.shutdown();
+        stop_tx.send(()).expect("task must be running");
+        time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(
+            starts.load(Ordering::SeqCst),
+            1,
+            "tasks must not be restarted during shutdown"
+        );
+        let metrics = report.as_display().to_string();
+        assert!(
+            metrics.contains("process_task_stopped_total{task=\"drained\"} 0"),
+            "tasks stopped during shutdown must not be reported"
+        );
+    }
+}
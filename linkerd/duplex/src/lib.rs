@@ -1,9 +1,16 @@
 //! A utility for copying data bi-directionally between two sockets.
 //!
-//! This module uses unsafe code to implement [`BufMut`].
+//! This module uses unsafe code to implement [`BufMut`] and, on Linux, to
+//! splice data between TCP sockets.
 
 #![deny(warnings, rust_2018_idioms)]
 
+#[cfg(target_os = "linux")]
+mod splice;
+
+#[cfg(target_os = "linux")]
+pub use self::splice::{SpliceDuplex, Spliced};
+
 use bytes::{Buf, BufMut};
 use futures::ready;
 use linkerd_io::{self as io, AsyncRead, AsyncWrite};
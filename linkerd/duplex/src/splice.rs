@@ -0,0 +1,261 @@
+//! Copies data bi-directionally between two TCP sockets with `splice(2)`, so
+//! that the kernel moves it through a pipe rather than copying it into
+//! userspace.
+
+use linkerd_io as io;
+use std::{
+    os::unix::io::{AsRawFd, RawFd},
+    task::{Context, Poll},
+};
+use tokio::{io::Interest, net::TcpStream};
+use tracing::trace;
+
+/// The maximum number of bytes moved by each call to `splice(2)`, matching
+/// the default capacity of a pipe.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// The number of bytes spliced in each direction.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Spliced {
+    pub client_to_server: usize,
+    pub server_to_client: usize,
+}
+
+/// Splices data bi-directionally between two TCP sockets.
+///
+/// Progress is reported as data is moved, so that callers may record it
+/// incrementally.
+pub struct SpliceDuplex {
+    client_to_server: HalfSplice,
+    server_to_client: HalfSplice,
+}
+
+/// Splices one direction of a connection through a pipe.
+struct HalfSplice {
+    pipe: Pipe,
+    /// The number of bytes read into the pipe that have not yet been written.
+    buffered: usize,
+    /// Set once the source has reached EOF and the destination has been shut
+    /// down.
+    done: bool,
+    direction: &'static str,
+}
+
+/// A pipe through which one direction of a connection is spliced.
+struct Pipe {
+    read: RawFd,
+    write: RawFd,
+}
+
+// === impl SpliceDuplex ===
+
+impl SpliceDuplex {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            client_to_server: HalfSplice::new("client->server")?,
+            server_to_client: HalfSplice::new("server->client")?,
+        })
+    }
+
+    /// Splices data between `client` and `server`, returning the number of
+    /// bytes moved in each direction once any data has been moved, or `None`
+    /// once both directions have reached EOF.
+    ///
+    /// The same sockets must be passed on each call.
+    pub fn poll_splice(
+        &mut self,
+        cx: &mut Context<'_>,
+        client: &TcpStream,
+        server: &TcpStream,
+    ) -> Poll<io::Result<Option<Spliced>>> {
+        let c2s = self.client_to_server.poll_splice(cx, client, server)?;
+        let s2c = self.server_to_client.poll_splice(cx, server, client)?;
+        match (c2s, s2c) {
+            (Poll::Ready(None), Poll::Ready(None)) => Poll::Ready(Ok(None)),
+            (Poll::Pending, Poll::Pending)
+            | (Poll::Pending, Poll::Ready(None))
+            | (Poll::Ready(None), Poll::Pending) => Poll::Pending,
+            (c2s, s2c) => Poll::Ready(Ok(Some(Spliced {
+                client_to_server: progress(c2s),
+                server_to_client: progress(s2c),
+            }))),
+        }
+    }
+}
+
+fn progress(poll: Poll<Option<usize>>) -> usize {
+    match poll {
+        Poll::Ready(Some(sz)) => sz,
+        _ => 0,
+    }
+}
+
+// === impl HalfSplice ===
+
+impl HalfSplice {
+    fn new(direction: &'static str) -> io::Result<Self> {
+        Ok(Self {
+            pipe: Pipe::new()?,
+            buffered: 0,
+            done: false,
+            direction,
+        })
+    }
+
+    /// Splices data from `src` into `dst`, returning the number of bytes
+    /// written to `dst`, or `None` once `src` has reached EOF and `dst` has
+    /// been shut down for writing.
+    fn poll_splice(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &TcpStream,
+        dst: &TcpStream,
+    ) -> Poll<io::Result<Option<usize>>> {
+        loop {
+            // The pipe is always drained before reading, so a read can only
+            // block on the source socket.
+            if self.buffered > 0 {
+                futures::ready!(dst.poll_write_ready(cx))?;
+                let (read, buffered) = (self.pipe.read, self.buffered);
+                match dst.try_io(Interest::WRITABLE, || {
+                    splice_fds(read, dst.as_raw_fd(), buffered)
+                }) {
+                    Ok(0) => return Poll::Ready(Err(super::write_zero())),
+                    Ok(sz) => {
+                        self.buffered -= sz;
+                        trace!(direction = %self.direction, "spliced {}B", sz);
+                        return Poll::Ready(Ok(Some(sz)));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+
+            if self.done {
+                return Poll::Ready(Ok(None));
+            }
+
+            futures::ready!(src.poll_read_ready(cx))?;
+            let write = self.pipe.write;
+            match src.try_io(Interest::READABLE, || {
+                splice_fds(src.as_raw_fd(), write, PIPE_SIZE)
+            }) {
+                Ok(0) => {
+                    trace!(direction = %self.direction, "shutting down");
+                    shutdown_write(dst)?;
+                    self.done = true;
+                }
+                Ok(sz) => self.buffered = sz,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+fn splice_fds(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    // Safety: Neither sockets nor pipes have offsets, so null offsets are
+    // passed.
+    let n = unsafe {
+        libc::splice(
+            fd_in,
+            std::ptr::null_mut(),
+            fd_out,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn shutdown_write(io: &TcpStream) -> io::Result<()> {
+    // Safety: The socket remains open for as long as `io` is borrowed.
+    if unsafe { libc::shutdown(io.as_raw_fd(), libc::SHUT_WR) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// === impl Pipe ===
+
+impl Pipe {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // Safety: `pipe2` writes two new file descriptors into `fds`, which
+        // are then owned (and closed) by the `Pipe`.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // Safety: The descriptors are owned by the pipe and are not used once
+        // it is dropped.
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn connected() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (client.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn splices_both_directions() {
+        let (mut client, proxy_in) = connected().await;
+        let (proxy_out, mut server) = connected().await;
+
+        // Records the progress reported as data is spliced.
+        let mut reports = Vec::new();
+        let splice = async {
+            let mut duplex = SpliceDuplex::new()?;
+            while let Some(spliced) =
+                futures::future::poll_fn(|cx| duplex.poll_splice(cx, &proxy_in, &proxy_out)).await?
+            {
+                reports.push(spliced);
+            }
+            Ok::<_, io::Error>(())
+        };
+        let (res, ()) = tokio::join!(splice, async {
+            client.write_all(b"hello").await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            server.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hello");
+
+            server.write_all(b"hi there").await.unwrap();
+            server.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hi there");
+        });
+        res.expect("splice must succeed");
+
+        // Progress is reported for each direction as it is spliced, rather
+        // than once the connection completes.
+        assert!(reports.len() >= 2);
+        assert_eq!(reports.iter().map(|s| s.client_to_server).sum::<usize>(), 5);
+        assert_eq!(reports.iter().map(|s| s.server_to_client).sum::<usize>(), 8);
+    }
+}
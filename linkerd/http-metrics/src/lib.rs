@@ -1,7 +1,10 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
-pub use self::{requests::Requests, retries::Retries};
+pub use self::{
+    requests::{Requests, StatusLabels},
+    retries::Retries,
+};
 use linkerd_metrics::SharedStore;
 use parking_lot::Mutex;
 use std::{fmt, hash::Hash, time::Duration};
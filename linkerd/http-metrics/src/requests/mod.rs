@@ -1,22 +1,23 @@
 mod report;
 mod service;
 
-pub use self::service::{NewHttpMetrics, ResponseBody};
+pub use self::service::{NewFilteredHttpMetrics, NewHttpMetrics, ResponseBody};
 use super::Report;
 use linkerd_http_classify::ClassifyResponse;
-use linkerd_metrics::{latency, Counter, FmtMetrics, Histogram, LastUpdate, NewMetrics};
+use linkerd_metrics::{latency, Counter, FmtMetrics, Histogram, LastUpdate};
 use linkerd_stack::{self as svc, layer};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     hash::Hash,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 type Registry<T, C> = super::Registry<T, Metrics<C>>;
 
 #[derive(Debug)]
-pub struct Requests<T, C>(Registry<T, C>)
+pub struct Requests<T, C>(Registry<T, C>, StatusLabels)
 where
     T: Hash + Eq,
     C: Hash + Eq;
@@ -28,7 +29,7 @@ where
 {
     last_update: Instant,
     total: Counter,
-    by_status: HashMap<Option<http::StatusCode>, StatusMetrics<C>>,
+    by_status: HashMap<Option<Status>, StatusMetrics<C>>,
 }
 
 #[derive(Debug)]
@@ -45,15 +46,40 @@ pub struct ClassMetrics {
     total: Counter,
 }
 
+/// Determines how response status codes are labeled in metrics.
+///
+/// By default, each status code is labeled distinctly. Status codes may
+/// instead be aggregated into classes (e.g. `status_code="5xx"`), in which
+/// case a set of codes may still be labeled distinctly. A distinct code is not
+/// counted in its class. Responses are recorded under their labels, so codes
+/// that share a label are never merged as metrics are reported.
+#[derive(Clone, Debug, Default)]
+pub struct StatusLabels {
+    /// When set, codes not in the set are aggregated into classes.
+    distinct: Option<Arc<HashSet<http::StatusCode>>>,
+}
+
+/// The label under which a response's status is recorded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Status {
+    Code(http::StatusCode),
+    /// The hundreds digit shared by the codes in a class.
+    Class(u16),
+}
+
 // === impl Requests ===
 
 impl<T: Hash + Eq, C: Hash + Eq> Default for Requests<T, C> {
     fn default() -> Self {
-        Requests(Registry::default())
+        Requests(Registry::default(), StatusLabels::default())
     }
 }
 
 impl<T: Hash + Eq, C: Hash + Eq> Requests<T, C> {
+    pub fn with_status_labels(self, status_labels: StatusLabels) -> Self {
+        Requests(self.0, status_labels)
+    }
+
     pub fn into_report(self, retain_idle: Duration) -> Report<T, Metrics<C>>
     where
         Report<T, Metrics<C>>: FmtMetrics,
@@ -63,19 +89,50 @@ impl<T: Hash + Eq, C: Hash + Eq> Requests<T, C> {
 
     pub fn to_layer<L, N, Tgt>(
         &self,
-    ) -> impl layer::Layer<N, Service = NewHttpMetrics<N, T, L, C, N::Service>> + Clone
+    ) -> impl layer::Layer<N, Service = NewHttpMetrics<N, T, C, L, Tgt>> + Clone
     where
         L: ClassifyResponse<Class = C> + Send + Sync + 'static,
         N: svc::NewService<Tgt>,
     {
-        let reg = self.0.clone();
-        NewMetrics::layer(reg)
+        let all: fn(&Tgt) -> bool = |_| true;
+        self.to_layer_filtered(all)
+    }
+
+    /// Like `to_layer`, but only records metrics for the targets for which
+    /// `filter` returns true. Metrics are not registered for other targets.
+    pub fn to_layer_filtered<L, N, Tgt, F>(
+        &self,
+        filter: F,
+    ) -> impl layer::Layer<N, Service = NewFilteredHttpMetrics<N, T, C, L, F>> + Clone
+    where
+        L: ClassifyResponse<Class = C> + Send + Sync + 'static,
+        N: svc::NewService<Tgt>,
+        F: Fn(&Tgt) -> bool + Clone,
+    {
+        NewFilteredHttpMetrics::layer(self.0.clone(), self.1.clone(), filter)
     }
 }
 
 impl<T: Hash + Eq, C: Hash + Eq> Clone for Requests<T, C> {
     fn clone(&self) -> Self {
-        Requests(self.0.clone())
+        Requests(self.0.clone(), self.1.clone())
+    }
+}
+
+// === impl StatusLabels ===
+
+impl StatusLabels {
+    pub fn classes(distinct: impl IntoIterator<Item = http::StatusCode>) -> Self {
+        Self {
+            distinct: Some(Arc::new(distinct.into_iter().collect())),
+        }
+    }
+
+    fn label(&self, status: http::StatusCode) -> Status {
+        match self.distinct.as_ref() {
+            Some(distinct) if !distinct.contains(&status) => Status::Class(status.as_u16() / 100),
+            _ => Status::Code(status),
+        }
     }
 }
 
@@ -179,4 +236,79 @@ mod tests {
 
         drop((registry, report));
     }
+
+    #[test]
+    fn aggregates_status_classes() {
+        use super::{Metrics, Report, StatusLabels};
+        use http::StatusCode;
+        use linkerd_metrics::{FmtLabels, FmtMetrics};
+        use std::{fmt, time::Duration};
+
+        #[derive(Clone, Debug, Hash, Eq, PartialEq)]
+        struct Target;
+        impl FmtLabels for Target {
+            fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "target=\"test\"")
+            }
+        }
+
+        #[derive(Clone, Debug, Hash, Eq, PartialEq)]
+        struct Class;
+        impl FmtLabels for Class {
+            fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "class=\"test\"")
+            }
+        }
+
+        // Records responses as the HTTP metrics layer does.
+        let record = |r: &super::Requests<Target, Class>| {
+            let metrics = r.0.lock().get_or_default(Target).clone();
+            for (code, n) in &[(200, 3), (204, 1), (429, 2), (500, 1), (502, 1), (503, 4)] {
+                let mut metrics = metrics.lock();
+                let status = r.1.label(StatusCode::from_u16(*code).unwrap());
+                let status = metrics.by_status.entry(Some(status)).or_default();
+                status.latency.add(Duration::from_millis(10));
+                status.by_class.entry(Class).or_default().total.add(*n);
+            }
+        };
+
+        let count = |report: &Report<Target, Metrics<Class>>, status: &str| {
+            let prefix = format!(
+                "response_total{{target=\"test\",status_code=\"{}\",class=\"test\"}} ",
+                status
+            );
+            let text = report.as_display().to_string();
+            text.lines()
+                .find_map(|line| line.strip_prefix(&prefix)?.parse::<u64>().ok())
+        };
+
+        let r = super::Requests::<Target, Class>::default();
+        record(&r);
+        let report = r.into_report(Duration::from_secs(10));
+        assert_eq!(count(&report, "503"), Some(4));
+        assert_eq!(count(&report, "5xx"), None);
+
+        let r = super::Requests::<Target, Class>::default().with_status_labels(
+            StatusLabels::classes(vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ]),
+        );
+        record(&r);
+        let report = r.into_report(Duration::from_secs(10));
+        assert_eq!(count(&report, "2xx"), Some(4));
+        assert_eq!(count(&report, "429"), Some(2));
+        assert_eq!(count(&report, "4xx"), None);
+        // Distinct codes are not also counted in their class.
+        assert_eq!(count(&report, "503"), Some(4));
+        assert_eq!(count(&report, "5xx"), Some(2));
+        assert_eq!(count(&report, "200"), None);
+
+        let text = report.as_display().to_string();
+        assert!(
+            text.contains("response_latency_ms_count{target=\"test\",status_code=\"2xx\"} 2"),
+            "{}",
+            text
+        );
+    }
 }
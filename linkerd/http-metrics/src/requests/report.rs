@@ -1,4 +1,4 @@
-use super::{ClassMetrics, Metrics, StatusMetrics};
+use super::{ClassMetrics, Metrics, Status, StatusMetrics};
 use crate::{Prefixed, Report};
 use linkerd_metrics::{
     latency, Counter, FmtLabels, FmtMetric, FmtMetrics, Histogram, Metric, Store,
@@ -7,9 +7,6 @@ use parking_lot::Mutex;
 use std::{fmt, hash::Hash, time::Instant};
 use tracing::trace;
 
-#[derive(Copy, Clone)]
-struct Status(http::StatusCode);
-
 impl<T, C> Report<T, Metrics<C>>
 where
     T: FmtLabels + Hash + Eq,
@@ -71,8 +68,7 @@ where
         for (tgt, tm) in registry.iter() {
             let tm = tm.lock();
             for (status, m) in &tm.by_status {
-                let status = status.as_ref().map(|s| Status(*s));
-                let labels = (tgt, status);
+                let labels = (tgt, *status);
                 get_metric(&*m).fmt_metric_labeled(f, &metric.name, labels)?;
             }
         }
@@ -94,8 +90,7 @@ where
             let tm = tm.lock();
             for (status, sm) in &tm.by_status {
                 for (cls, m) in &sm.by_class {
-                    let status = status.as_ref().map(|s| Status(*s));
-                    let labels = (tgt, (status, cls));
+                    let labels = (tgt, (*status, cls));
                     get_metric(&*m).fmt_metric_labeled(f, &metric.name, labels)?;
                 }
             }
@@ -145,6 +140,9 @@ where
 
 impl FmtLabels for Status {
     fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "status_code=\"{}\"", self.0.as_u16())
+        match self {
+            Self::Code(code) => write!(f, "status_code=\"{}\"", code.as_u16()),
+            Self::Class(class) => write!(f, "status_code=\"{}xx\"", class),
+        }
     }
 }
@@ -1,10 +1,9 @@
-use super::{ClassMetrics, Metrics, StatusMetrics};
+use super::{ClassMetrics, Metrics, Registry, Status, StatusLabels, StatusMetrics};
 use futures::{ready, TryFuture};
 use http_body::Body;
 use linkerd_error::Error;
 use linkerd_http_classify::{ClassifyEos, ClassifyResponse};
-use linkerd_metrics::NewMetrics;
-use linkerd_stack::Proxy;
+use linkerd_stack::{layer, NewService, Param, Proxy};
 use parking_lot::Mutex;
 use pin_project::{pin_project, pinned_drop};
 use std::{
@@ -17,9 +16,22 @@ use std::{
     time::Instant,
 };
 
-/// Wraps services to record metrics.
-pub type NewHttpMetrics<N, K, C, Class, S> =
-    NewMetrics<N, K, Mutex<Metrics<Class>>, HttpMetrics<S, C>>;
+/// Wraps services to record metrics for all targets.
+pub type NewHttpMetrics<N, K, Class, C, T> = NewFilteredHttpMetrics<N, K, Class, C, fn(&T) -> bool>;
+
+/// Wraps services to record metrics for the targets that an `F`-typed filter
+/// selects. Services for other targets do not record metrics.
+pub struct NewFilteredHttpMetrics<N, K, Class, C, F>
+where
+    K: Hash + Eq,
+    Class: Hash + Eq,
+{
+    registry: Registry<K, Class>,
+    status_labels: StatusLabels,
+    filter: F,
+    inner: N,
+    _p: PhantomData<fn() -> C>,
+}
 
 /// A middleware that records HTTP metrics.
 #[pin_project]
@@ -30,6 +42,7 @@ where
     C::Class: Hash + Eq,
 {
     metrics: Option<Arc<Mutex<Metrics<C::Class>>>>,
+    status_labels: StatusLabels,
     #[pin]
     inner: S,
     _p: PhantomData<fn() -> C>,
@@ -43,6 +56,7 @@ where
 {
     classify: Option<C>,
     metrics: Option<Arc<Mutex<Metrics<C::Class>>>>,
+    status_labels: StatusLabels,
     stream_open_at: Instant,
     #[pin]
     inner: F,
@@ -68,7 +82,7 @@ where
     C: ClassifyEos,
     C::Class: Hash + Eq,
 {
-    status: http::StatusCode,
+    status: Status,
     classify: Option<C>,
     metrics: Option<Arc<Mutex<Metrics<C::Class>>>>,
     stream_open_at: Instant,
@@ -77,22 +91,76 @@ where
     inner: B,
 }
 
-// === impl HttpMetrics ===
+// === impl NewFilteredHttpMetrics ===
+
+impl<N, K, Class, C, F> NewFilteredHttpMetrics<N, K, Class, C, F>
+where
+    K: Hash + Eq,
+    Class: Hash + Eq,
+    F: Clone,
+{
+    pub(super) fn layer(
+        registry: Registry<K, Class>,
+        status_labels: StatusLabels,
+        filter: F,
+    ) -> impl layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            registry: registry.clone(),
+            status_labels: status_labels.clone(),
+            filter: filter.clone(),
+            inner,
+            _p: PhantomData,
+        })
+    }
+}
 
-impl<S, C> From<(S, Arc<Mutex<Metrics<C::Class>>>)> for HttpMetrics<S, C>
+impl<T, N, K, C, F> NewService<T> for NewFilteredHttpMetrics<N, K, C::Class, C, F>
 where
+    T: Param<K>,
+    N: NewService<T>,
+    K: Hash + Eq,
     C: ClassifyResponse,
     C::Class: Hash + Eq,
+    F: Fn(&T) -> bool,
 {
-    fn from((inner, metrics): (S, Arc<Mutex<Metrics<C::Class>>>)) -> Self {
+    type Service = HttpMetrics<N::Service, C>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let metrics = if (self.filter)(&target) {
+            let key = target.param();
+            Some(self.registry.lock().get_or_default(key).clone())
+        } else {
+            None
+        };
+        HttpMetrics {
+            metrics,
+            status_labels: self.status_labels.clone(),
+            inner: self.inner.new_service(target),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<N, K, Class, C, F> Clone for NewFilteredHttpMetrics<N, K, Class, C, F>
+where
+    N: Clone,
+    K: Hash + Eq,
+    Class: Hash + Eq,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
         Self {
-            metrics: Some(metrics),
-            inner,
+            registry: self.registry.clone(),
+            status_labels: self.status_labels.clone(),
+            filter: self.filter.clone(),
+            inner: self.inner.clone(),
             _p: PhantomData,
         }
     }
 }
 
+// === impl HttpMetrics ===
+
 impl<S, C> Clone for HttpMetrics<S, C>
 where
     S: Clone,
@@ -103,6 +171,7 @@ where
         Self {
             inner: self.inner.clone(),
             metrics: self.metrics.clone(),
+            status_labels: self.status_labels.clone(),
             _p: PhantomData,
         }
     }
@@ -148,6 +217,7 @@ where
         ResponseFuture {
             classify: Some(classify),
             metrics: self.metrics.clone(),
+            status_labels: self.status_labels.clone(),
             stream_open_at: Instant::now(),
             inner: self.inner.proxy(svc, req),
         }
@@ -197,6 +267,7 @@ where
         ResponseFuture {
             classify: Some(classify),
             metrics: self.metrics.clone(),
+            status_labels: self.status_labels.clone(),
             stream_open_at: Instant::now(),
             inner: self.inner.call(req),
         }
@@ -224,7 +295,9 @@ where
                 let classify = classify.map(|c| c.start(&rsp));
                 let (head, inner) = rsp.into_parts();
                 let body = ResponseBody {
-                    status: head.status,
+                    // Status codes are labeled as they are recorded, so that
+                    // codes that share a label are not merged on each scrape.
+                    status: this.status_labels.label(head.status),
                     classify,
                     metrics,
                     stream_open_at: *this.stream_open_at,
@@ -309,7 +382,7 @@ where
 {
     fn default() -> Self {
         Self {
-            status: http::StatusCode::OK,
+            status: Status::Code(http::StatusCode::OK),
             inner: B::default(),
             stream_open_at: Instant::now(),
             classify: None,
@@ -368,11 +441,7 @@ where
     }
 }
 
-fn measure_class<C: Hash + Eq>(
-    lock: &Arc<Mutex<Metrics<C>>>,
-    class: C,
-    status: Option<http::StatusCode>,
-) {
+fn measure_class<C: Hash + Eq>(lock: &Arc<Mutex<Metrics<C>>>, class: C, status: Option<Status>) {
     let now = Instant::now();
     let mut metrics = lock.lock();
 
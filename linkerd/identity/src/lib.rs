@@ -46,7 +46,31 @@ pub struct CrtKey {
     id: LocalId,
     expiry: SystemTime,
     client_config: Arc<rustls::ClientConfig>,
-    server_config: Arc<rustls::ServerConfig>,
+    server_configs: ServerConfigs,
+}
+
+/// Determines how a TLS server authenticates its clients.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClientAuth {
+    /// Clients must present a certificate issued by a trust anchor; the
+    /// handshake fails otherwise.
+    Require,
+
+    /// Clients are asked for a certificate, but anonymous clients may
+    /// complete the handshake without a client identity.
+    Request,
+
+    /// Clients are not asked for a certificate, so no client identity is ever
+    /// established.
+    None,
+}
+
+/// A server config for each `ClientAuth` mode.
+#[derive(Clone)]
+struct ServerConfigs {
+    require: Arc<rustls::ServerConfig>,
+    request: Arc<rustls::ServerConfig>,
+    none: Arc<rustls::ServerConfig>,
 }
 
 struct CertResolver(rustls::sign::CertifiedKey);
@@ -275,8 +299,8 @@ impl TrustAnchors {
         // Enable client authentication.
         client.client_auth_cert_resolver = resolver.clone();
 
-        // Ask TLS clients for a certificate and accept any certificate issued
-        // by our trusted CA(s).
+        // Unless client authentication is disabled, ask TLS clients for a
+        // certificate and accept any certificate issued by our trusted CA(s).
         //
         // XXX: Rustls's built-in verifiers don't let us tweak things as fully
         // as we'd like (e.g. controlling the set of trusted signature
@@ -284,17 +308,26 @@ impl TrustAnchors {
         // TODO: lock down the verification further.
         //
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
-        let mut server = rustls::ServerConfig::new(
-            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.0.root_store.clone()),
-        );
-        server.versions = TLS_VERSIONS.to_vec();
-        server.cert_resolver = resolver;
+        let server = |verifier| {
+            let mut server = rustls::ServerConfig::new(verifier);
+            server.versions = TLS_VERSIONS.to_vec();
+            server.cert_resolver = resolver.clone();
+            Arc::new(server)
+        };
+        let roots = &self.0.root_store;
+        let server_configs = ServerConfigs {
+            require: server(rustls::AllowAnyAuthenticatedClient::new(roots.clone())),
+            request: server(rustls::AllowAnyAnonymousOrAuthenticatedClient::new(
+                roots.clone(),
+            )),
+            none: server(rustls::NoClientAuth::new()),
+        };
 
         Ok(CrtKey {
             id: crt.id,
             expiry: crt.expiry,
             client_config: Arc::new(client),
-            server_config: Arc::new(server),
+            server_configs,
         })
     }
 
@@ -336,6 +369,14 @@ impl From<&'_ Crt> for LocalId {
     }
 }
 
+// === impl ClientAuth ===
+
+impl Default for ClientAuth {
+    fn default() -> Self {
+        Self::Request
+    }
+}
+
 // === CrtKey ===
 
 impl CrtKey {
@@ -355,8 +396,18 @@ impl CrtKey {
         self.client_config.clone()
     }
 
+    /// Returns a server config that requests, but does not require, client
+    /// certificates.
     pub fn server_config(&self) -> Arc<rustls::ServerConfig> {
-        self.server_config.clone()
+        self.server_config_for(ClientAuth::Request)
+    }
+
+    pub fn server_config_for(&self, auth: ClientAuth) -> Arc<rustls::ServerConfig> {
+        match auth {
+            ClientAuth::Require => self.server_configs.require.clone(),
+            ClientAuth::Request => self.server_configs.request.clone(),
+            ClientAuth::None => self.server_configs.none.clone(),
+        }
     }
 }
 
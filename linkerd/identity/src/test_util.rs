@@ -15,6 +15,13 @@ pub static FOO_NS1: Identity = Identity {
     key: include_bytes!("testdata/foo-ns1-ca1/key.p8"),
 };
 
+pub static FOO_NS1_CA2: Identity = Identity {
+    name: "foo.ns1.serviceaccount.identity.linkerd.cluster.local",
+    trust_anchors: include_bytes!("testdata/ca2.pem"),
+    crt: include_bytes!("testdata/foo-ns1-ca2/crt.der"),
+    key: include_bytes!("testdata/foo-ns1-ca2/key.p8"),
+};
+
 pub static BAR_NS1: Identity = Identity {
     name: "bar.ns1.serviceaccount.identity.linkerd.cluster.local",
     trust_anchors: include_bytes!("testdata/ca1.pem"),
@@ -1,4 +1,4 @@
-use super::{AsyncRead, AsyncWrite, IoSlice, PeerAddr, Poll, ReadBuf, Result};
+use super::{AsyncRead, AsyncWrite, IoSlice, PeerAddr, Poll, ReadBuf, Result, Splice};
 use std::{pin::Pin, task::Context};
 
 /// A public wrapper around a `Box<Io>`.
@@ -7,17 +7,17 @@ use std::{pin::Pin, task::Context};
 /// to allow vectored writes to occur.
 pub struct BoxedIo(Pin<Box<dyn Io + Unpin>>);
 
-/// This is necessary for `BoxedIo`, as `dyn AsyncRead + AsyncWrite + PeerAddr`
+/// This is necessary for `BoxedIo`, as `dyn AsyncRead + AsyncWrite + PeerAddr + Splice`
 /// is not a valid trait object. However, it needn't be public --- it's just
 /// used internally.
-trait Io: AsyncRead + AsyncWrite + PeerAddr + Send {}
+trait Io: AsyncRead + AsyncWrite + PeerAddr + Splice + Send {}
 
-impl<I> Io for I where I: AsyncRead + AsyncWrite + PeerAddr + Send {}
+impl<I> Io for I where I: AsyncRead + AsyncWrite + PeerAddr + Splice + Send {}
 
 impl BoxedIo {
     pub fn new<T>(io: T) -> Self
     where
-        T: AsyncRead + AsyncWrite + PeerAddr + Send + Unpin + 'static,
+        T: AsyncRead + AsyncWrite + PeerAddr + Splice + Send + Unpin + 'static,
     {
         BoxedIo(Box::pin(io))
     }
@@ -29,6 +29,16 @@ impl PeerAddr for BoxedIo {
     }
 }
 
+impl Splice for BoxedIo {
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        self.0.tcp_stream()
+    }
+
+    fn record_spliced(&mut self, read: usize, written: usize) {
+        self.0.as_mut().get_mut().record_spliced(read, written)
+    }
+}
+
 impl AsyncRead for BoxedIo {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -84,6 +94,8 @@ mod tests {
         }
     }
 
+    impl Splice for WriteBufDetector {}
+
     impl AsyncRead for WriteBufDetector {
         fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<()> {
             unreachable!("not called in test")
@@ -33,6 +33,24 @@ impl<L: io::PeerAddr, R: io::PeerAddr> io::PeerAddr for EitherIo<L, R> {
     }
 }
 
+impl<L: io::Splice, R: io::Splice> io::Splice for EitherIo<L, R> {
+    #[inline]
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        match self {
+            Self::Left(l) => l.tcp_stream(),
+            Self::Right(r) => r.tcp_stream(),
+        }
+    }
+
+    #[inline]
+    fn record_spliced(&mut self, read: usize, written: usize) {
+        match self {
+            Self::Left(l) => l.record_spliced(read, written),
+            Self::Right(r) => r.record_spliced(read, written),
+        }
+    }
+}
+
 impl<L: io::AsyncRead, R: io::AsyncRead> io::AsyncRead for EitherIo<L, R> {
     #[inline]
     fn poll_read(
@@ -88,3 +88,38 @@ impl PeerAddr for tokio::io::DuplexStream {
         Ok(([0, 0, 0, 0], 0).into())
     }
 }
+
+// === Splice ===
+
+/// Exposes the TCP socket underlying a transport, so that bytes may be copied
+/// between sockets by the kernel without being read into userspace.
+///
+/// Transports that encrypt, buffer, or otherwise transform the bytes they
+/// carry must not expose their socket.
+pub trait Splice {
+    /// Returns the socket that this transport reads from and writes to
+    /// directly, if there is one.
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        None
+    }
+
+    /// Records bytes that were spliced from (`read`) and into (`written`) the
+    /// underlying socket, bypassing this transport.
+    fn record_spliced(&mut self, _read: usize, _written: usize) {}
+}
+
+impl Splice for tokio::net::TcpStream {
+    #[inline]
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        Some(self)
+    }
+}
+
+impl<T> Splice for tokio_rustls::client::TlsStream<T> {}
+
+impl<T> Splice for tokio_rustls::server::TlsStream<T> {}
+
+#[cfg(feature = "tokio-test")]
+impl Splice for tokio_test::io::Mock {}
+
+impl Splice for tokio::io::DuplexStream {}
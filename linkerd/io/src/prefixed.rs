@@ -50,6 +50,23 @@ impl<I: io::PeerAddr> io::PeerAddr for PrefixedIo<I> {
     }
 }
 
+impl<I: io::Splice> io::Splice for PrefixedIo<I> {
+    // The socket is only exposed once the prefix has been read, since the
+    // prefix would otherwise be skipped.
+    #[inline]
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        if !self.prefix.is_empty() {
+            return None;
+        }
+        self.io.tcp_stream()
+    }
+
+    #[inline]
+    fn record_spliced(&mut self, read: usize, written: usize) {
+        self.io.record_spliced(read, written)
+    }
+}
+
 impl<I: io::AsyncRead> io::AsyncRead for PrefixedIo<I> {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -77,6 +77,18 @@ impl<I: io::PeerAddr> io::PeerAddr for ScopedIo<I> {
     }
 }
 
+impl<I: io::Splice> io::Splice for ScopedIo<I> {
+    #[inline]
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        self.io.tcp_stream()
+    }
+
+    #[inline]
+    fn record_spliced(&mut self, read: usize, written: usize) {
+        self.io.record_spliced(read, written)
+    }
+}
+
 impl<I: io::AsyncRead> io::AsyncRead for ScopedIo<I> {
     #[inline]
     fn poll_read(
@@ -1,4 +1,4 @@
-use crate::{IoSlice, PeerAddr, Poll};
+use crate::{IoSlice, Peek, PeerAddr, Poll, Splice};
 use futures::ready;
 use linkerd_errno::Errno;
 use pin_project::pin_project;
@@ -77,8 +77,31 @@ impl<T: AsyncRead + AsyncWrite, S: Sensor> AsyncWrite for SensorIo<T, S> {
     }
 }
 
+// Peeked bytes are not recorded, since they are recorded when they are read.
+#[async_trait::async_trait]
+impl<T: Peek + Send + Sync, S: Send + Sync> Peek for SensorIo<T, S> {
+    async fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.io.peek(buf).await
+    }
+}
+
 impl<T: PeerAddr, S> PeerAddr for SensorIo<T, S> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr> {
         self.io.peer_addr()
     }
 }
+
+// Spliced bytes never pass through `poll_read` or `poll_write`, so they are
+// recorded here instead.
+impl<T: Splice, S: Sensor> Splice for SensorIo<T, S> {
+    #[inline]
+    fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        self.io.tcp_stream()
+    }
+
+    fn record_spliced(&mut self, read: usize, written: usize) {
+        self.sensor.record_read(read);
+        self.sensor.record_write(written);
+        self.io.record_spliced(read, written);
+    }
+}
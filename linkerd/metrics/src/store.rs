@@ -70,6 +70,11 @@ where
             .retain(|_, metric| Arc::strong_count(metric) > 1 || metric.last_update() >= epoch)
     }
 
+    /// Drops metrics that are no longer referenced outside of the store.
+    pub fn retain_referenced(&mut self) {
+        self.inner.retain(|_, metric| Arc::strong_count(metric) > 1)
+    }
+
     /// Formats a metric across all instances of `Metrics` in the registry.
     pub fn fmt_by<N, M>(
         &self,
@@ -2,6 +2,7 @@
 #![forbid(unsafe_code)]
 
 pub mod metrics;
+pub mod otlp;
 
 use futures::stream::{Stream, StreamExt};
 use http_body::Body as HttpBody;
@@ -41,6 +42,9 @@ struct SpanExporter<T, S> {
 #[derive(Debug)]
 struct SpanRxClosed;
 
+const MAX_BATCH_SIZE: usize = 1000;
+const MAX_BATCH_IDLE: time::Duration = time::Duration::from_secs(10);
+
 // === impl SpanExporter ===
 
 impl<T, S> SpanExporter<T, S>
@@ -51,9 +55,6 @@ where
     T::ResponseBody: Send + Sync + 'static,
     S: Stream<Item = Span> + Unpin,
 {
-    const MAX_BATCH_SIZE: usize = 1000;
-    const MAX_BATCH_IDLE: time::Duration = time::Duration::from_secs(10);
-
     fn new(client: T, node: Node, spans: S, metrics: Registry) -> Self {
         Self {
             client,
@@ -121,7 +122,7 @@ where
     ) -> Result<(), SpanRxClosed> {
         loop {
             // Collect spans into a batch.
-            let collect = Self::collect_batch(spans, accum).await;
+            let collect = collect_batch(spans, accum).await;
 
             // If we collected spans, flush them.
             if !accum.is_empty() {
@@ -157,36 +158,39 @@ where
             }
         }
     }
+}
 
-    /// Collects spans from the proxy into `accum`.
-    ///
-    /// Returns an error when the span sream has completed. An error may be
-    /// returned after accumulating spans.
-    async fn collect_batch(spans: &mut S, accum: &mut Vec<Span>) -> Result<(), SpanRxClosed> {
-        loop {
-            if accum.len() == Self::MAX_BATCH_SIZE {
-                trace!(capacity = Self::MAX_BATCH_SIZE, "Batch capacity reached");
-                return Ok(());
-            }
-
-            tokio::select! {
-                biased;
+/// Collects spans from the proxy into `accum`.
+///
+/// Returns an error when the span sream has completed. An error may be
+/// returned after accumulating spans.
+async fn collect_batch<S>(spans: &mut S, accum: &mut Vec<Span>) -> Result<(), SpanRxClosed>
+where
+    S: Stream<Item = Span> + Unpin,
+{
+    loop {
+        if accum.len() == MAX_BATCH_SIZE {
+            trace!(capacity = MAX_BATCH_SIZE, "Batch capacity reached");
+            return Ok(());
+        }
 
-                res = spans.next() => match res {
-                    Some(span) => {
-                        trace!(?span, "Adding to batch");
-                        accum.push(span);
-                    }
-                    None => return Err(SpanRxClosed),
-                },
+        tokio::select! {
+            biased;
 
-                // Don't hold spans indefinitely. Return if we hit an idle
-                // timeout and spans have been collected.
-                _ = time::sleep(Self::MAX_BATCH_IDLE) => {
-                    if !accum.is_empty() {
-                        trace!(spans = accum.len(), "Flushing spans due to inactivitiy");
-                        return Ok(());
-                    }
+            res = spans.next() => match res {
+                Some(span) => {
+                    trace!(?span, "Adding to batch");
+                    accum.push(span);
+                }
+                None => return Err(SpanRxClosed),
+            },
+
+            // Don't hold spans indefinitely. Return if we hit an idle
+            // timeout and spans have been collected.
+            _ = time::sleep(MAX_BATCH_IDLE) => {
+                if !accum.is_empty() {
+                    trace!(spans = accum.len(), "Flushing spans due to inactivitiy");
+                    return Ok(());
                 }
             }
         }
@@ -1,11 +1,18 @@
-use linkerd_metrics::{metrics, Counter, FmtMetrics};
+use linkerd_metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics};
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 metrics! {
     opencensus_span_export_streams: Counter { "Total count of opened span export streams" },
     opencensus_span_export_requests: Counter { "Total count of span export request messages" },
-    opencensus_span_exports: Counter { "Total count of spans exported" }
+    opencensus_span_exports: Counter { "Total count of spans exported" },
+    spans_sampled_total: Counter { "Total count of sampled spans sent to the collector" },
+    spans_dropped_total: Counter { "Total count of traced requests that did not emit a span because they were not sampled" },
+    spans_buffer_overflow_total: Counter { "Total count of sampled spans dropped because the collector's buffer was full" },
+    trace_sample_ratio: Ratio { "The fraction of requests without an upstream sampling decision that the proxy samples" }
 }
 
 #[derive(Debug)]
@@ -13,8 +20,16 @@ struct Metrics {
     streams: Counter,
     requests: Counter,
     spans: Counter,
+    sampled: Counter,
+    dropped: Counter,
+    overflow: Counter,
+    sample_ratio: Ratio,
 }
 
+/// A gauge that holds a fraction, which a `Gauge` cannot represent.
+#[derive(Debug)]
+struct Ratio(AtomicU64);
+
 #[derive(Clone, Debug)]
 pub struct Registry(Arc<Metrics>);
 
@@ -26,6 +41,10 @@ pub fn new() -> (Registry, Report) {
         streams: Counter::default(),
         requests: Counter::default(),
         spans: Counter::default(),
+        sampled: Counter::default(),
+        dropped: Counter::default(),
+        overflow: Counter::default(),
+        sample_ratio: Ratio::default(),
     };
     let shared = Arc::new(metrics);
     (Registry(shared.clone()), Report(shared))
@@ -40,6 +59,22 @@ impl Registry {
         self.0.requests.incr();
         self.0.spans.add(spans);
     }
+
+    pub fn span_sampled(&mut self) {
+        self.0.sampled.incr()
+    }
+
+    pub fn span_dropped(&mut self) {
+        self.0.dropped.incr()
+    }
+
+    pub fn span_overflowed(&mut self) {
+        self.0.overflow.incr()
+    }
+
+    pub fn set_sample_ratio(&mut self, ratio: f64) {
+        self.0.sample_ratio.set(ratio)
+    }
 }
 
 impl FmtMetrics for Report {
@@ -53,6 +88,81 @@ impl FmtMetrics for Report {
         opencensus_span_exports.fmt_help(f)?;
         opencensus_span_exports.fmt_metric(f, &self.0.spans)?;
 
+        spans_sampled_total.fmt_help(f)?;
+        spans_sampled_total.fmt_metric(f, &self.0.sampled)?;
+
+        spans_dropped_total.fmt_help(f)?;
+        spans_dropped_total.fmt_metric(f, &self.0.dropped)?;
+
+        spans_buffer_overflow_total.fmt_help(f)?;
+        spans_buffer_overflow_total.fmt_metric(f, &self.0.overflow)?;
+
+        trace_sample_ratio.fmt_help(f)?;
+        trace_sample_ratio.fmt_metric(f, &self.0.sample_ratio)?;
+
         Ok(())
     }
 }
+
+// === impl Ratio ===
+
+impl Ratio {
+    fn set(&self, ratio: f64) {
+        self.0.store(ratio.to_bits(), Ordering::Release)
+    }
+
+    fn value(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Acquire))
+    }
+}
+
+impl Default for Ratio {
+    fn default() -> Self {
+        Self(AtomicU64::new(0.0f64.to_bits()))
+    }
+}
+
+impl FmtMetric for Ratio {
+    const KIND: &'static str = "gauge";
+
+    fn fmt_metric<N: fmt::Display>(&self, f: &mut fmt::Formatter<'_>, name: N) -> fmt::Result {
+        writeln!(f, "{} {}", name, self.value())
+    }
+
+    fn fmt_metric_labeled<N, L>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+        labels: L,
+    ) -> fmt::Result
+    where
+        N: fmt::Display,
+        L: FmtLabels,
+    {
+        write!(f, "{}{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", self.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_sample_ratio() {
+        let (mut registry, report) = new();
+        assert!(report
+            .as_display()
+            .to_string()
+            .lines()
+            .any(|l| l == "trace_sample_ratio 0"));
+
+        registry.set_sample_ratio(0.25);
+        assert!(report
+            .as_display()
+            .to_string()
+            .lines()
+            .any(|l| l == "trace_sample_ratio 0.25"));
+    }
+}
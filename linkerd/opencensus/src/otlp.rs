@@ -0,0 +1,301 @@
+//! Exports spans to an OpenTelemetry collector via OTLP.
+//!
+//! The proxy records spans as OpenCensus protobufs. These are converted to
+//! their OTLP equivalents as they are exported, and the OpenCensus node that
+//! describes the proxy becomes the OTLP resource that produced them.
+
+use crate::{collect_batch, metrics::Registry, SpanRxClosed};
+use futures::stream::Stream;
+use http_body::Body as HttpBody;
+use linkerd_error::Error;
+use opencensus_proto::{agent::common::v1::Node, trace::v1 as oc};
+use opentelemetry_proto::{
+    collector::trace::v1::{trace_service_client::TraceServiceClient, ExportTraceServiceRequest},
+    common::v1::{any_value, AnyValue, KeyValue},
+    resource::v1::Resource,
+    trace::v1::{self as otlp, span::SpanKind, status::StatusCode},
+};
+use std::convert::TryFrom;
+use tonic::{self as grpc, body::BoxBody, client::GrpcService};
+use tracing::{debug, trace};
+
+pub use opentelemetry_proto as proto;
+
+/// Sends each batch of spans to the collector in its own `Export` request.
+///
+/// A batch that the collector fails to accept is dropped rather than retried,
+/// so that a slow or unavailable collector does not cause spans to accumulate
+/// in the proxy.
+pub async fn export_spans<T, S>(client: T, node: Node, mut spans: S, mut metrics: Registry)
+where
+    T: GrpcService<BoxBody> + Clone,
+    T::Error: Into<Error>,
+    <T::ResponseBody as HttpBody>::Error: Into<Error> + Send + Sync,
+    T::ResponseBody: Send + Sync + 'static,
+    S: Stream<Item = oc::Span> + Unpin,
+{
+    debug!("OTLP span exporter running");
+
+    let resource = resource(node);
+    let mut svc = TraceServiceClient::new(client);
+
+    // Holds the batch of pending spans. Cleared as the spans are flushed.
+    let mut accum = Vec::new();
+    loop {
+        let collect = collect_batch(&mut spans, &mut accum).await;
+
+        if !accum.is_empty() {
+            let spans = accum.drain(..).map(span).collect::<Vec<_>>();
+            let count = spans.len() as u64;
+            let req = ExportTraceServiceRequest {
+                resource_spans: vec![otlp::ResourceSpans {
+                    resource: Some(resource.clone()),
+                    instrumentation_library_spans: vec![otlp::InstrumentationLibrarySpans {
+                        spans,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+            };
+            trace!(spans = count, "Sending batch");
+            match svc.export(grpc::Request::new(req)).await {
+                Ok(_) => metrics.send(count),
+                Err(status) => debug!(%status, spans = count, "Failed to export spans"),
+            }
+        }
+
+        if let Err(SpanRxClosed) = collect {
+            debug!("Span channel lost");
+            return;
+        }
+    }
+}
+
+/// Describes the proxy, as identified by its OpenCensus node, with the
+/// attributes of the OpenTelemetry semantic conventions.
+fn resource(node: Node) -> Resource {
+    let mut attributes = Vec::new();
+    if let Some(service) = node.service_info {
+        attributes.push(string_attribute("service.name", service.name));
+    }
+    if let Some(id) = node.identifier {
+        if !id.host_name.is_empty() {
+            attributes.push(string_attribute("host.name", id.host_name));
+        }
+        attributes.push(KeyValue {
+            key: "process.pid".to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(id.pid.into())),
+            }),
+        });
+    }
+
+    // Configured attributes do not override those that identify the proxy.
+    let mut configured = node.attributes.into_iter().collect::<Vec<_>>();
+    configured.sort();
+    for (key, value) in configured {
+        if attributes.iter().all(|kv| kv.key != key) {
+            attributes.push(string_attribute(key, value));
+        }
+    }
+
+    Resource {
+        attributes,
+        dropped_attributes_count: 0,
+    }
+}
+
+fn span(span: oc::Span) -> otlp::Span {
+    let kind = match oc::span::SpanKind::from_i32(span.kind) {
+        Some(oc::span::SpanKind::Server) => SpanKind::Server,
+        Some(oc::span::SpanKind::Client) => SpanKind::Client,
+        _ => SpanKind::Unspecified,
+    };
+
+    let (attributes, dropped_attributes_count) = match span.attributes {
+        Some(attrs) => {
+            let mut attributes = attrs
+                .attribute_map
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = match value.value? {
+                        oc::attribute_value::Value::StringValue(s) => {
+                            any_value::Value::StringValue(s.value)
+                        }
+                        oc::attribute_value::Value::IntValue(i) => any_value::Value::IntValue(i),
+                        oc::attribute_value::Value::BoolValue(b) => any_value::Value::BoolValue(b),
+                        oc::attribute_value::Value::DoubleValue(d) => {
+                            any_value::Value::DoubleValue(d)
+                        }
+                    };
+                    Some(KeyValue {
+                        key,
+                        value: Some(AnyValue { value: Some(value) }),
+                    })
+                })
+                .collect::<Vec<_>>();
+            attributes.sort_by(|a, b| a.key.cmp(&b.key));
+            let dropped = u32::try_from(attrs.dropped_attributes_count).unwrap_or(0);
+            (attributes, dropped)
+        }
+        None => (Vec::new(), 0),
+    };
+
+    let trace_state = span
+        .tracestate
+        .map(|ts| {
+            ts.entries
+                .into_iter()
+                .map(|e| format!("{}={}", e.key, e.value))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .unwrap_or_default();
+
+    // OpenCensus statuses are gRPC status codes, of which only `OK` (0)
+    // indicates success.
+    let status = span.status.map(|status| {
+        let code = if status.code == 0 {
+            StatusCode::Unset
+        } else {
+            StatusCode::Error
+        };
+        otlp::Status {
+            code: code as i32,
+            message: status.message,
+        }
+    });
+
+    // The proxy does not record time events or links.
+    otlp::Span {
+        trace_id: span.trace_id,
+        span_id: span.span_id,
+        trace_state,
+        parent_span_id: span.parent_span_id,
+        name: span.name.map(|n| n.value).unwrap_or_default(),
+        kind: kind as i32,
+        start_time_unix_nano: span
+            .start_time
+            .map_or(0, |t| unix_nanos(t.seconds, t.nanos)),
+        end_time_unix_nano: span.end_time.map_or(0, |t| unix_nanos(t.seconds, t.nanos)),
+        attributes,
+        dropped_attributes_count,
+        status,
+        ..Default::default()
+    }
+}
+
+fn string_attribute(key: impl Into<String>, value: String) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value)),
+        }),
+    }
+}
+
+fn unix_nanos(seconds: i64, nanos: i32) -> u64 {
+    match (u64::try_from(seconds), u64::try_from(nanos)) {
+        (Ok(seconds), Ok(nanos)) => seconds.saturating_mul(1_000_000_000).saturating_add(nanos),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencensus_proto::agent::common::v1 as oc_common;
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    fn string_value(kv: &KeyValue) -> Option<&str> {
+        match kv.value.as_ref()?.value.as_ref()? {
+            any_value::Value::StringValue(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn converts_spans() {
+        let start = SystemTime::UNIX_EPOCH + Duration::new(1, 500);
+        let mut attribute_map = HashMap::new();
+        attribute_map.insert(
+            "http.method".to_string(),
+            oc::AttributeValue {
+                value: Some(oc::attribute_value::Value::StringValue(
+                    oc::TruncatableString {
+                        value: "GET".to_string(),
+                        truncated_byte_count: 0,
+                    },
+                )),
+            },
+        );
+        let oc_span = oc::Span {
+            trace_id: vec![1; 16],
+            span_id: vec![2; 8],
+            parent_span_id: vec![3; 8],
+            name: Some(oc::TruncatableString {
+                value: "foo.ns.svc.cluster.local:8080".to_string(),
+                truncated_byte_count: 0,
+            }),
+            kind: oc::span::SpanKind::Client as i32,
+            start_time: Some(start.into()),
+            end_time: Some((start + Duration::from_millis(2)).into()),
+            attributes: Some(oc::span::Attributes {
+                attribute_map,
+                dropped_attributes_count: 0,
+            }),
+            ..Default::default()
+        };
+
+        let span = span(oc_span);
+        assert_eq!(span.trace_id, vec![1; 16]);
+        assert_eq!(span.span_id, vec![2; 8]);
+        assert_eq!(span.parent_span_id, vec![3; 8]);
+        assert_eq!(span.name, "foo.ns.svc.cluster.local:8080");
+        assert_eq!(span.kind, SpanKind::Client as i32);
+        assert_eq!(span.start_time_unix_nano, 1_000_000_500);
+        assert_eq!(span.end_time_unix_nano, 1_002_000_500);
+        assert_eq!(span.attributes.len(), 1);
+        assert_eq!(span.attributes[0].key, "http.method");
+        assert_eq!(string_value(&span.attributes[0]), Some("GET"));
+        assert_eq!(span.status, None);
+    }
+
+    #[test]
+    fn describes_proxy_as_resource() {
+        let mut attributes = HashMap::new();
+        attributes.insert("k8s.pod.name".to_string(), "foo-abc".to_string());
+        attributes.insert("service.name".to_string(), "foo".to_string());
+        let node = Node {
+            identifier: Some(oc_common::ProcessIdentifier {
+                host_name: "foo-abc".to_string(),
+                pid: 1,
+                start_timestamp: None,
+            }),
+            service_info: Some(oc_common::ServiceInfo {
+                name: "linkerd-proxy".to_string(),
+            }),
+            attributes,
+            ..Default::default()
+        };
+
+        let resource = resource(node);
+        let attrs = resource
+            .attributes
+            .iter()
+            .map(|kv| (kv.key.as_str(), string_value(kv)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attrs,
+            vec![
+                ("service.name", Some("linkerd-proxy")),
+                ("host.name", Some("foo-abc")),
+                ("process.pid", None),
+                ("k8s.pod.name", Some("foo-abc")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,798 @@
+use futures::{prelude::*, ready};
+use linkerd_error::Error;
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use rand::Rng;
+use std::{
+    hash::Hash,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower::{
+    discover::{Change, Discover},
+    load::{
+        completion::{TrackCompletion, TrackCompletionFuture},
+        Load,
+    },
+    ready_cache::ReadyCache,
+};
+
+/// Bounds the latency sample of a single slow request, relative to an
+/// endpoint's current estimate.
+const MAX_SAMPLE_RATIO: f64 = 4.0;
+
+/// Bounds the probability that a balancer prefers an endpoint for its idle
+/// connection, so that the endpoints' loads still decide some choices.
+pub const MAX_AFFINITY_BIAS: f64 = 0.9;
+
+/// Determines how a balancer distributes requests over its endpoints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// Chooses the less loaded of two random endpoints, where load is the
+    /// peak-EWMA of an endpoint's latency.
+    PeakEwma,
+
+    /// Chooses the less loaded of two random endpoints, where load is the
+    /// number of requests in flight to an endpoint.
+    LeastRequest,
+
+    /// Chooses the less loaded of two random endpoints, where load is a
+    /// moving average of an endpoint's latency that is robust to individual
+    /// slow requests.
+    LatencyEwma,
+
+    /// Dispatches to each ready endpoint in turn.
+    RoundRobin,
+
+    /// Chooses a ready endpoint uniformly at random.
+    Random,
+}
+
+/// A balancer that dispatches requests to each of its ready endpoints in turn.
+///
+/// Endpoints are visited in the order they were discovered. Endpoints that are
+/// not ready are skipped until they become ready.
+pub struct RoundRobin<D: Discover, Req> {
+    discover: D,
+    services: ReadyCache<D::Key, D::Service, Req>,
+    keys: Vec<D::Key>,
+    next: usize,
+    ready: Option<D::Key>,
+}
+
+/// Configures balancers to prefer endpoints that are likely to have an idle
+/// connection, so that requests reuse open connections rather than opening
+/// new ones.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConnectionAffinity {
+    /// The probability that an endpoint with an idle connection is preferred
+    /// over an endpoint without one, regardless of their loads. Bounded by
+    /// `MAX_AFFINITY_BIAS`.
+    pub bias: f64,
+
+    /// How long an endpoint's connection is assumed to remain open once its
+    /// last request completes. This should not exceed the client's idle
+    /// timeout, after which the connection is closed.
+    pub idle_timeout: Duration,
+}
+
+/// Wraps each discovered endpoint in `PreferConnected`.
+#[pin_project]
+#[derive(Debug)]
+pub struct PreferConnectedDiscover<D> {
+    #[pin]
+    discover: D,
+    affinity: Option<ConnectionAffinity>,
+}
+
+/// Biases a balancer towards an endpoint while it is likely to have an idle
+/// connection.
+///
+/// An endpoint is considered to have an idle connection when no requests are
+/// in flight to it and its last request completed within the affinity's idle
+/// timeout. Endpoints that are busy are never preferred, so affinity does not
+/// concentrate load on a single endpoint.
+#[derive(Debug)]
+pub struct PreferConnected<S> {
+    inner: S,
+    affinity: Option<ConnectionAffinity>,
+    last_used: Arc<Mutex<Option<Instant>>>,
+}
+
+/// The load of an endpoint that may have an idle connection.
+///
+/// Endpoints that are preferred for their connections are considered less
+/// loaded than all others.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Affinity<M> {
+    Connected(M),
+    Balanced(M),
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct PreferConnectedFuture<F> {
+    #[pin]
+    inner: F,
+    // Held while the request is in flight.
+    last_used: Option<Arc<Mutex<Option<Instant>>>>,
+}
+
+/// Wraps each discovered endpoint in `PenalizeFailures`.
+#[pin_project]
+#[derive(Debug)]
+pub struct PenalizeFailuresDiscover<D> {
+    #[pin]
+    discover: D,
+    window: Option<Duration>,
+}
+
+/// Deprioritizes an endpoint for a time after a request to it fails.
+///
+/// The endpoint is not removed from its balancer. Instead, its load is
+/// reported as `Penalized::Failed`, which power-of-two-choices balancers
+/// consider more loaded than any healthy endpoint. The penalty applies with a
+/// probability that decays linearly over the window, so that the endpoint is
+/// gradually restored to its full weight once the window elapses.
+#[derive(Debug)]
+pub struct PenalizeFailures<S> {
+    inner: S,
+    window: Option<Duration>,
+    failed_at: Arc<Mutex<Option<Instant>>>,
+}
+
+/// The load of an endpoint that may have failed recently.
+///
+/// Healthy endpoints are always preferred. When all candidates have failed
+/// recently, the endpoint that failed least recently is preferred.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Penalized<M> {
+    Healthy(M),
+    Failed(Instant, M),
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct PenalizeFailuresFuture<F> {
+    #[pin]
+    inner: F,
+    failed_at: Option<Arc<Mutex<Option<Instant>>>>,
+}
+
+/// Wraps each discovered endpoint in `LatencyEwma`.
+#[pin_project]
+#[derive(Debug)]
+pub struct LatencyEwmaDiscover<D, C> {
+    #[pin]
+    discover: D,
+    default_rtt: Duration,
+    decay: Duration,
+    completion: C,
+}
+
+/// Measures an endpoint's load as an exponentially-weighted moving average of
+/// its response latency, scaled by the number of requests in flight to it.
+///
+/// Unlike a peak-EWMA, an estimate never jumps to the latency of a single slow
+/// request: each sample is bounded to `MAX_SAMPLE_RATIO` times the current
+/// estimate. Endpoints without any latency history are assumed to have the
+/// default RTT, which should be optimistic so that new endpoints are sent
+/// requests.
+#[derive(Debug)]
+pub struct LatencyEwma<S, C> {
+    inner: S,
+    completion: C,
+    estimate: Arc<Mutex<Estimate>>,
+}
+
+/// Records a latency sample when a request completes.
+#[derive(Debug)]
+pub struct LatencyHandle {
+    sent_at: Instant,
+    estimate: Arc<Mutex<Estimate>>,
+}
+
+/// The load of an endpoint as measured by `LatencyEwma`.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Latency(f64);
+
+#[derive(Debug)]
+struct Estimate {
+    rtt_ns: f64,
+    decay_ns: f64,
+    updated_at: Instant,
+}
+
+// === impl Algorithm ===
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Self::PeakEwma
+    }
+}
+
+// === impl RoundRobin ===
+
+impl<D, Req> RoundRobin<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<Req>,
+    <D::Service as tower::Service<Req>>::Error: Into<Error>,
+{
+    pub fn new(discover: D) -> Self {
+        Self {
+            discover,
+            services: ReadyCache::default(),
+            keys: Vec::new(),
+            next: 0,
+            ready: None,
+        }
+    }
+
+    /// Polls discovery for changes, returning `None` once discovery has
+    /// completed.
+    fn update_from_discover(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<(), Error>>> {
+        loop {
+            let change = ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(Into::into)?;
+            match change {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    self.keys.retain(|k| *k != key);
+                    self.services.evict(&key);
+                }
+                Some(Change::Insert(key, svc)) => {
+                    if !self.keys.contains(&key) {
+                        self.keys.push(key.clone());
+                    }
+                    self.services.push(key, svc);
+                }
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => return,
+                // The failed endpoint has been evicted; keep polling the
+                // others.
+                Poll::Ready(Err(_)) => {}
+            }
+        }
+    }
+}
+
+impl<D, Req> tower::Service<Req> for RoundRobin<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<Req>,
+    <D::Service as tower::Service<Req>>::Error: Into<Error>,
+{
+    type Response = <D::Service as tower::Service<Req>>::Response;
+    type Error = Error;
+    type Future = future::ErrInto<<D::Service as tower::Service<Req>>::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Once discovery completes, the balancer continues to use the
+        // endpoints it has already discovered.
+        let _ = self.update_from_discover(cx)?;
+        self.promote_pending_to_ready(cx);
+
+        let mut checked = 0;
+        while checked < self.keys.len() {
+            let i = self.next % self.keys.len();
+            match self.services.check_ready(cx, &self.keys[i]) {
+                Ok(true) => {
+                    self.next = i + 1;
+                    self.ready = Some(self.keys[i].clone());
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(false) => {
+                    self.next = i + 1;
+                    checked += 1;
+                }
+                // The endpoint failed and has been evicted, so it is skipped
+                // until it is rediscovered.
+                Err(_) => {
+                    self.keys.remove(i);
+                    self.next = i;
+                }
+            }
+        }
+
+        self.ready = None;
+        Poll::Pending
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let key = self.ready.take().expect("called before ready");
+        self.services.call_ready(&key, req).err_into()
+    }
+}
+
+// === impl PreferConnectedDiscover ===
+
+impl<D> PreferConnectedDiscover<D> {
+    /// Biases the balancer towards endpoints with idle connections. Endpoints
+    /// are never preferred if `affinity` is `None`.
+    pub fn new(discover: D, affinity: Option<ConnectionAffinity>) -> Self {
+        let affinity = affinity.map(|a| ConnectionAffinity {
+            bias: a.bias.max(0.0).min(MAX_AFFINITY_BIAS),
+            ..a
+        });
+        Self { discover, affinity }
+    }
+}
+
+impl<D: Discover> Stream for PreferConnectedDiscover<D> {
+    type Item = Result<Change<D::Key, PreferConnected<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let affinity = *this.affinity;
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(key)) => Change::Remove(key),
+            Some(Change::Insert(key, svc)) => {
+                Change::Insert(key, PreferConnected::new(svc, affinity))
+            }
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// === impl PreferConnected ===
+
+impl<S> PreferConnected<S> {
+    pub fn new(inner: S, affinity: Option<ConnectionAffinity>) -> Self {
+        Self {
+            inner,
+            affinity,
+            last_used: Default::default(),
+        }
+    }
+
+    fn is_idle_connected(&self, idle_timeout: Duration) -> bool {
+        // Each outstanding request holds a handle to the last use.
+        if Arc::strong_count(&self.last_used) > 1 {
+            return false;
+        }
+        match *self.last_used.lock() {
+            Some(last_used) => last_used.elapsed() < idle_timeout,
+            None => false,
+        }
+    }
+}
+
+impl<S: Load> Load for PreferConnected<S> {
+    type Metric = Affinity<S::Metric>;
+
+    fn load(&self) -> Self::Metric {
+        let load = self.inner.load();
+        if let Some(ConnectionAffinity { bias, idle_timeout }) = self.affinity {
+            if self.is_idle_connected(idle_timeout) && rand::thread_rng().gen_bool(bias) {
+                return Affinity::Connected(load);
+            }
+        }
+        Affinity::Balanced(load)
+    }
+}
+
+impl<S, Req> tower::Service<Req> for PreferConnected<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = PreferConnectedFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        PreferConnectedFuture {
+            inner: self.inner.call(req),
+            last_used: self.affinity.map(|_| self.last_used.clone()),
+        }
+    }
+}
+
+// === impl PreferConnectedFuture ===
+
+impl<F: Future> Future for PreferConnectedFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx));
+        if let Some(last_used) = this.last_used.take() {
+            *last_used.lock() = Some(Instant::now());
+        }
+        Poll::Ready(res)
+    }
+}
+
+// === impl PenalizeFailuresDiscover ===
+
+impl<D> PenalizeFailuresDiscover<D> {
+    /// Penalizes endpoints that fail for `window`. Endpoints are never
+    /// penalized if `window` is `None`.
+    pub fn new(discover: D, window: Option<Duration>) -> Self {
+        Self { discover, window }
+    }
+}
+
+impl<D: Discover> Stream for PenalizeFailuresDiscover<D> {
+    type Item = Result<Change<D::Key, PenalizeFailures<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let window = *this.window;
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(key)) => Change::Remove(key),
+            Some(Change::Insert(key, svc)) => {
+                Change::Insert(key, PenalizeFailures::new(svc, window))
+            }
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// === impl PenalizeFailures ===
+
+impl<S> PenalizeFailures<S> {
+    pub fn new(inner: S, window: Option<Duration>) -> Self {
+        Self {
+            inner,
+            window,
+            failed_at: Default::default(),
+        }
+    }
+}
+
+impl<S: Load> Load for PenalizeFailures<S> {
+    type Metric = Penalized<S::Metric>;
+
+    fn load(&self) -> Self::Metric {
+        let load = self.inner.load();
+        if let (Some(window), Some(failed_at)) = (self.window, *self.failed_at.lock()) {
+            let elapsed = failed_at.elapsed();
+            if elapsed < window {
+                let remaining = 1.0 - elapsed.as_secs_f64() / window.as_secs_f64();
+                if rand::thread_rng().gen_bool(remaining) {
+                    return Penalized::Failed(failed_at, load);
+                }
+            }
+        }
+        Penalized::Healthy(load)
+    }
+}
+
+impl<S, Req> tower::Service<Req> for PenalizeFailures<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = PenalizeFailuresFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        PenalizeFailuresFuture {
+            inner: self.inner.call(req),
+            failed_at: self.window.map(|_| self.failed_at.clone()),
+        }
+    }
+}
+
+// === impl PenalizeFailuresFuture ===
+
+impl<F, T, E> Future for PenalizeFailuresFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx));
+        if res.is_err() {
+            if let Some(failed_at) = this.failed_at.take() {
+                *failed_at.lock() = Some(Instant::now());
+            }
+        }
+        Poll::Ready(res)
+    }
+}
+
+// === impl LatencyEwmaDiscover ===
+
+impl<D, C> LatencyEwmaDiscover<D, C> {
+    pub fn new(discover: D, default_rtt: Duration, decay: Duration, completion: C) -> Self {
+        Self {
+            discover,
+            default_rtt,
+            decay,
+            completion,
+        }
+    }
+}
+
+impl<D, C> Stream for LatencyEwmaDiscover<D, C>
+where
+    D: Discover,
+    C: Clone,
+{
+    type Item = Result<Change<D::Key, LatencyEwma<D::Service, C>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(key)) => Change::Remove(key),
+            Some(Change::Insert(key, svc)) => Change::Insert(
+                key,
+                LatencyEwma::new(svc, *this.default_rtt, *this.decay, this.completion.clone()),
+            ),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// === impl LatencyEwma ===
+
+impl<S, C> LatencyEwma<S, C> {
+    pub fn new(inner: S, default_rtt: Duration, decay: Duration, completion: C) -> Self {
+        let estimate = Estimate {
+            rtt_ns: nanos(default_rtt),
+            decay_ns: nanos(decay),
+            updated_at: Instant::now(),
+        };
+        Self {
+            inner,
+            completion,
+            estimate: Arc::new(Mutex::new(estimate)),
+        }
+    }
+}
+
+impl<S, C> Load for LatencyEwma<S, C> {
+    type Metric = Latency;
+
+    fn load(&self) -> Latency {
+        // Each outstanding request holds a handle to the estimate.
+        let pending = Arc::strong_count(&self.estimate) - 1;
+        let rtt_ns = self.estimate.lock().rtt_ns;
+        Latency(rtt_ns * (pending + 1) as f64)
+    }
+}
+
+impl<S, C, Req> tower::Service<Req> for LatencyEwma<S, C>
+where
+    S: tower::Service<Req>,
+    C: TrackCompletion<LatencyHandle, S::Response>,
+{
+    type Response = C::Output;
+    type Error = S::Error;
+    type Future = TrackCompletionFuture<S::Future, C, LatencyHandle>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let handle = LatencyHandle {
+            sent_at: Instant::now(),
+            estimate: self.estimate.clone(),
+        };
+        TrackCompletionFuture::new(self.completion.clone(), handle, self.inner.call(req))
+    }
+}
+
+// === impl LatencyHandle ===
+
+impl Drop for LatencyHandle {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        let rtt = now.saturating_duration_since(self.sent_at);
+        self.estimate.lock().update(rtt, now);
+    }
+}
+
+// === impl Estimate ===
+
+impl Estimate {
+    fn update(&mut self, rtt: Duration, now: Instant) {
+        let sample = nanos(rtt).min(self.rtt_ns * MAX_SAMPLE_RATIO);
+
+        // Samples are weighted by the time since the estimate was last
+        // updated, so that the estimate follows an idle endpoint's latency
+        // more quickly than a busy endpoint's.
+        let elapsed = nanos(now.saturating_duration_since(self.updated_at));
+        let decay = (-elapsed / self.decay_ns).exp();
+        self.rtt_ns = self.rtt_ns * decay + sample * (1.0 - decay);
+        self.updated_at = now;
+    }
+}
+
+fn nanos(d: Duration) -> f64 {
+    d.as_secs_f64() * 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time;
+    use tower::{
+        load::{CompleteOnResponse, Constant},
+        Service, ServiceExt,
+    };
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    /// Returns an endpoint whose requests fail when they are `true`.
+    fn endpoint() -> PenalizeFailures<
+        Constant<
+            impl tower::Service<bool, Response = (), Error = Error, Future = impl Send>,
+            usize,
+        >,
+    > {
+        let svc = tower::service_fn(|fail: bool| async move {
+            if fail {
+                Err::<(), Error>("endpoint failed".into())
+            } else {
+                Ok(())
+            }
+        });
+        PenalizeFailures::new(Constant::new(svc, 0), Some(WINDOW))
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn deprioritizes_recent_failures() {
+        let healthy = endpoint();
+        let mut failing = endpoint();
+        assert_eq!(failing.load(), Penalized::Healthy(0));
+
+        failing.ready().await.unwrap().call(false).await.unwrap();
+        assert_eq!(failing.load(), Penalized::Healthy(0));
+
+        failing.ready().await.unwrap().call(true).await.unwrap_err();
+        let failed_at = Instant::now();
+        assert_eq!(failing.load(), Penalized::Failed(failed_at, 0));
+        assert!(healthy.load() < failing.load());
+
+        // Once the window elapses, the endpoint is restored.
+        time::advance(WINDOW).await;
+        assert_eq!(failing.load(), Penalized::Healthy(0));
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn prefers_least_recently_failed() {
+        let mut first = endpoint();
+        let mut second = endpoint();
+        first.ready().await.unwrap().call(true).await.unwrap_err();
+        time::advance(WINDOW / 2).await;
+        second.ready().await.unwrap().call(true).await.unwrap_err();
+
+        for _ in 0..100 {
+            assert!(first.load() < second.load());
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn bounds_latency_samples() {
+        const DEFAULT_RTT: Duration = Duration::from_millis(10);
+
+        let svc = tower::service_fn(|latency: Duration| async move {
+            time::sleep(latency).await;
+            Ok::<_, Error>(())
+        });
+        let mut endpoint = LatencyEwma::new(
+            svc,
+            DEFAULT_RTT,
+            Duration::from_secs(1),
+            CompleteOnResponse::default(),
+        );
+        assert_eq!(
+            endpoint.load(),
+            Latency(nanos(DEFAULT_RTT)),
+            "endpoints without history must assume the default RTT"
+        );
+
+        // The estimate has decayed entirely, so it would otherwise become the
+        // latency of the slow request.
+        time::advance(Duration::from_secs(60)).await;
+        endpoint
+            .ready()
+            .await
+            .unwrap()
+            .call(Duration::from_secs(10))
+            .await
+            .unwrap();
+        let Latency(load) = endpoint.load();
+        let bound = nanos(DEFAULT_RTT) * MAX_SAMPLE_RATIO;
+        assert!((load - bound).abs() < 1.0, "{} != {}", load, bound);
+
+        // Requests in flight increase the endpoint's load.
+        let _rsp = endpoint.ready().await.unwrap().call(Duration::from_secs(1));
+        let Latency(pending) = endpoint.load();
+        assert!(
+            (pending - 2.0 * load).abs() < 1.0,
+            "{} != {}",
+            pending,
+            load
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn prefers_idle_connections() {
+        const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+        let svc = tower::service_fn(|latency: Duration| async move {
+            time::sleep(latency).await;
+            Ok::<_, Error>(())
+        });
+        let affinity = ConnectionAffinity {
+            bias: 1.0,
+            idle_timeout: IDLE_TIMEOUT,
+        };
+        let discover = PreferConnectedDiscover::new((), Some(affinity));
+        assert_eq!(
+            discover.affinity.unwrap().bias,
+            MAX_AFFINITY_BIAS,
+            "bias must be bounded"
+        );
+        // Bypass the bound so that the endpoint is preferred every time.
+        let mut endpoint = PreferConnected::new(Constant::new(svc, 0), Some(affinity));
+        assert_eq!(
+            endpoint.load(),
+            Affinity::Balanced(0),
+            "endpoints without connections must not be preferred"
+        );
+
+        endpoint
+            .ready()
+            .await
+            .unwrap()
+            .call(Duration::from_millis(1))
+            .await
+            .unwrap();
+        assert_eq!(endpoint.load(), Affinity::Connected(0));
+
+        // Busy endpoints are not preferred.
+        let rsp = endpoint.ready().await.unwrap().call(Duration::from_secs(1));
+        assert_eq!(endpoint.load(), Affinity::Balanced(0));
+        rsp.await.unwrap();
+        assert_eq!(endpoint.load(), Affinity::Connected(0));
+
+        // Once the connection would have idled out, the endpoint is no longer
+        // preferred.
+        time::advance(IDLE_TIMEOUT).await;
+        assert_eq!(endpoint.load(), Affinity::Balanced(0));
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn ignores_failures_without_window() {
+        let svc = tower::service_fn(|()| async { Err::<(), Error>("endpoint failed".into()) });
+        let mut endpoint = PenalizeFailures::new(Constant::new(svc, 0), None);
+        endpoint.ready().await.unwrap().call(()).await.unwrap_err();
+        assert_eq!(endpoint.load(), Penalized::Healthy(0));
+    }
+}
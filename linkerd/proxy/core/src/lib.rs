@@ -1,6 +1,7 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
+pub mod balance;
 pub mod resolve;
 
 pub use self::resolve::{Resolve, ResolveService, Update};
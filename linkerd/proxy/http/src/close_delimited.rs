@@ -0,0 +1,301 @@
+use crate::upgrade::HttpConnect;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{future, prelude::*};
+use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http_body::{Body, SizeHint};
+use linkerd_error::Error;
+use linkerd_stack::layer;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Determines how HTTP/1 responses that lack both a `content-length` and a
+/// `transfer-encoding` are handled.
+///
+/// The bodies of these responses are delimited by the upstream closing its
+/// connection, which some clients handle poorly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CloseDelimited {
+    /// Responses are forwarded as-is.
+    PassThrough,
+
+    /// Response bodies of at most `max_bytes` are buffered so that the
+    /// response can be forwarded with a `content-length`. Larger bodies are
+    /// forwarded as-is.
+    Buffer { max_bytes: usize },
+
+    /// Responses fail with a `CloseDelimitedResponse` error.
+    Reject,
+}
+
+/// Applies a `CloseDelimited` policy to the responses of the inner service.
+#[derive(Clone, Debug)]
+pub struct HandleCloseDelimited<S> {
+    inner: S,
+    policy: CloseDelimited,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("upstream response is delimited by connection close")]
+pub struct CloseDelimitedResponse(());
+
+/// A response body that may have been partially or completely buffered.
+#[pin_project]
+#[derive(Debug)]
+pub struct CloseDelimitedBody<B> {
+    buffered: Option<Bytes>,
+    #[pin]
+    inner: Option<B>,
+}
+
+type Handled<B> = http::Response<CloseDelimitedBody<B>>;
+
+type BoxFuture<B> = Pin<Box<dyn Future<Output = Result<Handled<B>, Error>> + Send + 'static>>;
+
+// === impl CloseDelimited ===
+
+impl Default for CloseDelimited {
+    fn default() -> Self {
+        Self::PassThrough
+    }
+}
+
+// === impl HandleCloseDelimited ===
+
+impl<S> HandleCloseDelimited<S> {
+    pub fn layer(policy: CloseDelimited) -> impl layer::Layer<S, Service = Self> + Clone {
+        layer::mk(move |inner| Self { inner, policy })
+    }
+}
+
+impl<S, A, B> tower::Service<http::Request<A>> for HandleCloseDelimited<S>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+    B: Body<Data = Bytes> + Send + Unpin + 'static,
+    B::Error: Into<Error>,
+{
+    type Response = Handled<B>;
+    type Error = Error;
+    type Future = future::Either<
+        future::MapOk<future::ErrInto<S::Future, Error>, fn(http::Response<B>) -> Handled<B>>,
+        BoxFuture<B>,
+    >;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let is_head = req.method() == http::Method::HEAD;
+        let rsp = self.inner.call(req).err_into::<Error>();
+        let pass: fn(http::Response<B>) -> Handled<B> = |rsp| rsp.map(CloseDelimitedBody::new);
+        let buffer_max = match self.policy {
+            _ if is_head => return future::Either::Left(rsp.map_ok(pass)),
+            CloseDelimited::PassThrough => return future::Either::Left(rsp.map_ok(pass)),
+            CloseDelimited::Buffer { max_bytes } => Some(max_bytes),
+            CloseDelimited::Reject => None,
+        };
+
+        future::Either::Right(Box::pin(async move {
+            let rsp = rsp.await?;
+            if !is_close_delimited(&rsp) {
+                return Ok(pass(rsp));
+            }
+            match buffer_max {
+                Some(max_bytes) => buffer(rsp, max_bytes).await,
+                None => Err(CloseDelimitedResponse(()).into()),
+            }
+        }))
+    }
+}
+
+fn is_close_delimited<B>(rsp: &http::Response<B>) -> bool {
+    let status = rsp.status();
+    let has_body = !(status.is_informational()
+        || status == http::StatusCode::NO_CONTENT
+        || status == http::StatusCode::NOT_MODIFIED
+        || rsp.extensions().get::<HttpConnect>().is_some());
+    let is_http1 =
+        rsp.version() == http::Version::HTTP_10 || rsp.version() == http::Version::HTTP_11;
+
+    is_http1
+        && has_body
+        && !rsp.headers().contains_key(CONTENT_LENGTH)
+        && !rsp.headers().contains_key(TRANSFER_ENCODING)
+}
+
+/// Reads up to `max_bytes` of the response's body. If the body ends within the
+/// limit, the response is returned with a `content-length`; otherwise, the
+/// buffered data is forwarded before the remainder of the body.
+async fn buffer<B>(rsp: http::Response<B>, max_bytes: usize) -> Result<Handled<B>, Error>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Error>,
+{
+    let (mut head, mut body) = rsp.into_parts();
+    let mut buf = BytesMut::new();
+    while buf.len() <= max_bytes {
+        match body.data().await {
+            Some(data) => buf.extend_from_slice(data.map_err(Into::into)?.chunk()),
+            None => {
+                head.headers.insert(CONTENT_LENGTH, buf.len().into());
+                let body = CloseDelimitedBody {
+                    buffered: Some(buf.freeze()).filter(|b| !b.is_empty()),
+                    inner: None,
+                };
+                return Ok(http::Response::from_parts(head, body));
+            }
+        }
+    }
+
+    tracing::debug!(max_bytes, "Forwarding close-delimited response body");
+    let body = CloseDelimitedBody {
+        buffered: Some(buf.freeze()),
+        inner: Some(body),
+    };
+    Ok(http::Response::from_parts(head, body))
+}
+
+// === impl CloseDelimitedBody ===
+
+impl<B> CloseDelimitedBody<B> {
+    fn new(inner: B) -> Self {
+        Self {
+            buffered: None,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl<B: Default> Default for CloseDelimitedBody<B> {
+    fn default() -> Self {
+        Self::new(B::default())
+    }
+}
+
+impl<B> Body for CloseDelimitedBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        if let Some(buffered) = this.buffered.take() {
+            return Poll::Ready(Some(Ok(buffered)));
+        }
+        match this.inner.as_pin_mut() {
+            Some(inner) => inner.poll_data(cx).map_err(Into::into),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        match self.project().inner.as_pin_mut() {
+            Some(inner) => inner.poll_trailers(cx).map_err(Into::into),
+            None => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffered.is_none() && self.inner.as_ref().map_or(true, Body::is_end_stream)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffered.as_ref().map_or(0, |b| b.len() as u64);
+        let mut hint = match self.inner.as_ref() {
+            Some(inner) => inner.size_hint(),
+            None => SizeHint::with_exact(0),
+        };
+        if let Some(upper) = hint.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint.set_lower(hint.lower() + buffered);
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn close_delimited(chunks: &'static [&'static str]) -> http::Response<hyper::Body> {
+        let chunks = chunks
+            .iter()
+            .map(|c| Ok::<_, Error>(Bytes::from_static(c.as_bytes())));
+        let body = hyper::Body::wrap_stream(futures::stream::iter(chunks));
+        http::Response::new(body)
+    }
+
+    async fn handle(
+        policy: CloseDelimited,
+        rsp: http::Response<hyper::Body>,
+    ) -> Result<(Option<String>, String), Error> {
+        let mut rsp = Some(rsp);
+        let svc = HandleCloseDelimited {
+            inner: tower::service_fn(move |_: http::Request<()>| {
+                future::ok::<_, Error>(rsp.take().expect("called once"))
+            }),
+            policy,
+        };
+        let rsp = svc.oneshot(http::Request::new(())).await?;
+        let content_length = rsp
+            .headers()
+            .get(CONTENT_LENGTH)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(rsp.into_body()).await?;
+        Ok((content_length, String::from_utf8(body.to_vec()).unwrap()))
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn passes_through() {
+        let rsp = close_delimited(&["hello ", "world"]);
+        let (len, body) = handle(CloseDelimited::PassThrough, rsp).await.unwrap();
+        assert_eq!(len, None);
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn buffers_small_bodies() {
+        let policy = CloseDelimited::Buffer { max_bytes: 16 };
+        let rsp = close_delimited(&["hello ", "world"]);
+        let (len, body) = handle(policy, rsp).await.unwrap();
+        assert_eq!(len.as_deref(), Some("11"));
+        assert_eq!(body, "hello world");
+
+        // Bodies that exceed the limit are forwarded without a content-length.
+        let policy = CloseDelimited::Buffer { max_bytes: 4 };
+        let rsp = close_delimited(&["hello ", "world"]);
+        let (len, body) = handle(policy, rsp).await.unwrap();
+        assert_eq!(len, None);
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects() {
+        let rsp = close_delimited(&["hello"]);
+        let err = handle(CloseDelimited::Reject, rsp).await.unwrap_err();
+        assert!(err.is::<CloseDelimitedResponse>());
+
+        // Responses with a content-length are not rejected.
+        let mut rsp = close_delimited(&["hello"]);
+        rsp.headers_mut().insert(CONTENT_LENGTH, 5.into());
+        let (len, body) = handle(CloseDelimited::Reject, rsp).await.unwrap();
+        assert_eq!(len.as_deref(), Some("5"));
+        assert_eq!(body, "hello");
+    }
+}
@@ -10,6 +10,9 @@ use tracing::{debug, trace};
 const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0";
 const SMALLEST_POSSIBLE_HTTP1_REQ: &str = "GET / HTTP/1.1";
 
+// The maximum number of headers that hyper parses in an HTTP/1 message.
+const MAX_HTTP1_HEADERS: usize = 100;
+
 /// Attempts to detect the HTTP version of a stream.
 ///
 /// This module biases towards availability instead of correctness. I.e. instead
@@ -22,14 +25,65 @@ const SMALLEST_POSSIBLE_HTTP1_REQ: &str = "GET / HTTP/1.1";
 /// messages. In rare situations, we may fail to properly detect that a stream is
 /// HTTP.
 #[derive(Clone, Debug, Default)]
-pub struct DetectHttp(());
+pub struct DetectHttp {
+    validate_http1: bool,
+}
+
+impl DetectHttp {
+    /// Detects HTTP/1 streams only once their first message head has been
+    /// fully read and parsed.
+    ///
+    /// Streams whose message heads are malformed, or that close before a
+    /// complete head is read, are not detected as HTTP, so that they may be
+    /// forwarded opaquely, with the bytes that have already been read, instead
+    /// of failing in the HTTP server. Heads that do not fit in the detection
+    /// buffer are assumed to be valid.
+    pub fn validate_http1() -> Self {
+        Self {
+            validate_http1: true,
+        }
+    }
+
+    async fn read_http1_head<I: io::AsyncRead + Send + Unpin + 'static>(
+        io: &mut I,
+        buf: &mut BytesMut,
+        capacity: usize,
+    ) -> Result<Option<Version>, Error> {
+        loop {
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HTTP1_HEADERS];
+            match httparse::Request::new(&mut headers).parse(&buf[..]) {
+                Ok(httparse::Status::Complete(_)) | Err(httparse::Error::TooManyHeaders) => {
+                    trace!("Parsed HTTP/1 message head");
+                    return Ok(Some(Version::Http1));
+                }
+                Ok(httparse::Status::Partial) => {}
+                Err(error) => {
+                    debug!(%error, "Malformed HTTP/1 message head");
+                    return Ok(None);
+                }
+            }
+
+            if buf.len() >= capacity {
+                debug!(read = buf.len(), "HTTP/1 message head exceeds the buffer");
+                return Ok(Some(Version::Http1));
+            }
+
+            trace!(read = buf.len(), "Reading HTTP/1 message head");
+            if io.read_buf(buf).await? == 0 {
+                debug!(read = buf.len(), "Stream closed before HTTP/1 message head");
+                return Ok(None);
+            }
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl<I: io::AsyncRead + Send + Unpin + 'static> Detect<I> for DetectHttp {
     type Protocol = Version;
 
     async fn detect(&self, io: &mut I, buf: &mut BytesMut) -> Result<Option<Version>, Error> {
-        trace!(capacity = buf.capacity(), "Reading");
+        let capacity = buf.capacity();
+        trace!(capacity, "Reading");
         let sz = io.read_buf(buf).await?;
         trace!(sz, "Read");
         if sz == 0 {
@@ -58,6 +112,9 @@ impl<I: io::AsyncRead + Send + Unpin + 'static> Detect<I> for DetectHttp {
                 httparse::Request::new(&mut [httparse::EMPTY_HEADER; 0]).parse(&buf[..])
             {
                 trace!("Matched HTTP/1");
+                if self.validate_http1 {
+                    return Self::read_http1_head(io, buf, capacity).await;
+                }
                 return Ok(Some(Version::Http1));
             }
         }
@@ -85,7 +142,10 @@ mod tests {
             debug!(read = ?std::str::from_utf8(read).unwrap());
             let mut buf = BytesMut::with_capacity(1024);
             let mut io = io::Builder::new().read(read).build();
-            let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+            let kind = DetectHttp::default()
+                .detect(&mut io, &mut buf)
+                .await
+                .unwrap();
             assert_eq!(kind, Some(Version::H2));
         }
     }
@@ -98,14 +158,20 @@ mod tests {
             debug!(read = ?std::str::from_utf8(&HTTP11_LINE[..i]).unwrap());
             let mut buf = BytesMut::with_capacity(1024);
             let mut io = io::Builder::new().read(&HTTP11_LINE[..i]).build();
-            let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+            let kind = DetectHttp::default()
+                .detect(&mut io, &mut buf)
+                .await
+                .unwrap();
             assert_eq!(kind, None);
         }
 
         debug!(read = ?std::str::from_utf8(HTTP11_LINE).unwrap());
         let mut buf = BytesMut::with_capacity(1024);
         let mut io = io::Builder::new().read(HTTP11_LINE).build();
-        let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+        let kind = DetectHttp::default()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
         assert_eq!(kind, Some(Version::Http1));
 
         const REQ: &[u8] = b"GET /foo/bar/bar/blah HTTP/1.1\r\nHost: foob.example.com\r\n\r\n";
@@ -113,7 +179,10 @@ mod tests {
             debug!(read = ?std::str::from_utf8(&REQ[..i]).unwrap());
             let mut buf = BytesMut::with_capacity(1024);
             let mut io = io::Builder::new().read(&REQ[..i]).build();
-            let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+            let kind = DetectHttp::default()
+                .detect(&mut io, &mut buf)
+                .await
+                .unwrap();
             assert_eq!(kind, Some(Version::Http1));
             assert_eq!(buf[..], REQ[..i]);
         }
@@ -124,7 +193,10 @@ mod tests {
             let mut buf = BytesMut::with_capacity(1024);
             let mut io = io::Builder::new().read(&POST[..i]).build();
             debug!(read = ?std::str::from_utf8(&POST[..i]).unwrap());
-            let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+            let kind = DetectHttp::default()
+                .detect(&mut io, &mut buf)
+                .await
+                .unwrap();
             assert_eq!(kind, Some(Version::Http1));
             assert_eq!(buf[..], POST[..i]);
         }
@@ -136,16 +208,59 @@ mod tests {
 
         let mut buf = BytesMut::with_capacity(1024);
         let mut io = io::Builder::new().read(b"foo.bar.blah\r\nbobo").build();
-        let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+        let kind = DetectHttp::default()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
         assert_eq!(kind, None);
         assert_eq!(&buf[..], b"foo.bar.blah\r\nbobo");
 
         let mut buf = BytesMut::with_capacity(1024);
         let mut io = io::Builder::new().read(GARBAGE).build();
-        let kind = DetectHttp(()).detect(&mut io, &mut buf).await.unwrap();
+        let kind = DetectHttp::default()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
         assert_eq!(kind, None);
         assert_eq!(&buf[..], GARBAGE);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn validate_http1() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        const REQ: &[u8] = b"GET /foo HTTP/1.1\r\nHost: foob.example.com\r\n\r\n";
+        let mut buf = BytesMut::with_capacity(1024);
+        let mut io = io::Builder::new()
+            .read(&REQ[..SMALLEST_POSSIBLE_HTTP1_REQ.len()])
+            .read(&REQ[SMALLEST_POSSIBLE_HTTP1_REQ.len()..])
+            .build();
+        let kind = DetectHttp::validate_http1()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(kind, Some(Version::Http1));
+        assert_eq!(&buf[..], REQ);
+
+        // Looks like HTTP/1, but has a malformed header.
+        const MALFORMED: &[u8] = b"GET /foo HTTP/1.1\r\nnot a header\r\n\r\ngarbage";
+        let mut buf = BytesMut::with_capacity(1024);
+        let mut io = io::Builder::new().read(MALFORMED).build();
+        let kind = DetectHttp::validate_http1()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(kind, None);
+        assert_eq!(&buf[..], MALFORMED, "read bytes must be retained");
+
+        let mut buf = BytesMut::with_capacity(1024);
+        let mut io = io::Builder::new().read(MALFORMED).build();
+        let kind = DetectHttp::default()
+            .detect(&mut io, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(kind, Some(Version::Http1));
+    }
 }
 
 #[cfg(fuzzing)]
@@ -161,7 +276,10 @@ pub mod fuzz_logic {
         let write = tokio::spawn(async move { client.write_buf(&mut buf).await });
 
         let mut buf = BytesMut::with_capacity(1024);
-        let _kind = DetectHttp(()).detect(&mut server, &mut buf).await.unwrap();
+        let _kind = DetectHttp::default()
+            .detect(&mut server, &mut buf)
+            .await
+            .unwrap();
 
         write
             .await
@@ -0,0 +1,254 @@
+use futures::{future, prelude::*};
+use http::{HeaderMap, HeaderValue};
+use http_body::Body;
+use linkerd_error::Error;
+use linkerd_http_box::BoxBody;
+use linkerd_stack::layer;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tracing::{debug, Instrument};
+
+/// Determines what happens to a request that is still in flight when the
+/// client that sent it disconnects.
+///
+/// The server drops a request's response future, or the body of its response,
+/// when its HTTP/2 stream is reset or its HTTP/1 connection is closed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClientDisconnect {
+    /// The request is canceled, so that the upstream stream is reset or the
+    /// upstream connection is closed.
+    Cancel,
+
+    /// The request is dispatched to completion on a background task and its
+    /// response, including any of its body that has not been sent to the
+    /// client, is discarded.
+    Complete,
+}
+
+/// Applies a `ClientDisconnect` policy to the requests sent to the inner
+/// service.
+#[derive(Clone, Debug)]
+pub struct HandleDisconnect<S> {
+    inner: S,
+    policy: ClientDisconnect,
+}
+
+/// A response body that, if the client disconnects before it has been read to
+/// completion, is read to completion on a background task.
+struct CompleteBody {
+    inner: BoxBody,
+    complete: bool,
+}
+
+type Detached<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'static>>;
+
+// === impl ClientDisconnect ===
+
+impl Default for ClientDisconnect {
+    fn default() -> Self {
+        Self::Cancel
+    }
+}
+
+// === impl HandleDisconnect ===
+
+impl<S> HandleDisconnect<S> {
+    pub fn layer(policy: ClientDisconnect) -> impl layer::Layer<S, Service = Self> + Clone {
+        layer::mk(move |inner| Self { inner, policy })
+    }
+}
+
+impl<S, Req> tower::Service<Req> for HandleDisconnect<S>
+where
+    S: tower::Service<Req, Response = http::Response<BoxBody>>,
+    S::Error: Into<Error> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Error;
+    type Future = future::Either<future::ErrInto<S::Future, Error>, Detached<Self::Response>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let rsp = self.inner.call(req);
+        match self.policy {
+            ClientDisconnect::Cancel => future::Either::Left(rsp.err_into()),
+            ClientDisconnect::Complete => {
+                // Dropping the task's handle does not cancel the task.
+                let task = tokio::spawn(
+                    rsp.map_ok(|rsp| rsp.map(|inner| BoxBody::new(CompleteBody::new(inner))))
+                        .in_current_span(),
+                );
+                future::Either::Right(Box::pin(async move {
+                    // Notes the disconnect if the client drops this future
+                    // before the response is received.
+                    let mut disconnected = Disconnected(true);
+                    let rsp = task.await?.map_err(Into::into);
+                    disconnected.0 = false;
+                    rsp
+                }))
+            }
+        }
+    }
+}
+
+/// Logs that a client disconnected while its request was being completed.
+struct Disconnected(bool);
+
+impl Drop for Disconnected {
+    fn drop(&mut self) {
+        if self.0 {
+            debug!("Client disconnected; completing the request");
+        }
+    }
+}
+
+// === impl CompleteBody ===
+
+impl CompleteBody {
+    fn new(inner: BoxBody) -> Self {
+        Self {
+            inner,
+            complete: false,
+        }
+    }
+}
+
+impl Body for CompleteBody {
+    type Data = <BoxBody as Body>::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = futures::ready!(Pin::new(&mut self.inner).poll_data(cx));
+        if data.is_none() {
+            // HTTP/1 servers do not read trailers.
+            self.complete = true;
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap<HeaderValue>>, Self::Error>> {
+        let trailers = futures::ready!(Pin::new(&mut self.inner).poll_trailers(cx));
+        self.complete = true;
+        Poll::Ready(trailers)
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CompleteBody {
+    fn drop(&mut self) {
+        if self.complete || self.inner.is_end_stream() {
+            return;
+        }
+
+        debug!("Client disconnected; completing the response body");
+        let mut inner = std::mem::take(&mut self.inner);
+        tokio::spawn(
+            async move {
+                while let Some(data) = inner.data().await {
+                    if let Err(error) = data {
+                        debug!(%error, "Response body failed");
+                        return;
+                    }
+                }
+                if let Err(error) = inner.trailers().await {
+                    debug!(%error, "Response trailers failed");
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+    use tower::{Service, ServiceExt};
+
+    /// Returns a service whose single request completes when `release` is
+    /// notified and which notifies `done` when the request completes.
+    fn upstream(
+        release: oneshot::Receiver<()>,
+        done: oneshot::Sender<()>,
+    ) -> impl tower::Service<(), Response = http::Response<BoxBody>, Error = Error, Future = impl Send>
+    {
+        let mut rsp = Some(async move {
+            let _ = release.await;
+            let _ = done.send(());
+            Ok::<_, Error>(http::Response::new(BoxBody::default()))
+        });
+        tower::service_fn(move |()| rsp.take().expect("called once"))
+    }
+
+    async fn disconnect(policy: ClientDisconnect) -> bool {
+        let (release_tx, release_rx) = oneshot::channel();
+        let (done_tx, done_rx) = oneshot::channel();
+        let mut svc = HandleDisconnect {
+            inner: upstream(release_rx, done_tx),
+            policy,
+        };
+
+        // The client disconnects before the upstream responds.
+        let rsp = svc.ready().await.unwrap().call(());
+        drop(rsp);
+
+        let _ = release_tx.send(());
+        done_rx.await.is_ok()
+    }
+
+    /// Returns true if the upstream's response body is read to completion
+    /// after the client disconnects while it is streamed.
+    async fn disconnect_during_body(policy: ClientDisconnect) -> bool {
+        let (mut tx, body) = hyper::Body::channel();
+        let mut body = Some(body);
+        let mut svc = HandleDisconnect {
+            inner: tower::service_fn(move |()| {
+                let body = body.take().expect("called once");
+                future::ok::<_, Error>(http::Response::new(BoxBody::new(body)))
+            }),
+            policy,
+        };
+
+        let rsp = svc.ready().await.unwrap().call(()).await.unwrap();
+        tx.send_data("hello".into()).await.unwrap();
+
+        // The client reads part of the body and then disconnects.
+        let mut body = rsp.into_body();
+        body.data().await.unwrap().unwrap();
+        drop(body);
+
+        tx.send_data(" world".into()).await.is_ok()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_upstream_requests() {
+        assert!(!disconnect(ClientDisconnect::Cancel).await);
+        assert!(!disconnect_during_body(ClientDisconnect::Cancel).await);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn completes_upstream_requests() {
+        assert!(disconnect(ClientDisconnect::Complete).await);
+        assert!(disconnect_during_body(ClientDisconnect::Complete).await);
+    }
+}
@@ -230,6 +230,16 @@ pub(crate) fn wants_upgrade<B>(req: &http::Request<B>) -> bool {
     req.method() == http::Method::CONNECT
 }
 
+/// Checks upgrade requests to determine if they want to upgrade to a WebSocket.
+pub(crate) fn is_websocket_upgrade<B>(req: &http::Request<B>) -> bool {
+    req.headers()
+        .get_all(UPGRADE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|proto| proto.trim().eq_ignore_ascii_case("websocket"))
+}
+
 /// Checks responses to determine if they are successful HTTP upgrades.
 pub(crate) fn is_upgrade<B>(res: &http::Response<B>) -> bool {
     // Upgrades were introduced in HTTP/1.1
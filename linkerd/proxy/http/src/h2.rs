@@ -16,11 +16,17 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::instrument::Instrument;
 use tracing::{debug, debug_span, trace_span};
 
+pub mod peer_settings;
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Settings {
     pub initial_stream_window_size: Option<u32>,
     pub initial_connection_window_size: Option<u32>,
     pub keepalive_timeout: Option<Duration>,
+
+    /// When set, servers validate the client's initial SETTINGS frame against
+    /// these bounds. Ignored by clients.
+    pub peer_settings: Option<peer_settings::Bounds>,
 }
 
 #[derive(Debug)]
@@ -83,6 +89,7 @@ where
             initial_connection_window_size,
             initial_stream_window_size,
             keepalive_timeout,
+            ..
         } = self.h2_settings;
 
         let connect = self
@@ -0,0 +1,382 @@
+//! Validates the SETTINGS frame that opens an HTTP/2 client connection.
+//!
+//! The client connection preface is read before the connection is handed to
+//! the HTTP/2 server. Settings that exceed our configured bounds but are still
+//! legal are clamped in place; connections that advertise illegal or absurd
+//! settings are refused with a GOAWAY frame.
+//!
+//! Only the initial SETTINGS frame is inspected. Settings changes sent later in
+//! the connection's lifetime are handled by the HTTP/2 implementation as usual.
+
+use bytes::{Buf, BufMut, BytesMut};
+use linkerd_error::Error;
+use linkerd_io::{self as io, AsyncReadExt, AsyncWriteExt, PrefixedIo};
+use std::fmt;
+use thiserror::Error;
+use tracing::{debug, trace};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const FRAME_HEADER_LEN: usize = 9;
+const SETTING_LEN: usize = 6;
+
+const FRAME_TYPE_SETTINGS: u8 = 0x4;
+const FRAME_TYPE_GOAWAY: u8 = 0x7;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+const MIN_MAX_FRAME_SIZE: u32 = 1 << 14;
+const MAX_MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+/// Bounds applied to the settings a client advertises when it opens a
+/// connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bounds {
+    /// Larger `SETTINGS_HEADER_TABLE_SIZE` values are clamped.
+    pub max_header_table_size: u32,
+
+    /// Larger (but legal) `SETTINGS_MAX_FRAME_SIZE` values are clamped.
+    pub max_frame_size: u32,
+
+    /// Larger `SETTINGS_MAX_HEADER_LIST_SIZE` values are clamped.
+    pub max_header_list_size: u32,
+
+    /// Connections advertising a smaller `SETTINGS_INITIAL_WINDOW_SIZE` are
+    /// refused.
+    pub min_initial_window_size: u32,
+
+    /// Connections whose initial SETTINGS frame holds more entries are
+    /// refused.
+    pub max_settings: usize,
+}
+
+#[derive(Clone, Debug, Error)]
+#[error("HTTP/2 client sent invalid SETTINGS: {0}")]
+pub struct SettingsRejected(Reason);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The connection did not start with a SETTINGS frame.
+    MissingSettings,
+
+    /// The SETTINGS frame had a malformed length or stream ID.
+    Malformed,
+
+    /// The SETTINGS frame held more entries than permitted.
+    TooManySettings(usize),
+
+    /// A setting had a value that is not permitted by RFC 7540.
+    Illegal { id: u16, value: u32 },
+
+    /// `SETTINGS_INITIAL_WINDOW_SIZE` was below the configured minimum.
+    WindowTooSmall(u32),
+}
+
+// === impl Bounds ===
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            max_header_table_size: 64 * 1024,
+            max_frame_size: 1024 * 1024,
+            max_header_list_size: 1024 * 1024,
+            min_initial_window_size: 0,
+            max_settings: 32,
+        }
+    }
+}
+
+impl Bounds {
+    /// Reads the client's connection preface and initial SETTINGS frame from
+    /// `io`, validating it against these bounds.
+    ///
+    /// On success, the returned I/O replays the (possibly clamped) preface
+    /// before reading from the underlying stream. When the settings are
+    /// rejected, a GOAWAY frame is sent to the client and an error is returned.
+    pub async fn guard<I>(&self, mut io: I) -> Result<PrefixedIo<I>, Error>
+    where
+        I: io::AsyncRead + io::AsyncWrite + Unpin,
+    {
+        let mut buf = BytesMut::with_capacity(PREFACE.len() + FRAME_HEADER_LEN);
+        let payload_len = {
+            if !read_to(&mut io, &mut buf, PREFACE.len() + FRAME_HEADER_LEN).await? {
+                // Let the server deal with connections that close early.
+                return Ok(PrefixedIo::new(buf.freeze(), io));
+            }
+            match self.check_header(&buf[PREFACE.len()..]) {
+                Ok(len) => len,
+                Err(reason) => return Err(reject(&mut io, reason).await.into()),
+            }
+        };
+
+        let len = PREFACE.len() + FRAME_HEADER_LEN + payload_len;
+        if !read_to(&mut io, &mut buf, len).await? {
+            return Ok(PrefixedIo::new(buf.freeze(), io));
+        }
+
+        let start = PREFACE.len() + FRAME_HEADER_LEN;
+        if let Err(reason) = self.clamp_settings(&mut buf[start..len]) {
+            return Err(reject(&mut io, reason).await.into());
+        }
+
+        trace!(
+            settings = payload_len / SETTING_LEN,
+            "Accepted client SETTINGS"
+        );
+        Ok(PrefixedIo::new(buf.freeze(), io))
+    }
+
+    /// Validates a SETTINGS frame header, returning the payload length.
+    fn check_header(&self, hdr: &[u8]) -> Result<usize, Reason> {
+        let mut hdr = hdr;
+        let len = ((hdr.get_u16() as usize) << 8) | hdr.get_u8() as usize;
+        let kind = hdr.get_u8();
+        let _flags = hdr.get_u8();
+        let stream_id = hdr.get_u32() & MAX_WINDOW_SIZE;
+
+        if kind != FRAME_TYPE_SETTINGS {
+            return Err(Reason::MissingSettings);
+        }
+        if stream_id != 0 || len % SETTING_LEN != 0 {
+            return Err(Reason::Malformed);
+        }
+        let n = len / SETTING_LEN;
+        if n > self.max_settings {
+            return Err(Reason::TooManySettings(n));
+        }
+        Ok(len)
+    }
+
+    /// Validates each setting in a SETTINGS payload, clamping legal values
+    /// that exceed the configured bounds.
+    fn clamp_settings(&self, payload: &mut [u8]) -> Result<(), Reason> {
+        for setting in payload.chunks_mut(SETTING_LEN) {
+            let (id, value) = {
+                let mut s = &*setting;
+                (s.get_u16(), s.get_u32())
+            };
+
+            let clamped = match id {
+                SETTINGS_HEADER_TABLE_SIZE => value.min(self.max_header_table_size),
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(Reason::Illegal { id, value });
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE if value > MAX_WINDOW_SIZE => {
+                    return Err(Reason::Illegal { id, value });
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE if value < self.min_initial_window_size => {
+                    return Err(Reason::WindowTooSmall(value));
+                }
+                SETTINGS_MAX_FRAME_SIZE
+                    if !(MIN_MAX_FRAME_SIZE..=MAX_MAX_FRAME_SIZE).contains(&value) =>
+                {
+                    return Err(Reason::Illegal { id, value });
+                }
+                SETTINGS_MAX_FRAME_SIZE => value.min(self.max_frame_size.max(MIN_MAX_FRAME_SIZE)),
+                SETTINGS_MAX_HEADER_LIST_SIZE => value.min(self.max_header_list_size),
+                _ => value,
+            };
+
+            if clamped != value {
+                debug!(id, value, clamped, "Clamping client SETTINGS");
+                (&mut setting[2..]).put_u32(clamped);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads from `io` until `buf` holds at least `len` bytes. Returns false if the
+/// stream ended first.
+async fn read_to<I: io::AsyncRead + Unpin>(
+    io: &mut I,
+    buf: &mut BytesMut,
+    len: usize,
+) -> io::Result<bool> {
+    while buf.len() < len {
+        buf.reserve(len - buf.len());
+        if io.read_buf(buf).await? == 0 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Sends a GOAWAY frame to the client, returning an error describing why the
+/// connection was refused.
+async fn reject<I: io::AsyncWrite + Unpin>(io: &mut I, reason: Reason) -> SettingsRejected {
+    debug!(%reason, "Refusing HTTP/2 connection");
+    let mut frame = BytesMut::with_capacity(FRAME_HEADER_LEN + 8);
+    // Length (24 bits), type, flags, and stream ID.
+    frame.put_uint(8, 3);
+    frame.put_u8(FRAME_TYPE_GOAWAY);
+    frame.put_u8(0);
+    frame.put_u32(0);
+    // Last stream ID and error code.
+    frame.put_u32(0);
+    frame.put_u32(reason.h2_reason().into());
+    if let Err(error) = io.write_all(&frame).await {
+        debug!(%error, "Failed to send GOAWAY");
+    }
+    let _ = io.shutdown().await;
+
+    SettingsRejected(reason)
+}
+
+// === impl SettingsRejected ===
+
+impl SettingsRejected {
+    pub fn reason(&self) -> Reason {
+        self.0
+    }
+}
+
+// === impl Reason ===
+
+impl Reason {
+    fn h2_reason(&self) -> ::h2::Reason {
+        match self {
+            Self::TooManySettings(_) | Self::WindowTooSmall(_) => ::h2::Reason::ENHANCE_YOUR_CALM,
+            Self::Illegal {
+                id: SETTINGS_INITIAL_WINDOW_SIZE,
+                ..
+            } => ::h2::Reason::FLOW_CONTROL_ERROR,
+            Self::MissingSettings | Self::Malformed | Self::Illegal { .. } => {
+                ::h2::Reason::PROTOCOL_ERROR
+            }
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSettings => write!(f, "connection preface must start with SETTINGS"),
+            Self::Malformed => write!(f, "malformed SETTINGS frame"),
+            Self::TooManySettings(n) => write!(f, "too many settings ({})", n),
+            Self::Illegal { id, value } => {
+                write!(f, "illegal value {} for setting {:#x}", value, id)
+            }
+            Self::WindowTooSmall(sz) => write!(f, "initial window size too small ({})", sz),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::io::Builder;
+
+    fn settings(settings: &[(u16, u32)]) -> Vec<u8> {
+        let mut buf = BytesMut::from(PREFACE);
+        buf.put_uint((settings.len() * SETTING_LEN) as u64, 3);
+        buf.put_u8(FRAME_TYPE_SETTINGS);
+        buf.put_u8(0);
+        buf.put_u32(0);
+        for (id, value) in settings {
+            buf.put_u16(*id);
+            buf.put_u32(*value);
+        }
+        buf.to_vec()
+    }
+
+    fn goaway(reason: ::h2::Reason) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        buf.put_uint(8, 3);
+        buf.put_u8(FRAME_TYPE_GOAWAY);
+        buf.put_u8(0);
+        buf.put_u32(0);
+        buf.put_u32(0);
+        buf.put_u32(reason.into());
+        buf.to_vec()
+    }
+
+    async fn read_all<I: io::AsyncRead + Unpin>(mut io: I, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        io.read_exact(&mut buf).await.expect("read must succeed");
+        buf
+    }
+
+    #[tokio::test]
+    async fn accepts_settings_within_bounds() {
+        let frame = settings(&[
+            (SETTINGS_HEADER_TABLE_SIZE, 4096),
+            (SETTINGS_INITIAL_WINDOW_SIZE, 65_535),
+        ]);
+        let io = Builder::new().read(&frame).build();
+        let io = Bounds::default().guard(io).await.expect("must accept");
+        assert_eq!(read_all(io, frame.len()).await, frame);
+    }
+
+    #[tokio::test]
+    async fn clamps_large_settings() {
+        let bounds = Bounds::default();
+        let io = Builder::new()
+            .read(&settings(&[
+                (SETTINGS_HEADER_TABLE_SIZE, u32::MAX),
+                (SETTINGS_MAX_FRAME_SIZE, MAX_MAX_FRAME_SIZE),
+            ]))
+            .build();
+        let io = bounds.guard(io).await.expect("must accept");
+        let expected = settings(&[
+            (SETTINGS_HEADER_TABLE_SIZE, bounds.max_header_table_size),
+            (SETTINGS_MAX_FRAME_SIZE, bounds.max_frame_size),
+        ]);
+        assert_eq!(read_all(io, expected.len()).await, expected);
+    }
+
+    #[tokio::test]
+    async fn rejects_illegal_window_size() {
+        let io = Builder::new()
+            .read(&settings(&[(SETTINGS_INITIAL_WINDOW_SIZE, u32::MAX)]))
+            .write(&goaway(::h2::Reason::FLOW_CONTROL_ERROR))
+            .build();
+        let err = Bounds::default().guard(io).await.expect_err("must reject");
+        let rejected = err
+            .downcast_ref::<SettingsRejected>()
+            .expect("must be a SETTINGS error");
+        assert_eq!(
+            rejected.reason(),
+            Reason::Illegal {
+                id: SETTINGS_INITIAL_WINDOW_SIZE,
+                value: u32::MAX
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_small_window_size() {
+        let bounds = Bounds {
+            min_initial_window_size: 1024,
+            ..Bounds::default()
+        };
+        let io = Builder::new()
+            .read(&settings(&[(SETTINGS_INITIAL_WINDOW_SIZE, 1)]))
+            .write(&goaway(::h2::Reason::ENHANCE_YOUR_CALM))
+            .build();
+        bounds.guard(io).await.expect_err("must reject");
+    }
+
+    #[tokio::test]
+    async fn rejects_too_many_settings() {
+        let bounds = Bounds {
+            max_settings: 1,
+            ..Bounds::default()
+        };
+        let io = Builder::new()
+            .read(
+                &settings(&[
+                    (SETTINGS_HEADER_TABLE_SIZE, 4096),
+                    (SETTINGS_HEADER_TABLE_SIZE, 4096),
+                ])[..PREFACE.len() + FRAME_HEADER_LEN],
+            )
+            .write(&goaway(::h2::Reason::ENHANCE_YOUR_CALM))
+            .build();
+        bounds.guard(io).await.expect_err("must reject");
+    }
+}
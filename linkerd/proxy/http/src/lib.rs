@@ -7,7 +7,9 @@ use linkerd_error::Error;
 pub mod balance;
 pub mod client;
 pub mod client_handle;
+mod close_delimited;
 pub mod detect;
+mod disconnect;
 mod glue;
 pub mod h1;
 pub mod h2;
@@ -16,6 +18,7 @@ pub mod insert;
 pub mod normalize_uri;
 pub mod orig_proto;
 mod override_authority;
+mod read_ahead;
 mod retain;
 mod server;
 pub mod strip_header;
@@ -23,22 +26,29 @@ pub mod timeout;
 pub mod trace;
 pub mod upgrade;
 mod version;
+mod via;
 
 pub use self::{
     client_handle::{ClientHandle, SetClientHandle},
+    close_delimited::{
+        CloseDelimited, CloseDelimitedBody, CloseDelimitedResponse, HandleCloseDelimited,
+    },
     detect::DetectHttp,
+    disconnect::{ClientDisconnect, HandleDisconnect},
     glue::{HyperServerSvc, UpgradeBody},
     header_from_target::NewHeaderFromTarget,
     normalize_uri::{MarkAbsoluteForm, NewNormalizeUri},
     override_authority::{AuthorityOverride, NewOverrideAuthority},
+    read_ahead::{ReadAhead, ReadAheadBody},
     retain::Retain,
     server::NewServeHttp,
-    timeout::MakeTimeoutLayer,
+    timeout::{MakeTimeoutLayer, NewTimeoutRequestBody, RequestBodyTimedOut, RequestBodyTimeout},
     version::Version,
+    via::{AddVia, InvalidPseudonym, Via},
 };
 pub use http::{
     header::{self, HeaderName, HeaderValue},
-    uri, Request, Response, StatusCode,
+    uri, Method, Request, Response, StatusCode,
 };
 pub use hyper::body::HttpBody;
 pub use linkerd_http_box::{BoxBody, BoxRequest, BoxResponse};
@@ -0,0 +1,425 @@
+use bytes::Bytes;
+use futures::ready;
+use http_body::Body;
+use linkerd_error::Error;
+use linkerd_stack::layer;
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::sync::{mpsc, Notify};
+
+/// Reads ahead of the client on streaming response bodies, buffering up to
+/// `max_bytes` of each body.
+///
+/// Response bodies are normally read from the upstream only as quickly as
+/// they are written to the client, so a slow client also slows the upstream.
+/// Reading ahead allows the upstream to continue sending (e.g. by releasing
+/// HTTP/2 flow control capacity) while the client catches up, at the cost of
+/// buffering. Each body is read by a background task, which stops reading
+/// once the body's buffer reaches its high-water mark until the client has
+/// consumed buffered data, so each body's buffer is bounded by `max_bytes`
+/// plus the size of a single frame.
+///
+/// When `max_bytes` is zero, bodies are forwarded without buffering.
+#[derive(Clone, Debug)]
+pub struct ReadAhead<S> {
+    inner: S,
+    max_bytes: usize,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ReadAheadFuture<F> {
+    #[pin]
+    inner: F,
+    max_bytes: usize,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ReadAheadBody<B> {
+    /// Set when the body is forwarded without buffering.
+    #[pin]
+    inner: Option<B>,
+    buffer: Option<Buffer>,
+}
+
+/// Receives the frames read ahead by a body's background task.
+#[derive(Debug)]
+struct Buffer {
+    rx: mpsc::UnboundedReceiver<Frame>,
+    buffered: Arc<Buffered>,
+    trailers: Option<Option<http::HeaderMap>>,
+    eos: bool,
+}
+
+/// Tracks the number of bytes that have been read ahead of the client.
+#[derive(Debug, Default)]
+struct Buffered {
+    bytes: AtomicUsize,
+    consumed: Notify,
+}
+
+#[derive(Debug)]
+enum Frame {
+    Data(Bytes),
+    Trailers(Option<http::HeaderMap>),
+    Error(Error),
+}
+
+// === impl ReadAhead ===
+
+impl<S> ReadAhead<S> {
+    pub fn layer(max_bytes: usize) -> impl layer::Layer<S, Service = Self> + Clone {
+        layer::mk(move |inner| Self { inner, max_bytes })
+    }
+}
+
+impl<S, A, B> tower::Service<http::Request<A>> for ReadAhead<S>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<Error>,
+{
+    type Response = http::Response<ReadAheadBody<B>>;
+    type Error = S::Error;
+    type Future = ReadAheadFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        ReadAheadFuture {
+            inner: self.inner.call(req),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+// === impl ReadAheadFuture ===
+
+impl<F, B, E> Future for ReadAheadFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<Error>,
+{
+    type Output = Result<http::Response<ReadAheadBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let rsp = ready!(this.inner.poll(cx))?;
+        let max_bytes = *this.max_bytes;
+        Poll::Ready(Ok(rsp.map(|inner| ReadAheadBody::new(inner, max_bytes))))
+    }
+}
+
+// === impl ReadAheadBody ===
+
+impl<B> ReadAheadBody<B>
+where
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<Error>,
+{
+    fn new(inner: B, max_bytes: usize) -> Self {
+        if max_bytes == 0 || inner.is_end_stream() {
+            return Self {
+                inner: Some(inner),
+                buffer: None,
+            };
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let buffered = Arc::new(Buffered::default());
+        tokio::spawn(read_ahead(inner, max_bytes, buffered.clone(), tx));
+        Self {
+            inner: None,
+            buffer: Some(Buffer {
+                rx,
+                buffered,
+                trailers: None,
+                eos: false,
+            }),
+        }
+    }
+}
+
+impl<B: Default> Default for ReadAheadBody<B> {
+    fn default() -> Self {
+        Self {
+            inner: Some(B::default()),
+            buffer: None,
+        }
+    }
+}
+
+impl<B> Body for ReadAheadBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        if let Some(inner) = this.inner.as_pin_mut() {
+            return inner.poll_data(cx).map_err(Into::into);
+        }
+
+        let buffer = this.buffer.as_mut().expect("body must be buffered");
+        if buffer.eos {
+            return Poll::Ready(None);
+        }
+        let frame = match ready!(buffer.rx.poll_recv(cx)) {
+            Some(frame) => frame,
+            None => {
+                buffer.eos = true;
+                return Poll::Ready(None);
+            }
+        };
+        match frame {
+            Frame::Data(data) => {
+                buffer.consume(&data);
+                Poll::Ready(Some(Ok(data)))
+            }
+            Frame::Error(error) => {
+                buffer.eos = true;
+                Poll::Ready(Some(Err(error)))
+            }
+            Frame::Trailers(trailers) => {
+                buffer.eos = true;
+                buffer.trailers = Some(trailers);
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = self.project();
+        if let Some(inner) = this.inner.as_pin_mut() {
+            return inner.poll_trailers(cx).map_err(Into::into);
+        }
+
+        let buffer = this.buffer.as_mut().expect("body must be buffered");
+        if let Some(trailers) = buffer.trailers.take() {
+            return Poll::Ready(Ok(trailers));
+        }
+        // Any data that the client didn't read is discarded.
+        while !buffer.eos {
+            match ready!(buffer.rx.poll_recv(cx)) {
+                Some(Frame::Data(data)) => buffer.consume(&data),
+                Some(Frame::Trailers(trailers)) => {
+                    buffer.eos = true;
+                    return Poll::Ready(Ok(trailers));
+                }
+                Some(Frame::Error(error)) => {
+                    buffer.eos = true;
+                    return Poll::Ready(Err(error));
+                }
+                None => buffer.eos = true,
+            }
+        }
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match (self.inner.as_ref(), self.buffer.as_ref()) {
+            (Some(inner), _) => inner.is_end_stream(),
+            (None, Some(buffer)) => buffer.eos && buffer.trailers.is_none(),
+            (None, None) => true,
+        }
+    }
+}
+
+// === impl Buffer ===
+
+impl Buffer {
+    /// Releases buffer capacity as data is forwarded to the client.
+    fn consume(&mut self, data: &Bytes) {
+        self.buffered.bytes.fetch_sub(data.len(), Ordering::AcqRel);
+        self.buffered.consumed.notify_one();
+    }
+}
+
+/// Reads `body` into the buffer until the body completes or the client drops
+/// the buffer, pausing whenever `max_bytes` are buffered.
+async fn read_ahead<B>(
+    body: B,
+    max_bytes: usize,
+    buffered: Arc<Buffered>,
+    tx: mpsc::UnboundedSender<Frame>,
+) where
+    B: Body<Data = Bytes>,
+    B::Error: Into<Error>,
+{
+    tokio::pin!(body);
+    loop {
+        while buffered.bytes.load(Ordering::Acquire) >= max_bytes {
+            tokio::select! {
+                _ = buffered.consumed.notified() => {}
+                _ = tx.closed() => return,
+            }
+        }
+
+        let data = tokio::select! {
+            data = body.data() => data,
+            _ = tx.closed() => return,
+        };
+        let frame = match data {
+            Some(Ok(data)) => {
+                buffered.bytes.fetch_add(data.len(), Ordering::AcqRel);
+                Frame::Data(data)
+            }
+            Some(Err(error)) => Frame::Error(error.into()),
+            None => break,
+        };
+        let is_error = matches!(frame, Frame::Error(_));
+        if tx.send(frame).is_err() || is_error {
+            return;
+        }
+    }
+
+    let frame = tokio::select! {
+        trailers = body.trailers() => match trailers {
+            Ok(trailers) => Frame::Trailers(trailers),
+            Err(error) => Frame::Error(error.into()),
+        },
+        _ = tx.closed() => return,
+    };
+    let _ = tx.send(frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A body that always has another chunk of `chunk_size` bytes ready, and
+    /// that counts the bytes that have been read from it.
+    struct Upstream {
+        chunk_size: usize,
+        chunks: usize,
+        read: Arc<AtomicUsize>,
+    }
+
+    impl Body for Upstream {
+        type Data = Bytes;
+        type Error = Error;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Error>>> {
+            if self.chunks == 0 {
+                return Poll::Ready(None);
+            }
+            self.chunks -= 1;
+            self.read.fetch_add(self.chunk_size, Ordering::SeqCst);
+            Poll::Ready(Some(Ok(Bytes::from(vec![0; self.chunk_size]))))
+        }
+
+        fn poll_trailers(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<http::HeaderMap>, Error>> {
+            Poll::Ready(Ok(None))
+        }
+    }
+
+    fn upstream(chunk_size: usize, chunks: usize) -> (Upstream, Arc<AtomicUsize>) {
+        let read = Arc::new(AtomicUsize::new(0));
+        let body = Upstream {
+            chunk_size,
+            chunks,
+            read: read.clone(),
+        };
+        (body, read)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn reads_ahead_of_idle_clients() {
+        let (body, read) = upstream(1024, 1000);
+        let body = ReadAheadBody::new(body, 16 * 1024);
+
+        // The upstream is read before the client polls the body, up to the
+        // high-water mark.
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while read.load(Ordering::SeqCst) < 16 * 1024 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the upstream must be read ahead of the client");
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(read.load(Ordering::SeqCst), 16 * 1024);
+
+        // Once the client is gone, the upstream is no longer read.
+        drop(body);
+        tokio::task::yield_now().await;
+        assert_eq!(read.load(Ordering::SeqCst), 16 * 1024);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn bounds_buffered_bytes_for_slow_clients() {
+        let (body, read) = upstream(1024, 1000);
+        let mut body = ReadAheadBody::new(body, 16 * 1024);
+
+        // The client reads a single chunk at a time, but the upstream is never
+        // read more than the high-water mark (plus a chunk) ahead of it.
+        let mut written = 0;
+        while let Some(data) = body.data().await {
+            written += data.unwrap().len();
+            let buffered = read.load(Ordering::SeqCst) - written;
+            assert!(buffered <= 16 * 1024, "buffered {} bytes", buffered);
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(written, 1024 * 1000);
+        assert_eq!(body.trailers().await.unwrap(), None);
+        assert!(body.is_end_stream());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn disabled() {
+        let (body, read) = upstream(1024, 4);
+        let mut body = ReadAheadBody::new(body, 0);
+        let mut written = 0;
+        while let Some(data) = body.data().await {
+            written += data.unwrap().len();
+            assert_eq!(read.load(Ordering::SeqCst), written);
+        }
+        assert_eq!(written, 4096);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn forwards_buffered_data_before_errors() {
+        let chunks = vec![
+            Ok(Bytes::from_static(b"hello ")),
+            Ok(Bytes::from_static(b"world")),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ];
+        let inner = hyper::Body::wrap_stream(futures::stream::iter(chunks));
+        let mut body = ReadAheadBody::new(inner, 1024);
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello ");
+        assert_eq!(body.data().await.unwrap().unwrap(), "world");
+        assert!(body.data().await.unwrap().is_err());
+    }
+}
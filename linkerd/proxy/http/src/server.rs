@@ -2,7 +2,7 @@ use crate::{
     self as http,
     client_handle::SetClientHandle,
     glue::{HyperServerSvc, UpgradeBody},
-    h2::Settings as H2Settings,
+    h2::{peer_settings::Bounds as PeerSettingsBounds, Settings as H2Settings},
     trace, upgrade, Version,
 };
 use linkerd_error::Error;
@@ -12,6 +12,7 @@ use std::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use tower::Service;
 use tracing::debug;
@@ -22,6 +23,8 @@ type Server = hyper::server::conn::Http<trace::Executor>;
 pub struct NewServeHttp<N> {
     inner: N,
     server: Server,
+    peer_settings: Option<PeerSettingsBounds>,
+    websocket_idle_timeout: Option<Duration>,
     drain: drain::Watch,
 }
 
@@ -29,6 +32,8 @@ pub struct NewServeHttp<N> {
 pub struct ServeHttp<S> {
     version: Version,
     server: Server,
+    peer_settings: Option<PeerSettingsBounds>,
+    websocket_idle_timeout: Option<Duration>,
     inner: S,
     drain: drain::Watch,
 }
@@ -40,11 +45,27 @@ impl<N> NewServeHttp<N> {
         h2: H2Settings,
         drain: drain::Watch,
     ) -> impl layer::Layer<N, Service = Self> + Clone {
-        layer::mk(move |inner| Self::new(h2, inner, drain.clone()))
+        Self::layer_with_websocket_idle_timeout(h2, None, drain)
+    }
+
+    /// Like `layer`, but HTTP/1.1 connections that are upgraded to WebSockets
+    /// are closed once no data has been transferred on them for
+    /// `websocket_idle_timeout`.
+    pub fn layer_with_websocket_idle_timeout(
+        h2: H2Settings,
+        websocket_idle_timeout: Option<Duration>,
+        drain: drain::Watch,
+    ) -> impl layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self::new(h2, websocket_idle_timeout, inner, drain.clone()))
     }
 
     /// Creates a new `ServeHttp`.
-    fn new(h2: H2Settings, inner: N, drain: drain::Watch) -> Self {
+    fn new(
+        h2: H2Settings,
+        websocket_idle_timeout: Option<Duration>,
+        inner: N,
+        drain: drain::Watch,
+    ) -> Self {
         let mut server = hyper::server::conn::Http::new().with_executor(trace::Executor::new());
         server
             .http2_initial_stream_window_size(h2.initial_stream_window_size)
@@ -63,6 +84,8 @@ impl<N> NewServeHttp<N> {
         Self {
             inner,
             server,
+            peer_settings: h2.peer_settings,
+            websocket_idle_timeout,
             drain,
         }
     }
@@ -83,6 +106,8 @@ where
             inner,
             version,
             server: self.server.clone(),
+            peer_settings: self.peer_settings,
+            websocket_idle_timeout: self.websocket_idle_timeout,
             drain: self.drain.clone(),
         }
     }
@@ -114,6 +139,8 @@ where
             inner,
             drain,
             mut server,
+            peer_settings,
+            websocket_idle_timeout,
         } = self.clone();
         debug!(?version, "Handling as HTTP");
 
@@ -125,7 +152,14 @@ where
                     // Enable support for HTTP upgrades (CONNECT and websockets).
                     let mut conn = server
                         .http1_only(true)
-                        .serve_connection(io, upgrade::Service::new(svc, drain.clone()))
+                        .serve_connection(
+                            io,
+                            upgrade::Service::with_websocket_idle_timeout(
+                                svc,
+                                drain.clone(),
+                                websocket_idle_timeout,
+                            ),
+                        )
                         .with_upgrades();
                     tokio::select! {
                         res = &mut conn => {
@@ -145,6 +179,12 @@ where
                     }
                 }
                 Version::H2 => {
+                    // Refuse clients that open the connection with
+                    // pathological settings before hyper sees them.
+                    let io = match peer_settings {
+                        Some(bounds) => bounds.guard(io).await?,
+                        None => io::PrefixedIo::from(io),
+                    };
                     let mut conn = server
                         .http2_only(true)
                         .serve_connection(io, HyperServerSvc::new(svc));
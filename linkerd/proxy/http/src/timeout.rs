@@ -1,17 +1,79 @@
-use futures::{ready, TryFuture};
-use linkerd_stack::NewService;
+use futures::{future, ready, TryFuture, TryFutureExt};
+use linkerd_error::Error;
+use linkerd_http_box::BoxBody;
+use linkerd_stack::{layer, NewService, Proxy};
 use linkerd_timeout::Timeout;
 use pin_project::pin_project;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use thiserror::Error;
+use tokio::{sync::oneshot, time};
+use tracing::debug;
 
 /// Implement on targets to determine if a service has a timeout.
 pub trait HasTimeout {
     fn timeout(&self) -> Option<Duration>;
 }
 
+/// Determines how long clients may take to send request bodies.
+///
+/// When a request's body is not received in time, the request is canceled,
+/// so that the upstream stream is reset or the upstream connection is closed,
+/// and it fails with a 408 Request Timeout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RequestBodyTimeout {
+    /// Request bodies may take as long as their response.
+    Disabled,
+
+    /// A request's body must be received before its route's timeout elapses,
+    /// so that stalled and slow uploads alike fail. Routes without a timeout
+    /// are not limited.
+    Total,
+
+    /// A request fails when no data is received on its body for this long,
+    /// so that uploads that make progress may continue for as long as their
+    /// route's timeout permits.
+    Idle(Duration),
+}
+
+/// Applies a `RequestBodyTimeout` to the requests on each route.
+#[derive(Clone, Debug)]
+pub struct NewTimeoutRequestBody<N> {
+    inner: N,
+    policy: RequestBodyTimeout,
+}
+
+#[derive(Clone, Debug)]
+pub struct TimeoutRequestBody<P> {
+    inner: P,
+    policy: RequestBodyTimeout,
+    timeout: Option<Duration>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct TimeoutRequestBodyFuture<F> {
+    #[pin]
+    inner: F,
+    timed_out: Option<oneshot::Receiver<RequestBodyTimedOut>>,
+}
+
+#[derive(Debug)]
+struct TimeoutBody {
+    inner: BoxBody,
+    timeout: Duration,
+    idle: bool,
+    sleep: Pin<Box<time::Sleep>>,
+    timed_out: Option<oneshot::Sender<RequestBodyTimedOut>>,
+}
+
+/// An error indicating that a request's body was not received in time.
+#[derive(Copy, Clone, Debug, Error)]
+#[error("request body timed out after {0:?}")]
+pub struct RequestBodyTimedOut(Duration);
+
 /// An HTTP-specific optional timeout layer.
 ///
 /// The stack target must implement `HasTimeout`, and if a duration is
@@ -93,3 +155,244 @@ impl<F: TryFuture> Future for MakeFuture<F> {
         Poll::Ready(Ok(svc))
     }
 }
+
+// === impl RequestBodyTimeout ===
+
+impl Default for RequestBodyTimeout {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+// === impl NewTimeoutRequestBody ===
+
+impl<N> NewTimeoutRequestBody<N> {
+    pub fn layer(policy: RequestBodyTimeout) -> impl layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self { inner, policy })
+    }
+}
+
+impl<T, N> NewService<T> for NewTimeoutRequestBody<N>
+where
+    N: NewService<T>,
+    T: HasTimeout,
+{
+    type Service = TimeoutRequestBody<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let timeout = match self.policy {
+            RequestBodyTimeout::Disabled => None,
+            RequestBodyTimeout::Total => target.timeout(),
+            RequestBodyTimeout::Idle(idle) => Some(idle),
+        };
+        TimeoutRequestBody {
+            inner: self.inner.new_service(target),
+            policy: self.policy,
+            timeout,
+        }
+    }
+}
+
+// === impl TimeoutRequestBody ===
+
+impl<P, S> Proxy<http::Request<BoxBody>, S> for TimeoutRequestBody<P>
+where
+    P: Proxy<http::Request<BoxBody>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future =
+        future::Either<future::ErrInto<P::Future, Error>, TimeoutRequestBodyFuture<P::Future>>;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<BoxBody>) -> Self::Future {
+        use http_body::Body;
+
+        let timeout = match self.timeout {
+            Some(timeout) if !req.body().is_end_stream() => timeout,
+            _ => return future::Either::Left(self.inner.proxy(svc, req).err_into::<Error>()),
+        };
+
+        let (tx, rx) = oneshot::channel();
+        let idle = matches!(self.policy, RequestBodyTimeout::Idle(_));
+        let req = req.map(|inner| {
+            BoxBody::new(TimeoutBody {
+                inner,
+                timeout,
+                idle,
+                sleep: Box::pin(time::sleep(timeout)),
+                timed_out: Some(tx),
+            })
+        });
+        future::Either::Right(TimeoutRequestBodyFuture {
+            inner: self.inner.proxy(svc, req),
+            timed_out: Some(rx),
+        })
+    }
+}
+
+impl<F: TryFuture> Future for TimeoutRequestBodyFuture<F>
+where
+    F::Error: Into<Error>,
+{
+    type Output = Result<F::Ok, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Some(rx) = this.timed_out.as_mut() {
+            match Pin::new(rx).poll(cx) {
+                // The body timed out, so the request is canceled.
+                Poll::Ready(Ok(error)) => return Poll::Ready(Err(error.into())),
+                // The body completed.
+                Poll::Ready(Err(_)) => *this.timed_out = None,
+                Poll::Pending => {}
+            }
+        }
+        match ready!(this.inner.try_poll(cx)) {
+            Ok(rsp) => Poll::Ready(Ok(rsp)),
+            Err(error) => {
+                // If the request failed because its body timed out, the
+                // timeout is reported rather than the upstream error.
+                if let Some(Ok(timed_out)) = this.timed_out.as_mut().map(|rx| rx.try_recv()) {
+                    return Poll::Ready(Err(timed_out.into()));
+                }
+                Poll::Ready(Err(error.into()))
+            }
+        }
+    }
+}
+
+// === impl TimeoutBody ===
+
+impl TimeoutBody {
+    fn poll_timeout(&mut self, cx: &mut Context<'_>) -> Poll<Error> {
+        ready!(self.sleep.as_mut().poll(cx));
+        debug!(timeout = ?self.timeout, idle = self.idle, "Request body timed out");
+        let error = RequestBodyTimedOut(self.timeout);
+        if let Some(tx) = self.timed_out.take() {
+            let _ = tx.send(error);
+        }
+        Poll::Ready(error.into())
+    }
+}
+
+impl http_body::Body for TimeoutBody {
+    type Data = <BoxBody as http_body::Body>::Data;
+    type Error = Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_data(cx) {
+            Poll::Ready(Some(Ok(data))) => {
+                if this.idle {
+                    let deadline = time::Instant::now() + this.timeout;
+                    this.sleep.as_mut().reset(deadline);
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            Poll::Ready(res) => {
+                if res.is_none() && this.inner.is_end_stream() {
+                    this.timed_out = None;
+                }
+                Poll::Ready(res)
+            }
+            Poll::Pending => this.poll_timeout(cx).map(|e| Some(Err(e))),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_trailers(cx) {
+            Poll::Ready(res) => {
+                this.timed_out = None;
+                Poll::Ready(res)
+            }
+            Poll::Pending => this.poll_timeout(cx).map(Err),
+        }
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use linkerd_stack::layer::Layer;
+
+    /// Sends a request with a body whose chunks are sent `interval` apart,
+    /// returning whether the whole body was received.
+    async fn upload(policy: RequestBodyTimeout, chunks: usize, interval: Duration) -> bool {
+        let route_timeout = Duration::from_secs(10);
+        let proxy = NewTimeoutRequestBody::layer(policy)
+            .layer(|_: Duration| ())
+            .new_service(route_timeout);
+
+        let (mut tx, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..chunks {
+                time::sleep(interval).await;
+                if tx.send_data(Bytes::from_static(b"data")).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut svc = tower::service_fn(|req: http::Request<BoxBody>| async move {
+            hyper::body::to_bytes(req.into_body()).await?;
+            Ok::<_, Error>(())
+        });
+        let req = http::Request::new(BoxBody::new(body));
+        match proxy.proxy(&mut svc, req).await {
+            Ok(()) => true,
+            Err(error) => {
+                assert!(error.is::<RequestBodyTimedOut>(), "{}", error);
+                false
+            }
+        }
+    }
+
+    impl HasTimeout for Duration {
+        fn timeout(&self) -> Option<Duration> {
+            Some(*self)
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn times_out_slow_uploads() {
+        let second = Duration::from_secs(1);
+        let idle = RequestBodyTimeout::Idle(Duration::from_secs(2));
+
+        assert!(
+            !upload(RequestBodyTimeout::Total, 20, second).await,
+            "uploads must complete within the route timeout"
+        );
+        assert!(upload(RequestBodyTimeout::Total, 5, second).await);
+
+        assert!(
+            upload(idle, 20, second).await,
+            "uploads that make progress must not time out"
+        );
+        assert!(
+            !upload(idle, 1, Duration::from_secs(5)).await,
+            "stalled uploads must time out"
+        );
+
+        assert!(upload(RequestBodyTimeout::Disabled, 20, second).await);
+    }
+}
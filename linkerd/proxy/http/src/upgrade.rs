@@ -7,10 +7,17 @@ use futures::{
 };
 use hyper::upgrade::OnUpgrade;
 use linkerd_duplex::Duplex;
+use linkerd_errno::Errno;
+use linkerd_io::{self as io, Sensor, SensorIo};
 use std::fmt;
 use std::mem;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time;
 use tracing::instrument::Instrument;
 use tracing::{debug, info, trace};
 use try_lock::TryLock;
@@ -47,6 +54,8 @@ struct Inner {
     server: TryLock<Option<OnUpgrade>>,
     client: TryLock<Option<OnUpgrade>>,
     upgrade_drain_signal: Option<drain::Watch>,
+    /// Closes the upgraded connection after it has been idle for this long.
+    idle_timeout: Option<Duration>,
 }
 
 #[derive(Debug)]
@@ -60,6 +69,20 @@ pub struct Service<S> {
     service: S,
     /// Watch any spawned HTTP/1.1 upgrade tasks.
     upgrade_drain_signal: drain::Watch,
+    /// Closes WebSocket connections that have been idle for this long.
+    websocket_idle_timeout: Option<Duration>,
+}
+
+/// Records the time at which an upgraded connection last read or wrote data.
+#[derive(Clone, Debug)]
+struct Activity(Arc<ActivityInner>);
+
+#[derive(Debug)]
+struct ActivityInner {
+    start: time::Instant,
+    /// The number of milliseconds after `start` at which data was last
+    /// transferred.
+    last_ms: AtomicU64,
 }
 
 // ===== impl Http11Upgrade =====
@@ -70,10 +93,20 @@ impl Http11Upgrade {
     /// Each handle is used to insert 1 half of the upgrade. When both handles
     /// have inserted, the upgrade future will be spawned onto the executor.
     pub fn halves(upgrade_drain_signal: drain::Watch) -> Http11UpgradeHalves {
+        Self::halves_with_idle_timeout(upgrade_drain_signal, None)
+    }
+
+    /// Returns a pair of upgrade handles whose upgraded connection is closed
+    /// once no data has been transferred on it for `idle_timeout`.
+    pub fn halves_with_idle_timeout(
+        upgrade_drain_signal: drain::Watch,
+        idle_timeout: Option<Duration>,
+    ) -> Http11UpgradeHalves {
         let inner = Arc::new(Inner {
             server: TryLock::new(None),
             client: TryLock::new(None),
             upgrade_drain_signal: Some(upgrade_drain_signal),
+            idle_timeout,
         });
 
         Http11UpgradeHalves {
@@ -135,10 +168,15 @@ impl Drop for Inner {
 
             let client_upgrade = client.map_err(|e| debug!("client HTTP upgrade error: {}", e));
 
+            let idle_timeout = self.idle_timeout;
             let both_upgrades = async move {
                 let (server_conn, client_conn) = tokio::try_join!(server_upgrade, client_upgrade)?;
                 trace!("HTTP upgrade successful");
-                if let Err(e) = Duplex::new(client_conn, server_conn).await {
+                let res = match idle_timeout {
+                    Some(timeout) => duplex_until_idle(client_conn, server_conn, timeout).await,
+                    None => Duplex::new(client_conn, server_conn).await,
+                };
+                if let Err(e) = res {
                     info!("tcp duplex error: {}", e)
                 }
                 Ok::<(), ()>(())
@@ -160,12 +198,91 @@ impl Drop for Inner {
     }
 }
 
+/// Proxies data between the two upgraded connections until either side
+/// closes or until no data has been transferred for `timeout`.
+///
+/// WebSocket ping and pong frames are transferred like any other data, so
+/// they keep the connection alive.
+async fn duplex_until_idle<C, S>(
+    client_conn: C,
+    server_conn: S,
+    timeout: Duration,
+) -> io::Result<()>
+where
+    C: io::AsyncRead + io::AsyncWrite + Unpin,
+    S: io::AsyncRead + io::AsyncWrite + Unpin,
+{
+    let activity = Activity::new();
+    let duplex = Duplex::new(client_conn, SensorIo::new(server_conn, activity.clone()));
+    tokio::pin!(duplex);
+    loop {
+        tokio::select! {
+            res = &mut duplex => return res,
+            () = time::sleep_until(activity.last() + timeout) => {
+                // Data may have been transferred since the timer was set.
+                if activity.last() + timeout <= time::Instant::now() {
+                    debug!(?timeout, "Closing idle upgraded connection");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// ===== impl Activity =====
+
+impl Activity {
+    fn new() -> Self {
+        Self(Arc::new(ActivityInner {
+            start: time::Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }))
+    }
+
+    fn last(&self) -> time::Instant {
+        let ms = self.0.last_ms.load(Ordering::Acquire);
+        self.0.start + Duration::from_millis(ms)
+    }
+
+    fn touch(&self, sz: usize) {
+        if sz > 0 {
+            let ms = self.0.start.elapsed().as_millis() as u64;
+            self.0.last_ms.fetch_max(ms, Ordering::AcqRel);
+        }
+    }
+}
+
+impl Sensor for Activity {
+    fn record_read(&mut self, sz: usize) {
+        self.touch(sz);
+    }
+
+    fn record_write(&mut self, sz: usize) {
+        self.touch(sz);
+    }
+
+    fn record_close(&mut self, _: Option<Errno>) {}
+
+    fn record_error<T>(&mut self, op: Poll<T>) -> Poll<T> {
+        op
+    }
+}
+
 // ===== impl Service =====
 impl<S> Service<S> {
     pub fn new(service: S, upgrade_drain_signal: drain::Watch) -> Self {
+        Self::with_websocket_idle_timeout(service, upgrade_drain_signal, None)
+    }
+
+    pub fn with_websocket_idle_timeout(
+        service: S,
+        upgrade_drain_signal: drain::Watch,
+        websocket_idle_timeout: Option<Duration>,
+    ) -> Self {
         Self {
             service,
             upgrade_drain_signal,
+            websocket_idle_timeout,
         }
     }
 }
@@ -204,7 +321,13 @@ where
             // cannot be removed.
 
             // Setup HTTP Upgrade machinery.
-            let halves = Http11Upgrade::halves(self.upgrade_drain_signal.clone());
+            let idle_timeout = self
+                .websocket_idle_timeout
+                .filter(|_| h1::is_websocket_upgrade(&req));
+            let halves = Http11Upgrade::halves_with_idle_timeout(
+                self.upgrade_drain_signal.clone(),
+                idle_timeout,
+            );
             req.extensions_mut().insert(halves.client);
             let on_upgrade = hyper::upgrade::on(&mut req);
 
@@ -219,3 +342,63 @@ where
         Either::Left(self.service.call(req))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::{AsyncReadExt, AsyncWriteExt};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn closes_idle_connections() {
+        let (client_conn, mut client) = io::duplex(64);
+        let (server_conn, mut server) = io::duplex(64);
+
+        let start = time::Instant::now();
+        time::timeout(
+            IDLE_TIMEOUT + Duration::from_secs(1),
+            duplex_until_idle(client_conn, server_conn, IDLE_TIMEOUT),
+        )
+        .await
+        .expect("idle connection must be closed")
+        .expect("duplex must not fail");
+        assert!(start.elapsed() >= IDLE_TIMEOUT);
+
+        // Both peers observe the connection closing.
+        assert_eq!(client.read(&mut [0; 1]).await.unwrap(), 0);
+        assert_eq!(server.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn keeps_active_connections() {
+        let (client_conn, mut client) = io::duplex(64);
+        let (server_conn, mut server) = io::duplex(64);
+        let duplex = duplex_until_idle(client_conn, server_conn, IDLE_TIMEOUT);
+        tokio::pin!(duplex);
+
+        // Exchange a message in each direction (e.g. a ping and a pong) well
+        // within the idle timeout, for longer than the idle timeout.
+        let mut buf = [0; 4];
+        for _ in 0..5 {
+            let exchange = async {
+                time::sleep(IDLE_TIMEOUT / 2).await;
+                client.write_all(b"ping").await.unwrap();
+                server.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"ping");
+                server.write_all(b"pong").await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"pong");
+            };
+            tokio::select! {
+                res = &mut duplex => panic!("active connection closed: {:?}", res),
+                () = exchange => {}
+            }
+        }
+
+        // Once the connection is no longer used, it is closed.
+        let last = time::Instant::now();
+        duplex.await.expect("duplex must not fail");
+        assert!(last.elapsed() >= IDLE_TIMEOUT);
+    }
+}
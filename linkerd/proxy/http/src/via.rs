@@ -0,0 +1,197 @@
+use futures::{prelude::*, ready};
+use http::header::{HeaderMap, HeaderValue, VIA};
+use linkerd_stack::layer;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+
+/// The pseudonym with which the proxy identifies itself in `Via` headers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Via(Arc<str>);
+
+#[derive(Debug, Error)]
+#[error("Via pseudonyms must be non-empty tokens")]
+pub struct InvalidPseudonym(());
+
+/// Appends an entry for the proxy to the `Via` headers of the requests and
+/// responses it forwards.
+///
+/// Entries are appended after any that were set by prior hops, as required by
+/// RFC 7230, so that the header lists each proxy in the order it handled the
+/// message.
+#[derive(Clone, Debug)]
+pub struct AddVia<S> {
+    inner: S,
+    via: Option<Via>,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct AddViaFuture<F> {
+    #[pin]
+    inner: F,
+    via: Option<Via>,
+}
+
+// === impl Via ===
+
+impl Via {
+    pub fn new(pseudonym: &str) -> Result<Self, InvalidPseudonym> {
+        if pseudonym.is_empty() || !pseudonym.bytes().all(is_tchar) {
+            return Err(InvalidPseudonym(()));
+        }
+        Ok(Self(pseudonym.into()))
+    }
+
+    /// Appends an entry for a message received with the given version.
+    /// Messages with versions that have no protocol name are left unchanged.
+    fn append(&self, version: http::Version, headers: &mut HeaderMap) {
+        let protocol = match version {
+            http::Version::HTTP_09 => "0.9",
+            http::Version::HTTP_10 => "1.0",
+            http::Version::HTTP_11 => "1.1",
+            http::Version::HTTP_2 => "2",
+            http::Version::HTTP_3 => "3",
+            _ => return,
+        };
+        let value = HeaderValue::from_str(&format!("{} {}", protocol, self.0))
+            .expect("pseudonym must be a valid header value");
+        headers.append(VIA, value);
+    }
+}
+
+impl FromStr for Via {
+    type Err = InvalidPseudonym;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+/// Token characters, as defined by RFC 7230.
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// === impl AddVia ===
+
+impl<S> AddVia<S> {
+    pub fn layer(via: Option<Via>) -> impl layer::Layer<S, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            inner,
+            via: via.clone(),
+        })
+    }
+}
+
+impl<S, A, B> tower::Service<http::Request<A>> for AddVia<S>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = AddViaFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        if let Some(via) = self.via.as_ref() {
+            via.append(req.version(), req.headers_mut());
+        }
+        AddViaFuture {
+            inner: self.inner.call(req),
+            via: self.via.clone(),
+        }
+    }
+}
+
+// === impl AddViaFuture ===
+
+impl<F, B, E> Future for AddViaFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+{
+    type Output = Result<http::Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut rsp = ready!(this.inner.poll(cx))?;
+        if let Some(via) = this.via.take() {
+            via.append(rsp.version(), rsp.headers_mut());
+        }
+        Poll::Ready(Ok(rsp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn values(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(VIA)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn appends_to_via_headers() {
+        let svc = tower::service_fn(|req: http::Request<()>| {
+            assert_eq!(values(req.headers()), vec!["1.0 gateway", "1.1 linkerd"]);
+            let rsp = http::Response::builder()
+                .version(http::Version::HTTP_2)
+                .header(VIA, "2 app")
+                .body(())
+                .unwrap();
+            future::ok::<_, std::convert::Infallible>(rsp)
+        });
+        let via = "linkerd".parse::<Via>().unwrap();
+        let req = http::Request::builder()
+            .version(http::Version::HTTP_11)
+            .header(VIA, "1.0 gateway")
+            .body(())
+            .unwrap();
+        let rsp = AddVia {
+            inner: svc,
+            via: Some(via),
+        }
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(values(rsp.headers()), vec!["2 app", "2 linkerd"]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn disabled() {
+        let svc = tower::service_fn(|req: http::Request<()>| {
+            assert!(req.headers().get(VIA).is_none());
+            future::ok::<_, std::convert::Infallible>(http::Response::new(()))
+        });
+        let rsp = AddVia {
+            inner: svc,
+            via: None,
+        }
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+        assert!(rsp.headers().get(VIA).is_none());
+    }
+
+    #[test]
+    fn pseudonyms_must_be_tokens() {
+        assert!(Via::new("linkerd-proxy_1.0").is_ok());
+        assert!(Via::new("").is_err());
+        assert!(Via::new("linkerd proxy").is_err());
+        assert!(Via::new("linkerd,proxy").is_err());
+    }
+}
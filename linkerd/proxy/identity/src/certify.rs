@@ -29,6 +29,10 @@ pub struct Config {
 /// Holds the process's local TLS identity state.
 ///
 /// Updates dynamically as certificates are provisioned from the Identity service.
+/// TLS clients and servers obtain the current configuration as each handshake
+/// begins, so a rotated certificate is used by new handshakes immediately,
+/// while handshakes that are in progress complete with the prior certificate
+/// and established connections are not disrupted.
 #[pin_project]
 #[derive(Clone, Debug)]
 pub struct LocalCrtKey {
@@ -193,6 +197,11 @@ impl LocalCrtKey {
         Ok(self)
     }
 
+    /// Returns true once a certificate has been provisioned.
+    pub fn is_certified(&self) -> bool {
+        self.crt_key.borrow().is_some()
+    }
+
     pub fn metrics(&self) -> crate::metrics::Report {
         crate::metrics::Report::new(self.crt_key.clone(), self.refreshes.clone())
     }
@@ -214,8 +223,12 @@ impl LocalCrtKey {
     }
 
     pub fn server_config(&self) -> tls::server::Config {
+        self.server_config_for(tls::server::ClientAuth::default())
+    }
+
+    pub fn server_config_for(&self, auth: tls::server::ClientAuth) -> tls::server::Config {
         if let Some(ref c) = *self.crt_key.borrow() {
-            return c.server_config();
+            return c.server_config_for(auth);
         }
 
         tls::server::empty_config()
@@ -175,7 +175,13 @@ impl api::tap_server::Tap for Server {
         };
 
         // Register the tap with the server's tap registry
-        self.registry.register(tap);
+        if let Err(e) = self.registry.register(tap) {
+            warn!(%e, "tap rejected");
+            return Err(grpc::Status::new(
+                grpc::Code::ResourceExhausted,
+                e.to_string(),
+            ));
+        }
 
         let rsp = ResponseStream {
             shared: Some(shared),
@@ -526,6 +532,12 @@ fn base_event<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> api::TapEve
         source: inspect.src_addr(req).map(|a| a.into()),
         source_meta: {
             let mut m = api::tap_event::EndpointMeta::default();
+            if let Some(port) = inspect.src_port(req) {
+                m.labels.insert("src_port".to_owned(), port.to_string());
+            }
+            if let Some(id) = inspect.request_id(req) {
+                m.labels.insert("request_id".to_owned(), id);
+            }
             match inspect.src_tls(req) {
                 Conditional::None(reason) => {
                     m.labels.insert("tls".to_owned(), reason.to_string());
@@ -559,6 +571,9 @@ fn base_event<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> api::TapEve
                         .insert("server_id".to_owned(), server_id.to_string());
                 }
             }
+            if let Some(selection) = inspect.dst_selection(req) {
+                m.labels.insert("selection".to_owned(), selection);
+            }
             m
         }),
         route_meta: inspect.route_labels(req).map(|labels| {
@@ -589,3 +604,178 @@ fn headers_to_pb(
             .collect(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{
+        observe_request::{self, r#match},
+        tap_server::Tap as _,
+    };
+    use linkerd_metrics::FmtMetrics;
+    use std::net::SocketAddr;
+
+    fn observe_all() -> grpc::Request<api::ObserveRequest> {
+        grpc::Request::new(api::ObserveRequest {
+            limit: 100,
+            r#match: Some(observe_request::Match {
+                r#match: Some(r#match::Match::All(r#match::Seq { matches: vec![] })),
+            }),
+            extract: None,
+        })
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_subscriptions_beyond_limit() {
+        let (registry, server) = crate::new(Some(2));
+
+        let first = server
+            .observe(observe_all())
+            .await
+            .expect("first subscription must be accepted");
+        let _second = server
+            .observe(observe_all())
+            .await
+            .expect("second subscription must be accepted");
+        let status = server
+            .observe(observe_all())
+            .await
+            .expect_err("third subscription must be rejected");
+        assert_eq!(status.code(), grpc::Code::ResourceExhausted);
+
+        // The slot of a subscription whose client has disconnected is
+        // reclaimed.
+        drop(first);
+        server
+            .observe(observe_all())
+            .await
+            .expect("subscription must be accepted once a slot is reclaimed");
+        let metrics = registry.as_display().to_string();
+        assert!(
+            metrics.contains("tap_subscriptions_active 2"),
+            "unexpected metrics: {}",
+            metrics
+        );
+    }
+
+    #[derive(Clone, Debug)]
+    struct RequestIdInspect;
+
+    impl Inspect for RequestIdInspect {
+        fn src_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+            None
+        }
+
+        fn src_tls<B>(&self, _: &http::Request<B>) -> tls::ConditionalServerTls {
+            Conditional::None(tls::NoServerTls::Disabled)
+        }
+
+        fn request_id<B>(&self, req: &http::Request<B>) -> Option<String> {
+            req.headers()
+                .get("x-request-id")
+                .and_then(|id| id.to_str().ok())
+                .map(|id| id.to_owned())
+        }
+
+        fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+            None
+        }
+
+        fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&crate::Labels> {
+            None
+        }
+
+        fn dst_tls<B>(&self, _: &http::Request<B>) -> tls::ConditionalClientTls {
+            Conditional::None(tls::NoClientTls::Disabled)
+        }
+
+        fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<crate::Labels>> {
+            None
+        }
+
+        fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn labels_events_with_request_id() {
+        let req = http::Request::builder()
+            .header("x-request-id", "abc123")
+            .body(())
+            .unwrap();
+        let event = base_event(&req, &RequestIdInspect);
+        let labels = event.source_meta.expect("source must be described").labels;
+        assert_eq!(labels.get("request_id").map(String::as_str), Some("abc123"));
+
+        let req = http::Request::builder().body(()).unwrap();
+        let event = base_event(&req, &RequestIdInspect);
+        let labels = event.source_meta.expect("source must be described").labels;
+        assert!(!labels.contains_key("request_id"));
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct SelectionInspect(crate::Labels);
+
+    impl Inspect for SelectionInspect {
+        fn src_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+            None
+        }
+
+        fn src_tls<B>(&self, _: &http::Request<B>) -> tls::ConditionalServerTls {
+            Conditional::None(tls::NoServerTls::Disabled)
+        }
+
+        fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+            Some(([10, 0, 0, 2], 8080).into())
+        }
+
+        fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&crate::Labels> {
+            Some(&self.0)
+        }
+
+        fn dst_tls<B>(&self, _: &http::Request<B>) -> tls::ConditionalClientTls {
+            Conditional::None(tls::NoClientTls::Disabled)
+        }
+
+        fn dst_selection<B>(&self, req: &http::Request<B>) -> Option<String> {
+            req.extensions()
+                .get::<&'static str>()
+                .map(|s| s.to_string())
+        }
+
+        fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<crate::Labels>> {
+            None
+        }
+
+        fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn labels_destinations_with_selection() {
+        let mut req = http::Request::builder().body(()).unwrap();
+        req.extensions_mut().insert("least-request");
+        let event = base_event(&req, &SelectionInspect::default());
+        let labels = event
+            .destination_meta
+            .expect("destination must be described")
+            .labels;
+        assert_eq!(
+            labels.get("selection").map(String::as_str),
+            Some("least-request")
+        );
+
+        let req = http::Request::builder().body(()).unwrap();
+        let event = base_event(&req, &SelectionInspect::default());
+        let labels = event
+            .destination_meta
+            .expect("destination must be described")
+            .labels;
+        assert!(
+            !labels.contains_key("selection"),
+            "destinations must only be labeled with recorded selections"
+        );
+    }
+}
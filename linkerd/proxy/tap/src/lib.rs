@@ -18,8 +18,12 @@ pub type Registry = registry::Registry<grpc::Tap>;
 // The number of events that may be buffered for a given response.
 const PER_RESPONSE_EVENT_BUFFER_CAPACITY: usize = 400;
 
-pub fn new() -> (Registry, grpc::Server) {
-    let registry = Registry::new();
+/// Creates a registry and a gRPC server that registers taps with it.
+///
+/// If `max_taps` is set, the server rejects tap requests while that many
+/// taps are active.
+pub fn new(max_taps: Option<usize>) -> (Registry, grpc::Server) {
+    let registry = Registry::with_max_taps(max_taps);
     let server = grpc::Server::new(registry.clone());
     (registry, server)
 }
@@ -33,14 +37,32 @@ pub type Labels = std::collections::BTreeMap<String, String>;
 pub trait Inspect {
     fn src_addr<B>(&self, req: &http::Request<B>) -> Option<net::SocketAddr>;
 
+    /// Returns the client's source port, if it should be exposed explicitly
+    /// on tap events.
+    fn src_port<B>(&self, _: &http::Request<B>) -> Option<u16> {
+        None
+    }
+
     fn src_tls<B>(&self, req: &http::Request<B>) -> tls::ConditionalServerTls;
 
+    /// Returns the request's ID, if one was assigned by the proxy or its
+    /// client.
+    fn request_id<B>(&self, _: &http::Request<B>) -> Option<String> {
+        None
+    }
+
     fn dst_addr<B>(&self, req: &http::Request<B>) -> Option<net::SocketAddr>;
 
     fn dst_labels<B>(&self, req: &http::Request<B>) -> Option<&Labels>;
 
     fn dst_tls<B>(&self, req: &http::Request<B>) -> tls::ConditionalClientTls;
 
+    /// Describes why the destination endpoint was selected for the request,
+    /// if the decision should be exposed on tap events.
+    fn dst_selection<B>(&self, _: &http::Request<B>) -> Option<String> {
+        None
+    }
+
     fn route_labels<B>(&self, req: &http::Request<B>) -> Option<Arc<Labels>>;
 
     fn is_outbound<B>(&self, req: &http::Request<B>) -> bool;
@@ -1,10 +1,18 @@
 use crate::iface;
 use futures::{Stream, StreamExt};
+use linkerd_metrics::{metrics, FmtMetric, FmtMetrics, Gauge};
 use parking_lot::Mutex;
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
+use thiserror::Error;
 use tokio::sync::watch;
 use tracing::trace;
 
+metrics! {
+    tap_subscriptions_active: Gauge {
+        "The number of tap subscriptions that are currently observing traffic."
+    }
+}
+
 #[derive(Debug)]
 pub struct Registry<T> {
     inner: Arc<Mutex<Inner<T>>>,
@@ -15,14 +23,30 @@ pub struct Registry<T> {
 struct Inner<T> {
     taps: Vec<T>,
     taps_send: watch::Sender<Vec<T>>,
+    max_taps: Option<usize>,
 }
 
+/// Indicates that a tap could not be registered because the maximum number of
+/// taps are already active.
+#[derive(Debug, Error)]
+#[error("too many active tap subscriptions (limit: {0})")]
+pub struct TooManyTaps(usize);
+
 impl<T> Default for Registry<T> {
     fn default() -> Self {
+        Self::with_max_taps(None)
+    }
+}
+
+impl<T> Registry<T> {
+    /// Creates a registry that rejects new taps while `max_taps` taps are
+    /// active, if a limit is set.
+    pub fn with_max_taps(max_taps: Option<usize>) -> Self {
         let (taps_send, taps_recv) = watch::channel(vec![]);
         let inner = Inner {
             taps: Vec::default(),
             taps_send,
+            max_taps,
         };
         Self {
             inner: Arc::new(Mutex::new(inner)),
@@ -43,10 +67,19 @@ where
         self.taps_recv.borrow().clone()
     }
 
-    pub fn register(&self, tap: T) {
+    pub fn register(&self, tap: T) -> Result<(), TooManyTaps> {
         let mut inner = self.inner.lock();
+        if let Some(max) = inner.max_taps {
+            // Taps whose clients have disconnected are reclaimed eagerly so
+            // that they do not count against the limit until the next clean.
+            inner.taps.retain(|tap| tap.can_tap_more());
+            if inner.taps.len() >= max {
+                return Err(TooManyTaps(max));
+            }
+        }
         inner.taps.push(tap);
         let _ = inner.taps_send.send(inner.taps.clone());
+        Ok(())
     }
 
     pub async fn clean(self, wakeup: impl Stream) {
@@ -60,6 +93,23 @@ where
     }
 }
 
+impl<T> FmtMetrics for Registry<T>
+where
+    T: iface::Tap,
+{
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let active = self
+            .inner
+            .lock()
+            .taps
+            .iter()
+            .filter(|tap| tap.can_tap_more())
+            .count();
+        tap_subscriptions_active.fmt_help(f)?;
+        Gauge::from(active as u64).fmt_metric(f, tap_subscriptions_active.name)
+    }
+}
+
 impl<T> Clone for Registry<T> {
     fn clone(&self) -> Self {
         Self {
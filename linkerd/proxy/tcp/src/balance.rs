@@ -1,4 +1,7 @@
 use linkerd_error::Error;
+pub use linkerd_proxy_core::balance::{
+    Algorithm, LatencyEwmaDiscover, PenalizeFailuresDiscover, RoundRobin,
+};
 use linkerd_stack::layer;
 use rand::thread_rng;
 use std::{hash::Hash, time::Duration};
@@ -6,7 +9,11 @@ pub use tower::{
     balance::p2c::Balance,
     load::{Load, PeakEwmaDiscover},
 };
-use tower::{discover::Discover, load::CompleteOnResponse};
+use tower::{
+    discover::Discover,
+    load::{CompleteOnResponse, Constant, PendingRequestsDiscover},
+    util::BoxService,
+};
 
 /// Produces a PeakEWMA balancer that uses connect latency (and pending
 /// connections) as its load metric.
@@ -26,3 +33,247 @@ where
         Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid")
     })
 }
+
+/// Produces a balancer that uses the given algorithm to select among
+/// endpoints. The PeakEWMA, least-request, and latency-EWMA algorithms consider
+/// each connection pending until it is established. Unless the round-robin
+/// algorithm is used, endpoints that fail to connect are deprioritized for
+/// `failure_penalty`.
+pub fn layer_with<T, D>(
+    algorithm: Algorithm,
+    default_rtt: Duration,
+    decay: Duration,
+    failure_penalty: Option<Duration>,
+) -> impl tower::layer::Layer<
+    D,
+    Service = BoxService<T, <D::Service as tower::Service<T>>::Response, Error>,
+> + Clone
+where
+    T: Send + 'static,
+    D: Discover + Unpin + Send + 'static,
+    D::Key: Hash + Clone + Send,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<T> + Send,
+    <D::Service as tower::Service<T>>::Error: Into<Error>,
+    <D::Service as tower::Service<T>>::Future: Send + 'static,
+{
+    layer::mk(move |discover: D| match algorithm {
+        Algorithm::PeakEwma => {
+            let loaded =
+                PeakEwmaDiscover::new(discover, default_rtt, decay, CompleteOnResponse::default());
+            let loaded = PenalizeFailuresDiscover::new(loaded, failure_penalty);
+            BoxService::new(
+                Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid"),
+            )
+        }
+        Algorithm::LatencyEwma => {
+            let loaded = LatencyEwmaDiscover::new(
+                discover,
+                default_rtt,
+                decay,
+                CompleteOnResponse::default(),
+            );
+            let loaded = PenalizeFailuresDiscover::new(loaded, failure_penalty);
+            BoxService::new(
+                Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid"),
+            )
+        }
+        Algorithm::LeastRequest => {
+            let loaded = PendingRequestsDiscover::new(discover, CompleteOnResponse::default());
+            let loaded = PenalizeFailuresDiscover::new(loaded, failure_penalty);
+            BoxService::new(
+                Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid"),
+            )
+        }
+        // When all endpoints have the same load, the balancer chooses the first
+        // of its two random candidates.
+        Algorithm::Random => {
+            let loaded = PenalizeFailuresDiscover::new(Constant::new(discover, 0), failure_penalty);
+            BoxService::new(
+                Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid"),
+            )
+        }
+        Algorithm::RoundRobin => BoxService::new(RoundRobin::new(discover)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, stream, StreamExt};
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+    use tower::{discover::Change, Layer, Service, ServiceExt};
+
+    type Endpoints = stream::BoxStream<
+        'static,
+        Result<Change<usize, BoxService<(), usize, Infallible>>, Infallible>,
+    >;
+
+    /// Discovers endpoints that respond with their index. Endpoints in `slow`
+    /// never respond.
+    fn endpoints(n: usize, slow: &'static [usize]) -> Endpoints {
+        let changes = (0..n).map(move |i| {
+            let svc = tower::service_fn(move |()| {
+                if slow.contains(&i) {
+                    future::Either::Left(future::pending())
+                } else {
+                    future::Either::Right(future::ok::<_, Infallible>(i))
+                }
+            });
+            Ok(Change::Insert(i, BoxService::new(svc)))
+        });
+        stream::iter(changes).chain(stream::pending()).boxed()
+    }
+
+    async fn distribution(
+        algorithm: Algorithm,
+        n: usize,
+        requests: usize,
+    ) -> HashMap<usize, usize> {
+        let mut balance = layer_with(
+            algorithm,
+            Duration::from_millis(30),
+            Duration::from_secs(10),
+            None,
+        )
+        .layer(endpoints(n, &[]));
+        let mut counts = HashMap::new();
+        for _ in 0..requests {
+            let i = balance.ready().await.unwrap().call(()).await.unwrap();
+            *counts.entry(i).or_default() += 1;
+        }
+        counts
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn round_robin_visits_each_endpoint_in_turn() {
+        let mut balance = layer_with(
+            Algorithm::RoundRobin,
+            Duration::from_millis(30),
+            Duration::from_secs(10),
+            None,
+        )
+        .layer(endpoints(3, &[]));
+        let mut order = Vec::new();
+        for _ in 0..6 {
+            order.push(balance.ready().await.unwrap().call(()).await.unwrap());
+        }
+        assert_eq!(order, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn random_uses_every_endpoint() {
+        let counts = distribution(Algorithm::Random, 4, 400).await;
+        assert_eq!(counts.len(), 4, "{:?}", counts);
+        // Each endpoint expects 100 requests. This bound is exceeded with
+        // negligible probability.
+        assert!(counts.values().all(|&c| c > 40), "{:?}", counts);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn least_request_avoids_busy_endpoints() {
+        let mut balance = layer_with(
+            Algorithm::LeastRequest,
+            Duration::from_millis(30),
+            Duration::from_secs(10),
+            None,
+        )
+        .layer(endpoints(2, &[0]));
+
+        // Requests to endpoint 0 never complete, so once a request is in flight
+        // to it, all requests are sent to endpoint 1.
+        let mut in_flight = Vec::new();
+        let mut completed = 0;
+        for _ in 0..20 {
+            let mut rsp = balance.ready().await.unwrap().call(());
+            match futures::poll!(&mut rsp) {
+                std::task::Poll::Ready(rsp) => {
+                    assert_eq!(rsp.unwrap(), 1);
+                    completed += 1;
+                }
+                std::task::Poll::Pending => in_flight.push(rsp),
+            }
+        }
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(completed, 19);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn latency_ewma_prefers_fast_endpoints() {
+        // Endpoint 0 takes ten times as long to connect as endpoint 1.
+        let changes = (0..2).map(|i| {
+            let latency = Duration::from_millis(if i == 0 { 100 } else { 10 });
+            let svc = tower::service_fn(move |()| async move {
+                tokio::time::sleep(latency).await;
+                Ok::<_, Infallible>(i)
+            });
+            Ok::<_, Infallible>(Change::Insert(i, BoxService::new(svc)))
+        });
+        let mut balance = layer_with(
+            Algorithm::LatencyEwma,
+            Duration::from_millis(30),
+            Duration::from_secs(10),
+            None,
+        )
+        .layer(stream::iter(changes).chain(stream::pending()).boxed());
+
+        let mut counts = HashMap::<usize, usize>::new();
+        for _ in 0..100 {
+            let i = balance.ready().await.unwrap().call(()).await.unwrap();
+            *counts.entry(i).or_default() += 1;
+        }
+        // Only the requests dispatched before either endpoint has any latency
+        // history may be sent to the slow endpoint.
+        assert!(counts.get(&1).copied().unwrap_or(0) >= 95, "{:?}", counts);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn deprioritizes_recently_failed_endpoints() {
+        const PENALTY: Duration = Duration::from_secs(10);
+
+        // Endpoint 0 fails its first request.
+        let failed = Arc::new(AtomicBool::new(false));
+        let changes = (0..2).map(move |i| {
+            let failed = failed.clone();
+            let svc = tower::service_fn(move |()| {
+                future::ready(if i == 0 && !failed.swap(true, Ordering::SeqCst) {
+                    Err::<usize, Error>("endpoint failed".into())
+                } else {
+                    Ok(i)
+                })
+            });
+            Ok::<_, Infallible>(Change::Insert(i, BoxService::new(svc)))
+        });
+        let mut balance = layer_with(
+            Algorithm::Random,
+            Duration::from_millis(30),
+            Duration::from_secs(10),
+            Some(PENALTY),
+        )
+        .layer(stream::iter(changes).chain(stream::pending()).boxed());
+
+        while balance.ready().await.unwrap().call(()).await.is_ok() {}
+
+        // Time is paused, so the failure remains recent.
+        for _ in 0..50 {
+            let i = balance.ready().await.unwrap().call(()).await.unwrap();
+            assert_eq!(i, 1, "failed endpoint must be deprioritized");
+        }
+
+        // Once the penalty expires, the endpoint is selected again.
+        tokio::time::advance(PENALTY).await;
+        let mut counts = HashMap::<usize, usize>::new();
+        for _ in 0..100 {
+            let i = balance.ready().await.unwrap().call(()).await.unwrap();
+            *counts.entry(i).or_default() += 1;
+        }
+        assert!(counts.get(&0).copied().unwrap_or(0) > 0, "{:?}", counts);
+    }
+}
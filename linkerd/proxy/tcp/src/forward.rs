@@ -1,37 +1,99 @@
 use futures::prelude::*;
 use linkerd_duplex::Duplex;
+use linkerd_errno::Errno;
 use linkerd_error::Error;
-use linkerd_stack::layer;
+use linkerd_io::{self as io, Sensor, SensorIo};
+use linkerd_stack::{layer, ExtractParam, NewService};
 use std::{
     future::Future,
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time,
 };
-use tokio::io::{AsyncRead, AsyncWrite};
 use tower::Service;
+use tracing::{debug, trace};
 
+/// Forwards data between each accepted connection and a new connection to its
+/// target.
+///
+/// Connections are half-closed independently: when either peer shuts down its
+/// write half, the shutdown is propagated to the other peer, and data continues
+/// to be forwarded in the other direction until that peer also shuts down.
 #[derive(Clone, Debug)]
 pub struct Forward<C> {
     connect: C,
+    splice: bool,
+    idle_timeout: Option<Duration>,
 }
 
+/// Builds a `Forward` for each target, with the target's `IdleTimeout`.
+#[derive(Clone, Debug)]
+pub struct NewForward<X, N> {
+    inner: N,
+    params: X,
+    splice: bool,
+}
+
+/// When set, forwarded connections are closed once no data has been
+/// transferred on them, in either direction, for this long.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdleTimeout(pub Option<Duration>);
+
+#[derive(Debug, Error)]
+#[error("connection closed after being idle for {0:?}")]
+pub struct IdleTimeoutError(Duration);
+
+/// Records the time at which a forwarded connection last read or wrote data.
+#[derive(Clone, Debug)]
+struct Activity(Arc<ActivityInner>);
+
+#[derive(Debug)]
+struct ActivityInner {
+    start: time::Instant,
+    /// The number of milliseconds after `start` at which data was last
+    /// transferred.
+    last_ms: AtomicU64,
+}
+
+// === impl Forward ===
+
 impl<C> Forward<C> {
-    fn new(connect: C) -> Self {
-        Self { connect }
+    fn new(connect: C, splice: bool) -> Self {
+        Self {
+            connect,
+            splice,
+            idle_timeout: None,
+        }
     }
 
     pub fn layer() -> impl layer::Layer<C, Service = Self> + Clone + Copy {
-        layer::mk(Self::new)
+        Self::layer_with_splice(false)
+    }
+
+    /// Forwards data with `splice(2)` when `splice` is true, the proxy is
+    /// running on Linux, and both transports are plain TCP sockets.
+    /// Otherwise, data is copied through userspace.
+    pub fn layer_with_splice(splice: bool) -> impl layer::Layer<C, Service = Self> + Clone + Copy {
+        layer::mk(move |connect| Self::new(connect, splice))
     }
 }
 
 impl<C, I> Service<I> for Forward<C>
 where
-    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    I: AsyncRead + AsyncWrite + io::Splice + Send + Unpin + 'static,
     C: tower::Service<()> + Send + 'static,
     C::Error: Into<Error>,
     C::Future: Send + 'static,
-    C::Response: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    C::Response: AsyncRead + AsyncWrite + io::Splice + Send + Unpin + 'static,
 {
     type Response = ();
     type Error = Error;
@@ -43,11 +105,234 @@ where
     }
 
     fn call(&mut self, src_io: I) -> Self::Future {
+        let splice = self.splice;
+        let idle_timeout = self.idle_timeout;
         Box::pin(
             self.connect
                 .call(())
                 .err_into::<Error>()
-                .and_then(|dst_io| Duplex::new(src_io, dst_io).err_into::<Error>()),
+                .and_then(move |dst_io| forward(src_io, dst_io, splice, idle_timeout)),
         )
     }
 }
+
+// === impl NewForward ===
+
+impl<X: Clone, N> NewForward<X, N> {
+    /// Forwards data as `Forward::layer_with_splice` does, closing each
+    /// target's connections once they have been idle for the target's
+    /// `IdleTimeout`, as extracted by `params`.
+    pub fn layer_via(splice: bool, params: X) -> impl layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            inner,
+            params: params.clone(),
+            splice,
+        })
+    }
+}
+
+impl<T, X, N> NewService<T> for NewForward<X, N>
+where
+    X: ExtractParam<IdleTimeout, T>,
+    N: NewService<T>,
+{
+    type Service = Forward<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let IdleTimeout(idle_timeout) = self.params.extract_param(&target);
+        Forward {
+            connect: self.inner.new_service(target),
+            splice: self.splice,
+            idle_timeout,
+        }
+    }
+}
+
+async fn forward<I, O>(
+    mut src_io: I,
+    mut dst_io: O,
+    splice: bool,
+    idle_timeout: Option<Duration>,
+) -> Result<(), Error>
+where
+    I: AsyncRead + AsyncWrite + io::Splice + Unpin,
+    O: AsyncRead + AsyncWrite + io::Splice + Unpin,
+{
+    if let Some(timeout) = idle_timeout {
+        // Spliced data bypasses the transports, so the connection's activity
+        // could not be observed.
+        if splice {
+            trace!("Idle connections must be closed; not splicing");
+        }
+        return duplex_until_idle(src_io, dst_io, timeout).await;
+    }
+
+    #[cfg(target_os = "linux")]
+    if splice {
+        if src_io.tcp_stream().is_some() && dst_io.tcp_stream().is_some() {
+            trace!("Splicing");
+            let mut duplex = linkerd_duplex::SpliceDuplex::new()?;
+            loop {
+                let spliced =
+                    future::poll_fn(|cx| match (src_io.tcp_stream(), dst_io.tcp_stream()) {
+                        (Some(src), Some(dst)) => duplex.poll_splice(cx, src, dst),
+                        _ => unreachable!("transports must not stop exposing their sockets"),
+                    })
+                    .await?;
+                // Spliced bytes bypass the transports, so they are recorded
+                // explicitly as they are moved.
+                match spliced {
+                    Some(spliced) => {
+                        src_io.record_spliced(spliced.client_to_server, spliced.server_to_client);
+                        dst_io.record_spliced(spliced.server_to_client, spliced.client_to_server);
+                    }
+                    None => return Ok(()),
+                }
+            }
+        }
+        trace!("Transports cannot be spliced");
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if splice {
+        trace!("Splicing is not supported on this operating system");
+    }
+
+    Duplex::new(src_io, dst_io).await.map_err(Into::into)
+}
+
+/// Forwards data between the two connections until either side closes or
+/// until no data has been transferred for `timeout`.
+async fn duplex_until_idle<I, O>(src_io: I, dst_io: O, timeout: Duration) -> Result<(), Error>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+    O: AsyncRead + AsyncWrite + Unpin,
+{
+    // The source transport reads the data sent in one direction and writes
+    // the data sent in the other, so it observes all of the connection's
+    // activity.
+    let activity = Activity::new();
+    let duplex = Duplex::new(SensorIo::new(src_io, activity.clone()), dst_io);
+    tokio::pin!(duplex);
+    loop {
+        tokio::select! {
+            res = &mut duplex => return res.map_err(Into::into),
+            () = time::sleep_until(activity.last() + timeout) => {
+                // Data may have been transferred since the timer was set.
+                if activity.last() + timeout <= time::Instant::now() {
+                    debug!(?timeout, "Closing idle connection");
+                    return Err(IdleTimeoutError(timeout).into());
+                }
+            }
+        }
+    }
+}
+
+// === impl Activity ===
+
+impl Activity {
+    fn new() -> Self {
+        Self(Arc::new(ActivityInner {
+            start: time::Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }))
+    }
+
+    fn last(&self) -> time::Instant {
+        let ms = self.0.last_ms.load(Ordering::Acquire);
+        self.0.start + Duration::from_millis(ms)
+    }
+
+    fn touch(&self, sz: usize) {
+        if sz > 0 {
+            let ms = self.0.start.elapsed().as_millis() as u64;
+            self.0.last_ms.fetch_max(ms, Ordering::AcqRel);
+        }
+    }
+}
+
+impl Sensor for Activity {
+    fn record_read(&mut self, sz: usize) {
+        self.touch(sz);
+    }
+
+    fn record_write(&mut self, sz: usize) {
+        self.touch(sz);
+    }
+
+    fn record_close(&mut self, _: Option<Errno>) {}
+
+    fn record_error<T>(&mut self, op: Poll<T>) -> Poll<T> {
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn closes_idle_connections() {
+        let (src_io, mut client) = tokio::io::duplex(64);
+        let (dst_io, mut server) = tokio::io::duplex(64);
+        let forward = duplex_until_idle(src_io, dst_io, IDLE_TIMEOUT);
+        tokio::pin!(forward);
+
+        // Data sent in either direction keeps the connection open.
+        let mut buf = [0; 4];
+        for _ in 0..3 {
+            let exchange = async {
+                time::sleep(IDLE_TIMEOUT / 2).await;
+                client.write_all(b"ping").await.unwrap();
+                server.read_exact(&mut buf).await.unwrap();
+                time::sleep(IDLE_TIMEOUT / 2).await;
+                server.write_all(b"pong").await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+            };
+            tokio::select! {
+                res = &mut forward => panic!("active connection closed: {:?}", res),
+                () = exchange => {}
+            }
+        }
+
+        let last = time::Instant::now();
+        let error = forward.await.expect_err("idle connection must be closed");
+        assert!(error.is::<IdleTimeoutError>());
+        assert!(last.elapsed() >= IDLE_TIMEOUT);
+
+        // Both peers observe the connection closing.
+        assert_eq!(client.read(&mut [0; 1]).await.unwrap(), 0);
+        assert_eq!(server.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn propagates_half_close() {
+        for idle_timeout in [None, Some(IDLE_TIMEOUT)].iter().copied() {
+            let (src_io, mut client) = tokio::io::duplex(64);
+            let (dst_io, mut server) = tokio::io::duplex(64);
+            let forward = tokio::spawn(forward(src_io, dst_io, false, idle_timeout));
+
+            // The client sends its request and closes its write half...
+            client.write_all(b"request").await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            server.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"request", "the server must observe the client's FIN");
+
+            // ...but it still receives the server's response.
+            server.write_all(b"response").await.unwrap();
+            server.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"response");
+
+            forward
+                .await
+                .unwrap()
+                .expect("connections must close once both halves are shut down");
+        }
+    }
+}
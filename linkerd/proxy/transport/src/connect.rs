@@ -1,4 +1,4 @@
-use crate::{Keepalive, Remote, ServerAddr};
+use crate::{dscp, DscpMarking, Keepalive, Remote, ServerAddr};
 use linkerd_io as io;
 use linkerd_stack::Param;
 use std::{
@@ -9,14 +9,15 @@ use std::{
 use tokio::net::TcpStream;
 use tracing::debug;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct ConnectTcp {
     keepalive: Keepalive,
+    dscp: DscpMarking,
 }
 
 impl ConnectTcp {
-    pub fn new(keepalive: Keepalive) -> Self {
-        Self { keepalive }
+    pub fn new(keepalive: Keepalive, dscp: DscpMarking) -> Self {
+        Self { keepalive, dscp }
     }
 }
 
@@ -33,11 +34,15 @@ impl<T: Param<Remote<ServerAddr>>> tower::Service<T> for ConnectTcp {
     fn call(&mut self, t: T) -> Self::Future {
         let Keepalive(keepalive) = self.keepalive;
         let Remote(ServerAddr(addr)) = t.param();
+        let mark = self.dscp.for_port(addr.port());
         debug!(server.addr = %addr, "Connecting");
         Box::pin(async move {
             let io = TcpStream::connect(&addr).await?;
             super::set_nodelay_or_warn(&io);
             super::set_keepalive_or_warn(&io, keepalive);
+            if let Some(dscp) = mark {
+                dscp::set_dscp_or_warn(&io, dscp);
+            }
             debug!(
                 local.addr = %io.local_addr().expect("cannot load local addr"),
                 ?keepalive,
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, io, sync::Arc};
+use thiserror::Error;
+use tokio::net::TcpStream;
+
+/// A Differentiated Services Code Point, with which a connection's packets
+/// are marked so that the network may apply QoS policies to them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Dscp(u8);
+
+#[derive(Debug, Error)]
+#[error("DSCP value {0} exceeds the maximum of 63")]
+pub struct InvalidDscp(u8);
+
+/// Determines how connections are marked, by port.
+///
+/// Connections are marked by their destination port when connecting and by
+/// their original destination port when accepting. Ports without a configured
+/// mark use the default, if one is set.
+#[derive(Clone, Debug, Default)]
+pub struct DscpMarking {
+    default: Option<Dscp>,
+    ports: Arc<HashMap<u16, Dscp>>,
+}
+
+// === impl Dscp ===
+
+impl Dscp {
+    pub const MAX: u8 = 0b11_1111;
+
+    pub fn new(dscp: u8) -> Result<Self, InvalidDscp> {
+        if dscp > Self::MAX {
+            return Err(InvalidDscp(dscp));
+        }
+        Ok(Self(dscp))
+    }
+
+    /// The value of the TOS (or IPv6 traffic class) octet, the upper six bits
+    /// of which hold the DSCP. The lower two bits are left to ECN.
+    fn tos(self) -> u8 {
+        self.0 << 2
+    }
+}
+
+// === impl DscpMarking ===
+
+impl DscpMarking {
+    pub fn new(default: Option<Dscp>, ports: impl IntoIterator<Item = (u16, Dscp)>) -> Self {
+        Self {
+            default,
+            ports: Arc::new(ports.into_iter().collect()),
+        }
+    }
+
+    /// Returns the mark for connections on `port`: the port's own mark, if one
+    /// is configured, and otherwise the default.
+    pub fn for_port(&self, port: u16) -> Option<Dscp> {
+        self.ports.get(&port).copied().or(self.default)
+    }
+}
+
+pub(crate) fn set_dscp_or_warn(tcp: &TcpStream, dscp: Dscp) {
+    if let Err(e) = set_dscp(tcp, dscp) {
+        tracing::warn!(?dscp, "failed to set DSCP: {}", e);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_dscp(tcp: &TcpStream, dscp: Dscp) -> io::Result<()> {
+    use std::{net::IpAddr, os::unix::io::AsRawFd};
+
+    let fd = tcp.as_raw_fd();
+    match tcp.local_addr()?.ip() {
+        IpAddr::V4(_) => unsafe { linux::set_tos(fd, libc::IPPROTO_IP, libc::IP_TOS, dscp) },
+        IpAddr::V6(ip) => {
+            unsafe { linux::set_tos(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, dscp) }?;
+            // Dual-stack sockets carry IPv4 traffic for IPv4-mapped addresses,
+            // which is marked by `IP_TOS` rather than the traffic class.
+            if let [0, 0, 0, 0, 0, 0xffff, _, _] = ip.segments() {
+                unsafe { linux::set_tos(fd, libc::IPPROTO_IP, libc::IP_TOS, dscp) }?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_dscp(_: &TcpStream, _: Dscp) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "DSCP marking not supported on this operating system",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Dscp;
+    use std::{io, mem, os::unix::io::RawFd};
+
+    pub unsafe fn set_tos(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+        dscp: Dscp,
+    ) -> io::Result<()> {
+        let tos = libc::c_int::from(dscp.tos());
+        let ret = libc::setsockopt(
+            fd,
+            level,
+            name,
+            &tos as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        );
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub unsafe fn get_tos(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<u8> {
+        let mut tos: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut tos as *mut _ as *mut libc::c_void,
+            &mut len,
+        );
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(tos as u8)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{addrs::*, listen::Bind, BindTcp, ConnectTcp, Keepalive};
+    use futures::prelude::*;
+    use linkerd_stack::Param;
+    use std::{net::SocketAddr, os::unix::io::AsRawFd};
+    use tower::Service;
+
+    struct Server(SocketAddr, DscpMarking);
+
+    impl Param<ListenAddr> for Server {
+        fn param(&self) -> ListenAddr {
+            ListenAddr(self.0)
+        }
+    }
+
+    impl Param<Keepalive> for Server {
+        fn param(&self) -> Keepalive {
+            Keepalive(None)
+        }
+    }
+
+    impl Param<DscpMarking> for Server {
+        fn param(&self) -> DscpMarking {
+            self.1.clone()
+        }
+    }
+
+    fn tos(tcp: &TcpStream) -> u8 {
+        let (level, name) = if tcp.local_addr().unwrap().is_ipv6() {
+            (libc::IPPROTO_IPV6, libc::IPV6_TCLASS)
+        } else {
+            (libc::IPPROTO_IP, libc::IP_TOS)
+        };
+        unsafe { linux::get_tos(tcp.as_raw_fd(), level, name) }.expect("must read TOS")
+    }
+
+    /// Connects to and accepts a connection on `addr`, returning the TOS byte
+    /// of the client and server sockets.
+    async fn marks(addr: SocketAddr, connect: DscpMarking, accept: DscpMarking) -> (u8, u8) {
+        let (Local(ServerAddr(addr)), mut incoming) =
+            BindTcp::default().bind(&Server(addr, accept)).unwrap();
+        // `ConnectTcp` is always ready.
+        let client = ConnectTcp::new(Keepalive(None), connect)
+            .call(Remote(ServerAddr(addr)))
+            .await
+            .expect("must connect");
+        let (_, server) = incoming.next().await.unwrap().expect("must accept");
+        (tos(&client), tos(&server))
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert_eq!(Dscp::new(46).unwrap().tos(), 0b1011_1000);
+        assert!(Dscp::new(Dscp::MAX).is_ok());
+        assert!(Dscp::new(Dscp::MAX + 1).is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn marks_ipv4_sockets() {
+        let ef = Dscp::new(46).unwrap();
+        let af41 = Dscp::new(34).unwrap();
+
+        let marking = DscpMarking::new(Some(ef), None);
+        let (client, server) = marks(([127, 0, 0, 1], 0).into(), marking.clone(), marking).await;
+        assert_eq!((client, server), (ef.tos(), ef.tos()));
+
+        // Unmarked connections keep the default TOS.
+        let none = DscpMarking::default();
+        let (client, server) = marks(([127, 0, 0, 1], 0).into(), none.clone(), none).await;
+        assert_eq!((client, server), (0, 0));
+
+        // Per-port marks override the default.
+        let marking = DscpMarking::new(Some(ef), vec![(4143, af41)]);
+        assert_eq!(marking.for_port(4143), Some(af41));
+        assert_eq!(marking.for_port(8080), Some(ef));
+        let (client, server) = marks(([127, 0, 0, 1], 0).into(), marking.clone(), marking).await;
+        assert_eq!((client, server), (ef.tos(), ef.tos()));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn port_marks_take_precedence() {
+        let ef = Dscp::new(46).unwrap();
+        let af41 = Dscp::new(34).unwrap();
+
+        // Bind an ephemeral port so that a mark can be configured for it.
+        let port = std::net::TcpListener::bind(([127, 0, 0, 1], 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let marking = DscpMarking::new(Some(ef), vec![(port, af41)]);
+        let (client, server) = marks(([127, 0, 0, 1], port).into(), marking.clone(), marking).await;
+        assert_eq!((client, server), (af41.tos(), af41.tos()));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn marks_ipv6_sockets() {
+        let addr = SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 0));
+        if std::net::TcpListener::bind(addr).is_err() {
+            // IPv6 is not available in all test environments.
+            return;
+        }
+
+        let ef = Dscp::new(46).unwrap();
+        let marking = DscpMarking::new(Some(ef), None);
+        let (client, server) = marks(addr, marking.clone(), marking).await;
+        assert_eq!((client, server), (ef.tos(), ef.tos()));
+    }
+}
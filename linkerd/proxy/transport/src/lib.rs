@@ -1,12 +1,14 @@
 //! Utilities for use TCP servers & clients.
 //!
-//! Uses unsafe code to interact with socket options for keepalive and SO_ORIGINAL_DST.
+//! Uses unsafe code to interact with socket options for keepalive, DSCP
+//! marking, and SO_ORIGINAL_DST.
 
 #![deny(warnings, rust_2018_idioms)]
 //#![forbid(unsafe_code)]
 
 pub mod addrs;
 mod connect;
+mod dscp;
 pub mod listen;
 pub mod metrics;
 pub mod orig_dst;
@@ -14,6 +16,7 @@ pub mod orig_dst;
 pub use self::{
     addrs::{ClientAddr, ListenAddr, Local, OrigDstAddr, Remote, ServerAddr},
     connect::ConnectTcp,
+    dscp::{Dscp, DscpMarking, InvalidDscp},
     listen::{Bind, BindTcp},
     orig_dst::BindWithOrigDst,
 };
@@ -1,4 +1,4 @@
-use crate::{addrs::*, Keepalive};
+use crate::{addrs::*, dscp, DscpMarking, Keepalive};
 use futures::prelude::*;
 use linkerd_io as io;
 use linkerd_stack::Param;
@@ -15,6 +15,7 @@ pub trait Bind<T> {
         + io::AsyncWrite
         + io::Peek
         + io::PeerAddr
+        + io::Splice
         + fmt::Debug
         + Unpin
         + Send
@@ -47,7 +48,7 @@ impl BindTcp {
 
 impl<T> Bind<T> for BindTcp
 where
-    T: Param<ListenAddr> + Param<Keepalive>,
+    T: Param<ListenAddr> + Param<Keepalive> + Param<DscpMarking>,
 {
     type Addrs = Addrs;
     type Incoming = Pin<Box<dyn Stream<Item = io::Result<(Self::Addrs, Self::Io)>> + Send + Sync>>;
@@ -62,11 +63,15 @@ where
             tokio::net::TcpListener::from_std(l).expect("listener must be valid")
         };
         let server = Local(ServerAddr(listen.local_addr()?));
+        let dscp = Param::<DscpMarking>::param(params).for_port(server.as_ref().port());
         let Keepalive(keepalive) = params.param();
         let accept = TcpListenerStream::new(listen).map(move |res| {
             let tcp = res?;
             super::set_nodelay_or_warn(&tcp);
             super::set_keepalive_or_warn(&tcp, keepalive);
+            if let Some(dscp) = dscp {
+                dscp::set_dscp_or_warn(&tcp, dscp);
+            }
             let client = Remote(ClientAddr(tcp.peer_addr()?));
             Ok((Addrs { server, client }, tcp))
         });
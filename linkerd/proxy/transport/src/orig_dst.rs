@@ -1,6 +1,8 @@
 use crate::{
     addrs::*,
+    dscp,
     listen::{self, Bind, Bound},
+    DscpMarking,
 };
 use futures::prelude::*;
 use linkerd_io as io;
@@ -55,6 +57,7 @@ impl<B> From<B> for BindWithOrigDst<B> {
 
 impl<T, B> Bind<T> for BindWithOrigDst<B>
 where
+    T: Param<DscpMarking>,
     B: Bind<T, Io = TcpStream> + 'static,
 {
     type Addrs = Addrs<B::Addrs>;
@@ -64,10 +67,17 @@ where
 
     fn bind(self, t: &T) -> io::Result<Bound<Self::Incoming>> {
         let (addr, incoming) = self.inner.bind(t)?;
+        let marking: DscpMarking = t.param();
 
-        let incoming = incoming.map(|res| {
+        let incoming = incoming.map(move |res| {
             let (inner, tcp) = res?;
             let orig_dst = orig_dst_addr(&tcp)?;
+            // Accepted connections are marked for the port they target rather
+            // than for the listener's port, so a port's own mark takes
+            // precedence over the default.
+            if let Some(dscp) = marking.for_port(orig_dst.as_ref().port()) {
+                dscp::set_dscp_or_warn(&tcp, dscp);
+            }
             let addrs = Addrs { inner, orig_dst };
             Ok((addrs, tcp))
         });
@@ -95,6 +95,10 @@ impl Route {
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
+
+    pub fn clear_timeout(&mut self) {
+        self.timeout = None;
+    }
 }
 
 // === impl RequestMatch ===
@@ -0,0 +1,140 @@
+use linkerd_error::Error;
+use std::{fmt, io};
+use tokio_rustls::rustls::TLSError;
+
+/// Describes the TLS alert that ended a connection, as named by RFC 8446.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Alert {
+    /// The peer sent the alert.
+    Received(String),
+
+    /// The proxy sent the alert in response to the peer's behavior.
+    Sent(&'static str),
+}
+
+// === impl Alert ===
+
+impl Alert {
+    /// Finds the TLS alert that caused the error, if any.
+    ///
+    /// rustls does not expose the alerts it sends, so these are inferred from
+    /// the error that caused them.
+    pub fn from_error(error: &Error) -> Option<Self> {
+        let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(&**error);
+        while let Some(e) = cause {
+            if let Some(tls) = e.downcast_ref::<TLSError>() {
+                return Self::from_tls(tls);
+            }
+            // I/O errors do not expose the errors they wrap as their sources.
+            if let Some(tls) = e
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+                .and_then(|e| e.downcast_ref::<TLSError>())
+            {
+                return Self::from_tls(tls);
+            }
+            cause = e.source();
+        }
+        None
+    }
+
+    fn from_tls(error: &TLSError) -> Option<Self> {
+        let sent = match error {
+            TLSError::AlertReceived(desc) => {
+                return Some(Self::Received(snake_case(&format!("{:?}", desc))))
+            }
+            TLSError::InappropriateMessage { .. }
+            | TLSError::InappropriateHandshakeMessage { .. } => "unexpected_message",
+            TLSError::CorruptMessage | TLSError::CorruptMessagePayload(_) => "decode_error",
+            TLSError::NoCertificatesPresented => "certificate_required",
+            TLSError::DecryptError => "bad_record_mac",
+            TLSError::PeerIncompatibleError(_) => "handshake_failure",
+            TLSError::PeerMisbehavedError(_) => "illegal_parameter",
+            TLSError::WebPKIError(_) => "bad_certificate",
+            TLSError::PeerSentOversizedRecord => "record_overflow",
+            TLSError::NoApplicationProtocol => "no_application_protocol",
+            _ => return None,
+        };
+        Some(Self::Sent(sent))
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            Self::Received(desc) => desc.as_str(),
+            Self::Sent(desc) => desc,
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Received(desc) => write!(f, "received {}", desc),
+            Self::Sent(desc) => write!(f, "sent {}", desc),
+        }
+    }
+}
+
+/// Converts rustls' names for alert descriptions (e.g. `UnknownCA`) to those
+/// used by the RFCs (e.g. `unknown_ca`).
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).map_or(false, char::is_ascii_lowercase);
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_alerts() {
+        assert_eq!(snake_case("BadCertificate"), "bad_certificate");
+        assert_eq!(snake_case("UnknownCA"), "unknown_ca");
+        assert_eq!(snake_case("UnknownPSKIdentity"), "unknown_psk_identity");
+        assert_eq!(snake_case("Unknown(42)"), "unknown(42)");
+    }
+
+    #[test]
+    fn finds_alerts_in_errors() {
+        use tokio_rustls::rustls::internal::msgs::enums::AlertDescription;
+
+        let err = Error::from(io::Error::new(
+            io::ErrorKind::InvalidData,
+            TLSError::AlertReceived(AlertDescription::BadCertificate),
+        ));
+        let alert = Alert::from_error(&err).expect("alert must be found");
+        assert_eq!(alert.description(), "bad_certificate");
+        assert_eq!(alert.to_string(), "received bad_certificate");
+
+        let err = Error::from(io::Error::new(
+            io::ErrorKind::InvalidData,
+            TLSError::PeerMisbehavedError("bad".into()),
+        ));
+        let alert = Alert::from_error(&err).expect("alert must be found");
+        assert_eq!(alert, Alert::Sent("illegal_parameter"));
+        assert_eq!(alert.to_string(), "sent illegal_parameter");
+
+        let err = Error::from(TLSError::WebPKIError(webpki::Error::UnknownIssuer));
+        assert_eq!(
+            Alert::from_error(&err),
+            Some(Alert::Sent("bad_certificate"))
+        );
+
+        let err = Error::from(io::Error::new(io::ErrorKind::InvalidData, "nope"));
+        assert_eq!(Alert::from_error(&err), None);
+    }
+}
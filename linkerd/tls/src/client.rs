@@ -92,7 +92,7 @@ impl<L: Clone, C> Client<L, C> {
 
 impl<L, C, T> tower::Service<T> for Client<L, C>
 where
-    L: Clone + Param<Config>,
+    L: Clone + Param<Config> + Send + 'static,
     T: Param<ConditionalClientTls>,
     C: tower::Service<T, Error = io::Error>,
     C::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin,
@@ -116,26 +116,8 @@ where
             }
         };
 
-        let handshake = match self.local.as_ref() {
-            Some(local) => {
-                // Build a rustls ClientConfig for this connection.
-                //
-                // If ALPN protocols are configured by the endpoint, we have to clone the
-                // entire configuration and set the protocols. If there are no
-                // ALPN options, clone the Arc'd base configuration without
-                // extra allocation.
-                //
-                // TODO it would be better to avoid cloning the whole TLS config
-                // per-connection.
-                match alpn {
-                    None => tokio_rustls::TlsConnector::from(local.param()),
-                    Some(AlpnProtocols(protocols)) => {
-                        let mut config: rustls::ClientConfig = local.param().as_ref().clone();
-                        config.alpn_protocols = protocols;
-                        tokio_rustls::TlsConnector::from(Arc::new(config))
-                    }
-                }
-            }
+        let local = match self.local.clone() {
+            Some(local) => local,
             None => {
                 trace!("Local identity disabled");
                 return Either::Left(self.inner.call(target).map_ok(io::EitherIo::Left));
@@ -146,6 +128,26 @@ where
         let connect = self.inner.call(target);
         Either::Right(Box::pin(async move {
             let io = connect.await?;
+
+            // Build a rustls ClientConfig for this connection once it has
+            // been established, so that the handshake uses the current
+            // certificate even if it was rotated while connecting.
+            //
+            // If ALPN protocols are configured by the endpoint, we have to clone the
+            // entire configuration and set the protocols. If there are no
+            // ALPN options, clone the Arc'd base configuration without
+            // extra allocation.
+            //
+            // TODO it would be better to avoid cloning the whole TLS config
+            // per-connection.
+            let handshake = match alpn {
+                None => tokio_rustls::TlsConnector::from(local.param()),
+                Some(AlpnProtocols(protocols)) => {
+                    let mut config: rustls::ClientConfig = local.param().as_ref().clone();
+                    config.alpn_protocols = protocols;
+                    tokio_rustls::TlsConnector::from(Arc::new(config))
+                }
+            };
             let io = handshake.connect((&server_id.0).into(), io).await?;
             if let Some(alpn) = io.get_ref().1.get_alpn_protocol() {
                 debug!(alpn = ?std::str::from_utf8(alpn));
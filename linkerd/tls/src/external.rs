@@ -0,0 +1,414 @@
+//! TLS for peers outside of the mesh.
+//!
+//! Off-mesh destinations neither share the mesh's trust anchors nor accept the
+//! proxy's mesh identity, so each destination is configured with its own trust
+//! anchors, client certificate, and server name. Likewise, off-mesh clients
+//! (e.g. of an ingress) expect certificates issued by authorities they already
+//! trust, so a port may terminate TLS with its own server certificates.
+
+use linkerd_identity as id;
+use std::{fmt, io::Cursor, sync::Arc};
+use thiserror::Error;
+use tokio_rustls::rustls::{self, internal::pemfile, sign::CertifiedKey};
+use tracing::warn;
+
+pub use tokio_rustls::{client::TlsStream, Connect};
+
+/// Server TLS settings for off-mesh clients.
+///
+/// Several certificates may be configured, in which case each client is
+/// presented the certificate that is valid for the name it requests via SNI.
+/// Clients that do not send SNI, or that request a name for which no
+/// certificate is valid, are presented the first certificate.
+#[derive(Clone)]
+pub struct ServerConfig(Arc<rustls::ServerConfig>);
+
+/// Client TLS settings for an off-mesh destination.
+///
+/// The underlying rustls configuration is built once, when the settings are
+/// loaded, and is shared by all connections to the destination.
+#[derive(Clone)]
+pub struct ClientConfig {
+    server_name: id::Name,
+    config: Arc<rustls::ClientConfig>,
+}
+
+#[derive(Debug, Error)]
+pub enum InvalidConfig {
+    #[error("trust anchors could not be parsed")]
+    InvalidTrustAnchors,
+
+    #[error("no trust anchors found")]
+    NoTrustAnchors,
+
+    #[error("client certificate could not be parsed")]
+    InvalidCertificate,
+
+    #[error("no client certificate found")]
+    NoCertificate,
+
+    #[error("client key could not be parsed")]
+    InvalidKey,
+
+    #[error("no PKCS#8 client key found")]
+    NoKey,
+
+    #[error("unsupported client key: {0}")]
+    UnsupportedKey(#[source] rustls::TLSError),
+}
+
+#[derive(Debug, Error)]
+pub enum InvalidServerConfig {
+    #[error("no server certificates configured")]
+    NoCertificates,
+
+    #[error("server certificate could not be parsed")]
+    InvalidCertificate,
+
+    #[error("no server certificate found")]
+    NoCertificate,
+
+    #[error("server key could not be parsed")]
+    InvalidKey,
+
+    #[error("no PKCS#8 server key found")]
+    NoKey,
+
+    #[error("unsupported server key")]
+    UnsupportedKey,
+}
+
+/// Selects a server certificate by the client's SNI, falling back to the first
+/// certificate.
+struct ResolveBySni(Vec<CertifiedKey>);
+
+// === impl ClientConfig ===
+
+impl ClientConfig {
+    /// Builds a configuration from PEM-encoded trust anchors, a DER-encoded
+    /// certificate chain (leaf first), and a DER-encoded PKCS#8 key.
+    pub fn new(
+        server_name: id::Name,
+        trust_anchors_pem: &str,
+        chain: Vec<Vec<u8>>,
+        key: Vec<u8>,
+    ) -> Result<Self, InvalidConfig> {
+        let mut roots = rustls::RootCertStore::empty();
+        let (added, skipped) = roots
+            .add_pem_file(&mut Cursor::new(trust_anchors_pem))
+            .map_err(|()| InvalidConfig::InvalidTrustAnchors)?;
+        if skipped != 0 {
+            warn!(%server_name, "Skipped {} trust anchors", skipped);
+        }
+        if added == 0 {
+            return Err(InvalidConfig::NoTrustAnchors);
+        }
+        if chain.is_empty() {
+            return Err(InvalidConfig::NoCertificate);
+        }
+
+        let mut config = rustls::ClientConfig::new();
+        config.root_store = roots;
+        // As with the mesh client configuration, session resumption is
+        // disabled.
+        config.enable_tickets = false;
+        config
+            .set_single_client_cert(
+                chain.into_iter().map(rustls::Certificate).collect(),
+                rustls::PrivateKey(key),
+            )
+            .map_err(InvalidConfig::UnsupportedKey)?;
+
+        Ok(Self {
+            server_name,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Builds a configuration from PEM-encoded trust anchors, certificate
+    /// chain, and PKCS#8 key.
+    pub fn from_pem(
+        server_name: id::Name,
+        trust_anchors_pem: &str,
+        chain_pem: &str,
+        key_pem: &str,
+    ) -> Result<Self, InvalidConfig> {
+        let chain = pemfile::certs(&mut Cursor::new(chain_pem))
+            .map_err(|()| InvalidConfig::InvalidCertificate)?;
+        let key = pemfile::pkcs8_private_keys(&mut Cursor::new(key_pem))
+            .map_err(|()| InvalidConfig::InvalidKey)?
+            .into_iter()
+            .next()
+            .ok_or(InvalidConfig::NoKey)?;
+        Self::new(
+            server_name,
+            trust_anchors_pem,
+            chain.into_iter().map(|rustls::Certificate(c)| c).collect(),
+            key.0,
+        )
+    }
+
+    pub fn server_name(&self) -> &id::Name {
+        &self.server_name
+    }
+
+    /// Initiates a TLS session on `io`, verifying the server against this
+    /// destination's trust anchors and name.
+    pub fn connect<I>(&self, io: I) -> Connect<I>
+    where
+        I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        tokio_rustls::TlsConnector::from(self.config.clone())
+            .connect((&self.server_name).into(), io)
+    }
+}
+
+// === impl ServerConfig ===
+
+impl ServerConfig {
+    /// Builds a configuration from DER-encoded certificate chains (leaf first)
+    /// and their DER-encoded PKCS#8 keys.
+    pub fn new(
+        certs: impl IntoIterator<Item = (Vec<Vec<u8>>, Vec<u8>)>,
+    ) -> Result<Self, InvalidServerConfig> {
+        let mut keys = Vec::new();
+        for (chain, key) in certs {
+            if chain.is_empty() {
+                return Err(InvalidServerConfig::NoCertificate);
+            }
+            let key = rustls::sign::any_supported_type(&rustls::PrivateKey(key))
+                .map_err(|()| InvalidServerConfig::UnsupportedKey)?;
+            let key = CertifiedKey::new(
+                chain.into_iter().map(rustls::Certificate).collect(),
+                Arc::new(key),
+            );
+            key.cross_check_end_entity_cert(None)
+                .map_err(|_| InvalidServerConfig::InvalidCertificate)?;
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(InvalidServerConfig::NoCertificates);
+        }
+
+        let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        config.cert_resolver = Arc::new(ResolveBySni(keys));
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        Ok(Self(Arc::new(config)))
+    }
+
+    /// Builds a configuration from PEM-encoded certificate chains and their
+    /// PKCS#8 keys.
+    pub fn from_pem<'p>(
+        certs: impl IntoIterator<Item = (&'p str, &'p str)>,
+    ) -> Result<Self, InvalidServerConfig> {
+        let mut ders = Vec::new();
+        for (chain_pem, key_pem) in certs {
+            let chain = pemfile::certs(&mut Cursor::new(chain_pem))
+                .map_err(|()| InvalidServerConfig::InvalidCertificate)?;
+            let key = pemfile::pkcs8_private_keys(&mut Cursor::new(key_pem))
+                .map_err(|()| InvalidServerConfig::InvalidKey)?
+                .into_iter()
+                .next()
+                .ok_or(InvalidServerConfig::NoKey)?;
+            ders.push((
+                chain.into_iter().map(|rustls::Certificate(c)| c).collect(),
+                key.0,
+            ));
+        }
+        Self::new(ders)
+    }
+
+    pub fn server_config(&self) -> crate::server::Config {
+        self.0.clone()
+    }
+}
+
+impl fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerConfig").finish()
+    }
+}
+
+// === impl ResolveBySni ===
+
+impl rustls::ResolvesServerCert for ResolveBySni {
+    fn resolve(&self, hello: rustls::ClientHello<'_>) -> Option<CertifiedKey> {
+        let key = hello
+            .server_name()
+            .and_then(|sni| {
+                self.0
+                    .iter()
+                    .find(|key| key.cross_check_end_entity_cert(Some(sni)).is_ok())
+            })
+            .unwrap_or(&self.0[0]);
+        Some(key.clone())
+    }
+}
+
+impl fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_identity::test_util::{self as test, Identity};
+    use std::str::FromStr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn client_config(id: &Identity) -> ClientConfig {
+        ClientConfig::new(
+            id::Name::from_str(id.name).unwrap(),
+            std::str::from_utf8(id.trust_anchors).unwrap(),
+            vec![id.crt.to_vec()],
+            id.key.to_vec(),
+        )
+        .expect("client configuration must be valid")
+    }
+
+    /// Starts a server that presents `id`'s certificate and only accepts
+    /// clients with a certificate issued by `id`'s trust anchors.
+    fn server(id: &Identity) -> tokio_rustls::TlsAcceptor {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add_pem_file(&mut Cursor::new(id.trust_anchors))
+            .unwrap();
+        let mut config = rustls::ServerConfig::new(rustls::AllowAnyAuthenticatedClient::new(roots));
+        config
+            .set_single_cert(
+                vec![rustls::Certificate(id.crt.to_vec())],
+                rustls::PrivateKey(id.key.to_vec()),
+            )
+            .unwrap();
+        tokio_rustls::TlsAcceptor::from(Arc::new(config))
+    }
+
+    async fn handshake(client: &ClientConfig, server: &tokio_rustls::TlsAcceptor) -> bool {
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let accept = {
+            let server = server.clone();
+            tokio::spawn(async move {
+                let mut io = server.accept(server_io).await?;
+                io.write_all(b"hello").await?;
+                io.shutdown().await
+            })
+        };
+
+        let connected = match client.connect(client_io).await {
+            Ok(mut io) => {
+                let mut buf = Vec::new();
+                io.read_to_end(&mut buf).await.is_ok() && buf == b"hello"
+            }
+            Err(_) => false,
+        };
+        connected && accept.await.unwrap().is_ok()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn destinations_use_their_own_identity() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let ca1 = client_config(&test::FOO_NS1);
+        let ca2 = client_config(&test::FOO_NS1_CA2);
+        let ca1_server = server(&test::FOO_NS1);
+        let ca2_server = server(&test::FOO_NS1_CA2);
+
+        assert!(handshake(&ca1, &ca1_server).await);
+        assert!(handshake(&ca2, &ca2_server).await);
+
+        // Neither destination's settings are accepted by the other.
+        assert!(!handshake(&ca1, &ca2_server).await);
+        assert!(!handshake(&ca2, &ca1_server).await);
+    }
+
+    async fn connect_to(server: &ServerConfig, name: &str) -> bool {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add_pem_file(&mut Cursor::new(test::FOO_NS1.trust_anchors))
+            .unwrap();
+        let mut config = rustls::ClientConfig::new();
+        config.root_store = roots;
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let accept = tokio_rustls::TlsAcceptor::from(server.server_config());
+        let accept = tokio::spawn(async move {
+            let mut io = accept.accept(server_io).await?;
+            io.write_all(b"hello").await?;
+            io.shutdown().await
+        });
+
+        let name = webpki::DNSNameRef::try_from_ascii_str(name).unwrap();
+        let connected = match connector.connect(name, client_io).await {
+            Ok(mut io) => {
+                let mut buf = Vec::new();
+                io.read_to_end(&mut buf).await.is_ok() && buf == b"hello"
+            }
+            Err(_) => false,
+        };
+        connected && accept.await.unwrap().is_ok()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn servers_select_certificates_by_sni() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let server = ServerConfig::new(vec![
+            (vec![test::FOO_NS1.crt.to_vec()], test::FOO_NS1.key.to_vec()),
+            (vec![test::BAR_NS1.crt.to_vec()], test::BAR_NS1.key.to_vec()),
+        ])
+        .expect("server configuration must be valid");
+
+        assert!(connect_to(&server, test::FOO_NS1.name).await);
+        assert!(connect_to(&server, test::BAR_NS1.name).await);
+        // Clients that request other names are presented the first
+        // certificate, which they do not accept.
+        assert!(
+            !connect_to(
+                &server,
+                "baz.ns1.serviceaccount.identity.linkerd.cluster.local"
+            )
+            .await
+        );
+
+        assert!(matches!(
+            ServerConfig::new(vec![]),
+            Err(InvalidServerConfig::NoCertificates)
+        ));
+        assert!(matches!(
+            ServerConfig::new(vec![(
+                vec![test::FOO_NS1.crt.to_vec()],
+                b"not a key".to_vec()
+            )]),
+            Err(InvalidServerConfig::UnsupportedKey)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let name = id::Name::from_str(test::FOO_NS1.name).unwrap();
+        let trust_anchors = std::str::from_utf8(test::FOO_NS1.trust_anchors).unwrap();
+
+        assert!(matches!(
+            ClientConfig::new(name.clone(), "", vec![test::FOO_NS1.crt.to_vec()], vec![]),
+            Err(InvalidConfig::NoTrustAnchors)
+        ));
+        assert!(matches!(
+            ClientConfig::new(name.clone(), trust_anchors, vec![], vec![]),
+            Err(InvalidConfig::NoCertificate)
+        ));
+        assert!(matches!(
+            ClientConfig::new(
+                name,
+                trust_anchors,
+                vec![test::FOO_NS1.crt.to_vec()],
+                b"not a key".to_vec()
+            ),
+            Err(InvalidConfig::UnsupportedKey(_))
+        ));
+    }
+}
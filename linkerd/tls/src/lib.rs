@@ -5,7 +5,9 @@ pub use linkerd_identity::LocalId;
 use linkerd_io as io;
 pub use tokio_rustls::rustls::Session;
 
+pub mod alert;
 pub mod client;
+pub mod external;
 pub mod server;
 
 pub use self::{
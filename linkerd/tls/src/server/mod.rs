@@ -7,6 +7,7 @@ use linkerd_conditional::Conditional;
 use linkerd_dns_name as dns;
 use linkerd_error::Error;
 use linkerd_identity as id;
+pub use linkerd_identity::ClientAuth;
 use linkerd_io::{self as io, AsyncReadExt, EitherIo, PrefixedIo};
 use linkerd_stack::{layer, ExtractParam, InsertParam, NewService, Param};
 use std::{
@@ -67,7 +68,7 @@ pub enum NoServerTls {
 /// Indicates whether TLS was established on an accepted connection.
 pub type ConditionalServerTls = Conditional<ServerTls, NoServerTls>;
 
-type DetectIo<T> = EitherIo<T, PrefixedIo<T>>;
+pub type DetectIo<T> = EitherIo<T, PrefixedIo<T>>;
 
 pub type Io<T> = EitherIo<TlsStream<DetectIo<T>>, DetectIo<T>>;
 
@@ -78,6 +79,17 @@ pub struct NewDetectTls<P, L, N> {
     _local_identity: std::marker::PhantomData<fn() -> L>,
 }
 
+/// Terminates TLS on all accepted connections with a configured server
+/// config, rather than only on connections that target the local identity.
+///
+/// This is used on ports that serve clients outside of the mesh, so
+/// connections fail unless they begin with a TLS handshake.
+#[derive(Clone, Debug)]
+pub struct NewTerminateTls<P, N> {
+    inner: N,
+    params: P,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Timeout(pub Duration);
 
@@ -94,6 +106,15 @@ pub struct DetectTls<T, P, L, N> {
     inner: N,
 }
 
+#[derive(Clone, Debug)]
+pub struct TerminateTls<T, P, N> {
+    target: T,
+    config: Config,
+    timeout: Timeout,
+    params: P,
+    inner: N,
+}
+
 // The initial peek buffer is statically allocated on the stack and is fairly small; but it is
 // large enough to hold the ~300B ClientHello sent by proxies.
 const PEEK_CAPACITY: usize = 512;
@@ -145,7 +166,7 @@ where
     T: Clone + Send + 'static,
     P: InsertParam<ConditionalServerTls, T> + Clone + Send + Sync + 'static,
     P::Target: Send + 'static,
-    L: Param<LocalId> + Param<Config>,
+    L: Param<LocalId> + Param<Config> + Clone + Send + 'static,
     N: NewService<P::Target, Service = NSvc> + Clone + Send + 'static,
     NSvc: tower::Service<Io<I>, Response = ()> + Send + 'static,
     NSvc::Error: Into<Error>,
@@ -164,9 +185,8 @@ where
         let params = self.params.clone();
         let mut new_accept = self.inner.clone();
 
-        match self.local_identity.as_ref() {
+        match self.local_identity.clone() {
             Some(local) => {
-                let config: Config = local.param();
                 let LocalId(local_id) = local.param();
 
                 // Detect the SNI from a ClientHello (or timeout).
@@ -179,6 +199,14 @@ where
                         // If we detected an SNI matching this proxy, terminate TLS.
                         Some(ServerId(id)) if id == local_id => {
                             trace!("Identified local SNI");
+                            // The configuration is obtained only once the
+                            // handshake begins (and not when the connection
+                            // is accepted) so that a certificate that was
+                            // rotated while the SNI was being detected is
+                            // used. The handshake holds this configuration,
+                            // so it completes even if the certificate is
+                            // rotated again.
+                            let config: Config = local.param();
                             let (peer, io) = handshake(config, io).await?;
                             (Conditional::Some(peer), EitherIo::Left(io))
                         }
@@ -216,8 +244,116 @@ where
     }
 }
 
+// === impl NewTerminateTls ===
+
+impl<P, N> NewTerminateTls<P, N> {
+    pub fn new(params: P, inner: N) -> Self {
+        Self { inner, params }
+    }
+
+    pub fn layer(params: P) -> impl layer::Layer<N, Service = Self> + Clone
+    where
+        P: Clone,
+    {
+        layer::mk(move |inner| Self::new(params.clone(), inner))
+    }
+}
+
+impl<T, P, N> NewService<T> for NewTerminateTls<P, N>
+where
+    P: ExtractParam<Timeout, T> + ExtractParam<Config, T> + Clone,
+    N: Clone,
+{
+    type Service = TerminateTls<T, P, N>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let timeout = self.params.extract_param(&target);
+        let config = self.params.extract_param(&target);
+        TerminateTls {
+            target,
+            config,
+            timeout,
+            params: self.params.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// === impl TerminateTls ===
+
+impl<I, T, P, N, NSvc> tower::Service<I> for TerminateTls<T, P, N>
+where
+    I: io::AsyncRead + io::AsyncWrite + Send + Sync + Unpin + 'static,
+    T: Clone + Send + 'static,
+    P: InsertParam<ConditionalServerTls, T> + Clone + Send + Sync + 'static,
+    P::Target: Send + 'static,
+    N: NewService<P::Target, Service = NSvc> + Clone + Send + 'static,
+    NSvc: tower::Service<Io<I>, Response = ()> + Send + 'static,
+    NSvc::Error: Into<Error>,
+    NSvc::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, io: I) -> Self::Future {
+        let target = self.target.clone();
+        let params = self.params.clone();
+        let mut new_accept = self.inner.clone();
+
+        let Timeout(timeout) = self.timeout;
+        let handshake = time::timeout(timeout, handshake(self.config.clone(), EitherIo::Left(io)));
+        Box::pin(async move {
+            let (peer, io) = handshake.await.map_err(|_| ServerTlsTimeoutError(()))??;
+            new_accept
+                .new_service(params.insert_param(Conditional::Some(peer), target))
+                .oneshot(EitherIo::Left(io))
+                .err_into::<Error>()
+                .await
+        })
+    }
+}
+
 /// Peek or buffer the provided stream to determine an SNI value.
 async fn detect_sni<I>(mut io: I) -> io::Result<(Option<ServerId>, DetectIo<I>)>
+where
+    I: io::Peek + io::AsyncRead + io::AsyncWrite + Send + Sync + Unpin,
+{
+    let mut buf = BytesMut::new();
+    let sni = read_sni(&mut io, &mut buf).await?;
+    Ok((sni, detected_io(buf, io)))
+}
+
+/// Peeks or buffers the provided stream to determine an SNI value, for at most
+/// `timeout`.
+///
+/// Streams that don't send a ClientHello in time are returned without an SNI
+/// value, along with any data that was buffered.
+pub async fn detect_sni_within<I>(
+    mut io: I,
+    timeout: Duration,
+) -> io::Result<(Option<ServerId>, DetectIo<I>)>
+where
+    I: io::Peek + io::AsyncRead + io::AsyncWrite + Send + Sync + Unpin,
+{
+    let mut buf = BytesMut::new();
+    let sni = match time::timeout(timeout, read_sni(&mut io, &mut buf)).await {
+        Ok(sni) => sni?,
+        Err(_) => {
+            debug!(?timeout, buf.len = %buf.len(), "SNI detection timed out");
+            None
+        }
+    };
+    Ok((sni, detected_io(buf, io)))
+}
+
+/// Reads the SNI value from a TLS ClientHello, retaining any data that had to
+/// be read from the stream in `buf`.
+async fn read_sni<I>(io: &mut I, buf: &mut BytesMut) -> io::Result<Option<ServerId>>
 where
     I: io::Peek + io::AsyncRead + io::AsyncWrite + Send + Sync + Unpin,
 {
@@ -227,14 +363,14 @@ where
     //
     // Anecdotally, the ClientHello sent by Linkerd proxies is <300B. So a
     // ~500B byte buffer is more than enough.
-    let mut buf = [0u8; PEEK_CAPACITY];
-    let sz = io.peek(&mut buf).await?;
+    let mut peeked = [0u8; PEEK_CAPACITY];
+    let sz = io.peek(&mut peeked).await?;
     debug!(sz, "Peeked bytes from TCP stream");
     // Peek may return 0 bytes if the socket is not peekable.
     if sz > 0 {
-        match client_hello::parse_sni(&buf) {
+        match client_hello::parse_sni(&peeked) {
             Ok(sni) => {
-                return Ok((sni, EitherIo::Left(io)));
+                return Ok(sni);
             }
 
             Err(client_hello::Incomplete) => {}
@@ -244,13 +380,13 @@ where
     // Peeking didn't return enough data, so instead we'll allocate more
     // capacity and try reading data from the socket.
     debug!("Attempting to buffer TLS ClientHello after incomplete peek");
-    let mut buf = BytesMut::with_capacity(BUFFER_CAPACITY);
+    buf.reserve(BUFFER_CAPACITY);
     debug!(buf.capacity = %buf.capacity(), "Reading bytes from TCP stream");
-    while io.read_buf(&mut buf).await? != 0 {
+    while io.read_buf(buf).await? != 0 {
         debug!(buf.len = %buf.len(), "Read bytes from TCP stream");
         match client_hello::parse_sni(buf.as_ref()) {
             Ok(sni) => {
-                return Ok((sni, EitherIo::Right(PrefixedIo::new(buf.freeze(), io))));
+                return Ok(sni);
             }
 
             Err(client_hello::Incomplete) => {
@@ -267,8 +403,15 @@ where
     }
 
     trace!("Could not read TLS ClientHello via buffering");
-    let io = EitherIo::Right(PrefixedIo::new(buf.freeze(), io));
-    Ok((None, io))
+    Ok(None)
+}
+
+/// Replays any data that was read while detecting an SNI value.
+fn detected_io<I>(buf: BytesMut, io: I) -> DetectIo<I> {
+    if buf.is_empty() {
+        return EitherIo::Left(io);
+    }
+    EitherIo::Right(PrefixedIo::new(buf.freeze(), io))
 }
 
 async fn handshake<T>(tls_config: Config, io: T) -> io::Result<(ServerTls, TlsStream<T>)>
@@ -279,6 +422,15 @@ where
         .accept(io)
         .await?;
 
+    // The connection is only labeled as established once the client has
+    // finished its side of the handshake.
+    if io.get_ref().1.is_handshaking() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "TLS handshake did not complete",
+        ));
+    }
+
     // Determine the peer's identity, if it exist.
     let client_id = client_identity(&io);
 
@@ -367,6 +519,7 @@ mod tests {
     use io::AsyncWriteExt;
 
     use super::*;
+    use linkerd_identity::test_util as test;
     use std::str::FromStr;
 
     #[tokio::test(flavor = "current_thread")]
@@ -397,6 +550,104 @@ mod tests {
 
         client_task.await.expect("Client must not fail");
     }
+
+    #[derive(Clone)]
+    struct TerminateParams(Config);
+
+    impl ExtractParam<Timeout, ()> for TerminateParams {
+        fn extract_param(&self, _: &()) -> Timeout {
+            Timeout(Duration::from_millis(100))
+        }
+    }
+
+    impl ExtractParam<Config, ()> for TerminateParams {
+        fn extract_param(&self, _: &()) -> Config {
+            self.0.clone()
+        }
+    }
+
+    impl InsertParam<ConditionalServerTls, ()> for TerminateParams {
+        type Target = ConditionalServerTls;
+
+        fn insert_param(&self, tls: ConditionalServerTls, _: ()) -> ConditionalServerTls {
+            tls
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn terminate_labels_connections_once_handshakes_complete() {
+        let _trace = linkerd_tracing::test::trace_init();
+
+        let server = crate::external::ServerConfig::new(vec![(
+            vec![test::FOO_NS1.crt.to_vec()],
+            test::FOO_NS1.key.to_vec(),
+        )])
+        .expect("server configuration must be valid");
+        let accepted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let terminate = {
+            let accepted = accepted.clone();
+            NewTerminateTls::new(
+                TerminateParams(server.server_config()),
+                move |tls: ConditionalServerTls| {
+                    accepted.lock().unwrap().push(tls);
+                    tower::service_fn(|_: Io<io::DuplexStream>| future::ok::<(), Error>(()))
+                },
+            )
+            .new_service(())
+        };
+
+        let client = {
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .add_pem_file(&mut std::io::Cursor::new(test::FOO_NS1.trust_anchors))
+                .unwrap();
+            let mut config = rustls::ClientConfig::new();
+            config.root_store = roots;
+            Arc::new(config)
+        };
+        let name = webpki::DNSNameRef::try_from_ascii_str(test::FOO_NS1.name).unwrap();
+
+        // A client that sends its hello but never finishes the handshake is
+        // not served.
+        let (mut client_io, server_io) = tokio::io::duplex(4096);
+        let mut hello = Vec::new();
+        rustls::ClientSession::new(&client, name)
+            .write_tls(&mut hello)
+            .unwrap();
+        client_io.write_all(&hello).await.unwrap();
+        let error = terminate
+            .clone()
+            .oneshot(server_io)
+            .await
+            .expect_err("incomplete handshakes must fail");
+        assert!(error.is::<ServerTlsTimeoutError>(), "{}", error);
+        assert!(accepted.lock().unwrap().is_empty());
+        drop(client_io);
+
+        // Once the client finishes the handshake, the connection is labeled as
+        // established.
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let connect = tokio::spawn(async move {
+            tokio_rustls::TlsConnector::from(client)
+                .connect(name, client_io)
+                .await
+        });
+        terminate
+            .oneshot(server_io)
+            .await
+            .expect("complete handshakes must succeed");
+        connect
+            .await
+            .unwrap()
+            .expect("client must complete the handshake");
+        assert_eq!(
+            *accepted.lock().unwrap(),
+            vec![Conditional::Some(ServerTls::Established {
+                client_id: None,
+                negotiated_protocol: None,
+            })]
+        );
+    }
 }
 
 #[cfg(fuzzing)]
@@ -13,11 +13,15 @@ use linkerd_io::{self as io, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}
 use linkerd_proxy_transport::{
     addrs::*,
     listen::{Addrs, Bind, BindTcp},
-    ConnectTcp, Keepalive, ListenAddr,
+    ConnectTcp, DscpMarking, Keepalive, ListenAddr,
 };
 use linkerd_stack::{ExtractParam, InsertParam, NewService, Param};
 use linkerd_tls as tls;
-use std::{future::Future, time::Duration};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use std::{net::SocketAddr, sync::mpsc};
 use tokio::net::TcpStream;
 use tower::{
@@ -57,9 +61,9 @@ async fn proxy_to_proxy_tls_works() {
     let client_tls = id::test_util::BAR_NS1.validate().unwrap();
     let server_id = tls::ServerId(server_tls.name().clone());
     let (client_result, server_result) = run_test(
-        Conditional::Some((client_tls.clone(), server_id.clone())),
+        Conditional::Some((client_tls.client_config(), server_id.clone())),
         |conn| write_then_read(conn, PING),
-        Some(server_tls),
+        Some((server_tls, tls::server::ClientAuth::default())),
         |(_, conn)| read_then_write(conn, PING.len(), PONG),
     )
     .await;
@@ -93,9 +97,9 @@ async fn proxy_to_proxy_tls_pass_through_when_identity_does_not_match() {
     let sni = id::test_util::BAR_NS1.crt().name().clone();
 
     let (client_result, server_result) = run_test(
-        Conditional::Some((client_tls, tls::ServerId(sni.clone()))),
+        Conditional::Some((client_tls.client_config(), tls::ServerId(sni.clone()))),
         |conn| write_then_read(conn, PING),
-        Some(server_tls),
+        Some((server_tls, tls::server::ClientAuth::default())),
         |(_, conn)| read_then_write(conn, START_OF_TLS.len(), PONG),
     )
     .await;
@@ -113,6 +117,148 @@ async fn proxy_to_proxy_tls_pass_through_when_identity_does_not_match() {
     assert_eq!(&server_result.result.unwrap()[..], START_OF_TLS);
 }
 
+#[tokio::test(flavor = "current_thread")]
+async fn proxy_to_proxy_tls_client_auth_modes() {
+    use tls::server::ClientAuth;
+
+    let client_tls = id::test_util::BAR_NS1.validate().unwrap();
+    let client_id = tls::ClientId(client_tls.name().clone());
+    let with_crt = client_tls.client_config();
+    let anonymous = id::test_util::BAR_NS1.trust_anchors().client_config();
+
+    let established = |client_id| {
+        Some(Conditional::Some(tls::ServerTls::Established {
+            client_id,
+            negotiated_protocol: None,
+        }))
+    };
+
+    // Clients must present a certificate when authentication is required.
+    let server = run_client_auth_test(ClientAuth::Require, with_crt.clone()).await;
+    assert_eq!(server.tls, established(Some(client_id.clone())));
+    assert_eq!(&server.result.expect("ping")[..], PING);
+    let server = run_client_auth_test(ClientAuth::Require, anonymous.clone()).await;
+    assert_eq!(server.tls, None);
+    assert!(server.result.is_err(), "handshake must fail");
+
+    // Anonymous clients are permitted when authentication is requested.
+    let server = run_client_auth_test(ClientAuth::Request, with_crt.clone()).await;
+    assert_eq!(server.tls, established(Some(client_id)));
+    assert_eq!(&server.result.expect("ping")[..], PING);
+    let server = run_client_auth_test(ClientAuth::Request, anonymous.clone()).await;
+    assert_eq!(server.tls, established(None));
+    assert_eq!(&server.result.expect("ping")[..], PING);
+
+    // Clients are never identified when authentication is disabled.
+    let server = run_client_auth_test(ClientAuth::None, with_crt).await;
+    assert_eq!(server.tls, established(None));
+    assert_eq!(&server.result.expect("ping")[..], PING);
+    let server = run_client_auth_test(ClientAuth::None, anonymous).await;
+    assert_eq!(server.tls, established(None));
+    assert_eq!(&server.result.expect("ping")[..], PING);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn rotated_certificates_are_used_by_new_handshakes() {
+    let _trace = linkerd_tracing::test::trace_init();
+
+    let crt_key = Arc::new(Mutex::new(id::test_util::FOO_NS1.validate().unwrap()));
+    let server_id = tls::ServerId(crt_key.lock().unwrap().name().clone());
+    let ca1 = id::test_util::FOO_NS1.trust_anchors().client_config();
+    let ca2 = id::test_util::FOO_NS1_CA2.trust_anchors().client_config();
+
+    // Serves each connection by reading a ping and writing a pong.
+    let mut detect = tls::NewDetectTls::new(
+        RotatingServerParams(crt_key.clone()),
+        |_: (tls::ConditionalServerTls, Addrs)| {
+            service_fn(|conn: tls::server::Io<TcpStream>| async move {
+                if let Err(error) = read_then_write(conn, PING.len(), PONG).await {
+                    tracing::debug!(%error, "connection failed");
+                }
+                Ok::<(), Infallible>(())
+            })
+        },
+    );
+    let (listen_addr, listen) = BindTcp::default().bind(&Server).expect("must bind");
+    let server_addr = SocketAddr::from(listen_addr);
+    tokio::spawn(async move {
+        futures::pin_mut!(listen);
+        while let Some(Ok((addrs, io))) = listen.next().await {
+            let accept = detect.new_service(addrs);
+            tokio::spawn(async move {
+                if let Err(error) = accept.oneshot(io).await {
+                    tracing::debug!(%error, "handshake failed");
+                }
+            });
+        }
+    });
+
+    let handshake = |config: tls::client::Config, tcp: TcpStream| {
+        let server_id = server_id.clone();
+        async move {
+            tokio_rustls::TlsConnector::from(config)
+                .connect((&server_id.0).into(), tcp)
+                .await
+        }
+    };
+
+    let established = handshake(ca1.clone(), TcpStream::connect(server_addr).await.unwrap())
+        .await
+        .expect("handshake must succeed before the rotation");
+
+    // A connection that is accepted before the rotation, but whose handshake
+    // begins after it, uses the new certificate.
+    let accepted = TcpStream::connect(server_addr).await.unwrap();
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+
+    *crt_key.lock().unwrap() = id::test_util::FOO_NS1_CA2.validate().unwrap();
+
+    assert_eq!(
+        &write_then_read(established, PING).await.expect("pong")[..],
+        PONG,
+        "established connections must not be disrupted"
+    );
+
+    let accepted = handshake(ca2.clone(), accepted)
+        .await
+        .expect("handshake must use the new certificate");
+    assert_eq!(
+        &write_then_read(accepted, PING).await.expect("pong")[..],
+        PONG
+    );
+
+    assert!(
+        handshake(ca1, TcpStream::connect(server_addr).await.unwrap())
+            .await
+            .is_err(),
+        "new connections must not use the old certificate"
+    );
+    let new = handshake(ca2, TcpStream::connect(server_addr).await.unwrap())
+        .await
+        .expect("handshake must use the new certificate");
+    assert_eq!(&write_then_read(new, PING).await.expect("pong")[..], PONG);
+}
+
+/// Connects to a server that authenticates clients with `auth`, returning the
+/// server's view of the connection.
+async fn run_client_auth_test(
+    auth: tls::server::ClientAuth,
+    client_tls: tls::client::Config,
+) -> Transported<tls::ConditionalServerTls, Vec<u8>> {
+    let server_tls = id::test_util::FOO_NS1.validate().unwrap();
+    let server_id = tls::ServerId(server_tls.name().clone());
+    let (_, server_result) = run_test(
+        Conditional::Some((client_tls, server_id)),
+        |conn| write_then_read(conn, PING),
+        Some((server_tls, auth)),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    )
+    .await;
+    server_result
+}
+
 struct Transported<I, R> {
     tls: Option<I>,
 
@@ -120,18 +266,23 @@ struct Transported<I, R> {
     result: Result<R, io::Error>,
 }
 
+/// Terminates TLS with the current value of a certificate that may be
+/// rotated, as the proxy's local identity is.
+#[derive(Clone)]
+struct RotatingServerParams(Arc<Mutex<id::CrtKey>>);
+
 #[derive(Clone)]
 struct ServerParams {
-    identity: Option<id::CrtKey>,
+    identity: Option<(id::CrtKey, tls::server::ClientAuth)>,
 }
 
 /// Runs a test for a single TCP connection. `client` processes the connection
 /// on the client side and `server` processes the connection on the server
 /// side.
 async fn run_test<C, CF, CR, S, SF, SR>(
-    client_tls: Conditional<(id::CrtKey, tls::ServerId), tls::NoClientTls>,
+    client_tls: Conditional<(tls::client::Config, tls::ServerId), tls::NoClientTls>,
     client: C,
-    server_tls: Option<id::CrtKey>,
+    server_tls: Option<(id::CrtKey, tls::server::ClientAuth)>,
     server: S,
 ) -> (
     Transported<tls::ConditionalClientTls, CR>,
@@ -148,7 +299,7 @@ where
     SR: Send + 'static,
 {
     let (client_tls, client_server_id) = match client_tls {
-        Conditional::Some((crtkey, name)) => (Some(Tls(crtkey)), Conditional::Some(name)),
+        Conditional::Some((config, name)) => (Some(ClientTls(config)), Conditional::Some(name)),
         Conditional::None(reason) => (None, Conditional::None(reason)),
     };
 
@@ -158,6 +309,7 @@ where
     let (server, server_addr, server_result) = {
         // Saves the result of every connection.
         let (sender, receiver) = mpsc::channel::<Transported<tls::ConditionalServerTls, SR>>();
+        let failed = sender.clone();
 
         let mut detect = tls::NewDetectTls::new(
             ServerParams {
@@ -196,7 +348,17 @@ where
                 .expect("listener closed");
             tracing::debug!("incoming connection");
             let accept = detect.new_service(addrs);
-            accept.oneshot(io).await.expect("connection failed");
+            if let Err(error) = accept.oneshot(io).await {
+                // The connection failed before it could be served (e.g.
+                // because the TLS handshake failed).
+                tracing::debug!(%error, "connection failed");
+                failed
+                    .send(Transported {
+                        tls: None,
+                        result: Err(io::Error::new(io::ErrorKind::Other, error)),
+                    })
+                    .expect("send result");
+            }
             tracing::debug!("done");
         }
         .instrument(tracing::info_span!("run_server", %listen_addr));
@@ -215,7 +377,7 @@ where
         let tls = Some(client_server_id.clone().map(Into::into));
         let client = async move {
             let conn = tls::Client::layer(client_tls)
-                .layer(ConnectTcp::new(Keepalive(None)))
+                .layer(ConnectTcp::new(Keepalive(None), DscpMarking::default()))
                 .oneshot(Target(server_addr.into(), client_server_id.map(Into::into)))
                 .await;
             match conn {
@@ -309,7 +471,10 @@ struct Server;
 struct Target(SocketAddr, tls::ConditionalClientTls);
 
 #[derive(Clone)]
-struct Tls(id::CrtKey);
+struct ClientTls(tls::client::Config);
+
+#[derive(Clone)]
+struct Tls(id::CrtKey, tls::server::ClientAuth);
 
 // === impl Target ===
 
@@ -325,17 +490,19 @@ impl Param<tls::ConditionalClientTls> for Target {
     }
 }
 
-// === impl Tls ===
+// === impl ClientTls ===
 
-impl Param<tls::client::Config> for Tls {
+impl Param<tls::client::Config> for ClientTls {
     fn param(&self) -> tls::client::Config {
-        self.0.client_config()
+        self.0.clone()
     }
 }
 
+// === impl Tls ===
+
 impl Param<tls::server::Config> for Tls {
     fn param(&self) -> tls::server::Config {
-        self.0.server_config()
+        self.0.server_config_for(self.1)
     }
 }
 
@@ -362,6 +529,12 @@ impl Param<Keepalive> for Server {
     }
 }
 
+impl Param<DscpMarking> for Server {
+    fn param(&self) -> DscpMarking {
+        DscpMarking::default()
+    }
+}
+
 /// === impl ServerParams ===
 
 impl<T> ExtractParam<tls::server::Timeout, T> for ServerParams {
@@ -372,7 +545,44 @@ impl<T> ExtractParam<tls::server::Timeout, T> for ServerParams {
 
 impl<T> ExtractParam<Option<Tls>, T> for ServerParams {
     fn extract_param(&self, _: &T) -> Option<Tls> {
-        self.identity.clone().map(Tls)
+        self.identity
+            .clone()
+            .map(|(crt_key, auth)| Tls(crt_key, auth))
+    }
+}
+
+/// === impl RotatingServerParams ===
+
+impl Param<tls::server::Config> for RotatingServerParams {
+    fn param(&self) -> tls::server::Config {
+        self.0.lock().unwrap().server_config()
+    }
+}
+
+impl Param<tls::LocalId> for RotatingServerParams {
+    fn param(&self) -> tls::LocalId {
+        self.0.lock().unwrap().id().clone()
+    }
+}
+
+impl<T> ExtractParam<tls::server::Timeout, T> for RotatingServerParams {
+    fn extract_param(&self, _: &T) -> tls::server::Timeout {
+        tls::server::Timeout(Duration::from_secs(10))
+    }
+}
+
+impl<T> ExtractParam<Option<Self>, T> for RotatingServerParams {
+    fn extract_param(&self, _: &T) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl<T> InsertParam<tls::ConditionalServerTls, T> for RotatingServerParams {
+    type Target = (tls::ConditionalServerTls, T);
+
+    #[inline]
+    fn insert_param(&self, tls: tls::ConditionalServerTls, target: T) -> Self::Target {
+        (tls, target)
     }
 }
 
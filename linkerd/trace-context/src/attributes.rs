@@ -0,0 +1,71 @@
+use tracing::debug;
+
+/// Request metadata that is recorded as attributes of the request's span.
+///
+/// Inner layers find this in a request's extensions when they emit its span.
+/// The number of attributes and the length of their values are bounded so that
+/// request metadata cannot inflate spans arbitrarily: attributes beyond
+/// `MAX_ATTRIBUTES` are dropped and values are truncated to `MAX_VALUE_BYTES`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpanAttributes(Vec<(String, String)>);
+
+// === impl SpanAttributes ===
+
+impl SpanAttributes {
+    pub const MAX_ATTRIBUTES: usize = 16;
+    pub const MAX_VALUE_BYTES: usize = 256;
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        if self.0.len() >= Self::MAX_ATTRIBUTES {
+            debug!(%key, "Dropping span attribute");
+            return;
+        }
+
+        let mut value = value.into();
+        if value.len() > Self::MAX_VALUE_BYTES {
+            let mut end = Self::MAX_VALUE_BYTES;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+        }
+        self.0.push((key, value));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_attributes() {
+        let mut attrs = SpanAttributes::default();
+        for i in 0..SpanAttributes::MAX_ATTRIBUTES + 4 {
+            attrs.insert(format!("key{}", i), "value");
+        }
+        assert_eq!(attrs.iter().count(), SpanAttributes::MAX_ATTRIBUTES);
+        assert!(attrs.iter().all(|(k, _)| k != "key16"));
+
+        let mut attrs = SpanAttributes::default();
+        attrs.insert("long", "é".repeat(SpanAttributes::MAX_VALUE_BYTES));
+        let (_, value) = attrs.iter().next().unwrap();
+        assert_eq!(value.len(), SpanAttributes::MAX_VALUE_BYTES);
+
+        let mut attrs = SpanAttributes::default();
+        attrs.insert(
+            "odd",
+            format!("a{}", "é".repeat(SpanAttributes::MAX_VALUE_BYTES)),
+        );
+        let (_, value) = attrs.iter().next().unwrap();
+        assert_eq!(value.len(), SpanAttributes::MAX_VALUE_BYTES - 1);
+    }
+}
@@ -1,10 +1,17 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
+mod attributes;
 mod propagation;
+mod sample;
 mod service;
 
-pub use self::service::TraceContext;
+pub use self::{
+    attributes::SpanAttributes,
+    propagation::trace_id,
+    sample::{Sample, Sampler},
+    service::TraceContext,
+};
 use bytes::Bytes;
 use linkerd_error::Error;
 use rand::Rng;
@@ -15,6 +22,7 @@ use std::time::SystemTime;
 use thiserror::Error;
 
 const SPAN_ID_LEN: usize = 8;
+const TRACE_ID_LEN: usize = 16;
 
 #[derive(Debug, Default)]
 pub struct Id(Vec<u8>);
@@ -35,12 +43,17 @@ pub struct Span {
     pub start: SystemTime,
     pub end: SystemTime,
     pub labels: HashMap<&'static str, String>,
+    pub attributes: SpanAttributes,
 }
 
 pub trait SpanSink {
     fn is_enabled(&self) -> bool;
 
     fn try_send(&mut self, span: Span) -> Result<(), Error>;
+
+    /// Called for each traced request that is not marked for sampling and
+    /// therefore does not emit a span.
+    fn record_unsampled(&mut self) {}
 }
 
 impl<K: SpanSink> SpanSink for Option<K> {
@@ -53,6 +66,13 @@ impl<K: SpanSink> SpanSink for Option<K> {
     fn try_send(&mut self, span: Span) -> Result<(), Error> {
         self.as_mut().expect("Must be enabled").try_send(span)
     }
+
+    #[inline]
+    fn record_unsampled(&mut self) {
+        if let Some(sink) = self.as_mut() {
+            sink.record_unsampled();
+        }
+    }
 }
 
 // === impl Id ===
@@ -63,6 +83,12 @@ impl Id {
         rng.fill(bytes.as_mut_slice());
         Self(bytes)
     }
+
+    fn new_trace_id<R: Rng>(rng: &mut R) -> Self {
+        let mut bytes = vec![0; TRACE_ID_LEN];
+        rng.fill(bytes.as_mut_slice());
+        Self(bytes)
+    }
 }
 
 impl From<Id> for Vec<u8> {
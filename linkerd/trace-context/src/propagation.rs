@@ -28,6 +28,9 @@ pub struct TraceContext {
     pub trace_id: Id,
     pub parent_id: Id,
     pub flags: Flags,
+    /// Whether the request carried a sampling decision. When it did not, the
+    /// sampled flag defaults to unset.
+    pub has_sampling_decision: bool,
 }
 
 #[derive(Debug, Error)]
@@ -46,6 +49,11 @@ pub fn unpack_trace_context<B>(request: &http::Request<B>) -> Option<TraceContex
     unpack_grpc_trace_context(request).or_else(|| unpack_http_trace_context(request))
 }
 
+/// Returns the ID of the trace propagated with the request, if any.
+pub fn trace_id<B>(request: &http::Request<B>) -> Option<Id> {
+    unpack_trace_context(request).map(|ctx| ctx.trace_id)
+}
+
 // Generates a new span id, writes it to the request in the appropriate
 // propagation format and returns the generated span id.
 pub fn increment_span_id<B>(request: &mut http::Request<B>, context: &TraceContext) -> Id {
@@ -83,6 +91,7 @@ fn parse_grpc_trace_context_fields(buf: &mut Bytes) -> Option<TraceContext> {
         trace_id: Default::default(),
         parent_id: Default::default(),
         flags: Default::default(),
+        has_sampling_decision: false,
     };
 
     while !buf.is_empty() {
@@ -130,6 +139,7 @@ fn parse_grpc_trace_context_field(
                 flags
             );
             context.flags = flags.try_into()?;
+            context.has_sampling_decision = true;
         }
         id => {
             return Err(UnknownFieldId(id).into());
@@ -138,14 +148,66 @@ fn parse_grpc_trace_context_field(
     Ok(())
 }
 
-// This code looks significantly weirder if some of the elements are added using
-// the `vec![]` macro, despite clippy's suggestions otherwise...
-#[allow(clippy::vec_init_then_push)]
+/// Sets the sampled flag on the request's trace context, in the format in which
+/// it was propagated.
+pub fn set_sampled<B>(request: &mut http::Request<B>, context: &mut TraceContext, sampled: bool) {
+    context.flags = Flags(if sampled {
+        context.flags.0 | 1
+    } else {
+        context.flags.0 & !1
+    });
+    context.has_sampling_decision = true;
+    match context.propagation {
+        Propagation::Grpc => write_grpc_trace_context(
+            request,
+            &context.trace_id,
+            &context.parent_id,
+            &context.flags,
+        ),
+        Propagation::Http => {
+            let value = HeaderValue::from_static(if sampled { "1" } else { "0" });
+            request.headers_mut().insert(HTTP_SAMPLED_HEADER, value);
+        }
+    }
+}
+
+/// Starts a new, sampled trace for a request that has no trace context.
+pub fn start_trace<B>(request: &mut http::Request<B>) {
+    let mut rng = thread_rng();
+    let trace_id = Id::new_trace_id(&mut rng);
+    let span_id = Id::new_span_id(&mut rng);
+    trace!(%trace_id, %span_id, "started trace");
+
+    let headers = request.headers_mut();
+    for (name, id) in &[
+        (HTTP_TRACE_ID_HEADER, trace_id),
+        (HTTP_SPAN_ID_HEADER, span_id),
+    ] {
+        let value = HeaderValue::from_str(&hex::encode(id.as_ref()))
+            .expect("hex-encoded IDs must be valid header values");
+        headers.insert(*name, value);
+    }
+    headers.insert(HTTP_SAMPLED_HEADER, HeaderValue::from_static("1"));
+}
+
 fn increment_grpc_span_id<B>(request: &mut http::Request<B>, context: &TraceContext) -> Id {
     let span_id = Id::new_span_id(&mut thread_rng());
 
     trace!(message = "incremented span id", %span_id);
 
+    write_grpc_trace_context(request, &context.trace_id, &span_id, &context.flags);
+    span_id
+}
+
+// This code looks significantly weirder if some of the elements are added using
+// the `vec![]` macro, despite clippy's suggestions otherwise...
+#[allow(clippy::vec_init_then_push)]
+fn write_grpc_trace_context<B>(
+    request: &mut http::Request<B>,
+    trace_id: &Id,
+    span_id: &Id,
+    flags: &Flags,
+) {
     let mut bytes = Vec::<u8>::new();
 
     // version
@@ -153,7 +215,7 @@ fn increment_grpc_span_id<B>(request: &mut http::Request<B>, context: &TraceCont
 
     // trace id
     bytes.push(GRPC_TRACE_FIELD_TRACE_ID);
-    bytes.extend(context.trace_id.0.iter());
+    bytes.extend(trace_id.0.iter());
 
     // span id
     bytes.push(GRPC_TRACE_FIELD_SPAN_ID);
@@ -161,7 +223,7 @@ fn increment_grpc_span_id<B>(request: &mut http::Request<B>, context: &TraceCont
 
     // trace options
     bytes.push(GRPC_TRACE_FIELD_TRACE_OPTIONS);
-    bytes.push(context.flags.0);
+    bytes.push(flags.0);
 
     let bytes_b64 = base64::encode(&bytes);
 
@@ -170,13 +232,13 @@ fn increment_grpc_span_id<B>(request: &mut http::Request<B>, context: &TraceCont
     } else {
         warn!("invalid header: {:?}", &bytes_b64);
     }
-    span_id
 }
 
 fn unpack_http_trace_context<B>(request: &http::Request<B>) -> Option<TraceContext> {
     let parent_id = parse_header_id(request, HTTP_SPAN_ID_HEADER, 8)?;
     let trace_id = parse_header_id(request, HTTP_TRACE_ID_HEADER, 16)?;
-    let flags = match get_header_str(request, HTTP_SAMPLED_HEADER) {
+    let sampled = get_header_str(request, HTTP_SAMPLED_HEADER);
+    let flags = match sampled {
         Some("1") => Flags(1),
         _ => Flags(0),
     };
@@ -185,6 +247,7 @@ fn unpack_http_trace_context<B>(request: &http::Request<B>) -> Option<TraceConte
         trace_id,
         parent_id,
         flags,
+        has_sampling_decision: sampled.is_some(),
     })
 }
 
@@ -0,0 +1,207 @@
+use crate::propagation;
+use http::header::HeaderName;
+use linkerd_stack::layer;
+use rand::{thread_rng, Rng};
+use std::task::{Context, Poll};
+use tracing::debug;
+
+/// Decides whether requests that do not carry a sampling decision are traced.
+///
+/// A fraction of these requests are sampled, as are all requests that have the
+/// force-sample header. Requests that carry an upstream sampling decision are
+/// not re-sampled unless they have the force-sample header. Requests without a
+/// trace context start a new trace when they are sampled.
+///
+/// The default sampler never samples requests, so upstream decisions are
+/// always honored.
+#[derive(Clone, Debug, Default)]
+pub struct Sampler {
+    ratio: f64,
+    force_header: Option<HeaderName>,
+}
+
+/// A layer that makes a sampling decision for each request before it is traced.
+/// The decision is propagated to the inner service (and downstream) by setting
+/// the sampled flag on the request's trace context.
+#[derive(Clone, Debug)]
+pub struct Sample<S> {
+    inner: S,
+    sampler: Sampler,
+}
+
+// === impl Sampler ===
+
+impl Sampler {
+    /// Samples `ratio` (between 0 and 1) of the requests that do not carry a
+    /// sampling decision, and all requests with the `force_header`.
+    pub fn new(ratio: f64, force_header: Option<HeaderName>) -> Self {
+        debug_assert!((0.0..=1.0).contains(&ratio));
+        Self {
+            ratio,
+            force_header,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.ratio > 0.0 || self.force_header.is_some()
+    }
+
+    fn sample<B, R: Rng>(&self, req: &mut http::Request<B>, rng: &mut R) {
+        let forced = self
+            .force_header
+            .as_ref()
+            .map(|h| req.headers().contains_key(h))
+            .unwrap_or(false);
+
+        match propagation::unpack_trace_context(req) {
+            Some(mut context) => {
+                if forced && !context.is_sampled() {
+                    debug!("Forcing sampling");
+                    propagation::set_sampled(req, &mut context, true);
+                } else if !context.has_sampling_decision {
+                    let sampled = rng.gen::<f64>() < self.ratio;
+                    debug!(sampled, "Sampling decision");
+                    propagation::set_sampled(req, &mut context, sampled);
+                }
+            }
+            None => {
+                if forced || rng.gen::<f64>() < self.ratio {
+                    debug!(forced, "Starting a sampled trace");
+                    propagation::start_trace(req);
+                }
+            }
+        }
+    }
+}
+
+// === impl Sample ===
+
+impl<S> Sample<S> {
+    pub fn layer(sampler: Sampler) -> impl layer::Layer<S, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            inner,
+            sampler: sampler.clone(),
+        })
+    }
+}
+
+impl<B, S> tower::Service<http::Request<B>> for Sample<S>
+where
+    S: tower::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if self.sampler.is_enabled() {
+            self.sampler.sample(&mut req, &mut thread_rng());
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn sampled(req: &http::Request<()>) -> Option<bool> {
+        propagation::unpack_trace_context(req).map(|ctx| ctx.is_sampled())
+    }
+
+    fn traced(sampled: Option<&str>) -> http::Request<()> {
+        let mut req = http::Request::builder()
+            .header("x-b3-traceid", "0123456789abcdef0123456789abcdef")
+            .header("x-b3-spanid", "0123456789abcdef");
+        if let Some(s) = sampled {
+            req = req.header("x-b3-sampled", s);
+        }
+        req.body(()).unwrap()
+    }
+
+    #[test]
+    fn samples_configured_ratio() {
+        let sampler = Sampler::new(0.1, None);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut count = 0;
+        for _ in 0..10_000 {
+            let mut req = traced(None);
+            sampler.sample(&mut req, &mut rng);
+            let is_sampled = sampled(&req).expect("request must be traced");
+            assert_eq!(
+                req.headers()["x-b3-sampled"],
+                if is_sampled { "1" } else { "0" }
+            );
+            if is_sampled {
+                count += 1;
+            }
+        }
+        assert!((800..1200).contains(&count), "sampled {} requests", count);
+    }
+
+    #[test]
+    fn starts_sampled_traces() {
+        let sampler = Sampler::new(1.0, None);
+        let mut req = http::Request::new(());
+        sampler.sample(&mut req, &mut StdRng::seed_from_u64(0));
+        assert_eq!(sampled(&req), Some(true));
+
+        let sampler = Sampler::new(0.0, None);
+        let mut req = http::Request::new(());
+        sampler.sample(&mut req, &mut StdRng::seed_from_u64(0));
+        assert_eq!(sampled(&req), None, "unsampled requests must not be traced");
+    }
+
+    #[test]
+    fn honors_upstream_decisions() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut req = traced(Some("0"));
+        Sampler::new(1.0, None).sample(&mut req, &mut rng);
+        assert_eq!(sampled(&req), Some(false));
+
+        let mut req = traced(Some("1"));
+        Sampler::new(0.0, None).sample(&mut req, &mut rng);
+        assert_eq!(sampled(&req), Some(true));
+    }
+
+    #[test]
+    fn force_header_always_samples() {
+        let sampler = Sampler::new(0.0, Some(HeaderName::from_static("x-debug-trace")));
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for req in &mut [traced(None), traced(Some("0")), http::Request::new(())] {
+            req.headers_mut()
+                .insert("x-debug-trace", http::HeaderValue::from_static("1"));
+            sampler.sample(req, &mut rng);
+            assert_eq!(sampled(req), Some(true));
+        }
+
+        let mut req = traced(None);
+        sampler.sample(&mut req, &mut rng);
+        assert_eq!(sampled(&req), Some(false));
+    }
+
+    #[test]
+    fn sets_grpc_sampled_flag() {
+        let mut bytes = vec![0, 0];
+        bytes.extend_from_slice(&[0xab; 16]);
+        bytes.push(1);
+        bytes.extend_from_slice(&[0xcd; 8]);
+        let mut req = http::Request::builder()
+            .header("grpc-trace-bin", base64::encode(&bytes))
+            .body(())
+            .unwrap();
+        Sampler::new(1.0, None).sample(&mut req, &mut StdRng::seed_from_u64(0));
+        let ctx = propagation::unpack_trace_context(&req).expect("request must be traced");
+        assert!(ctx.is_sampled());
+        assert_eq!(ctx.trace_id.as_ref(), &[0xab; 16]);
+        assert_eq!(ctx.parent_id.as_ref(), &[0xcd; 8]);
+    }
+}
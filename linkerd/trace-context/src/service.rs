@@ -1,4 +1,4 @@
-use crate::{propagation, Span, SpanSink};
+use crate::{propagation, Span, SpanAttributes, SpanSink};
 use futures::{future::Either, prelude::*};
 use linkerd_stack::layer;
 use std::{
@@ -95,6 +95,11 @@ where
                     // If the request has been marked for sampling, record its metadata.
                     let start = SystemTime::now();
                     let req_labels = Self::request_labels(&req);
+                    let attributes = req
+                        .extensions()
+                        .get::<SpanAttributes>()
+                        .cloned()
+                        .unwrap_or_default();
                     let mut sink = self.sink.clone();
                     let span_name = req.uri().path().to_owned();
                     return Either::Right(Box::pin(self.inner.call(req).map_ok(move |rsp| {
@@ -107,6 +112,7 @@ where
                             start,
                             end: SystemTime::now(),
                             labels: Self::add_response_labels(req_labels, &rsp),
+                            attributes,
                         };
                         trace!(?span);
                         if let Err(error) = sink.try_send(span) {
@@ -115,6 +121,8 @@ where
                         rsp
                     })));
                 }
+
+                self.sink.record_unsampled();
             }
         }
 